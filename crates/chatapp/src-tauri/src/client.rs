@@ -16,6 +16,10 @@ impl RIGClient {
         Ok(Self { agent })
     }
 
+    pub fn session_id(&self) -> &str {
+        self.agent.session_id()
+    }
+
     pub async fn handle_command(&mut self, input: &str) -> Result<String> {
         // Process the command using the agent
         let response = self.agent.process_message(input).await?;
@@ -0,0 +1,377 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use tokio::net::TcpStream;
+use tokio::process::Command;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+// Restart delays for a component that keeps exiting
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+// A component that stayed up this long restarts without delay
+const STABLE_AFTER: Duration = Duration::from_secs(60);
+// How long the server waits for Anvil to accept connections before starting anyway
+const ANVIL_STARTUP_TIMEOUT: Duration = Duration::from_secs(30);
+
+type StatusMap = Arc<RwLock<BTreeMap<&'static str, String>>>;
+
+/// How the app reaches its MCP server
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ServerMode {
+    /// Connect to a server started separately
+    #[default]
+    External,
+    /// Spawn the mcp-server binary and restart it when it exits
+    ChildProcess,
+    /// Run the server inside the app through the mcp-server library
+    InProcess,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AnvilSettings {
+    pub binary: PathBuf,
+    pub port: u16,
+    /// Chain to fork; Anvil starts an empty chain when unset
+    pub fork_url: Option<String>,
+}
+
+impl Default for AnvilSettings {
+    fn default() -> Self {
+        Self {
+            binary: PathBuf::from("anvil"),
+            port: 8545,
+            fork_url: None,
+        }
+    }
+}
+
+impl AnvilSettings {
+    fn rpc_url(&self) -> String {
+        format!("http://127.0.0.1:{}", self.port)
+    }
+}
+
+/// Server settings edited from the app and stored as JSON in its config directory
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ServerSettings {
+    pub mode: ServerMode,
+    /// Address the embedded server listens on
+    pub server_addr: String,
+    /// mcp-server executable for child process mode; defaults to the one next to the app,
+    /// then to `mcp-server` on PATH
+    pub server_binary: Option<PathBuf>,
+    /// Directory containing the server's `data` folder
+    pub working_dir: Option<PathBuf>,
+    /// Also run a local Anvil node and point the server at it
+    pub anvil: Option<AnvilSettings>,
+}
+
+impl Default for ServerSettings {
+    fn default() -> Self {
+        Self {
+            mode: ServerMode::External,
+            server_addr: "127.0.0.1:3000".to_string(),
+            server_binary: None,
+            working_dir: None,
+            anvil: None,
+        }
+    }
+}
+
+impl ServerSettings {
+    /// Read the settings file, falling back to defaults when it doesn't exist yet
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = fs::read_to_string(path)?;
+        serde_json::from_str(&content)
+            .map_err(|e| anyhow!("Invalid server settings in {}: {}", path.display(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+// A child process to keep running
+struct ProcessSpec {
+    program: PathBuf,
+    args: Vec<String>,
+    env: Vec<(String, String)>,
+    working_dir: Option<PathBuf>,
+    /// Address to wait for before the first start
+    wait_for: Option<String>,
+}
+
+/// Supervises the server (and Anvil) the app runs itself, restarting them when they exit
+pub struct EmbeddedServer {
+    status: StatusMap,
+    shutdown: watch::Sender<bool>,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl EmbeddedServer {
+    /// Start the components `settings` asks for. Must be called within a Tokio runtime;
+    /// returns immediately while they start in the background.
+    pub fn start(settings: &ServerSettings) -> Result<Self> {
+        let (shutdown, _) = watch::channel(false);
+        let status: StatusMap = Arc::new(RwLock::new(BTreeMap::new()));
+        let mut tasks = Vec::new();
+
+        let anvil_addr = settings
+            .anvil
+            .as_ref()
+            .map(|anvil| format!("127.0.0.1:{}", anvil.port));
+        if let Some(anvil) = &settings.anvil {
+            let mut args = vec!["--port".to_string(), anvil.port.to_string()];
+            if let Some(fork_url) = &anvil.fork_url {
+                args.push("--fork-url".to_string());
+                args.push(fork_url.clone());
+            }
+            let spec = ProcessSpec {
+                program: anvil.binary.clone(),
+                args,
+                env: Vec::new(),
+                working_dir: None,
+                wait_for: None,
+            };
+            tasks.push(tokio::spawn(supervise_process(
+                "anvil",
+                spec,
+                status.clone(),
+                shutdown.subscribe(),
+            )));
+        }
+
+        match settings.mode {
+            ServerMode::External => {}
+            ServerMode::ChildProcess => {
+                let mut env = vec![("SERVER_ADDR".to_string(), settings.server_addr.clone())];
+                if let Some(anvil) = &settings.anvil {
+                    env.push(("ETH_RPC_URL".to_string(), anvil.rpc_url()));
+                }
+                let spec = ProcessSpec {
+                    program: settings
+                        .server_binary
+                        .clone()
+                        .unwrap_or_else(default_server_binary),
+                    args: Vec::new(),
+                    env,
+                    working_dir: settings.working_dir.clone(),
+                    wait_for: anvil_addr,
+                };
+                tasks.push(tokio::spawn(supervise_process(
+                    "mcp-server",
+                    spec,
+                    status.clone(),
+                    shutdown.subscribe(),
+                )));
+            }
+            ServerMode::InProcess => {
+                // The library reads its configuration from the environment and ./data, so
+                // both are set process-wide before it starts
+                if let Some(anvil) = &settings.anvil {
+                    std::env::set_var("ETH_RPC_URL", anvil.rpc_url());
+                }
+                if let Some(dir) = &settings.working_dir {
+                    std::env::set_current_dir(dir).map_err(|e| {
+                        anyhow!("Cannot use {} as working directory: {}", dir.display(), e)
+                    })?;
+                }
+                tasks.push(tokio::spawn(supervise_in_process(
+                    settings.server_addr.clone(),
+                    anvil_addr,
+                    status.clone(),
+                    shutdown.subscribe(),
+                )));
+            }
+        }
+
+        Ok(Self {
+            status,
+            shutdown,
+            tasks,
+        })
+    }
+
+    /// State of each supervised component, e.g. `{"anvil": "running (pid 4242)"}`
+    pub fn status(&self) -> Value {
+        match self.status.read() {
+            Ok(status) => json!(*status),
+            Err(_) => json!({}),
+        }
+    }
+
+    /// Stop everything that was started and wait for child processes to exit
+    pub async fn shutdown(self) {
+        let _ = self.shutdown.send(true);
+        for task in self.tasks {
+            let _ = task.await;
+        }
+    }
+}
+
+fn set_status(status: &StatusMap, name: &'static str, value: String) {
+    if let Ok(mut status) = status.write() {
+        status.insert(name, value);
+    }
+}
+
+// The mcp-server binary bundled next to the app executable, else the one on PATH
+fn default_server_binary() -> PathBuf {
+    let file_name = format!("mcp-server{}", std::env::consts::EXE_SUFFIX);
+    std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(|dir| dir.join(&file_name)))
+        .filter(|path| path.exists())
+        .unwrap_or_else(|| PathBuf::from(file_name))
+}
+
+async fn wait_for_port(addr: &str, timeout: Duration) {
+    let deadline = Instant::now() + timeout;
+    while Instant::now() < deadline {
+        if TcpStream::connect(addr).await.is_ok() {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(250)).await;
+    }
+    warn!("{} still not accepting connections, starting anyway", addr);
+}
+
+// Sleep before a restart; false when shutdown was requested meanwhile
+async fn wait_to_restart(
+    backoff: &mut Duration,
+    started: Instant,
+    shutdown: &mut watch::Receiver<bool>,
+) -> bool {
+    if started.elapsed() > STABLE_AFTER {
+        *backoff = MIN_BACKOFF;
+    }
+    let delay = *backoff;
+    *backoff = (*backoff * 2).min(MAX_BACKOFF);
+
+    tokio::select! {
+        _ = tokio::time::sleep(delay) => true,
+        _ = shutdown.changed() => false,
+    }
+}
+
+async fn supervise_process(
+    name: &'static str,
+    spec: ProcessSpec,
+    status: StatusMap,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    if let Some(addr) = &spec.wait_for {
+        set_status(&status, name, format!("waiting for {}", addr));
+        wait_for_port(addr, ANVIL_STARTUP_TIMEOUT).await;
+    }
+
+    let mut backoff = MIN_BACKOFF;
+    loop {
+        let mut command = Command::new(&spec.program);
+        command
+            .args(&spec.args)
+            .envs(spec.env.iter().cloned())
+            .kill_on_drop(true);
+        if let Some(dir) = &spec.working_dir {
+            command.current_dir(dir);
+        }
+
+        let started = Instant::now();
+        match command.spawn() {
+            Ok(mut child) => {
+                let pid = child.id().unwrap_or_default();
+                info!("Started {} (pid {})", name, pid);
+                set_status(&status, name, format!("running (pid {})", pid));
+
+                tokio::select! {
+                    exit = child.wait() => {
+                        let exit = exit
+                            .map(|code| code.to_string())
+                            .unwrap_or_else(|e| e.to_string());
+                        warn!("{} exited ({}), restarting", name, exit);
+                        set_status(&status, name, format!("exited ({}), restarting", exit));
+                    }
+                    _ = shutdown.changed() => {
+                        let _ = child.kill().await;
+                        set_status(&status, name, "stopped".to_string());
+                        return;
+                    }
+                }
+            }
+            Err(e) => {
+                warn!("Failed to start {}: {}", name, e);
+                set_status(
+                    &status,
+                    name,
+                    format!("failed to start {}: {}", spec.program.display(), e),
+                );
+            }
+        }
+
+        if !wait_to_restart(&mut backoff, started, &mut shutdown).await {
+            set_status(&status, name, "stopped".to_string());
+            return;
+        }
+    }
+}
+
+async fn supervise_in_process(
+    addr: String,
+    wait_for: Option<String>,
+    status: StatusMap,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    const NAME: &str = "mcp-server";
+    if let Some(rpc_addr) = &wait_for {
+        set_status(&status, NAME, format!("waiting for {}", rpc_addr));
+        wait_for_port(rpc_addr, ANVIL_STARTUP_TIMEOUT).await;
+    }
+
+    let mut backoff = MIN_BACKOFF;
+    loop {
+        let started = Instant::now();
+        // Building the server loads the RAG index and panics on bad config, so keep it
+        // off the async workers and turn panics into errors
+        let result = match tokio::task::spawn_blocking(mcp_server::server_from_env).await {
+            Ok(Ok(server)) => {
+                info!("Running MCP server in process on {}", addr);
+                set_status(&status, NAME, format!("running in process on {}", addr));
+                tokio::select! {
+                    result = server.run(&addr) => result,
+                    _ = shutdown.changed() => {
+                        set_status(&status, NAME, "stopped".to_string());
+                        return;
+                    }
+                }
+            }
+            Ok(Err(e)) => Err(e),
+            Err(e) => Err(anyhow!("Server setup panicked: {}", e)),
+        };
+
+        if let Err(e) = result {
+            warn!("In-process MCP server stopped: {}", e);
+            set_status(&status, NAME, format!("failed: {}, restarting", e));
+        }
+        if !wait_to_restart(&mut backoff, started, &mut shutdown).await {
+            set_status(&status, NAME, "stopped".to_string());
+            return;
+        }
+    }
+}
@@ -3,3 +3,4 @@ pub mod client;
 pub mod commands;
 pub mod mcp_client;
 pub mod repl;
+pub mod embedded;
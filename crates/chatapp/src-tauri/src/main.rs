@@ -2,9 +2,13 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 use anyhow::Result;
 use app_lib::client::RIGClient;
+use app_lib::embedded::{EmbeddedServer, ServerMode, ServerSettings};
+use app_lib::mcp_client::MCPClient;
 use clap::Parser;
 use dotenv::dotenv;
-use tauri::State;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tauri::{Manager, RunEvent, State};
 use tracing::info;
 
 #[derive(Parser)]
@@ -15,10 +19,18 @@ struct Args {
 
     #[arg(short, long, env = "ANTHROPIC_API_KEY")]
     api_key: String,
+
+    /// Server settings file; defaults to server_settings.json in the app's config directory
+    #[arg(long, env = "SERVER_SETTINGS")]
+    settings: Option<PathBuf>,
 }
 
 struct AppState {
     client: RIGClient,
+    mcp_server: String,
+    settings_path: PathBuf,
+    // Taken on exit to stop what the app started
+    embedded: Mutex<Option<EmbeddedServer>>,
 }
 
 #[tauri::command]
@@ -32,20 +44,94 @@ fn process_command(command: String, state: State<'_, AppState>) -> Result<String
     }
 }
 
+// A client in the chat's session, so payloads follow its display preferences
+fn session_client(state: &AppState) -> Result<MCPClient, String> {
+    MCPClient::new(&state.mcp_server)
+        .map(|client| client.with_session_id(state.client.session_id()))
+        .map_err(|e| e.to_string())
+}
+
+// Balance time series for the frontend charts, fetched straight from the MCP server
+#[tauri::command]
+fn get_balance_history(
+    account: String,
+    range: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<serde_json::Value, String> {
+    let mcp_client = session_client(&state)?;
+    let params = serde_json::json!({
+        "account": account,
+        "range": range.unwrap_or_else(|| "30d".to_string()),
+    });
+    futures::executor::block_on(mcp_client.get_balance_history(params)).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_display_preferences(state: State<'_, AppState>) -> Result<serde_json::Value, String> {
+    let mcp_client = session_client(&state)?;
+    futures::executor::block_on(mcp_client.get_display_preferences(serde_json::json!({})))
+        .map_err(|e| e.to_string())
+}
+
+// Changes only the given preferences; the chat and later payloads pick them up
+#[tauri::command]
+fn set_display_preferences(
+    preferences: serde_json::Value,
+    state: State<'_, AppState>,
+) -> Result<serde_json::Value, String> {
+    let mcp_client = session_client(&state)?;
+    futures::executor::block_on(mcp_client.set_display_preferences(preferences))
+        .map_err(|e| e.to_string())
+}
+
+// Current server settings and the state of anything the app runs itself
+#[tauri::command]
+fn get_server_settings(state: State<'_, AppState>) -> Result<serde_json::Value, String> {
+    let settings = ServerSettings::load(&state.settings_path).map_err(|e| e.to_string())?;
+    let status = state
+        .embedded
+        .lock()
+        .ok()
+        .and_then(|embedded| embedded.as_ref().map(|embedded| embedded.status()))
+        .unwrap_or_else(|| serde_json::json!({}));
+    Ok(serde_json::json!({
+        "settings": settings,
+        "status": status,
+        "mcp_server": state.mcp_server,
+        "settings_path": state.settings_path,
+    }))
+}
+
+// Saved settings apply the next time the app starts
+#[tauri::command]
+fn save_server_settings(
+    settings: ServerSettings,
+    state: State<'_, AppState>,
+) -> Result<String, String> {
+    settings
+        .save(&state.settings_path)
+        .map_err(|e| e.to_string())?;
+    Ok("Settings saved. Restart the app to apply them.".to_string())
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 #[tokio::main]
 async fn main() -> Result<()> {
     dotenv().ok();
 
     let args = Args::parse();
-    info!("MCP Server: {}", args.mcp_server);
-
-    let client = RIGClient::new(&args.mcp_server, &args.api_key)?;
+    let runtime = tokio::runtime::Handle::current();
 
-    tauri::Builder::default()
-        .manage(AppState { client: client })
-        .invoke_handler(tauri::generate_handler![process_command])
-        .setup(|app| {
+    let app = tauri::Builder::default()
+        .invoke_handler(tauri::generate_handler![
+            process_command,
+            get_balance_history,
+            get_display_preferences,
+            set_display_preferences,
+            get_server_settings,
+            save_server_settings
+        ])
+        .setup(move |app| {
             if cfg!(debug_assertions) {
                 app.handle().plugin(
                     tauri_plugin_log::Builder::default()
@@ -53,10 +139,51 @@ async fn main() -> Result<()> {
                         .build(),
                 )?;
             }
+
+            let settings_path = match &args.settings {
+                Some(path) => path.clone(),
+                None => app.path().app_config_dir()?.join("server_settings.json"),
+            };
+            let settings = ServerSettings::load(&settings_path)?;
+
+            // Embedded modes talk to the server the app starts, external mode to --mcp-server
+            let mcp_server = match settings.mode {
+                ServerMode::External => args.mcp_server.clone(),
+                _ => settings.server_addr.clone(),
+            };
+            info!("MCP Server: {} ({:?} mode)", mcp_server, settings.mode);
+
+            let embedded = {
+                let _guard = runtime.enter();
+                EmbeddedServer::start(&settings)?
+            };
+            let client = RIGClient::new(&mcp_server, &args.api_key)?;
+
+            app.manage(AppState {
+                client,
+                mcp_server,
+                settings_path,
+                embedded: Mutex::new(Some(embedded)),
+            });
             Ok(())
         })
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application");
+
+    app.run(|app_handle, event| {
+        if let RunEvent::Exit = event {
+            // Stop Anvil and the server so they don't outlive the app
+            let state = app_handle.state::<AppState>();
+            let embedded = state
+                .embedded
+                .lock()
+                .ok()
+                .and_then(|mut embedded| embedded.take());
+            if let Some(embedded) = embedded {
+                futures::executor::block_on(embedded.shutdown());
+            }
+        }
+    });
 
     Ok(())
 }
@@ -1,12 +1,31 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use serde_json::{json, Value};
+use std::io;
 use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
 use tokio::net::TcpStream;
+use tracing::warn;
+
+// Reconnect attempts after the server goes away, with doubling delays between them
+const MAX_RECONNECT_ATTEMPTS: u32 = 5;
+const RECONNECT_DELAY: Duration = Duration::from_millis(500);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(8);
+
+// How a request failed at the connection level
+enum ConnectionError {
+    // The server never received the request, so any method is safe to resend
+    NotSent(io::Error),
+    // The connection dropped after the request was written; the server may have run it
+    Lost(io::Error),
+}
 
 pub struct MCPClient {
     server_addr: String,
     request_id: AtomicU64,
+    auth_token: Option<String>,
+    // Ties requests to this conversation so server-side variables resolve
+    session_id: String,
 }
 
 impl MCPClient {
@@ -14,31 +33,78 @@ impl MCPClient {
         Ok(Self {
             server_addr: server_addr.to_string(),
             request_id: AtomicU64::new(1),
+            // Identifies the tenant namespace on multi-tenant servers
+            auth_token: std::env::var("MCP_AUTH_TOKEN").ok(),
+            session_id: uuid::Uuid::new_v4().to_string(),
         })
     }
 
+    /// Join an existing session so requests share its variables and display preferences
+    pub fn with_session_id(mut self, session_id: &str) -> Self {
+        self.session_id = session_id.to_string();
+        self
+    }
+
+    pub fn session_id(&self) -> &str {
+        &self.session_id
+    }
+
     async fn send_request(&self, method: &str, params: Value) -> Result<Value> {
         let id = self.request_id.fetch_add(1, Ordering::SeqCst);
 
-        let request = json!({
+        let mut request = json!({
             "jsonrpc": "2.0",
             "id": id,
             "method": method,
-            "params": params
+            "params": params,
+            "session_id": self.session_id
         });
+        if let Some(token) = &self.auth_token {
+            request["auth_token"] = json!(token);
+        }
 
         let request_str = serde_json::to_string(&request)?;
+        let replayable = !shared::WRITE_METHODS.contains(&method);
 
-        let stream = TcpStream::connect(&self.server_addr).await?;
-        let (reader, mut writer) = stream.into_split();
-
-        writer.write_all(request_str.as_bytes()).await?;
-        writer.write_all(b"\n").await?;
-
-        let mut reader = BufReader::new(reader);
-        let mut line = String::new();
+        let mut delay = RECONNECT_DELAY;
+        let mut attempt = 0;
+        let line = loop {
+            let error = match self.exchange(&request_str).await {
+                Ok(line) => break line,
+                Err(ConnectionError::NotSent(e)) => e,
+                Err(ConnectionError::Lost(e)) if replayable => e,
+                Err(ConnectionError::Lost(e)) => {
+                    // Resending a write could submit the transaction twice
+                    let state = if self.reconnect().await {
+                        "Reconnected"
+                    } else {
+                        "The server is still unreachable"
+                    };
+                    return Err(anyhow!(
+                        "Connection to the MCP server was lost during {} ({}), so it may or may not have been executed. {}; check the account's activity or balance, then retry the write if it didn't go through",
+                        method,
+                        e,
+                        state
+                    ));
+                }
+            };
 
-        reader.read_line(&mut line).await?;
+            attempt += 1;
+            if attempt > MAX_RECONNECT_ATTEMPTS {
+                return Err(anyhow!(
+                    "MCP server at {} is unreachable after {} attempts: {}",
+                    self.server_addr,
+                    attempt,
+                    error
+                ));
+            }
+            warn!(
+                "MCP server connection failed ({}), retrying {} in {:?}",
+                error, method, delay
+            );
+            tokio::time::sleep(delay).await;
+            delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+        };
 
         let response: Value = serde_json::from_str(&line)?;
 
@@ -49,6 +115,47 @@ impl MCPClient {
         Ok(response["result"].clone())
     }
 
+    // Send one request line and read the response line on a fresh connection
+    async fn exchange(&self, request_str: &str) -> Result<String, ConnectionError> {
+        let stream = TcpStream::connect(&self.server_addr)
+            .await
+            .map_err(ConnectionError::NotSent)?;
+        let (reader, mut writer) = stream.into_split();
+
+        writer
+            .write_all(format!("{}\n", request_str).as_bytes())
+            .await
+            .map_err(ConnectionError::NotSent)?;
+
+        let mut reader = BufReader::new(reader);
+        let mut line = String::new();
+        let read = reader
+            .read_line(&mut line)
+            .await
+            .map_err(ConnectionError::Lost)?;
+        if read == 0 {
+            return Err(ConnectionError::Lost(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "server closed the connection without responding",
+            )));
+        }
+
+        Ok(line)
+    }
+
+    // Wait for the server to accept connections again; false if it doesn't come back
+    async fn reconnect(&self) -> bool {
+        let mut delay = RECONNECT_DELAY;
+        for _ in 0..MAX_RECONNECT_ATTEMPTS {
+            if TcpStream::connect(&self.server_addr).await.is_ok() {
+                return true;
+            }
+            tokio::time::sleep(delay).await;
+            delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+        }
+        false
+    }
+
     pub async fn get_balance(&self, params: Value) -> Result<Value> {
         self.send_request("get_balance", params).await
     }
@@ -80,4 +187,457 @@ impl MCPClient {
     pub async fn get_document(&self, params: Value) -> Result<Value> {
         self.send_request("get_document", params).await
     }
+
+    pub async fn check_token_safety(&self, params: Value) -> Result<Value> {
+        self.send_request("check_token_safety", params).await
+    }
+
+    pub async fn preview_approval(&self, params: Value) -> Result<Value> {
+        self.send_request("preview_approval", params).await
+    }
+
+    pub async fn summarize_activity(&self, params: Value) -> Result<Value> {
+        self.send_request("summarize_activity", params).await
+    }
+
+    pub async fn list_networks(&self, params: Value) -> Result<Value> {
+        self.send_request("list_networks", params).await
+    }
+
+    pub async fn switch_network(&self, params: Value) -> Result<Value> {
+        self.send_request("switch_network", params).await
+    }
+
+    pub async fn send_erc20(&self, params: Value) -> Result<Value> {
+        self.send_request("send_erc20", params).await
+    }
+
+    pub async fn get_pnl(&self, params: Value) -> Result<Value> {
+        self.send_request("get_pnl", params).await
+    }
+
+    pub async fn estimate_gas(&self, params: Value) -> Result<Value> {
+        self.send_request("estimate_gas", params).await
+    }
+
+    pub async fn get_gas_report(&self, params: Value) -> Result<Value> {
+        self.send_request("get_gas_report", params).await
+    }
+
+    pub async fn get_balance_history(&self, params: Value) -> Result<Value> {
+        self.send_request("get_balance_history", params).await
+    }
+
+    pub async fn get_nft_balance(&self, params: Value) -> Result<Value> {
+        self.send_request("get_nft_balance", params).await
+    }
+
+    pub async fn owner_of(&self, params: Value) -> Result<Value> {
+        self.send_request("owner_of", params).await
+    }
+
+    pub async fn transfer_nft(&self, params: Value) -> Result<Value> {
+        self.send_request("transfer_nft", params).await
+    }
+
+    pub async fn get_historical_balance(&self, params: Value) -> Result<Value> {
+        self.send_request("get_historical_balance", params).await
+    }
+
+    pub async fn get_historical_price(&self, params: Value) -> Result<Value> {
+        self.send_request("get_historical_price", params).await
+    }
+
+    pub async fn estimate_swap(&self, params: Value) -> Result<Value> {
+        self.send_request("estimate_swap", params).await
+    }
+
+    pub async fn describe_capabilities(&self, params: Value) -> Result<Value> {
+        self.send_request("tools/list", params).await
+    }
+
+    pub async fn get_allowance(&self, params: Value) -> Result<Value> {
+        self.send_request("get_allowance", params).await
+    }
+
+    pub async fn set_variable(&self, params: Value) -> Result<Value> {
+        self.send_request("set_variable", params).await
+    }
+
+    pub async fn unset_variable(&self, params: Value) -> Result<Value> {
+        self.send_request("unset_variable", params).await
+    }
+
+    pub async fn list_variables(&self, params: Value) -> Result<Value> {
+        self.send_request("list_variables", params).await
+    }
+
+    pub async fn save_template(&self, params: Value) -> Result<Value> {
+        self.send_request("save_template", params).await
+    }
+
+    pub async fn run_template(&self, params: Value) -> Result<Value> {
+        self.send_request("run_template", params).await
+    }
+
+    pub async fn list_templates(&self, params: Value) -> Result<Value> {
+        self.send_request("list_templates", params).await
+    }
+
+    pub async fn delete_template(&self, params: Value) -> Result<Value> {
+        self.send_request("delete_template", params).await
+    }
+
+    pub async fn schedule_job(&self, params: Value) -> Result<Value> {
+        self.send_request("schedule_job", params).await
+    }
+
+    pub async fn list_jobs(&self, params: Value) -> Result<Value> {
+        self.send_request("list_jobs", params).await
+    }
+
+    pub async fn cancel_job(&self, params: Value) -> Result<Value> {
+        self.send_request("cancel_job", params).await
+    }
+
+    pub async fn get_notifications(&self, params: Value) -> Result<Value> {
+        self.send_request("get_notifications", params).await
+    }
+
+    pub async fn get_portfolio(&self, params: Value) -> Result<Value> {
+        self.send_request("get_portfolio", params).await
+    }
+
+    pub async fn get_transaction_history(&self, params: Value) -> Result<Value> {
+        self.send_request("get_transaction_history", params).await
+    }
+
+    pub async fn get_events(&self, params: Value) -> Result<Value> {
+        self.send_request("get_events", params).await
+    }
+
+    pub async fn set_display_preferences(&self, params: Value) -> Result<Value> {
+        self.send_request("set_display_preferences", params).await
+    }
+
+    pub async fn get_display_preferences(&self, params: Value) -> Result<Value> {
+        self.send_request("get_display_preferences", params).await
+    }
+
+    pub async fn deploy_contract(&self, params: Value) -> Result<Value> {
+        self.send_request("deploy_contract", params).await
+    }
+
+    pub async fn convert_units(&self, params: Value) -> Result<Value> {
+        self.send_request("convert_units", params).await
+    }
+
+    pub async fn call_contract(&self, params: Value) -> Result<Value> {
+        self.send_request("call_contract", params).await
+    }
+
+    pub async fn broadcast_raw_transaction(&self, params: Value) -> Result<Value> {
+        self.send_request("broadcast_raw_transaction", params).await
+    }
+
+    pub async fn write_contract(&self, params: Value) -> Result<Value> {
+        self.send_request("write_contract", params).await
+    }
+
+    pub async fn prepare_unsigned_transaction(&self, params: Value) -> Result<Value> {
+        self.send_request("prepare_unsigned_transaction", params)
+            .await
+    }
+
+    pub async fn attach_signature(&self, params: Value) -> Result<Value> {
+        self.send_request("attach_signature", params).await
+    }
+
+    pub async fn decode_calldata(&self, params: Value) -> Result<Value> {
+        self.send_request("decode_calldata", params).await
+    }
+
+    pub async fn parse_payment_uri(&self, params: Value) -> Result<Value> {
+        self.send_request("parse_payment_uri", params).await
+    }
+
+    pub async fn get_transaction(&self, params: Value) -> Result<Value> {
+        self.send_request("get_transaction", params).await
+    }
+
+    pub async fn get_inbox(&self, params: Value) -> Result<Value> {
+        self.send_request("get_inbox", params).await
+    }
+
+    pub async fn sign_message(&self, params: Value) -> Result<Value> {
+        self.send_request("sign_message", params).await
+    }
+
+    pub async fn verify_signature(&self, params: Value) -> Result<Value> {
+        self.send_request("verify_signature", params).await
+    }
+
+    pub async fn get_twap(&self, params: Value) -> Result<Value> {
+        self.send_request("get_twap", params).await
+    }
+
+    pub async fn approve_token(&self, params: Value) -> Result<Value> {
+        self.send_request("approve_token", params).await
+    }
+
+    pub async fn revoke_approval(&self, params: Value) -> Result<Value> {
+        self.send_request("revoke_approval", params).await
+    }
+
+    pub async fn send_batch(&self, params: Value) -> Result<Value> {
+        self.send_request("send_batch", params).await
+    }
+
+    pub async fn get_stablecoin_status(&self, params: Value) -> Result<Value> {
+        self.send_request("get_stablecoin_status", params).await
+    }
+
+    pub async fn get_gas_price(&self, params: Value) -> Result<Value> {
+        self.send_request("get_gas_price", params).await
+    }
+
+    pub async fn get_lp_performance(&self, params: Value) -> Result<Value> {
+        self.send_request("get_lp_performance", params).await
+    }
+
+    pub async fn find_yield(&self, params: Value) -> Result<Value> {
+        self.send_request("find_yield", params).await
+    }
+
+    pub async fn get_protocol_health(&self, params: Value) -> Result<Value> {
+        self.send_request("get_protocol_health", params).await
+    }
+
+    pub async fn snapshot_chain(&self, params: Value) -> Result<Value> {
+        self.send_request("snapshot_chain", params).await
+    }
+
+    pub async fn revert_chain(&self, params: Value) -> Result<Value> {
+        self.send_request("revert_chain", params).await
+    }
+
+    pub async fn list_proposals(&self, params: Value) -> Result<Value> {
+        self.send_request("list_proposals", params).await
+    }
+
+    pub async fn get_proposal(&self, params: Value) -> Result<Value> {
+        self.send_request("get_proposal", params).await
+    }
+
+    pub async fn get_voting_power(&self, params: Value) -> Result<Value> {
+        self.send_request("get_voting_power", params).await
+    }
+
+    pub async fn cast_vote(&self, params: Value) -> Result<Value> {
+        self.send_request("cast_vote", params).await
+    }
+
+    pub async fn increase_time(&self, params: Value) -> Result<Value> {
+        self.send_request("increase_time", params).await
+    }
+
+    pub async fn mine_blocks(&self, params: Value) -> Result<Value> {
+        self.send_request("mine_blocks", params).await
+    }
+
+    pub async fn set_next_block_timestamp(&self, params: Value) -> Result<Value> {
+        self.send_request("set_next_block_timestamp", params).await
+    }
+
+    pub async fn list_snapshot_spaces(&self, params: Value) -> Result<Value> {
+        self.send_request("list_snapshot_spaces", params).await
+    }
+
+    pub async fn list_snapshot_proposals(&self, params: Value) -> Result<Value> {
+        self.send_request("list_snapshot_proposals", params).await
+    }
+
+    pub async fn get_snapshot_proposal(&self, params: Value) -> Result<Value> {
+        self.send_request("get_snapshot_proposal", params).await
+    }
+
+    pub async fn get_snapshot_voting_power(&self, params: Value) -> Result<Value> {
+        self.send_request("get_snapshot_voting_power", params).await
+    }
+
+    pub async fn cast_snapshot_vote(&self, params: Value) -> Result<Value> {
+        self.send_request("cast_snapshot_vote", params).await
+    }
+
+    pub async fn check_claims(&self, params: Value) -> Result<Value> {
+        self.send_request("check_claims", params).await
+    }
+
+    pub async fn claim_airdrop(&self, params: Value) -> Result<Value> {
+        self.send_request("claim_airdrop", params).await
+    }
+
+    pub async fn fund_account(&self, params: Value) -> Result<Value> {
+        self.send_request("fund_account", params).await
+    }
+
+    pub async fn get_vesting(&self, params: Value) -> Result<Value> {
+        self.send_request("get_vesting", params).await
+    }
+
+    pub async fn release_vested(&self, params: Value) -> Result<Value> {
+        self.send_request("release_vested", params).await
+    }
+
+    pub async fn create_stream(&self, params: Value) -> Result<Value> {
+        self.send_request("create_stream", params).await
+    }
+
+    pub async fn cancel_stream(&self, params: Value) -> Result<Value> {
+        self.send_request("cancel_stream", params).await
+    }
+
+    pub async fn get_streams(&self, params: Value) -> Result<Value> {
+        self.send_request("get_streams", params).await
+    }
+
+    pub async fn deploy_token(&self, params: Value) -> Result<Value> {
+        self.send_request("deploy_token", params).await
+    }
+
+    pub async fn compute_address(&self, params: Value) -> Result<Value> {
+        self.send_request("compute_address", params).await
+    }
+
+    pub async fn get_smart_account(&self, params: Value) -> Result<Value> {
+        self.send_request("get_smart_account", params).await
+    }
+
+    pub async fn deploy_smart_account(&self, params: Value) -> Result<Value> {
+        self.send_request("deploy_smart_account", params).await
+    }
+
+    pub async fn estimate_user_operation(&self, params: Value) -> Result<Value> {
+        self.send_request("estimate_user_operation", params).await
+    }
+
+    pub async fn send_user_operation(&self, params: Value) -> Result<Value> {
+        self.send_request("send_user_operation", params).await
+    }
+
+    pub async fn get_tx_status(&self, params: Value) -> Result<Value> {
+        self.send_request("get_tx_status", params).await
+    }
+
+    pub async fn grant_session_key(&self, params: Value) -> Result<Value> {
+        self.send_request("grant_session_key", params).await
+    }
+
+    pub async fn list_session_keys(&self, params: Value) -> Result<Value> {
+        self.send_request("list_session_keys", params).await
+    }
+
+    pub async fn revoke_session_key(&self, params: Value) -> Result<Value> {
+        self.send_request("revoke_session_key", params).await
+    }
+
+    pub async fn list_pending_transactions(&self, params: Value) -> Result<Value> {
+        self.send_request("list_pending_transactions", params).await
+    }
+
+    pub async fn pair_wallet(&self, params: Value) -> Result<Value> {
+        self.send_request("pair_wallet", params).await
+    }
+
+    pub async fn list_wallets(&self, params: Value) -> Result<Value> {
+        self.send_request("list_wallets", params).await
+    }
+
+    pub async fn send_with_wallet(&self, params: Value) -> Result<Value> {
+        self.send_request("send_with_wallet", params).await
+    }
+
+    pub async fn disconnect_wallet(&self, params: Value) -> Result<Value> {
+        self.send_request("disconnect_wallet", params).await
+    }
+
+    pub async fn add_top_up_rule(&self, params: Value) -> Result<Value> {
+        self.send_request("add_top_up_rule", params).await
+    }
+
+    pub async fn list_top_up_rules(&self, params: Value) -> Result<Value> {
+        self.send_request("list_top_up_rules", params).await
+    }
+
+    pub async fn remove_top_up_rule(&self, params: Value) -> Result<Value> {
+        self.send_request("remove_top_up_rule", params).await
+    }
+
+    pub async fn get_pool_info(&self, params: Value) -> Result<Value> {
+        self.send_request("get_pool_info", params).await
+    }
+
+    pub async fn consolidate_dust(&self, params: Value) -> Result<Value> {
+        self.send_request("consolidate_dust", params).await
+    }
+
+    pub async fn add_liquidity(&self, params: Value) -> Result<Value> {
+        self.send_request("add_liquidity", params).await
+    }
+
+    pub async fn remove_liquidity(&self, params: Value) -> Result<Value> {
+        self.send_request("remove_liquidity", params).await
+    }
+
+    pub async fn list_token_migrations(&self, params: Value) -> Result<Value> {
+        self.send_request("list_token_migrations", params).await
+    }
+
+    pub async fn migrate_token(&self, params: Value) -> Result<Value> {
+        self.send_request("migrate_token", params).await
+    }
+
+    pub async fn get_lending_position(&self, params: Value) -> Result<Value> {
+        self.send_request("get_lending_position", params).await
+    }
+
+    pub async fn aave_supply(&self, params: Value) -> Result<Value> {
+        self.send_request("aave_supply", params).await
+    }
+
+    pub async fn aave_withdraw(&self, params: Value) -> Result<Value> {
+        self.send_request("aave_withdraw", params).await
+    }
+
+    pub async fn aave_borrow(&self, params: Value) -> Result<Value> {
+        self.send_request("aave_borrow", params).await
+    }
+
+    pub async fn aave_repay(&self, params: Value) -> Result<Value> {
+        self.send_request("aave_repay", params).await
+    }
+
+    pub async fn refresh_token_list(&self, params: Value) -> Result<Value> {
+        self.send_request("refresh_token_list", params).await
+    }
+
+    pub async fn forget_discovered_tokens(&self, params: Value) -> Result<Value> {
+        self.send_request("forget_discovered_tokens", params).await
+    }
+
+    pub async fn index_status(&self, params: Value) -> Result<Value> {
+        self.send_request("index_status", params).await
+    }
+
+    pub async fn get_chain_info(&self, params: Value) -> Result<Value> {
+        self.send_request("get_chain_info", params).await
+    }
+
+    pub async fn get_block(&self, params: Value) -> Result<Value> {
+        self.send_request("get_block", params).await
+    }
+
+    pub async fn get_contract_source(&self, params: Value) -> Result<Value> {
+        self.send_request("get_contract_source", params).await
+    }
 }
@@ -1,8 +1,9 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use colored::*;
 use rustyline::error::ReadlineError;
 use rustyline::history::DefaultHistory;
 use rustyline::Editor;
+use serde_json::json;
 
 use crate::agent::BlockchainAgent;
 
@@ -47,6 +48,11 @@ impl REPL {
                         "help" => {
                             self.print_help();
                         }
+                        _ if line == "prefs" || line.starts_with("prefs ") => {
+                            if let Err(e) = self.handle_prefs(line, &agent_clone).await {
+                                println!("{}: {}", "Error".red().bold(), e);
+                            }
+                        }
                         _ => match self.handle_command(line, &mut agent_clone).await {
                             Ok(_) => {}
                             Err(e) => {
@@ -77,6 +83,11 @@ impl REPL {
         println!("{}", "Available Commands:".yellow().bold());
         println!("  {:<20} - {}", "help".cyan(), "Show this help message");
         println!("  {:<20} - {}", "exit".cyan(), "Exit the application");
+        println!("  {:<20} - Show how amounts are displayed", "prefs".cyan());
+        println!(
+            "  {:<20} - Set decimals (0-18 or full), separators, usd (on/off) or gas (wei, gwei, ether)",
+            "prefs <name> <value>".cyan()
+        );
         println!();
         println!("{}", "Example Queries:".yellow().bold());
         println!("  {}", "send 1 ETH from Alice to Bob".cyan());
@@ -85,6 +96,63 @@ impl REPL {
         println!("  {}", "Swap 10 ETH for USDC on Alice's account".cyan());
     }
 
+    // "prefs" shows the display preferences, "prefs decimals 2" changes one
+    async fn handle_prefs(&self, line: &str, agent: &BlockchainAgent) -> Result<()> {
+        let args: Vec<&str> = line.split_whitespace().skip(1).collect();
+        let preferences = match args.as_slice() {
+            [] => agent.display_preferences().await?,
+            [name, value] => {
+                let enabled = || match value.to_lowercase().as_str() {
+                    "on" | "true" | "yes" => Ok(true),
+                    "off" | "false" | "no" => Ok(false),
+                    _ => Err(anyhow!("Use on or off for {}", name)),
+                };
+                let changes = match *name {
+                    "decimals" if *value == "full" => json!({"decimals": null}),
+                    "decimals" => json!({"decimals": value.parse::<u32>()?}),
+                    "separators" => json!({"thousands_separator": enabled()?}),
+                    "usd" => json!({"show_usd": enabled()?}),
+                    "gas" => json!({"gas_unit": value}),
+                    _ => return Err(anyhow!("Unknown preference '{}'", name)),
+                };
+                agent.set_display_preferences(changes).await?
+            }
+            _ => return Err(anyhow!("Usage: prefs [<name> <value>]")),
+        };
+
+        let decimals = match preferences.decimals {
+            Some(decimals) => decimals.to_string(),
+            None => "full".to_string(),
+        };
+        println!("{}", "Display Preferences:".yellow().bold());
+        println!("  {:<12} {}", "decimals".cyan(), decimals);
+        println!(
+            "  {:<12} {}",
+            "separators".cyan(),
+            if preferences.thousands_separator {
+                "on"
+            } else {
+                "off"
+            }
+        );
+        println!(
+            "  {:<12} {}",
+            "usd".cyan(),
+            if preferences.show_usd { "on" } else { "off" }
+        );
+        println!("  {:<12} {}", "gas".cyan(), preferences.gas_unit.as_str());
+        println!(
+            "  {:<12} {} ETH, {}, gas {}",
+            "example".cyan(),
+            preferences.format_amount("1234.56789"),
+            preferences
+                .format_usd(4321.5)
+                .unwrap_or_else(|| "no USD".to_string()),
+            preferences.format_gas_price("12.5")
+        );
+        Ok(())
+    }
+
     async fn handle_command(&self, input: &str, agent: &mut BlockchainAgent) -> Result<String> {
         // Process the command using the agent
         let response = agent.process_message(input).await?;
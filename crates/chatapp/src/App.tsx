@@ -1,5 +1,6 @@
 import { invoke } from '@tauri-apps/api/core'
 import { ChangeEvent, KeyboardEvent, useEffect, useRef, useState } from 'react'
+import ServerSettings from './ServerSettings'
 
 interface Message {
   content: string
@@ -19,6 +20,7 @@ export default function App() {
     }
   ])
   const [isDarkTheme, setIsDarkTheme] = useState(true)
+  const [showSettings, setShowSettings] = useState(false)
   const [commandHistory, setCommandHistory] = useState<string[]>([])
   const [historyIndex, setHistoryIndex] = useState(-1)
 
@@ -163,6 +165,13 @@ export default function App() {
     <div className='app'>
       <div className='header'>
         <h1>AI Blockchain Assistant</h1>
+        <button
+          className='theme-toggle settings-toggle'
+          onClick={() => setShowSettings(!showSettings)}
+          title='Server settings'
+        >
+          ⚙️
+        </button>
         <button
           className='theme-toggle'
           onClick={toggleTheme}
@@ -172,6 +181,8 @@ export default function App() {
         </button>
       </div>
 
+      {showSettings && <ServerSettings onClose={() => setShowSettings(false)} />}
+
       <div className='chat-container'>
         <div className='messages' id='messages' ref={messagesRef}>
           {history.map((message, index) => (
@@ -0,0 +1,167 @@
+import { invoke } from '@tauri-apps/api/core'
+import { useEffect, useState } from 'react'
+
+type ServerMode = 'external' | 'child_process' | 'in_process'
+
+interface AnvilSettings {
+  binary: string
+  port: number
+  fork_url: string | null
+}
+
+interface Settings {
+  mode: ServerMode
+  server_addr: string
+  server_binary: string | null
+  working_dir: string | null
+  anvil: AnvilSettings | null
+}
+
+interface SettingsResponse {
+  settings: Settings
+  status: Record<string, string>
+  mcp_server: string
+  settings_path: string
+}
+
+const MODES: { value: ServerMode; label: string }[] = [
+  { value: 'external', label: 'Connect to a server I run myself' },
+  { value: 'child_process', label: 'Start the server as a separate process' },
+  { value: 'in_process', label: 'Run the server inside the app' }
+]
+
+const DEFAULT_ANVIL: AnvilSettings = { binary: 'anvil', port: 8545, fork_url: null }
+
+export default function ServerSettings({ onClose }: { onClose: () => void }) {
+  const [data, setData] = useState<SettingsResponse | null>(null)
+  const [message, setMessage] = useState('')
+
+  useEffect(() => {
+    invoke<SettingsResponse>('get_server_settings')
+      .then(setData)
+      .catch(error => setMessage(`❌ ${error}`))
+  }, [])
+
+  if (!data) {
+    return <div className='settings-panel'>{message || 'Loading settings...'}</div>
+  }
+
+  const settings = data.settings
+  const update = (changes: Partial<Settings>) =>
+    setData({ ...data, settings: { ...settings, ...changes } })
+  const updateAnvil = (changes: Partial<AnvilSettings>) =>
+    update({ anvil: { ...(settings.anvil ?? DEFAULT_ANVIL), ...changes } })
+
+  const save = async () => {
+    try {
+      setMessage(`✅ ${await invoke<string>('save_server_settings', { settings })}`)
+    } catch (error) {
+      setMessage(`❌ ${error}`)
+    }
+  }
+
+  return (
+    <div className='settings-panel'>
+      <h2>Server</h2>
+      <p className='settings-hint'>
+        Connected to {data.mcp_server}. Settings are stored in {data.settings_path}.
+      </p>
+
+      <label>
+        Mode
+        <select
+          value={settings.mode}
+          onChange={e => update({ mode: e.target.value as ServerMode })}
+        >
+          {MODES.map(mode => (
+            <option key={mode.value} value={mode.value}>
+              {mode.label}
+            </option>
+          ))}
+        </select>
+      </label>
+
+      {settings.mode !== 'external' && (
+        <>
+          <label>
+            Server address
+            <input
+              value={settings.server_addr}
+              onChange={e => update({ server_addr: e.target.value })}
+            />
+          </label>
+          <label>
+            Data directory (contains the server's data folder)
+            <input
+              value={settings.working_dir ?? ''}
+              placeholder='Current directory'
+              onChange={e => update({ working_dir: e.target.value || null })}
+            />
+          </label>
+        </>
+      )}
+
+      {settings.mode === 'child_process' && (
+        <label>
+          mcp-server executable
+          <input
+            value={settings.server_binary ?? ''}
+            placeholder='Bundled with the app, or mcp-server on PATH'
+            onChange={e => update({ server_binary: e.target.value || null })}
+          />
+        </label>
+      )}
+
+      <label className='settings-checkbox'>
+        <input
+          type='checkbox'
+          checked={settings.anvil !== null}
+          onChange={e => update({ anvil: e.target.checked ? DEFAULT_ANVIL : null })}
+        />
+        Start a local Anvil node
+      </label>
+
+      {settings.anvil && (
+        <>
+          <label>
+            Anvil port
+            <input
+              type='number'
+              value={settings.anvil.port}
+              onChange={e => updateAnvil({ port: Number(e.target.value) })}
+            />
+          </label>
+          <label>
+            Fork URL
+            <input
+              value={settings.anvil.fork_url ?? ''}
+              placeholder='Empty for a fresh chain'
+              onChange={e => updateAnvil({ fork_url: e.target.value || null })}
+            />
+          </label>
+        </>
+      )}
+
+      {Object.keys(data.status).length > 0 && (
+        <ul className='settings-status'>
+          {Object.entries(data.status).map(([name, status]) => (
+            <li key={name}>
+              <strong>{name}</strong>: {status}
+            </li>
+          ))}
+        </ul>
+      )}
+
+      {message && <p className='settings-hint'>{message}</p>}
+
+      <div className='settings-actions'>
+        <button className='send-button' onClick={save}>
+          Save
+        </button>
+        <button className='send-button' onClick={onClose}>
+          Close
+        </button>
+      </div>
+    </div>
+  )
+}
@@ -0,0 +1,432 @@
+use anyhow::{Result, anyhow};
+use ethers::abi::{Abi, Token, parse_abi};
+use ethers::contract::Contract;
+use ethers::providers::Provider;
+use ethers::types::{Address, U256};
+use ethers::utils::format_units;
+use serde::{Deserialize, Serialize};
+use shared::{Account, ContractCall, TransactionResult, utils};
+use std::str::FromStr;
+
+use crate::blockchain::{BlockchainService, FeeOverrides, TokenInfo};
+use crate::failover::FailoverClient;
+
+// Aave V3 Pool deployments; AAVE_V3_POOL overrides them
+const AAVE_V3_POOL: &[(u64, &str)] = &[
+    (1, "0x87870Bca3F3fD6335C3F4ce8392D69350B4fA4E2"),
+    (10, "0x794a61358D6845594F94dc1DB02A252b5b4814aD"),
+    (137, "0x794a61358D6845594F94dc1DB02A252b5b4814aD"),
+    (8453, "0xA238Dd80C259a72e81d7e4664a9801593F98d1c5"),
+    (42161, "0x794a61358D6845594F94dc1DB02A252b5b4814aD"),
+    (11155111, "0x6Ae43d3271ff6888e7Fc43Fd7321a503ff738951"),
+];
+const POOL_ABI: &[&str] = &[
+    "function getUserAccountData(address user) view returns (uint256 totalCollateralBase, uint256 totalDebtBase, uint256 availableBorrowsBase, uint256 currentLiquidationThreshold, uint256 ltv, uint256 healthFactor)",
+    "function getReservesList() view returns (address[])",
+    "function getReserveData(address asset) view returns (((uint256),uint128,uint128,uint128,uint128,uint128,uint40,uint16,address,address,address,address,uint128,uint128,uint128))",
+];
+const SUPPLY: &str = "supply(address,uint256,address,uint16)";
+const WITHDRAW: &str = "withdraw(address,uint256,address)";
+const BORROW: &str = "borrow(address,uint256,uint256,uint16,address)";
+const REPAY: &str = "repay(address,uint256,uint256,address)";
+// Stable rate borrowing is switched off on V3 markets
+const VARIABLE_RATE: &str = "2";
+// The pool reports USD values with 8 decimals
+const BASE_DECIMALS: u32 = 8;
+/// Positions below this health factor get a warning; they're liquidated at 1.0
+pub const HEALTH_FACTOR_WARNING: f64 = 1.5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LendingAction {
+    Supply,
+    Withdraw,
+    Borrow,
+    Repay,
+}
+
+/// An asset an account has supplied to or borrowed from the pool
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReservePosition {
+    pub token: String,
+    pub symbol: String,
+    pub supplied: String,
+    /// Variable rate debt, including accrued interest
+    pub borrowed: String,
+    pub supply_apr_percent: f64,
+    pub borrow_apr_percent: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LendingPosition {
+    pub address: String,
+    pub chain_id: u64,
+    pub pool: String,
+    pub total_collateral_usd: f64,
+    pub total_debt_usd: f64,
+    pub available_borrows_usd: f64,
+    pub liquidation_threshold_percent: f64,
+    pub ltv_percent: f64,
+    /// None without debt; the position is liquidated below 1.0
+    pub health_factor: Option<f64>,
+    pub reserves: Vec<ReservePosition>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LendingResult {
+    pub action: LendingAction,
+    pub symbol: String,
+    /// "all" when the whole supply was withdrawn or the whole debt repaid
+    pub amount: String,
+    /// Approval first when one was needed, then the pool call
+    pub transactions: Vec<TransactionResult>,
+    pub total_collateral_usd: f64,
+    pub total_debt_usd: f64,
+    pub health_factor: Option<f64>,
+    pub warning: Option<String>,
+}
+
+// getUserAccountData, in USD and percent
+struct AccountData {
+    total_collateral_usd: f64,
+    total_debt_usd: f64,
+    available_borrows_usd: f64,
+    liquidation_threshold_percent: f64,
+    ltv_percent: f64,
+    health_factor: Option<f64>,
+}
+
+// The parts of getReserveData used here
+struct Reserve {
+    a_token: Address,
+    variable_debt_token: Address,
+    liquidity_rate: U256,
+    variable_borrow_rate: U256,
+}
+
+fn pool_address(chain_id: u64) -> Result<Address> {
+    if let Ok(address) = std::env::var("AAVE_V3_POOL") {
+        return Address::from_str(&address)
+            .map_err(|_| anyhow!("Invalid AAVE_V3_POOL address: {}", address));
+    }
+    // Anvil forking mainnet keeps mainnet contracts
+    let chain_id = if chain_id == 31337 { 1 } else { chain_id };
+    AAVE_V3_POOL
+        .iter()
+        .find(|(chain, _)| *chain == chain_id)
+        .map(|(_, address)| Address::from_str(address))
+        .transpose()?
+        .ok_or_else(|| {
+            anyhow!(
+                "No Aave V3 pool known for chain {}; set AAVE_V3_POOL",
+                chain_id
+            )
+        })
+}
+
+fn pool(blockchain_service: &BlockchainService) -> Result<Contract<Provider<FailoverClient>>> {
+    let abi: Abi = parse_abi(POOL_ABI)?;
+    Ok(Contract::new(
+        pool_address(blockchain_service.chain_id())?,
+        abi,
+        blockchain_service.provider(),
+    ))
+}
+
+fn to_f64(value: U256, decimals: u32) -> f64 {
+    format_units(value, decimals)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(0.0)
+}
+
+// Rates are per-second compounded APRs in ray (27 decimals)
+fn apr_percent(rate: U256) -> f64 {
+    to_f64(rate, 27) * 100.0
+}
+
+async fn account_data(
+    pool: &Contract<Provider<FailoverClient>>,
+    user: Address,
+) -> Result<AccountData> {
+    let (collateral, debt, available, threshold, ltv, health_factor): (
+        U256,
+        U256,
+        U256,
+        U256,
+        U256,
+        U256,
+    ) = pool
+        .method::<_, (U256, U256, U256, U256, U256, U256)>("getUserAccountData", user)?
+        .call()
+        .await?;
+    Ok(AccountData {
+        total_collateral_usd: to_f64(collateral, BASE_DECIMALS),
+        total_debt_usd: to_f64(debt, BASE_DECIMALS),
+        available_borrows_usd: to_f64(available, BASE_DECIMALS),
+        liquidation_threshold_percent: threshold.as_u64() as f64 / 100.0,
+        ltv_percent: ltv.as_u64() as f64 / 100.0,
+        health_factor: (!debt.is_zero()).then(|| to_f64(health_factor, 18)),
+    })
+}
+
+async fn reserve(pool: &Contract<Provider<FailoverClient>>, asset: Address) -> Result<Reserve> {
+    let data: Token = pool
+        .method::<_, Token>("getReserveData", asset)?
+        .call()
+        .await?;
+    let fields = match data {
+        Token::Tuple(fields) if fields.len() >= 11 => fields,
+        _ => return Err(anyhow!("Unexpected getReserveData result for {:?}", asset)),
+    };
+    let uint = |index: usize| fields[index].clone().into_uint().unwrap_or_default();
+    let address = |index: usize| fields[index].clone().into_address().unwrap_or_default();
+    let reserve = Reserve {
+        a_token: address(8),
+        variable_debt_token: address(10),
+        liquidity_rate: uint(2),
+        variable_borrow_rate: uint(4),
+    };
+    if reserve.a_token.is_zero() {
+        return Err(anyhow!("{:?} isn't listed on the Aave pool", asset));
+    }
+    Ok(reserve)
+}
+
+/// Collateral, debt, borrowing power and health factor of `address` on the current
+/// network's Aave V3 pool, with every asset it has supplied or borrowed
+pub async fn get_lending_position(
+    blockchain_service: &BlockchainService,
+    address: &str,
+) -> Result<LendingPosition> {
+    let user = Address::from_str(address)?;
+    let pool = pool(blockchain_service)?;
+    let data = account_data(&pool, user).await?;
+
+    let assets: Vec<Address> = pool
+        .method::<_, Vec<Address>>("getReservesList", ())?
+        .call()
+        .await?;
+    let mut reserves = Vec::new();
+    for asset in &assets {
+        reserves.push(reserve(&pool, *asset).await?);
+    }
+    let supplied = blockchain_service
+        .balances_of(
+            user,
+            &reserves.iter().map(|r| r.a_token).collect::<Vec<_>>(),
+        )
+        .await?
+        .tokens;
+    let borrowed = blockchain_service
+        .balances_of(
+            user,
+            &reserves
+                .iter()
+                .map(|r| r.variable_debt_token)
+                .collect::<Vec<_>>(),
+        )
+        .await?
+        .tokens;
+
+    let mut positions = Vec::new();
+    for (index, (asset, reserve)) in assets.iter().zip(&reserves).enumerate() {
+        let supplied = supplied.get(index).copied().flatten().unwrap_or_default();
+        let borrowed = borrowed.get(index).copied().flatten().unwrap_or_default();
+        if supplied.is_zero() && borrowed.is_zero() {
+            continue;
+        }
+        let token = blockchain_service
+            .resolve_token(&format!("{:?}", asset))
+            .await?;
+        positions.push(ReservePosition {
+            supplied: format_units(supplied, token.decimals as u32)?,
+            borrowed: format_units(borrowed, token.decimals as u32)?,
+            supply_apr_percent: apr_percent(reserve.liquidity_rate),
+            borrow_apr_percent: apr_percent(reserve.variable_borrow_rate),
+            token: token.address,
+            symbol: token.symbol,
+        });
+    }
+
+    Ok(LendingPosition {
+        address: address.to_string(),
+        chain_id: blockchain_service.chain_id(),
+        pool: format!("{:?}", pool.address()),
+        total_collateral_usd: data.total_collateral_usd,
+        total_debt_usd: data.total_debt_usd,
+        available_borrows_usd: data.available_borrows_usd,
+        liquidation_threshold_percent: data.liquidation_threshold_percent,
+        ltv_percent: data.ltv_percent,
+        health_factor: data.health_factor,
+        reserves: positions,
+    })
+}
+
+async fn token_balance(
+    blockchain_service: &BlockchainService,
+    token: Address,
+    holder: Address,
+) -> Result<U256> {
+    blockchain_service
+        .balances_of(holder, &[token])
+        .await?
+        .tokens
+        .first()
+        .copied()
+        .flatten()
+        .ok_or_else(|| anyhow!("Couldn't read the balance of {:?}", token))
+}
+
+/// Supply, withdraw, borrow or repay `amount` of `token` on the Aave V3 pool. Withdraw
+/// and repay take everything when `amount` is None or "max". Supplying and repaying
+/// approve the pool for what they move first when needed.
+pub async fn execute(
+    blockchain_service: &BlockchainService,
+    from_account: &Account,
+    action: LendingAction,
+    token: &str,
+    amount: Option<&str>,
+    fees: &FeeOverrides,
+) -> Result<LendingResult> {
+    if token.eq_ignore_ascii_case("eth") {
+        return Err(anyhow!(
+            "The Aave pool takes WETH rather than ETH; wrap it first and use WETH"
+        ));
+    }
+    let holder = Address::from_str(&from_account.address)?;
+    let token: TokenInfo = blockchain_service.resolve_token(token).await?;
+    let asset = Address::from_str(&token.address)?;
+    let pool = pool(blockchain_service)?;
+    let reserve = reserve(&pool, asset).await?;
+
+    let everything = amount.is_none_or(|amount| amount.eq_ignore_ascii_case("max"));
+    let raw = match amount {
+        Some(amount) if !everything => {
+            let raw = utils::parse_amount(amount, token.decimals)?;
+            if raw.is_zero() {
+                return Err(anyhow!("amount must be above zero"));
+            }
+            raw
+        }
+        _ if matches!(action, LendingAction::Withdraw | LendingAction::Repay) => U256::MAX,
+        _ => return Err(anyhow!("amount is required to supply or borrow")),
+    };
+
+    // The amount passed to the pool, and what it pulls from the account for the approval
+    let (raw, pulled) = match action {
+        LendingAction::Supply => (raw, Some(raw)),
+        LendingAction::Repay => {
+            let debt =
+                token_balance(blockchain_service, reserve.variable_debt_token, holder).await?;
+            if debt.is_zero() {
+                return Err(anyhow!("There's no {} debt to repay", token.symbol));
+            }
+            // Interest accrues until the repayment is mined
+            let debt = debt + debt / 1000 + 1;
+            let balance = token_balance(blockchain_service, asset, holder).await?;
+            match everything {
+                true if balance >= debt => (U256::MAX, Some(debt)),
+                // Repay as much as is held
+                true => (balance, Some(balance)),
+                false => (raw, Some(raw.min(debt))),
+            }
+        }
+        LendingAction::Withdraw | LendingAction::Borrow => (raw, None),
+    };
+    let mut transactions = Vec::new();
+    if let Some(pulled) = pulled {
+        let balance = token_balance(blockchain_service, asset, holder).await?;
+        if pulled.is_zero() || balance < pulled {
+            return Err(anyhow!(
+                "Only {} {} is held",
+                format_units(balance, token.decimals as u32)?,
+                token.symbol
+            ));
+        }
+        if blockchain_service
+            .token_allowance(asset, holder, pool.address())
+            .await?
+            < pulled
+        {
+            let approval = blockchain_service
+                .set_allowance(from_account, &token, pool.address(), pulled, fees)
+                .await?;
+            if approval.status != "success" {
+                return Err(anyhow!(
+                    "Approving the Aave pool failed ({}): {}",
+                    approval.status,
+                    approval.hash
+                ));
+            }
+            transactions.push(approval);
+        }
+    }
+
+    let account = format!("{:?}", holder);
+    let asset = token.address.clone();
+    let (function_signature, parameters) = match action {
+        LendingAction::Supply => (SUPPLY, vec![asset, raw.to_string(), account, "0".into()]),
+        LendingAction::Withdraw => (WITHDRAW, vec![asset, raw.to_string(), account]),
+        LendingAction::Borrow => (
+            BORROW,
+            vec![
+                asset,
+                raw.to_string(),
+                VARIABLE_RATE.into(),
+                "0".into(),
+                account,
+            ],
+        ),
+        LendingAction::Repay => (
+            REPAY,
+            vec![asset, raw.to_string(), VARIABLE_RATE.into(), account],
+        ),
+    };
+    let transaction = blockchain_service
+        .write_contract(
+            from_account,
+            &ContractCall {
+                contract_address: format!("{:?}", pool.address()),
+                function_signature: function_signature.to_string(),
+                parameters,
+                from: None,
+            },
+            "0",
+            None,
+            &|name| name.to_string(),
+            fees,
+        )
+        .await?;
+    let status = transaction.status.clone();
+    let hash = transaction.hash.clone();
+    transactions.push(transaction);
+    if status != "success" {
+        return Err(anyhow!("Aave transaction failed ({}): {}", status, hash));
+    }
+
+    let data = account_data(&pool, holder).await?;
+    let warning = data
+        .health_factor
+        .filter(|health_factor| *health_factor < HEALTH_FACTOR_WARNING)
+        .map(|health_factor| {
+            format!(
+                "Health factor is {:.2}; the position is liquidated below 1.0",
+                health_factor
+            )
+        });
+    Ok(LendingResult {
+        action,
+        symbol: token.symbol,
+        amount: if raw == U256::MAX {
+            "all".to_string()
+        } else {
+            format_units(raw, token.decimals as u32)?
+        },
+        transactions,
+        total_collateral_usd: data.total_collateral_usd,
+        total_debt_usd: data.total_debt_usd,
+        health_factor: data.health_factor,
+        warning,
+    })
+}
@@ -0,0 +1,88 @@
+use serde::{Deserialize, Serialize};
+use shared::Account;
+use std::collections::HashMap;
+
+// Named accounts plus aliases visible to a single namespace
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AccountManager {
+    accounts: HashMap<String, Account>,
+    /// Alias -> account name or raw address
+    aliases: HashMap<String, String>,
+}
+
+impl AccountManager {
+    pub fn new(accounts: HashMap<String, Account>) -> Self {
+        Self {
+            accounts,
+            aliases: HashMap::new(),
+        }
+    }
+
+    pub fn with_aliases(mut self, aliases: HashMap<String, String>) -> Self {
+        self.aliases = aliases
+            .into_iter()
+            .map(|(alias, target)| (alias.to_lowercase(), target))
+            .collect();
+        self
+    }
+
+    /// Look up a signing account by name or alias
+    pub fn get(&self, name: &str) -> Option<&Account> {
+        if let Some(account) = self
+            .accounts
+            .get(name)
+            .or_else(|| self.accounts.get(&name.to_lowercase()))
+        {
+            return Some(account);
+        }
+
+        let target = self.aliases.get(&name.to_lowercase())?;
+        self.accounts
+            .get(target)
+            .or_else(|| self.accounts.get(&target.to_lowercase()))
+    }
+
+    /// Resolve a name, alias or address to an address string
+    pub fn resolve_address(&self, name_or_address: &str) -> String {
+        if let Some(account) = self.get(name_or_address) {
+            return account.address.clone();
+        }
+
+        match self.aliases.get(&name_or_address.to_lowercase()) {
+            Some(target) => target.clone(),
+            None => name_or_address.to_string(),
+        }
+    }
+
+    /// Name of the account owning an address, if it belongs to this namespace
+    pub fn name_for_address(&self, address: &str) -> Option<String> {
+        self.accounts
+            .iter()
+            .find(|(_, account)| account.address.eq_ignore_ascii_case(address))
+            .map(|(name, _)| name.clone())
+    }
+
+    /// Accounts keyed by name, with account aliases included as extra keys
+    pub fn as_map(&self) -> HashMap<String, Account> {
+        let mut map = self.accounts.clone();
+        for alias in self.aliases.keys() {
+            if let Some(account) = self.get(alias) {
+                map.insert(alias.clone(), account.clone());
+            }
+        }
+        map
+    }
+
+    pub fn addresses(&self) -> Vec<String> {
+        self.accounts
+            .values()
+            .map(|account| account.address.clone())
+            .collect()
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.accounts.keys().cloned().collect();
+        names.sort();
+        names
+    }
+}
@@ -0,0 +1,324 @@
+use anyhow::{Result, anyhow};
+use ethers::{types::U256, utils::format_units};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use tracing::warn;
+
+use crate::blockchain::BlockchainService;
+use crate::external_apis::ExternalAPIService;
+use crate::tenants::Namespace;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransferRecord {
+    pub timestamp: i64,
+    pub counterparty: String,
+    pub asset: String,
+    pub amount: f64,
+    pub tx_hash: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SwapRecord {
+    pub timestamp: i64,
+    pub from_token: String,
+    pub to_token: String,
+    pub amount_in: String,
+    pub amount_out: String,
+    pub tx_hash: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GasSummary {
+    pub transactions: u64,
+    pub gas_used: u64,
+    /// Only known when on-chain history is available
+    pub eth_spent: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActivitySummary {
+    pub account: String,
+    pub address: String,
+    pub since: i64,
+    pub until: i64,
+    pub sends: Vec<TransferRecord>,
+    pub receives: Vec<TransferRecord>,
+    pub swaps: Vec<SwapRecord>,
+    pub failed_operations: usize,
+    pub gas: GasSummary,
+    /// Asset symbol -> net amount gained (negative when spent)
+    pub net_asset_change: HashMap<String, f64>,
+    pub sources: Vec<String>,
+}
+
+/// Parse a lookback range such as "24h", "7d" or "4w" into seconds
+pub fn parse_range(range: &str) -> Result<i64> {
+    let range = range.trim().to_lowercase();
+    let split = range
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(|| anyhow!("Range needs a unit (h, d or w): {}", range))?;
+    let (value, unit) = range.split_at(split);
+    let value: i64 = value
+        .parse()
+        .map_err(|_| anyhow!("Invalid range: {}", range))?;
+
+    let unit_secs = match unit {
+        "h" | "hour" | "hours" => 3600,
+        "d" | "day" | "days" => 86400,
+        "w" | "week" | "weeks" => 7 * 86400,
+        _ => return Err(anyhow!("Unknown range unit: {}", unit)),
+    };
+    Ok(value * unit_secs)
+}
+
+/// Aggregate an account's activity over a lookback range from the namespace audit log and,
+/// when an Etherscan key is configured, on-chain history
+pub async fn summarize_activity(
+    blockchain_service: &BlockchainService,
+    external_apis: &ExternalAPIService,
+    namespace: &Namespace,
+    account: &str,
+    range: &str,
+) -> Result<ActivitySummary> {
+    let until = chrono::Utc::now().timestamp();
+    let since = until - parse_range(range)?;
+
+    let address = namespace.accounts.resolve_address(account);
+    let account_name = namespace
+        .accounts
+        .name_for_address(&address)
+        .unwrap_or_else(|| account.to_string());
+
+    let mut summary = ActivitySummary {
+        account: account_name.clone(),
+        address: address.clone(),
+        since,
+        until,
+        sends: Vec::new(),
+        receives: Vec::new(),
+        swaps: Vec::new(),
+        failed_operations: 0,
+        gas: GasSummary::default(),
+        net_asset_change: HashMap::new(),
+        sources: vec!["audit_log".to_string()],
+    };
+
+    let chain_id = blockchain_service.chain_id();
+    let transactions = match external_apis
+        .get_account_history(chain_id, &address, "txlist")
+        .await
+    {
+        Ok(history) => history,
+        Err(e) => {
+            warn!("Failed to fetch transaction history for {}: {}", address, e);
+            None
+        }
+    };
+    let token_transfers = match external_apis
+        .get_account_history(chain_id, &address, "tokentx")
+        .await
+    {
+        Ok(history) => history,
+        Err(e) => {
+            warn!("Failed to fetch token transfers for {}: {}", address, e);
+            None
+        }
+    };
+
+    // Operations that went through this assistant
+    for entry in namespace.audit.entries(since, Some(&account_name)) {
+        if entry.timestamp > until {
+            continue;
+        }
+        if entry.status != "success" {
+            summary.failed_operations += 1;
+            continue;
+        }
+
+        let result = entry.result.clone().unwrap_or(Value::Null);
+        match entry.method.as_str() {
+            "swap_tokens" => summary.swaps.push(SwapRecord {
+                timestamp: entry.timestamp,
+                from_token: result["from_token"].as_str().unwrap_or("").to_string(),
+                to_token: result["to_token"].as_str().unwrap_or("").to_string(),
+                amount_in: result["input_amount"].as_str().unwrap_or("").to_string(),
+                amount_out: result["output_amount"].as_str().unwrap_or("").to_string(),
+                tx_hash: entry.tx_hash.clone(),
+            }),
+            // On-chain history is more complete when we have it
+            "send_eth" if transactions.is_none() => {
+                let amount = entry.params["amount"]
+                    .as_str()
+                    .and_then(|amount| amount.parse::<f64>().ok())
+                    .unwrap_or(0.0);
+                summary.sends.push(TransferRecord {
+                    timestamp: entry.timestamp,
+                    counterparty: entry.params["to"].as_str().unwrap_or("").to_string(),
+                    asset: "ETH".to_string(),
+                    amount,
+                    tx_hash: entry.tx_hash.clone(),
+                });
+            }
+            _ => {}
+        }
+
+        if transactions.is_none()
+            && let Some(gas_used) = entry.gas_used
+        {
+            summary.gas.transactions += 1;
+            summary.gas.gas_used += gas_used;
+        }
+    }
+
+    match transactions {
+        Some(transactions) => {
+            summary.sources.push("etherscan".to_string());
+            collect_transactions(&mut summary, &transactions);
+        }
+        None => {
+            // Without chain history, approximate the net change from our own operations
+            let sent: f64 = summary.sends.iter().map(|send| send.amount).sum();
+            *summary
+                .net_asset_change
+                .entry("ETH".to_string())
+                .or_default() -= sent;
+            for swap in &summary.swaps {
+                if let Ok(amount_in) = swap.amount_in.parse::<f64>() {
+                    *summary
+                        .net_asset_change
+                        .entry(swap.from_token.to_uppercase())
+                        .or_default() -= amount_in;
+                }
+                if let Ok(amount_out) = swap.amount_out.parse::<f64>() {
+                    *summary
+                        .net_asset_change
+                        .entry(swap.to_token.to_uppercase())
+                        .or_default() += amount_out;
+                }
+            }
+        }
+    }
+
+    if let Some(token_transfers) = token_transfers {
+        collect_token_transfers(&mut summary, &token_transfers);
+    }
+
+    Ok(summary)
+}
+
+fn collect_transactions(summary: &mut ActivitySummary, transactions: &[Value]) {
+    let mut gas_wei = U256::zero();
+
+    for tx in transactions {
+        let timestamp = field_i64(tx, "timeStamp");
+        if timestamp < summary.since || timestamp > summary.until {
+            continue;
+        }
+
+        let from = tx["from"].as_str().unwrap_or("");
+        let to = tx["to"].as_str().unwrap_or("");
+        let hash = tx["hash"].as_str().map(|hash| hash.to_string());
+        let value = from_base_units(tx["value"].as_str().unwrap_or("0"), 18);
+        let is_swap = tx["functionName"]
+            .as_str()
+            .is_some_and(|name| name.starts_with("swap"));
+
+        if from.eq_ignore_ascii_case(&summary.address) {
+            summary.gas.transactions += 1;
+            let gas_used = field_u256(tx, "gasUsed");
+            summary.gas.gas_used += gas_used.low_u64();
+            gas_wei += gas_used.saturating_mul(field_u256(tx, "gasPrice"));
+
+            *summary
+                .net_asset_change
+                .entry("ETH".to_string())
+                .or_default() -= value;
+            if value > 0.0 && !is_swap {
+                summary.sends.push(TransferRecord {
+                    timestamp,
+                    counterparty: to.to_string(),
+                    asset: "ETH".to_string(),
+                    amount: value,
+                    tx_hash: hash,
+                });
+            }
+        } else if to.eq_ignore_ascii_case(&summary.address) && value > 0.0 {
+            *summary
+                .net_asset_change
+                .entry("ETH".to_string())
+                .or_default() += value;
+            summary.receives.push(TransferRecord {
+                timestamp,
+                counterparty: from.to_string(),
+                asset: "ETH".to_string(),
+                amount: value,
+                tx_hash: hash,
+            });
+        }
+    }
+
+    let gas_eth = from_base_units(&gas_wei.to_string(), 18);
+    summary.gas.eth_spent = Some(gas_eth);
+    *summary
+        .net_asset_change
+        .entry("ETH".to_string())
+        .or_default() -= gas_eth;
+}
+
+fn collect_token_transfers(summary: &mut ActivitySummary, transfers: &[Value]) {
+    for transfer in transfers {
+        let timestamp = field_i64(transfer, "timeStamp");
+        if timestamp < summary.since || timestamp > summary.until {
+            continue;
+        }
+
+        let symbol = transfer["tokenSymbol"].as_str().unwrap_or("UNKNOWN");
+        let decimals = field_i64(transfer, "tokenDecimal") as u32;
+        let amount = from_base_units(transfer["value"].as_str().unwrap_or("0"), decimals);
+        let from = transfer["from"].as_str().unwrap_or("");
+        let to = transfer["to"].as_str().unwrap_or("");
+
+        if from.eq_ignore_ascii_case(&summary.address) {
+            *summary
+                .net_asset_change
+                .entry(symbol.to_string())
+                .or_default() -= amount;
+        } else if to.eq_ignore_ascii_case(&summary.address) {
+            *summary
+                .net_asset_change
+                .entry(symbol.to_string())
+                .or_default() += amount;
+            summary.receives.push(TransferRecord {
+                timestamp,
+                counterparty: from.to_string(),
+                asset: symbol.to_string(),
+                amount,
+                tx_hash: transfer["hash"].as_str().map(|hash| hash.to_string()),
+            });
+        }
+    }
+}
+
+// Etherscan returns every number as a decimal string
+pub fn field_i64(value: &Value, field: &str) -> i64 {
+    value[field]
+        .as_str()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(0)
+}
+
+pub fn field_u256(value: &Value, field: &str) -> U256 {
+    value[field]
+        .as_str()
+        .and_then(|v| U256::from_dec_str(v).ok())
+        .unwrap_or_default()
+}
+
+fn from_base_units(amount: &str, decimals: u32) -> f64 {
+    U256::from_dec_str(amount)
+        .ok()
+        .and_then(|amount| format_units(amount, decimals).ok())
+        .and_then(|amount| amount.parse().ok())
+        .unwrap_or(0.0)
+}
@@ -0,0 +1,459 @@
+use anyhow::{Result, anyhow};
+use ethers::types::{Address, Bytes, U256};
+use ethers::utils::format_units;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use shared::{Account, SwapResult, utils};
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use crate::blockchain::{BlockchainService, SwapCalldata, SwapQuote, TokenInfo};
+use crate::external_apis::ExternalAPIService;
+use crate::policy::SwapApprovals;
+
+// How the aggregator APIs name native ETH
+const NATIVE_TOKEN: &str = "0xEeeeeEeeeEeEeeEeEeEeeEEEeeeeEeeeeeeeEEeE";
+// 0x's AllowanceHolder and 1inch's v6 router, each at the same address on every
+// network. Quotes whose transaction goes anywhere else are refused.
+const ZEROX_ALLOWANCE_HOLDER: &str = "0x0000000000001fF3684f28c67538d4D072C22734";
+const ONEINCH_ROUTER: &str = "0x111111125421cA6dc452d289314280a0f8842A65";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Aggregator {
+    #[serde(rename = "0x")]
+    ZeroX,
+    #[serde(rename = "1inch")]
+    OneInch,
+}
+
+impl Aggregator {
+    pub const ALL: [Self; 2] = [Self::ZeroX, Self::OneInch];
+
+    /// The aggregators a swap's `dex` names: one of them, or both for "aggregator";
+    /// None when it names something else, such as a Uniswap version
+    pub fn from_dex(dex: &str) -> Option<Vec<Self>> {
+        match dex.to_lowercase().replace([' ', '-', '_'], "").as_str() {
+            "0x" | "zerox" => Some(vec![Self::ZeroX]),
+            "1inch" | "oneinch" => Some(vec![Self::OneInch]),
+            "aggregator" | "aggregators" => Some(Self::ALL.to_vec()),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::ZeroX => "0x",
+            Self::OneInch => "1inch",
+        }
+    }
+
+    // The contract quotes are filled through, which is also the spender to approve
+    fn router(&self) -> Address {
+        let address = match self {
+            Self::ZeroX => ZEROX_ALLOWANCE_HOLDER,
+            Self::OneInch => ONEINCH_ROUTER,
+        };
+        Address::from_str(address).expect("valid aggregator router address")
+    }
+}
+
+/// A swap to quote: `amount` of `from_token` into `to_token`, filled by `taker`
+#[derive(Debug, Clone)]
+pub struct QuoteRequest {
+    pub from_token: String,
+    pub to_token: String,
+    pub amount: String,
+    pub taker: Address,
+    pub slippage_percent: f64,
+}
+
+/// An aggregator's quote, with the transaction that fills it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AggregatorQuote {
+    pub aggregator: Aggregator,
+    pub from_token: String,
+    pub to_token: String,
+    pub amount_in: String,
+    pub expected_amount_out: String,
+    /// Least the transaction accepts at the requested slippage
+    pub minimum_amount_out: String,
+    /// Liquidity sources the order is split across, when the aggregator reports them
+    pub sources: Vec<String>,
+    pub gas: Option<u64>,
+    #[serde(skip)]
+    to: Address,
+    #[serde(skip)]
+    data: Bytes,
+    #[serde(skip)]
+    value: U256,
+    #[serde(skip)]
+    raw_amount_in: U256,
+    #[serde(skip)]
+    raw_amount_out: U256,
+    // None when selling ETH
+    #[serde(skip)]
+    token_in: Option<TokenInfo>,
+    // Address and decimals of the token bought; None for ETH
+    #[serde(skip)]
+    token_out: Option<(Address, u8)>,
+}
+
+impl AggregatorQuote {
+    // The transaction comes from the aggregator's API, so the ETH it sends must be the
+    // amount sold when selling ETH, and nothing otherwise
+    fn check_value(&self) -> Result<()> {
+        let expected = match self.token_in {
+            None => self.raw_amount_in,
+            Some(_) => U256::zero(),
+        };
+        if self.value != expected {
+            return Err(anyhow!(
+                "Refusing a {} transaction that sends {} wei of ETH when the swap should send {}",
+                self.aggregator.as_str(),
+                self.value,
+                expected
+            ));
+        }
+        Ok(())
+    }
+
+    /// The quote in the shape of a Uniswap one, for the price impact policy. Impact is
+    /// measured against Uniswap's mid-price, before LP fees, since aggregators don't
+    /// report one; without it, it's zero.
+    pub fn to_swap_quote(&self, uniswap: Option<&SwapQuote>) -> SwapQuote {
+        let amount_in: f64 = self.amount_in.parse().unwrap_or(0.0);
+        let amount_out: f64 = self.expected_amount_out.parse().unwrap_or(0.0);
+        let mid_price = uniswap.and_then(|quote| quote.mid_price);
+        let price_impact_percent = match mid_price {
+            Some(mid) if mid > 0.0 && amount_in > 0.0 => {
+                (((1.0 - amount_out / amount_in / mid) * 100.0).max(0.0) * 100.0).round() / 100.0
+            }
+            _ => 0.0,
+        };
+        SwapQuote {
+            dex: self.aggregator.as_str().to_string(),
+            route: self.sources.join(", "),
+            from_token: self.from_token.clone(),
+            to_token: self.to_token.clone(),
+            amount_in: self.amount_in.clone(),
+            expected_amount_out: self.expected_amount_out.clone(),
+            price_impact_percent,
+            mid_price,
+            minimum_amount_out: Some(self.minimum_amount_out.clone()),
+            steps: None,
+        }
+    }
+
+    /// Whether this quote pays out more than `other`, a quote for the same swap
+    pub fn beats(&self, other: &SwapQuote) -> bool {
+        let other: f64 = other.expected_amount_out.parse().unwrap_or(0.0);
+        self.expected_amount_out.parse::<f64>().unwrap_or(0.0) > other
+    }
+}
+
+// A swap leg as the APIs name it, with its token details; None for ETH
+struct Leg {
+    api_address: String,
+    symbol: String,
+    decimals: u8,
+    token: Option<TokenInfo>,
+}
+
+async fn leg(blockchain_service: &BlockchainService, token: &str) -> Result<Leg> {
+    if token.eq_ignore_ascii_case("eth") {
+        return Ok(Leg {
+            api_address: NATIVE_TOKEN.to_string(),
+            symbol: "ETH".to_string(),
+            decimals: 18,
+            token: None,
+        });
+    }
+    let info = blockchain_service.resolve_token(token).await?;
+    Ok(Leg {
+        api_address: info.address.clone(),
+        symbol: info.symbol.clone(),
+        decimals: info.decimals,
+        token: Some(info),
+    })
+}
+
+fn uint(value: &Value, field: &str) -> Result<U256> {
+    let text = match value {
+        Value::String(text) => text.clone(),
+        Value::Number(number) => number.to_string(),
+        _ => return Err(anyhow!("Quote has no {}", field)),
+    };
+    U256::from_dec_str(&text).map_err(|_| anyhow!("Invalid {} in quote: {}", field, text))
+}
+
+/// Quote a swap on one aggregator, with the transaction to fill it
+pub async fn quote(
+    blockchain_service: &BlockchainService,
+    external_apis: &ExternalAPIService,
+    aggregator: Aggregator,
+    request: &QuoteRequest,
+) -> Result<AggregatorQuote> {
+    let QuoteRequest {
+        amount,
+        taker,
+        slippage_percent,
+        ..
+    } = request;
+    let sell = leg(blockchain_service, &request.from_token).await?;
+    let buy = leg(blockchain_service, &request.to_token).await?;
+    let raw_amount_in = utils::parse_amount(amount, sell.decimals)?;
+    let chain_id = blockchain_service.chain_id();
+    let query = |pairs: Vec<(&str, String)>| {
+        pairs
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value))
+            .collect::<HashMap<_, _>>()
+    };
+
+    let (raw_amount_out, raw_minimum_out, transaction, sources) = match aggregator {
+        Aggregator::ZeroX => {
+            let body = external_apis
+                .get_0x_quote(query(vec![
+                    ("chainId", chain_id.to_string()),
+                    ("sellToken", sell.api_address.clone()),
+                    ("buyToken", buy.api_address.clone()),
+                    ("sellAmount", raw_amount_in.to_string()),
+                    ("taker", format!("{:?}", taker)),
+                    (
+                        "slippageBps",
+                        ((slippage_percent * 100.0).round() as u64).to_string(),
+                    ),
+                ]))
+                .await?;
+            if body["liquidityAvailable"] == Value::Bool(false) {
+                return Err(anyhow!("0x has no liquidity for this swap"));
+            }
+            let sources = body["route"]["fills"]
+                .as_array()
+                .map(|fills| {
+                    let mut sources: Vec<String> = fills
+                        .iter()
+                        .filter_map(|fill| fill["source"].as_str().map(str::to_string))
+                        .collect();
+                    sources.dedup();
+                    sources
+                })
+                .unwrap_or_default();
+            (
+                uint(&body["buyAmount"], "buyAmount")?,
+                uint(&body["minBuyAmount"], "minBuyAmount")?,
+                body["transaction"].clone(),
+                sources,
+            )
+        }
+        Aggregator::OneInch => {
+            let body = external_apis
+                .get_1inch_swap(
+                    chain_id,
+                    query(vec![
+                        ("src", sell.api_address.clone()),
+                        ("dst", buy.api_address.clone()),
+                        ("amount", raw_amount_in.to_string()),
+                        ("from", format!("{:?}", taker)),
+                        ("slippage", slippage_percent.to_string()),
+                        // The router may not be approved yet; that happens before sending
+                        ("disableEstimate", "true".to_string()),
+                    ]),
+                )
+                .await?;
+            let amount_out = uint(&body["dstAmount"], "dstAmount")?;
+            let bps = ((100.0 - slippage_percent.min(100.0)) * 100.0).round() as u64;
+            (
+                amount_out,
+                amount_out * U256::from(bps) / U256::from(10_000),
+                body["tx"].clone(),
+                Vec::new(),
+            )
+        }
+    };
+
+    let to = transaction["to"]
+        .as_str()
+        .and_then(|to| Address::from_str(to).ok())
+        .ok_or_else(|| anyhow!("{} quote has no transaction", aggregator.as_str()))?;
+    if to != aggregator.router() {
+        return Err(anyhow!(
+            "{} quote sends to {:?} rather than its router {:?}; refusing it",
+            aggregator.as_str(),
+            to,
+            aggregator.router()
+        ));
+    }
+    let data = transaction["data"]
+        .as_str()
+        .and_then(|data| Bytes::from_str(data).ok())
+        .ok_or_else(|| anyhow!("{} quote has no calldata", aggregator.as_str()))?;
+    let value = uint(&transaction["value"], "value").unwrap_or_default();
+
+    Ok(AggregatorQuote {
+        aggregator,
+        from_token: sell.symbol,
+        to_token: buy.symbol,
+        amount_in: amount.clone(),
+        expected_amount_out: format_units(raw_amount_out, buy.decimals as u32)?,
+        minimum_amount_out: format_units(raw_minimum_out, buy.decimals as u32)?,
+        sources,
+        gas: uint(&transaction["gas"], "gas")
+            .ok()
+            .map(|gas| gas.low_u64()),
+        to,
+        data,
+        value,
+        raw_amount_in,
+        raw_amount_out,
+        token_in: sell.token,
+        token_out: buy
+            .token
+            .map(|token| Address::from_str(&token.address).map(|address| (address, buy.decimals)))
+            .transpose()?,
+    })
+}
+
+/// The best quote among `aggregators`, skipping those that fail (or have no API key);
+/// an error only when none of them quotes
+pub async fn best_quote(
+    blockchain_service: &BlockchainService,
+    external_apis: &ExternalAPIService,
+    aggregators: &[Aggregator],
+    request: &QuoteRequest,
+) -> Result<AggregatorQuote> {
+    let mut best: Option<AggregatorQuote> = None;
+    let mut errors = Vec::new();
+    for aggregator in aggregators {
+        match quote(blockchain_service, external_apis, *aggregator, request).await {
+            Ok(quote) => {
+                if best
+                    .as_ref()
+                    .is_none_or(|best| quote.raw_amount_out > best.raw_amount_out)
+                {
+                    best = Some(quote);
+                }
+            }
+            Err(e) => errors.push(format!("{}: {}", aggregator.as_str(), e)),
+        }
+    }
+    best.ok_or_else(|| anyhow!("No aggregator quote ({})", errors.join("; ")))
+}
+
+/// Approve the aggregator's router for exactly the input if needed, then send the
+/// quote's transaction. Approvals, including any in the aggregator's calldata, are
+/// capped by the policy.
+pub async fn execute(
+    blockchain_service: &BlockchainService,
+    from_account: &Account,
+    quote: &AggregatorQuote,
+    approvals: &SwapApprovals<'_>,
+    simulate: bool,
+) -> Result<SwapResult> {
+    let router = quote.aggregator.router();
+    if quote.to != router {
+        return Err(anyhow!(
+            "Refusing a {} transaction to {:?}",
+            quote.aggregator.as_str(),
+            quote.to
+        ));
+    }
+    quote.check_value()?;
+    if let Some(token) = &quote.token_in {
+        let owner = Address::from_str(&from_account.address)?;
+        let token_address = Address::from_str(&token.address)?;
+        if blockchain_service
+            .token_allowance(token_address, owner, router)
+            .await?
+            < quote.raw_amount_in
+        {
+            let (approval, _) = blockchain_service
+                .set_swap_allowance(
+                    from_account,
+                    token,
+                    router,
+                    quote.raw_amount_in,
+                    quote.raw_amount_in,
+                    approvals,
+                )
+                .await?;
+            if approval.status != "success" {
+                return Err(anyhow!(
+                    "Approving the {} router failed ({}): {}",
+                    quote.aggregator.as_str(),
+                    approval.status,
+                    approval.hash
+                ));
+            }
+        }
+    }
+
+    let mut result = blockchain_service
+        .execute_swap_calldata(
+            from_account,
+            &SwapCalldata {
+                to: quote.to,
+                data: quote.data.clone(),
+                value: quote.value,
+                from_token: quote.from_token.clone(),
+                to_token: quote.to_token.clone(),
+                amount_in: quote.amount_in.clone(),
+                output_token: quote.token_out,
+                source: quote.aggregator.as_str().to_string(),
+                simulate,
+            },
+        )
+        .await?;
+    if !quote.sources.is_empty() {
+        result.route = Some(quote.sources.join(", "));
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quote(token_in: Option<TokenInfo>, value: U256) -> AggregatorQuote {
+        AggregatorQuote {
+            aggregator: Aggregator::ZeroX,
+            from_token: "ETH".to_string(),
+            to_token: "USDC".to_string(),
+            amount_in: "1".to_string(),
+            expected_amount_out: "3000".to_string(),
+            minimum_amount_out: "2985".to_string(),
+            sources: Vec::new(),
+            gas: None,
+            to: Aggregator::ZeroX.router(),
+            data: Bytes::new(),
+            value,
+            raw_amount_in: U256::exp10(18),
+            raw_amount_out: U256::from(3_000_000_000u64),
+            token_in,
+            token_out: None,
+        }
+    }
+
+    #[test]
+    fn selling_eth_sends_exactly_the_amount_in() {
+        assert!(quote(None, U256::exp10(18)).check_value().is_ok());
+        assert!(quote(None, U256::exp10(18) + 1).check_value().is_err());
+        assert!(quote(None, U256::zero()).check_value().is_err());
+    }
+
+    #[test]
+    fn selling_a_token_sends_no_eth() {
+        let usdc = TokenInfo {
+            address: "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48".to_string(),
+            symbol: "USDC".to_string(),
+            decimals: 6,
+            name: "USD Coin".to_string(),
+        };
+        assert!(
+            quote(Some(usdc.clone()), U256::zero())
+                .check_value()
+                .is_ok()
+        );
+        assert!(quote(Some(usdc), U256::one()).check_value().is_err());
+    }
+}
@@ -0,0 +1,153 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use tracing::warn;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub timestamp: i64,
+    pub namespace: String,
+    pub method: String,
+    pub account: Option<String>,
+    pub params: Value,
+    pub status: String, // "success" or "error"
+    pub tx_hash: Option<String>,
+    pub gas_used: Option<u64>,
+    /// Fee paid, priced at execution time
+    #[serde(default)]
+    pub gas_cost: Option<GasCost>,
+    pub result: Option<Value>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GasCost {
+    pub eth: f64,
+    pub usd: Option<f64>,
+}
+
+// Append-only record of write operations for one namespace, mirrored to a JSONL file
+pub struct AuditLog {
+    namespace: String,
+    path: Option<PathBuf>,
+    entries: RwLock<Vec<AuditEntry>>,
+}
+
+impl AuditLog {
+    /// An audit log that only lives in memory
+    pub fn in_memory(namespace: &str) -> Self {
+        Self {
+            namespace: namespace.to_string(),
+            path: None,
+            entries: RwLock::new(Vec::new()),
+        }
+    }
+
+    /// Open (or create) the audit log for a namespace under `dir`, loading existing entries
+    pub fn open(dir: impl AsRef<Path>, namespace: &str) -> Result<Self> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+        let path = dir.join(format!("{}.jsonl", namespace));
+
+        let mut entries = Vec::new();
+        if path.exists() {
+            for line in fs::read_to_string(&path)?.lines() {
+                match serde_json::from_str::<AuditEntry>(line) {
+                    Ok(entry) => entries.push(entry),
+                    Err(e) => warn!(
+                        "Skipping malformed audit entry in {}: {}",
+                        path.display(),
+                        e
+                    ),
+                }
+            }
+        }
+
+        Ok(Self {
+            namespace: namespace.to_string(),
+            path: Some(path),
+            entries: RwLock::new(entries),
+        })
+    }
+
+    pub fn namespace(&self) -> &str {
+        &self.namespace
+    }
+
+    pub fn record(
+        &self,
+        method: &str,
+        account: Option<String>,
+        params: &Value,
+        outcome: &Result<Value>,
+        gas_cost: Option<GasCost>,
+    ) {
+        let (status, result, error) = match outcome {
+            Ok(result) => ("success", Some(result.clone()), None),
+            Err(e) => ("error", None, Some(e.to_string())),
+        };
+
+        let entry = AuditEntry {
+            timestamp: chrono::Utc::now().timestamp(),
+            namespace: self.namespace.clone(),
+            method: method.to_string(),
+            account,
+            params: params.clone(),
+            status: status.to_string(),
+            tx_hash: result.as_ref().and_then(Self::find_tx_hash),
+            gas_used: result.as_ref().and_then(|r| r["gas_used"].as_u64()),
+            gas_cost,
+            result,
+            error,
+        };
+
+        if let Some(path) = &self.path
+            && let Err(e) = Self::append(path, &entry)
+        {
+            warn!("Failed to persist audit entry to {}: {}", path.display(), e);
+        }
+
+        if let Ok(mut entries) = self.entries.write() {
+            entries.push(entry);
+        }
+    }
+
+    /// Entries since `since` (unix seconds), optionally restricted to one account
+    pub fn entries(&self, since: i64, account: Option<&str>) -> Vec<AuditEntry> {
+        self.entries
+            .read()
+            .map(|entries| {
+                entries
+                    .iter()
+                    .filter(|entry| entry.timestamp >= since)
+                    .filter(|entry| match account {
+                        Some(account) => entry
+                            .account
+                            .as_deref()
+                            .is_some_and(|a| a.eq_ignore_ascii_case(account)),
+                        None => true,
+                    })
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    fn append(path: &Path, entry: &AuditEntry) -> Result<()> {
+        let line = serde_json::to_string(entry)?;
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        writeln!(file, "{}", line)?;
+        Ok(())
+    }
+
+    pub fn find_tx_hash(result: &Value) -> Option<String> {
+        result["hash"]
+            .as_str()
+            .or_else(|| result["transaction_hash"].as_str())
+            .map(|hash| hash.to_string())
+    }
+}
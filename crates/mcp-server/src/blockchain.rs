@@ -1,40 +1,123 @@
 use anyhow::{Result, anyhow};
 use ethers::{
-    abi::Abi,
-    contract::Contract,
+    abi::{
+        Abi, Event, EventExt, Function, FunctionExt, HumanReadableParser, Param, ParamType, Token,
+        parse_abi,
+        token::{LenientTokenizer, Tokenizer},
+    },
+    contract::{Contract, ContractFactory, MULTICALL_ADDRESS, Multicall},
     middleware::SignerMiddleware,
-    providers::{Http, Middleware, Provider},
-    signers::LocalWallet,
-    types::{Address, TransactionRequest as EthTransactionRequest, U256},
+    providers::{JsonRpcClient, Middleware, Provider, ProviderError},
+    signers::{LocalWallet, Signer, to_eip155_v},
+    types::{
+        Address, BlockNumber, Bytes, Eip1559TransactionRequest, Filter, H256, I256, Log, Signature,
+        TransactionReceipt, TransactionRequest as EthTransactionRequest, U256,
+        transaction::{eip712::TypedData, eip2718::TypedTransaction},
+    },
+    utils::{format_units, keccak256, parse_units, to_checksum},
+};
+use futures::future::join_all;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use shared::abi_loader::{AbiInterface, AbiLoader};
+use shared::{
+    Account, BalanceQuery, BalanceResult, ContractCall, SwapRequest, SwapResult, TransactionResult,
+    utils,
 };
-use shared::{Account, BalanceQuery, BalanceResult, SwapRequest, SwapResult, TransactionResult};
 use std::collections::HashMap;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
 use tracing::{info, warn};
 
+use crate::deploy_address::{CREATE2_DEPLOYER, create2_address};
+use crate::external_apis::VerifiedContract;
+use crate::failover::{EndpointHealth, FailoverClient, RetryPolicy};
+use crate::heads::HeadTracker;
+use crate::paths::data_path;
+use crate::pending::{PendingStore, PendingTransaction};
+use crate::policy::{ApprovalAdjustment, SwapApprovals};
+use crate::proxy::{self, ContractStatus};
+use crate::signing;
+use crate::simulation::{self, Simulation};
+use crate::token_list::{DiscoveredToken, TokenRegistry};
+use crate::tx_tracker;
+use crate::universal_router;
+
 // Type alias for the Ethereum provider
-pub type EthProvider = Arc<Provider<Http>>;
+pub type EthProvider = Arc<Provider<FailoverClient>>;
 
 pub type SignerProvider = Arc<SignerMiddleware<EthProvider, LocalWallet>>;
 
+// ENS lookups keyed by name or address, with the time they were resolved
+type EnsCache<K, V> = Arc<RwLock<HashMap<K, (V, Instant)>>>;
+
+/// Maps names in address arguments (account names, aliases) to addresses; anything
+/// it doesn't know should be returned unchanged
+pub type NameResolver<'a> = dyn Fn(&str) -> String + Send + Sync + 'a;
+
+// Balances keyed by owner and lowercase token address ("eth" for ETH)
+type BalanceCache = Arc<RwLock<HashMap<(Address, String), (BalanceResult, Instant)>>>;
+
 // Uniswap V2 Router address on Ethereum mainnet
 const UNISWAP_V2_ROUTER: &str = "0x7a250d5630B4cF539739dF2C5dAcb4c659F2488D";
 
 // WETH address on Ethereum mainnet
 const WETH_ADDRESS: &str = "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2";
 
+// Uniswap V3 SwapRouter and Quoter; the same addresses on mainnet, Arbitrum, Optimism and Polygon
+const UNISWAP_V3_ROUTER: &str = "0xE592427A0AEce92De3Edee1F18E0157C05861564";
+const UNISWAP_V3_QUOTER: &str = "0xb27308f9F90D607463bb33eA1BeBb41C27CE5AB6";
+// NonfungiblePositionManager, which holds V3 liquidity positions as NFTs
+const UNISWAP_V3_POSITION_MANAGER: &str = "0xC36442b4a4522E871399CD717aBDD847Ab11FE88";
+
+// Uniswap V3 pool fee tiers, in hundredths of a basis point
+const UNISWAP_V3_FEE_TIERS: [u32; 4] = [100, 500, 3000, 10000];
+// Tiers tried for each leg of a two-pool route through WETH
+const UNISWAP_V3_HOP_FEE_TIERS: [u32; 2] = [500, 3000];
+// Uniswap V2 pairs all charge 0.3%, in the same units
+const UNISWAP_V2_FEE: u32 = 3000;
+// Uniswap's Universal Router (v1.2) on mainnet
+const UNISWAP_UNIVERSAL_ROUTER: &str = "0x3fC91A3afd70395Cd496C647d5a6CC9D4B2b7FAD";
+// Permit2, deployed at this address on every network
+const PERMIT2_ADDRESS: &str = "0x000000000022D473030F116dDEE9F6B43aC78BA3";
+// How long a swap's permit and its signature stay valid, in seconds
+const PERMIT2_PERMIT_TTL: i64 = 1800;
+
+// Disperse (disperse.app), deployed at this address on mainnet and most L2s
+const DISPERSE_ADDRESS: &str = "0xD152f549545093347A162Dce210e7293f1452150";
+
 #[derive(Clone)]
 pub struct BlockchainService {
     provider: EthProvider,
+    chain_id: u64,
+    router: RouterConfig,
     erc20_abi: Abi,
     uniswap_router_abi: Abi,
-    token_registry: HashMap<String, TokenInfo>,
+    uniswap_v3_router_abi: Abi,
+    uniswap_v3_quoter_abi: Abi,
+    erc721_abi: Abi,
+    // Shared between clones so tokens registered at runtime are seen everywhere
+    token_registry: Arc<TokenRegistry>,
+    ens_cache: EnsCache<String, Address>,
+    reverse_ens_cache: EnsCache<Address, Option<String>>,
+    balance_cache: BalanceCache,
+    cache_settings: CacheSettings,
+    rpc_settings: RpcSettings,
+    nonces: NonceManager,
+    archive_node: Arc<tokio::sync::OnceCell<bool>>,
+    // Historical results never change, so they are cached without expiry
+    history_cache: Arc<RwLock<HashMap<String, Value>>>,
+    // Etherscan lookups by contract; None records an unverified contract so it isn't
+    // looked up again
+    verified_contracts: Arc<RwLock<HashMap<Address, Option<Arc<VerifiedContract>>>>>,
+    pending: Option<Arc<PendingStore>>,
+    heads: Arc<HeadTracker>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TokenInfo {
     pub address: String,
     pub symbol: String,
@@ -42,425 +125,3330 @@ pub struct TokenInfo {
     pub name: String,
 }
 
-impl BlockchainService {
-    pub fn new(provider: EthProvider) -> Result<Self> {
-        // Try to load ERC20 ABI from file
-        let erc20_abi = match Self::load_abi_from_file("./data/erc20_abi.json") {
-            Ok(abi) => {
-                info!("Successfully loaded ERC20 ABI from file");
-                abi
-            }
-            Err(e) => {
-                warn!("Failed to load ERC20 ABI from file: {}", e);
-                warn!("Using default ERC20 ABI");
-                Self::get_default_erc20_abi()?
-            }
-        };
+/// Unformatted balances from `balances_of`, tokens in the order they were requested
+#[derive(Debug, Clone)]
+pub struct RawBalances {
+    pub eth: U256,
+    pub tokens: Vec<Option<U256>>,
+    /// Whether they were read in a single Multicall3 call
+    pub multicall: bool,
+}
 
-        // Try to load Uniswap Router ABI
-        let uniswap_router_abi = match Self::load_abi_from_file("./data/uniswap_v2_router_abi.json")
-        {
-            Ok(abi) => {
-                info!("Successfully loaded Uniswap Router ABI from file");
-                abi
-            }
-            Err(e) => {
-                warn!("Failed to load Uniswap Router ABI: {}", e);
-                warn!("Swap functionality will be limited");
-                Self::get_default_uniswap_router_abi()?
+// Where each network's tokens discovered on-chain are kept
+const DISCOVERED_TOKENS_DIR: &str = "discovered_tokens";
+
+// Used when fee history has no priority fee samples (e.g. an idle local chain)
+const DEFAULT_PRIORITY_FEE_GWEI: &str = "1.5";
+// Checks in a row a sent transaction can be unknown to the node before it counts as dropped
+const DROPPED_AFTER_CHECKS: u32 = 3;
+
+// Optional user-provided EIP-1559 fee caps
+#[derive(Debug, Clone, Default)]
+pub struct FeeOverrides {
+    pub max_fee_per_gas: Option<U256>,
+    pub max_priority_fee_per_gas: Option<U256>,
+}
+
+impl FeeOverrides {
+    /// Read `max_fee_per_gas` / `max_priority_fee_per_gas` (in gwei) from tool parameters
+    pub fn from_params(params: &Value) -> Result<Self> {
+        let gwei = |field: &str| -> Result<Option<U256>> {
+            match params[field].as_str() {
+                Some(value) => Ok(Some(utils::parse_amount(value, 9)?)),
+                None => params[field]
+                    .as_f64()
+                    .map(|value| utils::parse_amount(&value.to_string(), 9))
+                    .transpose(),
             }
         };
 
-        let token_registry = Self::build_token_registry();
-
         Ok(Self {
-            provider,
-            erc20_abi,
-            uniswap_router_abi,
-            token_registry,
+            max_fee_per_gas: gwei("max_fee_per_gas")?,
+            max_priority_fee_per_gas: gwei("max_priority_fee_per_gas")?,
         })
     }
 
-    fn load_abi_from_file<P: AsRef<Path>>(path: P) -> Result<Abi> {
-        let abi_content = fs::read_to_string(path)?;
-        let abi: Abi = serde_json::from_str(&abi_content)?;
-        Ok(abi)
+    pub fn is_empty(&self) -> bool {
+        self.max_fee_per_gas.is_none() && self.max_priority_fee_per_gas.is_none()
     }
+}
 
-    fn get_default_erc20_abi() -> Result<Abi> {
-        let abi_json = r#"[
-          {
-              "constant": true,
-              "inputs": [{"name": "_owner", "type": "address"}],
-              "name": "balanceOf",
-              "outputs": [{"name": "balance", "type": "uint256"}],
-              "type": "function"
-          },
-          {
-              "constant": true,
-              "inputs": [],
-              "name": "decimals",
-              "outputs": [{"name": "", "type": "uint8"}],
-              "type": "function"
-          },
-          {
-              "constant": true,
-              "inputs": [],
-              "name": "symbol",
-              "outputs": [{"name": "", "type": "string"}],
-              "type": "function"
-          },
-          {
-              "constant": true,
-              "inputs": [],
-              "name": "name",
-              "outputs": [{"name": "", "type": "string"}],
-              "type": "function"
-          },
-          {
-              "constant": false,
-              "inputs": [
-                  {"name": "_to", "type": "address"},
-                  {"name": "_value", "type": "uint256"}
-              ],
-              "name": "transfer",
-              "outputs": [{"name": "", "type": "bool"}],
-              "type": "function"
-          },
-          {
-              "constant": false,
-              "inputs": [
-                  {"name": "_spender", "type": "address"},
-                  {"name": "_value", "type": "uint256"}
-              ],
-              "name": "approve",
-              "outputs": [{"name": "", "type": "bool"}],
-              "type": "function"
-          },
-          {
-              "constant": true,
-              "inputs": [
-                  {"name": "_owner", "type": "address"},
-                  {"name": "_spender", "type": "address"}
-              ],
-              "name": "allowance",
-              "outputs": [{"name": "remaining", "type": "uint256"}],
-              "type": "function"
-          },
-          {
-              "constant": false,
-              "inputs": [
-                  {"name": "_from", "type": "address"},
-                  {"name": "_to", "type": "address"},
-                  {"name": "_value", "type": "uint256"}
-              ],
-              "name": "transferFrom",
-              "outputs": [{"name": "", "type": "bool"}],
-              "type": "function"
-          },
-          {
-              "constant": true,
-              "inputs": [],
-              "name": "totalSupply",
-              "outputs": [{"name": "", "type": "uint256"}],
-              "type": "function"
-          }
-      ]"#;
+// What a swap pays out, used to read the received amount from the receipt
+enum SwapOutput {
+    Token {
+        address: Address,
+        decimals: u8,
+        receiver: Address,
+    },
+    /// Native ETH, unwrapped from WETH by the router
+    Eth { weth: Address },
+}
 
-        let abi = serde_json::from_str(abi_json)?;
-        Ok(abi)
-    }
+#[derive(Debug, Clone)]
+pub struct Eip1559Fees {
+    pub base_fee_per_gas: U256,
+    pub max_fee_per_gas: U256,
+    pub max_priority_fee_per_gas: U256,
+}
 
-    fn get_default_uniswap_router_abi() -> Result<Abi> {
-        // This is a minimal ABI for Uniswap V2 Router with just the methods we need
-        let abi_json = r#"[
-          {
-              "inputs": [
-                  {"internalType": "uint256", "name": "amountIn", "type": "uint256"},
-                  {"internalType": "uint256", "name": "amountOutMin", "type": "uint256"},
-                  {"internalType": "address[]", "name": "path", "type": "address[]"},
-                  {"internalType": "address", "name": "to", "type": "address"},
-                  {"internalType": "uint256", "name": "deadline", "type": "uint256"}
-              ],
-              "name": "swapExactTokensForTokens",
-              "outputs": [{"internalType": "uint256[]", "name": "amounts", "type": "uint256[]"}],
-              "stateMutability": "nonpayable",
-              "type": "function"
-          },
-          {
-              "inputs": [
-                  {"internalType": "uint256", "name": "amountOutMin", "type": "uint256"},
-                  {"internalType": "address[]", "name": "path", "type": "address[]"},
-                  {"internalType": "address", "name": "to", "type": "address"},
-                  {"internalType": "uint256", "name": "deadline", "type": "uint256"}
-              ],
-              "name": "swapExactETHForTokens",
-              "outputs": [{"internalType": "uint256[]", "name": "amounts", "type": "uint256[]"}],
-              "stateMutability": "payable",
-              "type": "function"
-          },
-          {
-              "inputs": [
-                  {"internalType": "uint256", "name": "amountIn", "type": "uint256"},
-                  {"internalType": "uint256", "name": "amountOutMin", "type": "uint256"},
-                  {"internalType": "address[]", "name": "path", "type": "address[]"},
-                  {"internalType": "address", "name": "to", "type": "address"},
-                  {"internalType": "uint256", "name": "deadline", "type": "uint256"}
-              ],
-              "name": "swapExactTokensForETH",
-              "outputs": [{"internalType": "uint256[]", "name": "amounts", "type": "uint256[]"}],
-              "stateMutability": "nonpayable",
-              "type": "function"
-          },
-          {
-              "inputs": [
-                  {"internalType": "uint256", "name": "amountIn", "type": "uint256"},
-                  {"internalType": "address[]", "name": "path", "type": "address[]"}
-              ],
-              "name": "getAmountsOut",
-              "outputs": [{"internalType": "uint256[]", "name": "amounts", "type": "uint256[]"}],
-              "stateMutability": "view",
-              "type": "function"
-          }
-      ]"#;
+// Hands out nonces per signer so back-to-back sends from one account don't collide.
+// The pending nonce is fetched once, then incremented locally until a send fails.
+#[derive(Clone, Default)]
+pub struct NonceManager {
+    next: Arc<tokio::sync::Mutex<HashMap<Address, U256>>>,
+}
 
-        let abi = serde_json::from_str(abi_json)?;
-        Ok(abi)
+impl NonceManager {
+    pub async fn next<M: Middleware>(&self, provider: &M, address: Address) -> Result<U256>
+    where
+        M::Error: 'static,
+    {
+        let mut next = self.next.lock().await;
+        let nonce = match next.get(&address) {
+            Some(nonce) => *nonce,
+            None => {
+                provider
+                    .get_transaction_count(address, Some(BlockNumber::Pending.into()))
+                    .await?
+            }
+        };
+        next.insert(address, nonce + 1);
+        Ok(nonce)
     }
 
-    fn build_token_registry() -> HashMap<String, TokenInfo> {
-        let mut registry = HashMap::new();
+    /// Forget the local nonce so the next send refetches it from the node
+    pub async fn resync(&self, address: Address) {
+        self.next.lock().await.remove(&address);
+    }
 
-        // Add major tokens on Ethereum mainnet
-        registry.insert(
-            "usdc".to_string(),
-            TokenInfo {
-                address: "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48".to_string(),
-                symbol: "USDC".to_string(),
-                decimals: 6,
-                name: "USD Coin".to_string(),
-            },
-        );
+    /// Forget every local nonce, e.g. after the chain's state was rolled back
+    pub async fn resync_all(&self) {
+        self.next.lock().await.clear();
+    }
+}
 
-        registry.insert(
-            "usdt".to_string(),
-            TokenInfo {
-                address: "0xdAC17F958D2ee523a2206206994597C13D831ec7".to_string(),
-                symbol: "USDT".to_string(),
-                decimals: 6,
-                name: "Tether USD".to_string(),
-            },
-        );
+// Upper bound on token IDs enumerated for a single NFT balance query
+const MAX_ENUMERATED_NFTS: u64 = 100;
 
-        registry.insert(
-            "dai".to_string(),
-            TokenInfo {
-                address: "0x6B175474E89094C44Da98b954EedeAC495271d0F".to_string(),
-                symbol: "DAI".to_string(),
-                decimals: 18,
-                name: "Dai Stablecoin".to_string(),
-            },
-        );
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NftBalance {
+    pub collection: String,
+    pub name: Option<String>,
+    pub owner: String,
+    pub balance: u64,
+    /// Only available for collections implementing ERC721Enumerable
+    pub token_ids: Option<Vec<String>>,
+}
 
-        registry.insert(
-            "weth".to_string(),
-            TokenInfo {
-                address: "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2".to_string(),
-                symbol: "WETH".to_string(),
-                decimals: 18,
-                name: "Wrapped Ether".to_string(),
-            },
-        );
+// Gas used by a swap when it can't be simulated yet (the router has no allowance)
+const SWAP_GAS_FALLBACK: u64 = 200_000;
 
-        registry.insert(
-            "uni".to_string(),
-            TokenInfo {
-                address: "0x1f9840a85d5aF5bf1D1762F925BDADdC4201F984".to_string(),
-                symbol: "UNI".to_string(),
-                decimals: 18,
-                name: "Uniswap".to_string(),
-            },
-        );
+// A transaction to estimate gas for, before it is sent
+#[derive(Debug, Clone)]
+pub enum GasEstimateRequest {
+    SendEth {
+        from: String,
+        to: String,
+        amount: String,
+    },
+    SendErc20 {
+        from: String,
+        to: String,
+        token: String,
+        amount: String,
+    },
+    Swap {
+        from: String,
+        from_token: String,
+        to_token: String,
+        amount: String,
+    },
+}
 
-        registry.insert(
-            "link".to_string(),
-            TokenInfo {
-                address: "0x514910771AF9Ca656af840dff83E8264EcF986CA".to_string(),
-                symbol: "LINK".to_string(),
-                decimals: 18,
-                name: "ChainLink Token".to_string(),
-            },
-        );
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GasEstimate {
+    pub gas_limit: u64,
+    /// Extra gas for the router approval a token swap needs first
+    pub approval_gas: Option<u64>,
+    pub base_fee_gwei: String,
+    pub max_fee_per_gas_gwei: String,
+    pub max_priority_fee_per_gas_gwei: String,
+    /// Cost at the current base fee plus tip
+    pub expected_cost_eth: String,
+    /// Worst-case cost at the max fee
+    pub max_cost_eth: String,
+    /// True when part of the estimate could not be simulated
+    pub approximate: bool,
+}
 
-        registry.insert(
-            "wbtc".to_string(),
-            TokenInfo {
-                address: "0x2260FAC5E5542a773Aa44fBCfeDf7C193bc2C599".to_string(),
-                symbol: "WBTC".to_string(),
-                decimals: 8,
-                name: "Wrapped BTC".to_string(),
-            },
-        );
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContractCallOutput {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub value: Value,
+}
 
-        // Add by address as well for direct lookups
-        let address_entries: Vec<(String, TokenInfo)> = registry
-            .values()
-            .cloned()
-            .map(|token| (token.address.to_lowercase(), token))
-            .collect();
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContractCallResult {
+    pub contract: String,
+    pub function: String,
+    /// Decoded return values; empty when the return types aren't known
+    pub outputs: Vec<ContractCallOutput>,
+    /// Returned data as hex
+    pub raw: String,
+}
 
-        for (addr, token) in address_entries {
-            registry.insert(addr, token);
-        }
+/// ABI and creation bytecode from a Foundry (`out/`) or Hardhat (`artifacts/`) build
+#[derive(Debug, Clone)]
+pub struct ContractArtifact {
+    pub name: Option<String>,
+    pub abi: Abi,
+    pub bytecode: Bytes,
+}
 
-        registry
-    }
+impl ContractArtifact {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let artifact = AbiLoader::load_artifact(path)?;
+        let bytecode = artifact
+            .deploy_bytecode()
+            .map_err(|e| anyhow!("Artifact {}: {}", path.display(), e))?;
 
-    pub async fn get_balance(&self, query: BalanceQuery) -> Result<BalanceResult> {
-        let address = Address::from_str(&query.address)?;
+        let name = artifact.name.or_else(|| {
+            path.file_stem()
+                .map(|stem| stem.to_string_lossy().to_string())
+        });
+        Ok(Self {
+            name,
+            abi: artifact.abi,
+            bytecode,
+        })
+    }
 
-        match query.token {
-            None => {
-                // ETH balance
-                let balance = self.provider.get_balance(address, None).await?;
-                Ok(BalanceResult {
-                    address: query.address,
-                    balance: self.format_balance(balance, 18),
-                    token: None,
-                    decimals: 18,
-                })
-            }
-            Some(token_identifier) => {
-                if token_identifier.to_lowercase() == "eth" {
-                    let balance = self.provider.get_balance(address, None).await?;
-                    return Ok(BalanceResult {
-                        address: query.address,
-                        balance: self.format_balance(balance, 18),
-                        token: Some("ETH".to_string()),
-                        decimals: 18,
-                    });
-                }
-                // ERC20 token balance
-                self.get_erc20_balance(&query.address, &token_identifier)
-                    .await
-            }
+    /// Load an artifact by path relative to the server's working directory, refusing
+    /// paths that resolve outside it
+    pub fn load_local(artifact: &str) -> Result<Self> {
+        let root = std::env::current_dir()?.canonicalize()?;
+        let path = root
+            .join(artifact)
+            .canonicalize()
+            .map_err(|e| anyhow!("Artifact {} not found: {}", artifact, e))?;
+        if !path.starts_with(&root) {
+            return Err(anyhow!(
+                "Artifact {} is outside the server directory",
+                artifact
+            ));
         }
+        Self::load(&path)
     }
+}
 
-    async fn get_erc20_balance(
-        &self,
-        address: &str,
-        token_identifier: &str,
-    ) -> Result<BalanceResult> {
-        // Resolve token info
-        let token_info = self.resolve_token(token_identifier).await?;
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeploymentResult {
+    pub hash: String,
+    pub status: String,
+    /// Address of the new contract once the deployment is mined
+    pub contract_address: Option<String>,
+    pub contract_name: Option<String>,
+    pub block_number: Option<u64>,
+    pub gas_used: Option<u64>,
+}
 
-        // Create contract instance
-        let token_address = Address::from_str(&token_info.address)?;
-        let contract = Contract::new(token_address, self.erc20_abi.clone(), self.provider.clone());
+/// A transaction signed for broadcasting elsewhere
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignedTransaction {
+    /// RLP-encoded signed transaction as hex, ready for eth_sendRawTransaction
+    pub raw: String,
+    pub hash: String,
+    pub from: String,
+    pub to: Option<String>,
+    pub nonce: u64,
+    pub chain_id: u64,
+    pub gas_limit: u64,
+}
 
-        // Get balance
-        let owner_address = Address::from_str(address)?;
-        let balance: U256 = contract
-            .method::<_, U256>("balanceOf", owner_address)?
-            .call()
-            .await?;
+/// A transaction to build for signing outside the assistant
+#[derive(Debug, Clone)]
+pub enum UnsignedRequest {
+    SendEth {
+        to: String,
+        amount: String,
+    },
+    SendErc20 {
+        to: String,
+        token: String,
+        amount: String,
+    },
+    ContractCall {
+        call: ContractCall,
+        value: String,
+        gas_limit: Option<u64>,
+    },
+}
+
+/// A fully filled in transaction for an offline signer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UnsignedTransaction {
+    /// EIP-2718 typed transaction; pass it back unchanged with the signature
+    pub transaction: TypedTransaction,
+    /// Hash to sign: keccak256 of `unsigned_raw`
+    pub signing_hash: String,
+    /// Unsigned encoding as hex, for signers that take the payload rather than the hash
+    pub unsigned_raw: String,
+    pub from: String,
+    pub nonce: u64,
+    pub chain_id: u64,
+}
+
+/// A balance a write touched, read before sending and again once the transaction was mined
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BalanceChange {
+    pub address: String,
+    /// Token as the request named it, or "ETH"
+    pub token: String,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AllowanceResult {
+    pub owner: String,
+    pub token: String,
+    pub spender: String,
+    /// Router or ENS name of the spender, when known
+    pub spender_name: Option<String>,
+    pub allowance: String,
+    pub unlimited: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SwapQuote {
+    pub dex: String,
+    pub route: String,
+    pub from_token: String,
+    pub to_token: String,
+    pub amount_in: String,
+    pub expected_amount_out: String,
+    /// How far the execution price falls below the pools' mid-price after LP fees, in
+    /// percent
+    pub price_impact_percent: f64,
+    /// One `from_token` in `to_token` at the pools' current reserves, before fees; None
+    /// when the pools couldn't be read and impact was estimated from a small quote
+    pub mid_price: Option<f64>,
+    /// Least the swap accepts at the given slippage tolerance
+    pub minimum_amount_out: Option<String>,
+    /// What the Universal Router would do, step by step; None when swaps don't go
+    /// through it
+    pub steps: Option<Vec<String>>,
+}
+
+/// A ready-made swap transaction, e.g. from a DEX aggregator
+#[derive(Debug, Clone)]
+pub struct SwapCalldata {
+    pub to: Address,
+    pub data: Bytes,
+    pub value: U256,
+    pub from_token: String,
+    pub to_token: String,
+    pub amount_in: String,
+    /// Address and decimals of the token bought; None for ETH
+    pub output_token: Option<(Address, u8)>,
+    /// Where the calldata came from, reported as the swap's dex
+    pub source: String,
+    pub simulate: bool,
+}
+
+// Where an ABI should be loaded from
+#[derive(Debug, Clone)]
+pub enum AbiSource {
+    /// Load from a JSON file, falling back to the bundled ABI if it can't be read
+    File(PathBuf),
+    /// Parse from an inline JSON string
+    Json(String),
+    /// Use an already parsed ABI
+    Abi(Abi),
+    /// Use the minimal ABI bundled with the service
+    Bundled,
+}
+
+// Router and wrapped native token addresses for a chain
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RouterConfig {
+    pub uniswap_v2_router: String,
+    pub weth: String,
+    /// None when Uniswap V3 isn't deployed (or configured) on the chain
+    #[serde(default)]
+    pub uniswap_v3: Option<UniswapV3Config>,
+    /// Uniswap's Universal Router; when set, swaps go through it in one transaction,
+    /// paying for tokens with a Permit2 signature instead of a fresh router approval
+    #[serde(default)]
+    pub universal_router: Option<String>,
+}
+
+impl RouterConfig {
+    pub fn mainnet() -> Self {
+        Self {
+            uniswap_v2_router: UNISWAP_V2_ROUTER.to_string(),
+            weth: WETH_ADDRESS.to_string(),
+            uniswap_v3: Some(UniswapV3Config::canonical()),
+            universal_router: Some(UNISWAP_UNIVERSAL_ROUTER.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UniswapV3Config {
+    pub router: String,
+    pub quoter: String,
+    /// Needed to read liquidity positions; None when not configured
+    #[serde(default)]
+    pub position_manager: Option<String>,
+}
+
+impl UniswapV3Config {
+    /// SwapRouter, Quoter and position manager at their canonical deployment addresses
+    pub fn canonical() -> Self {
+        Self {
+            router: UNISWAP_V3_ROUTER.to_string(),
+            quoter: UNISWAP_V3_QUOTER.to_string(),
+            position_manager: Some(UNISWAP_V3_POSITION_MANAGER.to_string()),
+        }
+    }
+}
+
+// Exchanges swaps can be routed through
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Dex {
+    UniswapV2,
+    UniswapV3,
+}
+
+impl Dex {
+    pub fn parse(name: &str) -> Result<Self> {
+        match name.to_lowercase().replace([' ', '-'], "_").as_str() {
+            "v2" | "uniswap_v2" | "uniswapv2" => Ok(Self::UniswapV2),
+            "v3" | "uniswap_v3" | "uniswapv3" => Ok(Self::UniswapV3),
+            other => Err(anyhow!(
+                "Unknown dex: {} (expected uniswap_v2 or uniswap_v3)",
+                other
+            )),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::UniswapV2 => "uniswap_v2",
+            Self::UniswapV3 => "uniswap_v3",
+        }
+    }
+}
+
+// What a Universal Router swap trades through
+enum UniversalSwap {
+    V2(Vec<Address>),
+    V3(V3Route),
+}
+
+impl UniversalSwap {
+    fn token_in(&self) -> Address {
+        match self {
+            Self::V2(path) => path[0],
+            Self::V3(route) => route.tokens[0],
+        }
+    }
+
+    fn dex(&self) -> Dex {
+        match self {
+            Self::V2(_) => Dex::UniswapV2,
+            Self::V3(_) => Dex::UniswapV3,
+        }
+    }
+}
+
+// Pools a V3 swap goes through: `fees[i]` is the pool between `tokens[i]` and `tokens[i + 1]`
+#[derive(Debug, Clone)]
+struct V3Route {
+    tokens: Vec<Address>,
+    fees: Vec<u32>,
+}
+
+impl V3Route {
+    // Packed path for exactInput/quoteExactInput: token (20 bytes), fee (3 bytes), token, ...
+    fn encode_path(&self) -> Bytes {
+        let mut path = Vec::with_capacity(self.tokens.len() * 23);
+        for (i, token) in self.tokens.iter().enumerate() {
+            path.extend_from_slice(token.as_bytes());
+            if let Some(fee) = self.fees.get(i) {
+                path.extend_from_slice(&fee.to_be_bytes()[1..]);
+            }
+        }
+        Bytes::from(path)
+    }
+
+    fn describe(&self, symbols: &HashMap<Address, String>) -> String {
+        let name = |token: &Address| {
+            symbols
+                .get(token)
+                .cloned()
+                .unwrap_or_else(|| format!("{:?}", token))
+        };
+        let mut route = name(&self.tokens[0]);
+        for (token, fee) in self.tokens[1..].iter().zip(&self.fees) {
+            route.push_str(&format!(
+                " -({}%)-> {}",
+                *fee as f64 / 10_000.0,
+                name(token)
+            ));
+        }
+        route
+    }
+}
+
+// Where the token registry should be populated from
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum TokenRegistrySource {
+    /// The mainnet tokens bundled with the service
+    Builtin,
+    /// A JSON file: a token list in the Uniswap token-list standard, filtered to the
+    /// network's chain, or the plain array of `data/tokens.json`. Edits are picked up
+    /// without a restart.
+    File(PathBuf),
+    /// An explicit list of tokens
+    Tokens(Vec<TokenInfo>),
+}
+
+// Settings for caching token metadata and ENS names fetched from contracts
+#[derive(Debug, Clone)]
+pub struct CacheSettings {
+    pub enabled: bool,
+    /// How long metadata of a token discovered on-chain is trusted before it's read again
+    pub token_metadata_ttl: Duration,
+    pub ens_ttl: Duration,
+    /// How long a balance read is reused; writes refresh the balances they touch
+    pub balance_ttl: Duration,
+}
+
+impl Default for CacheSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            token_metadata_ttl: Duration::from_secs(7 * 24 * 3600),
+            ens_ttl: Duration::from_secs(600),
+            balance_ttl: Duration::from_secs(15),
+        }
+    }
+}
+
+// How the service polls the RPC and how much staleness it tolerates
+#[derive(Debug, Clone)]
+pub struct RpcSettings {
+    /// Interval between polls for pending transactions and new blocks
+    pub poll_interval: Duration,
+    /// Blocks to wait on top of the one a transaction is mined in
+    pub confirmations: usize,
+    /// How long a request waits for its transaction to confirm before returning it as
+    /// pending (None waits indefinitely)
+    pub tx_timeout: Option<Duration>,
+    /// Warn when the latest block is older than this (None disables the check)
+    pub max_block_lag: Option<Duration>,
+    /// Endpoints that must return the same result for a read when a network lists
+    /// several RPC URLs; 1 just fails over between them
+    pub quorum: usize,
+    /// Retries after rate limiting and dropped connections
+    pub retry: RetryPolicy,
+}
+
+impl Default for RpcSettings {
+    fn default() -> Self {
+        Self {
+            poll_interval: Duration::from_secs(2),
+            confirmations: 1,
+            tx_timeout: Some(Duration::from_secs(180)),
+            max_block_lag: Some(Duration::from_secs(60)),
+            quorum: 1,
+            retry: RetryPolicy::default(),
+        }
+    }
+}
+
+impl RpcSettings {
+    /// Read RPC_POLL_INTERVAL_MS, CONFIRMATIONS, TX_TIMEOUT_SECS (0 waits indefinitely),
+    /// MAX_BLOCK_LAG_SECS (0 disables the lag check), RPC_QUORUM, RPC_MAX_ATTEMPTS (1 disables retries), RPC_RETRY_BACKOFF_MS
+    /// and RPC_MAX_RETRY_BACKOFF_MS
+    pub fn from_env() -> Self {
+        let env_u64 = |key: &str| {
+            std::env::var(key)
+                .ok()
+                .and_then(|value| value.parse::<u64>().ok())
+        };
+        let defaults = Self::default();
+
+        Self {
+            poll_interval: env_u64("RPC_POLL_INTERVAL_MS")
+                .map(Duration::from_millis)
+                .unwrap_or(defaults.poll_interval),
+            confirmations: env_u64("CONFIRMATIONS")
+                .map(|confirmations| confirmations.max(1) as usize)
+                .unwrap_or(defaults.confirmations),
+            tx_timeout: match env_u64("TX_TIMEOUT_SECS") {
+                Some(0) => None,
+                Some(secs) => Some(Duration::from_secs(secs)),
+                None => defaults.tx_timeout,
+            },
+            max_block_lag: match env_u64("MAX_BLOCK_LAG_SECS") {
+                Some(0) => None,
+                Some(secs) => Some(Duration::from_secs(secs)),
+                None => defaults.max_block_lag,
+            },
+            quorum: env_u64("RPC_QUORUM")
+                .map(|quorum| quorum.max(1) as usize)
+                .unwrap_or(defaults.quorum),
+            retry: RetryPolicy {
+                max_attempts: env_u64("RPC_MAX_ATTEMPTS")
+                    .map(|attempts| attempts.clamp(1, u64::from(u32::MAX)) as u32)
+                    .unwrap_or(defaults.retry.max_attempts),
+                initial_backoff: env_u64("RPC_RETRY_BACKOFF_MS")
+                    .map(Duration::from_millis)
+                    .unwrap_or(defaults.retry.initial_backoff),
+                max_backoff: env_u64("RPC_MAX_RETRY_BACKOFF_MS")
+                    .map(Duration::from_millis)
+                    .unwrap_or(defaults.retry.max_backoff),
+            },
+        }
+    }
+}
+
+// Everything needed to connect the service to one chain
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NetworkConfig {
+    pub name: String,
+    pub chain_id: u64,
+    /// One URL, or several separated by commas to fail over between
+    pub rpc_url: String,
+    /// WebSocket endpoint for new-block subscriptions; heads are polled over
+    /// `rpc_url` without one
+    #[serde(default)]
+    pub ws_url: Option<String>,
+    /// Uniswap V2 router and WETH; None uses the builder default for the chain
+    #[serde(default)]
+    pub router: Option<RouterConfig>,
+    #[serde(default = "NetworkConfig::default_tokens")]
+    pub tokens: TokenRegistrySource,
+}
+
+impl NetworkConfig {
+    /// Well-known networks; RPC URLs can be overridden with `<NAME>_RPC_URL`, a
+    /// WebSocket endpoint given with `<NAME>_WS_URL` and a token list with
+    /// `<NAME>_TOKENS_FILE`
+    pub fn presets() -> Vec<Self> {
+        let preset = |name: &str, chain_id, default_rpc: &str, router: RouterConfig, tokens| {
+            let rpc_env = format!("{}_RPC_URL", name.to_uppercase());
+            Self {
+                name: name.to_string(),
+                chain_id,
+                rpc_url: std::env::var(rpc_env).unwrap_or_else(|_| default_rpc.to_string()),
+                ws_url: std::env::var(format!("{}_WS_URL", name.to_uppercase())).ok(),
+                router: Some(router),
+                tokens: std::env::var(format!("{}_TOKENS_FILE", name.to_uppercase()))
+                    .map(|path| TokenRegistrySource::File(path.into()))
+                    .unwrap_or(tokens),
+            }
+        };
+        let router = |router: &str, weth: &str| RouterConfig {
+            uniswap_v2_router: router.to_string(),
+            weth: weth.to_string(),
+            uniswap_v3: None,
+            universal_router: None,
+        };
+
+        vec![
+            preset(
+                "mainnet",
+                1,
+                "https://ethereum-rpc.publicnode.com",
+                RouterConfig::mainnet(),
+                TokenRegistrySource::Builtin,
+            ),
+            preset(
+                "sepolia",
+                11155111,
+                "https://ethereum-sepolia-rpc.publicnode.com",
+                router(
+                    "0xeE567Fe1712Faf6149d80dA1E6934E354124CfE3",
+                    "0xfFf9976782d46CC05630D1f6eBAb18b2324d6B14",
+                ),
+                TokenRegistrySource::Tokens(Vec::new()),
+            ),
+            preset(
+                "arbitrum",
+                42161,
+                "https://arb1.arbitrum.io/rpc",
+                RouterConfig {
+                    uniswap_v3: Some(UniswapV3Config::canonical()),
+                    ..router(
+                        "0x4752ba5DBc23f44D87826276BF6Fd6b1C372aD24",
+                        "0x82aF49447D8a07e3bd95BD0d56f35241523fBab1",
+                    )
+                },
+                TokenRegistrySource::Tokens(Vec::new()),
+            ),
+            preset(
+                "base",
+                8453,
+                "https://mainnet.base.org",
+                router(
+                    "0x4752ba5DBc23f44D87826276BF6Fd6b1C372aD24",
+                    "0x4200000000000000000000000000000000000006",
+                ),
+                TokenRegistrySource::Tokens(Vec::new()),
+            ),
+            // Anvil forking mainnet keeps mainnet contracts
+            preset(
+                "anvil",
+                31337,
+                "http://localhost:8545",
+                RouterConfig::mainnet(),
+                TokenRegistrySource::Builtin,
+            ),
+        ]
+    }
+
+    fn default_tokens() -> TokenRegistrySource {
+        TokenRegistrySource::Tokens(Vec::new())
+    }
+
+    /// Connect to the network's RPC endpoint and build a service for it, recording
+    /// submitted transactions in `pending` when given
+    pub fn connect(
+        &self,
+        settings: &RpcSettings,
+        pending: Option<Arc<PendingStore>>,
+    ) -> Result<BlockchainService> {
+        // A quorum can't exceed the endpoints listed, so networks with fewer just fail over
+        let endpoints = self
+            .rpc_url
+            .split(',')
+            .filter(|url| !url.trim().is_empty())
+            .count();
+        let client = FailoverClient::new(&self.rpc_url, settings.quorum.min(endpoints))?
+            .with_retry(settings.retry.clone());
+        let provider = Provider::new(client).interval(settings.poll_interval);
+        let mut builder = BlockchainService::builder()
+            .provider(Arc::new(provider))
+            .chain_id(self.chain_id)
+            .token_registry(self.tokens.clone())
+            .discovered_tokens_file(
+                data_path(DISCOVERED_TOKENS_DIR).join(format!("{}.json", self.name.to_lowercase())),
+            )
+            .rpc_settings(settings.clone());
+        if let Some(router) = &self.router {
+            builder = builder.router(self.chain_id, router.clone());
+        }
+        if let Some(pending) = pending {
+            builder = builder.pending_store(pending);
+        }
+        builder.build()
+    }
+}
+
+// Configured networks and their (lazily connected) services
+pub struct NetworkRegistry {
+    default_network: String,
+    networks: Vec<NetworkConfig>,
+    settings: RpcSettings,
+    pending: Option<Arc<PendingStore>>,
+    services: RwLock<HashMap<String, Arc<BlockchainService>>>,
+}
+
+impl NetworkRegistry {
+    /// A registry whose default network is `default`
+    pub fn new(default: NetworkConfig) -> Self {
+        Self {
+            default_network: default.name.clone(),
+            networks: vec![default],
+            settings: RpcSettings::default(),
+            pending: None,
+            services: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Polling and lag settings applied to every network's service
+    pub fn with_rpc_settings(mut self, settings: RpcSettings) -> Self {
+        self.settings = settings;
+        self
+    }
+
+    /// Where every network's service records transactions awaiting a receipt
+    pub fn with_pending_store(mut self, store: Arc<PendingStore>) -> Self {
+        self.pending = Some(store);
+        self
+    }
+
+    /// Add a network, replacing any existing network with the same name
+    pub fn add(&mut self, network: NetworkConfig) {
+        if let Ok(mut services) = self.services.write() {
+            services.remove(&network.name);
+        }
+        self.networks
+            .retain(|existing| existing.name != network.name);
+        self.networks.push(network);
+    }
+
+    /// Add networks from a JSON array of `NetworkConfig`
+    pub fn load_file<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        let content = fs::read_to_string(path.as_ref())?;
+        let networks: Vec<NetworkConfig> = serde_json::from_str(&content)?;
+        info!(
+            "Loaded {} networks from {}",
+            networks.len(),
+            path.as_ref().display()
+        );
+        for network in networks {
+            self.add(network);
+        }
+        Ok(())
+    }
+
+    pub fn default_network(&self) -> &str {
+        &self.default_network
+    }
+
+    pub fn networks(&self) -> &[NetworkConfig] {
+        &self.networks
+    }
+
+    pub fn get(&self, name: &str) -> Option<&NetworkConfig> {
+        self.networks
+            .iter()
+            .find(|network| network.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Service for a network (the default when `name` is None), connecting on first use
+    pub fn service(&self, name: Option<&str>) -> Result<Arc<BlockchainService>> {
+        let network = self
+            .get(name.unwrap_or(&self.default_network))
+            .ok_or_else(|| anyhow!("Unknown network: {}", name.unwrap_or_default()))?;
+
+        if let Some(service) = self
+            .services
+            .read()
+            .ok()
+            .and_then(|services| services.get(&network.name).cloned())
+        {
+            return Ok(service);
+        }
+
+        info!(
+            "Connecting to {} (chain {}) at {}",
+            network.name, network.chain_id, network.rpc_url
+        );
+        let service = Arc::new(network.connect(&self.settings, self.pending.clone())?);
+        if let Ok(mut services) = self.services.write() {
+            services.insert(network.name.clone(), service.clone());
+        }
+        Ok(service)
+    }
+}
+
+pub struct BlockchainServiceBuilder {
+    provider: Option<EthProvider>,
+    chain_id: u64,
+    routers: HashMap<u64, RouterConfig>,
+    erc20_abi: AbiSource,
+    uniswap_router_abi: AbiSource,
+    uniswap_v3_router_abi: AbiSource,
+    erc721_abi: AbiSource,
+    token_registry: TokenRegistrySource,
+    discovered_tokens_file: Option<PathBuf>,
+    cache_settings: CacheSettings,
+    rpc_settings: RpcSettings,
+    pending_store: Option<Arc<PendingStore>>,
+}
+
+impl Default for BlockchainServiceBuilder {
+    fn default() -> Self {
+        let mut routers = HashMap::new();
+        routers.insert(1, RouterConfig::mainnet());
+
+        Self {
+            provider: None,
+            chain_id: 1,
+            routers,
+            erc20_abi: AbiSource::File(data_path("erc20_abi.json")),
+            uniswap_router_abi: AbiSource::File(data_path("uniswap_v2_router_abi.json")),
+            uniswap_v3_router_abi: AbiSource::File(data_path("uniswap_v3_router_abi.json")),
+            erc721_abi: AbiSource::File(data_path("erc721_abi.json")),
+            token_registry: TokenRegistrySource::Builtin,
+            discovered_tokens_file: None,
+            cache_settings: CacheSettings::default(),
+            rpc_settings: RpcSettings::default(),
+            pending_store: None,
+        }
+    }
+}
+
+impl BlockchainServiceBuilder {
+    pub fn provider(mut self, provider: EthProvider) -> Self {
+        self.provider = Some(provider);
+        self
+    }
+
+    /// Chain the service targets; selects the router configuration to use
+    pub fn chain_id(mut self, chain_id: u64) -> Self {
+        self.chain_id = chain_id;
+        self
+    }
+
+    pub fn router(mut self, chain_id: u64, router: RouterConfig) -> Self {
+        self.routers.insert(chain_id, router);
+        self
+    }
+
+    pub fn erc20_abi(mut self, source: AbiSource) -> Self {
+        self.erc20_abi = source;
+        self
+    }
+
+    pub fn uniswap_router_abi(mut self, source: AbiSource) -> Self {
+        self.uniswap_router_abi = source;
+        self
+    }
+
+    pub fn uniswap_v3_router_abi(mut self, source: AbiSource) -> Self {
+        self.uniswap_v3_router_abi = source;
+        self
+    }
+
+    pub fn erc721_abi(mut self, source: AbiSource) -> Self {
+        self.erc721_abi = source;
+        self
+    }
+
+    pub fn token_registry(mut self, source: TokenRegistrySource) -> Self {
+        self.token_registry = source;
+        self
+    }
+
+    /// JSON file that tokens discovered on-chain are kept in across restarts
+    pub fn discovered_tokens_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.discovered_tokens_file = Some(path.into());
+        self
+    }
+
+    pub fn cache_settings(mut self, settings: CacheSettings) -> Self {
+        self.cache_settings = settings;
+        self
+    }
+
+    pub fn rpc_settings(mut self, settings: RpcSettings) -> Self {
+        self.rpc_settings = settings;
+        self
+    }
+
+    pub fn pending_store(mut self, store: Arc<PendingStore>) -> Self {
+        self.pending_store = Some(store);
+        self
+    }
+
+    pub fn build(self) -> Result<BlockchainService> {
+        let provider = self
+            .provider
+            .ok_or_else(|| anyhow!("BlockchainService requires a provider"))?;
+
+        let router = self
+            .routers
+            .get(&self.chain_id)
+            .cloned()
+            .ok_or_else(|| anyhow!("No router configured for chain {}", self.chain_id))?;
+
+        let erc20_abi = Self::load_abi(
+            self.erc20_abi,
+            "ERC20",
+            AbiInterface::Erc20,
+            BlockchainService::get_default_erc20_abi,
+        )?;
+        let uniswap_router_abi = Self::load_abi(
+            self.uniswap_router_abi,
+            "Uniswap Router",
+            AbiInterface::UniswapV2Router,
+            BlockchainService::get_default_uniswap_router_abi,
+        )?;
+        let uniswap_v3_router_abi = Self::load_abi(
+            self.uniswap_v3_router_abi,
+            "Uniswap V3 Router",
+            AbiInterface::UniswapV3Router,
+            BlockchainService::get_default_uniswap_v3_router_abi,
+        )?;
+        let uniswap_v3_quoter_abi = BlockchainService::get_default_uniswap_v3_quoter_abi()?;
+        let erc721_abi = Self::load_abi(
+            self.erc721_abi,
+            "ERC721",
+            AbiInterface::Erc721,
+            BlockchainService::get_default_erc721_abi,
+        )?;
+
+        let mut token_registry = TokenRegistry::load(self.token_registry, self.chain_id)?;
+        if let Some(path) = self.discovered_tokens_file {
+            token_registry = token_registry.with_discovered_file(path);
+        }
+        let token_registry = Arc::new(token_registry);
+
+        Ok(BlockchainService {
+            provider,
+            chain_id: self.chain_id,
+            router,
+            erc20_abi,
+            uniswap_router_abi,
+            uniswap_v3_router_abi,
+            uniswap_v3_quoter_abi,
+            erc721_abi,
+            token_registry,
+            ens_cache: Arc::new(RwLock::new(HashMap::new())),
+            reverse_ens_cache: Arc::new(RwLock::new(HashMap::new())),
+            balance_cache: Arc::new(RwLock::new(HashMap::new())),
+            cache_settings: self.cache_settings,
+            rpc_settings: self.rpc_settings,
+            nonces: NonceManager::default(),
+            archive_node: Arc::new(tokio::sync::OnceCell::new()),
+            history_cache: Arc::new(RwLock::new(HashMap::new())),
+            verified_contracts: Arc::new(RwLock::new(HashMap::new())),
+            pending: self.pending_store,
+            heads: Arc::new(HeadTracker::new()),
+        })
+    }
+
+    fn load_abi(
+        source: AbiSource,
+        label: &str,
+        interface: AbiInterface,
+        bundled: fn() -> Result<Abi>,
+    ) -> Result<Abi> {
+        match source {
+            // Files may be bare ABIs or Foundry/Hardhat artifacts
+            AbiSource::File(path) => match AbiLoader::load_abi(&path, Some(interface)) {
+                Ok(abi) => {
+                    info!("Successfully loaded {} ABI from {}", label, path.display());
+                    Ok(abi)
+                }
+                Err(e) => {
+                    warn!(
+                        "Failed to load {} ABI from {}: {}",
+                        label,
+                        path.display(),
+                        e
+                    );
+                    warn!("Using bundled {} ABI", label);
+                    bundled()
+                }
+            },
+            AbiSource::Json(json) => Ok(serde_json::from_str(&json)?),
+            AbiSource::Abi(abi) => Ok(abi),
+            AbiSource::Bundled => bundled(),
+        }
+    }
+}
+
+impl BlockchainService {
+    pub fn new(provider: EthProvider) -> Result<Self> {
+        Self::builder().provider(provider).build()
+    }
+
+    pub fn builder() -> BlockchainServiceBuilder {
+        BlockchainServiceBuilder::default()
+    }
+
+    pub fn chain_id(&self) -> u64 {
+        self.chain_id
+    }
+
+    pub fn provider(&self) -> EthProvider {
+        self.provider.clone()
+    }
+
+    pub fn rpc_settings(&self) -> &RpcSettings {
+        &self.rpc_settings
+    }
+
+    /// Health of each of the network's RPC endpoints
+    pub fn rpc_endpoints(&self) -> Vec<EndpointHealth> {
+        let client: &FailoverClient = (*self.provider).as_ref();
+        client.health()
+    }
+
+    /// Latest chain head, as followed by `heads::spawn_head_subscription`
+    pub fn heads(&self) -> &HeadTracker {
+        &self.heads
+    }
+
+    /// The store sent transactions are recorded in until they resolve, if any
+    pub fn pending_store(&self) -> Option<&PendingStore> {
+        self.pending.as_deref()
+    }
+
+    /// Whether the service talks to a local dev chain (Anvil, Hardhat) that accepts
+    /// state-changing debug RPCs
+    pub fn is_dev_chain(&self) -> bool {
+        matches!(self.chain_id, 31337 | 1337)
+    }
+
+    /// Drop locally tracked nonces so the next sends read them from the node again
+    pub async fn resync_nonces(&self) {
+        self.nonces.resync_all().await;
+    }
+
+    /// Age of the latest block, warning when it exceeds the configured tolerance.
+    /// Returns the lag only when the RPC is behind.
+    pub async fn check_block_lag(&self) -> Result<Option<Duration>> {
+        let Some(max_lag) = self.rpc_settings.max_block_lag else {
+            return Ok(None);
+        };
+        // Anvil and other dev chains mine on demand, so an old head is normal there
+        if self.is_dev_chain() {
+            return Ok(None);
+        }
+
+        // A followed head is as fresh as the RPC can report, so it saves a request
+        let (block_number, timestamp) = match self.heads.latest() {
+            Some(head) if self.heads.is_live() => (head.block_number, head.timestamp),
+            _ => {
+                let block = self
+                    .provider
+                    .get_block(BlockNumber::Latest)
+                    .await?
+                    .ok_or_else(|| anyhow!("RPC returned no latest block"))?;
+                (
+                    block
+                        .number
+                        .map(|number| number.as_u64())
+                        .unwrap_or_default(),
+                    block.timestamp.as_u64(),
+                )
+            }
+        };
+        let now = chrono::Utc::now().timestamp().max(0) as u64;
+        let lag = Duration::from_secs(now.saturating_sub(timestamp));
+
+        if lag > max_lag {
+            warn!(
+                "RPC for chain {} is {}s behind (latest block {})",
+                self.chain_id,
+                lag.as_secs(),
+                block_number
+            );
+            return Ok(Some(lag));
+        }
+        Ok(None)
+    }
+
+    pub fn router_config(&self) -> &RouterConfig {
+        &self.router
+    }
+
+    /// Whether the RPC serves state for old blocks; probed once by reading a balance at block 1
+    pub async fn is_archive_node(&self) -> bool {
+        *self
+            .archive_node
+            .get_or_init(|| async {
+                let archive = self
+                    .provider
+                    .get_balance(Address::zero(), Some(BlockNumber::Number(1.into()).into()))
+                    .await
+                    .is_ok();
+                info!("RPC archive support: {}", archive);
+                archive
+            })
+            .await
+    }
+
+    pub fn cached_history(&self, key: &str) -> Option<Value> {
+        self.history_cache
+            .read()
+            .ok()
+            .and_then(|cache| cache.get(key).cloned())
+    }
+
+    pub fn cache_history(&self, key: &str, value: Value) {
+        if let Ok(mut cache) = self.history_cache.write() {
+            cache.insert(key.to_string(), value);
+        }
+    }
+
+    /// A contract's Etherscan lookup: None when it hasn't been looked up, Some(None) when
+    /// it isn't verified
+    pub fn cached_verified_contract(
+        &self,
+        address: Address,
+    ) -> Option<Option<Arc<VerifiedContract>>> {
+        self.verified_contracts
+            .read()
+            .ok()
+            .and_then(|cache| cache.get(&address).cloned())
+    }
+
+    /// Remember a contract's Etherscan lookup; its ABI is then used for calls to it
+    pub fn cache_verified_contract(
+        &self,
+        address: Address,
+        contract: Option<VerifiedContract>,
+    ) -> Option<Arc<VerifiedContract>> {
+        let contract = contract.map(Arc::new);
+        if let Ok(mut cache) = self.verified_contracts.write() {
+            cache.insert(address, contract.clone());
+        }
+        contract
+    }
+
+    /// Balance at a past block; needs an archive node
+    pub async fn get_balance_at_block(
+        &self,
+        address: &str,
+        token: Option<&str>,
+        block: u64,
+    ) -> Result<BalanceResult> {
+        let owner = Address::from_str(address)?;
+        let block_id = BlockNumber::Number(block.into()).into();
+
+        match token {
+            None => {
+                let balance = self.provider.get_balance(owner, Some(block_id)).await?;
+                Ok(BalanceResult {
+                    address: address.to_string(),
+                    balance: self.format_balance(balance, 18),
+                    token: Some("ETH".to_string()),
+                    decimals: 18,
+                    display_name: None,
+                })
+            }
+            Some(token) => {
+                let token_info = self.resolve_token(token).await?;
+                let contract = Contract::new(
+                    Address::from_str(&token_info.address)?,
+                    self.erc20_abi.clone(),
+                    self.provider.clone(),
+                );
+                let balance: U256 = contract
+                    .method::<_, U256>("balanceOf", owner)?
+                    .block(block_id)
+                    .call()
+                    .await?;
+                Ok(BalanceResult {
+                    address: address.to_string(),
+                    balance: self.format_balance(balance, token_info.decimals),
+                    token: Some(token_info.symbol),
+                    decimals: token_info.decimals,
+                    display_name: None,
+                })
+            }
+        }
+    }
+
+    /// Value of one token in `quote` (a token or "ETH") at a past block, quoted by the
+    /// Uniswap V2 router; needs an archive node
+    pub async fn quote_at_block(&self, token: &str, quote: &str, block: u64) -> Result<f64> {
+        let weth = Address::from_str(&self.router.weth)?;
+        let token_info = self.resolve_token(token).await?;
+        let token_addr = Address::from_str(&token_info.address)?;
+        let (quote_addr, quote_decimals) = if quote.eq_ignore_ascii_case("eth") {
+            (weth, 18)
+        } else {
+            let quote_info = self.resolve_token(quote).await?;
+            (Address::from_str(&quote_info.address)?, quote_info.decimals)
+        };
+
+        let path = if token_addr == weth || quote_addr == weth {
+            vec![token_addr, quote_addr]
+        } else {
+            vec![token_addr, weth, quote_addr]
+        };
+        let amount_in = U256::from(10).pow(U256::from(token_info.decimals));
+
+        let router = Contract::new(
+            Address::from_str(&self.router.uniswap_v2_router)?,
+            self.uniswap_router_abi.clone(),
+            self.provider.clone(),
+        );
+        let amounts: Vec<U256> = router
+            .method::<_, Vec<U256>>("getAmountsOut", (amount_in, path))?
+            .block(BlockNumber::Number(block.into()))
+            .call()
+            .await?;
+        let amount_out = amounts
+            .last()
+            .copied()
+            .ok_or_else(|| anyhow!("Router returned no quote"))?;
+
+        Ok(format_units(amount_out, quote_decimals as u32)?.parse()?)
+    }
+
+    /// Whether a symbol or address is part of the configured token registry
+    pub fn is_registered_token(&self, identifier: &str) -> bool {
+        identifier.eq_ignore_ascii_case("eth") || self.registered_token(identifier).is_some()
+    }
+
+    fn registered_token(&self, identifier: &str) -> Option<TokenInfo> {
+        self.token_registry.get(identifier)
+    }
+
+    /// Add a token to the registry so it can be referred to by symbol, replacing any
+    /// token already registered under the same symbol or address
+    pub fn register_token(&self, token: TokenInfo) {
+        self.token_registry.register(token);
+    }
+
+    /// Reload the token registry from its source, returning how many tokens it lists
+    pub fn refresh_token_list(&self) -> Result<usize> {
+        self.token_registry.refresh()
+    }
+
+    pub fn token_source(&self) -> &TokenRegistrySource {
+        self.token_registry.source()
+    }
+
+    fn get_default_erc20_abi() -> Result<Abi> {
+        let abi_json = r#"[
+          {
+              "constant": true,
+              "inputs": [{"name": "_owner", "type": "address"}],
+              "name": "balanceOf",
+              "outputs": [{"name": "balance", "type": "uint256"}],
+              "type": "function"
+          },
+          {
+              "constant": true,
+              "inputs": [],
+              "name": "decimals",
+              "outputs": [{"name": "", "type": "uint8"}],
+              "type": "function"
+          },
+          {
+              "constant": true,
+              "inputs": [],
+              "name": "symbol",
+              "outputs": [{"name": "", "type": "string"}],
+              "type": "function"
+          },
+          {
+              "constant": true,
+              "inputs": [],
+              "name": "name",
+              "outputs": [{"name": "", "type": "string"}],
+              "type": "function"
+          },
+          {
+              "constant": false,
+              "inputs": [
+                  {"name": "_to", "type": "address"},
+                  {"name": "_value", "type": "uint256"}
+              ],
+              "name": "transfer",
+              "outputs": [{"name": "", "type": "bool"}],
+              "type": "function"
+          },
+          {
+              "constant": false,
+              "inputs": [
+                  {"name": "_spender", "type": "address"},
+                  {"name": "_value", "type": "uint256"}
+              ],
+              "name": "approve",
+              "outputs": [{"name": "", "type": "bool"}],
+              "type": "function"
+          },
+          {
+              "constant": true,
+              "inputs": [
+                  {"name": "_owner", "type": "address"},
+                  {"name": "_spender", "type": "address"}
+              ],
+              "name": "allowance",
+              "outputs": [{"name": "remaining", "type": "uint256"}],
+              "type": "function"
+          },
+          {
+              "constant": false,
+              "inputs": [
+                  {"name": "_from", "type": "address"},
+                  {"name": "_to", "type": "address"},
+                  {"name": "_value", "type": "uint256"}
+              ],
+              "name": "transferFrom",
+              "outputs": [{"name": "", "type": "bool"}],
+              "type": "function"
+          },
+          {
+              "constant": true,
+              "inputs": [],
+              "name": "totalSupply",
+              "outputs": [{"name": "", "type": "uint256"}],
+              "type": "function"
+          }
+      ]"#;
+
+        let abi = serde_json::from_str(abi_json)?;
+        Ok(abi)
+    }
+
+    fn get_default_uniswap_router_abi() -> Result<Abi> {
+        // This is a minimal ABI for Uniswap V2 Router with just the methods we need
+        let abi_json = r#"[
+          {
+              "inputs": [
+                  {"internalType": "uint256", "name": "amountIn", "type": "uint256"},
+                  {"internalType": "uint256", "name": "amountOutMin", "type": "uint256"},
+                  {"internalType": "address[]", "name": "path", "type": "address[]"},
+                  {"internalType": "address", "name": "to", "type": "address"},
+                  {"internalType": "uint256", "name": "deadline", "type": "uint256"}
+              ],
+              "name": "swapExactTokensForTokens",
+              "outputs": [{"internalType": "uint256[]", "name": "amounts", "type": "uint256[]"}],
+              "stateMutability": "nonpayable",
+              "type": "function"
+          },
+          {
+              "inputs": [
+                  {"internalType": "uint256", "name": "amountOutMin", "type": "uint256"},
+                  {"internalType": "address[]", "name": "path", "type": "address[]"},
+                  {"internalType": "address", "name": "to", "type": "address"},
+                  {"internalType": "uint256", "name": "deadline", "type": "uint256"}
+              ],
+              "name": "swapExactETHForTokens",
+              "outputs": [{"internalType": "uint256[]", "name": "amounts", "type": "uint256[]"}],
+              "stateMutability": "payable",
+              "type": "function"
+          },
+          {
+              "inputs": [
+                  {"internalType": "uint256", "name": "amountIn", "type": "uint256"},
+                  {"internalType": "uint256", "name": "amountOutMin", "type": "uint256"},
+                  {"internalType": "address[]", "name": "path", "type": "address[]"},
+                  {"internalType": "address", "name": "to", "type": "address"},
+                  {"internalType": "uint256", "name": "deadline", "type": "uint256"}
+              ],
+              "name": "swapExactTokensForETH",
+              "outputs": [{"internalType": "uint256[]", "name": "amounts", "type": "uint256[]"}],
+              "stateMutability": "nonpayable",
+              "type": "function"
+          },
+          {
+              "inputs": [
+                  {"internalType": "uint256", "name": "amountIn", "type": "uint256"},
+                  {"internalType": "address[]", "name": "path", "type": "address[]"}
+              ],
+              "name": "getAmountsOut",
+              "outputs": [{"internalType": "uint256[]", "name": "amounts", "type": "uint256[]"}],
+              "stateMutability": "view",
+              "type": "function"
+          }
+      ]"#;
+
+        let abi = serde_json::from_str(abi_json)?;
+        Ok(abi)
+    }
+
+    fn get_default_uniswap_v3_router_abi() -> Result<Abi> {
+        // SwapRouter methods used for exact-input swaps, plus unwrapping WETH for ETH output
+        let abi_json = r#"[
+          {
+              "inputs": [
+                  {
+                      "components": [
+                          {"internalType": "address", "name": "tokenIn", "type": "address"},
+                          {"internalType": "address", "name": "tokenOut", "type": "address"},
+                          {"internalType": "uint24", "name": "fee", "type": "uint24"},
+                          {"internalType": "address", "name": "recipient", "type": "address"},
+                          {"internalType": "uint256", "name": "deadline", "type": "uint256"},
+                          {"internalType": "uint256", "name": "amountIn", "type": "uint256"},
+                          {"internalType": "uint256", "name": "amountOutMinimum", "type": "uint256"},
+                          {"internalType": "uint160", "name": "sqrtPriceLimitX96", "type": "uint160"}
+                      ],
+                      "internalType": "struct ISwapRouter.ExactInputSingleParams",
+                      "name": "params",
+                      "type": "tuple"
+                  }
+              ],
+              "name": "exactInputSingle",
+              "outputs": [{"internalType": "uint256", "name": "amountOut", "type": "uint256"}],
+              "stateMutability": "payable",
+              "type": "function"
+          },
+          {
+              "inputs": [
+                  {
+                      "components": [
+                          {"internalType": "bytes", "name": "path", "type": "bytes"},
+                          {"internalType": "address", "name": "recipient", "type": "address"},
+                          {"internalType": "uint256", "name": "deadline", "type": "uint256"},
+                          {"internalType": "uint256", "name": "amountIn", "type": "uint256"},
+                          {"internalType": "uint256", "name": "amountOutMinimum", "type": "uint256"}
+                      ],
+                      "internalType": "struct ISwapRouter.ExactInputParams",
+                      "name": "params",
+                      "type": "tuple"
+                  }
+              ],
+              "name": "exactInput",
+              "outputs": [{"internalType": "uint256", "name": "amountOut", "type": "uint256"}],
+              "stateMutability": "payable",
+              "type": "function"
+          },
+          {
+              "inputs": [{"internalType": "bytes[]", "name": "data", "type": "bytes[]"}],
+              "name": "multicall",
+              "outputs": [{"internalType": "bytes[]", "name": "results", "type": "bytes[]"}],
+              "stateMutability": "payable",
+              "type": "function"
+          },
+          {
+              "inputs": [
+                  {"internalType": "uint256", "name": "amountMinimum", "type": "uint256"},
+                  {"internalType": "address", "name": "recipient", "type": "address"}
+              ],
+              "name": "unwrapWETH9",
+              "outputs": [],
+              "stateMutability": "payable",
+              "type": "function"
+          }
+        ]"#;
+
+        let abi = serde_json::from_str(abi_json)?;
+        Ok(abi)
+    }
+
+    fn get_default_uniswap_v3_quoter_abi() -> Result<Abi> {
+        // Quoter V1; its quote methods aren't view functions but work through eth_call
+        let abi_json = r#"[
+          {
+              "inputs": [
+                  {"internalType": "bytes", "name": "path", "type": "bytes"},
+                  {"internalType": "uint256", "name": "amountIn", "type": "uint256"}
+              ],
+              "name": "quoteExactInput",
+              "outputs": [{"internalType": "uint256", "name": "amountOut", "type": "uint256"}],
+              "stateMutability": "nonpayable",
+              "type": "function"
+          }
+        ]"#;
+
+        let abi = serde_json::from_str(abi_json)?;
+        Ok(abi)
+    }
+
+    fn get_default_erc721_abi() -> Result<Abi> {
+        // Minimal ERC-721 ABI (plus the optional enumerable extension)
+        let abi_json = r#"[
+          {
+              "inputs": [{"name": "owner", "type": "address"}],
+              "name": "balanceOf",
+              "outputs": [{"name": "", "type": "uint256"}],
+              "stateMutability": "view",
+              "type": "function"
+          },
+          {
+              "inputs": [{"name": "tokenId", "type": "uint256"}],
+              "name": "ownerOf",
+              "outputs": [{"name": "", "type": "address"}],
+              "stateMutability": "view",
+              "type": "function"
+          },
+          {
+              "inputs": [],
+              "name": "name",
+              "outputs": [{"name": "", "type": "string"}],
+              "stateMutability": "view",
+              "type": "function"
+          },
+          {
+              "inputs": [
+                  {"name": "owner", "type": "address"},
+                  {"name": "index", "type": "uint256"}
+              ],
+              "name": "tokenOfOwnerByIndex",
+              "outputs": [{"name": "", "type": "uint256"}],
+              "stateMutability": "view",
+              "type": "function"
+          },
+          {
+              "inputs": [
+                  {"name": "from", "type": "address"},
+                  {"name": "to", "type": "address"},
+                  {"name": "tokenId", "type": "uint256"}
+              ],
+              "name": "safeTransferFrom",
+              "outputs": [],
+              "stateMutability": "nonpayable",
+              "type": "function"
+          }
+      ]"#;
+
+        let abi = serde_json::from_str(abi_json)?;
+        Ok(abi)
+    }
+
+    /// Parse a hex address or resolve an ENS name, caching resolved names
+    pub async fn parse_address(&self, name_or_address: &str) -> Result<Address> {
+        if !utils::is_ens_name(name_or_address) {
+            return utils::parse_address(name_or_address);
+        }
+
+        let name = name_or_address.trim().to_lowercase();
+        if self.cache_settings.enabled
+            && let Some((address, resolved_at)) = self
+                .ens_cache
+                .read()
+                .ok()
+                .and_then(|cache| cache.get(&name).copied())
+            && resolved_at.elapsed() < self.cache_settings.ens_ttl
+        {
+            return Ok(address);
+        }
+
+        let address = self
+            .provider
+            .resolve_name(&name)
+            .await
+            .map_err(|e| anyhow!("Could not resolve ENS name {}: {}", name, e))?;
+        info!("Resolved {} to {:?}", name, address);
+
+        if self.cache_settings.enabled
+            && let Ok(mut cache) = self.ens_cache.write()
+        {
+            cache.insert(name, (address, Instant::now()));
+        }
+        Ok(address)
+    }
+
+    /// Like `parse_address`, returning the checksummed hex string
+    pub async fn resolve_address(&self, name_or_address: &str) -> Result<String> {
+        let address = self.parse_address(name_or_address).await?;
+        Ok(to_checksum(&address, None))
+    }
+
+    /// Primary ENS name of an address, checked against forward resolution so a
+    /// reverse record can't claim a name it doesn't own
+    pub async fn lookup_name(&self, address: Address) -> Option<String> {
+        if self.cache_settings.enabled
+            && let Some((name, looked_up_at)) = self
+                .reverse_ens_cache
+                .read()
+                .ok()
+                .and_then(|cache| cache.get(&address).cloned())
+            && looked_up_at.elapsed() < self.cache_settings.ens_ttl
+        {
+            return name;
+        }
+
+        let name = match self.provider.lookup_address(address).await {
+            Ok(name) => match self.provider.resolve_name(&name).await {
+                Ok(resolved) if resolved == address => Some(name),
+                _ => None,
+            },
+            // Most addresses have no reverse record, and chains without ENS error here
+            Err(_) => None,
+        };
+
+        if self.cache_settings.enabled
+            && let Ok(mut cache) = self.reverse_ens_cache.write()
+        {
+            cache.insert(address, (name.clone(), Instant::now()));
+        }
+        name
+    }
+
+    pub async fn get_balance(&self, query: BalanceQuery) -> Result<BalanceResult> {
+        let address = self.parse_address(&query.address).await?;
+        let key = self.balance_key(address, query.token.as_deref()).await?;
+
+        if let Some(mut cached) = self.cached_balance(&key) {
+            cached.address = query.address;
+            cached.display_name = self.lookup_name(address).await;
+            return Ok(cached);
+        }
+
+        let mut result = match query.token {
+            None => {
+                // ETH balance
+                let balance = self.provider.get_balance(address, None).await?;
+                BalanceResult {
+                    address: query.address,
+                    balance: self.format_balance(balance, 18),
+                    token: None,
+                    decimals: 18,
+                    display_name: None,
+                }
+            }
+            Some(token_identifier) if token_identifier.eq_ignore_ascii_case("eth") => {
+                let balance = self.provider.get_balance(address, None).await?;
+                BalanceResult {
+                    address: query.address,
+                    balance: self.format_balance(balance, 18),
+                    token: Some("ETH".to_string()),
+                    decimals: 18,
+                    display_name: None,
+                }
+            }
+            Some(token_identifier) => {
+                // ERC20 token balance
+                self.get_erc20_balance(&query.address, &token_identifier)
+                    .await?
+            }
+        };
+
+        self.store_balance(key, &result);
+        result.display_name = self.lookup_name(address).await;
+        Ok(result)
+    }
+
+    async fn balance_key(&self, owner: Address, token: Option<&str>) -> Result<(Address, String)> {
+        let token = match token {
+            None => "eth".to_string(),
+            Some(token) if token.eq_ignore_ascii_case("eth") => "eth".to_string(),
+            Some(token) => self.resolve_token(token).await?.address.to_lowercase(),
+        };
+        Ok((owner, token))
+    }
+
+    fn cached_balance(&self, key: &(Address, String)) -> Option<BalanceResult> {
+        if !self.cache_settings.enabled {
+            return None;
+        }
+
+        let cache = self.balance_cache.read().ok()?;
+        cache
+            .get(key)
+            .filter(|(_, fetched_at)| fetched_at.elapsed() < self.cache_settings.balance_ttl)
+            .map(|(balance, _)| balance.clone())
+    }
+
+    fn store_balance(&self, key: (Address, String), balance: &BalanceResult) {
+        if self.cache_settings.enabled
+            && let Ok(mut cache) = self.balance_cache.write()
+        {
+            cache.insert(key, (balance.clone(), Instant::now()));
+        }
+    }
+
+    /// Read the balances a write is about to change, skipping the cache so the
+    /// "before" numbers are current
+    pub async fn balances_before(
+        &self,
+        targets: &[(String, Option<String>)],
+    ) -> Vec<BalanceChange> {
+        let mut changes = Vec::new();
+        for (address, token) in targets {
+            if let Ok(owner) = self.parse_address(address).await
+                && let Ok(key) = self.balance_key(owner, token.as_deref()).await
+                && let Ok(mut cache) = self.balance_cache.write()
+            {
+                cache.remove(&key);
+            }
+
+            let before = self
+                .get_balance(BalanceQuery {
+                    address: address.clone(),
+                    token: token.clone(),
+                })
+                .await
+                .map(|result| result.balance)
+                .ok();
+            changes.push(BalanceChange {
+                address: address.clone(),
+                token: token.clone().unwrap_or_else(|| "ETH".to_string()),
+                before,
+                after: None,
+            });
+        }
+        changes
+    }
+
+    /// Replace cached balances a write changed with their values at the block it was mined
+    /// in, so the next balance query reflects the write even on a lagging RPC node
+    pub async fn refresh_balances(
+        &self,
+        mut changes: Vec<BalanceChange>,
+        block: Option<u64>,
+    ) -> Vec<BalanceChange> {
+        for change in &mut changes {
+            let token =
+                Some(change.token.as_str()).filter(|token| !token.eq_ignore_ascii_case("eth"));
+            let Ok(owner) = self.parse_address(&change.address).await else {
+                continue;
+            };
+            let Ok(key) = self.balance_key(owner, token).await else {
+                continue;
+            };
+            if let Ok(mut cache) = self.balance_cache.write() {
+                cache.remove(&key);
+            }
+
+            let after = match block {
+                Some(block) => {
+                    self.get_balance_at_block(&format!("{:?}", owner), token, block)
+                        .await
+                }
+                None => {
+                    self.get_balance(BalanceQuery {
+                        address: change.address.clone(),
+                        token: token.map(|token| token.to_string()),
+                    })
+                    .await
+                }
+            };
+            match after {
+                Ok(result) => {
+                    if block.is_some() {
+                        self.store_balance(key, &result);
+                    }
+                    change.after = Some(result.balance);
+                }
+                Err(e) => warn!(
+                    "Failed to refresh {} balance of {}: {}",
+                    change.token, change.address, e
+                ),
+            }
+        }
+        changes
+    }
+
+    async fn get_erc20_balance(
+        &self,
+        address: &str,
+        token_identifier: &str,
+    ) -> Result<BalanceResult> {
+        // Resolve token info
+        let token_info = self.resolve_token(token_identifier).await?;
+
+        // Create contract instance
+        let token_address = Address::from_str(&token_info.address)?;
+        let contract = Contract::new(token_address, self.erc20_abi.clone(), self.provider.clone());
+
+        // Get balance
+        let owner_address = self.parse_address(address).await?;
+        let balance: U256 = contract
+            .method::<_, U256>("balanceOf", owner_address)?
+            .call()
+            .await?;
 
         Ok(BalanceResult {
             address: address.to_string(),
             balance: self.format_balance(balance, token_info.decimals),
             token: Some(token_info.symbol),
             decimals: token_info.decimals,
+            display_name: None,
+        })
+    }
+
+    pub async fn resolve_token(&self, identifier: &str) -> Result<TokenInfo> {
+        // Try to find by symbol first (case insensitive)
+        if let Some(token) = self.registered_token(identifier) {
+            return Ok(token);
+        }
+
+        // Try to find by address
+        if identifier.starts_with("0x") && identifier.len() == 42 {
+            if let Some(token) = self.registered_token(identifier) {
+                return Ok(token);
+            } else if self.cache_settings.enabled
+                && let Some(token) = self
+                    .token_registry
+                    .discovered(identifier, self.cache_settings.token_metadata_ttl)
+            {
+                return Ok(token);
+            } else {
+                // If not in registry, try to fetch token info from contract, remembering
+                // it unless some of the metadata had to be guessed
+                let (token, complete) = self.fetch_token_info_from_contract(identifier).await?;
+                if complete && self.cache_settings.enabled {
+                    self.token_registry.discover(token.clone());
+                }
+                return Ok(token);
+            }
+        }
+
+        Err(anyhow::anyhow!("Unknown token: {}", identifier))
+    }
+
+    /// Tokens found on-chain rather than configured
+    pub fn discovered_tokens(&self) -> Vec<DiscoveredToken> {
+        self.token_registry.discovered_tokens()
+    }
+
+    /// Forget the discovered token at `address`, or all of them, so their metadata is
+    /// read again; returns how many were forgotten
+    pub fn forget_discovered_tokens(&self, address: Option<&str>) -> usize {
+        self.token_registry.forget(address)
+    }
+
+    // Token metadata read from the contract, and whether all of it could be read
+    async fn fetch_token_info_from_contract(&self, address: &str) -> Result<(TokenInfo, bool)> {
+        let token_addr = Address::from_str(address)?;
+
+        let contract = Contract::new(token_addr, self.erc20_abi.clone(), self.provider.clone());
+
+        // Fetch token info from contract
+        let symbol = contract.method::<_, String>("symbol", ())?.call().await;
+        let decimals = contract.method::<_, u8>("decimals", ())?.call().await;
+        let name = contract.method::<_, String>("name", ())?.call().await;
+        let complete = symbol.is_ok() && decimals.is_ok() && name.is_ok();
+
+        Ok((
+            TokenInfo {
+                address: address.to_string(),
+                symbol: symbol.unwrap_or_else(|_| "UNKNOWN".to_string()),
+                decimals: decimals.unwrap_or(18),
+                name: name.unwrap_or_else(|_| "Unknown Token".to_string()),
+            },
+            complete,
+        ))
+    }
+
+    fn get_signer_provider(&self, account: &Account) -> Result<SignerProvider> {
+        let wallet = LocalWallet::from_str(&account.private_key)?;
+        let signer_provider = SignerMiddleware::new(self.provider.clone(), wallet);
+        Ok(Arc::new(signer_provider))
+    }
+
+    // Remember a submitted transaction until its receipt arrives, so one still in
+    // flight when the server stops is resolved on the next start
+    fn track_pending(&self, from: Address, nonce: U256, hash: H256, description: String) {
+        tx_tracker::record_submission(&format!("{:#x}", hash), &description);
+        let Some(store) = &self.pending else {
+            return;
+        };
+        let transaction = PendingTransaction {
+            hash: format!("{:#x}", hash),
+            chain_id: self.chain_id,
+            from: format!("{:?}", from),
+            nonce: nonce.as_u64(),
+            description,
+            submitted_at: chrono::Utc::now().timestamp(),
+        };
+        if let Err(e) = store.add(transaction) {
+            warn!("Failed to record pending transaction {:#x}: {}", hash, e);
+        }
+    }
+
+    // Wait for a sent transaction's receipt and the configured confirmations. While the
+    // chain head is followed the receipt is checked as each block arrives; otherwise
+    // this polls like `PendingTransaction::confirmations`. None means it was dropped or
+    // the configured timeout passed first, leaving it pending.
+    async fn await_receipt<P: JsonRpcClient>(
+        &self,
+        pending_tx: ethers::providers::PendingTransaction<'_, P>,
+    ) -> Result<Option<TransactionReceipt>, ProviderError> {
+        let hash = pending_tx.tx_hash();
+        let live = self.heads.is_live();
+        let wait = async {
+            if live {
+                self.receipt_on_new_heads(hash).await
+            } else {
+                pending_tx
+                    .confirmations(self.rpc_settings.confirmations)
+                    .await
+            }
+        };
+
+        if live {
+            self.heads.begin_wait(hash);
+        }
+        let receipt = match self.rpc_settings.tx_timeout {
+            Some(timeout) => tokio::time::timeout(timeout, wait)
+                .await
+                .unwrap_or_else(|_| {
+                    warn!(
+                        "{:#x} not confirmed within {}s; leaving it pending",
+                        hash,
+                        timeout.as_secs()
+                    );
+                    Ok(None)
+                }),
+            None => wait.await,
+        };
+        if live {
+            self.heads.end_wait(hash);
+        }
+        receipt
+    }
+
+    async fn receipt_on_new_heads(
+        &self,
+        hash: H256,
+    ) -> Result<Option<TransactionReceipt>, ProviderError> {
+        let mut heads = self.heads.subscribe();
+        let confirmations = self.rpc_settings.confirmations.max(1) as u64;
+        // A stalled subscription falls back to checking every few poll intervals
+        let head_timeout = self.rpc_settings.poll_interval * 5;
+        let mut missing = 0;
+        loop {
+            match self.provider.get_transaction_receipt(hash).await? {
+                Some(receipt) => {
+                    let mined = receipt
+                        .block_number
+                        .map(|number| number.as_u64())
+                        .unwrap_or_default();
+                    let head = self
+                        .heads
+                        .latest()
+                        .map_or(mined, |head| head.block_number.max(mined));
+                    if head + 1 >= mined + confirmations {
+                        return Ok(Some(receipt));
+                    }
+                }
+                None if self.provider.get_transaction(hash).await?.is_none() => {
+                    missing += 1;
+                    if missing >= DROPPED_AFTER_CHECKS {
+                        return Ok(None);
+                    }
+                }
+                None => missing = 0,
+            }
+            let _ = tokio::time::timeout(head_timeout, heads.changed()).await;
+        }
+    }
+
+    fn settle_pending(&self, hash: &str) {
+        if let Some(store) = &self.pending
+            && let Err(e) = store.remove(hash)
+        {
+            warn!("Failed to clear pending transaction {}: {}", hash, e);
+        }
+    }
+
+    // Sign and send `tx` from `account` with the account's next nonce, tracking it as
+    // pending. A failed send resyncs the nonce so the next one is refetched from the node.
+    async fn send_with_nonce(
+        &self,
+        account: &Account,
+        mut tx: TypedTransaction,
+        description: String,
+    ) -> Result<ethers::providers::PendingTransaction<'_, FailoverClient>> {
+        let signer_provider = self.get_signer_provider(account)?;
+        let signer = Address::from_str(&account.address)?;
+        let nonce = self.nonces.next(self.provider.as_ref(), signer).await?;
+        tx.set_nonce(nonce);
+        let tx_hash = match signer_provider.send_transaction(tx, None).await {
+            Ok(pending_tx) => pending_tx.tx_hash(),
+            Err(e) => {
+                self.nonces.resync(signer).await;
+                return Err(e.into());
+            }
+        };
+        self.track_pending(signer, nonce, tx_hash, description);
+        Ok(ethers::providers::PendingTransaction::new(
+            tx_hash,
+            self.provider.as_ref(),
+        ))
+    }
+
+    /// Estimate EIP-1559 fees from recent fee history, applying any overrides.
+    /// Returns None on networks without a base fee.
+    pub async fn estimate_eip1559_fees(
+        &self,
+        overrides: &FeeOverrides,
+    ) -> Result<Option<Eip1559Fees>> {
+        let history = match self
+            .provider
+            .fee_history(10u64, BlockNumber::Latest, &[50.0])
+            .await
+        {
+            Ok(history) => history,
+            Err(e) => {
+                warn!(
+                    "fee_history unavailable, falling back to legacy fees: {}",
+                    e
+                );
+                return Ok(None);
+            }
+        };
+
+        // The last entry is the base fee of the next block
+        let Some(base_fee) = history.base_fee_per_gas.last().copied() else {
+            return Ok(None);
+        };
+        if base_fee.is_zero() && overrides.is_empty() {
+            return Ok(None);
+        }
+
+        let max_priority_fee_per_gas = match overrides.max_priority_fee_per_gas {
+            Some(priority_fee) => priority_fee,
+            None => {
+                let samples: Vec<U256> = history
+                    .reward
+                    .iter()
+                    .filter_map(|rewards| rewards.first().copied())
+                    .filter(|reward| !reward.is_zero())
+                    .collect();
+                if samples.is_empty() {
+                    parse_units(DEFAULT_PRIORITY_FEE_GWEI, "gwei")?.into()
+                } else {
+                    samples
+                        .iter()
+                        .fold(U256::zero(), |sum, reward| sum + reward)
+                        / U256::from(samples.len())
+                }
+            }
+        };
+
+        // Leave room for the base fee to double before the transaction is mined
+        let max_fee_per_gas = overrides
+            .max_fee_per_gas
+            .unwrap_or(base_fee * 2 + max_priority_fee_per_gas);
+        if max_fee_per_gas < max_priority_fee_per_gas {
+            return Err(anyhow!(
+                "max_fee_per_gas must be at least max_priority_fee_per_gas"
+            ));
+        }
+
+        Ok(Some(Eip1559Fees {
+            base_fee_per_gas: base_fee,
+            max_fee_per_gas,
+            max_priority_fee_per_gas,
+        }))
+    }
+
+    // Set EIP-1559 fees on a transaction, or downgrade it to a legacy transaction
+    // on networks that don't support them
+    async fn apply_fees(&self, tx: &mut TypedTransaction, overrides: &FeeOverrides) -> Result<()> {
+        match self.estimate_eip1559_fees(overrides).await? {
+            Some(fees) => {
+                info!(
+                    "Using maxFeePerGas {} wei, maxPriorityFeePerGas {} wei",
+                    fees.max_fee_per_gas, fees.max_priority_fee_per_gas
+                );
+                if let TypedTransaction::Eip1559(inner) = tx {
+                    inner.max_fee_per_gas = Some(fees.max_fee_per_gas);
+                    inner.max_priority_fee_per_gas = Some(fees.max_priority_fee_per_gas);
+                }
+            }
+            None => {
+                if !overrides.is_empty() {
+                    return Err(anyhow!("This network does not support EIP-1559 fees"));
+                }
+                let mut legacy = EthTransactionRequest::new();
+                if let Some(from) = tx.from() {
+                    legacy = legacy.from(*from);
+                }
+                if let Some(to) = tx.to() {
+                    legacy = legacy.to(to.clone());
+                }
+                if let Some(value) = tx.value() {
+                    legacy = legacy.value(*value);
+                }
+                if let Some(data) = tx.data() {
+                    legacy = legacy.data(data.clone());
+                }
+                *tx = legacy.into();
+            }
+        }
+        Ok(())
+    }
+
+    /// Number of NFTs an address holds in a collection, with token IDs when enumerable
+    pub async fn get_nft_balance(&self, owner: &str, collection: &str) -> Result<NftBalance> {
+        let owner_addr = self.parse_address(owner).await?;
+        let contract = Contract::new(
+            Address::from_str(collection)?,
+            self.erc721_abi.clone(),
+            self.provider.clone(),
+        );
+
+        let balance: U256 = contract
+            .method::<_, U256>("balanceOf", owner_addr)?
+            .call()
+            .await?;
+        let name = contract.method::<_, String>("name", ())?.call().await.ok();
+
+        // tokenOfOwnerByIndex reverts on collections without the enumerable extension
+        let mut token_ids = Some(Vec::new());
+        for index in 0..balance.as_u64().min(MAX_ENUMERATED_NFTS) {
+            match contract
+                .method::<_, U256>("tokenOfOwnerByIndex", (owner_addr, U256::from(index)))?
+                .call()
+                .await
+            {
+                Ok(token_id) => token_ids
+                    .get_or_insert_with(Vec::new)
+                    .push(token_id.to_string()),
+                Err(_) => {
+                    token_ids = None;
+                    break;
+                }
+            }
+        }
+
+        Ok(NftBalance {
+            collection: collection.to_string(),
+            name,
+            owner: owner.to_string(),
+            balance: balance.as_u64(),
+            token_ids,
+        })
+    }
+
+    pub async fn owner_of(&self, collection: &str, token_id: &str) -> Result<String> {
+        let contract = Contract::new(
+            Address::from_str(collection)?,
+            self.erc721_abi.clone(),
+            self.provider.clone(),
+        );
+        let owner: Address = contract
+            .method::<_, Address>("ownerOf", U256::from_dec_str(token_id)?)?
+            .call()
+            .await?;
+        Ok(format!("{:#x}", owner))
+    }
+
+    pub async fn transfer_nft(
+        &self,
+        from_account: &Account,
+        collection: &str,
+        to_address: &str,
+        token_id: &str,
+        fees: &FeeOverrides,
+    ) -> Result<TransactionResult> {
+        info!(
+            "Transferring NFT {} #{} from {} to {}",
+            collection, token_id, from_account.address, to_address
+        );
+
+        let signer_provider = self.get_signer_provider(from_account)?;
+        let contract = Contract::new(
+            Address::from_str(collection)?,
+            self.erc721_abi.clone(),
+            signer_provider.clone(),
+        );
+
+        let from_addr = Address::from_str(&from_account.address)?;
+        let to_addr = self.parse_address(to_address).await?;
+        let display_name = self.lookup_name(to_addr).await;
+        let mut transfer_call = contract.method::<_, ()>(
+            "safeTransferFrom",
+            (from_addr, to_addr, U256::from_dec_str(token_id)?),
+        )?;
+        self.apply_fees(&mut transfer_call.tx, fees).await?;
+
+        let pending_tx = self
+            .send_with_nonce(
+                from_account,
+                transfer_call.tx,
+                format!(
+                    "Transfer NFT {} #{} to {}",
+                    collection, token_id, to_address
+                ),
+            )
+            .await?;
+
+        let tx_hash = format!("{:#x}", pending_tx.tx_hash());
+
+        match self.await_receipt(pending_tx).await {
+            Ok(Some(receipt)) => {
+                self.settle_pending(&tx_hash);
+                let status = if receipt.status == Some(1.into()) {
+                    "success".to_string()
+                } else {
+                    "failed".to_string()
+                };
+
+                Ok(TransactionResult {
+                    hash: tx_hash,
+                    status,
+                    block_number: receipt.block_number.map(|bn| bn.as_u64()),
+                    gas_used: receipt.gas_used.map(|gas| gas.as_u64()),
+                    display_name: display_name.clone(),
+                })
+            }
+            Ok(None) => Ok(TransactionResult {
+                hash: tx_hash,
+                status: "pending".to_string(),
+                block_number: None,
+                gas_used: None,
+                display_name: display_name.clone(),
+            }),
+            Err(e) => Err(anyhow!("Transaction failed: {}", e)),
+        }
+    }
+
+    /// Creation code for deploying `artifact` with `args`, i.e. its bytecode followed
+    /// by the encoded constructor arguments
+    pub async fn init_code(
+        &self,
+        artifact: &ContractArtifact,
+        args: &[Value],
+        resolve_name: &NameResolver<'_>,
+    ) -> Result<Bytes> {
+        let tokens = self
+            .constructor_tokens(artifact, args, resolve_name)
+            .await?;
+        match artifact.abi.constructor() {
+            Some(constructor) => Ok(constructor
+                .encode_input(artifact.bytecode.to_vec(), &tokens)?
+                .into()),
+            None => Ok(artifact.bytecode.clone()),
+        }
+    }
+
+    async fn constructor_tokens(
+        &self,
+        artifact: &ContractArtifact,
+        args: &[Value],
+        resolve_name: &NameResolver<'_>,
+    ) -> Result<Vec<Token>> {
+        let inputs = artifact
+            .abi
+            .constructor()
+            .map(|constructor| constructor.inputs.as_slice())
+            .unwrap_or_default();
+        let args: Vec<String> = args
+            .iter()
+            .map(|arg| match arg {
+                Value::String(text) => text.clone(),
+                other => other.to_string(),
+            })
+            .collect();
+        self.encode_args("The constructor", inputs, &args, resolve_name)
+            .await
+    }
+
+    /// Deploy a compiled contract from a named account. `args` are the constructor
+    /// arguments in order; address arguments go through `resolve_name` (e.g. to map
+    /// account names) and may also be ENS names. With a `salt` the contract is deployed
+    /// through the CREATE2 deployer, so its address depends only on the salt and the
+    /// init code rather than on the account's nonce; the constructor then sees the
+    /// CREATE2 deployer, not the account, as `msg.sender`.
+    pub async fn deploy_contract(
+        &self,
+        from_account: &Account,
+        artifact: &ContractArtifact,
+        args: &[Value],
+        salt: Option<H256>,
+        resolve_name: &NameResolver<'_>,
+        fees: &FeeOverrides,
+    ) -> Result<DeploymentResult> {
+        let contract_name = artifact.name.clone();
+        info!(
+            "Deploying {} from {}",
+            contract_name.as_deref().unwrap_or("contract"),
+            from_account.address
+        );
+
+        let tokens = self
+            .constructor_tokens(artifact, args, resolve_name)
+            .await?;
+        let signer_provider = self.get_signer_provider(from_account)?;
+        let factory = ContractFactory::new(
+            artifact.abi.clone(),
+            artifact.bytecode.clone(),
+            signer_provider.clone(),
+        );
+        let mut deployer = factory.deploy_tokens(tokens)?;
+        let create2_address = match salt {
+            Some(salt) => Some(self.route_through_create2(&mut deployer.tx, salt).await?),
+            None => None,
+        };
+        self.apply_fees(&mut deployer.tx, fees).await?;
+
+        let pending_tx = self
+            .send_with_nonce(
+                from_account,
+                deployer.tx,
+                format!("Deploy {}", contract_name.as_deref().unwrap_or("contract")),
+            )
+            .await?;
+
+        let tx_hash = format!("{:#x}", pending_tx.tx_hash());
+
+        match self.await_receipt(pending_tx).await {
+            Ok(Some(receipt)) => {
+                self.settle_pending(&tx_hash);
+                let status = if receipt.status == Some(1.into()) {
+                    "success".to_string()
+                } else {
+                    "failed".to_string()
+                };
+                // A CREATE2 deployment is a call, so its receipt has no contract address
+                let contract_address = receipt
+                    .contract_address
+                    .or(create2_address.filter(|_| status == "success"))
+                    .map(|address| to_checksum(&address, None));
+
+                Ok(DeploymentResult {
+                    hash: tx_hash,
+                    status,
+                    contract_address,
+                    contract_name,
+                    block_number: receipt.block_number.map(|bn| bn.as_u64()),
+                    gas_used: receipt.gas_used.map(|gas| gas.as_u64()),
+                })
+            }
+            Ok(None) => Ok(DeploymentResult {
+                hash: tx_hash,
+                status: "pending".to_string(),
+                contract_address: None,
+                contract_name,
+                block_number: None,
+                gas_used: None,
+            }),
+            Err(e) => Err(anyhow!("Transaction failed: {}", e)),
+        }
+    }
+
+    // Turn a contract creation into a call to the CREATE2 deployer with `salt`,
+    // returning where the contract will be deployed
+    async fn route_through_create2(
+        &self,
+        tx: &mut TypedTransaction,
+        salt: H256,
+    ) -> Result<Address> {
+        let deployer: Address = CREATE2_DEPLOYER.parse()?;
+        if self.provider.get_code(deployer, None).await?.is_empty() {
+            return Err(anyhow!(
+                "The CREATE2 deployer ({}) isn't deployed on chain {}; deploy without a salt",
+                CREATE2_DEPLOYER,
+                self.chain_id
+            ));
+        }
+        let init_code = tx.data().cloned().unwrap_or_default();
+        let address = create2_address(deployer, salt, H256(keccak256(&init_code)));
+        if !self.provider.get_code(address, None).await?.is_empty() {
+            return Err(anyhow!(
+                "A contract is already deployed at {} with this salt and init code",
+                to_checksum(&address, None)
+            ));
+        }
+
+        let mut data = salt.as_bytes().to_vec();
+        data.extend_from_slice(&init_code);
+        tx.set_to(deployer);
+        tx.set_data(data.into());
+        Ok(address)
+    }
+
+    // Encode string arguments with the types a function or constructor declares.
+    // Address arguments may be names or ENS names; arrays and tuples use "[a,b]" / "(a,b)".
+    async fn encode_args(
+        &self,
+        what: &str,
+        inputs: &[Param],
+        args: &[String],
+        resolve_name: &NameResolver<'_>,
+    ) -> Result<Vec<Token>> {
+        if inputs.len() != args.len() {
+            let types: Vec<String> = inputs.iter().map(|input| input.kind.to_string()).collect();
+            return Err(anyhow!(
+                "{} takes {} argument(s) ({}), got {}",
+                what,
+                inputs.len(),
+                types.join(", "),
+                args.len()
+            ));
+        }
+
+        let mut tokens = Vec::with_capacity(args.len());
+        for (input, arg) in inputs.iter().zip(args) {
+            let token = if input.kind == ParamType::Address {
+                Token::Address(self.parse_address(&resolve_name(arg)).await?)
+            } else {
+                LenientTokenizer::tokenize(&input.kind, arg).map_err(|e| {
+                    anyhow!(
+                        "Invalid {} value for argument '{}' of {}: {}",
+                        input.kind,
+                        input.name,
+                        what,
+                        e
+                    )
+                })?
+            };
+            tokens.push(token);
+        }
+        Ok(tokens)
+    }
+
+    /// Run eth_estimateGas for a send or swap and price it with current EIP-1559 fees
+    pub async fn estimate_gas(&self, request: GasEstimateRequest) -> Result<GasEstimate> {
+        let mut approval_gas = None;
+        let mut approximate = false;
+
+        let gas_limit = match request {
+            GasEstimateRequest::SendEth { from, to, amount } => {
+                let tx: TypedTransaction = Eip1559TransactionRequest::new()
+                    .from(Address::from_str(&from)?)
+                    .to(Address::from_str(&to)?)
+                    .value(self.parse_token_amount(&amount, 18)?)
+                    .into();
+                self.provider.estimate_gas(&tx, None).await?
+            }
+            GasEstimateRequest::SendErc20 {
+                from,
+                to,
+                token,
+                amount,
+            } => {
+                let token_info = self.resolve_token(&token).await?;
+                let amount_value = self.parse_token_amount(&amount, token_info.decimals)?;
+                let token_contract = Contract::new(
+                    Address::from_str(&token_info.address)?,
+                    self.erc20_abi.clone(),
+                    self.provider.clone(),
+                );
+                token_contract
+                    .method::<_, bool>("transfer", (Address::from_str(&to)?, amount_value))?
+                    .from(Address::from_str(&from)?)
+                    .estimate_gas()
+                    .await?
+            }
+            GasEstimateRequest::Swap {
+                from,
+                from_token,
+                to_token,
+                amount,
+            } => {
+                let from_addr = Address::from_str(&from)?;
+                let router_addr = Address::from_str(&self.router.uniswap_v2_router)?;
+                let weth = Address::from_str(&self.router.weth)?;
+                let router_contract = Contract::new(
+                    router_addr,
+                    self.uniswap_router_abi.clone(),
+                    self.provider.clone(),
+                );
+                let deadline = U256::from(chrono::Utc::now().timestamp() + 3600);
+
+                if from_token.eq_ignore_ascii_case("eth") {
+                    let to_token = self.resolve_token(&to_token).await?;
+                    let path = vec![weth, Address::from_str(&to_token.address)?];
+                    router_contract
+                        .method::<_, Vec<U256>>(
+                            "swapExactETHForTokens",
+                            (U256::zero(), path, from_addr, deadline),
+                        )?
+                        .from(from_addr)
+                        .value(self.parse_token_amount(&amount, 18)?)
+                        .estimate_gas()
+                        .await?
+                } else {
+                    let from_info = self.resolve_token(&from_token).await?;
+                    let from_token_addr = Address::from_str(&from_info.address)?;
+                    let amount_in = self.parse_token_amount(&amount, from_info.decimals)?;
+                    let (method, path) = if to_token.eq_ignore_ascii_case("eth") {
+                        ("swapExactTokensForETH", vec![from_token_addr, weth])
+                    } else {
+                        let to_token_addr =
+                            Address::from_str(&self.resolve_token(&to_token).await?.address)?;
+                        if from_token_addr != weth && to_token_addr != weth {
+                            (
+                                "swapExactTokensForTokens",
+                                vec![from_token_addr, weth, to_token_addr],
+                            )
+                        } else {
+                            (
+                                "swapExactTokensForTokens",
+                                vec![from_token_addr, to_token_addr],
+                            )
+                        }
+                    };
+
+                    // No approval is sent when the existing allowance covers the swap;
+                    // through the Universal Router, the token is approved to Permit2
+                    let spender = match self.router.universal_router {
+                        Some(_) => Address::from_str(PERMIT2_ADDRESS)?,
+                        None => router_addr,
+                    };
+                    let allowance = self
+                        .token_allowance(from_token_addr, from_addr, spender)
+                        .await?;
+                    if allowance < amount_in {
+                        let token_contract = Contract::new(
+                            from_token_addr,
+                            self.erc20_abi.clone(),
+                            self.provider.clone(),
+                        );
+                        approval_gas = Some(
+                            token_contract
+                                .method::<_, bool>("approve", (spender, amount_in))?
+                                .from(from_addr)
+                                .estimate_gas()
+                                .await?
+                                .as_u64(),
+                        );
+                    }
+
+                    // Simulating the swap reverts until the approval has been mined
+                    match router_contract
+                        .method::<_, Vec<U256>>(
+                            method,
+                            (amount_in, U256::zero(), path, from_addr, deadline),
+                        )?
+                        .from(from_addr)
+                        .estimate_gas()
+                        .await
+                    {
+                        Ok(gas) => gas,
+                        Err(e) => {
+                            info!("Swap simulation unavailable before approval: {}", e);
+                            approximate = true;
+                            U256::from(SWAP_GAS_FALLBACK)
+                        }
+                    }
+                }
+            }
+        };
+
+        let (base_fee, max_fee, priority_fee) =
+            match self.estimate_eip1559_fees(&FeeOverrides::default()).await? {
+                Some(fees) => (
+                    fees.base_fee_per_gas,
+                    fees.max_fee_per_gas,
+                    fees.max_priority_fee_per_gas,
+                ),
+                None => {
+                    let gas_price = self.provider.get_gas_price().await?;
+                    (gas_price, gas_price, U256::zero())
+                }
+            };
+
+        let total_gas = gas_limit + U256::from(approval_gas.unwrap_or(0));
+        Ok(GasEstimate {
+            gas_limit: gas_limit.as_u64(),
+            approval_gas,
+            base_fee_gwei: format_units(base_fee, "gwei")?,
+            max_fee_per_gas_gwei: format_units(max_fee, "gwei")?,
+            max_priority_fee_per_gas_gwei: format_units(priority_fee, "gwei")?,
+            expected_cost_eth: format_units(total_gas * (base_fee + priority_fee), "ether")?,
+            max_cost_eth: format_units(total_gas * max_fee, "ether")?,
+            approximate,
         })
     }
 
-    async fn resolve_token(&self, identifier: &str) -> Result<TokenInfo> {
-        // Try to find by symbol first (case insensitive)
-        if let Some(token) = self.token_registry.get(&identifier.to_lowercase()) {
-            return Ok(token.clone());
+    pub async fn send_transaction(
+        &self,
+        from_account: &Account,
+        to_address: &str,
+        amount: &str,
+        fees: &FeeOverrides,
+    ) -> Result<TransactionResult> {
+        info!(
+            "Sending {} ETH from {} to {}",
+            amount, from_account.address, to_address
+        );
+
+        // Create transaction request
+        let (tx, to_addr) = self.eth_transfer(to_address, amount, fees).await?;
+        let display_name = self.lookup_name(to_addr).await;
+
+        // Send transaction
+        let pending_tx = self
+            .send_with_nonce(
+                from_account,
+                tx,
+                format!("Send {} ETH to {}", amount, to_address),
+            )
+            .await?;
+
+        // Get transaction hash
+        let tx_hash = format!("{:#x}", pending_tx.tx_hash());
+
+        // Wait for transaction to be mined
+        match self.await_receipt(pending_tx).await {
+            Ok(Some(receipt)) => {
+                self.settle_pending(&tx_hash);
+                // Transaction was mined
+                let status = if receipt.status == Some(1.into()) {
+                    "success".to_string()
+                } else {
+                    "failed".to_string()
+                };
+
+                Ok(TransactionResult {
+                    hash: tx_hash,
+                    status,
+                    block_number: receipt.block_number.map(|bn| bn.as_u64()),
+                    gas_used: receipt.gas_used.map(|gas| gas.as_u64()),
+                    display_name: display_name.clone(),
+                })
+            }
+            Ok(None) => {
+                // Transaction was not mined yet
+                Ok(TransactionResult {
+                    hash: tx_hash,
+                    status: "pending".to_string(),
+                    block_number: None,
+                    gas_used: None,
+                    display_name: display_name.clone(),
+                })
+            }
+            Err(e) => Err(anyhow!("Transaction failed: {}", e)),
+        }
+    }
+
+    // An unsigned ETH transfer with fees applied, and its recipient
+    async fn eth_transfer(
+        &self,
+        to_address: &str,
+        amount: &str,
+        fees: &FeeOverrides,
+    ) -> Result<(TypedTransaction, Address)> {
+        // Parse amount as ether
+        let amount_wei = self.parse_token_amount(amount, 18)?;
+        let to_addr = self.parse_address(to_address).await?;
+        let mut tx: TypedTransaction = Eip1559TransactionRequest::new()
+            .to(to_addr)
+            .value(amount_wei)
+            .into();
+        self.apply_fees(&mut tx, fees).await?;
+        Ok((tx, to_addr))
+    }
+
+    /// Sign an ETH transfer and return it as raw hex instead of broadcasting it
+    pub async fn export_eth_transfer(
+        &self,
+        from_account: &Account,
+        to_address: &str,
+        amount: &str,
+        fees: &FeeOverrides,
+    ) -> Result<SignedTransaction> {
+        let (tx, _) = self.eth_transfer(to_address, amount, fees).await?;
+        self.sign_transaction(from_account, tx).await
+    }
+
+    /// Simulate an ETH transfer with the exact payload `send_transaction` would send
+    pub async fn simulate_eth_transfer(
+        &self,
+        from_account: &Account,
+        to_address: &str,
+        amount: &str,
+        fees: &FeeOverrides,
+    ) -> Result<Simulation> {
+        let (tx, _) = self.eth_transfer(to_address, amount, fees).await?;
+        simulation::simulate(
+            &self.provider,
+            Address::from_str(&from_account.address)?,
+            &tx,
+        )
+        .await
+    }
+
+    // Fill in the sender, chain, nonce and gas so the transaction can be signed. The
+    // nonce is the account's next one on chain and isn't reserved, so anything the
+    // account sends first invalidates the signed transaction.
+    async fn fill_for_signing(&self, from: Address, tx: &mut TypedTransaction) -> Result<()> {
+        let nonce = self
+            .provider
+            .get_transaction_count(from, Some(BlockNumber::Pending.into()))
+            .await?;
+        tx.set_from(from);
+        tx.set_chain_id(self.chain_id);
+        tx.set_nonce(nonce);
+        self.provider.fill_transaction(tx, None).await?;
+        Ok(())
+    }
+
+    // Sign without sending
+    async fn sign_transaction(
+        &self,
+        from_account: &Account,
+        mut tx: TypedTransaction,
+    ) -> Result<SignedTransaction> {
+        let wallet = LocalWallet::from_str(&from_account.private_key)?.with_chain_id(self.chain_id);
+        self.fill_for_signing(wallet.address(), &mut tx).await?;
+        let signature = wallet.sign_transaction(&tx).await?;
+        self.signed_transaction(&tx, &signature)
+    }
+
+    fn signed_transaction(
+        &self,
+        tx: &TypedTransaction,
+        signature: &Signature,
+    ) -> Result<SignedTransaction> {
+        let from = tx
+            .from()
+            .copied()
+            .ok_or_else(|| anyhow!("The transaction has no sender"))?;
+        let raw = tx.rlp_signed(signature);
+        Ok(SignedTransaction {
+            hash: format!("{:#x}", H256::from(keccak256(&raw))),
+            raw: raw.to_string(),
+            from: to_checksum(&from, None),
+            to: tx.to_addr().map(|to| to_checksum(to, None)),
+            nonce: tx.nonce().map(|nonce| nonce.as_u64()).unwrap_or_default(),
+            chain_id: self.chain_id,
+            gas_limit: tx.gas().map(|gas| gas.as_u64()).unwrap_or_default(),
+        })
+    }
+
+    /// Build a transaction for `from` without signing it, for keys that never touch this
+    /// machine. The signature for `signing_hash` goes back through `attach_signature`.
+    pub async fn prepare_unsigned(
+        &self,
+        from: &str,
+        request: UnsignedRequest,
+        resolve_name: &NameResolver<'_>,
+        fees: &FeeOverrides,
+    ) -> Result<UnsignedTransaction> {
+        let from = self.parse_address(&resolve_name(from)).await?;
+        let (mut tx, _) = match &request {
+            UnsignedRequest::SendEth { to, amount } => {
+                self.eth_transfer(&resolve_name(to), amount, fees).await?
+            }
+            UnsignedRequest::SendErc20 { to, token, amount } => {
+                self.erc20_transfer(&resolve_name(to), token, amount, fees)
+                    .await?
+            }
+            UnsignedRequest::ContractCall {
+                call,
+                value,
+                gas_limit,
+            } => {
+                self.contract_write(call, value, *gas_limit, resolve_name, fees)
+                    .await?
+            }
+        };
+        self.fill_for_signing(from, &mut tx).await?;
+
+        Ok(UnsignedTransaction {
+            signing_hash: format!("{:#x}", tx.sighash()),
+            unsigned_raw: tx.rlp().to_string(),
+            from: to_checksum(&from, None),
+            nonce: tx.nonce().map(|nonce| nonce.as_u64()).unwrap_or_default(),
+            chain_id: self.chain_id,
+            transaction: tx,
+        })
+    }
+
+    /// Attach an externally produced signature (65 bytes of hex, r || s || v) to a
+    /// transaction from `prepare_unsigned`, checking it was made by the sender
+    pub fn attach_signature(
+        &self,
+        tx: &TypedTransaction,
+        signature: &str,
+    ) -> Result<SignedTransaction> {
+        let mut signature = Signature::from_str(signature.trim().trim_start_matches("0x"))
+            .map_err(|e| anyhow!("Invalid signature: {}", e))?;
+        if tx.chain_id().map(|id| id.as_u64()) != Some(self.chain_id) {
+            return Err(anyhow!(
+                "The transaction isn't for this network (chain {})",
+                self.chain_id
+            ));
+        }
+        let from = tx
+            .from()
+            .ok_or_else(|| anyhow!("The transaction has no sender"))?;
+        let signer = signature
+            .recover(tx.sighash())
+            .map_err(|e| anyhow!("Invalid signature: {}", e))?;
+        if signer != *from {
+            return Err(anyhow!(
+                "The signature is from {:?}, but the transaction is from {:?}",
+                signer,
+                from
+            ));
+        }
+
+        // Signers report v as 0/1, 27/28 or EIP-155; legacy encodings need EIP-155
+        signature.v = to_eip155_v(signature.recovery_id()?.to_byte(), self.chain_id);
+        self.signed_transaction(tx, &signature)
+    }
+
+    /// Decode a signed raw transaction for this network, recovering its signer
+    pub fn decode_raw_transaction(&self, raw: &str) -> Result<(TypedTransaction, Address)> {
+        let raw =
+            Bytes::from_str(raw.trim()).map_err(|e| anyhow!("Invalid raw transaction: {}", e))?;
+        let (tx, signature) = TypedTransaction::decode_signed(&ethers::utils::rlp::Rlp::new(&raw))
+            .map_err(|e| anyhow!("Invalid raw transaction: {}", e))?;
+        if let Some(chain_id) = tx.chain_id()
+            && chain_id.as_u64() != self.chain_id
+        {
+            return Err(anyhow!(
+                "The transaction was signed for chain {}, but this network is chain {}",
+                chain_id,
+                self.chain_id
+            ));
+        }
+        let signer = signature.recover(tx.sighash())?;
+        Ok((tx, signer))
+    }
+
+    /// Submit a transaction signed elsewhere, e.g. one exported by `export_eth_transfer`,
+    /// and wait for it like any other send
+    pub async fn broadcast_raw_transaction(&self, raw: &str) -> Result<TransactionResult> {
+        let (tx, signer) = self.decode_raw_transaction(raw)?;
+        let raw = Bytes::from_str(raw.trim())?;
+        let display_name = match tx.to_addr() {
+            Some(to) => self.lookup_name(*to).await,
+            None => None,
+        };
+
+        info!("Broadcasting raw transaction from {:?}", signer);
+        let pending_tx = self.provider.send_raw_transaction(raw).await?;
+        // The account's nonce moved outside the nonce manager
+        self.nonces.resync(signer).await;
+        let tx_hash = format!("{:#x}", pending_tx.tx_hash());
+        self.track_pending(
+            signer,
+            tx.nonce().copied().unwrap_or_default(),
+            pending_tx.tx_hash(),
+            format!("Raw transaction from {:?}", signer),
+        );
+
+        match self.await_receipt(pending_tx).await {
+            Ok(Some(receipt)) => {
+                self.settle_pending(&tx_hash);
+                let status = if receipt.status == Some(1.into()) {
+                    "success".to_string()
+                } else {
+                    "failed".to_string()
+                };
+
+                Ok(TransactionResult {
+                    hash: tx_hash,
+                    status,
+                    block_number: receipt.block_number.map(|bn| bn.as_u64()),
+                    gas_used: receipt.gas_used.map(|gas| gas.as_u64()),
+                    display_name,
+                })
+            }
+            Ok(None) => Ok(TransactionResult {
+                hash: tx_hash,
+                status: "pending".to_string(),
+                block_number: None,
+                gas_used: None,
+                display_name,
+            }),
+            Err(e) => Err(anyhow!("Transaction failed: {}", e)),
+        }
+    }
+
+    fn parse_token_amount(&self, amount: &str, decimals: u8) -> Result<U256> {
+        utils::parse_amount(amount, decimals)
+    }
+
+    /// Whether the address holds code, and the implementation behind it if it's an
+    /// EIP-1967 or EIP-1822 proxy
+    pub async fn check_contract_deployed(&self, address: &str) -> Result<ContractStatus> {
+        let addr = self.parse_address(address).await?;
+        let code = self.provider.get_code(addr, None).await?;
+        if code.is_empty() {
+            return Ok(ContractStatus {
+                deployed: false,
+                proxy: None,
+            });
+        }
+        Ok(ContractStatus {
+            deployed: true,
+            proxy: proxy::detect(&self.provider, addr).await?,
+        })
+    }
+
+    /// Logs matching `filter`, which should bound its block range
+    pub async fn get_logs(&self, filter: &Filter) -> Result<Vec<Log>> {
+        Ok(self.provider.get_logs(filter).await?)
+    }
+
+    /// Events in the loaded ABIs named by `signature`, either just the name ("Swap") or
+    /// the full signature ("Transfer(address,address,uint256)"). ERC20 and ERC721 share
+    /// a Transfer signature, so more than one event can match.
+    pub fn find_events(&self, signature: &str) -> Vec<Event> {
+        let signature: String = signature.chars().filter(|c| !c.is_whitespace()).collect();
+        let mut events: Vec<Event> = Vec::new();
+        for abi in self.loaded_abis() {
+            for event in abi.events() {
+                let matches = if signature.contains('(') {
+                    event.abi_signature() == signature
+                } else {
+                    event.name == signature
+                };
+                if matches && !events.contains(event) {
+                    events.push(event.clone());
+                }
+            }
+        }
+        events
+    }
+
+    /// Events in the loaded ABIs whose signature hashes to `topic0`
+    pub fn find_events_by_topic(&self, topic0: H256) -> Vec<Event> {
+        let mut events: Vec<Event> = Vec::new();
+        for event in self.loaded_abis().into_iter().flat_map(|abi| abi.events()) {
+            if event.signature() == topic0 && !events.contains(event) {
+                events.push(event.clone());
+            }
+        }
+        events
+    }
+
+    // Function with this exact signature (e.g. "balanceOf(address)") in the loaded ABIs
+    fn find_function(&self, signature: &str) -> Option<Function> {
+        self.loaded_abis()
+            .into_iter()
+            .flat_map(|abi| abi.functions())
+            .find(|function| function.abi_signature() == signature)
+            .cloned()
+    }
+
+    /// Functions in the loaded ABIs with this 4-byte selector
+    pub fn find_functions_by_selector(&self, selector: [u8; 4]) -> Vec<Function> {
+        let mut functions: Vec<Function> = Vec::new();
+        for function in self
+            .loaded_abis()
+            .into_iter()
+            .flat_map(|abi| abi.functions())
+        {
+            if function.short_signature() == selector && !functions.contains(function) {
+                functions.push(function.clone());
+            }
+        }
+        functions
+    }
+
+    fn loaded_abis(&self) -> [&Abi; 5] {
+        [
+            &self.erc20_abi,
+            &self.erc721_abi,
+            &self.uniswap_router_abi,
+            &self.uniswap_v3_router_abi,
+            &self.uniswap_v3_quoter_abi,
+        ]
+    }
+
+    /// Run a view function with eth_call and decode what it returns. The signature may
+    /// be "balanceOf(address)", "balanceOf(address)(uint256)" or
+    /// "function balanceOf(address owner) view returns (uint256)"; without return types
+    /// the loaded ABIs supply them for functions they know.
+    pub async fn call_contract(
+        &self,
+        call: &ContractCall,
+        resolve_name: &NameResolver<'_>,
+    ) -> Result<ContractCallResult> {
+        let (function, contract, data) = self.encode_call(call, resolve_name).await?;
+
+        let mut tx = EthTransactionRequest::new().to(contract).data(data);
+        if let Some(from) = &call.from {
+            tx = tx.from(self.parse_address(&resolve_name(from)).await?);
+        }
+        let raw = self.provider.call(&tx.into(), None).await?;
+
+        let outputs = if function.outputs.is_empty() {
+            Vec::new()
+        } else {
+            let values = function
+                .decode_output(&raw)
+                .map_err(|e| anyhow!("Couldn't decode the return value: {}", e))?;
+            function
+                .outputs
+                .iter()
+                .zip(values)
+                .map(|(output, value)| ContractCallOutput {
+                    name: output.name.clone(),
+                    kind: output.kind.to_string(),
+                    value: token_to_json(value),
+                })
+                .collect()
+        };
+
+        Ok(ContractCallResult {
+            contract: to_checksum(&contract, None),
+            function: function.abi_signature(),
+            outputs,
+            raw: raw.to_string(),
+        })
+    }
+
+    fn format_balance(&self, balance: U256, decimals: u8) -> String {
+        let divisor = U256::from(10).pow(U256::from(decimals));
+        let integer_part = balance / divisor;
+        let fractional_part = balance % divisor;
+
+        if fractional_part.is_zero() {
+            integer_part.to_string()
+        } else {
+            let fractional_str = format!("{:0width$}", fractional_part, width = decimals as usize);
+            let fractional_trimmed = fractional_str.trim_end_matches('0');
+            if fractional_trimmed.is_empty() {
+                integer_part.to_string()
+            } else {
+                format!("{}.{}", integer_part, fractional_trimmed)
+            }
+        }
+    }
+
+    // Parse the call's function signature and ABI-encode its parameters
+    pub(crate) async fn encode_call(
+        &self,
+        call: &ContractCall,
+        resolve_name: &NameResolver<'_>,
+    ) -> Result<(Function, Address, Bytes)> {
+        let contract = self
+            .parse_address(&resolve_name(&call.contract_address))
+            .await?;
+        let verified = self.cached_verified_contract(contract).flatten();
+        let signature = call.function_signature.trim();
+
+        // A verified contract's functions can be called by name alone
+        let mut function = match &verified {
+            Some(verified) if !signature.contains('(') => {
+                let functions = verified.abi.functions_by_name(signature).map_err(|_| {
+                    anyhow!("{} has no function named {}", verified.name, signature)
+                })?;
+                match functions.as_slice() {
+                    [function] => function.clone(),
+                    _ => {
+                        return Err(anyhow!(
+                            "{} is overloaded on {}; pass one of: {}",
+                            signature,
+                            verified.name,
+                            functions
+                                .iter()
+                                .map(|function| function.abi_signature())
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        ));
+                    }
+                }
+            }
+            _ => HumanReadableParser::parse_function(signature).map_err(|e| {
+                anyhow!(
+                    "Invalid function signature '{}': {}",
+                    call.function_signature,
+                    e
+                )
+            })?,
+        };
+        if function.outputs.is_empty() {
+            let signature = function.abi_signature();
+            let known = verified
+                .iter()
+                .flat_map(|verified| verified.abi.functions())
+                .find(|function| function.abi_signature() == signature)
+                .cloned()
+                .or_else(|| self.find_function(&signature));
+            if let Some(known) = known {
+                function.outputs = known.outputs;
+            }
+        }
+
+        let tokens = self
+            .encode_args(
+                &function.abi_signature(),
+                &function.inputs,
+                &call.parameters,
+                resolve_name,
+            )
+            .await?;
+        let data = function.encode_input(&tokens)?;
+        Ok((function, contract, data.into()))
+    }
+
+    // An unsigned contract call with the ETH value, gas limit and fees applied
+    async fn contract_write(
+        &self,
+        call: &ContractCall,
+        value: &str,
+        gas_limit: Option<u64>,
+        resolve_name: &NameResolver<'_>,
+        fees: &FeeOverrides,
+    ) -> Result<(TypedTransaction, Address)> {
+        let (_, contract, data) = self.encode_call(call, resolve_name).await?;
+        let value = self.parse_token_amount(value, 18)?;
+        let mut tx: TypedTransaction = Eip1559TransactionRequest::new()
+            .to(contract)
+            .data(data)
+            .value(value)
+            .into();
+        self.apply_fees(&mut tx, fees).await?;
+        if let Some(gas_limit) = gas_limit {
+            tx.set_gas(gas_limit);
         }
+        Ok((tx, contract))
+    }
+
+    /// Sign and send a call to any contract function, sending `value` ETH with it.
+    /// The gas limit is estimated unless given.
+    pub async fn write_contract(
+        &self,
+        from_account: &Account,
+        call: &ContractCall,
+        value: &str,
+        gas_limit: Option<u64>,
+        resolve_name: &NameResolver<'_>,
+        fees: &FeeOverrides,
+    ) -> Result<TransactionResult> {
+        info!(
+            "Calling {} on {} from {}",
+            call.function_signature, call.contract_address, from_account.address
+        );
+
+        let (tx, contract) = self
+            .contract_write(call, value, gas_limit, resolve_name, fees)
+            .await?;
+        self.send_contract_tx(
+            from_account,
+            tx,
+            contract,
+            format!(
+                "Call {} on {}",
+                call.function_signature, call.contract_address
+            ),
+        )
+        .await
+    }
+
+    // Sign and send a transaction to `contract`, waiting for its receipt
+    async fn send_contract_tx(
+        &self,
+        from_account: &Account,
+        tx: TypedTransaction,
+        contract: Address,
+        description: String,
+    ) -> Result<TransactionResult> {
+        let display_name = self.lookup_name(contract).await;
+
+        let pending_tx = self.send_with_nonce(from_account, tx, description).await?;
+        let tx_hash = format!("{:#x}", pending_tx.tx_hash());
+
+        match self.await_receipt(pending_tx).await {
+            Ok(Some(receipt)) => {
+                self.settle_pending(&tx_hash);
+                let status = if receipt.status == Some(1.into()) {
+                    "success".to_string()
+                } else {
+                    "failed".to_string()
+                };
 
-        // Try to find by address
-        if identifier.starts_with("0x") && identifier.len() == 42 {
-            if let Some(token) = self.token_registry.get(&identifier.to_lowercase()) {
-                return Ok(token.clone());
-            } else {
-                // If not in registry, try to fetch token info from contract
-                return self.fetch_token_info_from_contract(identifier).await;
+                Ok(TransactionResult {
+                    hash: tx_hash,
+                    status,
+                    block_number: receipt.block_number.map(|bn| bn.as_u64()),
+                    gas_used: receipt.gas_used.map(|gas| gas.as_u64()),
+                    display_name,
+                })
             }
+            Ok(None) => Ok(TransactionResult {
+                hash: tx_hash,
+                status: "pending".to_string(),
+                block_number: None,
+                gas_used: None,
+                display_name,
+            }),
+            Err(e) => Err(anyhow!("Transaction failed: {}", e)),
         }
+    }
 
-        Err(anyhow::anyhow!("Unknown token: {}", identifier))
+    /// Sign a contract call and return it as raw hex instead of broadcasting it
+    pub async fn export_contract_write(
+        &self,
+        from_account: &Account,
+        call: &ContractCall,
+        value: &str,
+        gas_limit: Option<u64>,
+        resolve_name: &NameResolver<'_>,
+        fees: &FeeOverrides,
+    ) -> Result<SignedTransaction> {
+        let (tx, _) = self
+            .contract_write(call, value, gas_limit, resolve_name, fees)
+            .await?;
+        self.sign_transaction(from_account, tx).await
     }
 
-    async fn fetch_token_info_from_contract(&self, address: &str) -> Result<TokenInfo> {
-        let token_addr = Address::from_str(address)?;
+    pub fn get_supported_tokens(&self) -> Vec<TokenInfo> {
+        self.token_registry.tokens()
+    }
 
-        let contract = Contract::new(token_addr, self.erc20_abi.clone(), self.provider.clone());
+    /// ETH and token balances of an address in one Multicall3 call, or one call per
+    /// token on chains without Multicall3. Tokens whose balanceOf fails come back as None.
+    pub async fn balances_of(&self, owner: Address, tokens: &[Address]) -> Result<RawBalances> {
+        let multicall_deployed = !self
+            .provider
+            .get_code(MULTICALL_ADDRESS, None)
+            .await?
+            .is_empty();
 
-        // Fetch token info from contract
-        let symbol: String = contract
-            .method::<_, String>("symbol", ())?
-            .call()
-            .await
-            .unwrap_or_else(|_| "UNKNOWN".to_string());
+        if multicall_deployed {
+            let mut multicall = Multicall::new(self.provider.clone(), Some(MULTICALL_ADDRESS))
+                .await
+                .map_err(|e| anyhow!("Multicall setup failed: {}", e))?;
+            multicall.add_get_eth_balance(owner, false);
+            for token in tokens {
+                let contract = Contract::new(*token, self.erc20_abi.clone(), self.provider.clone());
+                multicall.add_call(contract.method::<_, U256>("balanceOf", owner)?, true);
+            }
 
-        let decimals: u8 = contract
-            .method::<_, u8>("decimals", ())?
-            .call()
-            .await
-            .unwrap_or(18);
+            match multicall.call_raw().await {
+                Ok(results) => {
+                    let mut results = results.into_iter().map(|result| match result {
+                        Ok(Token::Uint(balance)) => Some(balance),
+                        _ => None,
+                    });
+                    let eth = results
+                        .next()
+                        .flatten()
+                        .ok_or_else(|| anyhow!("Multicall returned no ETH balance"))?;
+                    return Ok(RawBalances {
+                        eth,
+                        tokens: results.collect(),
+                        multicall: true,
+                    });
+                }
+                Err(e) => warn!(
+                    "Multicall balance query failed, querying tokens one by one: {}",
+                    e
+                ),
+            }
+        }
 
-        let name: String = contract
-            .method::<_, String>("name", ())?
-            .call()
-            .await
-            .unwrap_or_else(|_| "Unknown Token".to_string());
+        let eth = self.provider.get_balance(owner, None).await?;
+        let token_balances = join_all(tokens.iter().map(|token| async move {
+            let contract = Contract::new(*token, self.erc20_abi.clone(), self.provider.clone());
+            contract
+                .method::<_, U256>("balanceOf", owner)
+                .ok()?
+                .call()
+                .await
+                .ok()
+        }))
+        .await;
 
-        Ok(TokenInfo {
-            address: address.to_string(),
-            symbol,
-            decimals,
-            name,
+        Ok(RawBalances {
+            eth,
+            tokens: token_balances,
+            multicall: false,
         })
     }
 
-    fn get_signer_provider(&self, account: &Account) -> Result<SignerProvider> {
-        let wallet = LocalWallet::from_str(&account.private_key)?;
-        let signer_provider = SignerMiddleware::new(self.provider.clone(), wallet);
-        Ok(Arc::new(signer_provider))
-    }
-
-    pub async fn send_transaction(
+    // Send ERC20 token transaction
+    pub async fn send_erc20(
         &self,
         from_account: &Account,
         to_address: &str,
+        token_identifier: &str,
         amount: &str,
+        fees: &FeeOverrides,
     ) -> Result<TransactionResult> {
         info!(
-            "Sending {} ETH from {} to {}",
-            amount, from_account.address, to_address
+            "Sending {} {} from {} to {}",
+            amount, token_identifier, from_account.address, to_address
         );
 
-        // Parse amount as ether
-        let amount_wei = ethers::utils::parse_ether(amount)?;
-
-        // Create signer provider
-        let signer_provider = self.get_signer_provider(from_account)?;
-
-        // Create transaction request
-        let to_addr = Address::from_str(to_address)?;
-        let tx = EthTransactionRequest::new().to(to_addr).value(amount_wei);
+        // Create transfer call
+        let (tx, to_addr) = self
+            .erc20_transfer(to_address, token_identifier, amount, fees)
+            .await?;
+        let display_name = self.lookup_name(to_addr).await;
 
         // Send transaction
-        let pending_tx = signer_provider.send_transaction(tx, None).await?;
+        let pending_tx = self
+            .send_with_nonce(
+                from_account,
+                tx,
+                format!("Send {} {} to {}", amount, token_identifier, to_address),
+            )
+            .await?;
 
         // Get transaction hash
         let tx_hash = format!("{:#x}", pending_tx.tx_hash());
 
         // Wait for transaction to be mined
-        match pending_tx.await {
+        match self.await_receipt(pending_tx).await {
             Ok(Some(receipt)) => {
+                self.settle_pending(&tx_hash);
                 // Transaction was mined
                 let status = if receipt.status == Some(1.into()) {
                     "success".to_string()
@@ -473,6 +3461,7 @@ impl BlockchainService {
                     status,
                     block_number: receipt.block_number.map(|bn| bn.as_u64()),
                     gas_used: receipt.gas_used.map(|gas| gas.as_u64()),
+                    display_name: display_name.clone(),
                 })
             }
             Ok(None) => {
@@ -482,177 +3471,1272 @@ impl BlockchainService {
                     status: "pending".to_string(),
                     block_number: None,
                     gas_used: None,
+                    display_name: display_name.clone(),
                 })
             }
-            Err(e) => Err(anyhow!("Transaction failed: {}", e)),
-        }
-    }
+            Err(e) => Err(anyhow!("Transaction failed: {}", e)),
+        }
+    }
+
+    // An unsigned ERC20 transfer with fees applied, and its recipient
+    async fn erc20_transfer(
+        &self,
+        to_address: &str,
+        token_identifier: &str,
+        amount: &str,
+        fees: &FeeOverrides,
+    ) -> Result<(TypedTransaction, Address)> {
+        // Resolve token info
+        let token_info = self.resolve_token(token_identifier).await?;
+
+        // Parse amount based on token decimals
+        let amount_value = self.parse_token_amount(amount, token_info.decimals)?;
+
+        let token_addr = Address::from_str(&token_info.address)?;
+        let token_contract =
+            Contract::new(token_addr, self.erc20_abi.clone(), self.provider.clone());
+        let to_addr = self.parse_address(to_address).await?;
+        let mut transfer_call =
+            token_contract.method::<_, bool>("transfer", (to_addr, amount_value))?;
+        self.apply_fees(&mut transfer_call.tx, fees).await?;
+        Ok((transfer_call.tx, to_addr))
+    }
+
+    /// Sign an ERC20 transfer and return it as raw hex instead of broadcasting it
+    pub async fn export_erc20_transfer(
+        &self,
+        from_account: &Account,
+        to_address: &str,
+        token_identifier: &str,
+        amount: &str,
+        fees: &FeeOverrides,
+    ) -> Result<SignedTransaction> {
+        let (tx, _) = self
+            .erc20_transfer(to_address, token_identifier, amount, fees)
+            .await?;
+        self.sign_transaction(from_account, tx).await
+    }
+
+    /// Simulate an ERC20 transfer with the exact payload `send_erc20` would send
+    pub async fn simulate_erc20_transfer(
+        &self,
+        from_account: &Account,
+        to_address: &str,
+        token_identifier: &str,
+        amount: &str,
+        fees: &FeeOverrides,
+    ) -> Result<Simulation> {
+        let (tx, _) = self
+            .erc20_transfer(to_address, token_identifier, amount, fees)
+            .await?;
+        simulation::simulate(
+            &self.provider,
+            Address::from_str(&from_account.address)?,
+            &tx,
+        )
+        .await
+    }
+
+    // Refuse to send a transaction that would fail, explaining why
+    async fn preflight(&self, from_account: &Account, tx: &TypedTransaction) -> Result<()> {
+        let from = Address::from_str(&from_account.address)?;
+        let simulation = simulation::simulate(&self.provider, from, tx).await?;
+        if !simulation.success {
+            return Err(anyhow!(simulation.failure_message()));
+        }
+        Ok(())
+    }
+
+    pub(crate) async fn token_allowance(
+        &self,
+        token: Address,
+        owner: Address,
+        spender: Address,
+    ) -> Result<U256> {
+        let contract = Contract::new(token, self.erc20_abi.clone(), self.provider.clone());
+        Ok(contract
+            .method::<_, U256>("allowance", (owner, spender))?
+            .call()
+            .await?)
+    }
+
+    /// How much `spender` may transfer of `owner`'s tokens. The spender can be an address,
+    /// an ENS name, or `uniswap_v2` / `uniswap_v3` for the configured routers.
+    pub async fn get_allowance(
+        &self,
+        owner: &str,
+        token: &str,
+        spender: &str,
+    ) -> Result<AllowanceResult> {
+        let token_info = self.resolve_token(token).await?;
+        let owner_addr = self.parse_address(owner).await?;
+        let (spender_addr, spender_name) = self.resolve_spender(spender).await?;
+
+        let allowance = self
+            .token_allowance(
+                Address::from_str(&token_info.address)?,
+                owner_addr,
+                spender_addr,
+            )
+            .await?;
+
+        Ok(AllowanceResult {
+            owner: to_checksum(&owner_addr, None),
+            token: token_info.symbol,
+            spender: to_checksum(&spender_addr, None),
+            spender_name,
+            allowance: format_units(allowance, token_info.decimals as u32)?,
+            // Routers and wallets treat anything near 2^256 as "unlimited"
+            unlimited: allowance >= U256::MAX / 2,
+        })
+    }
+
+    /// A spender's address and display name: "uniswap_v2" and "uniswap_v3" are the
+    /// routers, anything else an address or ENS name
+    pub async fn resolve_spender(&self, spender: &str) -> Result<(Address, Option<String>)> {
+        match Dex::parse(spender) {
+            Ok(Dex::UniswapV2) => Ok((
+                Address::from_str(&self.router.uniswap_v2_router)?,
+                Some("Uniswap V2 Router".to_string()),
+            )),
+            Ok(Dex::UniswapV3) => {
+                let v3 = self.router.uniswap_v3.as_ref().ok_or_else(|| {
+                    anyhow!("Uniswap V3 isn't configured for chain {}", self.chain_id)
+                })?;
+                Ok((
+                    Address::from_str(&v3.router)?,
+                    Some("Uniswap V3 Router".to_string()),
+                ))
+            }
+            Err(_) => {
+                let address = self.parse_address(spender).await?;
+                Ok((address, self.lookup_name(address).await))
+            }
+        }
+    }
+
+    /// Set a named account's allowance of `token` for `spender` to `amount` base units;
+    /// zero revokes it
+    pub async fn set_allowance(
+        &self,
+        from_account: &Account,
+        token: &TokenInfo,
+        spender: Address,
+        amount: U256,
+        fees: &FeeOverrides,
+    ) -> Result<TransactionResult> {
+        let call = ContractCall {
+            contract_address: token.address.clone(),
+            function_signature: "approve(address,uint256)".to_string(),
+            parameters: vec![format!("{:?}", spender), amount.to_string()],
+            from: None,
+        };
+        self.write_contract(
+            from_account,
+            &call,
+            "0",
+            None,
+            &|name| name.to_string(),
+            fees,
+        )
+        .await
+    }
+
+    /// Approve `spender` for `amount` of `token` as part of a swap of `swap_amount`. The
+    /// approve calldata goes through the policy's approval caps before it's signed.
+    pub async fn set_swap_allowance(
+        &self,
+        from_account: &Account,
+        token: &TokenInfo,
+        spender: Address,
+        amount: U256,
+        swap_amount: U256,
+        approvals: &SwapApprovals<'_>,
+    ) -> Result<(TransactionResult, Option<ApprovalAdjustment>)> {
+        let token_addr = Address::from_str(&token.address)?;
+        let data = self
+            .erc20_abi
+            .function("approve")?
+            .encode_input(&[Token::Address(spender), Token::Uint(amount)])?;
+        let (data, adjustment) = approvals.policy.cap_approval_calldata(
+            &data,
+            swap_amount,
+            token.decimals,
+            approvals.price_usd,
+        )?;
+
+        let mut tx: TypedTransaction = Eip1559TransactionRequest::new()
+            .to(token_addr)
+            .data(data)
+            .into();
+        self.apply_fees(&mut tx, &FeeOverrides::default()).await?;
+        let result = self
+            .send_contract_tx(
+                from_account,
+                tx,
+                token_addr,
+                format!("Approve {:?} to spend {}", spender, token.symbol),
+            )
+            .await?;
+        Ok((result, adjustment))
+    }
+
+    /// Whether the Disperse contract is deployed on this network
+    pub async fn has_disperse(&self) -> bool {
+        match Address::from_str(DISPERSE_ADDRESS) {
+            Ok(address) => self
+                .provider
+                .get_code(address, None)
+                .await
+                .is_ok_and(|code| !code.is_empty()),
+            Err(_) => false,
+        }
+    }
+
+    /// Send ETH, or `token`, to several recipients in one transaction through the
+    /// Disperse contract. Amounts are in base units; for a token the contract is
+    /// approved for the total first unless its allowance already covers it.
+    pub async fn disperse(
+        &self,
+        from_account: &Account,
+        token: Option<&TokenInfo>,
+        transfers: &[(Address, U256)],
+        fees: &FeeOverrides,
+    ) -> Result<TransactionResult> {
+        if !self.has_disperse().await {
+            return Err(anyhow!(
+                "The Disperse contract isn't deployed on chain {}",
+                self.chain_id
+            ));
+        }
+        let disperse = Address::from_str(DISPERSE_ADDRESS)?;
+        let total = transfers
+            .iter()
+            .try_fold(U256::zero(), |total, (_, amount)| {
+                total.checked_add(*amount)
+            })
+            .ok_or_else(|| anyhow!("The batch total overflows"))?;
+        let recipients = transfers
+            .iter()
+            .map(|(to, _)| format!("{:?}", to))
+            .collect::<Vec<_>>()
+            .join(",");
+        let amounts = transfers
+            .iter()
+            .map(|(_, amount)| amount.to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+
+        let (call, value) = match token {
+            None => (
+                ContractCall {
+                    contract_address: DISPERSE_ADDRESS.to_string(),
+                    function_signature: "disperseEther(address[],uint256[])".to_string(),
+                    parameters: vec![format!("[{}]", recipients), format!("[{}]", amounts)],
+                    from: None,
+                },
+                format_units(total, 18)?,
+            ),
+            Some(token) => {
+                let token_addr = Address::from_str(&token.address)?;
+                let owner = Address::from_str(&from_account.address)?;
+                if self.token_allowance(token_addr, owner, disperse).await? < total {
+                    let approval = self
+                        .set_allowance(from_account, token, disperse, total, fees)
+                        .await?;
+                    if approval.status != "success" {
+                        return Err(anyhow!(
+                            "Approving Disperse for {} {} did not succeed ({})",
+                            format_units(total, token.decimals as u32)?,
+                            token.symbol,
+                            approval.hash
+                        ));
+                    }
+                }
+                (
+                    ContractCall {
+                        contract_address: DISPERSE_ADDRESS.to_string(),
+                        function_signature: "disperseToken(address,address[],uint256[])"
+                            .to_string(),
+                        parameters: vec![
+                            token.address.clone(),
+                            format!("[{}]", recipients),
+                            format!("[{}]", amounts),
+                        ],
+                        from: None,
+                    },
+                    "0".to_string(),
+                )
+            }
+        };
+        self.write_contract(
+            from_account,
+            &call,
+            &value,
+            None,
+            &|name| name.to_string(),
+            fees,
+        )
+        .await
+    }
+
+    // Approve a Uniswap router (`spender`) to spend tokens
+    async fn approve_token_for_router(
+        &self,
+        from_account: &Account,
+        token_address: &str,
+        amount: &str,
+        decimals: u8,
+        spender: Address,
+    ) -> Result<()> {
+        // Skip approval for ETH
+        if token_address.to_lowercase() == "eth" {
+            return Ok(());
+        }
+
+        info!(
+            "Approving Uniswap Router {:?} to spend {} from {}",
+            spender, amount, from_account.address
+        );
+
+        // Parse amount
+        let amount_value = self.parse_token_amount(amount, decimals)?;
+
+        // Skip the transaction when the router may already spend enough
+        let token_addr = Address::from_str(token_address)?;
+        let owner = Address::from_str(&from_account.address)?;
+        let allowance = self.token_allowance(token_addr, owner, spender).await?;
+        if allowance >= amount_value {
+            info!(
+                "Existing allowance {} covers {}, skipping approval",
+                allowance, amount_value
+            );
+            return Ok(());
+        }
+
+        // Create signer provider
+        let signer_provider = self.get_signer_provider(from_account)?;
+
+        // Create contract instance with signer
+        let token_contract =
+            Contract::new(token_addr, self.erc20_abi.clone(), signer_provider.clone());
+
+        // Create approve call
+        let approve_call = token_contract.method::<_, bool>("approve", (spender, amount_value))?;
+
+        // Send transaction
+        let pending_tx = self
+            .send_with_nonce(
+                from_account,
+                approve_call.tx,
+                format!(
+                    "Approve {:?} to spend {} of {}",
+                    spender, amount, token_address
+                ),
+            )
+            .await?;
+
+        // Wait for transaction to be mined
+        match self.await_receipt(pending_tx).await {
+            Ok(Some(receipt)) => {
+                self.settle_pending(&format!("{:#x}", receipt.transaction_hash));
+                if receipt.status != Some(1.into()) {
+                    return Err(anyhow!("Token approval failed"));
+                }
+                Ok(())
+            }
+            Ok(None) => Err(anyhow!("Token approval failed")),
+            Err(e) => Err(anyhow!("Token approval failed: {}", e)),
+        }
+    }
+
+    /// Swap on the requested dex, or on whichever of Uniswap V2 and V3 quotes more
+    /// output when the request doesn't name one
+    pub async fn swap_tokens(
+        &self,
+        from_account: &Account,
+        swap_request: SwapRequest,
+        approvals: &SwapApprovals<'_>,
+    ) -> Result<SwapResult> {
+        let requested = swap_request.dex.as_deref().map(Dex::parse).transpose()?;
+        let v3 = match (&self.router.uniswap_v3, requested) {
+            (Some(v3), Some(Dex::UniswapV3) | None) => v3.clone(),
+            (None, Some(Dex::UniswapV3)) => {
+                return Err(anyhow!(
+                    "Uniswap V3 isn't configured for chain {}",
+                    self.chain_id
+                ));
+            }
+            _ => {
+                return self
+                    .swap_tokens_v2(from_account, swap_request, approvals)
+                    .await;
+            }
+        };
+
+        let (token_in, decimals_in, symbol_in) = self.swap_token(&swap_request.from_token).await?;
+        let (token_out, _, symbol_out) = self.swap_token(&swap_request.to_token).await?;
+        let amount_in = self.parse_token_amount(&swap_request.amount, decimals_in)?;
+
+        let v3_quote = self
+            .best_v3_route(&v3, token_in, token_out, amount_in)
+            .await;
+        let (route, quote) = match (requested, v3_quote) {
+            (Some(Dex::UniswapV3), None) => {
+                return Err(anyhow!(
+                    "No Uniswap V3 pool found for {}/{}",
+                    symbol_in,
+                    symbol_out
+                ));
+            }
+            (Some(Dex::UniswapV3), Some(best)) => best,
+            (_, v3_quote) => {
+                let v2_quote = self
+                    .quote_v2(token_in, token_out, amount_in)
+                    .await
+                    .unwrap_or_default();
+                match v3_quote {
+                    Some((route, quote)) if quote > v2_quote => {
+                        info!(
+                            "Uniswap V3 quotes {} vs {} on V2, routing through V3",
+                            quote, v2_quote
+                        );
+                        (route, quote)
+                    }
+                    _ => {
+                        return self
+                            .swap_tokens_v2(from_account, swap_request, approvals)
+                            .await;
+                    }
+                }
+            }
+        };
+
+        let min_amount_out = Self::apply_slippage(quote, swap_request.slippage);
+
+        // ETH legs keep their "ETH" symbol; later entries win
+        let symbols = HashMap::from([
+            (Address::from_str(&self.router.weth)?, "WETH".to_string()),
+            (token_in, symbol_in.clone()),
+            (token_out, symbol_out.clone()),
+        ]);
+        let description = route.describe(&symbols);
+
+        let mut result = if self.router.universal_router.is_some() {
+            self.swap_through_universal_router(
+                from_account,
+                &swap_request,
+                UniversalSwap::V3(route),
+                amount_in,
+                min_amount_out,
+                approvals,
+            )
+            .await?
+        } else {
+            self.swap_tokens_v3(
+                from_account,
+                &v3,
+                &swap_request,
+                &route,
+                amount_in,
+                min_amount_out,
+            )
+            .await?
+        };
+        result.from_token = symbol_in;
+        result.to_token = symbol_out;
+        result.route = Some(description);
+        Ok(result)
+    }
+
+    // Address, decimals and symbol of a swap leg; ETH trades as WETH
+    async fn swap_token(&self, token: &str) -> Result<(Address, u8, String)> {
+        if token.eq_ignore_ascii_case("eth") {
+            return Ok((Address::from_str(&self.router.weth)?, 18, "ETH".to_string()));
+        }
+        let info = self.resolve_token(token).await?;
+        Ok((
+            Address::from_str(&info.address)?,
+            info.decimals,
+            info.symbol,
+        ))
+    }
+
+    // Output the V2 router gives for `amount_in`, routed through WETH when needed
+    async fn quote_v2(
+        &self,
+        token_in: Address,
+        token_out: Address,
+        amount_in: U256,
+    ) -> Result<U256> {
+        let path = self.v2_path(token_in, token_out)?;
+
+        let router = Contract::new(
+            Address::from_str(&self.router.uniswap_v2_router)?,
+            self.uniswap_router_abi.clone(),
+            self.provider.clone(),
+        );
+        let amounts: Vec<U256> = router
+            .method::<_, Vec<U256>>("getAmountsOut", (amount_in, path))?
+            .call()
+            .await?;
+        amounts
+            .last()
+            .copied()
+            .ok_or_else(|| anyhow!("Router returned no quote"))
+    }
+
+    // Direct pair when either side is WETH, otherwise through WETH
+    fn v2_path(&self, token_in: Address, token_out: Address) -> Result<Vec<Address>> {
+        let weth = Address::from_str(&self.router.weth)?;
+        Ok(if token_in == weth || token_out == weth {
+            vec![token_in, token_out]
+        } else {
+            vec![token_in, weth, token_out]
+        })
+    }
+
+    fn v3_quoter(&self, v3: &UniswapV3Config) -> Result<Contract<Provider<FailoverClient>>> {
+        Ok(Contract::new(
+            Address::from_str(&v3.quoter)?,
+            self.uniswap_v3_quoter_abi.clone(),
+            self.provider.clone(),
+        ))
+    }
+
+    // Output of a V3 route; None when one of its pools doesn't exist (the quote reverts)
+    async fn quote_v3(
+        &self,
+        quoter: &Contract<Provider<FailoverClient>>,
+        route: &V3Route,
+        amount_in: U256,
+    ) -> Option<U256> {
+        quoter
+            .method::<_, U256>("quoteExactInput", (route.encode_path(), amount_in))
+            .ok()?
+            .call()
+            .await
+            .ok()
+    }
+
+    // Minimum output for a quote at `slippage` percent; without one there is no
+    // protection, as on the V2 path
+    fn apply_slippage(quote: U256, slippage: Option<f64>) -> U256 {
+        match slippage {
+            Some(slippage) if slippage > 0.0 => {
+                let bps = ((100.0 - slippage.min(100.0)) * 100.0).round() as u64;
+                quote * U256::from(bps) / U256::from(10_000)
+            }
+            _ => U256::zero(),
+        }
+    }
+
+    /// Quote a swap without sending anything. Quotes the requested dex, or both Uniswap
+    /// versions when `dex` is None and reports the better one.
+    pub async fn quote_swap(
+        &self,
+        from_token: &str,
+        to_token: &str,
+        amount: &str,
+        dex: Option<&str>,
+        slippage: Option<f64>,
+    ) -> Result<SwapQuote> {
+        let requested = dex.map(Dex::parse).transpose()?;
+        let (token_in, decimals_in, symbol_in) = self.swap_token(from_token).await?;
+        let (token_out, decimals_out, symbol_out) = self.swap_token(to_token).await?;
+        let amount_in = self.parse_token_amount(amount, decimals_in)?;
+
+        let v2 = match requested {
+            Some(Dex::UniswapV3) => None,
+            _ => self.quote_v2(token_in, token_out, amount_in).await.ok(),
+        };
+        let v3 = match (&self.router.uniswap_v3, requested) {
+            (Some(v3), Some(Dex::UniswapV3) | None) => self
+                .best_v3_route(v3, token_in, token_out, amount_in)
+                .await
+                .map(|(route, amount_out)| (v3, route, amount_out)),
+            (None, Some(Dex::UniswapV3)) => {
+                return Err(anyhow!(
+                    "Uniswap V3 isn't configured for chain {}",
+                    self.chain_id
+                ));
+            }
+            _ => None,
+        };
+
+        // ETH legs keep their "ETH" symbol; later entries win
+        let symbols = HashMap::from([
+            (Address::from_str(&self.router.weth)?, "WETH".to_string()),
+            (token_in, symbol_in.clone()),
+            (token_out, symbol_out.clone()),
+        ]);
+
+        // Quoting a sliver of the amount approximates the spot price when the pools'
+        // reserves can't be read
+        let reference_in = (amount_in / 1000).max(U256::one());
+        let (dex, route, amount_out, mid, reference_out, swap) = match (v2, v3) {
+            (v2_out, Some((v3, route, v3_out))) if v2_out.is_none_or(|v2_out| v3_out > v2_out) => {
+                let mid = self
+                    .mid_price(Dex::UniswapV3, &route.tokens, &route.fees)
+                    .await
+                    .ok();
+                let reference_out = match mid {
+                    Some(_) => None,
+                    None => {
+                        let quoter = self.v3_quoter(v3)?;
+                        self.quote_v3(&quoter, &route, reference_in).await
+                    }
+                };
+                (
+                    Dex::UniswapV3,
+                    route.describe(&symbols),
+                    v3_out,
+                    mid,
+                    reference_out,
+                    UniversalSwap::V3(route),
+                )
+            }
+            (Some(v2_out), _) => {
+                let path = self.v2_path(token_in, token_out)?;
+                let fees = vec![UNISWAP_V2_FEE; path.len() - 1];
+                let mid = self.mid_price(Dex::UniswapV2, &path, &fees).await.ok();
+                let reference_out = match mid {
+                    Some(_) => None,
+                    None => self.quote_v2(token_in, token_out, reference_in).await.ok(),
+                };
+                let route = path
+                    .iter()
+                    .map(|token| {
+                        symbols
+                            .get(token)
+                            .cloned()
+                            .unwrap_or_else(|| format!("{:?}", token))
+                    })
+                    .collect::<Vec<_>>()
+                    .join(" -> ");
+                (
+                    Dex::UniswapV2,
+                    route,
+                    v2_out,
+                    mid,
+                    reference_out,
+                    UniversalSwap::V2(path),
+                )
+            }
+            _ => {
+                return Err(anyhow!(
+                    "No Uniswap pool can fill {} {} for {}",
+                    amount,
+                    symbol_in,
+                    symbol_out
+                ));
+            }
+        };
 
-    fn parse_token_amount(&self, amount: &str, decimals: u8) -> Result<U256> {
-        // Parse amount as float
-        let amount_float: f64 = amount.parse()?;
+        let as_f64 = |value: U256| value.to_string().parse::<f64>().unwrap_or(0.0);
+        let spot = match (mid, reference_out) {
+            (Some((_, net)), _) => Some(net),
+            (None, Some(reference_out)) if !reference_out.is_zero() => {
+                Some(as_f64(reference_out) / as_f64(reference_in))
+            }
+            _ => None,
+        };
+        let price_impact_percent = match spot {
+            Some(spot) if spot > 0.0 => {
+                let execution = as_f64(amount_out) / as_f64(amount_in);
+                (((1.0 - execution / spot) * 100.0).max(0.0) * 100.0).round() / 100.0
+            }
+            _ => 0.0,
+        };
+        let mid_price =
+            mid.map(|(gross, _)| gross * 10f64.powi(decimals_in as i32 - decimals_out as i32));
+        let minimum_amount_out = match slippage {
+            Some(_) => Some(format_units(
+                Self::apply_slippage(amount_out, slippage),
+                decimals_out as u32,
+            )?),
+            None => None,
+        };
 
-        // Convert to token units
-        let multiplier = 10u64.pow(decimals as u32) as f64;
-        let amount_raw = (amount_float * multiplier).round() as u64;
+        // The commands the Universal Router would run, decoded back into steps
+        let steps = match &self.router.universal_router {
+            Some(universal_router) => {
+                let from_is_eth = from_token.eq_ignore_ascii_case("eth");
+                let mut commands = Vec::new();
+                if !from_is_eth {
+                    commands.push(universal_router::Command::Permit2Permit {
+                        token: token_in,
+                        amount: amount_in,
+                        expiration: (chrono::Utc::now().timestamp() + PERMIT2_PERMIT_TTL) as u64,
+                        nonce: 0,
+                        spender: Address::from_str(universal_router)?,
+                        sig_deadline: U256::zero(),
+                        signature: Bytes::default(),
+                    });
+                }
+                commands.extend(Self::universal_swap_commands(
+                    &swap,
+                    from_is_eth,
+                    to_token.eq_ignore_ascii_case("eth"),
+                    amount_in,
+                    Self::apply_slippage(amount_out, slippage),
+                ));
+                let (command_bytes, inputs) = universal_router::encode_commands(&commands);
+                let decoded = universal_router::decode_commands(&command_bytes, &inputs)?;
+                let mut tokens: HashMap<Address, (String, u8)> = symbols
+                    .iter()
+                    .map(|(token, symbol)| (*token, (symbol.clone(), 18)))
+                    .collect();
+                tokens.insert(token_in, (symbol_in.clone(), decimals_in));
+                tokens.insert(token_out, (symbol_out.clone(), decimals_out));
+                Some(universal_router::describe_commands(
+                    &command_bytes,
+                    &decoded,
+                    &tokens,
+                ))
+            }
+            None => None,
+        };
 
-        Ok(U256::from(amount_raw))
+        Ok(SwapQuote {
+            dex: dex.as_str().to_string(),
+            route,
+            from_token: symbol_in,
+            to_token: symbol_out,
+            amount_in: amount.to_string(),
+            expected_amount_out: format_units(amount_out, decimals_out as u32)?,
+            price_impact_percent,
+            mid_price,
+            minimum_amount_out,
+            steps,
+        })
     }
 
-    pub async fn check_contract_deployed(&self, address: &str) -> Result<bool> {
-        let addr = Address::from_str(address)?;
-        let code = self.provider.get_code(addr, None).await?;
-        Ok(!code.is_empty())
+    // Output per unit of input (raw units) along `tokens` at the pools' current
+    // reserves, before and after LP fees
+    async fn mid_price(&self, dex: Dex, tokens: &[Address], fees: &[u32]) -> Result<(f64, f64)> {
+        let router = match dex {
+            Dex::UniswapV2 => self.router.uniswap_v2_router.clone(),
+            Dex::UniswapV3 => self
+                .router
+                .uniswap_v3
+                .as_ref()
+                .ok_or_else(|| anyhow!("Uniswap V3 isn't configured"))?
+                .router
+                .clone(),
+        };
+        let abi = parse_abi(&[
+            "function factory() view returns (address)",
+            "function getPair(address,address) view returns (address)",
+            "function getPool(address,address,uint24) view returns (address)",
+            "function getReserves() view returns (uint112 reserve0, uint112 reserve1, uint32 blockTimestampLast)",
+            "function slot0() view returns (uint160 sqrtPriceX96, int24 tick, uint16 observationIndex, uint16 observationCardinality, uint16 observationCardinalityNext, uint8 feeProtocol, bool unlocked)",
+        ])?;
+        let contract =
+            |address: Address| Contract::new(address, abi.clone(), self.provider.clone());
+        let factory: Address = contract(Address::from_str(&router)?)
+            .method::<_, Address>("factory", ())?
+            .call()
+            .await?;
+        let factory = contract(factory);
+
+        let (mut gross, mut net) = (1.0, 1.0);
+        for (pair, fee) in tokens.windows(2).zip(fees) {
+            let (token_in, token_out) = (pair[0], pair[1]);
+            let rate = match dex {
+                Dex::UniswapV2 => {
+                    let pair: Address = factory
+                        .method::<_, Address>("getPair", (token_in, token_out))?
+                        .call()
+                        .await?;
+                    let (reserve0, reserve1, _): (u128, u128, u32) = contract(pair)
+                        .method::<_, (u128, u128, u32)>("getReserves", ())?
+                        .call()
+                        .await?;
+                    let (reserve_in, reserve_out) = if token_in < token_out {
+                        (reserve0, reserve1)
+                    } else {
+                        (reserve1, reserve0)
+                    };
+                    reserve_out as f64 / reserve_in as f64
+                }
+                Dex::UniswapV3 => {
+                    let pool: Address = factory
+                        .method::<_, Address>("getPool", (token_in, token_out, *fee))?
+                        .call()
+                        .await?;
+                    let (sqrt_price_x96, ..): (U256, i32, u16, u16, u16, u8, bool) = contract(pool)
+                        .method::<_, (U256, i32, u16, u16, u16, u8, bool)>("slot0", ())?
+                        .call()
+                        .await?;
+                    // sqrtPriceX96 is the square root of token1 per token0
+                    let sqrt_price = sqrt_price_x96.to_string().parse::<f64>()? / 2f64.powi(96);
+                    let token1_per_token0 = sqrt_price * sqrt_price;
+                    if token_in < token_out {
+                        token1_per_token0
+                    } else {
+                        1.0 / token1_per_token0
+                    }
+                }
+            };
+            if !rate.is_finite() || rate <= 0.0 {
+                return Err(anyhow!(
+                    "Pool for {:?}/{:?} has no liquidity",
+                    token_in,
+                    token_out
+                ));
+            }
+            gross *= rate;
+            net *= rate * (1.0 - *fee as f64 / 1_000_000.0);
+        }
+        Ok((gross, net))
     }
 
-    fn format_balance(&self, balance: U256, decimals: u8) -> String {
-        let divisor = U256::from(10).pow(U256::from(decimals));
-        let integer_part = balance / divisor;
-        let fractional_part = balance % divisor;
+    // Best V3 route over every fee tier, direct and through WETH. Quotes for pools
+    // that don't exist revert and are skipped; None when no pool can fill the swap.
+    async fn best_v3_route(
+        &self,
+        v3: &UniswapV3Config,
+        token_in: Address,
+        token_out: Address,
+        amount_in: U256,
+    ) -> Option<(V3Route, U256)> {
+        let weth = Address::from_str(&self.router.weth).ok()?;
+        let quoter = self.v3_quoter(v3).ok()?;
 
-        if fractional_part.is_zero() {
-            integer_part.to_string()
-        } else {
-            let fractional_str = format!("{:0width$}", fractional_part, width = decimals as usize);
-            let fractional_trimmed = fractional_str.trim_end_matches('0');
-            if fractional_trimmed.is_empty() {
-                integer_part.to_string()
-            } else {
-                format!("{}.{}", integer_part, fractional_trimmed)
+        let mut routes: Vec<V3Route> = UNISWAP_V3_FEE_TIERS
+            .iter()
+            .map(|fee| V3Route {
+                tokens: vec![token_in, token_out],
+                fees: vec![*fee],
+            })
+            .collect();
+        if token_in != weth && token_out != weth {
+            for fee_in in UNISWAP_V3_HOP_FEE_TIERS {
+                for fee_out in UNISWAP_V3_HOP_FEE_TIERS {
+                    routes.push(V3Route {
+                        tokens: vec![token_in, weth, token_out],
+                        fees: vec![fee_in, fee_out],
+                    });
+                }
             }
         }
-    }
 
-    pub fn get_supported_tokens(&self) -> Vec<&TokenInfo> {
-        self.token_registry
-            .values()
-            .filter(|token| token.address.starts_with("0x") && token.address.len() == 42)
-            .collect()
+        let quotes = join_all(
+            routes
+                .iter()
+                .map(|route| self.quote_v3(&quoter, route, amount_in)),
+        )
+        .await;
+
+        routes
+            .into_iter()
+            .zip(quotes)
+            .filter_map(|(route, quote)| quote.map(|quote| (route, quote)))
+            .max_by_key(|(_, quote)| *quote)
     }
 
-    // Send ERC20 token transaction
-    pub async fn send_erc20(
+    async fn swap_tokens_v3(
         &self,
         from_account: &Account,
-        to_address: &str,
-        token_identifier: &str,
-        amount: &str,
-    ) -> Result<TransactionResult> {
-        // Resolve token info
-        let token_info = self.resolve_token(token_identifier).await?;
+        v3: &UniswapV3Config,
+        swap_request: &SwapRequest,
+        route: &V3Route,
+        amount_in: U256,
+        min_amount_out: U256,
+    ) -> Result<SwapResult> {
+        let signer_provider = self.get_signer_provider(from_account)?;
+        let router_addr = Address::from_str(&v3.router)?;
+        let router_contract = Contract::new(
+            router_addr,
+            self.uniswap_v3_router_abi.clone(),
+            signer_provider,
+        );
+
+        let deadline = U256::from(chrono::Utc::now().timestamp() + 3600); // 1 hour from now
+        let receiver = Address::from_str(&from_account.address)?;
+        let from_is_eth = swap_request.from_token.eq_ignore_ascii_case("eth");
+        let to_is_eth = swap_request.to_token.eq_ignore_ascii_case("eth");
 
         info!(
-            "Sending {} {} from {} to {}",
-            amount, token_info.symbol, from_account.address, to_address
+            "Swapping {} {} for {} on Uniswap V3 from account {}",
+            swap_request.amount,
+            swap_request.from_token,
+            swap_request.to_token,
+            from_account.address
         );
 
-        // Parse amount based on token decimals
-        let amount_value = self.parse_token_amount(amount, token_info.decimals)?;
+        if !from_is_eth {
+            let (token_in, decimals_in, _) = self.swap_token(&swap_request.from_token).await?;
+            self.approve_token_for_router(
+                from_account,
+                &format!("{:?}", token_in),
+                &swap_request.amount,
+                decimals_in,
+                router_addr,
+            )
+            .await?;
+        }
 
-        // Create signer provider
-        let signer_provider = self.get_signer_provider(from_account)?;
+        // WETH bought for ETH is sent to the router, which unwraps it to the receiver
+        let recipient = if to_is_eth { router_addr } else { receiver };
+        let swap = if route.fees.len() == 1 {
+            router_contract.encode(
+                "exactInputSingle",
+                ((
+                    route.tokens[0],
+                    route.tokens[1],
+                    route.fees[0],
+                    recipient,
+                    deadline,
+                    amount_in,
+                    min_amount_out,
+                    U256::zero(),
+                ),),
+            )?
+        } else {
+            router_contract.encode(
+                "exactInput",
+                ((
+                    route.encode_path(),
+                    recipient,
+                    deadline,
+                    amount_in,
+                    min_amount_out,
+                ),),
+            )?
+        };
+        let mut calls = vec![swap];
+        if to_is_eth {
+            calls.push(router_contract.encode("unwrapWETH9", (min_amount_out, receiver))?);
+        }
 
-        // Create contract instance with signer
-        let token_addr = Address::from_str(&token_info.address)?;
-        let token_contract =
-            Contract::new(token_addr, self.erc20_abi.clone(), signer_provider.clone());
+        // Call multicall, sending ETH along when it is the input
+        let mut swap_call = router_contract.method::<_, Vec<Bytes>>("multicall", (calls,))?;
+        if from_is_eth {
+            swap_call = swap_call.value(amount_in);
+        }
 
-        // Create transfer call
-        let to_addr = Address::from_str(to_address)?;
-        let transfer_call =
-            token_contract.method::<_, bool>("transfer", (to_addr, amount_value))?;
+        if swap_request.simulate {
+            self.preflight(from_account, &swap_call.tx).await?;
+        }
 
         // Send transaction
-        let pending_tx = transfer_call.send().await?;
-
-        // Get transaction hash
-        let tx_hash = format!("{:#x}", pending_tx.tx_hash());
-
-        // Wait for transaction to be mined
-        match pending_tx.await {
-            Ok(Some(receipt)) => {
-                // Transaction was mined
-                let status = if receipt.status == Some(1.into()) {
-                    "success".to_string()
-                } else {
-                    "failed".to_string()
-                };
+        let pending_tx = self
+            .send_with_nonce(
+                from_account,
+                swap_call.tx,
+                format!(
+                    "Swap {} {} for {}",
+                    swap_request.amount, swap_request.from_token, swap_request.to_token
+                ),
+            )
+            .await?;
 
-                Ok(TransactionResult {
-                    hash: tx_hash,
-                    status,
-                    block_number: receipt.block_number.map(|bn| bn.as_u64()),
-                    gas_used: receipt.gas_used.map(|gas| gas.as_u64()),
-                })
+        let output = if to_is_eth {
+            SwapOutput::Eth {
+                weth: Address::from_str(&self.router.weth)?,
             }
-            Ok(None) => {
-                // Transaction was not mined yet
-                Ok(TransactionResult {
-                    hash: tx_hash,
-                    status: "pending".to_string(),
-                    block_number: None,
-                    gas_used: None,
-                })
+        } else {
+            let (address, decimals, _) = self.swap_token(&swap_request.to_token).await?;
+            SwapOutput::Token {
+                address,
+                decimals,
+                receiver,
             }
-            Err(e) => Err(anyhow!("Transaction failed: {}", e)),
+        };
+        self.process_swap_transaction(
+            pending_tx,
+            swap_request.from_token.clone(),
+            swap_request.to_token.clone(),
+            swap_request.amount.to_string(),
+            output,
+            Dex::UniswapV3.as_str(),
+        )
+        .await
+    }
+
+    // Universal Router commands for a swap, without the permit: ETH input is wrapped
+    // by the router first, and ETH output is swapped to the router and unwrapped to
+    // the sender, all in the one transaction
+    fn universal_swap_commands(
+        swap: &UniversalSwap,
+        from_is_eth: bool,
+        to_is_eth: bool,
+        amount_in: U256,
+        min_amount_out: U256,
+    ) -> Vec<universal_router::Command> {
+        let mut commands = Vec::new();
+        if from_is_eth {
+            commands.push(universal_router::Command::WrapEth {
+                recipient: universal_router::address_this(),
+                amount_min: amount_in,
+            });
+        }
+        let recipient = if to_is_eth {
+            universal_router::address_this()
+        } else {
+            universal_router::msg_sender()
+        };
+        commands.push(match swap {
+            UniversalSwap::V2(path) => universal_router::Command::V2SwapExactIn {
+                recipient,
+                amount_in,
+                amount_out_min: min_amount_out,
+                path: path.clone(),
+                payer_is_user: !from_is_eth,
+            },
+            UniversalSwap::V3(route) => universal_router::Command::V3SwapExactIn {
+                recipient,
+                amount_in,
+                amount_out_min: min_amount_out,
+                tokens: route.tokens.clone(),
+                fees: route.fees.clone(),
+                payer_is_user: !from_is_eth,
+            },
+        });
+        if to_is_eth {
+            commands.push(universal_router::Command::UnwrapWeth {
+                recipient: universal_router::msg_sender(),
+                amount_min: min_amount_out,
+            });
         }
+        commands
     }
 
-    // Approve tokens for Uniswap Router
-    async fn approve_token_for_router(
+    // Swap through the Universal Router. Tokens are paid with Permit2: the token is
+    // approved to Permit2 as far as the policy allows, then each swap signs a permit for
+    // exactly its input that expires shortly after.
+    async fn swap_through_universal_router(
         &self,
         from_account: &Account,
-        token_address: &str,
-        amount: &str,
-        decimals: u8,
-    ) -> Result<()> {
-        // Skip approval for ETH
-        if token_address.to_lowercase() == "eth" {
-            return Ok(());
-        }
+        swap_request: &SwapRequest,
+        swap: UniversalSwap,
+        amount_in: U256,
+        min_amount_out: U256,
+        approvals: &SwapApprovals<'_>,
+    ) -> Result<SwapResult> {
+        let universal_router =
+            self.router.universal_router.as_deref().ok_or_else(|| {
+                anyhow!("No Universal Router configured for chain {}", self.chain_id)
+            })?;
+        let router_addr = Address::from_str(universal_router)?;
+        let owner = Address::from_str(&from_account.address)?;
+        let from_is_eth = swap_request.from_token.eq_ignore_ascii_case("eth");
+        let to_is_eth = swap_request.to_token.eq_ignore_ascii_case("eth");
+        let now = chrono::Utc::now().timestamp();
 
         info!(
-            "Approving Uniswap Router to spend {} from {}",
-            amount, from_account.address
+            "Swapping {} {} for {} through the Universal Router from account {}",
+            swap_request.amount,
+            swap_request.from_token,
+            swap_request.to_token,
+            from_account.address
         );
 
-        // Parse amount
-        let amount_value = self.parse_token_amount(amount, decimals)?;
+        let mut commands = Vec::new();
+        if !from_is_eth
+            && let Some(permit) = self
+                .permit2_permit(
+                    from_account,
+                    swap.token_in(),
+                    router_addr,
+                    amount_in,
+                    approvals,
+                )
+                .await?
+        {
+            commands.push(permit);
+        }
+        commands.extend(Self::universal_swap_commands(
+            &swap,
+            from_is_eth,
+            to_is_eth,
+            amount_in,
+            min_amount_out,
+        ));
+        let (command_bytes, inputs) = universal_router::encode_commands(&commands);
 
-        // Create signer provider
-        let signer_provider = self.get_signer_provider(from_account)?;
+        let router = Contract::new(
+            router_addr,
+            parse_abi(&[
+                "function execute(bytes commands, bytes[] inputs, uint256 deadline) payable",
+            ])?,
+            self.get_signer_provider(from_account)?,
+        );
+        let deadline = U256::from(now + 3600); // 1 hour from now
+        let mut swap_call = router.method::<_, ()>("execute", (command_bytes, inputs, deadline))?;
+        if from_is_eth {
+            swap_call = swap_call.value(amount_in);
+        }
+        if swap_request.simulate {
+            self.preflight(from_account, &swap_call.tx).await?;
+        }
 
-        // Create contract instance with signer
-        let token_addr = Address::from_str(token_address)?;
-        let token_contract =
-            Contract::new(token_addr, self.erc20_abi.clone(), signer_provider.clone());
+        let pending_tx = self
+            .send_with_nonce(
+                from_account,
+                swap_call.tx,
+                format!(
+                    "Swap {} {} for {}",
+                    swap_request.amount, swap_request.from_token, swap_request.to_token
+                ),
+            )
+            .await?;
 
-        // Create approve call
-        let router_addr = Address::from_str(UNISWAP_V2_ROUTER)?;
-        let approve_call =
-            token_contract.method::<_, bool>("approve", (router_addr, amount_value))?;
+        let (_, _, symbol_in) = self.swap_token(&swap_request.from_token).await?;
+        let (address, decimals, symbol_out) = self.swap_token(&swap_request.to_token).await?;
+        let output = if to_is_eth {
+            SwapOutput::Eth { weth: address }
+        } else {
+            SwapOutput::Token {
+                address,
+                decimals,
+                receiver: owner,
+            }
+        };
+        self.process_swap_transaction(
+            pending_tx,
+            symbol_in,
+            symbol_out,
+            swap_request.amount.to_string(),
+            output,
+            swap.dex().as_str(),
+        )
+        .await
+    }
 
-        // Send transaction
-        let pending_tx = approve_call.send().await?;
+    // Make sure Permit2 may move `token` for the account, approving it once if not, and
+    // sign a permit letting `spender` pull `amount` through it. None when a permit from
+    // an earlier swap that was never mined still covers this one.
+    async fn permit2_permit(
+        &self,
+        from_account: &Account,
+        token: Address,
+        spender: Address,
+        amount: U256,
+        approvals: &SwapApprovals<'_>,
+    ) -> Result<Option<universal_router::Command>> {
+        let permit2_addr = Address::from_str(PERMIT2_ADDRESS)?;
+        let owner = Address::from_str(&from_account.address)?;
+        let now = chrono::Utc::now().timestamp();
 
-        // Wait for transaction to be mined
-        match pending_tx.await {
-            Ok(Some(receipt)) => {
-                if receipt.status != Some(1.into()) {
-                    return Err(anyhow!("Token approval failed"));
-                }
-                Ok(())
+        // The standing approval: Permit2 may move the token, but only for a permit. It's
+        // unlimited unless the policy caps it.
+        if self.token_allowance(token, owner, permit2_addr).await? < amount {
+            let info = self.resolve_token(&format!("{:?}", token)).await?;
+            let (approval, _) = self
+                .set_swap_allowance(
+                    from_account,
+                    &info,
+                    permit2_addr,
+                    U256::MAX,
+                    amount,
+                    approvals,
+                )
+                .await?;
+            if approval.status != "success" {
+                return Err(anyhow!(
+                    "Approving Permit2 failed ({}): {}",
+                    approval.status,
+                    approval.hash
+                ));
             }
-            Ok(None) => Err(anyhow!("Token approval failed")),
-            Err(e) => Err(anyhow!("Token approval failed: {}", e)),
         }
+
+        let permit2 = Contract::new(
+            permit2_addr,
+            parse_abi(&[
+                "function allowance(address owner, address token, address spender) view returns (uint160 amount, uint48 expiration, uint48 nonce)",
+            ])?,
+            self.provider.clone(),
+        );
+        let (allowed, expiration, nonce): (U256, u64, u64) = permit2
+            .method::<_, (U256, u64, u64)>("allowance", (owner, token, spender))?
+            .call()
+            .await?;
+        if allowed >= amount && expiration as i64 > now + 60 {
+            return Ok(None);
+        }
+
+        let expiration = (now + PERMIT2_PERMIT_TTL) as u64;
+        let permit: TypedData = serde_json::from_value(serde_json::json!({
+            "types": {
+                "EIP712Domain": [
+                    {"name": "name", "type": "string"},
+                    {"name": "chainId", "type": "uint256"},
+                    {"name": "verifyingContract", "type": "address"}
+                ],
+                "PermitSingle": [
+                    {"name": "details", "type": "PermitDetails"},
+                    {"name": "spender", "type": "address"},
+                    {"name": "sigDeadline", "type": "uint256"}
+                ],
+                "PermitDetails": [
+                    {"name": "token", "type": "address"},
+                    {"name": "amount", "type": "uint160"},
+                    {"name": "expiration", "type": "uint48"},
+                    {"name": "nonce", "type": "uint48"}
+                ]
+            },
+            "primaryType": "PermitSingle",
+            "domain": {
+                "name": "Permit2",
+                "chainId": self.chain_id,
+                "verifyingContract": format!("{:?}", permit2_addr)
+            },
+            "message": {
+                "details": {
+                    "token": format!("{:?}", token),
+                    "amount": amount.to_string(),
+                    "expiration": expiration.to_string(),
+                    "nonce": nonce.to_string()
+                },
+                "spender": format!("{:?}", spender),
+                "sigDeadline": expiration.to_string()
+            }
+        }))?;
+        let (signature, _) = signing::sign_typed_data(from_account, &permit).await?;
+        Ok(Some(universal_router::Command::Permit2Permit {
+            token,
+            amount,
+            expiration,
+            nonce,
+            spender,
+            sig_deadline: expiration.into(),
+            signature: Bytes::from(signature.to_vec()),
+        }))
     }
 
-    pub async fn swap_tokens(
+    async fn swap_tokens_v2(
         &self,
         from_account: &Account,
         swap_request: SwapRequest,
+        approvals: &SwapApprovals<'_>,
     ) -> Result<SwapResult> {
+        if self.router.universal_router.is_some() {
+            let (token_in, decimals_in, _) = self.swap_token(&swap_request.from_token).await?;
+            let (token_out, _, _) = self.swap_token(&swap_request.to_token).await?;
+            let amount_in = self.parse_token_amount(&swap_request.amount, decimals_in)?;
+            let min_amount_out = match swap_request.slippage {
+                Some(_) => Self::apply_slippage(
+                    self.quote_v2(token_in, token_out, amount_in).await?,
+                    swap_request.slippage,
+                ),
+                None => U256::zero(),
+            };
+            return self
+                .swap_through_universal_router(
+                    from_account,
+                    &swap_request,
+                    UniversalSwap::V2(self.v2_path(token_in, token_out)?),
+                    amount_in,
+                    min_amount_out,
+                    approvals,
+                )
+                .await;
+        }
+
         // Resolve token info
 
         // Create signer provider
@@ -660,12 +4744,12 @@ impl BlockchainService {
         let uniswap_router_abi = self.uniswap_router_abi.clone();
 
         // Create router contract instance
-        let router_addr = Address::from_str(UNISWAP_V2_ROUTER)?; // Uniswap V2 Router
+        let router_addr = Address::from_str(&self.router.uniswap_v2_router)?; // Uniswap V2 Router
         let router_contract =
             Contract::new(router_addr, uniswap_router_abi, signer_provider.clone());
 
         // Constants
-        let weth_address = WETH_ADDRESS; // WETH on mainnet
+        let weth_address = self.router.weth.as_str(); // Wrapped native token for this chain
         let deadline = U256::from(chrono::Utc::now().timestamp() + 3600); // 1 hour from now
         let min_amount_out = U256::from(0); // No slippage protection for simplicity
         let receiver = Address::from_str(&from_account.address)?;
@@ -690,7 +4774,7 @@ impl BlockchainService {
             let path = vec![Address::from_str(weth_address)?, to_token_addr];
 
             // Parse amount as ether
-            let amount_in = ethers::utils::parse_ether(&swap_request.amount)?;
+            let amount_in = self.parse_token_amount(&swap_request.amount, 18)?;
 
             // Call swapExactETHForTokens
             let swap_call = router_contract.method::<_, Vec<U256>>(
@@ -700,7 +4784,19 @@ impl BlockchainService {
 
             // Send transaction with ETH
             let value_call = swap_call.value(amount_in);
-            let pending_tx = value_call.send().await?;
+            if swap_request.simulate {
+                self.preflight(from_account, &value_call.tx).await?;
+            }
+            let pending_tx = self
+                .send_with_nonce(
+                    from_account,
+                    value_call.tx,
+                    format!(
+                        "Swap {} {} for {}",
+                        swap_request.amount, swap_request.from_token, swap_request.to_token
+                    ),
+                )
+                .await?;
 
             // Get transaction hash and wait for it to be mined
             return self
@@ -709,6 +4805,12 @@ impl BlockchainService {
                     "ETH".to_string(),
                     to_token.symbol,
                     swap_request.amount.to_string(),
+                    SwapOutput::Token {
+                        address: to_token_addr,
+                        decimals: to_token.decimals,
+                        receiver,
+                    },
+                    Dex::UniswapV2.as_str(),
                 )
                 .await;
         } else if to_is_eth {
@@ -726,6 +4828,7 @@ impl BlockchainService {
                 &from_token.address,
                 &swap_request.amount,
                 from_token.decimals,
+                router_addr,
             )
             .await?;
 
@@ -735,8 +4838,21 @@ impl BlockchainService {
                 (amount_in, min_amount_out, path, receiver, deadline),
             )?;
 
+            if swap_request.simulate {
+                self.preflight(from_account, &swap_call.tx).await?;
+            }
+
             // Send transaction
-            let pending_tx = swap_call.send().await?;
+            let pending_tx = self
+                .send_with_nonce(
+                    from_account,
+                    swap_call.tx,
+                    format!(
+                        "Swap {} {} for {}",
+                        swap_request.amount, swap_request.from_token, swap_request.to_token
+                    ),
+                )
+                .await?;
 
             // Get transaction hash and wait for it to be mined
             return self
@@ -745,6 +4861,10 @@ impl BlockchainService {
                     from_token.symbol,
                     "ETH".to_string(),
                     swap_request.amount.to_string(),
+                    SwapOutput::Eth {
+                        weth: Address::from_str(weth_address)?,
+                    },
+                    Dex::UniswapV2.as_str(),
                 )
                 .await;
         } else {
@@ -774,6 +4894,7 @@ impl BlockchainService {
                 &from_token.address,
                 &swap_request.amount,
                 from_token.decimals,
+                router_addr,
             )
             .await?;
 
@@ -783,8 +4904,21 @@ impl BlockchainService {
                 (amount_in, min_amount_out, path, receiver, deadline),
             )?;
 
+            if swap_request.simulate {
+                self.preflight(from_account, &swap_call.tx).await?;
+            }
+
             // Send transaction
-            let pending_tx = swap_call.send().await?;
+            let pending_tx = self
+                .send_with_nonce(
+                    from_account,
+                    swap_call.tx,
+                    format!(
+                        "Swap {} {} for {}",
+                        swap_request.amount, swap_request.from_token, swap_request.to_token
+                    ),
+                )
+                .await?;
 
             // Get transaction hash and wait for it to be mined
             return self
@@ -793,25 +4927,89 @@ impl BlockchainService {
                     from_token.symbol,
                     to_token.symbol,
                     swap_request.amount.to_string(),
+                    SwapOutput::Token {
+                        address: to_token_addr,
+                        decimals: to_token.decimals,
+                        receiver,
+                    },
+                    Dex::UniswapV2.as_str(),
                 )
                 .await;
         }
     }
 
+    /// Send a swap whose calldata was built elsewhere, such as an aggregator's quote,
+    /// and read what it paid out from the receipt like any other swap
+    pub async fn execute_swap_calldata(
+        &self,
+        from_account: &Account,
+        swap: &SwapCalldata,
+    ) -> Result<SwapResult> {
+        let receiver = Address::from_str(&from_account.address)?;
+        info!(
+            "Swapping {} {} for {} through {} from account {}",
+            swap.amount_in, swap.from_token, swap.to_token, swap.source, from_account.address
+        );
+
+        let mut tx: TypedTransaction = Eip1559TransactionRequest::new()
+            .from(receiver)
+            .to(swap.to)
+            .data(swap.data.clone())
+            .value(swap.value)
+            .into();
+        self.apply_fees(&mut tx, &FeeOverrides::default()).await?;
+        if swap.simulate {
+            self.preflight(from_account, &tx).await?;
+        }
+        let pending_tx = self
+            .send_with_nonce(
+                from_account,
+                tx,
+                format!(
+                    "Swap {} {} for {} through {}",
+                    swap.amount_in, swap.from_token, swap.to_token, swap.source
+                ),
+            )
+            .await?;
+
+        let output = match swap.output_token {
+            Some((address, decimals)) => SwapOutput::Token {
+                address,
+                decimals,
+                receiver,
+            },
+            None => SwapOutput::Eth {
+                weth: Address::from_str(&self.router.weth)?,
+            },
+        };
+        self.process_swap_transaction(
+            pending_tx,
+            swap.from_token.clone(),
+            swap.to_token.clone(),
+            swap.amount_in.clone(),
+            output,
+            &swap.source,
+        )
+        .await
+    }
+
     // Helper method to process a swap transaction and create a result
     async fn process_swap_transaction(
         &self,
-        pending_tx: ethers::providers::PendingTransaction<'_, Http>,
+        pending_tx: ethers::providers::PendingTransaction<'_, FailoverClient>,
         from_token: String,
         to_token: String,
         amount_in: String,
+        output: SwapOutput,
+        dex: &str,
     ) -> Result<SwapResult> {
         // Get transaction hash
         let tx_hash = format!("{:#x}", pending_tx.tx_hash());
 
         // Wait for transaction to be mined
-        match pending_tx.await {
+        match self.await_receipt(pending_tx).await {
             Ok(Some(receipt)) => {
+                self.settle_pending(&tx_hash);
                 // Transaction was mined
                 let status = if receipt.status == Some(1.into()) {
                     "success".to_string()
@@ -819,9 +5017,8 @@ impl BlockchainService {
                     "failed".to_string()
                 };
 
-                // In a real implementation, you would parse the swap event logs
-                // to get the exact amount received. For simplicity, we're just
-                // returning "Unknown" for the amount_out.
+                let amount_out = Self::swap_amount_out(&receipt.logs, &output)
+                    .unwrap_or_else(|| "Unknown".to_string());
 
                 Ok(SwapResult {
                     hash: tx_hash,
@@ -829,13 +5026,157 @@ impl BlockchainService {
                     from_token,
                     to_token,
                     amount_in,
-                    amount_out: "Unknown".to_string(), // Would require event parsing
+                    amount_out,
                     block_number: receipt.block_number.map(|bn| bn.as_u64()),
                     gas_used: receipt.gas_used.map(|gas| gas.as_u64()),
+                    dex: Some(dex.to_string()),
+                    route: None,
                 })
             }
             Ok(None) => Err(anyhow!("Swap failed")),
             Err(e) => Err(anyhow!("Swap failed: {}", e)),
         }
     }
+
+    // Amount received by a swap, read from the receipt's Transfer (or WETH Withdrawal) logs
+    fn swap_amount_out(logs: &[Log], output: &SwapOutput) -> Option<String> {
+        let (token, topic, decimals) = match output {
+            SwapOutput::Token {
+                address, decimals, ..
+            } => (
+                *address,
+                H256::from(keccak256("Transfer(address,address,uint256)")),
+                *decimals,
+            ),
+            SwapOutput::Eth { weth } => (
+                *weth,
+                H256::from(keccak256("Withdrawal(address,uint256)")),
+                18,
+            ),
+        };
+
+        let amount = logs
+            .iter()
+            .filter(|log| log.address == token && log.topics.first() == Some(&topic))
+            .filter(|log| match output {
+                // Transfer(from, to, value): `to` is the second indexed topic
+                SwapOutput::Token { receiver, .. } => {
+                    log.topics.get(2) == Some(&H256::from(*receiver))
+                }
+                SwapOutput::Eth { .. } => true,
+            })
+            .map(|log| U256::from_big_endian(&log.data))
+            .fold(U256::zero(), |sum, value| sum + value);
+
+        // Fall back to the pool's own accounting, e.g. for tokens whose transfers emit
+        // non-standard events
+        let amount = if amount.is_zero() {
+            Self::last_pool_swap_out(logs)?
+        } else {
+            amount
+        };
+        if amount.is_zero() {
+            return None;
+        }
+        format_units(amount, decimals as u32).ok()
+    }
+
+    // Amount paid out by the last pool of a swap, from its Uniswap V2 or V3 Swap event
+    fn last_pool_swap_out(logs: &[Log]) -> Option<U256> {
+        let v2_swap = H256::from(keccak256(
+            "Swap(address,uint256,uint256,uint256,uint256,address)",
+        ));
+        let v3_swap = H256::from(keccak256(
+            "Swap(address,address,int256,int256,uint160,uint128,int24)",
+        ));
+
+        let log = logs.iter().rev().find(|log| {
+            log.topics
+                .first()
+                .is_some_and(|topic| *topic == v2_swap || *topic == v3_swap)
+        })?;
+        let word = |i: usize| {
+            log.data
+                .get(i * 32..(i + 1) * 32)
+                .map(U256::from_big_endian)
+        };
+
+        if log.topics[0] == v2_swap {
+            // amount0In, amount1In, amount0Out, amount1Out: one of the outs is zero
+            Some(word(2)?.max(word(3)?))
+        } else {
+            // Signed deltas from the pool's side; the negative one left the pool
+            let amount0 = I256::from_raw(word(0)?);
+            let amount1 = I256::from_raw(word(1)?);
+            let out = if amount0.is_negative() {
+                amount0
+            } else {
+                amount1
+            };
+            Some(out.unsigned_abs())
+        }
+    }
+}
+
+/// An ABI value as JSON. Numbers become decimal strings so large uint256 values
+/// survive JSON.
+pub fn token_to_json(token: Token) -> Value {
+    match token {
+        Token::Address(address) => Value::String(format!("{:?}", address)),
+        Token::Uint(value) => Value::String(value.to_string()),
+        Token::Int(value) => Value::String(I256::from_raw(value).to_string()),
+        Token::Bool(flag) => Value::Bool(flag),
+        Token::String(text) => Value::String(text),
+        Token::Bytes(bytes) | Token::FixedBytes(bytes) => {
+            Value::String(format!("0x{}", hex::encode(bytes)))
+        }
+        Token::Array(items) | Token::FixedArray(items) | Token::Tuple(items) => {
+            Value::Array(items.into_iter().map(token_to_json).collect())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn concurrent_reservations_get_consecutive_nonces() {
+        let (provider, mock) = Provider::mocked();
+        // The mock answers one eth_getTransactionCount; a second fetch would fail
+        mock.push(U256::from(7)).unwrap();
+        let nonces = NonceManager::default();
+        let address = Address::repeat_byte(0x11);
+
+        let mut reserved =
+            futures::future::try_join_all((0..5).map(|_| nonces.next(&provider, address)))
+                .await
+                .unwrap();
+        reserved.sort();
+        assert_eq!(reserved, (7..12).map(U256::from).collect::<Vec<_>>());
+        assert_eq!(nonces.next(&provider, address).await.unwrap(), 12.into());
+    }
+
+    #[tokio::test]
+    async fn resync_refetches_the_pending_nonce() {
+        let (provider, mock) = Provider::mocked();
+        mock.push(U256::from(3)).unwrap();
+        let nonces = NonceManager::default();
+        let address = Address::repeat_byte(0x22);
+        let other = Address::repeat_byte(0x33);
+
+        assert_eq!(nonces.next(&provider, address).await.unwrap(), 3.into());
+        mock.push(U256::from(40)).unwrap();
+        assert_eq!(nonces.next(&provider, other).await.unwrap(), 40.into());
+
+        // After a failed send the node's count wins, even if it went backwards
+        nonces.resync(address).await;
+        mock.push(U256::from(3)).unwrap();
+        assert_eq!(nonces.next(&provider, address).await.unwrap(), 3.into());
+        // Other signers keep their local nonce
+        assert_eq!(nonces.next(&provider, other).await.unwrap(), 41.into());
+
+        nonces.resync_all().await;
+        assert!(nonces.next(&provider, other).await.is_err());
+    }
 }
@@ -0,0 +1,156 @@
+use anyhow::{Result, anyhow};
+use ethers::abi::{Function, FunctionExt, HumanReadableParser};
+use ethers::types::{Address, Bytes};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use std::sync::Arc;
+use tracing::warn;
+
+use crate::blockchain::{BlockchainService, ContractCallOutput, token_to_json};
+use crate::external_apis::{ExternalAPIService, VerifiedContract};
+use crate::universal_router;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DecodedCalldata {
+    pub selector: String,
+    /// Matched function signature, e.g. "transfer(address,uint256)"; None when unknown
+    pub function: Option<String>,
+    pub arguments: Vec<ContractCallOutput>,
+    /// Where the function came from: "loaded_abi", "etherscan" or "4byte"
+    pub source: Option<String>,
+    /// Other 4byte signatures sharing the selector that also fit the data
+    pub alternatives: Vec<String>,
+    /// For a Universal Router `execute` call, what each of its commands does
+    pub steps: Option<Vec<String>>,
+}
+
+/// Work out which function transaction input calls and decode its arguments, trying
+/// the loaded ABIs, the contract's verified ABI on Etherscan and the 4byte directory
+pub async fn decode_calldata(
+    blockchain_service: &BlockchainService,
+    external_apis: &ExternalAPIService,
+    data: &str,
+    contract: Option<&str>,
+) -> Result<DecodedCalldata> {
+    let data = Bytes::from_str(data.trim()).map_err(|e| anyhow!("Invalid calldata: {}", e))?;
+    if data.len() < 4 {
+        return Err(anyhow!(
+            "Calldata needs at least a 4-byte function selector"
+        ));
+    }
+    let selector: [u8; 4] = data[..4].try_into()?;
+    let selector_hex = format!("0x{}", hex::encode(selector));
+    let mut decoded = DecodedCalldata {
+        selector: selector_hex.clone(),
+        function: None,
+        arguments: Vec::new(),
+        source: None,
+        alternatives: Vec::new(),
+        steps: None,
+    };
+
+    // Universal Router calls pack their commands into bytes arguments; spell them out
+    match universal_router::decode_execute(&data) {
+        Ok(Some((command_bytes, commands))) => {
+            let tokens = universal_router::token_labels(blockchain_service, &commands).await;
+            decoded.steps = Some(universal_router::describe_commands(
+                &command_bytes,
+                &commands,
+                &tokens,
+            ));
+        }
+        Ok(None) => {}
+        Err(e) => warn!("Couldn't decode Universal Router commands: {}", e),
+    }
+
+    // The contract's own ABI comes first: it is authoritative and names the arguments
+    let mut candidates: Vec<(Function, &str)> = Vec::new();
+    if let Some(contract) = contract {
+        match verified_contract(blockchain_service, external_apis, contract).await {
+            Ok(Some(verified)) => candidates.extend(
+                verified
+                    .abi
+                    .functions()
+                    .filter(|function| function.short_signature() == selector)
+                    .map(|function| (function.clone(), "etherscan")),
+            ),
+            Ok(None) => {}
+            Err(e) => warn!("Verified ABI for {} unavailable: {}", contract, e),
+        }
+    }
+
+    candidates.extend(
+        blockchain_service
+            .find_functions_by_selector(selector)
+            .into_iter()
+            .map(|function| (function, "loaded_abi")),
+    );
+    match external_apis
+        .lookup_function_signatures(&selector_hex)
+        .await
+    {
+        Ok(signatures) => candidates.extend(
+            signatures
+                .iter()
+                .filter_map(|signature| HumanReadableParser::parse_function(signature).ok())
+                .map(|function| (function, "4byte")),
+        ),
+        Err(e) => warn!("4byte lookup for {} failed: {}", selector_hex, e),
+    }
+
+    // Take the first candidate whose parameters decode the data exactly; selector
+    // collisions rarely also fit the argument encoding
+    for (function, source) in candidates {
+        let Some(arguments) = decode_arguments(&function, &data) else {
+            continue;
+        };
+        let signature = function.abi_signature();
+        if decoded.function.is_none() {
+            decoded.function = Some(signature);
+            decoded.arguments = arguments;
+            decoded.source = Some(source.to_string());
+        } else if decoded.function.as_ref() != Some(&signature)
+            && !decoded.alternatives.contains(&signature)
+        {
+            decoded.alternatives.push(signature);
+        }
+    }
+    Ok(decoded)
+}
+
+fn decode_arguments(function: &Function, data: &[u8]) -> Option<Vec<ContractCallOutput>> {
+    let values = function.decode_input(&data[4..]).ok()?;
+    if function.encode_input(&values).ok()? != data {
+        return None;
+    }
+    Some(
+        function
+            .inputs
+            .iter()
+            .zip(values)
+            .map(|(input, value)| ContractCallOutput {
+                name: input.name.clone(),
+                kind: input.kind.to_string(),
+                value: token_to_json(value),
+            })
+            .collect(),
+    )
+}
+
+/// A contract's verified source and ABI from Etherscan, looked up once per contract.
+/// Once found, calls to the contract use its ABI.
+pub async fn verified_contract(
+    blockchain_service: &BlockchainService,
+    external_apis: &ExternalAPIService,
+    contract: &str,
+) -> Result<Option<Arc<VerifiedContract>>> {
+    let address = blockchain_service.resolve_address(contract).await?;
+    let address_key = Address::from_str(&address)?;
+    if let Some(cached) = blockchain_service.cached_verified_contract(address_key) {
+        return Ok(cached);
+    }
+    let verified = external_apis
+        .get_verified_contract(blockchain_service.chain_id(), &address)
+        .await?;
+    Ok(blockchain_service.cache_verified_contract(address_key, verified))
+}
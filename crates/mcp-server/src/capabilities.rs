@@ -0,0 +1,592 @@
+use serde::Serialize;
+use serde_json::{Value, json};
+
+use crate::policy::WRITE_METHODS;
+use crate::tools::{ToolContext, ToolRegistry};
+
+// A server method and the confirmation it needs before running, if any
+struct MethodSpec {
+    name: &'static str,
+    description: &'static str,
+    confirmation: Option<&'static str>,
+}
+
+const fn method(name: &'static str, description: &'static str) -> MethodSpec {
+    MethodSpec {
+        name,
+        description,
+        confirmation: None,
+    }
+}
+
+const fn gated(
+    name: &'static str,
+    description: &'static str,
+    confirmation: &'static str,
+) -> MethodSpec {
+    MethodSpec {
+        name,
+        description,
+        confirmation: Some(confirmation),
+    }
+}
+
+// Every method the server answers; keep in step with `Server::handle_request`
+const METHODS: &[MethodSpec] = &[
+    method(
+        "get_balance",
+        "ETH or token balance of an address, named account or ENS name",
+    ),
+    gated(
+        "send_eth",
+        "Send ETH from a named account, or sign it for export with export: true",
+        "Confirm amount and recipient with the user. Flagged recipients are refused unless override_screening is set after the user accepts the risk.",
+    ),
+    gated(
+        "send_erc20",
+        "Send ERC20 tokens from a named account, or sign it for export with export: true",
+        "Confirm token, amount and recipient with the user. Flagged recipients are refused unless override_screening is set after the user accepts the risk.",
+    ),
+    gated(
+        "send_batch",
+        "Send ETH or a token from a named account to several recipients",
+        "Confirm every recipient and amount, and the total, with the user. Flagged recipients are refused unless override_screening is set after the user accepts the risk.",
+    ),
+    gated(
+        "broadcast_raw_transaction",
+        "Submit an already signed raw transaction",
+        "Confirm with the user that the signed transaction should be submitted now. Flagged recipients are refused unless override_screening is set after the user accepts the risk.",
+    ),
+    gated(
+        "attach_signature",
+        "Attach an offline signature to a prepared transaction and broadcast it",
+        "Confirm with the user that the signed transaction should be submitted now. Flagged recipients are refused unless override_screening is set after the user accepts the risk.",
+    ),
+    gated(
+        "sign_message",
+        "Sign a message with a named account's key (personal_sign)",
+        "Show the user the exact message and which site or person asked for it, and sign only after they agree.",
+    ),
+    gated(
+        "swap_tokens",
+        "Swap tokens on Uniswap V2 or V3",
+        "Show an estimate_swap quote and get the user's confirmation first. Tokens outside the registry with safety risks need acknowledge_risk after the user confirms. Swaps above the price impact limit need accept_price_impact after the user confirms.",
+    ),
+    gated(
+        "add_liquidity",
+        "Deposit two tokens into their Uniswap V2 pair, matching the second amount to the pair's price when it's omitted",
+        "Check the pair with get_pool_info and confirm both amounts and the account with the user.",
+    ),
+    gated(
+        "remove_liquidity",
+        "Withdraw some or all of an account's Uniswap V2 liquidity back into both tokens",
+        "Confirm the pair, the share to withdraw and the account with the user.",
+    ),
+    method(
+        "get_lending_position",
+        "Aave V3 collateral, debt, borrowing power, health factor and supplied or borrowed assets of an address",
+    ),
+    gated(
+        "aave_supply",
+        "Supply a token from a named account to the Aave V3 pool",
+        "Confirm the token, amount and account with the user.",
+    ),
+    gated(
+        "aave_withdraw",
+        "Withdraw some or all of an account's supplied token from the Aave V3 pool",
+        "Check get_lending_position first; withdrawing collateral lowers the health factor. Confirm the token, amount and account with the user.",
+    ),
+    gated(
+        "aave_borrow",
+        "Borrow a token at the variable rate against an account's Aave V3 collateral",
+        "Check get_lending_position first and tell the user how the borrow moves the health factor toward liquidation at 1.0. Confirm the token, amount and account with them.",
+    ),
+    gated(
+        "aave_repay",
+        "Repay some or all of an account's variable rate Aave V3 debt",
+        "Confirm the token, amount and account with the user.",
+    ),
+    gated(
+        "transfer_nft",
+        "Transfer an ERC721 token from a named account",
+        "Confirm the token and recipient with the user. Flagged recipients are refused unless override_screening is set after the user accepts the risk.",
+    ),
+    method(
+        "estimate_swap",
+        "Quote a swap (output, price impact, route, gas) without sending anything",
+    ),
+    method(
+        "estimate_gas",
+        "Gas and fee estimate for an ETH transfer, token transfer or swap",
+    ),
+    method(
+        "get_gas_price",
+        "Current base fee, priority fee percentiles and slow/standard/fast fee recommendations",
+    ),
+    method("get_gas_report", "Gas spent by an account's transactions"),
+    method(
+        "get_portfolio",
+        "Every token balance of an address with USD values and a total",
+    ),
+    method(
+        "get_allowance",
+        "How much of an account's tokens a router or address may spend",
+    ),
+    method(
+        "preview_approval",
+        "Decode an approval before signing it and flag unlimited allowances",
+    ),
+    method(
+        "check_token_safety",
+        "Honeypot, ownership and liquidity checks for a token",
+    ),
+    method(
+        "screen_address",
+        "Check an address against scam and sanction lists",
+    ),
+    method(
+        "check_contract",
+        "Whether a contract is deployed at an address, and the implementation behind it if it is a proxy",
+    ),
+    method(
+        "list_supported_tokens",
+        "Tokens in the registry for the current network",
+    ),
+    method(
+        "refresh_token_list",
+        "Reload the current network's token list from its file",
+    ),
+    method(
+        "forget_discovered_tokens",
+        "Drop cached metadata of tokens discovered on-chain",
+    ),
+    method("get_token_price", "Current token price in USD"),
+    method(
+        "get_historical_balance",
+        "Balance of an account at a past block",
+    ),
+    method("get_historical_price", "Token price at a past block"),
+    method(
+        "get_twap",
+        "Time-weighted average price from a Uniswap pool's oracle",
+    ),
+    method(
+        "get_pool_info",
+        "Reserves, spot price, fee tier and liquidity depth of the Uniswap pools for a token pair",
+    ),
+    method(
+        "get_stablecoin_status",
+        "Chainlink, DEX and API prices of stablecoins and whether any has lost its peg",
+    ),
+    method("get_balance_history", "Balance time series of an account"),
+    method("get_pnl", "Profit and loss of an account's swaps"),
+    method(
+        "get_protocol_health",
+        "TVL and token trends, governance, incidents and exploit news for a DeFi protocol",
+    ),
+    method(
+        "find_yield",
+        "Ranked DefiLlama yield opportunities filtered by chain, asset, TVL and audited protocols",
+    ),
+    method(
+        "list_proposals",
+        "Open proposals on a GovernorBravo governance contract, with their descriptions",
+    ),
+    method(
+        "get_proposal",
+        "A governance proposal's description, state and vote tallies",
+    ),
+    method(
+        "get_voting_power",
+        "An account's governance votes, delegate and vote on a proposal",
+    ),
+    gated(
+        "cast_vote",
+        "Vote for, against or abstain on a governance proposal from a named account",
+        "Confirm the proposal, the vote, the reason and the voting account with the user; votes can't be changed once cast.",
+    ),
+    method(
+        "list_snapshot_spaces",
+        "Snapshot spaces by id, or those an account follows",
+    ),
+    method(
+        "list_snapshot_proposals",
+        "Off-chain proposals in a Snapshot space and their results",
+    ),
+    method(
+        "get_snapshot_proposal",
+        "A Snapshot proposal's description, choices and results",
+    ),
+    method(
+        "get_snapshot_voting_power",
+        "An account's voting power in a Snapshot space and any vote it cast",
+    ),
+    gated(
+        "cast_snapshot_vote",
+        "Sign and submit an off-chain Snapshot vote from a named account",
+        "Confirm the proposal, the choice, the reason and the voting account with the user; the signed vote is published.",
+    ),
+    method(
+        "get_lp_performance",
+        "Impermanent loss, fees earned and net result against holding for Uniswap LP positions",
+    ),
+    method(
+        "summarize_activity",
+        "Summary of an account's recent activity",
+    ),
+    method(
+        "get_transaction_history",
+        "Recent transactions of an account with direction, counterparty and status",
+    ),
+    method(
+        "get_events",
+        "Decoded event logs a contract emitted over a block range",
+    ),
+    method(
+        "convert_units",
+        "Exact conversion between wei, gwei, ether and token units",
+    ),
+    method(
+        "parse_payment_uri",
+        "Read an EIP-681 ethereum: payment URI into send parameters",
+    ),
+    method(
+        "get_tx_status",
+        "Status and confirmations of a transaction, e.g. one returned as submitted or pending, and the result of the request that sent it",
+    ),
+    method(
+        "list_pending_transactions",
+        "Transactions from the namespace's accounts still waiting to confirm, and write requests still running",
+    ),
+    method(
+        "get_chain_info",
+        "Chain ID, latest block number and timestamp, base fee and client version",
+    ),
+    method(
+        "get_block",
+        "A block by number, hash or tag with a summary of its transactions",
+    ),
+    method(
+        "get_transaction",
+        "A transaction by hash with its status, fee, decoded call and events",
+    ),
+    method(
+        "decode_calldata",
+        "Identify and decode the function call in transaction input data",
+    ),
+    method(
+        "get_contract_source",
+        "A contract's verified source, functions and events from Etherscan",
+    ),
+    method(
+        "verify_signature",
+        "Recover the account that signed a personal_sign message",
+    ),
+    method(
+        "prepare_unsigned_transaction",
+        "Build an unsigned transaction for signing on another device",
+    ),
+    method(
+        "pair_wallet",
+        "Pair an external wallet such as MetaMask or Rainbow over WalletConnect; returns a URI to open in the wallet",
+    ),
+    method(
+        "list_wallets",
+        "Paired external wallets, their addresses, chains and connection status",
+    ),
+    gated(
+        "send_with_wallet",
+        "Prepare a transfer or contract call and send it to a paired wallet, which signs and submits it once the user approves there",
+        "Confirm the transaction with the user; they approve it again in their wallet. Flagged recipients are refused unless override_screening is set after the user accepts the risk.",
+    ),
+    method("disconnect_wallet", "End a wallet's WalletConnect session"),
+    method(
+        "call_contract",
+        "Call a view function on any contract and decode the result",
+    ),
+    method(
+        "get_audit_log",
+        "Write operations recorded for the namespace",
+    ),
+    gated(
+        "approve_token",
+        "Set an account's ERC20 allowance for a spender",
+        "Confirm the token, spender and amount with the user; prefer an exact amount over unlimited. Flagged spenders are refused unless override_screening is set, and amounts above the approval cap unless override_approval_cap is set, after the user accepts the risk.",
+    ),
+    gated(
+        "revoke_approval",
+        "Set an account's ERC20 allowance for a spender to zero",
+        "Confirm the token and spender with the user.",
+    ),
+    gated(
+        "write_contract",
+        "Call a state-changing function on any contract from a named account",
+        "Confirm the contract, function, arguments, ETH value and account with the user.",
+    ),
+    method(
+        "get_smart_account",
+        "ERC-4337 smart account of an owner: its counterfactual address, deployment, nonce and balances",
+    ),
+    gated(
+        "deploy_smart_account",
+        "Deploy an owner's ERC-4337 smart account from the owner's own account",
+        "Confirm the owner account and network with the user.",
+    ),
+    method(
+        "estimate_user_operation",
+        "Build a user operation for a smart account call and estimate its gas through the bundler or paymaster",
+    ),
+    gated(
+        "send_user_operation",
+        "Make a call or ETH transfer from an owner's ERC-4337 smart account through the bundler",
+        "Confirm the smart account, target, function, arguments, ETH value and who pays gas with the user, unless sending with a session_key whose scope covers the call. While the smart account has an active session key, calls outside its scope are refused until the user revokes it. Flagged targets are refused unless override_screening is set after the user accepts the risk.",
+    ),
+    gated(
+        "grant_session_key",
+        "Let the assistant send user operations from a smart account to allowed targets, up to an ETH value per operation, until the key expires",
+        "Confirm the smart account, allowed targets, ETH limit and expiry with the user; operations in that scope then run without asking.",
+    ),
+    method(
+        "list_session_keys",
+        "Unexpired session keys and their scopes",
+    ),
+    method(
+        "revoke_session_key",
+        "Revoke a session key before it expires",
+    ),
+    method(
+        "compute_address",
+        "Address a contract will get from CREATE (deployer and nonce) or CREATE2 (salt and init code)",
+    ),
+    gated(
+        "deploy_contract",
+        "Deploy a contract from a Foundry or Hardhat artifact",
+        "Confirm the contract, constructor arguments, deploying account and network with the user.",
+    ),
+    gated(
+        "deploy_token",
+        "Deploy a mintable ERC20 with a given name, symbol, decimals and supply, and register it",
+        "Confirm the name, symbol, decimals, supply, any mints, deploying account and network with the user.",
+    ),
+    method(
+        "check_claims",
+        "Unclaimed airdrop allocations of an address in the configured merkle distributors",
+    ),
+    gated(
+        "claim_airdrop",
+        "Claim a named account's airdrop allocation from a configured distributor",
+        "Confirm the distributor, token, amount and claiming account with the user.",
+    ),
+    method(
+        "list_token_migrations",
+        "Configured old-to-new token migrations on the current network, with an address's old-token balances",
+    ),
+    gated(
+        "migrate_token",
+        "Move an old token into its replacement through the published migration contract",
+        "Call without confirm to get the approval and migration steps, show them to the user, then call again with confirm set after they agree.",
+    ),
+    method(
+        "get_vesting",
+        "Vested, claimable and locked amounts and unlock dates in a VestingWallet or Sablier stream",
+    ),
+    gated(
+        "release_vested",
+        "Release what has vested from a VestingWallet or withdraw it from a Sablier stream",
+        "Confirm the vesting contract, token, amount and sending account with the user.",
+    ),
+    gated(
+        "create_stream",
+        "Stream tokens to a recipient over time with Sablier or a Superfluid flow",
+        "Confirm the protocol, recipient, token, total amount, duration and sending account with the user. Flagged recipients are refused unless override_screening is set after the user accepts the risk.",
+    ),
+    gated(
+        "cancel_stream",
+        "Cancel a payment stream the account sends, refunding what hasn't streamed yet",
+        "Confirm which stream is being cancelled and the sending account with the user.",
+    ),
+    method(
+        "get_streams",
+        "Rate, streamed, withdrawable and remaining amounts of payment streams",
+    ),
+    method(
+        "get_nft_balance",
+        "Number of NFTs an address holds in a collection",
+    ),
+    method("owner_of", "Owner of an NFT"),
+    method(
+        "list_networks",
+        "Networks the server can use and which one is active",
+    ),
+    gated(
+        "switch_network",
+        "Switch the network later requests use",
+        "Tell the user which network balances and transactions will use from now on.",
+    ),
+    method(
+        "search_docs",
+        "Search the blockchain documentation knowledge base",
+    ),
+    method("get_document", "Fetch a document from the knowledge base"),
+    method(
+        "index_status",
+        "Size of the document index and how fast it was last built",
+    ),
+    method("search_web", "Search the web"),
+    method(
+        "self_check",
+        "Check the server's RPC, data files, API keys, accounts and index",
+    ),
+    method(
+        "set_variable",
+        "Store a conversation variable that later requests can reference by name",
+    ),
+    method("unset_variable", "Remove a conversation variable"),
+    method("list_variables", "Variables defined in this conversation"),
+    method(
+        "set_display_preferences",
+        "Decimal places, thousands separators, USD equivalents and gas unit for results",
+    ),
+    method(
+        "get_display_preferences",
+        "How amounts are formatted in this conversation",
+    ),
+    method(
+        "save_template",
+        "Save a named, parameterized sequence of sends and swaps",
+    ),
+    gated(
+        "run_template",
+        "Preview or run a saved template's steps in order",
+        "Call without confirm to simulate the steps, show them to the user, then call again with confirm set after they agree.",
+    ),
+    gated(
+        "consolidate_dust",
+        "Swap an account's small token balances into one asset",
+        "Call without confirm to plan and quote the swaps, show the plan to the user, then call again with confirm set after they agree.",
+    ),
+    method("list_templates", "Templates saved in the namespace"),
+    method("delete_template", "Delete a saved template"),
+    gated(
+        "schedule_job",
+        "Queue a transaction or template to run later, optionally only below a gas price",
+        "Confirm the operation, its timing and any gas limit with the user; the job runs without asking again.",
+    ),
+    gated(
+        "add_top_up_rule",
+        "Keep an account's ETH above a minimum by topping it up from another account as blocks arrive, within per-top-up and daily caps",
+        "Confirm both accounts, the minimum and target balances and the caps with the user; top-ups then run without asking.",
+    ),
+    method(
+        "list_top_up_rules",
+        "Top-up rules with their recent top-ups and any errors",
+    ),
+    method("remove_top_up_rule", "Stop a top-up rule"),
+    method("list_jobs", "Scheduled jobs and their status"),
+    method("cancel_job", "Cancel a scheduled job that hasn't run"),
+    method(
+        "get_notifications",
+        "Deferrals, results and expiries of scheduled jobs",
+    ),
+    method(
+        "get_inbox",
+        "Transfers, approvals and balance changes on the namespace's accounts, and stablecoin depegs, since the last check",
+    ),
+    method(
+        "snapshot_chain",
+        "Checkpoint a local dev chain's state so it can be rolled back",
+    ),
+    gated(
+        "revert_chain",
+        "Roll a local dev chain back to a snapshot",
+        "Confirm with the user: every transaction since the snapshot is undone, and the snapshot can't be reused.",
+    ),
+    method(
+        "increase_time",
+        "Advance a local dev chain's clock by a number of seconds",
+    ),
+    method("mine_blocks", "Mine empty blocks on a local dev chain"),
+    method(
+        "set_next_block_timestamp",
+        "Set the timestamp of a local dev chain's next block",
+    ),
+    method(
+        "fund_account",
+        "Give an account ETH or ERC20 tokens on a local dev chain by rewriting its state",
+    ),
+    method("tools/list", "List these capabilities"),
+];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Capability {
+    pub name: String,
+    pub description: String,
+    /// "write" for methods that sign and send transactions, otherwise "read"
+    pub access: &'static str,
+    pub requires_confirmation: bool,
+    /// What to confirm with the user, or which flag unlocks the call
+    pub confirmation: Option<String>,
+}
+
+/// The methods this server exposes, how they are gated, and the network and accounts
+/// they act on for the caller's namespace
+pub fn tools_list(tool_registry: &ToolRegistry, context: &ToolContext) -> Value {
+    let access = |name: &str| {
+        if WRITE_METHODS.contains(&name) {
+            "write"
+        } else {
+            "read"
+        }
+    };
+
+    let mut tools: Vec<Capability> = METHODS
+        .iter()
+        .map(|spec| Capability {
+            name: spec.name.to_string(),
+            description: spec.description.to_string(),
+            access: access(spec.name),
+            requires_confirmation: spec.confirmation.is_some(),
+            confirmation: spec.confirmation.map(|text| text.to_string()),
+        })
+        .collect();
+
+    // Registered tools the table above doesn't know about yet
+    for tool in tool_registry.tools() {
+        if !METHODS.iter().any(|spec| spec.name == tool.name()) {
+            tools.push(Capability {
+                name: tool.name().to_string(),
+                description: tool.description().to_string(),
+                access: access(tool.name()),
+                requires_confirmation: WRITE_METHODS.contains(&tool.name()),
+                confirmation: None,
+            });
+        }
+    }
+
+    let accounts = &context.namespace.accounts;
+    let accounts: Vec<Value> = accounts
+        .names()
+        .into_iter()
+        .filter_map(|name| {
+            accounts
+                .get(&name)
+                .map(|account| json!({"name": name, "address": account.address}))
+        })
+        .collect();
+
+    let network = context
+        .namespace
+        .network()
+        .unwrap_or_else(|| context.networks.default_network().to_string());
+
+    json!({
+        "tools": tools,
+        "network": {
+            "name": network,
+            "chain_id": context.blockchain_service.chain_id(),
+        },
+        "namespace": context.namespace.name,
+        "accounts": accounts,
+        "submission": "Write requests answer with status \"submitted\" and a hash once their first transaction is sent; pass wait: true to get the final result instead.",
+    })
+}
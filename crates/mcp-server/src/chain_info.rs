@@ -0,0 +1,158 @@
+use anyhow::{Result, anyhow};
+use ethers::providers::Middleware;
+use ethers::types::{BlockId, BlockNumber, H256};
+use ethers::utils::format_units;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+use crate::blockchain::BlockchainService;
+
+/// Most transactions listed for a block unless asked for more
+pub const DEFAULT_BLOCK_TRANSACTIONS: usize = 25;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChainInfo {
+    pub chain_id: u64,
+    /// Chain the RPC endpoint reports; a mismatch means it serves another network
+    pub rpc_chain_id: u64,
+    pub latest_block: u64,
+    pub latest_block_timestamp: i64,
+    /// Seconds since the latest block was produced
+    pub latest_block_age_secs: i64,
+    /// None before London or on chains without EIP-1559
+    pub base_fee_gwei: Option<String>,
+    pub client_version: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlockTransaction {
+    pub hash: String,
+    pub from: String,
+    /// None for contract deployments
+    pub to: Option<String>,
+    pub value_eth: String,
+    /// Function called, when a loaded ABI knows the selector; otherwise the selector
+    pub method: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlockDetails {
+    pub number: Option<u64>,
+    pub hash: Option<String>,
+    pub parent_hash: String,
+    pub timestamp: i64,
+    /// Fee recipient
+    pub miner: Option<String>,
+    pub gas_used: u64,
+    pub gas_limit: u64,
+    pub base_fee_gwei: Option<String>,
+    pub transaction_count: usize,
+    /// The first transactions in the block, up to the requested limit
+    pub transactions: Vec<BlockTransaction>,
+}
+
+/// Chain ID, the latest block and the client behind the RPC endpoint
+pub async fn get_chain_info(blockchain_service: &BlockchainService) -> Result<ChainInfo> {
+    let provider = blockchain_service.provider();
+    let (rpc_chain_id, block, client_version) = tokio::join!(
+        provider.get_chainid(),
+        provider.get_block(BlockNumber::Latest),
+        provider.client_version(),
+    );
+    let block = block?.ok_or_else(|| anyhow!("The node returned no latest block"))?;
+    let timestamp = block.timestamp.as_u64() as i64;
+
+    Ok(ChainInfo {
+        chain_id: blockchain_service.chain_id(),
+        rpc_chain_id: rpc_chain_id?.as_u64(),
+        latest_block: block
+            .number
+            .ok_or_else(|| anyhow!("Latest block has no number"))?
+            .as_u64(),
+        latest_block_timestamp: timestamp,
+        latest_block_age_secs: chrono::Utc::now().timestamp() - timestamp,
+        base_fee_gwei: block
+            .base_fee_per_gas
+            .map(|fee| format_units(fee, "gwei"))
+            .transpose()?,
+        // Some providers don't expose web3_clientVersion
+        client_version: client_version.ok(),
+    })
+}
+
+// A block number (decimal or hex), a block hash, or a tag such as "latest"
+fn parse_block_id(block: &str) -> Result<BlockId> {
+    let block = block.trim();
+    if block.len() == 66 && block.starts_with("0x") {
+        return H256::from_str(block)
+            .map(BlockId::Hash)
+            .map_err(|_| anyhow!("Invalid block hash '{}'", block));
+    }
+    let number = match block.to_lowercase().as_str() {
+        "latest" => BlockNumber::Latest,
+        "pending" => BlockNumber::Pending,
+        "safe" => BlockNumber::Safe,
+        "finalized" => BlockNumber::Finalized,
+        "earliest" => BlockNumber::Earliest,
+        hex if hex.starts_with("0x") => u64::from_str_radix(&hex[2..], 16)
+            .map(|number| BlockNumber::Number(number.into()))
+            .map_err(|_| anyhow!("Invalid block number '{}'", block))?,
+        decimal => decimal
+            .parse::<u64>()
+            .map(|number| BlockNumber::Number(number.into()))
+            .map_err(|_| anyhow!("Invalid block '{}': use a number, hash or latest", block))?,
+    };
+    Ok(BlockId::Number(number))
+}
+
+/// A block's header and a summary of its first `limit` transactions
+pub async fn get_block(
+    blockchain_service: &BlockchainService,
+    block: &str,
+    limit: usize,
+) -> Result<BlockDetails> {
+    let block = blockchain_service
+        .provider()
+        .get_block_with_txs(parse_block_id(block)?)
+        .await?
+        .ok_or_else(|| anyhow!("Block {} not found on this network", block))?;
+
+    let transactions = block
+        .transactions
+        .iter()
+        .take(limit)
+        .map(|tx| {
+            let method = tx.input.get(..4).map(|selector| {
+                let selector: [u8; 4] = selector.try_into().expect("four-byte selector");
+                blockchain_service
+                    .find_functions_by_selector(selector)
+                    .first()
+                    .map(|function| function.name.clone())
+                    .unwrap_or_else(|| format!("0x{}", hex::encode(selector)))
+            });
+            Ok(BlockTransaction {
+                hash: format!("{:?}", tx.hash),
+                from: format!("{:?}", tx.from),
+                to: tx.to.map(|to| format!("{:?}", to)),
+                value_eth: format_units(tx.value, "ether")?,
+                method: method.filter(|_| tx.to.is_some()),
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(BlockDetails {
+        number: block.number.map(|number| number.as_u64()),
+        hash: block.hash.map(|hash| format!("{:?}", hash)),
+        parent_hash: format!("{:?}", block.parent_hash),
+        timestamp: block.timestamp.as_u64() as i64,
+        miner: block.author.map(|miner| format!("{:?}", miner)),
+        gas_used: block.gas_used.as_u64(),
+        gas_limit: block.gas_limit.as_u64(),
+        base_fee_gwei: block
+            .base_fee_per_gas
+            .map(|fee| format_units(fee, "gwei"))
+            .transpose()?,
+        transaction_count: block.transactions.len(),
+        transactions,
+    })
+}
@@ -0,0 +1,180 @@
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use ethers::abi::parse_abi;
+use ethers::contract::Contract;
+use ethers::providers::Provider;
+use ethers::types::{Address, I256, U256};
+use ethers::utils::format_units;
+use std::collections::HashMap;
+use std::str::FromStr;
+use tracing::warn;
+
+use crate::blockchain::BlockchainService;
+use crate::failover::FailoverClient;
+use crate::prices::PriceProvider;
+
+// Mainnet Feed Registry, which maps (token, USD) to its aggregator
+const FEED_REGISTRY: &str = "0x47Fb2585D2C56Fe188D0E6ec628a38b74fCeeeDf";
+// Chainlink's denomination address for USD
+const USD_DENOMINATION: &str = "0x0000000000000000000000000000000000000348";
+// Feeds found through the registry are assumed to update at least daily
+const DEFAULT_HEARTBEAT: u64 = 24 * 3600;
+// Extra time a feed may lag its heartbeat before its answer counts as stale
+const STALENESS_GRACE: u64 = 600;
+
+const AGGREGATOR_ABI: &[&str] = &[
+    "function decimals() view returns (uint8)",
+    "function latestRoundData() view returns (uint80 roundId, int256 answer, uint256 startedAt, uint256 updatedAt, uint80 answeredInRound)",
+];
+const REGISTRY_ABI: &[&str] = &[
+    "function decimals(address base, address quote) view returns (uint8)",
+    "function latestRoundData(address base, address quote) view returns (uint80 roundId, int256 answer, uint256 startedAt, uint256 updatedAt, uint80 answeredInRound)",
+];
+
+// A USD aggregator for a token: (token, aggregator, heartbeat in seconds)
+type Feed = (&'static str, &'static str, u64);
+
+const MAINNET_FEEDS: &[Feed] = &[
+    // WETH priced by ETH / USD
+    (
+        "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2",
+        "0x5f4eC3Df9cbd43714FE2740f5E3616155c5b8419",
+        3600,
+    ),
+    (
+        "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48",
+        "0x8fFfFfd4AfB6115b954Bd326cbe7B4BA576818f6",
+        86400,
+    ),
+    (
+        "0xdAC17F958D2ee523a2206206994597C13D831ec7",
+        "0x3E7d1eAB13ad0104d2750B8863b489D65364e32D",
+        86400,
+    ),
+    (
+        "0x6B175474E89094C44Da98b954EedeAC495271d0F",
+        "0xAed0c38402a5d19df6E4c03F4E2DceD6e29c1ee9",
+        3600,
+    ),
+    (
+        "0x514910771AF9Ca656af840dff83E8264EcF986CA",
+        "0x2c1d072e956AFFC0D435Cb7AC38EF18d24d9127c",
+        3600,
+    ),
+];
+const ARBITRUM_FEEDS: &[Feed] = &[(
+    "0x82aF49447D8a07e3bd95BD0d56f35241523fBab1",
+    "0x639Fe6ab55C921f74e7fac1ee960C0B6293ba612",
+    86400,
+)];
+const BASE_FEEDS: &[Feed] = &[(
+    "0x4200000000000000000000000000000000000006",
+    "0x71041dddad3595F9CEd3DcCFBe3D1F4b0a16Bb70",
+    1200,
+)];
+
+// Known feeds and whether the Feed Registry is deployed, by chain; Anvil forks mainnet
+fn chain_feeds(chain_id: u64) -> (&'static [Feed], bool) {
+    match chain_id {
+        1 | 31337 => (MAINNET_FEEDS, true),
+        42161 => (ARBITRUM_FEEDS, false),
+        8453 => (BASE_FEEDS, false),
+        _ => (&[], false),
+    }
+}
+
+/// On-chain USD prices from Chainlink aggregators: the known feed for a token, or on
+/// mainnet whatever the Feed Registry has. Stale or non-positive answers are ignored.
+pub struct ChainlinkPrices<'a> {
+    blockchain_service: &'a BlockchainService,
+}
+
+impl<'a> ChainlinkPrices<'a> {
+    pub fn new(blockchain_service: &'a BlockchainService) -> Self {
+        Self { blockchain_service }
+    }
+
+    fn contract(&self, address: &str, abi: &[&str]) -> Result<Contract<Provider<FailoverClient>>> {
+        Ok(Contract::new(
+            Address::from_str(address)?,
+            parse_abi(abi)?,
+            self.blockchain_service.provider(),
+        ))
+    }
+
+    /// USD price of a token, None when no feed covers it
+    pub async fn usd_price(&self, token_address: &str) -> Result<Option<f64>> {
+        let (feeds, has_registry) = chain_feeds(self.blockchain_service.chain_id());
+        let feed = feeds
+            .iter()
+            .find(|(token, _, _)| token.eq_ignore_ascii_case(token_address));
+
+        let (round, decimals, heartbeat) = match feed {
+            Some((_, aggregator, heartbeat)) => {
+                let aggregator = self.contract(aggregator, AGGREGATOR_ABI)?;
+                let decimals: u8 = aggregator.method::<_, u8>("decimals", ())?.call().await?;
+                let round: (u128, I256, U256, U256, u128) = aggregator
+                    .method::<_, (u128, I256, U256, U256, u128)>("latestRoundData", ())?
+                    .call()
+                    .await?;
+                (round, decimals, *heartbeat)
+            }
+            None if has_registry => {
+                let registry = self.contract(FEED_REGISTRY, REGISTRY_ABI)?;
+                let pair = (
+                    Address::from_str(token_address)?,
+                    Address::from_str(USD_DENOMINATION)?,
+                );
+                // The registry reverts for pairs without a feed
+                let Ok(round) = registry
+                    .method::<_, (u128, I256, U256, U256, u128)>("latestRoundData", pair)?
+                    .call()
+                    .await
+                else {
+                    return Ok(None);
+                };
+                let decimals: u8 = registry.method::<_, u8>("decimals", pair)?.call().await?;
+                (round, decimals, DEFAULT_HEARTBEAT)
+            }
+            None => return Ok(None),
+        };
+
+        let (round_id, answer, _, updated_at, answered_in_round) = round;
+        if answer <= I256::zero() {
+            return Err(anyhow!("Chainlink feed for {} has no price", token_address));
+        }
+        let age = (chrono::Utc::now().timestamp() as u64).saturating_sub(updated_at.as_u64());
+        if answered_in_round < round_id || age > heartbeat + STALENESS_GRACE {
+            return Err(anyhow!(
+                "Chainlink feed for {} is stale (updated {}s ago)",
+                token_address,
+                age
+            ));
+        }
+
+        Ok(Some(
+            format_units(answer.into_raw(), decimals as u32)?.parse()?,
+        ))
+    }
+}
+
+#[async_trait]
+impl PriceProvider for ChainlinkPrices<'_> {
+    fn name(&self) -> &'static str {
+        "chainlink"
+    }
+
+    async fn usd_prices(&self, token_addresses: &[String]) -> Result<HashMap<String, f64>> {
+        let mut prices = HashMap::new();
+        for address in token_addresses {
+            match self.usd_price(address).await {
+                Ok(Some(price)) => {
+                    prices.insert(address.to_lowercase(), price);
+                }
+                Ok(None) => {}
+                Err(e) => warn!("{}", e),
+            }
+        }
+        Ok(prices)
+    }
+}
@@ -0,0 +1,352 @@
+use anyhow::{Result, anyhow};
+use ethers::abi::{Abi, parse_abi};
+use ethers::contract::Contract;
+use ethers::types::{Address, H256, U256};
+use ethers::utils::{format_units, keccak256, to_checksum};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use shared::{Account, ContractCall, TransactionResult};
+use std::str::FromStr;
+use tracing::warn;
+
+use crate::blockchain::{BlockchainService, FeeOverrides};
+use crate::external_apis::ExternalAPIService;
+
+const DISTRIBUTOR_ABI: &[&str] = &[
+    "function token() view returns (address)",
+    "function merkleRoot() view returns (bytes32)",
+    "function isClaimed(uint256 index) view returns (bool)",
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClaimSource {
+    pub name: String,
+    pub chain_id: u64,
+    /// MerkleDistributor contract holding the allocations
+    pub distributor: String,
+    /// Merkle tree JSON in the Uniswap distributor format ({"claims": {address: {index, amount, proof}}})
+    #[serde(default)]
+    pub proofs_file: Option<String>,
+    /// API returning an address's {index, amount, proof}; "{address}" is replaced with it
+    #[serde(default)]
+    pub proofs_url: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ClaimsConfig {
+    pub sources: Vec<ClaimSource>,
+}
+
+impl ClaimsConfig {
+    /// Claim sources listed in the JSON file at `CLAIMS_FILE`, if any
+    pub fn from_env() -> Self {
+        let sources = match std::env::var("CLAIMS_FILE") {
+            Ok(path) => match std::fs::read_to_string(&path)
+                .map_err(anyhow::Error::from)
+                .and_then(|content| Ok(serde_json::from_str::<Vec<ClaimSource>>(&content)?))
+            {
+                Ok(sources) => sources,
+                Err(e) => {
+                    warn!("Ignoring claims file {}: {}", path, e);
+                    Vec::new()
+                }
+            },
+            Err(_) => Vec::new(),
+        };
+        Self { sources }
+    }
+
+    fn for_chain(&self, chain_id: u64) -> impl Iterator<Item = &ClaimSource> {
+        self.sources
+            .iter()
+            .filter(move |source| source.chain_id == chain_id)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClaimStatus {
+    pub source: String,
+    pub distributor: String,
+    pub token: Option<String>,
+    pub symbol: Option<String>,
+    pub amount: String,
+    pub index: u64,
+    pub claimed: bool,
+    /// Whether `claim_airdrop` would succeed: an unclaimed allocation whose proof matches
+    /// the distributor's merkle root
+    pub claimable: bool,
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClaimCheck {
+    pub address: String,
+    pub chain_id: u64,
+    /// Sources the address has an allocation in
+    pub allocations: Vec<ClaimStatus>,
+    /// Sources searched without finding an allocation
+    pub checked: Vec<String>,
+    /// Sources that couldn't be checked, with why
+    pub errors: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClaimResult {
+    pub transaction: TransactionResult,
+    pub source: String,
+    pub token: String,
+    pub amount: String,
+}
+
+// An address's leaf in a distributor's merkle tree
+struct Allocation {
+    index: U256,
+    amount: U256,
+    proof: Vec<H256>,
+}
+
+fn parse_amount(value: &Value) -> Option<U256> {
+    match value {
+        Value::String(text) if text.starts_with("0x") => U256::from_str(text).ok(),
+        Value::String(text) => U256::from_dec_str(text).ok(),
+        Value::Number(number) => number.as_u64().map(U256::from),
+        _ => None,
+    }
+}
+
+fn parse_allocation(entry: &Value) -> Result<Allocation> {
+    let index = parse_amount(&entry["index"]).ok_or_else(|| anyhow!("allocation has no index"))?;
+    let amount =
+        parse_amount(&entry["amount"]).ok_or_else(|| anyhow!("allocation has no amount"))?;
+    let proof = entry["proof"]
+        .as_array()
+        .ok_or_else(|| anyhow!("allocation has no proof"))?
+        .iter()
+        .map(|node| {
+            node.as_str()
+                .and_then(|node| H256::from_str(node).ok())
+                .ok_or_else(|| anyhow!("invalid proof node {}", node))
+        })
+        .collect::<Result<Vec<H256>>>()?;
+    Ok(Allocation {
+        index,
+        amount,
+        proof,
+    })
+}
+
+// The address's allocation from the source's proofs file or API, None when it has none
+async fn find_allocation(
+    external_apis: &ExternalAPIService,
+    source: &ClaimSource,
+    address: Address,
+) -> Result<Option<Allocation>> {
+    if let Some(path) = &source.proofs_file {
+        let tree: Value = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        let claims = tree["claims"]
+            .as_object()
+            .ok_or_else(|| anyhow!("{} has no claims object", path))?;
+        return claims
+            .iter()
+            .find(|(claimant, _)| {
+                Address::from_str(claimant).is_ok_and(|claimant| claimant == address)
+            })
+            .map(|(_, entry)| parse_allocation(entry))
+            .transpose();
+    }
+    if let Some(url) = &source.proofs_url {
+        let url = url.replace("{address}", &to_checksum(&address, None));
+        return match external_apis.get_claim_proof(&url).await? {
+            Some(entry) => parse_allocation(&entry).map(Some),
+            None => Ok(None),
+        };
+    }
+    Err(anyhow!("has neither proofs_file nor proofs_url"))
+}
+
+// Whether the proof leads from the address's leaf to `root`, as MerkleProof.verify
+// checks it: keccak256(abi.encodePacked(index, account, amount)) hashed up in sorted pairs
+fn verify_proof(root: H256, address: Address, allocation: &Allocation) -> bool {
+    let mut leaf = Vec::with_capacity(84);
+    let mut word = [0u8; 32];
+    allocation.index.to_big_endian(&mut word);
+    leaf.extend_from_slice(&word);
+    leaf.extend_from_slice(address.as_bytes());
+    allocation.amount.to_big_endian(&mut word);
+    leaf.extend_from_slice(&word);
+
+    let mut hash = keccak256(&leaf);
+    for node in &allocation.proof {
+        let node = node.to_fixed_bytes();
+        hash = if hash <= node {
+            keccak256([hash, node].concat())
+        } else {
+            keccak256([node, hash].concat())
+        };
+    }
+    H256(hash) == root
+}
+
+async fn status(
+    blockchain_service: &BlockchainService,
+    external_apis: &ExternalAPIService,
+    source: &ClaimSource,
+    address: Address,
+) -> Result<Option<(ClaimStatus, Allocation)>> {
+    let Some(allocation) = find_allocation(external_apis, source, address).await? else {
+        return Ok(None);
+    };
+    let distributor_address = Address::from_str(&source.distributor)
+        .map_err(|_| anyhow!("invalid distributor address {}", source.distributor))?;
+    let abi: Abi = parse_abi(DISTRIBUTOR_ABI)?;
+    let distributor = Contract::new(distributor_address, abi, blockchain_service.provider());
+
+    let claimed: bool = distributor
+        .method("isClaimed", allocation.index)?
+        .call()
+        .await?;
+    let root: H256 = distributor
+        .method::<_, [u8; 32]>("merkleRoot", ())?
+        .call()
+        .await
+        .map(H256)?;
+    let proof_valid = verify_proof(root, address, &allocation);
+    let token: Option<Address> = distributor.method("token", ())?.call().await.ok();
+    let token_info = match token {
+        Some(token) => blockchain_service
+            .resolve_token(&format!("{:?}", token))
+            .await
+            .ok(),
+        None => None,
+    };
+
+    let note = if claimed {
+        Some("Already claimed".to_string())
+    } else if !proof_valid {
+        Some("The proof doesn't match the distributor's merkle root; the proofs source may be out of date".to_string())
+    } else {
+        None
+    };
+    Ok(Some((
+        ClaimStatus {
+            source: source.name.clone(),
+            distributor: source.distributor.clone(),
+            token: token.map(|token| format!("{:?}", token)),
+            symbol: token_info.as_ref().map(|info| info.symbol.clone()),
+            amount: match &token_info {
+                Some(info) => format_units(allocation.amount, info.decimals as u32)?,
+                None => allocation.amount.to_string(),
+            },
+            index: allocation.index.as_u64(),
+            claimed,
+            claimable: !claimed && proof_valid,
+            note,
+        },
+        allocation,
+    )))
+}
+
+/// Look for unclaimed allocations of `address` in every configured merkle distributor
+/// on the current chain. A source that fails is reported in `errors` without stopping
+/// the others.
+pub async fn check_claims(
+    blockchain_service: &BlockchainService,
+    external_apis: &ExternalAPIService,
+    config: &ClaimsConfig,
+    address: &str,
+) -> Result<ClaimCheck> {
+    let address =
+        Address::from_str(address).map_err(|_| anyhow!("Invalid address: {}", address))?;
+    let chain_id = blockchain_service.chain_id();
+    let mut check = ClaimCheck {
+        address: to_checksum(&address, None),
+        chain_id,
+        allocations: Vec::new(),
+        checked: Vec::new(),
+        errors: Vec::new(),
+    };
+
+    for source in config.for_chain(chain_id) {
+        match status(blockchain_service, external_apis, source, address).await {
+            Ok(Some((status, _))) => check.allocations.push(status),
+            Ok(None) => check.checked.push(source.name.clone()),
+            Err(e) => check.errors.push(format!("{}: {}", source.name, e)),
+        }
+    }
+    if config.for_chain(chain_id).next().is_none() {
+        check.errors.push(format!(
+            "No claim sources are configured for chain {}; list distributors in CLAIMS_FILE",
+            chain_id
+        ));
+    }
+    Ok(check)
+}
+
+/// Claim a named account's allocation from a configured distributor. Refused when it
+/// was already claimed or its proof doesn't match the distributor's root.
+pub async fn claim(
+    blockchain_service: &BlockchainService,
+    external_apis: &ExternalAPIService,
+    config: &ClaimsConfig,
+    from_account: &Account,
+    source_name: &str,
+    fees: &FeeOverrides,
+) -> Result<ClaimResult> {
+    let chain_id = blockchain_service.chain_id();
+    let source = config
+        .for_chain(chain_id)
+        .find(|source| source.name.eq_ignore_ascii_case(source_name.trim()))
+        .ok_or_else(|| {
+            anyhow!(
+                "No claim source called {} on chain {}",
+                source_name,
+                chain_id
+            )
+        })?;
+    let address = Address::from_str(&from_account.address)?;
+    let (status, allocation) = status(blockchain_service, external_apis, source, address)
+        .await?
+        .ok_or_else(|| anyhow!("{} has no allocation in {}", from_account.name, source.name))?;
+    if !status.claimable {
+        return Err(anyhow!(
+            "{}'s {} allocation can't be claimed: {}",
+            from_account.name,
+            source.name,
+            status.note.unwrap_or_default()
+        ));
+    }
+
+    let proof: Vec<String> = allocation
+        .proof
+        .iter()
+        .map(|node| format!("{:?}", node))
+        .collect();
+    let call = ContractCall {
+        contract_address: source.distributor.clone(),
+        function_signature: "claim(uint256,address,uint256,bytes32[])".to_string(),
+        parameters: vec![
+            allocation.index.to_string(),
+            format!("{:?}", address),
+            allocation.amount.to_string(),
+            format!("[{}]", proof.join(",")),
+        ],
+        from: None,
+    };
+    let transaction = blockchain_service
+        .write_contract(
+            from_account,
+            &call,
+            "0",
+            None,
+            &|name| name.to_string(),
+            fees,
+        )
+        .await?;
+
+    Ok(ClaimResult {
+        transaction,
+        source: source.name.clone(),
+        token: status.symbol.or(status.token).unwrap_or_default(),
+        amount: status.amount,
+    })
+}
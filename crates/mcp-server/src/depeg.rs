@@ -0,0 +1,231 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::blockchain::BlockchainService;
+use crate::chainlink::ChainlinkPrices;
+use crate::external_apis::ExternalAPIService;
+use crate::inbox::InboxStore;
+use crate::prices::PriceProvider;
+use crate::tenants::TenantRegistry;
+use crate::twap;
+
+// DEX prices are averaged over this window so a single swap can't trip the alert
+const DEX_TWAP_WINDOW: u64 = 600;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DepegConfig {
+    /// Symbols or addresses of the stablecoins to watch; empty disables the monitor
+    pub stablecoins: Vec<String>,
+    /// How far from $1, in percent, a price may drift before it counts as a depeg
+    pub threshold_percent: f64,
+    pub interval_secs: u64,
+}
+
+impl Default for DepegConfig {
+    fn default() -> Self {
+        Self {
+            stablecoins: vec!["USDC".to_string(), "USDT".to_string(), "DAI".to_string()],
+            threshold_percent: 0.5,
+            interval_secs: 300,
+        }
+    }
+}
+
+impl DepegConfig {
+    /// Read the monitor settings from `DEPEG_*` environment variables
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+
+        Self {
+            stablecoins: std::env::var("DEPEG_STABLECOINS")
+                .map(|v| {
+                    v.split(',')
+                        .map(|symbol| symbol.trim().to_string())
+                        .filter(|symbol| !symbol.is_empty())
+                        .collect()
+                })
+                .unwrap_or(defaults.stablecoins),
+            threshold_percent: std::env::var("DEPEG_THRESHOLD_PERCENT")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(defaults.threshold_percent),
+            interval_secs: std::env::var("DEPEG_INTERVAL_SECS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(defaults.interval_secs),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StablecoinStatus {
+    pub symbol: String,
+    pub address: String,
+    /// USD price by source: "chainlink", "dex" and "defillama", whichever answered
+    pub sources: BTreeMap<String, f64>,
+    /// Median of the sources, None when none of them could price the token
+    pub price: Option<f64>,
+    pub deviation_percent: Option<f64>,
+    pub depegged: bool,
+}
+
+/// Price every configured stablecoin from Chainlink, a Uniswap TWAP against WETH and
+/// DefiLlama. A coin counts as depegged when the median of its sources is further
+/// than the threshold from $1, so one bad source alone can't trigger it.
+pub async fn check_stablecoins(
+    blockchain_service: &BlockchainService,
+    external_apis: &ExternalAPIService,
+    config: &DepegConfig,
+) -> Vec<StablecoinStatus> {
+    let chainlink = ChainlinkPrices::new(blockchain_service);
+    let weth = blockchain_service.router_config().weth.clone();
+    let eth_usd = chainlink.usd_price(&weth).await.ok().flatten();
+
+    let mut statuses = Vec::new();
+    for identifier in &config.stablecoins {
+        let token = match blockchain_service.resolve_token(identifier).await {
+            Ok(token) => token,
+            Err(e) => {
+                warn!("Cannot watch stablecoin {}: {}", identifier, e);
+                continue;
+            }
+        };
+
+        let mut sources = BTreeMap::new();
+        match chainlink.usd_price(&token.address).await {
+            Ok(Some(price)) => {
+                sources.insert(chainlink.name().to_string(), price);
+            }
+            Ok(None) => {}
+            Err(e) => warn!("{}", e),
+        }
+        if let Some(eth_usd) = eth_usd {
+            match twap::get_twap(
+                blockchain_service,
+                &token.address,
+                &weth,
+                DEX_TWAP_WINDOW,
+                None,
+                None,
+            )
+            .await
+            {
+                Ok(result) => {
+                    sources.insert("dex".to_string(), result.twap * eth_usd);
+                }
+                Err(e) => warn!("No DEX price for {}: {}", token.symbol, e),
+            }
+        }
+        match external_apis
+            .usd_prices(std::slice::from_ref(&token.address))
+            .await
+        {
+            Ok(prices) => {
+                if let Some(price) = prices.get(&token.address.to_lowercase()) {
+                    sources.insert(external_apis.name().to_string(), *price);
+                }
+            }
+            Err(e) => warn!("{} prices unavailable: {}", external_apis.name(), e),
+        }
+
+        let price = median(sources.values().copied().collect());
+        let deviation_percent = price.map(|price| (price - 1.0) * 100.0);
+        statuses.push(StablecoinStatus {
+            symbol: token.symbol.clone(),
+            address: token.address.clone(),
+            sources,
+            price,
+            deviation_percent,
+            depegged: deviation_percent
+                .is_some_and(|deviation| deviation.abs() > config.threshold_percent),
+        });
+    }
+    statuses
+}
+
+fn median(mut values: Vec<f64>) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_by(|a, b| a.total_cmp(b));
+    let middle = values.len() / 2;
+    Some(if values.len().is_multiple_of(2) {
+        (values[middle - 1] + values[middle]) / 2.0
+    } else {
+        values[middle]
+    })
+}
+
+/// Built-in alert that re-checks the configured stablecoins every `interval_secs` and
+/// posts a "depeg" item to every namespace's inbox when one loses its peg, and another
+/// once it recovers
+pub fn spawn_depeg_monitor(
+    blockchain_service: Arc<BlockchainService>,
+    external_apis: Arc<ExternalAPIService>,
+    tenants: Arc<TenantRegistry>,
+    inbox: Arc<InboxStore>,
+    config: DepegConfig,
+) {
+    if config.stablecoins.is_empty() || config.interval_secs == 0 {
+        return;
+    }
+    info!(
+        "Watching {} for depegs beyond {}% every {}s",
+        config.stablecoins.join(", "),
+        config.threshold_percent,
+        config.interval_secs
+    );
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(Duration::from_secs(config.interval_secs));
+        // Coins currently off their peg, so each depeg is reported once
+        let mut depegged: HashSet<String> = HashSet::new();
+        loop {
+            ticker.tick().await;
+
+            for status in check_stablecoins(&blockchain_service, &external_apis, &config).await {
+                let (Some(price), Some(deviation)) = (status.price, status.deviation_percent)
+                else {
+                    continue;
+                };
+                let message = if status.depegged && depegged.insert(status.symbol.clone()) {
+                    format!(
+                        "{} has lost its peg: ${:.4} ({:+.2}%), sources {}",
+                        status.symbol,
+                        price,
+                        deviation,
+                        describe_sources(&status)
+                    )
+                } else if !status.depegged && depegged.remove(&status.symbol) {
+                    format!("{} is back at its peg: ${:.4}", status.symbol, price)
+                } else {
+                    continue;
+                };
+
+                warn!("{}", message);
+                if let Err(e) = inbox.record_for_all(
+                    &tenants,
+                    &status.symbol,
+                    &status.address,
+                    "depeg",
+                    &message,
+                ) {
+                    warn!("Failed to record depeg of {}: {}", status.symbol, e);
+                }
+            }
+        }
+    });
+}
+
+fn describe_sources(status: &StablecoinStatus) -> String {
+    status
+        .sources
+        .iter()
+        .map(|(source, price)| format!("{} ${:.4}", source, price))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
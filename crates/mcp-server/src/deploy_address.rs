@@ -0,0 +1,132 @@
+use anyhow::{Result, anyhow};
+use ethers::providers::Middleware;
+use ethers::types::{Address, BlockNumber, Bytes, H256, U256};
+use ethers::utils::{get_contract_address, get_create2_address_from_hash, keccak256, to_checksum};
+use serde::{Deserialize, Serialize};
+
+use crate::blockchain::BlockchainService;
+
+/// The deterministic deployment proxy: a call with `salt ++ init_code` CREATE2-deploys
+/// the init code, so a contract lands at the same address on every chain that has it.
+/// Anvil and Hardhat include it; on other chains it was deployed from a presigned transaction.
+pub const CREATE2_DEPLOYER: &str = "0x4e59b44847b379578588920cA78FbF26c0B4956C";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeployScheme {
+    /// Address from the deployer and its nonce
+    Create,
+    /// Address from the deployer, a salt and the init code, independent of nonces
+    Create2,
+}
+
+/// Where a contract will be (or was) deployed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ComputedAddress {
+    pub scheme: DeployScheme,
+    pub address: String,
+    pub deployer: String,
+    pub nonce: Option<u64>,
+    pub salt: Option<String>,
+    pub init_code_hash: Option<String>,
+    /// Whether there is already code at the address on the current chain
+    pub deployed: bool,
+}
+
+/// What to derive an address from. With a salt the CREATE2 address is computed, which
+/// needs the init code or its hash; otherwise the CREATE address for `nonce`, or for the
+/// deployer's next nonce when it's unset.
+#[derive(Debug, Clone, Default)]
+pub struct AddressInputs {
+    pub nonce: Option<u64>,
+    pub salt: Option<H256>,
+    pub init_code: Option<Bytes>,
+    pub init_code_hash: Option<H256>,
+}
+
+/// Parse a CREATE2 salt: hex of up to 32 bytes (left-padded) or a decimal number
+pub fn parse_salt(salt: &str) -> Result<H256> {
+    let salt = salt.trim();
+    if let Some(hex_digits) = salt.strip_prefix("0x") {
+        let bytes = hex::decode(if hex_digits.len() % 2 == 1 {
+            format!("0{}", hex_digits)
+        } else {
+            hex_digits.to_string()
+        })
+        .map_err(|e| anyhow!("Invalid salt {}: {}", salt, e))?;
+        if bytes.len() > 32 {
+            return Err(anyhow!("A salt is at most 32 bytes, got {}", bytes.len()));
+        }
+        let mut padded = [0u8; 32];
+        padded[32 - bytes.len()..].copy_from_slice(&bytes);
+        return Ok(H256(padded));
+    }
+    let number = U256::from_dec_str(salt)
+        .map_err(|_| anyhow!("Invalid salt {}: use 0x-prefixed hex or a number", salt))?;
+    let mut padded = [0u8; 32];
+    number.to_big_endian(&mut padded);
+    Ok(H256(padded))
+}
+
+/// Address of a contract CREATE2-deployed by `deployer` with `salt`
+pub fn create2_address(deployer: Address, salt: H256, init_code_hash: H256) -> Address {
+    get_create2_address_from_hash(deployer, salt.as_bytes(), init_code_hash.as_bytes())
+}
+
+/// Compute the address a contract deployed by `deployer` gets, and whether something
+/// is already there
+pub async fn compute_address(
+    blockchain_service: &BlockchainService,
+    deployer: Address,
+    inputs: AddressInputs,
+) -> Result<ComputedAddress> {
+    let provider = blockchain_service.provider();
+    let (scheme, address, nonce, salt, init_code_hash) = match inputs.salt {
+        Some(salt) => {
+            let init_code_hash = match (inputs.init_code_hash, &inputs.init_code) {
+                (Some(hash), None) => hash,
+                (None, Some(init_code)) => H256(keccak256(init_code)),
+                (Some(hash), Some(init_code)) if hash == H256(keccak256(init_code)) => hash,
+                (Some(_), Some(_)) => {
+                    return Err(anyhow!("init_code_hash doesn't match the init code"));
+                }
+                (None, None) => {
+                    return Err(anyhow!("CREATE2 addresses need the init code or its hash"));
+                }
+            };
+            (
+                DeployScheme::Create2,
+                create2_address(deployer, salt, init_code_hash),
+                None,
+                Some(salt),
+                Some(init_code_hash),
+            )
+        }
+        None => {
+            let nonce = match inputs.nonce {
+                Some(nonce) => nonce,
+                None => provider
+                    .get_transaction_count(deployer, Some(BlockNumber::Pending.into()))
+                    .await?
+                    .as_u64(),
+            };
+            (
+                DeployScheme::Create,
+                get_contract_address(deployer, nonce),
+                Some(nonce),
+                None,
+                None,
+            )
+        }
+    };
+
+    Ok(ComputedAddress {
+        scheme,
+        address: to_checksum(&address, None),
+        deployer: to_checksum(&deployer, None),
+        nonce,
+        salt: salt.map(|salt| format!("{:#x}", salt)),
+        init_code_hash: init_code_hash.map(|hash| format!("{:#x}", hash)),
+        deployed: !provider.get_code(address, None).await?.is_empty(),
+    })
+}
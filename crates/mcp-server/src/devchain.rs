@@ -0,0 +1,362 @@
+use anyhow::{Result, anyhow};
+use ethers::abi::{Abi, parse_abi};
+use ethers::contract::Contract;
+use ethers::providers::Middleware;
+use ethers::types::{Address, BlockNumber, H256, U256};
+use ethers::utils::{format_units, keccak256};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use shared::utils;
+use std::str::FromStr;
+
+use crate::blockchain::BlockchainService;
+
+/// Most blocks `mine_blocks` mines in one call
+pub const MAX_MINE_BLOCKS: u64 = 10_000;
+// Storage slots probed for a token's balances mapping
+const MAX_BALANCE_SLOT: u64 = 100;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChainSnapshot {
+    /// Id to pass to `revert_chain`
+    pub id: String,
+    pub block_number: u64,
+    pub timestamp: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChainRevert {
+    pub id: String,
+    /// Head of the chain after the revert
+    pub block_number: u64,
+    pub timestamp: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChainTime {
+    /// Head of the chain after the change
+    pub block_number: u64,
+    pub timestamp: u64,
+    pub blocks_mined: u64,
+    /// Set when the change only takes effect with the next mined block
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FundedBalance {
+    pub account: String,
+    /// "ETH" or the token's symbol
+    pub asset: String,
+    pub token_address: Option<String>,
+    pub previous_balance: String,
+    pub balance: String,
+}
+
+// Debug RPCs rewrite chain state, so they are refused anywhere but a local dev chain
+fn require_dev_chain(blockchain_service: &BlockchainService) -> Result<()> {
+    if !blockchain_service.is_dev_chain() {
+        return Err(anyhow!(
+            "Chain {} isn't a local dev chain; snapshots and time controls only work on Anvil or Hardhat",
+            blockchain_service.chain_id()
+        ));
+    }
+    Ok(())
+}
+
+// Number and timestamp of the latest block
+async fn head(blockchain_service: &BlockchainService) -> Result<(u64, u64)> {
+    let block = blockchain_service
+        .provider()
+        .get_block(BlockNumber::Latest)
+        .await?
+        .ok_or_else(|| anyhow!("RPC returned no latest block"))?;
+    Ok((
+        block.number.map(|number| number.as_u64()).unwrap_or(0),
+        block.timestamp.as_u64(),
+    ))
+}
+
+/// Checkpoint the dev chain's state with `evm_snapshot`
+pub async fn snapshot_chain(blockchain_service: &BlockchainService) -> Result<ChainSnapshot> {
+    require_dev_chain(blockchain_service)?;
+    let id: U256 = blockchain_service
+        .provider()
+        .request("evm_snapshot", ())
+        .await?;
+    let (block_number, timestamp) = head(blockchain_service).await?;
+
+    Ok(ChainSnapshot {
+        id: format!("{:#x}", id),
+        block_number,
+        timestamp,
+    })
+}
+
+/// Roll the dev chain back to a snapshot with `evm_revert`. A snapshot can only be
+/// reverted to once, and reverting also discards every snapshot taken after it.
+pub async fn revert_chain(blockchain_service: &BlockchainService, id: &str) -> Result<ChainRevert> {
+    require_dev_chain(blockchain_service)?;
+    let snapshot = U256::from_str_radix(id.trim().trim_start_matches("0x"), 16)
+        .map_err(|_| anyhow!("Invalid snapshot id: {}", id))?;
+    let reverted: bool = blockchain_service
+        .provider()
+        .request("evm_revert", [snapshot])
+        .await?;
+    if !reverted {
+        return Err(anyhow!(
+            "Snapshot {} doesn't exist; it may already have been used",
+            id
+        ));
+    }
+    // Accounts' nonces went back along with everything else
+    blockchain_service.resync_nonces().await;
+    let (block_number, timestamp) = head(blockchain_service).await?;
+
+    Ok(ChainRevert {
+        id: id.to_string(),
+        block_number,
+        timestamp,
+    })
+}
+
+async fn mine(blockchain_service: &BlockchainService) -> Result<()> {
+    let _: Value = blockchain_service
+        .provider()
+        .request("evm_mine", ())
+        .await?;
+    Ok(())
+}
+
+async fn chain_time(
+    blockchain_service: &BlockchainService,
+    blocks_mined: u64,
+    note: Option<String>,
+) -> Result<ChainTime> {
+    let (block_number, timestamp) = head(blockchain_service).await?;
+    Ok(ChainTime {
+        block_number,
+        timestamp,
+        blocks_mined,
+        note,
+    })
+}
+
+/// Move the dev chain's clock forward with `evm_increaseTime`, then mine a block so
+/// the new time is visible to contracts unless `mine_block` is false
+pub async fn increase_time(
+    blockchain_service: &BlockchainService,
+    seconds: u64,
+    mine_block: bool,
+) -> Result<ChainTime> {
+    require_dev_chain(blockchain_service)?;
+    if seconds == 0 {
+        return Err(anyhow!("seconds must be greater than zero"));
+    }
+    let _: Value = blockchain_service
+        .provider()
+        .request("evm_increaseTime", [U256::from(seconds)])
+        .await?;
+    if !mine_block {
+        return chain_time(
+            blockchain_service,
+            0,
+            Some(format!(
+                "The next mined block will be {} seconds later",
+                seconds
+            )),
+        )
+        .await;
+    }
+    mine(blockchain_service).await?;
+    chain_time(blockchain_service, 1, None).await
+}
+
+/// Mine `blocks` empty blocks with `evm_mine`
+pub async fn mine_blocks(blockchain_service: &BlockchainService, blocks: u64) -> Result<ChainTime> {
+    require_dev_chain(blockchain_service)?;
+    if blocks == 0 || blocks > MAX_MINE_BLOCKS {
+        return Err(anyhow!("blocks must be between 1 and {}", MAX_MINE_BLOCKS));
+    }
+    for _ in 0..blocks {
+        mine(blockchain_service).await?;
+    }
+    chain_time(blockchain_service, blocks, None).await
+}
+
+/// Fix the timestamp of the next block with `evm_setNextBlockTimestamp`, mining it
+/// straight away unless `mine_block` is false. Chains only move forward, so the timestamp
+/// must be later than the latest block's.
+pub async fn set_next_block_timestamp(
+    blockchain_service: &BlockchainService,
+    timestamp: u64,
+    mine_block: bool,
+) -> Result<ChainTime> {
+    require_dev_chain(blockchain_service)?;
+    let (_, current) = head(blockchain_service).await?;
+    if timestamp <= current {
+        return Err(anyhow!(
+            "Timestamp {} isn't after the latest block's ({}); a dev chain's clock can only move forward, or be rolled back with revert_chain",
+            timestamp,
+            current
+        ));
+    }
+    let _: Value = blockchain_service
+        .provider()
+        .request("evm_setNextBlockTimestamp", [U256::from(timestamp)])
+        .await?;
+    if !mine_block {
+        return chain_time(
+            blockchain_service,
+            0,
+            Some(format!(
+                "The next mined block will have timestamp {}",
+                timestamp
+            )),
+        )
+        .await;
+    }
+    mine(blockchain_service).await?;
+    chain_time(blockchain_service, 1, None).await
+}
+
+// Anvil's name for a dev RPC, falling back to Hardhat's
+async fn dev_request<T: serde::Serialize + std::fmt::Debug + Send + Sync>(
+    blockchain_service: &BlockchainService,
+    method: &str,
+    params: T,
+) -> Result<()> {
+    let provider = blockchain_service.provider();
+    let anvil: Result<Value, _> = provider
+        .request(&format!("anvil_{}", method), &params)
+        .await;
+    if anvil.is_err() {
+        let _: Value = provider
+            .request(&format!("hardhat_{}", method), &params)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Add `amount` ETH, or `amount` of `token`, to an account's balance on the dev chain.
+/// ETH is set with `anvil_setBalance`; tokens by finding the slot of their balances
+/// mapping and writing it with `anvil_setStorageAt`. Total supply is left alone.
+pub async fn fund_account(
+    blockchain_service: &BlockchainService,
+    account: &str,
+    token: Option<&str>,
+    amount: &str,
+) -> Result<FundedBalance> {
+    require_dev_chain(blockchain_service)?;
+    let holder = Address::from_str(account).map_err(|_| anyhow!("Invalid account: {}", account))?;
+
+    let Some(token) = token.filter(|token| !token.eq_ignore_ascii_case("ETH")) else {
+        let added = utils::parse_amount(amount, 18)?;
+        let previous = blockchain_service
+            .provider()
+            .get_balance(holder, None)
+            .await?;
+        let balance = previous.saturating_add(added);
+        dev_request(blockchain_service, "setBalance", (holder, balance)).await?;
+        return Ok(FundedBalance {
+            account: format!("{:?}", holder),
+            asset: "ETH".to_string(),
+            token_address: None,
+            previous_balance: format_units(previous, "ether")?,
+            balance: format_units(balance, "ether")?,
+        });
+    };
+
+    let token_info = blockchain_service.resolve_token(token).await?;
+    let added = utils::parse_amount(amount, token_info.decimals)?;
+    let token_address = Address::from_str(&token_info.address)?;
+    let abi: Abi = parse_abi(&["function balanceOf(address owner) view returns (uint256)"])?;
+    let contract = Contract::new(token_address, abi, blockchain_service.provider());
+    let balance_of = || async {
+        let balance: U256 = contract.method("balanceOf", holder)?.call().await?;
+        Ok::<U256, anyhow::Error>(balance)
+    };
+
+    let previous = balance_of().await?;
+    let balance = previous.saturating_add(added);
+    let slot = balance_slot(blockchain_service, token_address, holder, &balance_of)
+        .await?
+        .ok_or_else(|| {
+            anyhow!(
+                "Couldn't find where {} stores balances; it may compute them (rebasing tokens) or keep them in a separate contract",
+                token_info.symbol
+            )
+        })?;
+    dev_request(
+        blockchain_service,
+        "setStorageAt",
+        (token_address, slot, word(balance)),
+    )
+    .await?;
+    if balance_of().await? != balance {
+        return Err(anyhow!(
+            "{}'s balance didn't change as expected after writing its storage",
+            token_info.symbol
+        ));
+    }
+
+    Ok(FundedBalance {
+        account: format!("{:?}", holder),
+        asset: token_info.symbol.clone(),
+        token_address: Some(token_info.address.clone()),
+        previous_balance: format_units(previous, token_info.decimals as u32)?,
+        balance: format_units(balance, token_info.decimals as u32)?,
+    })
+}
+
+fn word(value: U256) -> H256 {
+    let mut bytes = [0u8; 32];
+    value.to_big_endian(&mut bytes);
+    H256(bytes)
+}
+
+// Storage key of `holder`'s entry in a mapping at `slot`: keccak256(key . slot) as
+// Solidity lays it out, or keccak256(slot . key) as Vyper does
+fn mapping_keys(holder: Address, slot: u64) -> [H256; 2] {
+    let mut key = [0u8; 32];
+    key[12..].copy_from_slice(holder.as_bytes());
+    let slot = word(U256::from(slot)).to_fixed_bytes();
+    [
+        H256(keccak256([key, slot].concat())),
+        H256(keccak256([slot, key].concat())),
+    ]
+}
+
+// Find the storage key behind balanceOf(holder) by writing a marker value to each
+// candidate and checking whether balanceOf returns it; each probe is undone
+async fn balance_slot<F, Fut>(
+    blockchain_service: &BlockchainService,
+    token: Address,
+    holder: Address,
+    balance_of: &F,
+) -> Result<Option<H256>>
+where
+    F: Fn() -> Fut,
+    Fut: std::future::Future<Output = Result<U256>>,
+{
+    let marker = U256::from_big_endian(&keccak256(b"fund_account probe"));
+    for slot in 0..MAX_BALANCE_SLOT {
+        for key in mapping_keys(holder, slot) {
+            let original = blockchain_service
+                .provider()
+                .get_storage_at(token, key, None)
+                .await?;
+            dev_request(
+                blockchain_service,
+                "setStorageAt",
+                (token, key, word(marker)),
+            )
+            .await?;
+            let found = balance_of().await.is_ok_and(|balance| balance == marker);
+            dev_request(blockchain_service, "setStorageAt", (token, key, original)).await?;
+            if found {
+                return Ok(Some(key));
+            }
+        }
+    }
+    Ok(None)
+}
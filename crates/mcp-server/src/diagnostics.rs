@@ -0,0 +1,277 @@
+use ethers::providers::Middleware;
+use serde::{Deserialize, Serialize};
+use shared::BalanceQuery;
+use std::path::Path;
+
+use crate::paths::data_dir;
+use crate::tools::ToolContext;
+
+// Data files the server reads from the data directory, with what happens when one is missing
+const DATA_FILES: &[(&str, &str)] = &[
+    ("erc20_abi.json", "the bundled ERC20 ABI is used"),
+    (
+        "uniswap_v2_router_abi.json",
+        "the bundled Uniswap V2 router ABI is used",
+    ),
+    (
+        "uniswap_v3_router_abi.json",
+        "the bundled Uniswap V3 router ABI is used",
+    ),
+    ("erc721_abi.json", "the bundled ERC721 ABI is used"),
+    (
+        "denylist.json",
+        "recipients are only screened via external APIs",
+    ),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Ok,
+    Warn,
+    Fail,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CheckResult {
+    pub name: String,
+    pub status: CheckStatus,
+    pub detail: String,
+    /// What to do about a warning or failure
+    pub fix: Option<String>,
+}
+
+impl CheckResult {
+    fn ok(name: impl Into<String>, detail: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            status: CheckStatus::Ok,
+            detail: detail.into(),
+            fix: None,
+        }
+    }
+
+    fn warn(name: impl Into<String>, detail: impl Into<String>, fix: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            status: CheckStatus::Warn,
+            detail: detail.into(),
+            fix: Some(fix.into()),
+        }
+    }
+
+    fn fail(name: impl Into<String>, detail: impl Into<String>, fix: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            status: CheckStatus::Fail,
+            detail: detail.into(),
+            fix: Some(fix.into()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SelfCheckReport {
+    /// False when any check failed; warnings don't count
+    pub healthy: bool,
+    pub checks: Vec<CheckResult>,
+}
+
+/// Check the server's environment: RPC, data files, API keys, accounts and the RAG index
+pub async fn self_check(context: &ToolContext) -> SelfCheckReport {
+    let mut checks = Vec::new();
+
+    checks.extend(check_rpc(context).await);
+    checks.extend(check_data_files(data_dir()));
+    checks.extend(check_api_keys(context).await);
+    checks.extend(check_accounts(context).await);
+    checks.push(check_rag(context).await);
+
+    SelfCheckReport {
+        healthy: checks.iter().all(|check| check.status != CheckStatus::Fail),
+        checks,
+    }
+}
+
+async fn check_rpc(context: &ToolContext) -> Vec<CheckResult> {
+    let service = &context.blockchain_service;
+    let provider = service.provider();
+
+    let rpc_chain_id = match provider.get_chainid().await {
+        Ok(chain_id) => chain_id.as_u64(),
+        Err(e) => {
+            return vec![CheckResult::fail(
+                "rpc",
+                format!("RPC unreachable: {}", e),
+                "Check ETH_RPC_URL, or start a local fork with `anvil --fork-url <mainnet RPC>`",
+            )];
+        }
+    };
+
+    let mut checks = Vec::new();
+    if rpc_chain_id == service.chain_id() {
+        let block = provider
+            .get_block_number()
+            .await
+            .map(|block| block.as_u64())
+            .unwrap_or_default();
+        checks.push(CheckResult::ok(
+            "rpc",
+            format!("Chain {} at block {}", rpc_chain_id, block),
+        ));
+    } else {
+        checks.push(CheckResult::fail(
+            "rpc",
+            format!(
+                "RPC serves chain {} but the server is configured for chain {}",
+                rpc_chain_id,
+                service.chain_id()
+            ),
+            format!(
+                "Set CHAIN_ID={} or point ETH_RPC_URL at the right network",
+                rpc_chain_id
+            ),
+        ));
+    }
+
+    match service.check_block_lag().await {
+        Ok(Some(lag)) => checks.push(CheckResult::warn(
+            "block_lag",
+            format!("Latest block is {}s old", lag.as_secs()),
+            "The RPC may be syncing or rate limited; try another endpoint or raise MAX_BLOCK_LAG_SECS",
+        )),
+        Ok(None) => {}
+        Err(e) => checks.push(CheckResult::warn(
+            "block_lag",
+            format!("Couldn't read the latest block: {}", e),
+            "Check the RPC endpoint's health",
+        )),
+    }
+
+    // With several endpoints, one failing is survivable but worth knowing about
+    let endpoints = service.rpc_endpoints();
+    if endpoints.len() > 1 {
+        for endpoint in endpoints {
+            let name = format!("rpc_endpoint {}", endpoint.endpoint);
+            let usage = format!(
+                "{} requests, {} failed",
+                endpoint.requests, endpoint.failures
+            );
+            checks.push(match endpoint.retry_in_secs {
+                None => CheckResult::ok(name, usage),
+                Some(retry_in) => CheckResult::warn(
+                    name,
+                    format!(
+                        "Benched after {} failures in a row, retried in {}s ({}): {}",
+                        endpoint.consecutive_failures,
+                        retry_in,
+                        usage,
+                        endpoint.last_error.unwrap_or_default()
+                    ),
+                    "Requests are going to the other endpoints; replace this one if it keeps failing",
+                ),
+            });
+        }
+    }
+
+    checks
+}
+
+fn check_data_files(data_dir: &Path) -> Vec<CheckResult> {
+    if !data_dir.is_dir() {
+        return vec![CheckResult::fail(
+            "data_dir",
+            format!("{} not found", data_dir.display()),
+            "Set DATA_DIR to the data directory, or start the server from the repository root",
+        )];
+    }
+
+    DATA_FILES
+        .iter()
+        .map(|(file, fallback)| {
+            let path = data_dir.join(file);
+            if path.is_file() {
+                CheckResult::ok(*file, format!("{} present", path.display()))
+            } else {
+                CheckResult::warn(
+                    *file,
+                    format!("{} missing; {}", path.display(), fallback),
+                    format!("Restore {} from the repository", path.display()),
+                )
+            }
+        })
+        .collect()
+}
+
+async fn check_api_keys(context: &ToolContext) -> Vec<CheckResult> {
+    context
+        .external_apis
+        .check_api_keys()
+        .await
+        .into_iter()
+        .map(|(key, result)| match result {
+            Some(Ok(())) => CheckResult::ok(key, "Key accepted"),
+            Some(Err(e)) => CheckResult::fail(
+                key,
+                format!("Key rejected: {}", e),
+                format!("Replace {} with a valid key", key),
+            ),
+            None => CheckResult::warn(
+                key,
+                "Not set; features using it fall back or are unavailable",
+                format!("export {}=<your key>", key),
+            ),
+        })
+        .collect()
+}
+
+async fn check_accounts(context: &ToolContext) -> Vec<CheckResult> {
+    let accounts = &context.namespace.accounts;
+    let mut checks = Vec::new();
+
+    for address in accounts.addresses() {
+        let name = accounts
+            .name_for_address(&address)
+            .unwrap_or_else(|| address.clone());
+        let check_name = format!("account:{}", name);
+
+        match context
+            .blockchain_service
+            .get_balance(BalanceQuery {
+                address: address.clone(),
+                token: None,
+            })
+            .await
+        {
+            Ok(balance) if balance.balance.parse::<f64>().unwrap_or(0.0) > 0.0 => {
+                checks.push(CheckResult::ok(
+                    check_name,
+                    format!("{} ETH", balance.balance),
+                ));
+            }
+            Ok(_) => checks.push(CheckResult::warn(
+                check_name,
+                format!("{} has no ETH to pay gas", address),
+                "Fund it, e.g. `cast send --value 10ether` from a funded Anvil account",
+            )),
+            Err(e) => checks.push(CheckResult::fail(
+                check_name,
+                format!("Balance lookup failed: {}", e),
+                "Check the RPC connection",
+            )),
+        }
+    }
+
+    checks
+}
+
+async fn check_rag(context: &ToolContext) -> CheckResult {
+    match context.rag_service.document_count().await {
+        0 => CheckResult::warn(
+            "rag_index",
+            "No documents indexed; search_docs will return nothing",
+            "Run ./scripts/populate_rag.sh and restart the server",
+        ),
+        count => CheckResult::ok("rag_index", format!("{} documents indexed", count)),
+    }
+}
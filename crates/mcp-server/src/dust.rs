@@ -0,0 +1,165 @@
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+
+use crate::blockchain::{BlockchainService, GasEstimateRequest, SwapQuote};
+use crate::external_apis::ExternalAPIService;
+use crate::portfolio;
+use crate::streams::StreamStore;
+
+/// Balances worth less than this many USD count as dust when the caller gives no threshold
+pub const DEFAULT_DUST_THRESHOLD_USD: f64 = 10.0;
+
+/// Swapping one small balance into the target asset
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DustSwap {
+    pub token: String,
+    pub token_address: String,
+    pub balance: String,
+    pub value_usd: f64,
+    pub quote: SwapQuote,
+    /// Gas for the swap, including the router approval it may need first
+    pub gas_cost_eth: Option<String>,
+    pub gas_cost_usd: Option<f64>,
+}
+
+/// A dust balance left out of the plan, and why
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SkippedDust {
+    pub token: String,
+    pub balance: String,
+    pub value_usd: f64,
+    pub reason: String,
+}
+
+/// Every swap that turns an account's dust into one asset, run in order once the user
+/// confirms
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DustPlan {
+    pub address: String,
+    pub target: String,
+    pub threshold_usd: f64,
+    pub swaps: Vec<DustSwap>,
+    pub skipped: Vec<SkippedDust>,
+    pub total_value_usd: f64,
+    /// Sum of the quotes' expected output, in the target asset
+    pub expected_total_out: f64,
+    pub total_gas_cost_usd: Option<f64>,
+}
+
+/// Find the token balances of `address` worth less than `threshold_usd` and quote
+/// swapping each into `target`. ETH is left alone since it pays for the gas, and
+/// swaps that would cost more in gas than they return are skipped.
+pub async fn plan_dust_consolidation(
+    blockchain_service: &BlockchainService,
+    external_apis: &ExternalAPIService,
+    streams: &StreamStore,
+    address: &str,
+    target: &str,
+    threshold_usd: f64,
+    discover_blocks: Option<u64>,
+) -> Result<DustPlan> {
+    if threshold_usd <= 0.0 {
+        return Err(anyhow!("threshold_usd must be above zero"));
+    }
+    let target_address = if target.eq_ignore_ascii_case("eth") {
+        blockchain_service.router_config().weth.clone()
+    } else {
+        blockchain_service.resolve_token(target).await?.address
+    };
+    let portfolio = portfolio::get_portfolio(
+        blockchain_service,
+        external_apis,
+        streams,
+        address,
+        discover_blocks,
+    )
+    .await?;
+    let eth_price = portfolio
+        .holdings
+        .iter()
+        .find(|holding| holding.token_address.is_none())
+        .and_then(|holding| holding.price_usd);
+
+    let mut swaps = Vec::new();
+    let mut skipped = Vec::new();
+    for holding in portfolio.holdings {
+        let (Some(token_address), Some(value_usd)) = (holding.token_address, holding.value_usd)
+        else {
+            continue;
+        };
+        if value_usd <= 0.0
+            || value_usd >= threshold_usd
+            || token_address.eq_ignore_ascii_case(&target_address)
+        {
+            continue;
+        }
+        let skip = |reason: String| SkippedDust {
+            token: holding.symbol.clone(),
+            balance: holding.balance.clone(),
+            value_usd,
+            reason,
+        };
+
+        let quote = match blockchain_service
+            .quote_swap(&token_address, target, &holding.balance, None, None)
+            .await
+        {
+            Ok(quote) => quote,
+            Err(e) => {
+                skipped.push(skip(format!("No swap route: {}", e)));
+                continue;
+            }
+        };
+        let gas = blockchain_service
+            .estimate_gas(GasEstimateRequest::Swap {
+                from: portfolio.address.clone(),
+                from_token: token_address.clone(),
+                to_token: target.to_string(),
+                amount: holding.balance.clone(),
+            })
+            .await
+            .ok();
+        let gas_cost_eth = gas.map(|gas| gas.expected_cost_eth);
+        let gas_cost_usd = gas_cost_eth
+            .as_ref()
+            .and_then(|eth| eth.parse::<f64>().ok())
+            .zip(eth_price)
+            .map(|(eth, price)| eth * price);
+        if let Some(cost) = gas_cost_usd
+            && cost >= value_usd
+        {
+            skipped.push(skip(format!(
+                "Gas (${:.2}) would cost more than the balance is worth",
+                cost
+            )));
+            continue;
+        }
+
+        swaps.push(DustSwap {
+            token: holding.symbol,
+            token_address,
+            balance: holding.balance,
+            value_usd,
+            quote,
+            gas_cost_eth,
+            gas_cost_usd,
+        });
+    }
+
+    Ok(DustPlan {
+        address: portfolio.address,
+        target: target.to_string(),
+        threshold_usd,
+        total_value_usd: swaps.iter().map(|swap| swap.value_usd).sum(),
+        expected_total_out: swaps
+            .iter()
+            .filter_map(|swap| swap.quote.expected_amount_out.parse::<f64>().ok())
+            .sum(),
+        total_gas_cost_usd: swaps
+            .iter()
+            .map(|swap| swap.gas_cost_usd)
+            .sum::<Option<f64>>(),
+        swaps,
+        skipped,
+    })
+}
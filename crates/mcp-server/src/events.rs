@@ -0,0 +1,244 @@
+use anyhow::{Result, anyhow};
+use ethers::abi::{Event, EventExt, HumanReadableParser, RawLog};
+use ethers::providers::Middleware;
+use ethers::types::{Filter, H256, Log, U256, ValueOrArray};
+use ethers::utils::keccak256;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::str::FromStr;
+
+use crate::blockchain::{BlockchainService, token_to_json};
+
+/// Blocks searched when the caller gives no range
+pub const DEFAULT_EVENT_BLOCKS: u64 = 1_000;
+// Wider ranges are rejected by most public RPC providers anyway
+const MAX_EVENT_BLOCKS: u64 = 50_000;
+pub const DEFAULT_EVENT_LIMIT: usize = 100;
+
+/// What to look for with `get_events`
+#[derive(Debug, Clone, Default)]
+pub struct EventQuery {
+    pub address: String,
+    /// Event name from a loaded ABI ("Swap"), or a signature such as
+    /// "Transfer(address indexed from, address indexed to, uint256 value)"
+    pub signature: String,
+    /// Filters for the indexed params (topics 1-3): each a value, a list of
+    /// alternatives, or null to match anything
+    pub topics: Vec<Value>,
+    pub from_block: Option<u64>,
+    pub to_block: Option<u64>,
+    pub limit: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DecodedEvent {
+    pub block_number: Option<u64>,
+    pub transaction_hash: Option<String>,
+    pub log_index: Option<u64>,
+    /// Param name -> value; None when no known layout of the event fits the log
+    pub args: Option<Map<String, Value>>,
+    pub topics: Vec<String>,
+    pub data: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EventsResult {
+    pub address: String,
+    pub event: String,
+    pub topic0: String,
+    pub from_block: u64,
+    pub to_block: u64,
+    /// Oldest first; the most recent `limit` when `truncated`
+    pub events: Vec<DecodedEvent>,
+    pub truncated: bool,
+}
+
+/// Logs a contract emitted for one event over a block range, decoded with the loaded
+/// ABIs or the given signature
+pub async fn get_events(
+    blockchain_service: &BlockchainService,
+    query: EventQuery,
+) -> Result<EventsResult> {
+    let address = blockchain_service.parse_address(&query.address).await?;
+    let candidates = event_candidates(blockchain_service, &query.signature)?;
+    let event_signature = candidates[0].abi_signature();
+    let topic0 = H256::from(keccak256(event_signature.as_bytes()));
+
+    let to_block = match query.to_block {
+        Some(block) => block,
+        None => blockchain_service
+            .provider()
+            .get_block_number()
+            .await?
+            .as_u64(),
+    };
+    let from_block = query
+        .from_block
+        .unwrap_or_else(|| to_block.saturating_sub(DEFAULT_EVENT_BLOCKS - 1));
+    if from_block > to_block {
+        return Err(anyhow!(
+            "from_block {} is after to_block {}",
+            from_block,
+            to_block
+        ));
+    }
+    if to_block - from_block >= MAX_EVENT_BLOCKS {
+        return Err(anyhow!(
+            "Block ranges are limited to {} blocks",
+            MAX_EVENT_BLOCKS
+        ));
+    }
+    if query.topics.len() > 3 {
+        return Err(anyhow!("Events have at most 3 indexed params to filter on"));
+    }
+
+    let mut filter = Filter::new()
+        .address(address)
+        .topic0(topic0)
+        .from_block(from_block)
+        .to_block(to_block);
+    for (index, value) in query.topics.iter().enumerate() {
+        let Some(topic) = topic_filter(blockchain_service, value).await? else {
+            continue;
+        };
+        filter = match index {
+            0 => filter.topic1(topic),
+            1 => filter.topic2(topic),
+            _ => filter.topic3(topic),
+        };
+    }
+
+    let logs = blockchain_service.get_logs(&filter).await?;
+    let truncated = logs.len() > query.limit;
+    let skip = logs.len().saturating_sub(query.limit);
+    let events = logs
+        .into_iter()
+        .skip(skip)
+        .map(|log| decode_log(&candidates, log))
+        .collect();
+
+    Ok(EventsResult {
+        address: format!("{:?}", address),
+        event: event_signature,
+        topic0: format!("{:?}", topic0),
+        from_block,
+        to_block,
+        events,
+        truncated,
+    })
+}
+
+// Layouts to decode with, best first: a signature that marks its indexed params, then
+// matching events from the loaded ABIs, then a bare signature (assumed unindexed)
+fn event_candidates(blockchain_service: &BlockchainService, signature: &str) -> Result<Vec<Event>> {
+    let parsed = if signature.contains('(') {
+        Some(
+            HumanReadableParser::parse_event(signature)
+                .map_err(|e| anyhow!("Invalid event signature '{}': {}", signature, e))?,
+        )
+    } else {
+        None
+    };
+    let lookup = match &parsed {
+        Some(event) => event.abi_signature(),
+        None => signature.to_string(),
+    };
+
+    let mut candidates = blockchain_service.find_events(&lookup);
+    if let Some(event) = parsed {
+        if event.inputs.iter().any(|input| input.indexed) {
+            candidates.insert(0, event);
+        } else if candidates.is_empty() {
+            candidates.push(event);
+        }
+    }
+    if candidates.is_empty() {
+        return Err(anyhow!(
+            "No loaded ABI has an event named '{}'; pass its full signature, e.g. \
+             'Transfer(address indexed from, address indexed to, uint256 value)'",
+            signature
+        ));
+    }
+    Ok(candidates)
+}
+
+// A topic filter value: a 32-byte hex topic, an address or account name, a number,
+// or a list of those
+async fn topic_filter(
+    blockchain_service: &BlockchainService,
+    value: &Value,
+) -> Result<Option<ValueOrArray<Option<H256>>>> {
+    match value {
+        Value::Null => Ok(None),
+        Value::Array(values) => {
+            let mut topics = Vec::new();
+            for value in values {
+                topics.push(Some(topic_value(blockchain_service, value).await?));
+            }
+            Ok(Some(ValueOrArray::Array(topics)))
+        }
+        value => Ok(Some(ValueOrArray::Value(Some(
+            topic_value(blockchain_service, value).await?,
+        )))),
+    }
+}
+
+async fn topic_value(blockchain_service: &BlockchainService, value: &Value) -> Result<H256> {
+    let text = match value {
+        Value::String(text) => text.trim().to_string(),
+        Value::Number(number) => number.to_string(),
+        Value::Bool(flag) => return Ok(H256::from_low_u64_be(*flag as u64)),
+        other => return Err(anyhow!("Invalid topic filter {}", other)),
+    };
+
+    if text.starts_with("0x") && text.len() == 66 {
+        return Ok(H256::from_str(&text)?);
+    }
+    if let Ok(number) = U256::from_dec_str(&text) {
+        let mut topic = [0u8; 32];
+        number.to_big_endian(&mut topic);
+        return Ok(H256::from(topic));
+    }
+    let address = blockchain_service.parse_address(&text).await?;
+    Ok(H256::from(address))
+}
+
+/// Decode a log with the first candidate layout that fits it
+pub fn decode_log(candidates: &[Event], log: Log) -> DecodedEvent {
+    let args = candidates.iter().find_map(|event| {
+        let decoded = event
+            .parse_log(RawLog {
+                topics: log.topics.clone(),
+                data: log.data.to_vec(),
+            })
+            .ok()?;
+        Some(
+            decoded
+                .params
+                .into_iter()
+                .enumerate()
+                .map(|(index, param)| {
+                    let name = if param.name.is_empty() {
+                        format!("arg{}", index)
+                    } else {
+                        param.name
+                    };
+                    (name, token_to_json(param.value))
+                })
+                .collect(),
+        )
+    });
+
+    DecodedEvent {
+        block_number: log.block_number.map(|number| number.as_u64()),
+        transaction_hash: log.transaction_hash.map(|hash| format!("{:?}", hash)),
+        log_index: log.log_index.map(|index| index.as_u64()),
+        args,
+        topics: log
+            .topics
+            .iter()
+            .map(|topic| format!("{:?}", topic))
+            .collect(),
+        data: log.data.to_string(),
+    }
+}
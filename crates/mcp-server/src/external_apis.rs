@@ -1,12 +1,31 @@
 use anyhow::Result;
+use ethers::abi::Abi;
 use reqwest::Client;
+use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use std::collections::HashMap;
 
+/// A contract's verified source and ABI from Etherscan
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VerifiedContract {
+  pub address: String,
+  pub name: String,
+  pub compiler: String,
+  /// For a proxy, the implementation the name, source and ABI were taken from
+  pub implementation: Option<String>,
+  pub abi: Abi,
+  /// Solidity source, or Etherscan's standard-JSON input for multi-file contracts
+  pub source_code: String,
+}
+
 #[derive(Clone)]
 pub struct ExternalAPIService {
   client: Client,
   brave_api_key: Option<String>,
+  chainabuse_api_key: Option<String>,
+  etherscan_api_key: Option<String>,
+  zerox_api_key: Option<String>,
+  oneinch_api_key: Option<String>,
 }
 
 impl ExternalAPIService {
@@ -14,6 +33,10 @@ impl ExternalAPIService {
       Self {
           client: Client::new(),
           brave_api_key: std::env::var("BRAVE_API_KEY").ok(),
+          chainabuse_api_key: std::env::var("CHAINABUSE_API_KEY").ok(),
+          etherscan_api_key: std::env::var("ETHERSCAN_API_KEY").ok(),
+          zerox_api_key: std::env::var("ZEROX_API_KEY").ok(),
+          oneinch_api_key: std::env::var("ONEINCH_API_KEY").ok(),
       }
   }
 
@@ -71,33 +94,474 @@ impl ExternalAPIService {
       }
   }
 
+  /// USD price of a token contract from DefiLlama, if it is known
+  pub async fn get_token_usd_price(&self, token_address: &str) -> Result<Option<f64>> {
+      let price_data = self.get_defi_llama_price(token_address).await?;
+      let key = format!("ethereum:{}", token_address);
+      Ok(price_data["coins"][&key]["price"].as_f64())
+  }
+
+  /// USD prices for several token contracts in one DefiLlama request, keyed by lowercase address.
+  /// Tokens DefiLlama doesn't know are left out.
+  pub async fn get_token_usd_prices(&self, token_addresses: &[String]) -> Result<HashMap<String, f64>> {
+      if token_addresses.is_empty() {
+          return Ok(HashMap::new());
+      }
+      let coins: Vec<String> = token_addresses
+          .iter()
+          .map(|address| format!("ethereum:{}", address))
+          .collect();
+      let url = format!("https://api.llama.fi/prices/current/{}", coins.join(","));
+
+      let response = self.client
+          .get(&url)
+          .send()
+          .await?;
+      if !response.status().is_success() {
+          return Err(anyhow::anyhow!("DefiLlama returned {}", response.status()));
+      }
+
+      let price_data: Value = response.json().await?;
+      let mut prices = HashMap::new();
+      if let Some(coins) = price_data["coins"].as_object() {
+          for (key, coin) in coins {
+              if let (Some(address), Some(price)) = (key.strip_prefix("ethereum:"), coin["price"].as_f64()) {
+                  prices.insert(address.to_lowercase(), price);
+              }
+          }
+      }
+      Ok(prices)
+  }
+
+  /// Firm swap quote from 0x's AllowanceHolder API (chainId, sellToken, buyToken,
+  /// sellAmount, taker, slippageBps), with the transaction to send
   pub async fn get_0x_quote(&self, params: HashMap<String, String>) -> Result<Value> {
-      let mut url = "https://api.0x.org/swap/v1/quote?".to_string();
-      for (key, value) in params {
-          url.push_str(&format!("{}={}&", key, value));
+      let api_key = self
+          .zerox_api_key
+          .as_ref()
+          .ok_or_else(|| anyhow::anyhow!("ZEROX_API_KEY isn't set"))?;
+      let response = self.client
+          .get("https://api.0x.org/swap/allowance-holder/quote")
+          .header("0x-api-key", api_key)
+          .header("0x-version", "v2")
+          .query(&params)
+          .send()
+          .await?;
+
+      // There's no fallback: a made-up quote can't be executed
+      let status = response.status();
+      let body: Value = response.json().await?;
+      if !status.is_success() {
+          return Err(anyhow::anyhow!("0x quote failed ({}): {}", status, body));
       }
-      url.pop(); // Remove trailing &
+      Ok(body)
+  }
 
+  /// Swap transaction from 1inch's v6 API (src, dst, amount, from, slippage)
+  pub async fn get_1inch_swap(&self, chain_id: u64, params: HashMap<String, String>) -> Result<Value> {
+      let api_key = self
+          .oneinch_api_key
+          .as_ref()
+          .ok_or_else(|| anyhow::anyhow!("ONEINCH_API_KEY isn't set"))?;
+      let url = format!("https://api.1inch.dev/swap/v6.0/{}/swap", chain_id);
       let response = self.client
           .get(&url)
+          .bearer_auth(api_key)
+          .query(&params)
           .send()
           .await?;
 
-      if response.status().is_success() {
-          let quote: Value = response.json().await?;
-          Ok(quote)
-      } else {
-          // Return mock quote
-          Ok(json!({
-              "price": "1.0",
-              "guaranteedPrice": "1.0",
-              "to": "0x...",
-              "data": "0x...",
-              "value": "0",
-              "gas": "150000",
-              "estimatedGas": "150000",
-              "gasPrice": "20000000000"
-          }))
+      let status = response.status();
+      let body: Value = response.json().await?;
+      if !status.is_success() {
+          return Err(anyhow::anyhow!("1inch quote failed ({}): {}", status, body));
       }
+      Ok(body)
   }
-}
\ No newline at end of file
+
+  /// Validate each configured API key with a cheap request; None for keys that aren't set
+  pub async fn check_api_keys(&self) -> Vec<(&'static str, Option<Result<()>>)> {
+      let etherscan = match &self.etherscan_api_key {
+          Some(api_key) => Some(self.check_etherscan_key(api_key).await),
+          None => None,
+      };
+      let brave = match &self.brave_api_key {
+          Some(_) => Some(self.search_brave("ethereum").await.and_then(|body| {
+              if body.get("web").is_some() {
+                  Ok(())
+              } else {
+                  Err(anyhow::anyhow!("Unexpected response: {}", body))
+              }
+          })),
+          None => None,
+      };
+      let chainabuse = match &self.chainabuse_api_key {
+          Some(_) => Some(
+              self.get_chainabuse_reports("0x0000000000000000000000000000000000000000")
+                  .await
+                  .map(|_| ()),
+          ),
+          None => None,
+      };
+
+      vec![
+          ("ETHERSCAN_API_KEY", etherscan),
+          ("BRAVE_API_KEY", brave),
+          ("CHAINABUSE_API_KEY", chainabuse),
+      ]
+  }
+
+  async fn check_etherscan_key(&self, api_key: &str) -> Result<()> {
+      let body: Value = self.client
+          .get("https://api.etherscan.io/v2/api")
+          .query(&[
+              ("chainid", "1"),
+              ("module", "stats"),
+              ("action", "ethprice"),
+              ("apikey", api_key),
+          ])
+          .send()
+          .await?
+          .error_for_status()?
+          .json()
+          .await?;
+
+      match body["status"].as_str() {
+          Some("1") => Ok(()),
+          _ => Err(anyhow::anyhow!("{}", body["result"].as_str().unwrap_or("Etherscan rejected the key"))),
+      }
+  }
+
+  /// Number of scam reports filed against an address, or None when no API key is configured
+  pub async fn get_chainabuse_reports(&self, address: &str) -> Result<Option<u64>> {
+      let Some(api_key) = &self.chainabuse_api_key else {
+          return Ok(None);
+      };
+
+      let response = self.client
+          .get("https://api.chainabuse.com/v0/reports")
+          .basic_auth(api_key, Some(api_key))
+          .query(&[("address", address)])
+          .send()
+          .await?
+          .error_for_status()?;
+
+      let reports: Value = response.json().await?;
+      let count = match &reports {
+          Value::Array(items) => items.len() as u64,
+          _ => reports["count"]
+              .as_u64()
+              .or_else(|| reports["reports"].as_array().map(|r| r.len() as u64))
+              .unwrap_or(0),
+      };
+
+      Ok(Some(count))
+  }
+
+  /// Etherscan `getsourcecode` entry for a contract, or None when no API key is configured
+  pub async fn get_contract_source(&self, chain_id: u64, address: &str) -> Result<Option<Value>> {
+      let Some(api_key) = &self.etherscan_api_key else {
+          return Ok(None);
+      };
+
+      let chain_id = chain_id.to_string();
+      let response = self.client
+          .get("https://api.etherscan.io/v2/api")
+          .query(&[
+              ("chainid", chain_id.as_str()),
+              ("module", "contract"),
+              ("action", "getsourcecode"),
+              ("address", address),
+              ("apikey", api_key.as_str()),
+          ])
+          .send()
+          .await?
+          .error_for_status()?;
+
+      let body: Value = response.json().await?;
+      Ok(body["result"].as_array().and_then(|r| r.first()).cloned())
+  }
+
+  /// Verified source and ABI of a contract, following proxies Etherscan recognizes to
+  /// their implementation. None when the contract isn't verified or no API key is set.
+  pub async fn get_verified_contract(&self, chain_id: u64, address: &str) -> Result<Option<VerifiedContract>> {
+      let Some(mut source) = self.get_contract_source(chain_id, address).await? else {
+          return Ok(None);
+      };
+      let mut implementation = None;
+      if source["Proxy"].as_str() == Some("1")
+          && let Some(proxied) = source["Implementation"].as_str().filter(|proxied| !proxied.is_empty())
+          && let Some(proxied_source) = self.get_contract_source(chain_id, proxied).await?
+      {
+          implementation = Some(proxied.to_string());
+          source = proxied_source;
+      }
+
+      // Unverified contracts report "Contract source code not verified" as the ABI
+      let Some(abi) = source["ABI"].as_str().and_then(|abi| serde_json::from_str(abi).ok()) else {
+          return Ok(None);
+      };
+      Ok(Some(VerifiedContract {
+          address: address.to_string(),
+          name: source["ContractName"].as_str().unwrap_or_default().to_string(),
+          compiler: source["CompilerVersion"].as_str().unwrap_or_default().to_string(),
+          implementation,
+          abi,
+          source_code: source["SourceCode"].as_str().unwrap_or_default().to_string(),
+      }))
+  }
+
+  /// Account history from Etherscan: `action` is "txlist" for normal transactions or
+  /// "tokentx" for ERC20 transfers. Returns None when no API key is configured.
+  pub async fn get_account_history(
+      &self,
+      chain_id: u64,
+      address: &str,
+      action: &str,
+  ) -> Result<Option<Vec<Value>>> {
+      let Some(api_key) = &self.etherscan_api_key else {
+          return Ok(None);
+      };
+
+      let chain_id = chain_id.to_string();
+      let response = self.client
+          .get("https://api.etherscan.io/v2/api")
+          .query(&[
+              ("chainid", chain_id.as_str()),
+              ("module", "account"),
+              ("action", action),
+              ("address", address),
+              ("sort", "asc"),
+              ("apikey", api_key.as_str()),
+          ])
+          .send()
+          .await?
+          .error_for_status()?;
+
+      let body: Value = response.json().await?;
+      // "No transactions found" comes back as status 0 with an empty result
+      Ok(Some(body["result"].as_array().cloned().unwrap_or_default()))
+  }
+
+  /// Balance of an address at a past block from Etherscan (ETH, or an ERC20 when `token` is set).
+  /// These are Etherscan API Pro endpoints; returns None when no API key is configured.
+  pub async fn get_historical_balance(
+      &self,
+      chain_id: u64,
+      address: &str,
+      token: Option<&str>,
+      block: u64,
+  ) -> Result<Option<String>> {
+      let Some(api_key) = &self.etherscan_api_key else {
+          return Ok(None);
+      };
+
+      let chain_id = chain_id.to_string();
+      let block = block.to_string();
+      let mut query = vec![
+          ("chainid", chain_id.as_str()),
+          ("module", "account"),
+          ("address", address),
+          ("blockno", block.as_str()),
+          ("apikey", api_key.as_str()),
+      ];
+      match token {
+          Some(token) => {
+              query.push(("action", "tokenbalancehistory"));
+              query.push(("contractaddress", token));
+          }
+          None => query.push(("action", "balancehistory")),
+      }
+
+      let response = self.client
+          .get("https://api.etherscan.io/v2/api")
+          .query(&query)
+          .send()
+          .await?
+          .error_for_status()?;
+
+      let body: Value = response.json().await?;
+      if body["status"].as_str() != Some("1") {
+          return Err(anyhow::anyhow!(
+              "Etherscan balance history unavailable: {}",
+              body["result"].as_str().unwrap_or("unknown error")
+          ));
+      }
+      Ok(body["result"].as_str().map(|balance| balance.to_string()))
+  }
+
+  /// USD price of a token contract at a past unix timestamp from DefiLlama
+  pub async fn get_historical_token_price(
+      &self,
+      token_address: &str,
+      timestamp: i64,
+  ) -> Result<Option<f64>> {
+      let key = format!("ethereum:{}", token_address);
+      let url = format!("https://coins.llama.fi/prices/historical/{}/{}", timestamp, key);
+
+      let price_data: Value = self.client
+          .get(&url)
+          .send()
+          .await?
+          .error_for_status()?
+          .json()
+          .await?;
+      Ok(price_data["coins"][&key]["price"].as_f64())
+  }
+
+  /// Every yield pool DefiLlama tracks, with TVL and APY broken down into base and rewards
+  pub async fn get_yield_pools(&self) -> Result<Vec<Value>> {
+      let mut body: Value = self.client
+          .get("https://yields.llama.fi/pools")
+          .send()
+          .await?
+          .error_for_status()?
+          .json()
+          .await?;
+
+      match body["data"].take() {
+          Value::Array(pools) => Ok(pools),
+          _ => Err(anyhow::anyhow!("DefiLlama yields returned no pools")),
+      }
+  }
+
+  /// Whether web search goes to Brave; without a key `search_brave` returns a mock result
+  pub fn has_search_key(&self) -> bool {
+      self.brave_api_key.is_some()
+  }
+
+  /// A protocol's DefiLlama profile (TVL history, token, audits, governance and
+  /// hallmarks) by slug, or None when DefiLlama has no protocol by that slug
+  pub async fn get_protocol(&self, slug: &str) -> Result<Option<Value>> {
+      let response = self.client
+          .get(format!("https://api.llama.fi/protocol/{}", slug))
+          .send()
+          .await?;
+      if !response.status().is_success() {
+          return Ok(None);
+      }
+      let body: Value = response.json().await?;
+      // Unknown slugs come back as a 200 with an error message on some deployments
+      Ok(body["name"].is_string().then_some(body))
+  }
+
+  /// Summary of every protocol DefiLlama tracks, used to look one up by name
+  pub async fn list_protocols(&self) -> Result<Vec<Value>> {
+      let body: Value = self.client
+          .get("https://api.llama.fi/protocols")
+          .send()
+          .await?
+          .error_for_status()?
+          .json()
+          .await?;
+      Ok(body.as_array().cloned().unwrap_or_default())
+  }
+
+  /// Current USD price and percentage change over `period` (e.g. "7d") for a DefiLlama
+  /// coin key such as "coingecko:aave"
+  pub async fn get_coin_price_change(&self, coin: &str, period: &str) -> Result<(Option<f64>, Option<f64>)> {
+      let price: Value = self.client
+          .get(format!("https://coins.llama.fi/prices/current/{}", coin))
+          .send()
+          .await?
+          .error_for_status()?
+          .json()
+          .await?;
+      let change: Value = self.client
+          .get(format!("https://coins.llama.fi/percentage/{}", coin))
+          .query(&[("period", period)])
+          .send()
+          .await?
+          .error_for_status()?
+          .json()
+          .await?;
+      Ok((price["coins"][coin]["price"].as_f64(), change["coins"][coin].as_f64()))
+  }
+
+  /// Most recent Snapshot proposals of a governance space, newest first
+  pub async fn get_snapshot_proposals(&self, space: &str, limit: u32) -> Result<Vec<Value>> {
+      let query = format!(
+          r#"{{ proposals(first: {}, where: {{ space: "{}" }}, orderBy: "created", orderDirection: desc) {{ title state start end link }} }}"#,
+          limit,
+          space.replace('"', "")
+      );
+      let body: Value = self.client
+          .post("https://hub.snapshot.org/graphql")
+          .json(&json!({ "query": query }))
+          .send()
+          .await?
+          .error_for_status()?
+          .json()
+          .await?;
+      Ok(body["data"]["proposals"].as_array().cloned().unwrap_or_default())
+  }
+
+  /// Run a query against Snapshot's GraphQL hub, returning its `data`
+  pub async fn query_snapshot(&self, query: &str, variables: Value) -> Result<Value> {
+      let body: Value = self.client
+          .post("https://hub.snapshot.org/graphql")
+          .json(&json!({ "query": query, "variables": variables }))
+          .send()
+          .await?
+          .error_for_status()?
+          .json()
+          .await?;
+      if let Some(message) = body["errors"][0]["message"].as_str() {
+          return Err(anyhow::anyhow!("Snapshot query failed: {}", message));
+      }
+      Ok(body["data"].clone())
+  }
+
+  /// Submit a signed Snapshot message (a vote) to the Snapshot sequencer
+  pub async fn submit_snapshot_message(&self, envelope: &Value) -> Result<Value> {
+      let response = self.client
+          .post("https://seq.snapshot.org/")
+          .json(envelope)
+          .send()
+          .await?;
+      let status = response.status();
+      let body: Value = response.json().await.unwrap_or(Value::Null);
+      if !status.is_success() {
+          let reason = body["error_description"]
+              .as_str()
+              .or_else(|| body["error"].as_str())
+              .unwrap_or("no reason given");
+          return Err(anyhow::anyhow!("Snapshot rejected the vote ({}): {}", status, reason));
+      }
+      Ok(body)
+  }
+
+  /// An address's airdrop allocation from a claim API, None when the API has none for it
+  pub async fn get_claim_proof(&self, url: &str) -> Result<Option<Value>> {
+      let response = self.client.get(url).send().await?;
+      if response.status() == reqwest::StatusCode::NOT_FOUND {
+          return Ok(None);
+      }
+      let body: Value = response.error_for_status()?.json().await?;
+      Ok((!body.is_null()).then_some(body))
+  }
+
+  /// Text signatures registered for a 4-byte function selector (e.g. "0xa9059cbb") in the
+  /// 4byte directory, oldest first since later submissions are more often collisions
+  pub async fn lookup_function_signatures(&self, selector: &str) -> Result<Vec<String>> {
+      let body: Value = self.client
+          .get("https://www.4byte.directory/api/v1/signatures/")
+          .query(&[("hex_signature", selector), ("ordering", "created_at")])
+          .send()
+          .await?
+          .error_for_status()?
+          .json()
+          .await?;
+
+      Ok(body["results"]
+          .as_array()
+          .map(|results| {
+              results
+                  .iter()
+                  .filter_map(|result| result["text_signature"].as_str())
+                  .map(|signature| signature.to_string())
+                  .collect()
+          })
+          .unwrap_or_default())
+  }
+}
@@ -0,0 +1,570 @@
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use ethers::core::rand::{self, Rng};
+use ethers::providers::{
+    Http, HttpClientError, JsonRpcClient, JsonRpcError, ProviderError, RpcError,
+};
+use ethers::types::U64;
+use futures::future::join_all;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt;
+use std::io::ErrorKind;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+/// Transport failures in a row before an endpoint is benched
+const FAILURE_THRESHOLD: u32 = 3;
+// A benched endpoint is tried again after this, doubling each time it fails again
+const MIN_COOLDOWN: Duration = Duration::from_secs(5);
+const MAX_COOLDOWN: Duration = Duration::from_secs(300);
+// JSON-RPC code nodes use for rate limiting; worth another endpoint, unlike other errors
+const LIMIT_EXCEEDED: i64 = -32005;
+// Some providers put the HTTP status in the JSON-RPC error instead
+const TOO_MANY_REQUESTS: i64 = 429;
+
+/// How requests are retried after transient failures: rate limiting, or the
+/// connection dropping. Other errors are returned straight away.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Tries per request, including the first; 1 disables retries
+    pub max_attempts: u32,
+    /// Wait before the first retry, doubled for each one after it
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 4,
+            initial_backoff: Duration::from_millis(250),
+            max_backoff: Duration::from_secs(5),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Wait before retry number `retry` (from 1): the doubled backoff, less a random
+    /// part of up to half so clients hitting the same limit don't retry in step
+    fn backoff(&self, retry: u32) -> Duration {
+        let backoff = self
+            .initial_backoff
+            .saturating_mul(2u32.saturating_pow(retry.saturating_sub(1)))
+            .min(self.max_backoff);
+        backoff.mul_f64(1.0 - rand::thread_rng().gen_range(0.0..0.5))
+    }
+}
+
+// Ways a request can fail that are worth retrying
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Transient {
+    /// Turned away before being processed: rate limited, or the connection was refused
+    Rejected,
+    /// The connection dropped or timed out, possibly after the node got the request
+    Interrupted,
+}
+
+fn mentions_rate_limit(text: &str) -> bool {
+    let text = text.to_lowercase();
+    text.contains("rate limit") || text.contains("too many requests")
+}
+
+fn rate_limited(error: &HttpClientError) -> bool {
+    match error {
+        HttpClientError::JsonRpcError(error) => {
+            error.code == LIMIT_EXCEEDED
+                || error.code == TOO_MANY_REQUESTS
+                || mentions_rate_limit(&error.message)
+        }
+        // An HTTP 429 comes back as a body that isn't JSON-RPC
+        HttpClientError::SerdeJson { text, .. } => mentions_rate_limit(text),
+        _ => false,
+    }
+}
+
+fn transient(error: &HttpClientError) -> Option<Transient> {
+    if rate_limited(error) {
+        return Some(Transient::Rejected);
+    }
+    let HttpClientError::ReqwestError(error) = error else {
+        return None;
+    };
+    if error.is_connect() {
+        return Some(Transient::Rejected);
+    }
+    if error.is_timeout() {
+        return Some(Transient::Interrupted);
+    }
+    let mut source = std::error::Error::source(error);
+    while let Some(cause) = source {
+        if let Some(io) = cause.downcast_ref::<std::io::Error>()
+            && matches!(
+                io.kind(),
+                ErrorKind::ConnectionReset
+                    | ErrorKind::ConnectionAborted
+                    | ErrorKind::BrokenPipe
+                    | ErrorKind::UnexpectedEof
+                    | ErrorKind::TimedOut
+            )
+        {
+            return Some(Transient::Interrupted);
+        }
+        let message = cause.to_string().to_lowercase();
+        if message.contains("connection reset") || message.contains("connection closed") {
+            return Some(Transient::Interrupted);
+        }
+        source = cause.source();
+    }
+    None
+}
+
+/// An endpoint's health as seen by the server
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EndpointHealth {
+    /// Host of the endpoint; paths are left out as they often carry API keys
+    pub endpoint: String,
+    pub healthy: bool,
+    pub requests: u64,
+    pub failures: u64,
+    pub consecutive_failures: u32,
+    pub last_error: Option<String>,
+    pub last_latency_ms: Option<u64>,
+    /// Seconds until a benched endpoint is tried again
+    pub retry_in_secs: Option<u64>,
+}
+
+#[derive(Debug, Default)]
+struct EndpointState {
+    requests: u64,
+    failures: u64,
+    consecutive_failures: u32,
+    last_error: Option<String>,
+    last_latency: Option<Duration>,
+    benched_until: Option<Instant>,
+    cooldown: Option<Duration>,
+}
+
+#[derive(Debug)]
+struct Endpoint {
+    name: String,
+    client: Http,
+    state: Mutex<EndpointState>,
+}
+
+impl Endpoint {
+    fn is_available(&self, now: Instant) -> bool {
+        self.state
+            .lock()
+            .map(|state| state.benched_until.is_none_or(|until| until <= now))
+            .unwrap_or(true)
+    }
+
+    fn succeeded(&self, latency: Duration) {
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+        if state.benched_until.is_some() {
+            info!("RPC endpoint {} recovered", self.name);
+        }
+        state.requests += 1;
+        state.consecutive_failures = 0;
+        state.last_latency = Some(latency);
+        state.benched_until = None;
+        state.cooldown = None;
+    }
+
+    fn failed(&self, error: &HttpClientError) {
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+        state.requests += 1;
+        state.failures += 1;
+        state.consecutive_failures += 1;
+        state.last_error = Some(error.to_string());
+        if state.consecutive_failures >= FAILURE_THRESHOLD {
+            let cooldown = state
+                .cooldown
+                .map_or(MIN_COOLDOWN, |cooldown| (cooldown * 2).min(MAX_COOLDOWN));
+            warn!(
+                "RPC endpoint {} failed {} times in a row; benched for {}s",
+                self.name,
+                state.consecutive_failures,
+                cooldown.as_secs()
+            );
+            state.cooldown = Some(cooldown);
+            state.benched_until = Some(Instant::now() + cooldown);
+        }
+    }
+
+    fn health(&self, now: Instant) -> EndpointHealth {
+        let state = match self.state.lock() {
+            Ok(state) => state,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        let retry_in = state
+            .benched_until
+            .filter(|until| *until > now)
+            .map(|until| until - now);
+        EndpointHealth {
+            endpoint: self.name.clone(),
+            healthy: retry_in.is_none(),
+            requests: state.requests,
+            failures: state.failures,
+            consecutive_failures: state.consecutive_failures,
+            last_error: state.last_error.clone(),
+            last_latency_ms: state.last_latency.map(|latency| latency.as_millis() as u64),
+            retry_in_secs: retry_in.map(|retry_in| retry_in.as_secs().max(1)),
+        }
+    }
+}
+
+// Whether the node itself answered, so asking another endpoint would get the same reply
+fn answered(error: &HttpClientError) -> bool {
+    matches!(error, HttpClientError::JsonRpcError(_)) && !rate_limited(error)
+}
+
+#[derive(Debug)]
+pub enum FailoverError {
+    /// An endpoint's own reply: a JSON-RPC error or a response that didn't decode
+    Endpoint(HttpClientError),
+    /// No endpoint could be reached; `last` is the final endpoint's error
+    AllFailed {
+        last: HttpClientError,
+        errors: Vec<String>,
+    },
+    /// The client was built without endpoints
+    NoEndpoints,
+    /// Fewer than `needed` endpoints returned the same result
+    NoQuorum {
+        method: String,
+        needed: usize,
+        agreeing: usize,
+    },
+}
+
+impl fmt::Display for FailoverError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FailoverError::Endpoint(error) => write!(f, "{}", error),
+            FailoverError::AllFailed { last, errors } if errors.len() <= 1 => {
+                write!(f, "{}", last)
+            }
+            FailoverError::AllFailed { errors, .. } => write!(
+                f,
+                "All {} RPC endpoints failed: {}",
+                errors.len(),
+                errors.join("; ")
+            ),
+            FailoverError::NoEndpoints => write!(f, "No RPC endpoints configured"),
+            FailoverError::NoQuorum {
+                method,
+                needed,
+                agreeing,
+            } => write!(
+                f,
+                "RPC endpoints disagree on {}: {} of the {} needed returned the same result",
+                method, agreeing, needed
+            ),
+        }
+    }
+}
+
+impl FailoverError {
+    fn transient(&self) -> Option<Transient> {
+        match self {
+            FailoverError::Endpoint(error) | FailoverError::AllFailed { last: error, .. } => {
+                transient(error)
+            }
+            FailoverError::NoEndpoints | FailoverError::NoQuorum { .. } => None,
+        }
+    }
+}
+
+impl std::error::Error for FailoverError {}
+
+impl RpcError for FailoverError {
+    fn as_error_response(&self) -> Option<&JsonRpcError> {
+        match self {
+            FailoverError::Endpoint(error) | FailoverError::AllFailed { last: error, .. } => {
+                error.as_error_response()
+            }
+            FailoverError::NoEndpoints | FailoverError::NoQuorum { .. } => None,
+        }
+    }
+
+    fn as_serde_error(&self) -> Option<&serde_json::Error> {
+        match self {
+            FailoverError::Endpoint(error) | FailoverError::AllFailed { last: error, .. } => {
+                error.as_serde_error()
+            }
+            FailoverError::NoEndpoints | FailoverError::NoQuorum { .. } => None,
+        }
+    }
+}
+
+impl From<FailoverError> for ProviderError {
+    fn from(error: FailoverError) -> Self {
+        ProviderError::JsonRpcClientError(Box::new(error))
+    }
+}
+
+/// JSON-RPC transport over one or more HTTP endpoints. Requests go to the first
+/// healthy endpoint and move on to the next when one can't be reached; an endpoint
+/// that keeps failing is benched for a growing cooldown and then tried again. With a
+/// quorum above one, reads are sent to every healthy endpoint and only a result that
+/// enough of them agree on is returned. Requests that fail transiently everywhere are
+/// retried with backoff according to the client's `RetryPolicy`.
+#[derive(Debug)]
+pub struct FailoverClient {
+    endpoints: Vec<Endpoint>,
+    quorum: usize,
+    retry: RetryPolicy,
+}
+
+impl FailoverClient {
+    /// Endpoints from a comma-separated list of URLs, in order of preference
+    pub fn new(urls: &str, quorum: usize) -> Result<Self> {
+        let endpoints = urls
+            .split(',')
+            .map(str::trim)
+            .filter(|url| !url.is_empty())
+            .map(|url| {
+                let client =
+                    Http::from_str(url).map_err(|e| anyhow!("Invalid RPC URL {}: {}", url, e))?;
+                let name = reqwest::Url::parse(url)
+                    .ok()
+                    .and_then(|parsed| {
+                        let host = parsed.host_str()?.to_string();
+                        Some(match parsed.port() {
+                            Some(port) => format!("{}:{}", host, port),
+                            None => host,
+                        })
+                    })
+                    .unwrap_or_else(|| url.to_string());
+                Ok(Endpoint {
+                    name,
+                    client,
+                    state: Mutex::new(EndpointState::default()),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if endpoints.is_empty() {
+            return Err(anyhow!("No RPC URL given"));
+        }
+        if quorum > endpoints.len() {
+            return Err(anyhow!(
+                "A quorum of {} needs at least as many RPC URLs, but {} were given",
+                quorum,
+                endpoints.len()
+            ));
+        }
+
+        Ok(Self {
+            endpoints,
+            quorum: quorum.max(1),
+            retry: RetryPolicy::default(),
+        })
+    }
+
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Health of each endpoint, in order of preference
+    pub fn health(&self) -> Vec<EndpointHealth> {
+        let now = Instant::now();
+        self.endpoints
+            .iter()
+            .map(|endpoint| endpoint.health(now))
+            .collect()
+    }
+
+    // Endpoints to try, in order: available ones first, then benched ones so a request
+    // still has a chance when every endpoint is benched
+    fn candidates(&self) -> Vec<&Endpoint> {
+        let now = Instant::now();
+        let (mut available, benched): (Vec<&Endpoint>, Vec<&Endpoint>) = self
+            .endpoints
+            .iter()
+            .partition(|endpoint| endpoint.is_available(now));
+        available.extend(benched);
+        available
+    }
+
+    async fn request_quorum<T>(&self, method: &str, params: &T) -> Result<Value, FailoverError>
+    where
+        T: fmt::Debug + Serialize + Send + Sync,
+    {
+        let now = Instant::now();
+        let endpoints: Vec<&Endpoint> = self
+            .endpoints
+            .iter()
+            .filter(|endpoint| endpoint.is_available(now))
+            .collect();
+        let replies = join_all(endpoints.iter().map(|endpoint| async move {
+            let started = Instant::now();
+            let reply = endpoint.client.request::<_, Value>(method, params).await;
+            match &reply {
+                Err(error) if !answered(error) => endpoint.failed(error),
+                _ => endpoint.succeeded(started.elapsed()),
+            }
+            (endpoint.name.as_str(), reply)
+        }))
+        .await;
+
+        let mut results: Vec<Value> = Vec::new();
+        let mut node_error = None;
+        let mut transport_error = None;
+        let mut errors = Vec::new();
+        for (name, reply) in replies {
+            match reply {
+                Ok(result) => results.push(result),
+                Err(error) => {
+                    errors.push(format!("{}: {}", name, error));
+                    if answered(&error) {
+                        node_error.get_or_insert(error);
+                    } else {
+                        transport_error = Some(error);
+                    }
+                }
+            }
+        }
+
+        // Nodes a block apart would never agree on the head, so take the highest
+        // block that a quorum of them has reached
+        if method == "eth_blockNumber" {
+            let mut numbers: Vec<U64> = results
+                .iter()
+                .filter_map(|result| serde_json::from_value(result.clone()).ok())
+                .collect();
+            numbers.sort_unstable_by(|a, b| b.cmp(a));
+            if let Some(number) = numbers.get(self.quorum - 1) {
+                return Ok(serde_json::to_value(number).unwrap_or_default());
+            }
+        } else {
+            let mut best: Option<(&Value, usize)> = None;
+            for result in &results {
+                let agreeing = results.iter().filter(|other| *other == result).count();
+                if best.is_none_or(|(_, count)| agreeing > count) {
+                    best = Some((result, agreeing));
+                }
+            }
+            if let Some((result, agreeing)) = best
+                && agreeing >= self.quorum
+            {
+                return Ok(result.clone());
+            }
+        }
+
+        if let Some(error) = node_error {
+            return Err(FailoverError::Endpoint(error));
+        }
+        if results.is_empty()
+            && let Some(last) = transport_error
+        {
+            return Err(FailoverError::AllFailed { last, errors });
+        }
+        let agreeing = results
+            .iter()
+            .map(|result| results.iter().filter(|other| *other == result).count())
+            .max()
+            .unwrap_or_default();
+        Err(FailoverError::NoQuorum {
+            method: method.to_string(),
+            needed: self.quorum,
+            agreeing,
+        })
+    }
+
+    // One pass over the endpoints, or one quorum read
+    async fn request_once<T, R>(&self, method: &str, params: &T) -> Result<R, FailoverError>
+    where
+        T: fmt::Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        // Transactions are broadcast through a single endpoint; sending them everywhere
+        // would report the copies as already known
+        if self.quorum > 1 && !method.starts_with("eth_send") {
+            let result = self.request_quorum(method, params).await?;
+            return serde_json::from_value(result.clone()).map_err(|err| {
+                FailoverError::Endpoint(HttpClientError::SerdeJson {
+                    err,
+                    text: result.to_string(),
+                })
+            });
+        }
+
+        let mut errors = Vec::new();
+        let mut last = None;
+        for endpoint in self.candidates() {
+            let started = Instant::now();
+            match endpoint.client.request::<_, R>(method, params).await {
+                Ok(result) => {
+                    endpoint.succeeded(started.elapsed());
+                    return Ok(result);
+                }
+                Err(error) if answered(&error) => {
+                    endpoint.succeeded(started.elapsed());
+                    return Err(FailoverError::Endpoint(error));
+                }
+                Err(error) => {
+                    if self.endpoints.len() > 1 {
+                        warn!("{} via {} failed: {}", method, endpoint.name, error);
+                    }
+                    endpoint.failed(&error);
+                    errors.push(format!("{}: {}", endpoint.name, error));
+                    last = Some(error);
+                }
+            }
+        }
+        match last {
+            Some(last) => Err(FailoverError::AllFailed { last, errors }),
+            None => Err(FailoverError::NoEndpoints),
+        }
+    }
+}
+
+#[async_trait]
+impl JsonRpcClient for FailoverClient {
+    type Error = FailoverError;
+
+    async fn request<T, R>(&self, method: &str, params: T) -> Result<R, Self::Error>
+    where
+        T: fmt::Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        let mut attempt = 1;
+        loop {
+            let error = match self.request_once(method, &params).await {
+                Ok(result) => return Ok(result),
+                Err(error) => error,
+            };
+            // A dropped connection may have delivered a transaction already, so
+            // sends are only retried when they were turned away
+            let retry = match error.transient() {
+                Some(Transient::Rejected) => true,
+                Some(Transient::Interrupted) => !method.starts_with("eth_send"),
+                None => false,
+            };
+            if !retry || attempt >= self.retry.max_attempts {
+                return Err(error);
+            }
+            let backoff = self.retry.backoff(attempt);
+            warn!(
+                "{} failed ({}); retry {} of {} in {}ms",
+                method,
+                error,
+                attempt,
+                self.retry.max_attempts - 1,
+                backoff.as_millis()
+            );
+            tokio::time::sleep(backoff).await;
+            attempt += 1;
+        }
+    }
+}
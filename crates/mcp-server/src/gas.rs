@@ -0,0 +1,266 @@
+use anyhow::{Result, anyhow};
+use ethers::{
+    providers::Middleware,
+    types::{BlockNumber, H256, U256},
+    utils::format_units,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use crate::audit::{AuditLog, GasCost};
+use crate::blockchain::BlockchainService;
+use crate::external_apis::ExternalAPIService;
+use crate::pnl;
+
+/// Blocks of fee history looked at when the caller gives no window
+pub const DEFAULT_FEE_HISTORY_BLOCKS: u64 = 100;
+// Most nodes refuse longer eth_feeHistory ranges
+const MAX_FEE_HISTORY_BLOCKS: u64 = 1024;
+// Priority fee percentiles behind the slow, standard and fast tiers
+const PRIORITY_PERCENTILES: [f64; 3] = [10.0, 50.0, 90.0];
+// Gas of a plain ETH transfer, used to put the tiers in USD terms
+const TRANSFER_GAS: u64 = 21_000;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GasTotals {
+    pub transactions: u64,
+    pub gas_used: u64,
+    pub cost_eth: f64,
+    pub cost_usd: f64,
+    /// Transactions recorded without a USD price at execution
+    pub unpriced_transactions: u64,
+}
+
+impl GasTotals {
+    fn add(&mut self, gas_used: u64, cost: Option<&GasCost>) {
+        self.transactions += 1;
+        self.gas_used += gas_used;
+        match cost {
+            Some(cost) => {
+                self.cost_eth += cost.eth;
+                match cost.usd {
+                    Some(usd) => self.cost_usd += usd,
+                    None => self.unpriced_transactions += 1,
+                }
+            }
+            None => self.unpriced_transactions += 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GasReport {
+    pub since: i64,
+    pub total: GasTotals,
+    pub by_account: BTreeMap<String, GasTotals>,
+    pub by_method: BTreeMap<String, GasTotals>,
+    /// Keyed by UTC date (YYYY-MM-DD)
+    pub by_day: BTreeMap<String, GasTotals>,
+}
+
+/// Fee paid by a write operation's transaction, priced with the current ETH/USD rate
+pub async fn execution_cost(
+    blockchain_service: &BlockchainService,
+    external_apis: &ExternalAPIService,
+    result: &Value,
+) -> Option<GasCost> {
+    let hash = H256::from_str(&AuditLog::find_tx_hash(result)?).ok()?;
+    let receipt = blockchain_service
+        .provider()
+        .get_transaction_receipt(hash)
+        .await
+        .ok()??;
+
+    let gas_used = receipt.gas_used?;
+    let gas_price = receipt.effective_gas_price.unwrap_or(U256::zero());
+    let eth: f64 = format_units(gas_used * gas_price, "ether")
+        .ok()?
+        .parse()
+        .ok()?;
+    let usd = pnl::token_usd_price(blockchain_service, external_apis, "ETH")
+        .await
+        .map(|price| price * eth);
+
+    Some(GasCost { eth, usd })
+}
+
+/// Gas spent by recorded write operations since `since`, optionally for a single account
+pub fn gas_report(audit: &AuditLog, since: i64, account: Option<&str>) -> GasReport {
+    let mut report = GasReport {
+        since,
+        total: GasTotals::default(),
+        by_account: BTreeMap::new(),
+        by_method: BTreeMap::new(),
+        by_day: BTreeMap::new(),
+    };
+
+    for entry in audit.entries(since, account) {
+        // Failed requests that never reached the chain cost nothing
+        let Some(gas_used) = entry.gas_used else {
+            continue;
+        };
+        let cost = entry.gas_cost.as_ref();
+        let day = chrono::DateTime::from_timestamp(entry.timestamp, 0)
+            .map(|time| time.format("%Y-%m-%d").to_string())
+            .unwrap_or_default();
+
+        report.total.add(gas_used, cost);
+        report
+            .by_account
+            .entry(entry.account.clone().unwrap_or_default())
+            .or_default()
+            .add(gas_used, cost);
+        report
+            .by_method
+            .entry(entry.method.clone())
+            .or_default()
+            .add(gas_used, cost);
+        report.by_day.entry(day).or_default().add(gas_used, cost);
+    }
+
+    report
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeeTier {
+    pub max_priority_fee_per_gas_gwei: f64,
+    /// Leaves room for the base fee to double before the transaction is mined
+    pub max_fee_per_gas_gwei: f64,
+    /// Cost of a plain ETH transfer at the next base fee plus this tip
+    pub transfer_cost_eth: f64,
+    pub transfer_cost_usd: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GasPriceReport {
+    pub chain_id: u64,
+    pub block_number: u64,
+    /// Base fee of the next block
+    pub base_fee_gwei: f64,
+    /// Median and range of the base fee over the sampled blocks
+    pub median_base_fee_gwei: f64,
+    pub min_base_fee_gwei: f64,
+    pub max_base_fee_gwei: f64,
+    pub blocks_sampled: u64,
+    /// Average share of the gas limit used, 0.5 being the target
+    pub average_gas_used_ratio: f64,
+    /// Priority fees paid at the 10th, 50th and 90th percentile, as medians over the
+    /// sampled blocks, keyed "p10", "p50" and "p90"
+    pub priority_fee_percentiles_gwei: BTreeMap<String, f64>,
+    pub slow: FeeTier,
+    pub standard: FeeTier,
+    pub fast: FeeTier,
+    /// "low", "normal" or "high": the next base fee against the sampled median
+    pub assessment: String,
+}
+
+fn gwei(value: U256) -> Result<f64> {
+    Ok(format_units(value, "gwei")?.parse()?)
+}
+
+fn median(mut values: Vec<f64>) -> f64 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    values.sort_by(|a, b| a.total_cmp(b));
+    let middle = values.len() / 2;
+    if values.len().is_multiple_of(2) {
+        (values[middle - 1] + values[middle]) / 2.0
+    } else {
+        values[middle]
+    }
+}
+
+/// Current gas prices from `eth_feeHistory` over the last `blocks` blocks: the next
+/// base fee, how it compares to the recent median, and slow/standard/fast tips taken
+/// from the priority fee percentiles recent transactions paid
+pub async fn get_gas_price(
+    blockchain_service: &BlockchainService,
+    external_apis: &ExternalAPIService,
+    blocks: u64,
+) -> Result<GasPriceReport> {
+    if blocks == 0 || blocks > MAX_FEE_HISTORY_BLOCKS {
+        return Err(anyhow!(
+            "blocks must be between 1 and {}",
+            MAX_FEE_HISTORY_BLOCKS
+        ));
+    }
+
+    let provider = blockchain_service.provider();
+    let history = provider
+        .fee_history(blocks, BlockNumber::Latest, &PRIORITY_PERCENTILES)
+        .await
+        .map_err(|e| anyhow!("This network doesn't support eth_feeHistory: {}", e))?;
+
+    // The last entry is the base fee of the next block, the rest belong to sampled ones
+    let (base_fee, sampled) = history
+        .base_fee_per_gas
+        .split_last()
+        .ok_or_else(|| anyhow!("Fee history returned no base fees"))?;
+    if base_fee.is_zero() {
+        return Err(anyhow!(
+            "This network has no base fee; use estimate_gas for legacy gas prices"
+        ));
+    }
+    let base_fee_gwei = gwei(*base_fee)?;
+    let sampled: Vec<f64> = sampled
+        .iter()
+        .map(|fee| gwei(*fee))
+        .collect::<Result<_>>()?;
+    let median_base_fee_gwei = median(sampled.clone());
+
+    // Empty blocks report zero tips; leave them out rather than drag the medians down
+    let mut percentiles = Vec::new();
+    for (i, percentile) in PRIORITY_PERCENTILES.iter().enumerate() {
+        let rewards: Vec<f64> = history
+            .reward
+            .iter()
+            .filter_map(|rewards| rewards.get(i).copied())
+            .filter(|reward| !reward.is_zero())
+            .map(gwei)
+            .collect::<Result<_>>()?;
+        percentiles.push((format!("p{}", percentile), median(rewards)));
+    }
+
+    let eth_usd = pnl::token_usd_price(blockchain_service, external_apis, "ETH").await;
+    let tier = |priority_fee: f64| -> FeeTier {
+        let transfer_cost_eth = (base_fee_gwei + priority_fee) * TRANSFER_GAS as f64 / 1e9;
+        FeeTier {
+            max_priority_fee_per_gas_gwei: priority_fee,
+            max_fee_per_gas_gwei: base_fee_gwei * 2.0 + priority_fee,
+            transfer_cost_eth,
+            transfer_cost_usd: eth_usd.map(|price| price * transfer_cost_eth),
+        }
+    };
+
+    let assessment = if base_fee_gwei < median_base_fee_gwei * 0.8 {
+        "low"
+    } else if base_fee_gwei > median_base_fee_gwei * 1.25 {
+        "high"
+    } else {
+        "normal"
+    };
+    let ratios = &history.gas_used_ratio;
+
+    Ok(GasPriceReport {
+        chain_id: blockchain_service.chain_id(),
+        block_number: provider.get_block_number().await?.as_u64(),
+        base_fee_gwei,
+        median_base_fee_gwei,
+        min_base_fee_gwei: sampled.iter().copied().fold(f64::INFINITY, f64::min),
+        max_base_fee_gwei: sampled.iter().copied().fold(0.0, f64::max),
+        blocks_sampled: sampled.len() as u64,
+        average_gas_used_ratio: if ratios.is_empty() {
+            0.0
+        } else {
+            ratios.iter().sum::<f64>() / ratios.len() as f64
+        },
+        slow: tier(percentiles[0].1),
+        standard: tier(percentiles[1].1),
+        fast: tier(percentiles[2].1),
+        priority_fee_percentiles_gwei: percentiles.into_iter().collect(),
+        assessment: assessment.to_string(),
+    })
+}
@@ -0,0 +1,669 @@
+use anyhow::{Result, anyhow};
+use ethers::abi::{Abi, ParamType, Token, decode, parse_abi};
+use ethers::contract::Contract;
+use ethers::providers::{Middleware, Provider};
+use ethers::types::{Address, Filter, H256, U256};
+use ethers::utils::{format_units, keccak256};
+use serde::{Deserialize, Serialize};
+use shared::{Account, ContractCall, TransactionResult};
+use std::str::FromStr;
+use tracing::warn;
+
+use crate::blockchain::{BlockchainService, FeeOverrides};
+use crate::failover::FailoverClient;
+use crate::scanner::{CheckpointStore, LogScanner, ScanConfig};
+
+/// Proposals returned when the caller gives no limit
+pub const DEFAULT_PROPOSAL_LIMIT: usize = 10;
+const MAX_PROPOSAL_LIMIT: usize = 50;
+// ProposalCreated is looked for this many blocks either side of where votingDelay puts it,
+// since the delay may have changed since the proposal was made
+const PROPOSAL_SEARCH_SLACK: u64 = 50_000;
+const SECONDS_PER_BLOCK: u64 = 12;
+
+const GOVERNOR_ABI: &[&str] = &[
+    "function proposalCount() view returns (uint256)",
+    "function proposals(uint256 proposalId) view returns (uint256 id, address proposer, uint256 eta, uint256 startBlock, uint256 endBlock, uint256 forVotes, uint256 againstVotes, uint256 abstainVotes, bool canceled, bool executed)",
+    "function state(uint256 proposalId) view returns (uint8)",
+    "function getReceipt(uint256 proposalId, address voter) view returns (bool hasVoted, uint8 support, uint96 votes)",
+    "function votingDelay() view returns (uint256)",
+    "function quorumVotes() view returns (uint256)",
+];
+const VOTES_TOKEN_ABI: &[&str] = &[
+    "function symbol() view returns (string)",
+    "function decimals() view returns (uint8)",
+    "function balanceOf(address owner) view returns (uint256)",
+    "function delegates(address delegator) view returns (address)",
+    "function getCurrentVotes(address account) view returns (uint96)",
+    "function getPriorVotes(address account, uint256 blockNumber) view returns (uint96)",
+];
+// Bravo forks name the governance token's getter after the token
+const TOKEN_GETTERS: &[&str] = &["comp", "uni", "token"];
+const PROPOSAL_STATES: &[&str] = &[
+    "pending",
+    "active",
+    "canceled",
+    "defeated",
+    "succeeded",
+    "queued",
+    "expired",
+    "executed",
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GovernorConfig {
+    pub name: String,
+    pub chain_id: u64,
+    /// GovernorBravo proxy
+    pub address: String,
+    /// Votes token; looked up from the governor when omitted
+    #[serde(default)]
+    pub token: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct GovernanceConfig {
+    pub governors: Vec<GovernorConfig>,
+}
+
+impl GovernanceConfig {
+    pub fn presets() -> Vec<GovernorConfig> {
+        let preset = |name: &str, address: &str, token: &str| GovernorConfig {
+            name: name.to_string(),
+            chain_id: 1,
+            address: address.to_string(),
+            token: Some(token.to_string()),
+        };
+
+        vec![
+            preset(
+                "compound",
+                "0xc0Da02939E1441F497fd74F78cE7Decb17B66529",
+                "0xc00e94Cb662C3520282E6f5717aed2d3b4B4b5f2",
+            ),
+            preset(
+                "uniswap",
+                "0x408ED6354d4973f66138C91495F2f2FCbd8724C3",
+                "0x1f9840a85d5aF5bf1D1762F925BDADdC4201F984",
+            ),
+        ]
+    }
+
+    /// The presets plus any governors listed in the JSON file at `GOVERNORS_FILE`
+    pub fn from_env() -> Self {
+        let mut governors = Self::presets();
+        if let Ok(path) = std::env::var("GOVERNORS_FILE") {
+            match std::fs::read_to_string(&path)
+                .map_err(anyhow::Error::from)
+                .and_then(|content| Ok(serde_json::from_str::<Vec<GovernorConfig>>(&content)?))
+            {
+                Ok(extra) => governors.extend(extra),
+                Err(e) => warn!("Ignoring governors file {}: {}", path, e),
+            }
+        }
+        Self { governors }
+    }
+
+    /// A configured governor by name or address, or an unlisted GovernorBravo address
+    pub fn resolve(&self, governor: &str, chain_id: u64) -> Result<GovernorConfig> {
+        let governor = governor.trim();
+        // Anvil forks mainnet, so mainnet governors are usable there too
+        let on_chain = |config: &&GovernorConfig| {
+            config.chain_id == chain_id || (config.chain_id == 1 && chain_id == 31337)
+        };
+        if let Some(config) = self.governors.iter().filter(on_chain).find(|config| {
+            config.name.eq_ignore_ascii_case(governor)
+                || config.address.eq_ignore_ascii_case(governor)
+        }) {
+            return Ok(config.clone());
+        }
+        if Address::from_str(governor).is_ok() {
+            return Ok(GovernorConfig {
+                name: governor.to_string(),
+                chain_id,
+                address: governor.to_string(),
+                token: None,
+            });
+        }
+        let known: Vec<&str> = self
+            .governors
+            .iter()
+            .filter(on_chain)
+            .map(|config| config.name.as_str())
+            .collect();
+        Err(anyhow!(
+            "Unknown governor '{}'; use a GovernorBravo address or one of: {}",
+            governor,
+            known.join(", ")
+        ))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GovernanceProposal {
+    pub id: String,
+    /// "pending", "active", "canceled", "defeated", "succeeded", "queued", "expired" or "executed"
+    pub state: String,
+    pub proposer: String,
+    /// First line of the description, without markdown heading marks
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub start_block: u64,
+    pub end_block: u64,
+    /// Rough time left to vote, from the remaining blocks; None once voting has ended
+    pub voting_ends_in_secs: Option<u64>,
+    pub for_votes: String,
+    pub against_votes: String,
+    pub abstain_votes: String,
+    pub quorum_votes: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProposalList {
+    pub governor: String,
+    pub address: String,
+    pub proposal_count: u64,
+    pub proposals: Vec<GovernanceProposal>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VotingPower {
+    pub governor: String,
+    pub account: String,
+    pub token: String,
+    pub token_balance: String,
+    /// Votes delegated to the account right now
+    pub current_votes: String,
+    /// Address the account's own tokens are delegated to, if any
+    pub delegate: Option<String>,
+    pub proposal_id: Option<String>,
+    /// Votes the account can cast on the proposal, fixed at its start block
+    pub votes_for_proposal: Option<String>,
+    pub has_voted: Option<bool>,
+    /// "for", "against" or "abstain", once the account has voted
+    pub vote: Option<String>,
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CastVote {
+    pub transaction: TransactionResult,
+    pub governor: String,
+    pub proposal_id: String,
+    pub support: String,
+    pub votes: String,
+    pub reason: String,
+}
+
+struct Governor<'a> {
+    blockchain_service: &'a BlockchainService,
+    config: GovernorConfig,
+    contract: Contract<Provider<FailoverClient>>,
+}
+
+struct VotesToken {
+    contract: Contract<Provider<FailoverClient>>,
+    symbol: String,
+    decimals: u8,
+}
+
+// Fields of the governor's proposals(id) getter
+struct ProposalRecord {
+    proposer: Address,
+    start_block: U256,
+    end_block: U256,
+    for_votes: U256,
+    against_votes: U256,
+    abstain_votes: U256,
+}
+
+impl<'a> Governor<'a> {
+    fn new(blockchain_service: &'a BlockchainService, config: GovernorConfig) -> Result<Self> {
+        let address = Address::from_str(&config.address)
+            .map_err(|_| anyhow!("Invalid governor address: {}", config.address))?;
+        let abi: Abi = parse_abi(GOVERNOR_ABI)?;
+        Ok(Self {
+            blockchain_service,
+            contract: Contract::new(address, abi, blockchain_service.provider()),
+            config,
+        })
+    }
+
+    async fn state(&self, id: U256) -> Result<&'static str> {
+        let state: u8 = self
+            .contract
+            .method("state", id)?
+            .call()
+            .await
+            .map_err(|e| anyhow!("Proposal {} not found on {}: {}", id, self.config.name, e))?;
+        PROPOSAL_STATES
+            .get(state as usize)
+            .copied()
+            .ok_or_else(|| anyhow!("Unknown proposal state {}", state))
+    }
+
+    async fn record(&self, id: U256) -> Result<ProposalRecord> {
+        let (_, proposer, _, start_block, end_block, for_votes, against_votes, abstain_votes, _, _): (
+            U256,
+            Address,
+            U256,
+            U256,
+            U256,
+            U256,
+            U256,
+            U256,
+            bool,
+            bool,
+        ) = self.contract.method("proposals", id)?.call().await?;
+        Ok(ProposalRecord {
+            proposer,
+            start_block,
+            end_block,
+            for_votes,
+            against_votes,
+            abstain_votes,
+        })
+    }
+
+    async fn token(&self) -> Result<VotesToken> {
+        let address = match &self.config.token {
+            Some(token) => Address::from_str(token)
+                .map_err(|_| anyhow!("Invalid votes token address: {}", token))?,
+            None => self.find_token().await?,
+        };
+        let abi: Abi = parse_abi(VOTES_TOKEN_ABI)?;
+        let contract = Contract::new(address, abi, self.blockchain_service.provider());
+        let symbol: String = contract
+            .method("symbol", ())?
+            .call()
+            .await
+            .unwrap_or_else(|_| "votes".to_string());
+        let decimals: u8 = contract.method("decimals", ())?.call().await.unwrap_or(18);
+        Ok(VotesToken {
+            contract,
+            symbol,
+            decimals,
+        })
+    }
+
+    async fn find_token(&self) -> Result<Address> {
+        for getter in TOKEN_GETTERS {
+            let abi: Abi = parse_abi(&[&format!("function {}() view returns (address)", getter)])?;
+            let contract = Contract::new(
+                self.contract.address(),
+                abi,
+                self.blockchain_service.provider(),
+            );
+            if let Ok(address) = contract.method::<_, Address>(getter, ())?.call().await {
+                return Ok(address);
+            }
+        }
+        Err(anyhow!(
+            "Couldn't find {}'s votes token; add the governor with its token to GOVERNORS_FILE",
+            self.config.name
+        ))
+    }
+
+    async fn proposal(
+        &self,
+        id: U256,
+        latest: u64,
+        quorum: &str,
+        token: &VotesToken,
+    ) -> Result<GovernanceProposal> {
+        let state = self.state(id).await?;
+        let record = self.record(id).await?;
+        let description = self.description(id, record.start_block).await;
+        let end_block = record.end_block.as_u64();
+
+        Ok(GovernanceProposal {
+            id: id.to_string(),
+            state: state.to_string(),
+            proposer: format!("{:?}", record.proposer),
+            title: description.as_deref().map(title),
+            description,
+            start_block: record.start_block.as_u64(),
+            end_block,
+            voting_ends_in_secs: (state == "active" || state == "pending")
+                .then(|| end_block.saturating_sub(latest) * SECONDS_PER_BLOCK),
+            for_votes: token.amount(record.for_votes),
+            against_votes: token.amount(record.against_votes),
+            abstain_votes: token.amount(record.abstain_votes),
+            quorum_votes: quorum.to_string(),
+        })
+    }
+
+    // The description only lives in the ProposalCreated event, emitted votingDelay
+    // blocks before voting starts
+    async fn description(&self, id: U256, start_block: U256) -> Option<String> {
+        let delay: U256 = self
+            .contract
+            .method("votingDelay", ())
+            .ok()?
+            .call()
+            .await
+            .unwrap_or_default();
+        let created = start_block.saturating_sub(delay).as_u64();
+        let from_block = created.saturating_sub(PROPOSAL_SEARCH_SLACK);
+        let to_block = start_block.as_u64();
+
+        let event = H256::from(keccak256(
+            "ProposalCreated(uint256,address,address[],uint256[],string[],bytes[],uint256,uint256,string)",
+        ));
+        let filter = Filter::new().address(self.contract.address()).topic0(event);
+        let fields = [
+            ParamType::Uint(256),
+            ParamType::Address,
+            ParamType::Array(Box::new(ParamType::Address)),
+            ParamType::Array(Box::new(ParamType::Uint(256))),
+            ParamType::Array(Box::new(ParamType::String)),
+            ParamType::Array(Box::new(ParamType::Bytes)),
+            ParamType::Uint(256),
+            ParamType::Uint(256),
+            ParamType::String,
+        ];
+
+        let mut description = None;
+        let checkpoints = CheckpointStore::in_memory();
+        let scan = LogScanner::new(self.blockchain_service.provider(), &checkpoints)
+            .with_config(ScanConfig {
+                chunk_size: PROPOSAL_SEARCH_SLACK,
+                ..ScanConfig::default()
+            })
+            .scan("governance", &filter, from_block, Some(to_block), |logs| {
+                for log in logs {
+                    let Ok(tokens) = decode(&fields, &log.data) else {
+                        continue;
+                    };
+                    if let (Some(Token::Uint(created_id)), Some(Token::String(text))) =
+                        (tokens.first(), tokens.last())
+                        && *created_id == id
+                    {
+                        description = Some(text.clone());
+                    }
+                }
+                Ok(())
+            })
+            .await;
+        if let Err(e) = scan {
+            warn!("Couldn't read proposal {}'s description: {}", id, e);
+        }
+        description
+    }
+}
+
+impl VotesToken {
+    fn amount(&self, raw: U256) -> String {
+        format_units(raw, self.decimals as u32).unwrap_or_else(|_| raw.to_string())
+    }
+
+    async fn prior_votes(&self, account: Address, block: U256) -> Result<U256> {
+        Ok(self
+            .contract
+            .method("getPriorVotes", (account, block))?
+            .call()
+            .await?)
+    }
+}
+
+// First line of a proposal description, without markdown heading marks
+fn title(description: &str) -> String {
+    description
+        .lines()
+        .map(|line| line.trim().trim_start_matches('#').trim())
+        .find(|line| !line.is_empty())
+        .unwrap_or_default()
+        .to_string()
+}
+
+fn parse_proposal_id(id: &str) -> Result<U256> {
+    U256::from_dec_str(id.trim()).map_err(|_| anyhow!("Invalid proposal id: {}", id))
+}
+
+fn support_name(support: u8) -> &'static str {
+    match support {
+        0 => "against",
+        1 => "for",
+        _ => "abstain",
+    }
+}
+
+/// The governor's latest proposals, newest first; only those open for voting unless
+/// `include_closed` is set. Descriptions come from each proposal's ProposalCreated event.
+pub async fn list_proposals(
+    blockchain_service: &BlockchainService,
+    config: GovernorConfig,
+    include_closed: bool,
+    limit: usize,
+) -> Result<ProposalList> {
+    if limit == 0 || limit > MAX_PROPOSAL_LIMIT {
+        return Err(anyhow!(
+            "limit must be between 1 and {}",
+            MAX_PROPOSAL_LIMIT
+        ));
+    }
+    let governor = Governor::new(blockchain_service, config)?;
+    let count: U256 = governor
+        .contract
+        .method("proposalCount", ())?
+        .call()
+        .await?;
+    let token = governor.token().await?;
+    let quorum: U256 = governor.contract.method("quorumVotes", ())?.call().await?;
+    let quorum = token.amount(quorum);
+    let latest = blockchain_service
+        .provider()
+        .get_block_number()
+        .await?
+        .as_u64();
+
+    let mut proposals = Vec::new();
+    let mut id = count;
+    while !id.is_zero() && proposals.len() < limit {
+        let state = governor.state(id).await?;
+        if include_closed || matches!(state, "pending" | "active") {
+            proposals.push(governor.proposal(id, latest, &quorum, &token).await?);
+        } else if !matches!(state, "queued" | "succeeded") {
+            // Ids are sequential, so voting on older proposals ended before this one's
+            // did; a finished one means none further back are still open
+            let record = governor.record(id).await?;
+            if record.end_block.as_u64() < latest {
+                break;
+            }
+        }
+        id -= U256::one();
+    }
+
+    Ok(ProposalList {
+        governor: governor.config.name.clone(),
+        address: governor.config.address.clone(),
+        proposal_count: count.as_u64(),
+        proposals,
+    })
+}
+
+/// One proposal with its full description and vote tallies
+pub async fn get_proposal(
+    blockchain_service: &BlockchainService,
+    config: GovernorConfig,
+    proposal_id: &str,
+) -> Result<GovernanceProposal> {
+    let id = parse_proposal_id(proposal_id)?;
+    let governor = Governor::new(blockchain_service, config)?;
+    let token = governor.token().await?;
+    let quorum: U256 = governor.contract.method("quorumVotes", ())?.call().await?;
+    let latest = blockchain_service
+        .provider()
+        .get_block_number()
+        .await?
+        .as_u64();
+    governor
+        .proposal(id, latest, &token.amount(quorum), &token)
+        .await
+}
+
+/// An account's governance token balance, delegated votes and delegate; with a
+/// proposal, also the votes it can cast on it and whether it already has
+pub async fn get_voting_power(
+    blockchain_service: &BlockchainService,
+    config: GovernorConfig,
+    account: &str,
+    proposal_id: Option<&str>,
+) -> Result<VotingPower> {
+    let account =
+        Address::from_str(account).map_err(|_| anyhow!("Invalid account: {}", account))?;
+    let governor = Governor::new(blockchain_service, config)?;
+    let token = governor.token().await?;
+
+    let balance: U256 = token.contract.method("balanceOf", account)?.call().await?;
+    let current: U256 = token
+        .contract
+        .method("getCurrentVotes", account)?
+        .call()
+        .await?;
+    let delegate: Option<Address> = token
+        .contract
+        .method::<_, Address>("delegates", account)?
+        .call()
+        .await
+        .ok()
+        .filter(|delegate| !delegate.is_zero());
+
+    let mut power = VotingPower {
+        governor: governor.config.name.clone(),
+        account: format!("{:?}", account),
+        token: token.symbol.clone(),
+        token_balance: token.amount(balance),
+        current_votes: token.amount(current),
+        delegate: delegate.map(|delegate| format!("{:?}", delegate)),
+        proposal_id: None,
+        votes_for_proposal: None,
+        has_voted: None,
+        vote: None,
+        note: None,
+    };
+    if !balance.is_zero() && delegate.is_none() {
+        power.note = Some(format!(
+            "The account holds {} but hasn't delegated, so it has no votes from them; delegating to itself enables voting on proposals created afterwards",
+            token.symbol
+        ));
+    }
+
+    if let Some(proposal_id) = proposal_id {
+        let id = parse_proposal_id(proposal_id)?;
+        let record = governor.record(id).await?;
+        let latest = blockchain_service
+            .provider()
+            .get_block_number()
+            .await?
+            .as_u64();
+        // getPriorVotes only answers for mined blocks
+        let votes = if record.start_block.as_u64() < latest {
+            token.prior_votes(account, record.start_block).await?
+        } else {
+            current
+        };
+        let (has_voted, support, _): (bool, u8, u128) = governor
+            .contract
+            .method("getReceipt", (id, account))?
+            .call()
+            .await?;
+        power.proposal_id = Some(id.to_string());
+        power.votes_for_proposal = Some(token.amount(votes));
+        power.has_voted = Some(has_voted);
+        power.vote = has_voted.then(|| support_name(support).to_string());
+    }
+
+    Ok(power)
+}
+
+/// Vote on an active proposal from a named account with castVoteWithReason. `support`
+/// is "for", "against" or "abstain". Refused when the account has no votes on the
+/// proposal or has already voted.
+pub async fn cast_vote(
+    blockchain_service: &BlockchainService,
+    config: GovernorConfig,
+    from_account: &Account,
+    proposal_id: &str,
+    support: &str,
+    reason: &str,
+    fees: &FeeOverrides,
+) -> Result<CastVote> {
+    let id = parse_proposal_id(proposal_id)?;
+    let support_value: u8 = match support.trim().to_lowercase().as_str() {
+        "against" => 0,
+        "for" => 1,
+        "abstain" => 2,
+        _ => {
+            return Err(anyhow!(
+                "support must be \"for\", \"against\" or \"abstain\", got '{}'",
+                support
+            ));
+        }
+    };
+    let voter = Address::from_str(&from_account.address)?;
+    let governor = Governor::new(blockchain_service, config)?;
+
+    let state = governor.state(id).await?;
+    if state != "active" {
+        return Err(anyhow!(
+            "Proposal {} is {}; votes can only be cast while it's active",
+            id,
+            state
+        ));
+    }
+    let (has_voted, previous, _): (bool, u8, u128) = governor
+        .contract
+        .method("getReceipt", (id, voter))?
+        .call()
+        .await?;
+    if has_voted {
+        return Err(anyhow!(
+            "{} already voted {} on proposal {}",
+            from_account.name,
+            support_name(previous),
+            id
+        ));
+    }
+    let record = governor.record(id).await?;
+    let token = governor.token().await?;
+    let votes = token.prior_votes(voter, record.start_block).await?;
+    if votes.is_zero() {
+        return Err(anyhow!(
+            "{} had no {} votes delegated to it when proposal {} started, so its vote wouldn't count",
+            from_account.name,
+            token.symbol,
+            id
+        ));
+    }
+
+    let call = ContractCall {
+        contract_address: governor.config.address.clone(),
+        function_signature: "castVoteWithReason(uint256,uint8,string)".to_string(),
+        parameters: vec![
+            id.to_string(),
+            support_value.to_string(),
+            reason.to_string(),
+        ],
+        from: None,
+    };
+    let transaction = blockchain_service
+        .write_contract(
+            from_account,
+            &call,
+            "0",
+            None,
+            &|name| name.to_string(),
+            fees,
+        )
+        .await?;
+
+    Ok(CastVote {
+        transaction,
+        governor: governor.config.name.clone(),
+        proposal_id: id.to_string(),
+        support: support_name(support_value).to_string(),
+        votes: token.amount(votes),
+        reason: reason.to_string(),
+    })
+}
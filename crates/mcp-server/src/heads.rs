@@ -0,0 +1,253 @@
+use anyhow::{Result, anyhow};
+use ethers::providers::{Middleware, StreamExt};
+use ethers::types::{Block, BlockNumber, H256};
+use ethers::utils::format_units;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tokio::sync::watch;
+use tracing::{info, warn};
+
+use crate::blockchain::BlockchainService;
+use crate::inbox::InboxStore;
+use crate::pending::{self, PendingStore};
+use crate::tenants::TenantRegistry;
+
+// Longest wait between WebSocket reconnect attempts
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(60);
+// Transactions younger than this are left to the request that sent them
+const PENDING_GRACE_SECS: i64 = 30;
+
+/// The latest block seen on a chain
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChainHead {
+    pub chain_id: u64,
+    pub block_number: u64,
+    pub hash: String,
+    pub timestamp: u64,
+    pub base_fee_gwei: Option<String>,
+    /// "websocket" or "polling"
+    pub source: String,
+    /// When the server saw the block
+    pub received_at: i64,
+}
+
+// A service's view of its chain head, kept fresh by `spawn_head_subscription`.
+// Transaction waits watch it to check for receipts as blocks arrive.
+pub struct HeadTracker {
+    head: watch::Sender<Option<ChainHead>>,
+    live: AtomicBool,
+    // Transactions a request is waiting on; the head task leaves them alone
+    awaiting: Mutex<HashSet<H256>>,
+}
+
+impl Default for HeadTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HeadTracker {
+    pub fn new() -> Self {
+        Self {
+            head: watch::channel(None).0,
+            live: AtomicBool::new(false),
+            awaiting: Mutex::new(HashSet::new()),
+        }
+    }
+
+    pub fn latest(&self) -> Option<ChainHead> {
+        self.head.borrow().clone()
+    }
+
+    /// Receiver notified of every new head
+    pub fn subscribe(&self) -> watch::Receiver<Option<ChainHead>> {
+        self.head.subscribe()
+    }
+
+    /// Whether a head subscription is running, so waits can rely on new heads arriving
+    pub fn is_live(&self) -> bool {
+        self.live.load(Ordering::Relaxed)
+    }
+
+    fn publish(&self, head: ChainHead) {
+        self.head.send_replace(Some(head));
+    }
+
+    pub(crate) fn begin_wait(&self, hash: H256) {
+        if let Ok(mut awaiting) = self.awaiting.lock() {
+            awaiting.insert(hash);
+        }
+    }
+
+    pub(crate) fn end_wait(&self, hash: H256) {
+        if let Ok(mut awaiting) = self.awaiting.lock() {
+            awaiting.remove(&hash);
+        }
+    }
+
+    fn is_awaited(&self, hash: &str) -> bool {
+        hash.parse::<H256>().is_ok_and(|hash| {
+            self.awaiting
+                .lock()
+                .is_ok_and(|awaiting| awaiting.contains(&hash))
+        })
+    }
+}
+
+fn chain_head(chain_id: u64, block: &Block<H256>, source: &str) -> Option<ChainHead> {
+    Some(ChainHead {
+        chain_id,
+        block_number: block.number?.as_u64(),
+        hash: format!("{:#x}", block.hash?),
+        timestamp: block.timestamp.as_u64(),
+        base_fee_gwei: block
+            .base_fee_per_gas
+            .and_then(|fee| format_units(fee, "gwei").ok()),
+        source: source.to_string(),
+        received_at: chrono::Utc::now().timestamp(),
+    })
+}
+
+/// Follow the service's chain head over `ws_url` with `eth_subscribe("newHeads")`,
+/// reconnecting with backoff when the socket drops and polling the HTTP provider in
+/// the meantime (or throughout when there is no WebSocket endpoint). On each head,
+/// transactions sent since startup that no request is still waiting on are resolved
+/// and reported to their sender's inbox.
+pub fn spawn_head_subscription(
+    service: Arc<BlockchainService>,
+    ws_url: Option<String>,
+    tenants: Arc<TenantRegistry>,
+    store: Arc<PendingStore>,
+    inbox: Arc<InboxStore>,
+) {
+    let started_at = chrono::Utc::now().timestamp();
+    let on_head = move |service: Arc<BlockchainService>, head: ChainHead| {
+        let tenants = tenants.clone();
+        let store = store.clone();
+        let inbox = inbox.clone();
+        async move {
+            service.heads().publish(head.clone());
+            resolve_pending(&service, &head, started_at, &tenants, &store, &inbox).await;
+        }
+    };
+
+    service.heads().live.store(true, Ordering::Relaxed);
+    tokio::spawn(async move {
+        let poll_interval = service.rpc_settings().poll_interval;
+        let mut retry_delay = poll_interval;
+        loop {
+            let Some(url) = &ws_url else {
+                poll_heads(&service, &on_head, None).await;
+                return;
+            };
+            match follow_ws(&service, url, &on_head).await {
+                // The stream ran until the node closed it; reconnect promptly
+                Ok(()) => {
+                    warn!("Head subscription for chain {} closed", service.chain_id());
+                    retry_delay = poll_interval;
+                }
+                Err(e) => {
+                    warn!(
+                        "Head subscription for chain {} failed: {}",
+                        service.chain_id(),
+                        e
+                    );
+                    retry_delay = (retry_delay * 2).min(MAX_RECONNECT_DELAY);
+                }
+            }
+            // Keep heads coming over HTTP until the socket is retried
+            poll_heads(&service, &on_head, Some(retry_delay)).await;
+        }
+    });
+}
+
+// Publish the latest block over HTTP every poll interval, for `duration` or forever
+async fn poll_heads<F, Fut>(
+    service: &Arc<BlockchainService>,
+    on_head: &F,
+    duration: Option<Duration>,
+) where
+    F: Fn(Arc<BlockchainService>, ChainHead) -> Fut,
+    Fut: std::future::Future<Output = ()>,
+{
+    let until = duration.map(|duration| tokio::time::Instant::now() + duration);
+    let mut ticker = tokio::time::interval(service.rpc_settings().poll_interval);
+    let mut last = None;
+    while until.is_none_or(|until| tokio::time::Instant::now() < until) {
+        ticker.tick().await;
+        match service.provider().get_block(BlockNumber::Latest).await {
+            Ok(Some(block)) if block.hash != last => {
+                last = block.hash;
+                if let Some(head) = chain_head(service.chain_id(), &block, "polling") {
+                    on_head(service.clone(), head).await;
+                }
+            }
+            Ok(_) => {}
+            Err(e) => warn!("Polling chain {} head failed: {}", service.chain_id(), e),
+        }
+    }
+}
+
+async fn follow_ws<F, Fut>(service: &Arc<BlockchainService>, url: &str, on_head: &F) -> Result<()>
+where
+    F: Fn(Arc<BlockchainService>, ChainHead) -> Fut,
+    Fut: std::future::Future<Output = ()>,
+{
+    let provider = crate::create_ws_provider(url).await?;
+    let chain_id = provider.get_chainid().await?.as_u64();
+    if chain_id != service.chain_id() {
+        return Err(anyhow!(
+            "{} serves chain {}, not {}",
+            url,
+            chain_id,
+            service.chain_id()
+        ));
+    }
+    let mut blocks = provider.subscribe_blocks().await?;
+    info!("Subscribed to chain {} heads at {}", chain_id, url);
+
+    while let Some(block) = blocks.next().await {
+        if let Some(head) = chain_head(chain_id, &block, "websocket") {
+            on_head(service.clone(), head).await;
+        }
+    }
+    Ok(())
+}
+
+// Resolve transactions on this chain that were sent this run but whose request
+// stopped waiting (timed out, errored or was cancelled), recording the outcome
+async fn resolve_pending(
+    service: &BlockchainService,
+    head: &ChainHead,
+    started_at: i64,
+    tenants: &TenantRegistry,
+    store: &PendingStore,
+    inbox: &InboxStore,
+) {
+    let provider = service.provider();
+    for transaction in store.list() {
+        if transaction.chain_id != head.chain_id
+            || transaction.submitted_at < started_at
+            || head.received_at - transaction.submitted_at < PENDING_GRACE_SECS
+            || service.heads().is_awaited(&transaction.hash)
+        {
+            continue;
+        }
+        match pending::resolve_with(&provider, &transaction).await {
+            Ok(Some((resolution, block_number))) => pending::settle(
+                tenants,
+                store,
+                inbox,
+                &transaction,
+                resolution,
+                block_number,
+            ),
+            Ok(None) => {}
+            Err(e) => warn!("Failed to check pending {}: {}", transaction.hash, e),
+        }
+    }
+}
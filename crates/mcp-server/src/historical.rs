@@ -0,0 +1,183 @@
+use anyhow::{Result, anyhow};
+use ethers::{providers::Middleware, types::U256, utils::format_units};
+use serde_json::{Value, json};
+use tracing::warn;
+
+use crate::blockchain::BlockchainService;
+use crate::external_apis::ExternalAPIService;
+
+// Stablecoin used to quote on-chain historical prices in USD
+const USD_QUOTE_TOKEN: &str = "USDC";
+
+/// A block number given as a JSON number or a string such as "19,000,000" or "0x121eac0".
+/// Missing, null and "latest" mean the current block and come back as None.
+pub fn parse_block(value: &Value) -> Result<Option<u64>> {
+    if value.is_null() {
+        return Ok(None);
+    }
+    if let Some(block) = value.as_u64() {
+        return Ok(Some(block));
+    }
+    let text = value
+        .as_str()
+        .ok_or_else(|| anyhow!("Invalid block {}: use a block number", value))?;
+    let digits: String = text
+        .trim()
+        .chars()
+        .filter(|c| !matches!(c, ',' | '_' | ' '))
+        .collect();
+    if digits.is_empty() || digits.eq_ignore_ascii_case("latest") {
+        return Ok(None);
+    }
+    let block = match digits.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => digits.parse(),
+    };
+    block.map(Some).map_err(|_| {
+        anyhow!(
+            "Invalid block '{}': use a block number, resolving dates like \"yesterday\" to a block first",
+            text
+        )
+    })
+}
+
+/// Balance of an address at a past block. Reads state from the RPC when it is an archive
+/// node, otherwise falls back to Etherscan balance history; the result names its source.
+pub async fn historical_balance(
+    blockchain_service: &BlockchainService,
+    external_apis: &ExternalAPIService,
+    address: &str,
+    token: Option<&str>,
+    block: u64,
+) -> Result<Value> {
+    let cache_key = format!(
+        "balance:{}:{}:{}:{}",
+        blockchain_service.chain_id(),
+        address.to_lowercase(),
+        token.unwrap_or("eth").to_lowercase(),
+        block
+    );
+    if let Some(cached) = blockchain_service.cached_history(&cache_key) {
+        return Ok(cached);
+    }
+
+    let result = if blockchain_service.is_archive_node().await {
+        let balance = blockchain_service
+            .get_balance_at_block(address, token, block)
+            .await?;
+        json!({
+            "address": address,
+            "block": block,
+            "token": balance.token,
+            "balance": balance.balance,
+            "source": "archive_node",
+        })
+    } else {
+        let token_info = match token {
+            Some(token) if !token.eq_ignore_ascii_case("eth") => {
+                Some(blockchain_service.resolve_token(token).await?)
+            }
+            _ => None,
+        };
+        let raw = external_apis
+            .get_historical_balance(
+                blockchain_service.chain_id(),
+                address,
+                token_info.as_ref().map(|info| info.address.as_str()),
+                block,
+            )
+            .await?
+            .ok_or_else(|| {
+                anyhow!(
+                    "The configured RPC is not an archive node and no ETHERSCAN_API_KEY is set, so balances at past blocks are unavailable"
+                )
+            })?;
+
+        let decimals = token_info.as_ref().map(|info| info.decimals).unwrap_or(18);
+        let balance = format_units(U256::from_dec_str(&raw)?, decimals as u32)?;
+        json!({
+            "address": address,
+            "block": block,
+            "token": token_info.map(|info| info.symbol).unwrap_or_else(|| "ETH".to_string()),
+            "balance": balance,
+            "source": "etherscan",
+            "note": "The configured RPC is not an archive node; this balance comes from Etherscan's balance history",
+        })
+    };
+
+    blockchain_service.cache_history(&cache_key, result.clone());
+    Ok(result)
+}
+
+/// USD price of a token at a past block. Quotes the Uniswap V2 router at that block on
+/// archive nodes, otherwise uses DefiLlama's price at the block's timestamp.
+pub async fn historical_price(
+    blockchain_service: &BlockchainService,
+    external_apis: &ExternalAPIService,
+    token: &str,
+    block: u64,
+) -> Result<Value> {
+    let cache_key = format!(
+        "price:{}:{}:{}",
+        blockchain_service.chain_id(),
+        token.to_lowercase(),
+        block
+    );
+    if let Some(cached) = blockchain_service.cached_history(&cache_key) {
+        return Ok(cached);
+    }
+
+    let block_info = blockchain_service
+        .provider()
+        .get_block(block)
+        .await?
+        .ok_or_else(|| anyhow!("Block {} not found", block))?;
+    let timestamp = block_info.timestamp.as_u64() as i64;
+
+    if blockchain_service.is_archive_node().await {
+        let priced_token = if token.eq_ignore_ascii_case("eth") {
+            "WETH"
+        } else {
+            token
+        };
+        match blockchain_service
+            .quote_at_block(priced_token, USD_QUOTE_TOKEN, block)
+            .await
+        {
+            Ok(price) => {
+                let result = json!({
+                    "token": token,
+                    "block": block,
+                    "timestamp": timestamp,
+                    "price_usd": price,
+                    "source": "archive_node",
+                    "note": format!("Uniswap V2 quote for 1 {} in {} at block {}", token, USD_QUOTE_TOKEN, block),
+                });
+                blockchain_service.cache_history(&cache_key, result.clone());
+                return Ok(result);
+            }
+            Err(e) => warn!("On-chain quote at block {} failed: {}", block, e),
+        }
+    }
+
+    let token_address = if token.eq_ignore_ascii_case("eth") {
+        blockchain_service.router_config().weth.clone()
+    } else {
+        blockchain_service.resolve_token(token).await?.address
+    };
+    let price = external_apis
+        .get_historical_token_price(&token_address, timestamp)
+        .await?
+        .ok_or_else(|| anyhow!("No historical price found for {} at block {}", token, block))?;
+
+    let result = json!({
+        "token": token,
+        "block": block,
+        "timestamp": timestamp,
+        "price_usd": price,
+        "source": "defillama",
+        "note": "Priced from DefiLlama at the block's timestamp, not read from chain state",
+    });
+    blockchain_service.cache_history(&cache_key, result.clone());
+    Ok(result)
+}
@@ -0,0 +1,396 @@
+use anyhow::{Result, anyhow};
+use ethers::providers::Middleware;
+use ethers::types::{Address, Filter, H256, Log, U256, ValueOrArray};
+use ethers::utils::{format_units, keccak256};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::blockchain::BlockchainService;
+use crate::scanner::{CheckpointStore, LogScanner};
+use crate::tenants::TenantRegistry;
+
+/// How often tracked accounts are checked for new activity
+pub const INBOX_INTERVAL: Duration = Duration::from_secs(60);
+const MAX_INBOX_ITEMS: usize = 500;
+// After a long downtime only the most recent blocks are caught up on
+const MAX_CATCHUP_BLOCKS: u64 = 10_000;
+// ETH balance moves of at least this share of the previous balance are reported
+const LARGE_CHANGE_PERCENT: u64 = 20;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InboxItem {
+    pub id: u64,
+    pub timestamp: i64,
+    pub namespace: String,
+    pub account: String,
+    pub address: String,
+    /// "incoming_transfer", "approval", "balance_change", "transaction_resolved", "top_up"
+    /// or "depeg"
+    pub kind: String,
+    pub message: String,
+    pub block_number: Option<u64>,
+    pub transaction_hash: Option<String>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct InboxState {
+    next_id: u64,
+    items: VecDeque<InboxItem>,
+    /// Namespace -> id of the last item it has read
+    read_up_to: HashMap<String, u64>,
+    /// Address -> last seen ETH balance in wei
+    balances: HashMap<String, U256>,
+}
+
+// Activity on tracked accounts, kept until a client reads it; persisted as one JSON file
+pub struct InboxStore {
+    path: Option<PathBuf>,
+    state: RwLock<InboxState>,
+}
+
+impl InboxStore {
+    pub fn in_memory() -> Self {
+        Self {
+            path: None,
+            state: RwLock::new(InboxState::default()),
+        }
+    }
+
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let state = if path.exists() {
+            serde_json::from_str(&fs::read_to_string(&path)?)?
+        } else {
+            InboxState::default()
+        };
+
+        Ok(Self {
+            path: Some(path),
+            state: RwLock::new(state),
+        })
+    }
+
+    fn add(&self, mut item: InboxItem) -> Result<()> {
+        self.update(|state| {
+            state.next_id += 1;
+            item.id = state.next_id;
+            state.items.push_back(item);
+            while state.items.len() > MAX_INBOX_ITEMS {
+                state.items.pop_front();
+            }
+        })
+    }
+
+    /// Record an event for every namespace holding `address` as an account
+    pub fn record_for_address(
+        &self,
+        tenants: &TenantRegistry,
+        address: &str,
+        kind: &str,
+        message: &str,
+        block_number: Option<u64>,
+        transaction_hash: Option<String>,
+    ) -> Result<()> {
+        let now = chrono::Utc::now().timestamp();
+        for namespace in tenants.namespaces() {
+            let Some(account) = namespace.accounts.name_for_address(address) else {
+                continue;
+            };
+            self.add(InboxItem {
+                id: 0,
+                timestamp: now,
+                namespace: namespace.name.clone(),
+                account,
+                address: address.to_lowercase(),
+                kind: kind.to_string(),
+                message: message.to_string(),
+                block_number,
+                transaction_hash: transaction_hash.clone(),
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Record an event that concerns every namespace, such as a market alert, filed
+    /// under `subject` instead of an account
+    pub fn record_for_all(
+        &self,
+        tenants: &TenantRegistry,
+        subject: &str,
+        address: &str,
+        kind: &str,
+        message: &str,
+    ) -> Result<()> {
+        let now = chrono::Utc::now().timestamp();
+        for namespace in tenants.namespaces() {
+            self.add(InboxItem {
+                id: 0,
+                timestamp: now,
+                namespace: namespace.name.clone(),
+                account: subject.to_string(),
+                address: address.to_lowercase(),
+                kind: kind.to_string(),
+                message: message.to_string(),
+                block_number: None,
+                transaction_hash: None,
+            })?;
+        }
+        Ok(())
+    }
+
+    /// A namespace's items, oldest first: only those it hasn't read unless `include_read`
+    pub fn items(&self, namespace: &str, include_read: bool) -> Vec<InboxItem> {
+        self.state
+            .read()
+            .map(|state| {
+                let read_up_to = state.read_up_to.get(namespace).copied().unwrap_or(0);
+                state
+                    .items
+                    .iter()
+                    .filter(|item| item.namespace == namespace)
+                    .filter(|item| include_read || item.id > read_up_to)
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Mark everything in the namespace's inbox up to `id` as read
+    pub fn mark_read(&self, namespace: &str, id: u64) -> Result<()> {
+        self.update(|state| {
+            let read_up_to = state.read_up_to.entry(namespace.to_string()).or_default();
+            *read_up_to = (*read_up_to).max(id);
+        })
+    }
+
+    // Remember an address's ETH balance, returning the previous one
+    fn swap_balance(&self, address: &str, balance: U256) -> Result<Option<U256>> {
+        let previous = self
+            .state
+            .read()
+            .ok()
+            .and_then(|state| state.balances.get(address).copied());
+        if previous != Some(balance) {
+            self.update(|state| {
+                state.balances.insert(address.to_string(), balance);
+            })?;
+        }
+        Ok(previous)
+    }
+
+    fn update(&self, change: impl FnOnce(&mut InboxState)) -> Result<()> {
+        let mut state = self
+            .state
+            .write()
+            .map_err(|_| anyhow!("Inbox lock poisoned"))?;
+        change(&mut state);
+
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(&*state)?)?;
+        Ok(())
+    }
+}
+
+// An account some namespace holds, as the inbox reports it
+struct Tracked {
+    namespace: String,
+    account: String,
+}
+
+/// Background job that records incoming transfers, approvals granted to and large ETH
+/// balance changes of every namespace's accounts, so clients learn of them later
+pub fn spawn_inbox_watcher(
+    blockchain_service: Arc<BlockchainService>,
+    tenants: Arc<TenantRegistry>,
+    checkpoints: Arc<CheckpointStore>,
+    store: Arc<InboxStore>,
+) {
+    info!(
+        "Watching tracked accounts for the inbox every {}s",
+        INBOX_INTERVAL.as_secs()
+    );
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(INBOX_INTERVAL);
+        loop {
+            ticker.tick().await;
+
+            let mut tracked: HashMap<String, Vec<Tracked>> = HashMap::new();
+            for namespace in tenants.namespaces() {
+                for address in namespace.accounts.addresses() {
+                    let account = namespace
+                        .accounts
+                        .name_for_address(&address)
+                        .unwrap_or_else(|| address.clone());
+                    tracked
+                        .entry(address.to_lowercase())
+                        .or_default()
+                        .push(Tracked {
+                            namespace: namespace.name.clone(),
+                            account,
+                        });
+                }
+            }
+
+            for (address, holders) in &tracked {
+                if let Err(e) =
+                    check_account(&blockchain_service, &checkpoints, &store, address, holders).await
+                {
+                    warn!("Inbox check failed for {}: {}", address, e);
+                }
+            }
+        }
+    });
+}
+
+async fn check_account(
+    blockchain_service: &BlockchainService,
+    checkpoints: &CheckpointStore,
+    store: &InboxStore,
+    address: &str,
+    holders: &[Tracked],
+) -> Result<()> {
+    let provider = blockchain_service.provider();
+    let owner = Address::from_str(address)?;
+    let latest = provider.get_block_number().await?.as_u64();
+    let now = chrono::Utc::now().timestamp();
+    let record = |kind: &str, message: String, log: Option<&Log>| -> Result<()> {
+        for holder in holders {
+            store.add(InboxItem {
+                id: 0,
+                timestamp: now,
+                namespace: holder.namespace.clone(),
+                account: holder.account.clone(),
+                address: address.to_string(),
+                kind: kind.to_string(),
+                message: message.clone(),
+                block_number: log.and_then(|log| log.block_number).map(|n| n.as_u64()),
+                transaction_hash: log
+                    .and_then(|log| log.transaction_hash)
+                    .map(|hash| format!("{:?}", hash)),
+            })?;
+        }
+        Ok(())
+    };
+
+    // Token transfers to the account and approvals naming it as spender both index
+    // it as the second topic
+    let transfer_topic = H256::from(keccak256("Transfer(address,address,uint256)"));
+    let approval_topic = H256::from(keccak256("Approval(address,address,uint256)"));
+    let filter = Filter::new()
+        .topic0(ValueOrArray::Array(vec![
+            Some(transfer_topic),
+            Some(approval_topic),
+        ]))
+        .topic2(H256::from(owner));
+    let scan_id = format!("inbox:{}:{}", blockchain_service.chain_id(), address);
+    // The first check starts at the head instead of reporting old history
+    let from_block = checkpoints
+        .get(&scan_id)
+        .unwrap_or(latest)
+        .max(latest.saturating_sub(MAX_CATCHUP_BLOCKS));
+    let mut logs = Vec::new();
+    LogScanner::new(provider.clone(), checkpoints)
+        .scan(&scan_id, &filter, from_block, Some(latest), |chunk| {
+            logs.extend(chunk);
+            Ok(())
+        })
+        .await?;
+
+    for log in &logs {
+        if log.topics.len() < 3 {
+            continue;
+        }
+        let counterparty = format!("{:?}", Address::from(log.topics[1]));
+        let token = blockchain_service
+            .resolve_token(&format!("{:?}", log.address))
+            .await
+            .ok();
+        let symbol = token
+            .as_ref()
+            .map(|token| token.symbol.clone())
+            .unwrap_or_else(|| format!("{:?}", log.address));
+
+        if log.topics[0] == approval_topic {
+            let allowance = U256::from_big_endian(&log.data);
+            let amount = if allowance == U256::MAX {
+                "unlimited".to_string()
+            } else {
+                format_amount(allowance, token.as_ref().map_or(0, |t| t.decimals as u32))
+            };
+            record(
+                "approval",
+                format!(
+                    "{} approved you to spend {} {}",
+                    counterparty, amount, symbol
+                ),
+                Some(log),
+            )?;
+        } else if log.topics.len() == 4 {
+            // ERC721 transfers index the token ID
+            let token_id = U256::from_big_endian(log.topics[3].as_bytes());
+            record(
+                "incoming_transfer",
+                format!("Received {} #{} from {}", symbol, token_id, counterparty),
+                Some(log),
+            )?;
+        } else {
+            let amount = format_amount(
+                U256::from_big_endian(&log.data),
+                token.as_ref().map_or(0, |t| t.decimals as u32),
+            );
+            record(
+                "incoming_transfer",
+                format!("Received {} {} from {}", amount, symbol, counterparty),
+                Some(log),
+            )?;
+        }
+    }
+
+    // ETH arrives without logs, so it shows up as a balance change
+    let balance = provider.get_balance(owner, None).await?;
+    if let Some(previous) = store.swap_balance(address, balance)? {
+        let change = if balance > previous {
+            balance - previous
+        } else {
+            previous - balance
+        };
+        if !change.is_zero() && change * U256::from(100) >= previous * LARGE_CHANGE_PERCENT {
+            let direction = if balance > previous { "rose" } else { "fell" };
+            record(
+                "balance_change",
+                format!(
+                    "ETH balance {} by {} to {} ETH",
+                    direction,
+                    format_amount(change, 18),
+                    format_amount(balance, 18)
+                ),
+                None,
+            )?;
+        }
+    }
+    Ok(())
+}
+
+fn format_amount(amount: U256, decimals: u32) -> String {
+    let formatted = format_units(amount, decimals).unwrap_or_else(|_| amount.to_string());
+    if formatted.contains('.') {
+        formatted
+            .trim_end_matches('0')
+            .trim_end_matches('.')
+            .to_string()
+    } else {
+        formatted
+    }
+}
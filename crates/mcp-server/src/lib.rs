@@ -3,14 +3,163 @@ pub mod tools;
 pub mod blockchain;
 pub mod external_apis;
 pub mod rag_service;
+pub mod policy;
+pub mod screening;
+pub mod token_safety;
+pub mod accounts;
+pub mod audit;
+pub mod tenants;
+pub mod activity;
+pub mod pnl;
+pub mod gas;
+pub mod snapshots;
+pub mod historical;
+pub mod scanner;
+pub mod diagnostics;
+pub mod paths;
+pub mod capabilities;
+pub mod sessions;
+pub mod templates;
+pub mod scheduler;
+pub mod portfolio;
+pub mod tx_history;
+pub mod events;
+pub mod units;
+pub mod calldata;
+pub mod chain_info;
+pub mod proxy;
+pub mod payment_uri;
+pub mod tx_inspect;
+pub mod inbox;
+pub mod signing;
+pub mod pending;
+pub mod twap;
+pub mod pools;
+pub mod dust;
+pub mod liquidity;
+pub mod migrations;
+pub mod aave;
+pub mod universal_router;
+pub mod aggregator;
+pub mod token_list;
+pub mod prices;
+pub mod chainlink;
+pub mod depeg;
+pub mod lp;
+pub mod simulation;
+pub mod yields;
+pub mod protocol_health;
+pub mod devchain;
+pub mod governance;
+pub mod snapshot_voting;
+pub mod claims;
+pub mod vesting;
+pub mod heads;
+pub mod failover;
+pub mod streams;
+pub mod token_deploy;
+pub mod deploy_address;
+pub mod smart_accounts;
+pub mod session_keys;
+pub mod tx_tracker;
+pub mod walletconnect;
+pub mod topups;
 
 use anyhow::Result;
-use ethers::providers::{Http, Provider};
+use ethers::providers::{Provider, Ws};
 use std::sync::Arc;
+use std::time::Duration;
 
-pub type EthProvider = Arc<Provider<Http>>;
+use crate::blockchain::{NetworkConfig, NetworkRegistry, RouterConfig, RpcSettings, TokenRegistrySource, UniswapV3Config};
+use crate::failover::FailoverClient;
+use crate::policy::{PolicyConfig, PolicyEngine};
+use crate::tools::ToolRegistry;
+use shared::get_test_accounts;
 
+pub type EthProvider = Arc<Provider<FailoverClient>>;
+
+/// Connect over HTTP; `rpc_url` may list several endpoints, comma-separated, to fail over between
 pub async fn create_provider(rpc_url: &str) -> Result<EthProvider> {
-  let provider = Provider::<Http>::try_from(rpc_url)?;
+  let provider = Provider::new(FailoverClient::new(rpc_url, 1)?);
+  Ok(Arc::new(provider))
+}
+
+/// Connect to a WebSocket endpoint (ws:// or wss://), for subscriptions such as new heads
+pub async fn create_ws_provider(ws_url: &str) -> Result<Arc<Provider<Ws>>> {
+  let provider = Provider::<Ws>::connect(ws_url).await?;
   Ok(Arc::new(provider))
-}
\ No newline at end of file
+}
+
+/// Build a server configured from the environment (ETH_RPC_URL, CHAIN_ID, NETWORKS_FILE, ...),
+/// as the mcp-server binary runs it. Embedders call `run` on the result.
+pub fn server_from_env() -> Result<server::Server> {
+  // Find the data directory and check its ABIs before anything loads from it
+  paths::preflight()?;
+
+  // Default network, configured from the environment
+  let rpc_url = std::env::var("ETH_RPC_URL").unwrap_or_else(|_| "http://localhost:8545".to_string());
+  let chain_id = std::env::var("CHAIN_ID")
+      .ok()
+      .and_then(|id| id.parse::<u64>().ok())
+      .unwrap_or(1);
+  let router = match (std::env::var("UNISWAP_V2_ROUTER"), std::env::var("WETH_ADDRESS")) {
+      (Ok(router), Ok(weth)) => {
+          // V3 is only used on custom networks when both of its contracts are given
+          let uniswap_v3 = match (std::env::var("UNISWAP_V3_ROUTER"), std::env::var("UNISWAP_V3_QUOTER")) {
+              (Ok(router), Ok(quoter)) => Some(UniswapV3Config {
+                  router,
+                  quoter,
+                  position_manager: std::env::var("UNISWAP_V3_POSITION_MANAGER").ok(),
+              }),
+              _ => None,
+          };
+          Some(RouterConfig {
+              uniswap_v2_router: router,
+              weth,
+              uniswap_v3,
+              universal_router: std::env::var("UNISWAP_UNIVERSAL_ROUTER").ok(),
+          })
+      }
+      _ => None,
+  };
+  let tokens = match std::env::var("TOKENS_FILE") {
+      Ok(tokens_file) => TokenRegistrySource::File(tokens_file.into()),
+      Err(_) => TokenRegistrySource::Builtin,
+  };
+  let default_network = NetworkConfig {
+      name: std::env::var("NETWORK_NAME").unwrap_or_else(|_| "local".to_string()),
+      chain_id,
+      rpc_url,
+      ws_url: std::env::var("ETH_WS_URL").ok(),
+      router,
+      tokens,
+  };
+
+  // Other networks the agent can switch to
+  let mut networks = NetworkRegistry::new(default_network).with_rpc_settings(RpcSettings::from_env());
+  for network in NetworkConfig::presets() {
+      networks.add(network);
+  }
+  if let Ok(networks_file) = std::env::var("NETWORKS_FILE") {
+      networks.load_file(networks_file)?;
+  }
+  networks.service(None)?;
+  
+  // Create and register tools
+  let mut tool_registry = ToolRegistry::new();
+  tool_registry.register_default_tools();
+  
+  // Get test accounts
+  let accounts = get_test_accounts();
+  
+  // Load transaction policy
+  let policy = PolicyEngine::new(PolicyConfig::from_env());
+
+  // Create server
+  let snapshot_interval = std::env::var("SNAPSHOT_INTERVAL_SECS")
+      .ok()
+      .and_then(|secs| secs.parse::<u64>().ok())
+      .unwrap_or(3600);
+  Ok(server::Server::new(networks, tool_registry, accounts, policy)?
+      .with_snapshot_interval((snapshot_interval > 0).then(|| Duration::from_secs(snapshot_interval))))
+}
@@ -0,0 +1,565 @@
+use anyhow::{Result, anyhow};
+use ethers::abi::parse_abi;
+use ethers::contract::Contract;
+use ethers::providers::{Middleware, Provider};
+use ethers::types::{Address, BlockNumber, H256, U256};
+use ethers::utils::{format_units, keccak256};
+use serde::{Deserialize, Serialize};
+use shared::{Account, ContractCall, TransactionResult, utils};
+use std::str::FromStr;
+
+use crate::blockchain::{BlockchainService, FeeOverrides, TokenInfo};
+use crate::failover::FailoverClient;
+
+/// Slippage tolerance when the caller gives none, in percent
+pub const DEFAULT_LIQUIDITY_SLIPPAGE: f64 = 0.5;
+// Router calls revert if they're mined after this long
+const DEADLINE_SECS: u64 = 1_200;
+
+const V2_PAIR_ABI: &[&str] = &[
+    "function balanceOf(address owner) view returns (uint256)",
+    "function totalSupply() view returns (uint256)",
+    "function getReserves() view returns (uint112 reserve0, uint112 reserve1, uint32 blockTimestampLast)",
+];
+
+/// Tokens and amounts to deposit into a Uniswap V2 pair
+#[derive(Debug, Clone)]
+pub struct AddLiquidityRequest {
+    /// Token symbol, address or "ETH"
+    pub token_a: String,
+    pub amount_a: String,
+    pub token_b: String,
+    /// None deposits the amount of `token_b` matching the pair's current price
+    pub amount_b: Option<String>,
+    pub slippage_percent: f64,
+}
+
+/// An account's share of a V2 pair after adding or removing liquidity
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LiquidityResult {
+    pub pair: String,
+    pub token_a: String,
+    pub token_b: String,
+    /// Amounts deposited or withdrawn, as the router settled them
+    pub amount_a: String,
+    pub amount_b: String,
+    /// Approvals first, then the router call
+    pub transactions: Vec<TransactionResult>,
+    /// LP tokens the account holds now
+    pub lp_balance: String,
+    pub pool_share_percent: f64,
+}
+
+// Pair contract state, with reserves ordered as the caller's tokens
+struct PairState {
+    address: Address,
+    contract: Contract<Provider<FailoverClient>>,
+    reserve_a: U256,
+    reserve_b: U256,
+}
+
+// A leg of the pair: ETH deposits and withdrawals go through WETH
+struct Leg {
+    info: TokenInfo,
+    address: Address,
+    is_eth: bool,
+}
+
+async fn leg(blockchain_service: &BlockchainService, token: &str) -> Result<Leg> {
+    if token.eq_ignore_ascii_case("eth") {
+        let weth = blockchain_service.router_config().weth.clone();
+        let mut info = blockchain_service.resolve_token(&weth).await?;
+        info.symbol = "ETH".to_string();
+        return Ok(Leg {
+            address: Address::from_str(&weth)?,
+            info,
+            is_eth: true,
+        });
+    }
+    let info = blockchain_service.resolve_token(token).await?;
+    Ok(Leg {
+        address: Address::from_str(&info.address)?,
+        info,
+        is_eth: false,
+    })
+}
+
+async fn legs(
+    blockchain_service: &BlockchainService,
+    token_a: &str,
+    token_b: &str,
+) -> Result<(Leg, Leg)> {
+    let a = leg(blockchain_service, token_a).await?;
+    let b = leg(blockchain_service, token_b).await?;
+    if a.address == b.address {
+        return Err(anyhow!("A pair needs two different tokens"));
+    }
+    Ok((a, b))
+}
+
+fn router(blockchain_service: &BlockchainService) -> Result<Address> {
+    Ok(Address::from_str(
+        &blockchain_service.router_config().uniswap_v2_router,
+    )?)
+}
+
+// The pair for two tokens, or None when it hasn't been created yet
+async fn pair(
+    blockchain_service: &BlockchainService,
+    a: &Leg,
+    b: &Leg,
+) -> Result<Option<PairState>> {
+    let provider = blockchain_service.provider();
+    let router = Contract::new(
+        router(blockchain_service)?,
+        parse_abi(&["function factory() view returns (address)"])?,
+        provider.clone(),
+    );
+    let factory: Address = router.method::<_, Address>("factory", ())?.call().await?;
+    let factory = Contract::new(
+        factory,
+        parse_abi(&["function getPair(address,address) view returns (address)"])?,
+        provider.clone(),
+    );
+    let address: Address = factory
+        .method::<_, Address>("getPair", (a.address, b.address))?
+        .call()
+        .await?;
+    if address.is_zero() {
+        return Ok(None);
+    }
+
+    let contract = Contract::new(address, parse_abi(V2_PAIR_ABI)?, provider);
+    let (reserve0, reserve1, _): (u128, u128, u32) = contract
+        .method::<_, (u128, u128, u32)>("getReserves", ())?
+        .call()
+        .await?;
+    let (reserve_a, reserve_b) = if a.address < b.address {
+        (reserve0, reserve1)
+    } else {
+        (reserve1, reserve0)
+    };
+    Ok(Some(PairState {
+        address,
+        contract,
+        reserve_a: U256::from(reserve_a),
+        reserve_b: U256::from(reserve_b),
+    }))
+}
+
+fn parse_amount(amount: &str, token: &TokenInfo) -> Result<U256> {
+    let amount = utils::parse_amount(amount, token.decimals)?;
+    if amount.is_zero() {
+        return Err(anyhow!("amount must be greater than zero"));
+    }
+    Ok(amount)
+}
+
+fn units(raw: U256, token: &TokenInfo) -> Result<String> {
+    Ok(format_units(raw, token.decimals as u32)?)
+}
+
+// Least of `amount` the router may settle for at `slippage_percent`
+fn minimum(amount: U256, slippage_percent: f64) -> Result<U256> {
+    if !(0.0..50.0).contains(&slippage_percent) {
+        return Err(anyhow!("slippage must be between 0 and 50 percent"));
+    }
+    let basis_points = U256::from((slippage_percent * 100.0).round() as u64);
+    Ok(amount * (U256::from(10_000) - basis_points) / U256::from(10_000))
+}
+
+async fn deadline(blockchain_service: &BlockchainService) -> Result<u64> {
+    let now = blockchain_service
+        .provider()
+        .get_block(BlockNumber::Latest)
+        .await?
+        .map(|block| block.timestamp.as_u64())
+        .unwrap_or_else(|| chrono::Utc::now().timestamp() as u64);
+    Ok(now + DEADLINE_SECS)
+}
+
+// Approve the router for exactly `amount` of a token when the allowance falls short
+async fn ensure_allowance(
+    blockchain_service: &BlockchainService,
+    from_account: &Account,
+    token: &TokenInfo,
+    amount: U256,
+    fees: &FeeOverrides,
+    transactions: &mut Vec<TransactionResult>,
+) -> Result<()> {
+    let owner = Address::from_str(&from_account.address)?;
+    let spender = router(blockchain_service)?;
+    let allowance = blockchain_service
+        .token_allowance(Address::from_str(&token.address)?, owner, spender)
+        .await?;
+    if allowance >= amount {
+        return Ok(());
+    }
+    let approval = blockchain_service
+        .set_allowance(from_account, token, spender, amount, fees)
+        .await?;
+    if approval.status != "success" {
+        return Err(anyhow!(
+            "Approving the router to spend {} failed ({}): {}",
+            token.symbol,
+            approval.status,
+            approval.hash
+        ));
+    }
+    transactions.push(approval);
+    Ok(())
+}
+
+async fn send(
+    blockchain_service: &BlockchainService,
+    from_account: &Account,
+    function_signature: &str,
+    parameters: Vec<String>,
+    value_eth: &str,
+    fees: &FeeOverrides,
+) -> Result<TransactionResult> {
+    let call = ContractCall {
+        contract_address: format!("{:?}", router(blockchain_service)?),
+        function_signature: function_signature.to_string(),
+        parameters,
+        from: None,
+    };
+    blockchain_service
+        .write_contract(
+            from_account,
+            &call,
+            value_eth,
+            None,
+            &|name| name.to_string(),
+            fees,
+        )
+        .await
+}
+
+// Amounts of the caller's two tokens a pair's Mint or Burn event reports for a
+// transaction; both events log amount0 and amount1 first in their data
+async fn settled(
+    blockchain_service: &BlockchainService,
+    transaction: &TransactionResult,
+    pair: Address,
+    event: &str,
+    a_is_token0: bool,
+) -> Result<Option<(U256, U256)>> {
+    let Some(receipt) = blockchain_service
+        .provider()
+        .get_transaction_receipt(H256::from_str(&transaction.hash)?)
+        .await?
+    else {
+        return Ok(None);
+    };
+    let topic = H256::from(keccak256(event));
+    Ok(receipt
+        .logs
+        .iter()
+        .find(|log| log.address == pair && log.topics.first() == Some(&topic))
+        .filter(|log| log.data.len() >= 64)
+        .map(|log| {
+            let amount0 = U256::from_big_endian(&log.data[..32]);
+            let amount1 = U256::from_big_endian(&log.data[32..64]);
+            if a_is_token0 {
+                (amount0, amount1)
+            } else {
+                (amount1, amount0)
+            }
+        }))
+}
+
+// The account's LP balance and share of the pair once a transaction has settled
+async fn position(pair: &PairState, owner: Address) -> Result<(String, f64)> {
+    let balance: U256 = pair
+        .contract
+        .method::<_, U256>("balanceOf", owner)?
+        .call()
+        .await?;
+    let supply: U256 = pair
+        .contract
+        .method::<_, U256>("totalSupply", ())?
+        .call()
+        .await?;
+    let as_f64 = |value: U256| value.to_string().parse::<f64>().unwrap_or(0.0);
+    let share = if supply.is_zero() {
+        0.0
+    } else {
+        as_f64(balance) / as_f64(supply) * 100.0
+    };
+    Ok((format_units(balance, 18)?, share))
+}
+
+/// Deposit two tokens into their Uniswap V2 pair through the router, approving it for
+/// the exact amounts first. Without `amount_b` the pair's current price sets it; a new
+/// pair needs both amounts, which set its starting price.
+pub async fn add_liquidity(
+    blockchain_service: &BlockchainService,
+    from_account: &Account,
+    request: &AddLiquidityRequest,
+    fees: &FeeOverrides,
+) -> Result<LiquidityResult> {
+    let (a, b) = legs(blockchain_service, &request.token_a, &request.token_b).await?;
+    if a.is_eth && b.is_eth {
+        return Err(anyhow!("A pair needs two different tokens"));
+    }
+    let amount_a = parse_amount(&request.amount_a, &a.info)?;
+    let existing = pair(blockchain_service, &a, &b).await?;
+    let amount_b = match (&request.amount_b, &existing) {
+        (Some(amount), _) => parse_amount(amount, &b.info)?,
+        (None, Some(pair)) if !pair.reserve_a.is_zero() => {
+            amount_a * pair.reserve_b / pair.reserve_a
+        }
+        (None, _) => {
+            return Err(anyhow!(
+                "There's no {}/{} liquidity to price the deposit yet; give amount_b to set the pair's starting price",
+                a.info.symbol,
+                b.info.symbol
+            ));
+        }
+    };
+    if amount_b.is_zero() {
+        return Err(anyhow!(
+            "{} {} is worth less than one unit of {}",
+            request.amount_a,
+            a.info.symbol,
+            b.info.symbol
+        ));
+    }
+
+    let owner = Address::from_str(&from_account.address)?;
+    let deadline = deadline(blockchain_service).await?;
+    let mut transactions = Vec::new();
+    let transaction = if a.is_eth || b.is_eth {
+        let (token, token_amount, eth_amount) = if a.is_eth {
+            (&b, amount_b, amount_a)
+        } else {
+            (&a, amount_a, amount_b)
+        };
+        ensure_allowance(
+            blockchain_service,
+            from_account,
+            &token.info,
+            token_amount,
+            fees,
+            &mut transactions,
+        )
+        .await?;
+        send(
+            blockchain_service,
+            from_account,
+            "addLiquidityETH(address,uint256,uint256,uint256,address,uint256)",
+            vec![
+                format!("{:?}", token.address),
+                token_amount.to_string(),
+                minimum(token_amount, request.slippage_percent)?.to_string(),
+                minimum(eth_amount, request.slippage_percent)?.to_string(),
+                format!("{:?}", owner),
+                deadline.to_string(),
+            ],
+            &format_units(eth_amount, 18)?,
+            fees,
+        )
+        .await?
+    } else {
+        for (leg, amount) in [(&a, amount_a), (&b, amount_b)] {
+            ensure_allowance(
+                blockchain_service,
+                from_account,
+                &leg.info,
+                amount,
+                fees,
+                &mut transactions,
+            )
+            .await?;
+        }
+        send(
+            blockchain_service,
+            from_account,
+            "addLiquidity(address,address,uint256,uint256,uint256,uint256,address,uint256)",
+            vec![
+                format!("{:?}", a.address),
+                format!("{:?}", b.address),
+                amount_a.to_string(),
+                amount_b.to_string(),
+                minimum(amount_a, request.slippage_percent)?.to_string(),
+                minimum(amount_b, request.slippage_percent)?.to_string(),
+                format!("{:?}", owner),
+                deadline.to_string(),
+            ],
+            "0",
+            fees,
+        )
+        .await?
+    };
+    if transaction.status != "success" {
+        return Err(anyhow!(
+            "Adding liquidity failed ({}): {}",
+            transaction.status,
+            transaction.hash
+        ));
+    }
+
+    // The router only takes the ratio the pair accepts; read back what it settled on
+    let pair = pair(blockchain_service, &a, &b)
+        .await?
+        .ok_or_else(|| anyhow!("The pair wasn't created"))?;
+    let (settled_a, settled_b) = settled(
+        blockchain_service,
+        &transaction,
+        pair.address,
+        "Mint(address,uint256,uint256)",
+        a.address < b.address,
+    )
+    .await?
+    .unwrap_or((amount_a, amount_b));
+    transactions.push(transaction);
+    let (lp_balance, pool_share_percent) = position(&pair, owner).await?;
+
+    Ok(LiquidityResult {
+        pair: format!("{:?}", pair.address),
+        token_a: a.info.symbol.clone(),
+        token_b: b.info.symbol.clone(),
+        amount_a: units(settled_a, &a.info)?,
+        amount_b: units(settled_b, &b.info)?,
+        transactions,
+        lp_balance,
+        pool_share_percent,
+    })
+}
+
+/// Withdraw `percent` of the account's LP tokens from a Uniswap V2 pair, getting both
+/// tokens back in proportion to the reserves (ETH rather than WETH for an ETH leg)
+pub async fn remove_liquidity(
+    blockchain_service: &BlockchainService,
+    from_account: &Account,
+    token_a: &str,
+    token_b: &str,
+    percent: f64,
+    slippage_percent: f64,
+    fees: &FeeOverrides,
+) -> Result<LiquidityResult> {
+    if !(percent > 0.0 && percent <= 100.0) {
+        return Err(anyhow!("percent must be above 0 and at most 100"));
+    }
+    let (a, b) = legs(blockchain_service, token_a, token_b).await?;
+    let pair = pair(blockchain_service, &a, &b).await?.ok_or_else(|| {
+        anyhow!(
+            "There's no Uniswap V2 pair for {}/{}",
+            a.info.symbol,
+            b.info.symbol
+        )
+    })?;
+    let owner = Address::from_str(&from_account.address)?;
+    let balance: U256 = pair
+        .contract
+        .method::<_, U256>("balanceOf", owner)?
+        .call()
+        .await?;
+    if balance.is_zero() {
+        return Err(anyhow!(
+            "{} has no {}/{} liquidity",
+            from_account.name,
+            a.info.symbol,
+            b.info.symbol
+        ));
+    }
+    let liquidity = balance * U256::from((percent * 100.0).round() as u64) / U256::from(10_000);
+    let supply: U256 = pair
+        .contract
+        .method::<_, U256>("totalSupply", ())?
+        .call()
+        .await?;
+    let expected_a = liquidity * pair.reserve_a / supply;
+    let expected_b = liquidity * pair.reserve_b / supply;
+
+    // The router burns LP tokens on the account's behalf, so it needs an allowance too
+    let lp_token = TokenInfo {
+        address: format!("{:?}", pair.address),
+        symbol: "UNI-V2".to_string(),
+        decimals: 18,
+        name: format!("Uniswap V2 {}/{}", a.info.symbol, b.info.symbol),
+    };
+    let mut transactions = Vec::new();
+    ensure_allowance(
+        blockchain_service,
+        from_account,
+        &lp_token,
+        liquidity,
+        fees,
+        &mut transactions,
+    )
+    .await?;
+
+    let deadline = deadline(blockchain_service).await?;
+    let transaction = if a.is_eth || b.is_eth {
+        let (token, token_amount, eth_amount) = if a.is_eth {
+            (&b, expected_b, expected_a)
+        } else {
+            (&a, expected_a, expected_b)
+        };
+        send(
+            blockchain_service,
+            from_account,
+            "removeLiquidityETH(address,uint256,uint256,uint256,address,uint256)",
+            vec![
+                format!("{:?}", token.address),
+                liquidity.to_string(),
+                minimum(token_amount, slippage_percent)?.to_string(),
+                minimum(eth_amount, slippage_percent)?.to_string(),
+                format!("{:?}", owner),
+                deadline.to_string(),
+            ],
+            "0",
+            fees,
+        )
+        .await?
+    } else {
+        send(
+            blockchain_service,
+            from_account,
+            "removeLiquidity(address,address,uint256,uint256,uint256,address,uint256)",
+            vec![
+                format!("{:?}", a.address),
+                format!("{:?}", b.address),
+                liquidity.to_string(),
+                minimum(expected_a, slippage_percent)?.to_string(),
+                minimum(expected_b, slippage_percent)?.to_string(),
+                format!("{:?}", owner),
+                deadline.to_string(),
+            ],
+            "0",
+            fees,
+        )
+        .await?
+    };
+    if transaction.status != "success" {
+        return Err(anyhow!(
+            "Removing liquidity failed ({}): {}",
+            transaction.status,
+            transaction.hash
+        ));
+    }
+
+    let (withdrawn_a, withdrawn_b) = settled(
+        blockchain_service,
+        &transaction,
+        pair.address,
+        "Burn(address,uint256,uint256,address)",
+        a.address < b.address,
+    )
+    .await?
+    .unwrap_or((expected_a, expected_b));
+    transactions.push(transaction);
+    let (lp_balance, pool_share_percent) = position(&pair, owner).await?;
+    Ok(LiquidityResult {
+        pair: format!("{:?}", pair.address),
+        token_a: a.info.symbol.clone(),
+        token_b: b.info.symbol.clone(),
+        amount_a: units(withdrawn_a, &a.info)?,
+        amount_b: units(withdrawn_b, &b.info)?,
+        transactions,
+        lp_balance,
+        pool_share_percent,
+    })
+}
@@ -0,0 +1,545 @@
+use anyhow::{Result, anyhow};
+use ethers::abi::{Abi, parse_abi};
+use ethers::contract::Contract;
+use ethers::providers::{Middleware, Provider};
+use ethers::types::{Address, Filter, H256, Log, U256, ValueOrArray};
+use ethers::utils::{format_units, keccak256};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::str::FromStr;
+
+use crate::blockchain::BlockchainService;
+use crate::external_apis::ExternalAPIService;
+use crate::failover::FailoverClient;
+use crate::prices;
+use crate::scanner::{CheckpointStore, LogScanner, ScanConfig};
+
+/// How far back deposits and withdrawals are looked for when the caller gives no window
+pub const DEFAULT_LP_LOOKBACK_BLOCKS: u64 = 1_000_000;
+// Position events are filtered by contract and topic, so wide ranges are cheap
+const LP_SCAN_CHUNK: u64 = 50_000;
+
+const POSITION_MANAGER_ABI: &[&str] = &[
+    "function balanceOf(address owner) view returns (uint256)",
+    "function tokenOfOwnerByIndex(address owner, uint256 index) view returns (uint256)",
+    "function positions(uint256 tokenId) view returns (uint96 nonce, address operator, address token0, address token1, uint24 fee, int24 tickLower, int24 tickUpper, uint128 liquidity, uint256 feeGrowthInside0LastX128, uint256 feeGrowthInside1LastX128, uint128 tokensOwed0, uint128 tokensOwed1)",
+    "function collect((uint256 tokenId, address recipient, uint128 amount0Max, uint128 amount1Max) params) returns (uint256 amount0, uint256 amount1)",
+];
+const V2_PAIR_ABI: &[&str] = &[
+    "function token0() view returns (address)",
+    "function token1() view returns (address)",
+    "function factory() view returns (address)",
+    "function balanceOf(address owner) view returns (uint256)",
+    "function totalSupply() view returns (uint256)",
+    "function getReserves() view returns (uint112 reserve0, uint112 reserve1, uint32 blockTimestampLast)",
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LpPosition {
+    /// "uniswap_v2" or "uniswap_v3"
+    pub dex: String,
+    /// Pair address for V2, position NFT id for V3
+    pub id: String,
+    pub pool: String,
+    pub token0: String,
+    pub token1: String,
+    /// V3 fee tier in hundredths of a bip
+    pub fee: Option<u32>,
+    /// Whether the current price is inside the V3 position's range; None for V2
+    pub in_range: Option<bool>,
+    /// Principal in the pool now, without fees
+    pub amount0: f64,
+    pub amount1: f64,
+    /// Deposits minus withdrawals within the lookback window
+    pub deposited0: f64,
+    pub deposited1: f64,
+    /// Fees earned, collected or not; V2 fees are estimated from the pool's growth
+    pub fees0: f64,
+    pub fees1: f64,
+    pub position_value_usd: Option<f64>,
+    /// What the deposited tokens would be worth now had they been held instead
+    pub hold_value_usd: Option<f64>,
+    pub fees_usd: Option<f64>,
+    /// Principal value minus hold value; negative is a loss
+    pub impermanent_loss_usd: Option<f64>,
+    pub impermanent_loss_percent: Option<f64>,
+    /// Impermanent loss plus fees: whether providing liquidity beat holding
+    pub net_vs_hold_usd: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LpPerformance {
+    pub address: String,
+    pub chain_id: u64,
+    pub from_block: u64,
+    pub positions: Vec<LpPosition>,
+    pub total_value_usd: f64,
+    pub total_fees_usd: f64,
+    pub total_impermanent_loss_usd: f64,
+    pub total_net_vs_hold_usd: f64,
+    /// Positions with a token that couldn't be priced, left out of the totals
+    pub unpriced: Vec<String>,
+}
+
+// A position before pricing, with the token contracts its amounts are in
+struct Found {
+    position: LpPosition,
+    tokens: [Address; 2],
+}
+
+/// Impermanent loss, fees and net performance against holding for the address's
+/// Uniswap V3 positions and the V2 pairs it minted liquidity in during the last
+/// `lookback` blocks. Deposits made before the window aren't counted, so the hold
+/// comparison is only complete when the window covers the position's whole life.
+pub async fn get_lp_performance(
+    blockchain_service: &BlockchainService,
+    external_apis: &ExternalAPIService,
+    address: &str,
+    lookback: u64,
+) -> Result<LpPerformance> {
+    let owner = blockchain_service.parse_address(address).await?;
+    let latest = blockchain_service
+        .provider()
+        .get_block_number()
+        .await?
+        .as_u64();
+    let from_block = latest.saturating_sub(lookback);
+
+    let mut found = v3_positions(blockchain_service, owner, from_block, latest).await?;
+    found.extend(v2_positions(blockchain_service, owner, from_block, latest).await?);
+
+    let addresses: Vec<String> = found
+        .iter()
+        .flat_map(|found| found.tokens)
+        .map(|token| format!("{:?}", token))
+        .collect();
+    let prices = prices::usd_prices(blockchain_service, external_apis, &addresses).await;
+    let price = |token: &Address| prices.get(&format!("{:?}", token)).copied();
+
+    let mut report = LpPerformance {
+        address: format!("{:?}", owner),
+        chain_id: blockchain_service.chain_id(),
+        from_block,
+        positions: Vec::new(),
+        total_value_usd: 0.0,
+        total_fees_usd: 0.0,
+        total_impermanent_loss_usd: 0.0,
+        total_net_vs_hold_usd: 0.0,
+        unpriced: Vec::new(),
+    };
+    for Found {
+        mut position,
+        tokens,
+    } in found
+    {
+        match (price(&tokens[0]), price(&tokens[1])) {
+            (Some(price0), Some(price1)) => {
+                let value = |amount0: f64, amount1: f64| amount0 * price0 + amount1 * price1;
+                let principal = value(position.amount0, position.amount1);
+                let hold = value(position.deposited0, position.deposited1);
+                let fees = value(position.fees0, position.fees1);
+                let loss = principal - hold;
+
+                position.position_value_usd = Some(principal + fees);
+                position.hold_value_usd = Some(hold);
+                position.fees_usd = Some(fees);
+                position.impermanent_loss_usd = Some(loss);
+                position.impermanent_loss_percent = (hold > 0.0).then(|| loss / hold * 100.0);
+                position.net_vs_hold_usd = Some(loss + fees);
+
+                report.total_value_usd += principal + fees;
+                report.total_fees_usd += fees;
+                report.total_impermanent_loss_usd += loss;
+                report.total_net_vs_hold_usd += loss + fees;
+            }
+            _ => report.unpriced.push(position.id.clone()),
+        }
+        report.positions.push(position);
+    }
+    Ok(report)
+}
+
+fn contract(
+    blockchain_service: &BlockchainService,
+    address: Address,
+    abi: &[&str],
+) -> Result<Contract<Provider<FailoverClient>>> {
+    let abi: Abi = parse_abi(abi)?;
+    Ok(Contract::new(address, abi, blockchain_service.provider()))
+}
+
+fn amount(raw: U256, decimals: u8) -> Result<f64> {
+    Ok(format_units(raw, decimals as u32)?.parse()?)
+}
+
+fn topic(value: U256) -> H256 {
+    let mut bytes = [0u8; 32];
+    value.to_big_endian(&mut bytes);
+    H256(bytes)
+}
+
+// Two uint256 words after the indexed topics, as V2 Mint/Burn and the position
+// manager's liquidity events carry their amounts
+fn data_words(log: &Log, skip: usize) -> Option<(U256, U256)> {
+    let word = |i: usize| {
+        log.data
+            .get((skip + i) * 32..(skip + i + 1) * 32)
+            .map(U256::from_big_endian)
+    };
+    Some((word(0)?, word(1)?))
+}
+
+fn scanner_config() -> ScanConfig {
+    ScanConfig {
+        chunk_size: LP_SCAN_CHUNK,
+        ..ScanConfig::default()
+    }
+}
+
+// V3 positions are NFTs the owner holds; their deposits, withdrawals and collected
+// fees come from the position manager's events for each token id
+async fn v3_positions(
+    blockchain_service: &BlockchainService,
+    owner: Address,
+    from_block: u64,
+    latest: u64,
+) -> Result<Vec<Found>> {
+    let Some(manager) = blockchain_service
+        .router_config()
+        .uniswap_v3
+        .as_ref()
+        .and_then(|v3| v3.position_manager.clone())
+    else {
+        return Ok(Vec::new());
+    };
+    let manager_addr = Address::from_str(&manager)?;
+    let manager = contract(blockchain_service, manager_addr, POSITION_MANAGER_ABI)?;
+
+    let count: U256 = manager
+        .method::<_, U256>("balanceOf", owner)?
+        .call()
+        .await?;
+    let mut ids = Vec::new();
+    for index in 0..count.as_u64() {
+        let id: U256 = manager
+            .method::<_, U256>("tokenOfOwnerByIndex", (owner, U256::from(index)))?
+            .call()
+            .await?;
+        ids.push(id);
+    }
+    if ids.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let increase_topic = H256::from(keccak256(
+        "IncreaseLiquidity(uint256,uint128,uint256,uint256)",
+    ));
+    let decrease_topic = H256::from(keccak256(
+        "DecreaseLiquidity(uint256,uint128,uint256,uint256)",
+    ));
+    let collect_topic = H256::from(keccak256("Collect(uint256,address,uint256,uint256)"));
+    let filter = Filter::new()
+        .address(manager_addr)
+        .topic0(ValueOrArray::Array(vec![
+            Some(increase_topic),
+            Some(decrease_topic),
+            Some(collect_topic),
+        ]))
+        .topic1(ValueOrArray::Array(
+            ids.iter().map(|id| Some(topic(*id))).collect(),
+        ));
+
+    // Per token id: (deposited, withdrawn, collected), each as (amount0, amount1)
+    let mut flows: HashMap<H256, [(U256, U256); 3]> = HashMap::new();
+    let checkpoints = CheckpointStore::in_memory();
+    LogScanner::new(blockchain_service.provider(), &checkpoints)
+        .with_config(scanner_config())
+        .scan("lp_v3", &filter, from_block, Some(latest), |logs| {
+            for log in logs {
+                let (Some(event), Some(id)) = (log.topics.first(), log.topics.get(1)) else {
+                    continue;
+                };
+                // Liquidity events lead with the liquidity delta, Collect with the recipient
+                let Some((amount0, amount1)) = data_words(&log, 1) else {
+                    continue;
+                };
+                let slot = match *event {
+                    topic if topic == increase_topic => 0,
+                    topic if topic == decrease_topic => 1,
+                    _ => 2,
+                };
+                let entry = &mut flows.entry(*id).or_default()[slot];
+                entry.0 += amount0;
+                entry.1 += amount1;
+            }
+            Ok(())
+        })
+        .await?;
+
+    let factory = v3_factory(blockchain_service).await?;
+    let mut found = Vec::new();
+    for id in ids {
+        let (_, _, token0, token1, fee, tick_lower, tick_upper, liquidity, _, _, _, _): (
+            U256,
+            Address,
+            Address,
+            Address,
+            u32,
+            i32,
+            i32,
+            u128,
+            U256,
+            U256,
+            u128,
+            u128,
+        ) = manager.method("positions", id)?.call().await?;
+        let token0_info = blockchain_service
+            .resolve_token(&format!("{:?}", token0))
+            .await?;
+        let token1_info = blockchain_service
+            .resolve_token(&format!("{:?}", token1))
+            .await?;
+
+        let pool_addr: Address = factory
+            .method::<_, Address>("getPool", (token0, token1, fee))?
+            .call()
+            .await?;
+        let pool = contract(
+            blockchain_service,
+            pool_addr,
+            &[
+                "function slot0() view returns (uint160 sqrtPriceX96, int24 tick, uint16 observationIndex, uint16 observationCardinality, uint16 observationCardinalityNext, uint8 feeProtocol, bool unlocked)",
+            ],
+        )?;
+        let (sqrt_price_x96, tick, _, _, _, _, _): (U256, i32, u16, u16, u16, u8, bool) = pool
+            .method::<_, (U256, i32, u16, u16, u16, u8, bool)>("slot0", ())?
+            .call()
+            .await?;
+
+        // Token amounts the liquidity is worth at the current price
+        let sqrt_price = sqrt_price_x96.to_string().parse::<f64>()? / 2f64.powi(96);
+        let sqrt_lower = 1.0001f64.powf(tick_lower as f64 / 2.0);
+        let sqrt_upper = 1.0001f64.powf(tick_upper as f64 / 2.0);
+        let liquidity = liquidity as f64;
+        let (raw0, raw1) = if sqrt_price <= sqrt_lower {
+            (
+                liquidity * (sqrt_upper - sqrt_lower) / (sqrt_lower * sqrt_upper),
+                0.0,
+            )
+        } else if sqrt_price >= sqrt_upper {
+            (0.0, liquidity * (sqrt_upper - sqrt_lower))
+        } else {
+            (
+                liquidity * (sqrt_upper - sqrt_price) / (sqrt_price * sqrt_upper),
+                liquidity * (sqrt_price - sqrt_lower),
+            )
+        };
+
+        // Simulating a collect from the owner returns everything it could withdraw:
+        // fees accrued so far plus principal already removed but not yet collected
+        let (owed0, owed1): (U256, U256) = manager
+            .method::<_, (U256, U256)>("collect", ((id, owner, u128::MAX, u128::MAX),))?
+            .from(owner)
+            .call()
+            .await?;
+
+        let [deposited, withdrawn, collected] = flows.remove(&topic(id)).unwrap_or_default();
+        // Collections include withdrawn principal; what's left over is fees
+        let fees0 = (collected.0 + owed0).saturating_sub(withdrawn.0);
+        let fees1 = (collected.1 + owed1).saturating_sub(withdrawn.1);
+
+        found.push(Found {
+            position: LpPosition {
+                dex: "uniswap_v3".to_string(),
+                id: id.to_string(),
+                pool: format!("{:?}", pool_addr),
+                token0: token0_info.symbol.clone(),
+                token1: token1_info.symbol.clone(),
+                fee: Some(fee),
+                in_range: Some(tick >= tick_lower && tick < tick_upper),
+                amount0: raw0 / 10f64.powi(token0_info.decimals as i32),
+                amount1: raw1 / 10f64.powi(token1_info.decimals as i32),
+                deposited0: amount(deposited.0, token0_info.decimals)?
+                    - amount(withdrawn.0, token0_info.decimals)?,
+                deposited1: amount(deposited.1, token1_info.decimals)?
+                    - amount(withdrawn.1, token1_info.decimals)?,
+                fees0: amount(fees0, token0_info.decimals)?,
+                fees1: amount(fees1, token1_info.decimals)?,
+                position_value_usd: None,
+                hold_value_usd: None,
+                fees_usd: None,
+                impermanent_loss_usd: None,
+                impermanent_loss_percent: None,
+                net_vs_hold_usd: None,
+            },
+            tokens: [token0, token1],
+        });
+    }
+    Ok(found)
+}
+
+async fn v3_factory(
+    blockchain_service: &BlockchainService,
+) -> Result<Contract<Provider<FailoverClient>>> {
+    let v3 = blockchain_service
+        .router_config()
+        .uniswap_v3
+        .clone()
+        .ok_or_else(|| anyhow!("Uniswap V3 isn't configured"))?;
+    let router = contract(
+        blockchain_service,
+        Address::from_str(&v3.router)?,
+        &["function factory() view returns (address)"],
+    )?;
+    let factory: Address = router.method::<_, Address>("factory", ())?.call().await?;
+    contract(
+        blockchain_service,
+        factory,
+        &["function getPool(address,address,uint24) view returns (address)"],
+    )
+}
+
+// V2 liquidity is an ERC20 minted to the provider, so pairs are found from mints to
+// the owner; deposit amounts come from the Mint event in the same transaction
+async fn v2_positions(
+    blockchain_service: &BlockchainService,
+    owner: Address,
+    from_block: u64,
+    latest: u64,
+) -> Result<Vec<Found>> {
+    let provider = blockchain_service.provider();
+    let router = contract(
+        blockchain_service,
+        Address::from_str(&blockchain_service.router_config().uniswap_v2_router)?,
+        &["function factory() view returns (address)"],
+    )?;
+    let factory: Address = router.method::<_, Address>("factory", ())?.call().await?;
+
+    let transfer_topic = H256::from(keccak256("Transfer(address,address,uint256)"));
+    let filter = Filter::new()
+        .topic0(transfer_topic)
+        .topic1(H256::zero())
+        .topic2(H256::from(owner));
+    let mut mints: BTreeMap<Address, HashSet<H256>> = BTreeMap::new();
+    let checkpoints = CheckpointStore::in_memory();
+    let scanner = LogScanner::new(provider.clone(), &checkpoints).with_config(scanner_config());
+    scanner
+        .scan("lp_v2_mints", &filter, from_block, Some(latest), |logs| {
+            for log in logs.into_iter().filter(|log| log.topics.len() == 3) {
+                if let Some(hash) = log.transaction_hash {
+                    mints.entry(log.address).or_default().insert(hash);
+                }
+            }
+            Ok(())
+        })
+        .await?;
+
+    let mint_topic = H256::from(keccak256("Mint(address,uint256,uint256)"));
+    let burn_topic = H256::from(keccak256("Burn(address,uint256,uint256,address)"));
+    let mut found = Vec::new();
+    for (pair_addr, transactions) in mints {
+        let pair = contract(blockchain_service, pair_addr, V2_PAIR_ABI)?;
+        // Anything else minted to the owner isn't a pair of this factory
+        let Ok(pair_factory) = pair.method::<_, Address>("factory", ())?.call().await else {
+            continue;
+        };
+        if pair_factory != factory {
+            continue;
+        }
+        let balance: U256 = pair.method::<_, U256>("balanceOf", owner)?.call().await?;
+        if balance.is_zero() {
+            continue;
+        }
+        let token0: Address = pair.method::<_, Address>("token0", ())?.call().await?;
+        let token1: Address = pair.method::<_, Address>("token1", ())?.call().await?;
+        let token0_info = blockchain_service
+            .resolve_token(&format!("{:?}", token0))
+            .await?;
+        let token1_info = blockchain_service
+            .resolve_token(&format!("{:?}", token1))
+            .await?;
+        let supply: U256 = pair.method::<_, U256>("totalSupply", ())?.call().await?;
+        let (reserve0, reserve1, _): (u128, u128, u32) = pair
+            .method::<_, (u128, u128, u32)>("getReserves", ())?
+            .call()
+            .await?;
+
+        let mut deposited = (U256::zero(), U256::zero());
+        for hash in transactions {
+            let Some(receipt) = provider.get_transaction_receipt(hash).await? else {
+                continue;
+            };
+            for log in receipt
+                .logs
+                .iter()
+                .filter(|log| log.address == pair_addr && log.topics.first() == Some(&mint_topic))
+            {
+                if let Some((amount0, amount1)) = data_words(log, 0) {
+                    deposited.0 += amount0;
+                    deposited.1 += amount1;
+                }
+            }
+        }
+        // Burns name the recipient of the withdrawn tokens as their last topic
+        let mut withdrawn = (U256::zero(), U256::zero());
+        let burns = Filter::new()
+            .address(pair_addr)
+            .topic0(burn_topic)
+            .topic2(H256::from(owner));
+        scanner
+            .scan("lp_v2_burns", &burns, from_block, Some(latest), |logs| {
+                for log in &logs {
+                    if let Some((amount0, amount1)) = data_words(log, 0) {
+                        withdrawn.0 += amount0;
+                        withdrawn.1 += amount1;
+                    }
+                }
+                Ok(())
+            })
+            .await?;
+
+        let share = amount(balance, 18)? / amount(supply, 18)?;
+        let current0 = share * amount(U256::from(reserve0), token0_info.decimals)?;
+        let current1 = share * amount(U256::from(reserve1), token1_info.decimals)?;
+        let deposited0 =
+            amount(deposited.0, token0_info.decimals)? - amount(withdrawn.0, token0_info.decimals)?;
+        let deposited1 =
+            amount(deposited.1, token1_info.decimals)? - amount(withdrawn.1, token1_info.decimals)?;
+
+        // Without fees the deposit would have slid along x * y = k to the current
+        // price; what the position holds beyond that is fees
+        let (principal0, principal1) = if current0 > 0.0 && deposited0 > 0.0 && deposited1 > 0.0 {
+            let k = deposited0 * deposited1;
+            let price = current1 / current0;
+            ((k / price).sqrt(), (k * price).sqrt())
+        } else {
+            (current0, current1)
+        };
+
+        found.push(Found {
+            position: LpPosition {
+                dex: "uniswap_v2".to_string(),
+                id: format!("{:?}", pair_addr),
+                pool: format!("{:?}", pair_addr),
+                token0: token0_info.symbol.clone(),
+                token1: token1_info.symbol.clone(),
+                fee: None,
+                in_range: None,
+                amount0: principal0.min(current0),
+                amount1: principal1.min(current1),
+                deposited0,
+                deposited1,
+                fees0: (current0 - principal0).max(0.0),
+                fees1: (current1 - principal1).max(0.0),
+                position_value_usd: None,
+                hold_value_usd: None,
+                fees_usd: None,
+                impermanent_loss_usd: None,
+                impermanent_loss_percent: None,
+                net_vs_hold_usd: None,
+            },
+            tokens: [token0, token1],
+        });
+    }
+    Ok(found)
+}
@@ -6,11 +6,6 @@ use tracing_subscriber::FmtSubscriber;
 // Type alias for the Ethereum provider
 pub type EthProvider = Arc<Provider<Http>>;
 
-use mcp_server::blockchain::BlockchainService;
-use mcp_server::tools::ToolRegistry;
-use mcp_server::server::Server;
-use shared::get_test_accounts;
-
 #[tokio::main]
 async fn main() -> Result<()> {
   // Initialize tracing
@@ -19,23 +14,7 @@ async fn main() -> Result<()> {
       .finish();
   tracing::subscriber::set_global_default(subscriber)?;
   
-  // Create Ethereum provider
-  let provider_url = std::env::var("ETH_RPC_URL").unwrap_or_else(|_| "http://localhost:8545".to_string());
-  let provider = Provider::<Http>::try_from(provider_url)?;
-  let provider = Arc::new(provider);
-  
-  // Create blockchain service
-  let blockchain_service = BlockchainService::new(provider)?;
-  
-  // Create and register tools
-  let mut tool_registry = ToolRegistry::new();
-  tool_registry.register_default_tools();
-  
-  // Get test accounts
-  let accounts = get_test_accounts();
-  
-  // Create server
-  let server = Server::new(blockchain_service, tool_registry, accounts);
+  let server = mcp_server::server_from_env()?;
   
   // Run server
   let server_addr = std::env::var("SERVER_ADDR").unwrap_or_else(|_| "127.0.0.1:3000".to_string());
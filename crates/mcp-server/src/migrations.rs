@@ -0,0 +1,387 @@
+use anyhow::{Result, anyhow};
+use ethers::types::{Address, U256};
+use ethers::utils::{format_units, to_checksum};
+use serde::{Deserialize, Serialize};
+use shared::{Account, BalanceQuery, ContractCall, TransactionResult, utils};
+use std::str::FromStr;
+use tracing::warn;
+
+use crate::blockchain::{BlockchainService, FeeOverrides, TokenInfo};
+
+fn default_function() -> String {
+    "migrate(uint256)".to_string()
+}
+
+fn default_parameters() -> Vec<String> {
+    vec!["{amount}".to_string()]
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// A published migration from an old token contract to its replacement
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenMigration {
+    pub name: String,
+    pub chain_id: u64,
+    pub old_token: String,
+    pub new_token: String,
+    /// Contract that takes the old tokens and hands out the new ones
+    pub migrator: String,
+    /// Function called on the migrator
+    #[serde(default = "default_function")]
+    pub function: String,
+    /// Its arguments; "{amount}" is replaced with the raw old-token amount and
+    /// "{account}" with the holder's address
+    #[serde(default = "default_parameters")]
+    pub parameters: Vec<String>,
+    /// Whether the migrator pulls the old tokens, so it needs an approval first
+    #[serde(default = "default_true")]
+    pub approve: bool,
+    /// New tokens per old token, when the migration isn't one for one
+    #[serde(default)]
+    pub ratio: Option<f64>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MigrationsConfig {
+    pub migrations: Vec<TokenMigration>,
+}
+
+impl MigrationsConfig {
+    /// Migrations listed in the JSON file at `MIGRATIONS_FILE`, if any
+    pub fn from_env() -> Self {
+        let migrations = match std::env::var("MIGRATIONS_FILE") {
+            Ok(path) => match std::fs::read_to_string(&path)
+                .map_err(anyhow::Error::from)
+                .and_then(|content| Ok(serde_json::from_str::<Vec<TokenMigration>>(&content)?))
+            {
+                Ok(migrations) => migrations,
+                Err(e) => {
+                    warn!("Ignoring migrations file {}: {}", path, e);
+                    Vec::new()
+                }
+            },
+            Err(_) => Vec::new(),
+        };
+        Self { migrations }
+    }
+
+    fn for_chain(&self, chain_id: u64) -> impl Iterator<Item = &TokenMigration> {
+        self.migrations
+            .iter()
+            .filter(move |migration| migration.chain_id == chain_id)
+    }
+}
+
+/// A migration with the tokens resolved and, when an address is given, its balance
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MigrationInfo {
+    pub name: String,
+    pub old_token: String,
+    pub old_symbol: String,
+    pub new_token: String,
+    pub new_symbol: String,
+    pub migrator: String,
+    pub old_balance: Option<String>,
+}
+
+/// What migrating would do: the approval if one is needed, then the migrator call
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MigrationPlan {
+    pub migration: String,
+    pub account: String,
+    pub amount: String,
+    pub old_symbol: String,
+    pub new_symbol: String,
+    /// New tokens expected, from the configured ratio (one for one without it)
+    pub expected_new_amount: String,
+    pub steps: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MigrationResult {
+    pub migration: String,
+    pub amount: String,
+    /// Approval first when one was needed, then the migration
+    pub transactions: Vec<TransactionResult>,
+    pub new_symbol: String,
+    pub new_balance: String,
+}
+
+// A migration's call with its token details, ready to plan or send
+struct Resolved<'a> {
+    migration: &'a TokenMigration,
+    old: TokenInfo,
+    new: TokenInfo,
+    migrator: Address,
+    amount: U256,
+    needs_approval: bool,
+}
+
+async fn token_balance(
+    blockchain_service: &BlockchainService,
+    token: Address,
+    holder: Address,
+) -> Result<U256> {
+    blockchain_service
+        .balances_of(holder, &[token])
+        .await?
+        .tokens
+        .first()
+        .copied()
+        .flatten()
+        .ok_or_else(|| anyhow!("Couldn't read the balance of {:?}", token))
+}
+
+/// Configured migrations on the current chain, with `address`'s old-token balances
+pub async fn list_migrations(
+    blockchain_service: &BlockchainService,
+    config: &MigrationsConfig,
+    address: Option<&str>,
+) -> Result<Vec<MigrationInfo>> {
+    let mut migrations = Vec::new();
+    for migration in config.for_chain(blockchain_service.chain_id()) {
+        let old = blockchain_service
+            .resolve_token(&migration.old_token)
+            .await?;
+        let new = blockchain_service
+            .resolve_token(&migration.new_token)
+            .await?;
+        let old_balance = match address {
+            Some(address) => Some(
+                blockchain_service
+                    .get_balance(BalanceQuery {
+                        address: address.to_string(),
+                        token: Some(old.address.clone()),
+                    })
+                    .await?
+                    .balance,
+            ),
+            None => None,
+        };
+        migrations.push(MigrationInfo {
+            name: migration.name.clone(),
+            old_token: old.address,
+            old_symbol: old.symbol,
+            new_token: new.address,
+            new_symbol: new.symbol,
+            migrator: migration.migrator.clone(),
+            old_balance,
+        });
+    }
+    Ok(migrations)
+}
+
+// Find the migration for `token` (its name, or the old token's symbol or address) and
+// work out the amount and whether the migrator needs an approval
+async fn resolve<'a>(
+    blockchain_service: &BlockchainService,
+    config: &'a MigrationsConfig,
+    holder: Address,
+    token: &str,
+    amount: Option<&str>,
+) -> Result<Resolved<'a>> {
+    let chain_id = blockchain_service.chain_id();
+    let mut found = None;
+    for migration in config.for_chain(chain_id) {
+        if migration.name.eq_ignore_ascii_case(token.trim()) {
+            found = Some((migration, None));
+            break;
+        }
+        let old = blockchain_service
+            .resolve_token(&migration.old_token)
+            .await?;
+        if old.symbol.eq_ignore_ascii_case(token.trim())
+            || old.address.eq_ignore_ascii_case(token.trim())
+        {
+            found = Some((migration, Some(old)));
+            break;
+        }
+    }
+    let (migration, old) = found.ok_or_else(|| {
+        anyhow!(
+            "No migration for {} is configured on chain {}; list migrations in MIGRATIONS_FILE",
+            token,
+            chain_id
+        )
+    })?;
+    let old = match old {
+        Some(old) => old,
+        None => {
+            blockchain_service
+                .resolve_token(&migration.old_token)
+                .await?
+        }
+    };
+    let new = blockchain_service
+        .resolve_token(&migration.new_token)
+        .await?;
+    let migrator = Address::from_str(&migration.migrator)
+        .map_err(|_| anyhow!("Invalid migrator address {}", migration.migrator))?;
+    let old_address = Address::from_str(&old.address)?;
+
+    let balance = token_balance(blockchain_service, old_address, holder).await?;
+    let amount = match amount {
+        Some(amount) => utils::parse_amount(amount, old.decimals)?,
+        None => balance,
+    };
+    if amount.is_zero() {
+        return Err(anyhow!("There's no {} to migrate", old.symbol));
+    }
+    if amount > balance {
+        return Err(anyhow!(
+            "Only {} {} is held",
+            format_units(balance, old.decimals as u32)?,
+            old.symbol
+        ));
+    }
+    let needs_approval = migration.approve
+        && blockchain_service
+            .token_allowance(old_address, holder, migrator)
+            .await?
+            < amount;
+
+    Ok(Resolved {
+        migration,
+        old,
+        new,
+        migrator,
+        amount,
+        needs_approval,
+    })
+}
+
+fn expected_new_amount(resolved: &Resolved) -> Result<String> {
+    let old: f64 = format_units(resolved.amount, resolved.old.decimals as u32)?.parse()?;
+    Ok(format!("{}", old * resolved.migration.ratio.unwrap_or(1.0)))
+}
+
+fn migrate_call(resolved: &Resolved, holder: Address) -> ContractCall {
+    let parameters = resolved
+        .migration
+        .parameters
+        .iter()
+        .map(|parameter| {
+            parameter
+                .replace("{amount}", &resolved.amount.to_string())
+                .replace("{account}", &format!("{:?}", holder))
+        })
+        .collect();
+    ContractCall {
+        contract_address: format!("{:?}", resolved.migrator),
+        function_signature: resolved.migration.function.clone(),
+        parameters,
+        from: None,
+    }
+}
+
+/// Describe migrating `amount` (all of it by default) of an old token without
+/// sending anything
+pub async fn plan_migration(
+    blockchain_service: &BlockchainService,
+    config: &MigrationsConfig,
+    from_account: &Account,
+    token: &str,
+    amount: Option<&str>,
+) -> Result<MigrationPlan> {
+    let holder = Address::from_str(&from_account.address)?;
+    let resolved = resolve(blockchain_service, config, holder, token, amount).await?;
+    let amount = format_units(resolved.amount, resolved.old.decimals as u32)?;
+
+    let mut steps = Vec::new();
+    if resolved.needs_approval {
+        steps.push(format!(
+            "Approve {} {} for the migrator {}",
+            amount,
+            resolved.old.symbol,
+            to_checksum(&resolved.migrator, None)
+        ));
+    }
+    let call = migrate_call(&resolved, holder);
+    steps.push(format!(
+        "Call {}({}) on {}",
+        call.function_signature
+            .split('(')
+            .next()
+            .unwrap_or_default(),
+        call.parameters.join(", "),
+        call.contract_address
+    ));
+
+    Ok(MigrationPlan {
+        migration: resolved.migration.name.clone(),
+        account: from_account.name.clone(),
+        expected_new_amount: expected_new_amount(&resolved)?,
+        amount,
+        old_symbol: resolved.old.symbol,
+        new_symbol: resolved.new.symbol,
+        steps,
+    })
+}
+
+/// Approve the migrator for exactly the amount if needed, then call it
+pub async fn migrate(
+    blockchain_service: &BlockchainService,
+    config: &MigrationsConfig,
+    from_account: &Account,
+    token: &str,
+    amount: Option<&str>,
+    fees: &FeeOverrides,
+) -> Result<MigrationResult> {
+    let holder = Address::from_str(&from_account.address)?;
+    let resolved = resolve(blockchain_service, config, holder, token, amount).await?;
+    let mut transactions = Vec::new();
+
+    if resolved.needs_approval {
+        let approval = blockchain_service
+            .set_allowance(
+                from_account,
+                &resolved.old,
+                resolved.migrator,
+                resolved.amount,
+                fees,
+            )
+            .await?;
+        if approval.status != "success" {
+            return Err(anyhow!(
+                "Approving the migrator failed ({}): {}",
+                approval.status,
+                approval.hash
+            ));
+        }
+        transactions.push(approval);
+    }
+
+    let transaction = blockchain_service
+        .write_contract(
+            from_account,
+            &migrate_call(&resolved, holder),
+            "0",
+            None,
+            &|name| name.to_string(),
+            fees,
+        )
+        .await?;
+    let status = transaction.status.clone();
+    let hash = transaction.hash.clone();
+    transactions.push(transaction);
+    if status != "success" {
+        return Err(anyhow!("Migration failed ({}): {}", status, hash));
+    }
+
+    let new_balance = token_balance(
+        blockchain_service,
+        Address::from_str(&resolved.new.address)?,
+        holder,
+    )
+    .await?;
+    Ok(MigrationResult {
+        migration: resolved.migration.name.clone(),
+        amount: format_units(resolved.amount, resolved.old.decimals as u32)?,
+        transactions,
+        new_balance: format_units(new_balance, resolved.new.decimals as u32)?,
+        new_symbol: resolved.new.symbol,
+    })
+}
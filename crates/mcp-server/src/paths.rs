@@ -0,0 +1,90 @@
+use anyhow::{Result, anyhow};
+use shared::abi_loader::{AbiInterface, AbiLoader};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use tracing::{info, warn};
+
+// ABI files loaded from the data directory at startup; a bundled copy stands in for
+// any that are missing
+pub const ABI_FILES: &[(&str, AbiInterface)] = &[
+    ("erc20_abi.json", AbiInterface::Erc20),
+    ("uniswap_v2_router_abi.json", AbiInterface::UniswapV2Router),
+    ("uniswap_v3_router_abi.json", AbiInterface::UniswapV3Router),
+    ("erc721_abi.json", AbiInterface::Erc721),
+];
+
+static DATA_DIR: OnceLock<(PathBuf, &'static str)> = OnceLock::new();
+
+// The data directory and how it was found. DATA_DIR wins; otherwise ./data, then a data
+// directory beside the executable or above it, which finds the repository's when the
+// binary runs from target/debug in another working directory.
+fn resolve() -> (PathBuf, &'static str) {
+    if let Ok(dir) = std::env::var("DATA_DIR") {
+        return (PathBuf::from(dir), "DATA_DIR");
+    }
+    let cwd = PathBuf::from("./data");
+    if cwd.is_dir() {
+        return (cwd, "working directory");
+    }
+    if let Ok(exe) = std::env::current_exe()
+        && let Some(dir) = exe
+            .ancestors()
+            .skip(1)
+            .map(|dir| dir.join("data"))
+            .find(|dir| dir.is_dir())
+    {
+        return (dir, "executable location");
+    }
+    (cwd, "default")
+}
+
+/// The server's data directory, resolved once
+pub fn data_dir() -> &'static Path {
+    &DATA_DIR.get_or_init(resolve).0
+}
+
+/// A file or directory inside the data directory
+pub fn data_path(relative: impl AsRef<Path>) -> PathBuf {
+    data_dir().join(relative)
+}
+
+/// Check the data directory before the server starts: log where it resolved to, warn
+/// about missing ABI files, and fail on ABI files that don't parse or on a DATA_DIR
+/// that doesn't exist, listing the paths involved.
+pub fn preflight() -> Result<()> {
+    let (dir, found_by) = DATA_DIR.get_or_init(resolve);
+    if !dir.is_dir() {
+        if *found_by == "DATA_DIR" {
+            return Err(anyhow!("DATA_DIR {} is not a directory", dir.display()));
+        }
+        warn!(
+            "No data directory found at {} or near the executable; bundled ABIs are used and state is written to {}. Set DATA_DIR to the repository's data directory.",
+            dir.display(),
+            dir.display()
+        );
+        return Ok(());
+    }
+    let absolute = fs::canonicalize(dir).unwrap_or_else(|_| dir.clone());
+    info!("Data directory: {} (from {})", absolute.display(), found_by);
+
+    let mut invalid = Vec::new();
+    for (file, interface) in ABI_FILES {
+        let path = dir.join(file);
+        if !path.is_file() {
+            warn!("{} missing; using the bundled ABI", path.display());
+            continue;
+        }
+        if let Err(e) = AbiLoader::load_abi(&path, Some(*interface)) {
+            invalid.push(e.to_string());
+        }
+    }
+    if !invalid.is_empty() {
+        return Err(anyhow!(
+            "Invalid ABI files in {}:\n  {}",
+            absolute.display(),
+            invalid.join("\n  ")
+        ));
+    }
+    Ok(())
+}
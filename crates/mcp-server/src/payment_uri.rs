@@ -0,0 +1,137 @@
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::collections::HashMap;
+
+use crate::blockchain::{BlockchainService, NetworkRegistry};
+use crate::units::shift_decimals;
+
+/// What an EIP-681 payment URI asks for, ready to hand to send_eth or send_erc20
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PaymentRequest {
+    pub uri: String,
+    /// Chain the URI names; None means any (usually mainnet)
+    pub chain_id: Option<u64>,
+    /// Whether the URI is for the network requests currently use
+    pub on_current_network: bool,
+    /// Configured network for the URI's chain, to switch_network to when it isn't current
+    pub network: Option<String>,
+    pub recipient: String,
+    /// "ETH" or the token symbol
+    pub asset: String,
+    pub token_address: Option<String>,
+    /// Amount in display units; None when the URI leaves it to the payer
+    pub amount: Option<String>,
+    /// Method that makes the payment and its parameters, without the sending account
+    pub method: String,
+    pub params: Value,
+}
+
+/// Parse an EIP-681 URI: "ethereum:<address>[@chain][?value=<wei>]" for ETH, or
+/// "ethereum:<token>[@chain]/transfer?address=<recipient>&uint256=<base units>" for
+/// an ERC20 transfer. Tokens are looked up on the URI's chain when it is configured.
+pub async fn parse_payment_uri(
+    blockchain_service: &BlockchainService,
+    networks: &NetworkRegistry,
+    uri: &str,
+) -> Result<PaymentRequest> {
+    let uri = uri.trim();
+    let rest = uri
+        .get(..9)
+        .filter(|scheme| scheme.eq_ignore_ascii_case("ethereum:"))
+        .map(|_| &uri[9..])
+        .ok_or_else(|| anyhow!("Not an ethereum: payment URI"))?;
+    let rest = rest.strip_prefix("pay-").unwrap_or(rest);
+
+    let (path, query) = rest.split_once('?').unwrap_or((rest, ""));
+    let (target, function) = match path.split_once('/') {
+        Some((target, function)) => (target, Some(function)),
+        None => (path, None),
+    };
+    let (target, chain_id) = match target.split_once('@') {
+        Some((target, chain_id)) => (
+            target,
+            Some(
+                chain_id
+                    .parse::<u64>()
+                    .map_err(|_| anyhow!("Invalid chain id '{}'", chain_id))?,
+            ),
+        ),
+        None => (target, None),
+    };
+    if target.is_empty() {
+        return Err(anyhow!("The payment URI has no target address"));
+    }
+    let query: HashMap<&str, &str> = query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .collect();
+    let on_current_network = chain_id.is_none_or(|id| id == blockchain_service.chain_id());
+    let network = chain_id.and_then(|id| {
+        networks
+            .networks()
+            .iter()
+            .find(|network| network.chain_id == id)
+            .map(|network| network.name.clone())
+    });
+
+    match function {
+        None => {
+            let amount = query
+                .get("value")
+                .map(|value| shift_decimals(value, 0, 18))
+                .transpose()?;
+            let mut params = json!({"to": target});
+            if let Some(amount) = &amount {
+                params["amount"] = json!(amount);
+            }
+            Ok(PaymentRequest {
+                uri: uri.to_string(),
+                chain_id,
+                on_current_network,
+                network,
+                recipient: target.to_string(),
+                asset: "ETH".to_string(),
+                token_address: None,
+                amount,
+                method: "send_eth".to_string(),
+                params,
+            })
+        }
+        Some("transfer") => {
+            let recipient = query
+                .get("address")
+                .ok_or_else(|| anyhow!("The transfer URI has no recipient address"))?;
+            let token = match &network {
+                Some(name) if !on_current_network => {
+                    networks.service(Some(name))?.resolve_token(target).await?
+                }
+                _ => blockchain_service.resolve_token(target).await?,
+            };
+            let amount = query
+                .get("uint256")
+                .map(|amount| shift_decimals(amount, 0, token.decimals as u32))
+                .transpose()?;
+            let mut params = json!({"to": recipient, "token": token.address});
+            if let Some(amount) = &amount {
+                params["amount"] = json!(amount);
+            }
+            Ok(PaymentRequest {
+                uri: uri.to_string(),
+                chain_id,
+                on_current_network,
+                network,
+                recipient: recipient.to_string(),
+                asset: token.symbol,
+                token_address: Some(token.address),
+                amount,
+                method: "send_erc20".to_string(),
+                params,
+            })
+        }
+        Some(other) => Err(anyhow!(
+            "Only ETH payments and ERC20 transfer URIs are supported, not '{}'",
+            other
+        )),
+    }
+}
@@ -0,0 +1,325 @@
+use anyhow::{Result, anyhow};
+use ethers::providers::Middleware;
+use ethers::types::{Address, H256};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::blockchain::{BlockchainService, EthProvider, NetworkRegistry};
+use crate::inbox::InboxStore;
+use crate::tenants::TenantRegistry;
+
+/// How often transactions left over from a previous run are checked until resolved
+pub const RECONCILE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// A transaction that was submitted but whose receipt hasn't been seen yet
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingTransaction {
+    pub hash: String,
+    pub chain_id: u64,
+    pub from: String,
+    pub nonce: u64,
+    /// What the transaction does, e.g. "Send 0.5 ETH to 0x…"
+    pub description: String,
+    pub submitted_at: i64,
+}
+
+/// How a pending transaction turned out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Resolution {
+    Confirmed,
+    Failed,
+    /// Another transaction with the same nonce was mined instead
+    Replaced,
+    /// The network no longer knows the transaction and its nonce is still free
+    Dropped,
+}
+
+// Submitted transactions awaiting a receipt; persisted as one JSON file so they
+// survive a restart
+pub struct PendingStore {
+    path: Option<PathBuf>,
+    transactions: RwLock<Vec<PendingTransaction>>,
+}
+
+impl PendingStore {
+    pub fn in_memory() -> Self {
+        Self {
+            path: None,
+            transactions: RwLock::new(Vec::new()),
+        }
+    }
+
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let transactions = if path.exists() {
+            serde_json::from_str(&fs::read_to_string(&path)?)?
+        } else {
+            Vec::new()
+        };
+
+        Ok(Self {
+            path: Some(path),
+            transactions: RwLock::new(transactions),
+        })
+    }
+
+    pub fn add(&self, transaction: PendingTransaction) -> Result<()> {
+        self.update(|transactions| {
+            transactions.retain(|existing| existing.hash != transaction.hash);
+            transactions.push(transaction);
+        })
+    }
+
+    pub fn remove(&self, hash: &str) -> Result<()> {
+        self.update(|transactions| {
+            transactions.retain(|existing| !existing.hash.eq_ignore_ascii_case(hash))
+        })
+    }
+
+    pub fn list(&self) -> Vec<PendingTransaction> {
+        self.transactions
+            .read()
+            .map(|transactions| transactions.clone())
+            .unwrap_or_default()
+    }
+
+    fn update(&self, change: impl FnOnce(&mut Vec<PendingTransaction>)) -> Result<()> {
+        let mut transactions = self
+            .transactions
+            .write()
+            .map_err(|_| anyhow!("Pending transaction lock poisoned"))?;
+        change(&mut transactions);
+
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(&*transactions)?)?;
+        Ok(())
+    }
+}
+
+/// Resolve the transactions a previous run submitted but never saw confirmed,
+/// recording the outcome in the inbox of every namespace holding the sender.
+/// Ones still in the mempool are checked again until they resolve.
+pub fn spawn_pending_reconciler(
+    networks: Arc<NetworkRegistry>,
+    tenants: Arc<TenantRegistry>,
+    store: Arc<PendingStore>,
+    inbox: Arc<InboxStore>,
+) {
+    // Only what is on file now: transactions sent after startup are resolved by the
+    // request that sent them
+    let mut leftover = store.list();
+    if leftover.is_empty() {
+        return;
+    }
+    info!(
+        "Reconciling {} transactions pending from the previous run",
+        leftover.len()
+    );
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(RECONCILE_INTERVAL);
+        while !leftover.is_empty() {
+            ticker.tick().await;
+
+            let mut unresolved = Vec::new();
+            for transaction in leftover {
+                match resolve(&networks, &transaction).await {
+                    Ok(Some((resolution, block_number))) => settle(
+                        &tenants,
+                        &store,
+                        &inbox,
+                        &transaction,
+                        resolution,
+                        block_number,
+                    ),
+                    Ok(None) => unresolved.push(transaction),
+                    Err(e) => {
+                        warn!("Failed to check pending {}: {}", transaction.hash, e);
+                        unresolved.push(transaction);
+                    }
+                }
+            }
+            leftover = unresolved;
+        }
+    });
+}
+
+// Record how a pending transaction turned out in the sender's inboxes and forget it
+pub(crate) fn settle(
+    tenants: &TenantRegistry,
+    store: &PendingStore,
+    inbox: &InboxStore,
+    transaction: &PendingTransaction,
+    resolution: Resolution,
+    block_number: Option<u64>,
+) {
+    info!("Pending transaction {} {:?}", transaction.hash, resolution);
+    let message = match resolution {
+        Resolution::Confirmed => format!("Confirmed: {}", transaction.description),
+        Resolution::Failed => format!("Failed on chain: {}", transaction.description),
+        Resolution::Replaced => format!(
+            "Replaced by another transaction with nonce {}: {}",
+            transaction.nonce, transaction.description
+        ),
+        Resolution::Dropped => format!(
+            "Dropped by the network and never mined: {}",
+            transaction.description
+        ),
+    };
+    if let Err(e) = inbox.record_for_address(
+        tenants,
+        &transaction.from,
+        "transaction_resolved",
+        &message,
+        block_number,
+        Some(transaction.hash.clone()),
+    ) {
+        warn!("Failed to record {} in the inbox: {}", transaction.hash, e);
+    }
+    if let Err(e) = store.remove(&transaction.hash) {
+        warn!("Failed to remove pending {}: {}", transaction.hash, e);
+    }
+}
+
+// The outcome of a pending transaction and the block it was mined in, or None while
+// it is still waiting in the mempool
+async fn resolve(
+    networks: &NetworkRegistry,
+    transaction: &PendingTransaction,
+) -> Result<Option<(Resolution, Option<u64>)>> {
+    let network = networks
+        .networks()
+        .iter()
+        .find(|network| network.chain_id == transaction.chain_id)
+        .ok_or_else(|| anyhow!("No configured network for chain {}", transaction.chain_id))?;
+    let service = networks.service(Some(&network.name))?;
+    resolve_with(&service.provider(), transaction).await
+}
+
+/// `resolve` against a provider already connected to the transaction's chain
+pub(crate) async fn resolve_with(
+    provider: &EthProvider,
+    transaction: &PendingTransaction,
+) -> Result<Option<(Resolution, Option<u64>)>> {
+    let hash = H256::from_str(&transaction.hash)?;
+
+    if let Some(receipt) = provider.get_transaction_receipt(hash).await? {
+        let resolution = if receipt.status == Some(1.into()) {
+            Resolution::Confirmed
+        } else {
+            Resolution::Failed
+        };
+        return Ok(Some((
+            resolution,
+            receipt.block_number.map(|number| number.as_u64()),
+        )));
+    }
+    if provider.get_transaction(hash).await?.is_some() {
+        return Ok(None);
+    }
+
+    // Unknown to the node: either its nonce was used by something else or it was dropped
+    let from = Address::from_str(&transaction.from)?;
+    let mined_nonce = provider.get_transaction_count(from, None).await?;
+    let resolution = if mined_nonce.as_u64() > transaction.nonce {
+        Resolution::Replaced
+    } else {
+        Resolution::Dropped
+    };
+    Ok(Some((resolution, None)))
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransactionStatus {
+    pub hash: String,
+    /// "pending", "success", "failed", "replaced", "dropped" or "not_found"
+    pub status: String,
+    pub block_number: Option<u64>,
+    /// Blocks including and on top of the one the transaction was mined in
+    pub confirmations: u64,
+    pub required_confirmations: u64,
+    /// Whether the transaction has resolved and, if mined, has the required confirmations
+    pub settled: bool,
+    /// What the transaction does, when it was sent through this server
+    pub description: Option<String>,
+    pub submitted_at: Option<i64>,
+}
+
+/// Where a transaction stands, e.g. one a request returned as pending after its
+/// confirmation timeout. Transactions sent through this server that the node no longer
+/// knows are reported as replaced or dropped.
+pub async fn transaction_status(
+    blockchain_service: &BlockchainService,
+    hash: &str,
+) -> Result<TransactionStatus> {
+    let tx_hash =
+        H256::from_str(hash.trim()).map_err(|_| anyhow!("Invalid transaction hash '{}'", hash))?;
+    let hash = format!("{:#x}", tx_hash);
+    let provider = blockchain_service.provider();
+    let required = blockchain_service.rpc_settings().confirmations.max(1) as u64;
+    let sent = blockchain_service.pending_store().and_then(|store| {
+        store
+            .list()
+            .into_iter()
+            .find(|transaction| transaction.hash.eq_ignore_ascii_case(&hash))
+    });
+    let mut status = TransactionStatus {
+        hash: hash.clone(),
+        status: "not_found".to_string(),
+        block_number: None,
+        confirmations: 0,
+        required_confirmations: required,
+        settled: false,
+        description: sent
+            .as_ref()
+            .map(|transaction| transaction.description.clone()),
+        submitted_at: sent.as_ref().map(|transaction| transaction.submitted_at),
+    };
+
+    if let Some(receipt) = provider.get_transaction_receipt(tx_hash).await? {
+        let mined = receipt
+            .block_number
+            .map(|number| number.as_u64())
+            .unwrap_or_default();
+        let head = provider.get_block_number().await?.as_u64().max(mined);
+        status.status = if receipt.status == Some(1.into()) {
+            "success".to_string()
+        } else {
+            "failed".to_string()
+        };
+        status.block_number = Some(mined);
+        status.confirmations = head - mined + 1;
+        status.settled = status.confirmations >= required;
+        return Ok(status);
+    }
+    if provider.get_transaction(tx_hash).await?.is_some() {
+        status.status = "pending".to_string();
+        return Ok(status);
+    }
+
+    // Only transactions sent from here have a known sender and nonce to check
+    if let Some(transaction) = &sent
+        && let Some((resolution, _)) = resolve_with(&provider, transaction).await?
+    {
+        status.status = match resolution {
+            Resolution::Replaced => "replaced",
+            Resolution::Dropped => "dropped",
+            // Mined between the checks above; report it as still settling
+            Resolution::Confirmed | Resolution::Failed => "pending",
+        }
+        .to_string();
+        status.settled = status.status != "pending";
+    }
+    Ok(status)
+}
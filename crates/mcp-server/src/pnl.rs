@@ -0,0 +1,159 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::blockchain::BlockchainService;
+use crate::external_apis::ExternalAPIService;
+use crate::prices;
+use crate::tenants::Namespace;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Position {
+    pub token: String,
+    pub quantity: f64,
+    pub cost_basis_usd: f64,
+    pub average_cost_usd: Option<f64>,
+    pub current_price_usd: Option<f64>,
+    pub market_value_usd: Option<f64>,
+    pub realized_pnl_usd: f64,
+    pub unrealized_pnl_usd: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PnlReport {
+    pub account: String,
+    pub positions: Vec<Position>,
+    pub total_realized_pnl_usd: f64,
+    pub total_unrealized_pnl_usd: f64,
+    /// Swaps that couldn't be costed (missing execution prices or output amount)
+    pub skipped_swaps: usize,
+}
+
+#[derive(Default)]
+struct Lot {
+    quantity: f64,
+    cost_usd: f64,
+    realized_usd: f64,
+}
+
+/// USD price of a token symbol or address; ETH is priced as WETH
+pub async fn token_usd_price(
+    blockchain_service: &BlockchainService,
+    external_apis: &ExternalAPIService,
+    token: &str,
+) -> Option<f64> {
+    let address = if token.eq_ignore_ascii_case("eth") {
+        blockchain_service.router_config().weth.clone()
+    } else {
+        blockchain_service.resolve_token(token).await.ok()?.address
+    };
+
+    prices::usd_price(blockchain_service, external_apis, &address).await
+}
+
+/// Average-cost P&L for positions opened through the assistant's swaps
+pub async fn get_pnl(
+    blockchain_service: &BlockchainService,
+    external_apis: &ExternalAPIService,
+    namespace: &Namespace,
+    account: &str,
+    token: Option<&str>,
+) -> Result<PnlReport> {
+    let account_name = namespace
+        .accounts
+        .name_for_address(&namespace.accounts.resolve_address(account))
+        .unwrap_or_else(|| account.to_string());
+
+    let mut entries = namespace.audit.entries(0, Some(&account_name));
+    entries.sort_by_key(|entry| entry.timestamp);
+
+    let mut lots: HashMap<String, Lot> = HashMap::new();
+    let mut skipped_swaps = 0;
+
+    for entry in entries
+        .iter()
+        .filter(|entry| entry.method == "swap_tokens" && entry.status == "success")
+    {
+        let Some(result) = &entry.result else {
+            continue;
+        };
+        let from_token = result["from_token"].as_str().unwrap_or("").to_uppercase();
+        let to_token = result["to_token"].as_str().unwrap_or("").to_uppercase();
+        let amount_in = result["input_amount"]
+            .as_str()
+            .and_then(|amount| amount.parse::<f64>().ok());
+        let amount_out = result["output_amount"]
+            .as_str()
+            .and_then(|amount| amount.parse::<f64>().ok());
+        let price_in = result["prices_usd"]["from"].as_f64();
+        let price_out = result["prices_usd"]["to"].as_f64();
+
+        // Value of the trade at execution, preferring the side that was spent
+        let (Some(amount_in), Some(amount_out)) = (amount_in, amount_out) else {
+            skipped_swaps += 1;
+            continue;
+        };
+        let Some(trade_value) = price_in
+            .map(|price| price * amount_in)
+            .or(price_out.map(|price| price * amount_out))
+        else {
+            skipped_swaps += 1;
+            continue;
+        };
+
+        // Dispose of the sold token at average cost; holdings acquired outside
+        // the assistant have no known basis and are ignored
+        let sold = lots.entry(from_token).or_default();
+        let tracked = amount_in.min(sold.quantity);
+        if tracked > 0.0 {
+            let average_cost = sold.cost_usd / sold.quantity;
+            let proceeds = trade_value * tracked / amount_in;
+            sold.realized_usd += proceeds - average_cost * tracked;
+            sold.cost_usd -= average_cost * tracked;
+            sold.quantity -= tracked;
+        }
+
+        let bought = lots.entry(to_token).or_default();
+        bought.quantity += amount_out;
+        bought.cost_usd += trade_value;
+    }
+
+    let mut positions = Vec::new();
+    for (symbol, lot) in lots {
+        if let Some(token) = token
+            && !symbol.eq_ignore_ascii_case(token)
+        {
+            continue;
+        }
+        if lot.quantity <= f64::EPSILON && lot.realized_usd == 0.0 {
+            continue;
+        }
+
+        let current_price = if lot.quantity > f64::EPSILON {
+            token_usd_price(blockchain_service, external_apis, &symbol).await
+        } else {
+            None
+        };
+        let market_value = current_price.map(|price| price * lot.quantity);
+
+        positions.push(Position {
+            average_cost_usd: (lot.quantity > f64::EPSILON).then(|| lot.cost_usd / lot.quantity),
+            current_price_usd: current_price,
+            market_value_usd: market_value,
+            unrealized_pnl_usd: market_value.map(|value| value - lot.cost_usd),
+            token: symbol,
+            quantity: lot.quantity,
+            cost_basis_usd: lot.cost_usd,
+            realized_pnl_usd: lot.realized_usd,
+        });
+    }
+    positions.sort_by(|a, b| a.token.cmp(&b.token));
+
+    Ok(PnlReport {
+        account: account_name,
+        total_realized_pnl_usd: positions.iter().map(|p| p.realized_pnl_usd).sum(),
+        total_unrealized_pnl_usd: positions.iter().filter_map(|p| p.unrealized_pnl_usd).sum(),
+        positions,
+        skipped_swaps,
+    })
+}
@@ -0,0 +1,443 @@
+use anyhow::{Result, anyhow};
+use ethers::{
+    abi::{ParamType, Token, decode, encode},
+    types::{Bytes, U256},
+    utils::{format_units, parse_units},
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+use crate::blockchain::SwapQuote;
+use crate::screening::ScreeningResult;
+
+// ERC20 approve(address,uint256) selector
+const APPROVE_SELECTOR: [u8; 4] = [0x09, 0x5e, 0xa7, 0xb3];
+
+pub use shared::WRITE_METHODS;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PolicyConfig {
+    /// Maximum write transactions per account inside `write_window_secs` (None = unlimited)
+    pub max_writes_per_window: Option<u32>,
+    pub write_window_secs: u64,
+    /// Whether a request may send to a flagged address by passing `override_screening`
+    pub allow_screening_override: bool,
+    /// Whether approve_token may grant more than the approval caps by passing
+    /// `override_approval_cap`
+    pub allow_approval_cap_override: bool,
+    /// Never approve more than this multiple of the amount being swapped (None = no cap)
+    pub max_approval_multiplier: Option<f64>,
+    /// Hard cap on the USD value of any single approval (None = no cap)
+    pub max_approval_usd: Option<f64>,
+    /// Swaps whose price impact is above this percentage need `accept_price_impact`
+    /// (None = no limit)
+    pub max_price_impact_percent: Option<f64>,
+}
+
+impl Default for PolicyConfig {
+    fn default() -> Self {
+        Self {
+            max_writes_per_window: None,
+            write_window_secs: 3600,
+            allow_screening_override: false,
+            allow_approval_cap_override: false,
+            max_approval_multiplier: Some(1.0),
+            max_approval_usd: None,
+            max_price_impact_percent: Some(5.0),
+        }
+    }
+}
+
+impl PolicyConfig {
+    /// Read policy knobs from `POLICY_*` environment variables
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+
+        Self {
+            max_writes_per_window: std::env::var("POLICY_MAX_WRITES_PER_WINDOW")
+                .ok()
+                .and_then(|v| v.parse().ok()),
+            write_window_secs: std::env::var("POLICY_WRITE_WINDOW_SECS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(defaults.write_window_secs),
+            allow_screening_override: std::env::var("POLICY_ALLOW_SCREENING_OVERRIDE")
+                .map(|v| v == "true" || v == "1")
+                .unwrap_or(defaults.allow_screening_override),
+            allow_approval_cap_override: std::env::var("POLICY_ALLOW_APPROVAL_CAP_OVERRIDE")
+                .map(|v| v == "true" || v == "1")
+                .unwrap_or(defaults.allow_approval_cap_override),
+            max_approval_multiplier: std::env::var("POLICY_MAX_APPROVAL_MULTIPLIER")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .or(defaults.max_approval_multiplier),
+            max_approval_usd: std::env::var("POLICY_MAX_APPROVAL_USD")
+                .ok()
+                .and_then(|v| v.parse().ok()),
+            max_price_impact_percent: std::env::var("POLICY_MAX_PRICE_IMPACT_PERCENT")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .or(defaults.max_price_impact_percent),
+        }
+    }
+}
+
+// An approval that was reduced to fit the policy caps
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApprovalAdjustment {
+    pub requested: String,
+    pub approved: String,
+    pub reason: String,
+}
+
+// What a swap's approvals are capped by: the policy, and the input token's USD price for
+// the USD cap
+#[derive(Clone, Copy)]
+pub struct SwapApprovals<'a> {
+    pub policy: &'a PolicyEngine,
+    pub price_usd: Option<f64>,
+}
+
+pub struct PolicyEngine {
+    config: PolicyConfig,
+    write_log: Mutex<HashMap<String, VecDeque<Instant>>>,
+}
+
+impl PolicyEngine {
+    pub fn new(config: PolicyConfig) -> Self {
+        Self {
+            config,
+            write_log: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn config(&self) -> &PolicyConfig {
+        &self.config
+    }
+
+    /// Decide whether a send to a flagged recipient may proceed
+    pub fn check_screening(
+        &self,
+        screening: &ScreeningResult,
+        override_requested: bool,
+    ) -> Result<()> {
+        if !screening.flagged {
+            return Ok(());
+        }
+
+        if override_requested && self.config.allow_screening_override {
+            warn!(
+                "Sending to flagged address {} under policy override: {}",
+                screening.address,
+                screening.reasons.join("; ")
+            );
+            return Ok(());
+        }
+
+        Err(anyhow!(
+            "Recipient {} is flagged: {}. Transaction blocked{}",
+            screening.address,
+            screening.reasons.join("; "),
+            if self.config.allow_screening_override {
+                " (pass override_screening to proceed anyway)"
+            } else {
+                ""
+            }
+        ))
+    }
+
+    /// Refuse a swap into a pool too thin for its size unless the user accepted the impact
+    pub fn check_price_impact(&self, quote: &SwapQuote, accepted: bool) -> Result<()> {
+        let Some(limit) = self.config.max_price_impact_percent else {
+            return Ok(());
+        };
+        if quote.price_impact_percent <= limit {
+            return Ok(());
+        }
+        if accepted {
+            warn!(
+                "Swapping {} {} for {} at {}% price impact, above the {}% limit, as accepted",
+                quote.amount_in,
+                quote.from_token,
+                quote.to_token,
+                quote.price_impact_percent,
+                limit
+            );
+            return Ok(());
+        }
+
+        Err(anyhow!(
+            "Swapping {} {} would move the price {}%, above the {}% limit: expect {} {} back. Suggest a smaller amount, or confirm with the user and retry with accept_price_impact set",
+            quote.amount_in,
+            quote.from_token,
+            quote.price_impact_percent,
+            limit,
+            quote.expected_amount_out,
+            quote.to_token
+        ))
+    }
+
+    /// Decide whether an approval the caps reduced may be granted in full instead
+    pub fn allows_uncapped_approval(
+        &self,
+        adjustment: &ApprovalAdjustment,
+        override_requested: bool,
+    ) -> bool {
+        if !override_requested || !self.config.allow_approval_cap_override {
+            return false;
+        }
+        warn!(
+            "Approving {} above the policy cap ({}) under policy override",
+            adjustment.requested, adjustment.reason
+        );
+        true
+    }
+
+    /// Cap an approval amount relative to the swap amount and the USD limit
+    pub fn cap_approval(
+        &self,
+        requested: U256,
+        swap_amount: U256,
+        decimals: u8,
+        price_usd: Option<f64>,
+    ) -> Result<(U256, Option<ApprovalAdjustment>)> {
+        let mut approved = requested;
+        let mut reasons = Vec::new();
+
+        if let Some(multiplier) = self.config.max_approval_multiplier {
+            // Scale by 1e6 so fractional multipliers stay exact enough in integer math
+            let scaled = U256::from((multiplier * 1_000_000.0) as u64);
+            let cap = swap_amount.saturating_mul(scaled) / U256::from(1_000_000u64);
+            if approved > cap {
+                approved = cap;
+                reasons.push(format!("limited to {}x the swap amount", multiplier));
+            }
+        }
+
+        if let (Some(max_usd), Some(price)) = (self.config.max_approval_usd, price_usd)
+            && price > 0.0
+        {
+            let max_tokens = format!("{:.*}", decimals as usize, max_usd / price);
+            let cap: U256 = parse_units(max_tokens, decimals as u32)?.into();
+            if approved > cap {
+                approved = cap;
+                reasons.push(format!("limited to ${} of value", max_usd));
+            }
+        }
+
+        if reasons.is_empty() {
+            return Ok((requested, None));
+        }
+
+        let requested_display = if requested == U256::MAX {
+            "unlimited".to_string()
+        } else {
+            format_units(requested, decimals as u32)?
+        };
+
+        Ok((
+            approved,
+            Some(ApprovalAdjustment {
+                requested: requested_display,
+                approved: format_units(approved, decimals as u32)?,
+                reason: reasons.join(", "),
+            }),
+        ))
+    }
+
+    /// Downgrade an ERC20 `approve` found in externally supplied calldata to the policy caps
+    pub fn cap_approval_calldata(
+        &self,
+        data: &[u8],
+        swap_amount: U256,
+        decimals: u8,
+        price_usd: Option<f64>,
+    ) -> Result<(Bytes, Option<ApprovalAdjustment>)> {
+        if data.len() < 4 || data[..4] != APPROVE_SELECTOR {
+            return Ok((Bytes::from(data.to_vec()), None));
+        }
+
+        let args = decode(&[ParamType::Address, ParamType::Uint(256)], &data[4..])?;
+        let (Some(Token::Address(spender)), Some(Token::Uint(requested))) =
+            (args.first().cloned(), args.get(1).cloned())
+        else {
+            return Err(anyhow!("Malformed approve calldata"));
+        };
+
+        let (approved, adjustment) =
+            self.cap_approval(requested, swap_amount, decimals, price_usd)?;
+        if adjustment.is_none() {
+            return Ok((Bytes::from(data.to_vec()), None));
+        }
+
+        let mut rewritten = APPROVE_SELECTOR.to_vec();
+        rewritten.extend(encode(&[Token::Address(spender), Token::Uint(approved)]));
+        warn!(
+            "Downgraded approval for {:#x}: {:?}",
+            spender,
+            adjustment.as_ref().map(|a| &a.reason)
+        );
+
+        Ok((rewritten.into(), adjustment))
+    }
+
+    /// Check the per-account write limit and record the write if it is allowed
+    pub fn check_write(&self, account: &str) -> Result<()> {
+        let Some(max_writes) = self.config.max_writes_per_window else {
+            return Ok(());
+        };
+
+        let window = Duration::from_secs(self.config.write_window_secs);
+        let now = Instant::now();
+
+        let mut write_log = self
+            .write_log
+            .lock()
+            .map_err(|_| anyhow!("Policy state is poisoned"))?;
+        let writes = write_log.entry(account.to_lowercase()).or_default();
+
+        while let Some(oldest) = writes.front() {
+            if now.duration_since(*oldest) >= window {
+                writes.pop_front();
+            } else {
+                break;
+            }
+        }
+
+        if writes.len() >= max_writes as usize {
+            warn!(
+                "Rate limit hit for {}: {} writes in the last {}s",
+                account,
+                writes.len(),
+                self.config.write_window_secs
+            );
+            return Err(anyhow!(
+                "Rate limit exceeded for {}: at most {} transactions per {} seconds",
+                account,
+                max_writes,
+                self.config.write_window_secs
+            ));
+        }
+
+        writes.push_back(now);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::types::Address;
+
+    fn policy(multiplier: Option<f64>, max_usd: Option<f64>) -> PolicyEngine {
+        PolicyEngine::new(PolicyConfig {
+            max_approval_multiplier: multiplier,
+            max_approval_usd: max_usd,
+            ..PolicyConfig::default()
+        })
+    }
+
+    fn units(amount: &str, decimals: u32) -> U256 {
+        parse_units(amount, decimals).unwrap().into()
+    }
+
+    fn approve_calldata(spender: Address, amount: U256) -> Vec<u8> {
+        let mut data = APPROVE_SELECTOR.to_vec();
+        data.extend(encode(&[Token::Address(spender), Token::Uint(amount)]));
+        data
+    }
+
+    #[test]
+    fn approval_is_capped_to_a_multiple_of_the_swap() {
+        let (approved, adjustment) = policy(Some(1.5), None)
+            .cap_approval(U256::MAX, units("100", 6), 6, None)
+            .unwrap();
+        assert_eq!(approved, units("150", 6));
+        let adjustment = adjustment.unwrap();
+        assert_eq!(adjustment.requested, "unlimited");
+        assert_eq!(adjustment.approved, "150.000000");
+        assert_eq!(adjustment.reason, "limited to 1.5x the swap amount");
+    }
+
+    #[test]
+    fn approval_is_capped_to_the_usd_limit() {
+        // $100 of a $2 token is 50 tokens, below 2x the swap
+        let (approved, adjustment) = policy(Some(2.0), Some(100.0))
+            .cap_approval(units("1000", 18), units("40", 18), 18, Some(2.0))
+            .unwrap();
+        assert_eq!(approved, units("50", 18));
+        assert_eq!(
+            adjustment.unwrap().reason,
+            "limited to 2x the swap amount, limited to $100 of value"
+        );
+
+        // Without a price the USD cap can't apply
+        let (approved, _) = policy(None, Some(100.0))
+            .cap_approval(units("1000", 18), units("40", 18), 18, None)
+            .unwrap();
+        assert_eq!(approved, units("1000", 18));
+    }
+
+    #[test]
+    fn approval_cap_override_needs_the_policy_flag() {
+        let (_, adjustment) = policy(Some(1.0), None)
+            .cap_approval(U256::MAX, units("100", 6), 6, None)
+            .unwrap();
+        let adjustment = adjustment.unwrap();
+        assert!(!policy(Some(1.0), None).allows_uncapped_approval(&adjustment, true));
+
+        let permissive = PolicyEngine::new(PolicyConfig {
+            allow_approval_cap_override: true,
+            ..PolicyConfig::default()
+        });
+        assert!(!permissive.allows_uncapped_approval(&adjustment, false));
+        assert!(permissive.allows_uncapped_approval(&adjustment, true));
+    }
+
+    #[test]
+    fn approval_within_the_caps_is_unchanged() {
+        let (approved, adjustment) = policy(Some(1.0), Some(1000.0))
+            .cap_approval(units("10", 18), units("10", 18), 18, Some(3.0))
+            .unwrap();
+        assert_eq!(approved, units("10", 18));
+        assert!(adjustment.is_none());
+    }
+
+    #[test]
+    fn approve_calldata_is_downgraded_for_the_same_spender() {
+        let spender = Address::repeat_byte(0x11);
+        let data = approve_calldata(spender, U256::MAX);
+        let (capped, adjustment) = policy(Some(1.0), None)
+            .cap_approval_calldata(&data, units("25", 6), 6, None)
+            .unwrap();
+        assert_eq!(capped.to_vec(), approve_calldata(spender, units("25", 6)));
+        assert_eq!(adjustment.unwrap().approved, "25.000000");
+
+        let within = approve_calldata(spender, units("25", 6));
+        let (unchanged, adjustment) = policy(Some(1.0), None)
+            .cap_approval_calldata(&within, units("25", 6), 6, None)
+            .unwrap();
+        assert_eq!(unchanged.to_vec(), within);
+        assert!(adjustment.is_none());
+    }
+
+    #[test]
+    fn other_calldata_passes_through() {
+        let swap = [0x38, 0xed, 0x17, 0x39, 0x00, 0x01];
+        let (data, adjustment) = policy(Some(1.0), None)
+            .cap_approval_calldata(&swap, U256::one(), 18, None)
+            .unwrap();
+        assert_eq!(data.to_vec(), swap);
+        assert!(adjustment.is_none());
+
+        assert!(
+            policy(Some(1.0), None)
+                .cap_approval_calldata(&APPROVE_SELECTOR, U256::one(), 18, None)
+                .is_err()
+        );
+    }
+}
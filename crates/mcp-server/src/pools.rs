@@ -0,0 +1,291 @@
+use anyhow::{Result, anyhow};
+use ethers::abi::{Abi, parse_abi};
+use ethers::contract::Contract;
+use ethers::providers::Provider;
+use ethers::types::{Address, U256};
+use ethers::utils::format_units;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+use crate::blockchain::{BlockchainService, TokenInfo};
+use crate::failover::FailoverClient;
+
+const V3_FEE_TIERS: [u32; 4] = [100, 500, 3000, 10000];
+/// Price move the liquidity depth is measured against, in percent
+pub const DEPTH_PERCENT: f64 = 2.0;
+
+const V3_POOL_ABI: &[&str] = &[
+    "function liquidity() view returns (uint128)",
+    "function slot0() view returns (uint160 sqrtPriceX96, int24 tick, uint16 observationIndex, uint16 observationCardinality, uint16 observationCardinalityNext, uint8 feeProtocol, bool unlocked)",
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PoolState {
+    /// "uniswap_v2" or "uniswap_v3"
+    pub dex: String,
+    pub pool: String,
+    /// V3 fee tier in hundredths of a bip; V2 pairs charge 3000
+    pub fee: u32,
+    /// Price of one `token` in `quote`
+    pub price: f64,
+    /// Tokens the pool holds; for V3 this includes liquidity outside the current range
+    pub token_reserve: f64,
+    pub quote_reserve: f64,
+    /// V3 liquidity in the current tick range
+    pub liquidity: Option<String>,
+    pub tick: Option<i32>,
+    /// `token` that can be sold before the price falls DEPTH_PERCENT, before fees
+    pub token_depth: f64,
+    /// `quote` that can be spent before the price rises DEPTH_PERCENT, before fees
+    pub quote_depth: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PoolInfo {
+    pub token: String,
+    pub quote: String,
+    pub chain_id: u64,
+    /// Price in the deepest pool
+    pub price: f64,
+    pub depth_percent: f64,
+    /// The V2 pair and every V3 fee tier with a pool, deepest first
+    pub pools: Vec<PoolState>,
+}
+
+/// Reserves, spot price, fee tier and depth of the Uniswap pools trading `token`
+/// against `quote`, read straight from the pool contracts
+pub async fn get_pool_info(
+    blockchain_service: &BlockchainService,
+    token: &str,
+    quote: &str,
+) -> Result<PoolInfo> {
+    let base = pool_token(blockchain_service, token).await?;
+    let quote_token = pool_token(blockchain_service, quote).await?;
+    if base.address.eq_ignore_ascii_case(&quote_token.address) {
+        return Err(anyhow!("token and quote are the same token"));
+    }
+
+    let mut pools = Vec::new();
+    let mut errors = Vec::new();
+    match v2_pool(blockchain_service, &base, &quote_token).await {
+        Ok(Some(pool)) => pools.push(pool),
+        Ok(None) => {}
+        Err(e) => errors.push(format!("Uniswap V2: {}", e)),
+    }
+    if blockchain_service.router_config().uniswap_v3.is_some() {
+        match v3_pools(blockchain_service, &base, &quote_token).await {
+            Ok(found) => pools.extend(found),
+            Err(e) => errors.push(format!("Uniswap V3: {}", e)),
+        }
+    }
+    if pools.is_empty() {
+        let mut message = format!(
+            "No Uniswap pool found for {}/{}",
+            base.symbol, quote_token.symbol
+        );
+        if !errors.is_empty() {
+            message = format!("{} ({})", message, errors.join("; "));
+        }
+        return Err(anyhow!(message));
+    }
+    pools.sort_by(|a, b| b.quote_depth.total_cmp(&a.quote_depth));
+
+    Ok(PoolInfo {
+        token: base.symbol,
+        quote: quote_token.symbol,
+        chain_id: blockchain_service.chain_id(),
+        price: pools[0].price,
+        depth_percent: DEPTH_PERCENT,
+        pools,
+    })
+}
+
+// Pools hold WETH rather than ETH
+async fn pool_token(blockchain_service: &BlockchainService, token: &str) -> Result<TokenInfo> {
+    if token.eq_ignore_ascii_case("eth") {
+        return blockchain_service
+            .resolve_token(&blockchain_service.router_config().weth)
+            .await;
+    }
+    blockchain_service.resolve_token(token).await
+}
+
+fn contract(
+    blockchain_service: &BlockchainService,
+    address: Address,
+    abi: &[&str],
+) -> Result<Contract<Provider<FailoverClient>>> {
+    let abi: Abi = parse_abi(abi)?;
+    Ok(Contract::new(address, abi, blockchain_service.provider()))
+}
+
+fn amount(raw: U256, decimals: u8) -> Result<f64> {
+    Ok(format_units(raw, decimals as u32)?.parse()?)
+}
+
+fn to_f64(value: U256) -> f64 {
+    value.to_string().parse::<f64>().unwrap_or(0.0)
+}
+
+async fn factory(
+    blockchain_service: &BlockchainService,
+    router: &str,
+    abi: &str,
+) -> Result<Contract<Provider<FailoverClient>>> {
+    let router = contract(
+        blockchain_service,
+        Address::from_str(router)?,
+        &["function factory() view returns (address)"],
+    )?;
+    let factory: Address = router.method::<_, Address>("factory", ())?.call().await?;
+    contract(blockchain_service, factory, &[abi])
+}
+
+// A constant product pair: selling dx of a reserve x moves its price by (x / (x + dx))^2
+async fn v2_pool(
+    blockchain_service: &BlockchainService,
+    base: &TokenInfo,
+    quote: &TokenInfo,
+) -> Result<Option<PoolState>> {
+    let base_addr = Address::from_str(&base.address)?;
+    let quote_addr = Address::from_str(&quote.address)?;
+    let factory = factory(
+        blockchain_service,
+        &blockchain_service.router_config().uniswap_v2_router,
+        "function getPair(address,address) view returns (address)",
+    )
+    .await?;
+    let pair_addr: Address = factory
+        .method::<_, Address>("getPair", (base_addr, quote_addr))?
+        .call()
+        .await?;
+    if pair_addr.is_zero() {
+        return Ok(None);
+    }
+
+    let pair = contract(
+        blockchain_service,
+        pair_addr,
+        &[
+            "function getReserves() view returns (uint112 reserve0, uint112 reserve1, uint32 blockTimestampLast)",
+        ],
+    )?;
+    let (reserve0, reserve1, _): (u128, u128, u32) = pair
+        .method::<_, (u128, u128, u32)>("getReserves", ())?
+        .call()
+        .await?;
+    let (reserve_base, reserve_quote) = if base_addr < quote_addr {
+        (reserve0, reserve1)
+    } else {
+        (reserve1, reserve0)
+    };
+    let token_reserve = amount(U256::from(reserve_base), base.decimals)?;
+    let quote_reserve = amount(U256::from(reserve_quote), quote.decimals)?;
+    let move_fraction = DEPTH_PERCENT / 100.0;
+
+    Ok(Some(PoolState {
+        dex: "uniswap_v2".to_string(),
+        pool: format!("{:?}", pair_addr),
+        fee: 3000,
+        price: if token_reserve > 0.0 {
+            quote_reserve / token_reserve
+        } else {
+            0.0
+        },
+        token_reserve,
+        quote_reserve,
+        liquidity: None,
+        tick: None,
+        token_depth: token_reserve * (1.0 / (1.0 - move_fraction).sqrt() - 1.0),
+        quote_depth: quote_reserve * ((1.0 + move_fraction).sqrt() - 1.0),
+    }))
+}
+
+// Every initialized V3 pool for the pair. Depth assumes the current range's liquidity
+// holds across the whole move, which understates it when the next ranges are deeper.
+async fn v3_pools(
+    blockchain_service: &BlockchainService,
+    base: &TokenInfo,
+    quote: &TokenInfo,
+) -> Result<Vec<PoolState>> {
+    let v3 = blockchain_service
+        .router_config()
+        .uniswap_v3
+        .clone()
+        .ok_or_else(|| anyhow!("Uniswap V3 isn't configured"))?;
+    let base_addr = Address::from_str(&base.address)?;
+    let quote_addr = Address::from_str(&quote.address)?;
+    let factory = factory(
+        blockchain_service,
+        &v3.router,
+        "function getPool(address,address,uint24) view returns (address)",
+    )
+    .await?;
+    let erc20_abi = ["function balanceOf(address owner) view returns (uint256)"];
+    let base_contract = contract(blockchain_service, base_addr, &erc20_abi)?;
+    let quote_contract = contract(blockchain_service, quote_addr, &erc20_abi)?;
+    let base_is_token0 = base_addr < quote_addr;
+    let move_fraction = DEPTH_PERCENT / 100.0;
+
+    let mut pools = Vec::new();
+    for tier in V3_FEE_TIERS {
+        let pool_addr: Address = factory
+            .method::<_, Address>("getPool", (base_addr, quote_addr, tier))?
+            .call()
+            .await?;
+        if pool_addr.is_zero() {
+            continue;
+        }
+        let pool = contract(blockchain_service, pool_addr, V3_POOL_ABI)?;
+        let (sqrt_price_x96, tick, _, _, _, _, _): (U256, i32, u16, u16, u16, u8, bool) = pool
+            .method::<_, (U256, i32, u16, u16, u16, u8, bool)>("slot0", ())?
+            .call()
+            .await?;
+        if sqrt_price_x96.is_zero() {
+            continue;
+        }
+        let liquidity: u128 = pool.method::<_, u128>("liquidity", ())?.call().await?;
+        let token_reserve: U256 = base_contract
+            .method::<_, U256>("balanceOf", pool_addr)?
+            .call()
+            .await?;
+        let quote_reserve: U256 = quote_contract
+            .method::<_, U256>("balanceOf", pool_addr)?
+            .call()
+            .await?;
+
+        // sqrt of token1 per token0 in raw units
+        let sqrt_price = to_f64(sqrt_price_x96) / 2f64.powi(96);
+        let l = liquidity as f64;
+        let scale = 10f64.powi(base.decimals as i32 - quote.decimals as i32);
+        // Raw amounts moving the sqrt price from s to s': token0 is L(1/s' - 1/s),
+        // token1 is L(s' - s)
+        let (price, token_depth, quote_depth) = if base_is_token0 {
+            (
+                sqrt_price * sqrt_price * scale,
+                l / sqrt_price * (1.0 / (1.0 - move_fraction).sqrt() - 1.0),
+                l * sqrt_price * ((1.0 + move_fraction).sqrt() - 1.0),
+            )
+        } else {
+            (
+                scale / (sqrt_price * sqrt_price),
+                l * sqrt_price * (1.0 / (1.0 - move_fraction).sqrt() - 1.0),
+                l / sqrt_price * ((1.0 + move_fraction).sqrt() - 1.0),
+            )
+        };
+
+        pools.push(PoolState {
+            dex: "uniswap_v3".to_string(),
+            pool: format!("{:?}", pool_addr),
+            fee: tier,
+            price,
+            token_reserve: amount(token_reserve, base.decimals)?,
+            quote_reserve: amount(quote_reserve, quote.decimals)?,
+            liquidity: Some(liquidity.to_string()),
+            tick: Some(tick),
+            token_depth: token_depth / 10f64.powi(base.decimals as i32),
+            quote_depth: quote_depth / 10f64.powi(quote.decimals as i32),
+        });
+    }
+    Ok(pools)
+}
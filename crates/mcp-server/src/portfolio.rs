@@ -0,0 +1,233 @@
+use anyhow::Result;
+use ethers::providers::Middleware;
+use ethers::types::{Address, Filter, H256, U256};
+use ethers::utils::{format_units, keccak256};
+use futures::future::join_all;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::str::FromStr;
+use tracing::warn;
+
+use crate::blockchain::{BlockchainService, TokenInfo};
+use crate::external_apis::ExternalAPIService;
+use crate::prices;
+use crate::scanner::{CheckpointStore, LogScanner};
+use crate::streams::{self, PaymentStream, StreamStore};
+
+/// How far back to look for incoming token transfers when discovering tokens
+pub const DEFAULT_DISCOVERY_BLOCKS: u64 = 50_000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Holding {
+    pub symbol: String,
+    /// Token contract; None for ETH
+    pub token_address: Option<String>,
+    pub balance: String,
+    pub price_usd: Option<f64>,
+    pub value_usd: Option<f64>,
+    /// Percentage of the portfolio's priced value
+    pub share: Option<f64>,
+    /// Found through Transfer logs rather than the token registry
+    pub discovered: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Portfolio {
+    pub address: String,
+    pub display_name: Option<String>,
+    pub chain_id: u64,
+    /// Non-zero holdings, most valuable first
+    pub holdings: Vec<Holding>,
+    pub total_usd: f64,
+    /// Holdings without a USD price, left out of the total
+    pub unpriced: Vec<String>,
+    /// Whether balances were read in a single Multicall3 call
+    pub multicall: bool,
+    /// Payment streams the address sends or receives that are still running or have
+    /// funds left to withdraw; not counted in the total
+    pub streams: Vec<PaymentStream>,
+}
+
+/// Every registry token (and optionally tokens the address received recently) with its
+/// balance, USD price and share of the total, plus the address's active payment streams
+pub async fn get_portfolio(
+    blockchain_service: &BlockchainService,
+    external_apis: &ExternalAPIService,
+    streams: &StreamStore,
+    address: &str,
+    discover_blocks: Option<u64>,
+) -> Result<Portfolio> {
+    let owner = blockchain_service.parse_address(address).await?;
+
+    let mut tokens: Vec<(TokenInfo, bool)> = Vec::new();
+    for token in blockchain_service.get_supported_tokens() {
+        if !tokens
+            .iter()
+            .any(|(known, _)| known.address.eq_ignore_ascii_case(&token.address))
+        {
+            tokens.push((token, false));
+        }
+    }
+
+    if let Some(blocks) = discover_blocks {
+        match discover_tokens(blockchain_service, owner, blocks).await {
+            Ok(discovered) => {
+                let unknown: Vec<Address> = discovered
+                    .into_iter()
+                    .filter(|token| {
+                        !tokens.iter().any(|(known, _)| {
+                            Address::from_str(&known.address).ok() == Some(*token)
+                        })
+                    })
+                    .collect();
+                let infos = join_all(unknown.iter().map(|token| async move {
+                    blockchain_service
+                        .resolve_token(&format!("{:?}", token))
+                        .await
+                }))
+                .await;
+                // Contracts without ERC20 metadata are skipped
+                tokens.extend(infos.into_iter().flatten().map(|info| (info, true)));
+            }
+            Err(e) => warn!("Token discovery for {} failed: {}", address, e),
+        }
+    }
+
+    // Tokens with malformed registry addresses can't be queried
+    tokens.retain(|(token, _)| Address::from_str(&token.address).is_ok());
+    let token_addresses: Vec<Address> = tokens
+        .iter()
+        .filter_map(|(token, _)| Address::from_str(&token.address).ok())
+        .collect();
+    let balances = blockchain_service
+        .balances_of(owner, &token_addresses)
+        .await?;
+
+    // ETH is priced through WETH
+    let weth = blockchain_service.router_config().weth.to_lowercase();
+    let mut price_keys: Vec<String> = tokens
+        .iter()
+        .map(|(token, _)| token.address.to_lowercase())
+        .collect();
+    price_keys.push(weth.clone());
+    let prices = prices::usd_prices(blockchain_service, external_apis, &price_keys).await;
+
+    let mut holdings = Vec::new();
+    let mut add = |symbol: &str,
+                   token_address: Option<String>,
+                   balance: U256,
+                   decimals: u8,
+                   price_key: &str,
+                   discovered: bool| {
+        if balance.is_zero() {
+            return;
+        }
+        let Ok(balance) = format_units(balance, decimals as u32) else {
+            return;
+        };
+        let price_usd = prices.get(price_key).copied();
+        let value_usd = price_usd.and_then(|price| balance.parse::<f64>().ok().map(|b| b * price));
+        holdings.push(Holding {
+            symbol: symbol.to_string(),
+            token_address,
+            balance: trim_amount(&balance),
+            price_usd,
+            value_usd,
+            share: None,
+            discovered,
+        });
+    };
+
+    add("ETH", None, balances.eth, 18, &weth, false);
+    for ((token, discovered), balance) in tokens.iter().zip(&balances.tokens) {
+        if let Some(balance) = balance {
+            add(
+                &token.symbol,
+                Some(token.address.clone()),
+                *balance,
+                token.decimals,
+                &token.address.to_lowercase(),
+                *discovered,
+            );
+        }
+    }
+
+    let total_usd: f64 = holdings
+        .iter()
+        .filter_map(|holding| holding.value_usd)
+        .sum();
+    for holding in &mut holdings {
+        holding.share = holding
+            .value_usd
+            .filter(|_| total_usd > 0.0)
+            .map(|value| value / total_usd * 100.0);
+    }
+    holdings.sort_by(|a, b| {
+        b.value_usd
+            .unwrap_or(-1.0)
+            .partial_cmp(&a.value_usd.unwrap_or(-1.0))
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    let unpriced = holdings
+        .iter()
+        .filter(|holding| holding.price_usd.is_none())
+        .map(|holding| holding.symbol.clone())
+        .collect();
+
+    Ok(Portfolio {
+        address: format!("{:?}", owner),
+        display_name: blockchain_service.lookup_name(owner).await,
+        chain_id: blockchain_service.chain_id(),
+        holdings,
+        total_usd,
+        unpriced,
+        multicall: balances.multicall,
+        streams: streams::active_streams(blockchain_service, streams, owner).await,
+    })
+}
+
+// ERC20 contracts that sent the address tokens within the last `blocks` blocks
+async fn discover_tokens(
+    blockchain_service: &BlockchainService,
+    owner: Address,
+    blocks: u64,
+) -> Result<BTreeSet<Address>> {
+    let provider = blockchain_service.provider();
+    let latest = provider.get_block_number().await?.as_u64();
+    let filter = Filter::new()
+        .topic0(H256::from(keccak256("Transfer(address,address,uint256)")))
+        .topic2(H256::from(owner));
+
+    // Discovery always rescans the window, so its progress isn't persisted
+    let checkpoints = CheckpointStore::in_memory();
+    let mut tokens = BTreeSet::new();
+    LogScanner::new(provider, &checkpoints)
+        .scan(
+            "portfolio_discovery",
+            &filter,
+            latest.saturating_sub(blocks),
+            Some(latest),
+            |logs| {
+                // ERC721 transfers index the token ID as a fourth topic
+                tokens.extend(
+                    logs.iter()
+                        .filter(|log| log.topics.len() == 3)
+                        .map(|log| log.address),
+                );
+                Ok(())
+            },
+        )
+        .await?;
+    Ok(tokens)
+}
+
+fn trim_amount(amount: &str) -> String {
+    if amount.contains('.') {
+        amount
+            .trim_end_matches('0')
+            .trim_end_matches('.')
+            .to_string()
+    } else {
+        amount.to_string()
+    }
+}
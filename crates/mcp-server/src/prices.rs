@@ -0,0 +1,71 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use std::collections::HashMap;
+use tracing::warn;
+
+use crate::blockchain::BlockchainService;
+use crate::chainlink::ChainlinkPrices;
+use crate::external_apis::ExternalAPIService;
+
+/// A source of current USD prices for token contracts
+#[async_trait]
+pub trait PriceProvider: Send + Sync {
+    fn name(&self) -> &'static str;
+    /// Prices keyed by lowercase token address; tokens the source doesn't know are
+    /// left out
+    async fn usd_prices(&self, token_addresses: &[String]) -> Result<HashMap<String, f64>>;
+}
+
+#[async_trait]
+impl PriceProvider for ExternalAPIService {
+    fn name(&self) -> &'static str {
+        "defillama"
+    }
+
+    async fn usd_prices(&self, token_addresses: &[String]) -> Result<HashMap<String, f64>> {
+        self.get_token_usd_prices(token_addresses).await
+    }
+}
+
+/// USD prices from DefiLlama, with tokens it can't price (or all of them, when it is
+/// down or rate-limited) filled in from Chainlink feeds on the current network
+pub async fn usd_prices(
+    blockchain_service: &BlockchainService,
+    external_apis: &ExternalAPIService,
+    token_addresses: &[String],
+) -> HashMap<String, f64> {
+    let chainlink = ChainlinkPrices::new(blockchain_service);
+    let providers: [&dyn PriceProvider; 2] = [external_apis, &chainlink];
+
+    let mut prices = HashMap::new();
+    for provider in providers {
+        let missing: Vec<String> = token_addresses
+            .iter()
+            .map(|address| address.to_lowercase())
+            .filter(|address| !prices.contains_key(address))
+            .collect();
+        if missing.is_empty() {
+            break;
+        }
+        match provider.usd_prices(&missing).await {
+            Ok(found) => prices.extend(found),
+            Err(e) => warn!("{} prices unavailable: {}", provider.name(), e),
+        }
+    }
+    prices
+}
+
+/// USD price of a single token contract, see `usd_prices`
+pub async fn usd_price(
+    blockchain_service: &BlockchainService,
+    external_apis: &ExternalAPIService,
+    token_address: &str,
+) -> Option<f64> {
+    usd_prices(
+        blockchain_service,
+        external_apis,
+        &[token_address.to_string()],
+    )
+    .await
+    .remove(&token_address.to_lowercase())
+}
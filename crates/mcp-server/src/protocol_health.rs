@@ -0,0 +1,308 @@
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::warn;
+
+use crate::external_apis::ExternalAPIService;
+
+const DAY: i64 = 24 * 3600;
+const GOVERNANCE_PROPOSALS: u32 = 5;
+const MAX_HALLMARKS: usize = 5;
+// Hallmarks this recent that mention an exploit are called out as warnings
+const RECENT_INCIDENT_DAYS: i64 = 180;
+// A 7-day TVL or token price fall at least this steep is called out as a warning
+const TVL_DROP_WARNING_PERCENT: f64 = -20.0;
+const PRICE_DROP_WARNING_PERCENT: f64 = -25.0;
+const INCIDENT_WORDS: &[&str] = &["exploit", "hack", "drain", "attack", "stolen", "vulnerab"];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenTrend {
+    pub symbol: Option<String>,
+    pub price_usd: Option<f64>,
+    pub change_7d_percent: Option<f64>,
+    pub change_30d_percent: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Proposal {
+    pub space: String,
+    pub title: String,
+    /// "active", "closed" or "pending"
+    pub state: String,
+    pub end: Option<i64>,
+    pub link: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Hallmark {
+    pub timestamp: i64,
+    pub event: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NewsResult {
+    pub title: String,
+    pub url: String,
+    pub description: Option<String>,
+    /// How old the page is, as the search engine reports it
+    pub age: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProtocolHealth {
+    pub name: String,
+    pub slug: String,
+    pub category: Option<String>,
+    pub url: Option<String>,
+    pub tvl_usd: Option<f64>,
+    pub tvl_change_7d_percent: Option<f64>,
+    pub tvl_change_30d_percent: Option<f64>,
+    pub token: Option<TokenTrend>,
+    pub audit_links: Vec<String>,
+    /// Latest Snapshot proposals, when the protocol governs through Snapshot
+    pub governance: Vec<Proposal>,
+    /// Notable events DefiLlama marked on the protocol's TVL chart, newest first
+    pub hallmarks: Vec<Hallmark>,
+    /// Web search results for exploit news; empty without a search API key
+    pub news: Vec<NewsResult>,
+    /// Signals worth raising with the user
+    pub warnings: Vec<String>,
+    /// Sources that couldn't be reached, so their sections are incomplete
+    pub unavailable: Vec<String>,
+}
+
+/// Health signals for a named protocol from several sources at once: TVL and token
+/// price trends from DefiLlama, recent Snapshot governance, DefiLlama hallmarks and a
+/// web search for exploit news. Each source is optional; failures are listed in
+/// `unavailable` rather than failing the whole report.
+pub async fn get_protocol_health(
+    external_apis: &ExternalAPIService,
+    protocol: &str,
+) -> Result<ProtocolHealth> {
+    let profile = find_protocol(external_apis, protocol).await?;
+    let name = profile["name"].as_str().unwrap_or(protocol).to_string();
+    let now = chrono::Utc::now().timestamp();
+
+    let mut health = ProtocolHealth {
+        slug: profile["slug"]
+            .as_str()
+            .map(str::to_string)
+            .unwrap_or_else(|| slug(&name)),
+        category: profile["category"].as_str().map(str::to_string),
+        url: profile["url"].as_str().map(str::to_string),
+        tvl_usd: None,
+        tvl_change_7d_percent: None,
+        tvl_change_30d_percent: None,
+        token: None,
+        audit_links: profile["audit_links"]
+            .as_array()
+            .map(|links| {
+                links
+                    .iter()
+                    .filter_map(|link| link.as_str().map(str::to_string))
+                    .collect()
+            })
+            .unwrap_or_default(),
+        governance: Vec::new(),
+        hallmarks: Vec::new(),
+        news: Vec::new(),
+        warnings: Vec::new(),
+        unavailable: Vec::new(),
+        name,
+    };
+
+    // TVL history, oldest first
+    let tvl: Vec<(i64, f64)> = profile["tvl"]
+        .as_array()
+        .map(|points| {
+            points
+                .iter()
+                .filter_map(|point| {
+                    Some((
+                        point["date"].as_i64()?,
+                        point["totalLiquidityUSD"].as_f64()?,
+                    ))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    if let Some(&(latest_date, latest)) = tvl.last() {
+        let change = |days: i64| {
+            tvl.iter()
+                .rev()
+                .find(|(date, _)| *date <= latest_date - days * DAY)
+                .filter(|(_, past)| *past > 0.0)
+                .map(|(_, past)| (latest - past) / past * 100.0)
+        };
+        health.tvl_usd = Some(latest);
+        health.tvl_change_7d_percent = change(7);
+        health.tvl_change_30d_percent = change(30);
+    }
+    if let Some(change) = health
+        .tvl_change_7d_percent
+        .filter(|change| *change <= TVL_DROP_WARNING_PERCENT)
+    {
+        health
+            .warnings
+            .push(format!("TVL fell {:.1}% over the last 7 days", -change));
+    }
+
+    if let Some(gecko_id) = profile["gecko_id"].as_str() {
+        let coin = format!("coingecko:{}", gecko_id);
+        let week = external_apis.get_coin_price_change(&coin, "7d").await;
+        let month = external_apis.get_coin_price_change(&coin, "30d").await;
+        match (week, month) {
+            (Ok((price_usd, change_7d)), Ok((_, change_30d))) => {
+                if let Some(change) = change_7d.filter(|c| *c <= PRICE_DROP_WARNING_PERCENT) {
+                    health.warnings.push(format!(
+                        "The protocol's token fell {:.1}% over the last 7 days",
+                        -change
+                    ));
+                }
+                health.token = Some(TokenTrend {
+                    symbol: profile["symbol"]
+                        .as_str()
+                        .filter(|symbol| *symbol != "-")
+                        .map(str::to_string),
+                    price_usd,
+                    change_7d_percent: change_7d,
+                    change_30d_percent: change_30d,
+                });
+            }
+            (Err(e), _) | (_, Err(e)) => {
+                warn!("Token price trend unavailable for {}: {}", gecko_id, e);
+                health.unavailable.push("token_price".to_string());
+            }
+        }
+    }
+
+    let spaces: Vec<String> = profile["governanceID"]
+        .as_array()
+        .map(|ids| {
+            ids.iter()
+                .filter_map(|id| id.as_str()?.strip_prefix("snapshot:"))
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default();
+    for space in spaces {
+        match external_apis
+            .get_snapshot_proposals(&space, GOVERNANCE_PROPOSALS)
+            .await
+        {
+            Ok(proposals) => health
+                .governance
+                .extend(proposals.iter().map(|proposal| Proposal {
+                    space: space.clone(),
+                    title: proposal["title"].as_str().unwrap_or_default().to_string(),
+                    state: proposal["state"].as_str().unwrap_or_default().to_string(),
+                    end: proposal["end"].as_i64(),
+                    link: proposal["link"].as_str().map(str::to_string),
+                })),
+            Err(e) => {
+                warn!("Snapshot proposals unavailable for {}: {}", space, e);
+                health.unavailable.push("governance".to_string());
+            }
+        }
+    }
+
+    let mut hallmarks: Vec<Hallmark> = profile["hallmarks"]
+        .as_array()
+        .map(|hallmarks| {
+            hallmarks
+                .iter()
+                .filter_map(|hallmark| {
+                    Some(Hallmark {
+                        timestamp: hallmark[0].as_i64()?,
+                        event: hallmark[1].as_str()?.to_string(),
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    hallmarks.sort_by_key(|hallmark| std::cmp::Reverse(hallmark.timestamp));
+    hallmarks.truncate(MAX_HALLMARKS);
+    for hallmark in &hallmarks {
+        if hallmark.timestamp >= now - RECENT_INCIDENT_DAYS * DAY
+            && mentions_incident(&hallmark.event)
+        {
+            health.warnings.push(format!(
+                "DefiLlama marks a recent incident: {}",
+                hallmark.event
+            ));
+        }
+    }
+    health.hallmarks = hallmarks;
+
+    if external_apis.has_search_key() {
+        match external_apis
+            .search_brave(&format!("{} exploit OR hack", health.name))
+            .await
+        {
+            Ok(results) => {
+                health.news = results["web"]["results"]
+                    .as_array()
+                    .map(|results| {
+                        results
+                            .iter()
+                            .filter_map(|result| {
+                                Some(NewsResult {
+                                    title: result["title"].as_str()?.to_string(),
+                                    url: result["url"].as_str()?.to_string(),
+                                    description: result["description"].as_str().map(str::to_string),
+                                    age: result["age"].as_str().map(str::to_string),
+                                })
+                            })
+                            .collect()
+                    })
+                    .unwrap_or_default();
+            }
+            Err(e) => {
+                warn!("Exploit news search failed for {}: {}", health.name, e);
+                health.unavailable.push("news".to_string());
+            }
+        }
+    } else {
+        health.unavailable.push("news".to_string());
+    }
+
+    Ok(health)
+}
+
+// DefiLlama's profile for a protocol, by slug or else by name or token symbol
+async fn find_protocol(external_apis: &ExternalAPIService, protocol: &str) -> Result<Value> {
+    if let Some(profile) = external_apis.get_protocol(&slug(protocol)).await? {
+        return Ok(profile);
+    }
+
+    let protocols = external_apis.list_protocols().await?;
+    let found = protocols
+        .iter()
+        .filter(|entry| {
+            ["name", "slug", "symbol"].iter().any(|field| {
+                entry[*field]
+                    .as_str()
+                    .is_some_and(|value| value.eq_ignore_ascii_case(protocol.trim()))
+            })
+        })
+        // Several protocols can share a token; the largest is the likely one
+        .max_by(|a, b| {
+            let tvl = |entry: &Value| entry["tvl"].as_f64().unwrap_or(0.0);
+            tvl(a).total_cmp(&tvl(b))
+        })
+        .and_then(|entry| entry["slug"].as_str())
+        .ok_or_else(|| anyhow!("DefiLlama doesn't track a protocol called {}", protocol))?;
+    external_apis
+        .get_protocol(found)
+        .await?
+        .ok_or_else(|| anyhow!("DefiLlama has no profile for {}", found))
+}
+
+fn slug(name: &str) -> String {
+    name.trim().to_lowercase().replace(' ', "-")
+}
+
+fn mentions_incident(text: &str) -> bool {
+    let text = text.to_lowercase();
+    INCIDENT_WORDS.iter().any(|word| text.contains(word))
+}
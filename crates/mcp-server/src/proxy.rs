@@ -0,0 +1,93 @@
+use anyhow::Result;
+use ethers::abi::parse_abi;
+use ethers::contract::Contract;
+use ethers::providers::Middleware;
+use ethers::types::{Address, H256};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+use crate::blockchain::EthProvider;
+
+// EIP-1967 slots: keccak256("eip1967.proxy.<name>") - 1
+const IMPLEMENTATION_SLOT: &str =
+    "0x360894a13ba1a3210667c828492db98dca3e2076cc3735a920a3ca505d382bbc";
+const ADMIN_SLOT: &str = "0xb53127684a568b3173ae13b9f8a6016e243e63b6e8ee1178d6a717850b5d6103";
+const BEACON_SLOT: &str = "0xa3f0ad74e5423aebfd80d3ef4346578335a9a72aeaee59ff6cb3582b35133d50";
+// EIP-1822 (UUPS) slot: keccak256("PROXIABLE")
+const PROXIABLE_SLOT: &str = "0xc5f16f0fcc639fa48a6947836d9850f504798523bf8c9a3a87d5876cf622bcf7";
+
+const BEACON_ABI: &[&str] = &["function implementation() view returns (address)"];
+
+/// Whether an address holds code, and where it forwards calls if it's a proxy
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContractStatus {
+    pub deployed: bool,
+    pub proxy: Option<ProxyInfo>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProxyInfo {
+    /// "EIP-1967", "EIP-1967 beacon" or "EIP-1822"
+    pub standard: String,
+    /// Contract the proxy delegates to; None when a beacon couldn't be asked for it
+    pub implementation: Option<String>,
+    /// Account allowed to upgrade the proxy, from the EIP-1967 admin slot. UUPS proxies
+    /// keep upgrade rights in the implementation, so they usually leave it empty.
+    pub admin: Option<String>,
+    pub beacon: Option<String>,
+}
+
+// The address stored in a slot, or None for an empty slot
+async fn read_address(
+    provider: &EthProvider,
+    proxy: Address,
+    slot: &str,
+) -> Result<Option<Address>> {
+    let value = provider
+        .get_storage_at(proxy, H256::from_str(slot)?, None)
+        .await?;
+    let address = Address::from_slice(&value.as_bytes()[12..]);
+    Ok((!address.is_zero()).then_some(address))
+}
+
+/// The proxy pattern of the contract at `address`, read from the EIP-1967 and EIP-1822
+/// storage slots; None when it uses neither
+pub async fn detect(provider: &EthProvider, address: Address) -> Result<Option<ProxyInfo>> {
+    let (implementation, admin, beacon, proxiable) = tokio::try_join!(
+        read_address(provider, address, IMPLEMENTATION_SLOT),
+        read_address(provider, address, ADMIN_SLOT),
+        read_address(provider, address, BEACON_SLOT),
+        read_address(provider, address, PROXIABLE_SLOT),
+    )?;
+    let admin = admin.map(|admin| format!("{:?}", admin));
+
+    if let Some(implementation) = implementation {
+        return Ok(Some(ProxyInfo {
+            standard: "EIP-1967".to_string(),
+            implementation: Some(format!("{:?}", implementation)),
+            admin,
+            beacon: None,
+        }));
+    }
+    if let Some(beacon) = beacon {
+        // Beacon proxies ask the beacon for the implementation on every call
+        let contract = Contract::new(beacon, parse_abi(BEACON_ABI)?, provider.clone());
+        let implementation = contract
+            .method::<_, Address>("implementation", ())?
+            .call()
+            .await
+            .ok();
+        return Ok(Some(ProxyInfo {
+            standard: "EIP-1967 beacon".to_string(),
+            implementation: implementation.map(|implementation| format!("{:?}", implementation)),
+            admin,
+            beacon: Some(format!("{:?}", beacon)),
+        }));
+    }
+    Ok(proxiable.map(|implementation| ProxyInfo {
+        standard: "EIP-1822".to_string(),
+        implementation: Some(format!("{:?}", implementation)),
+        admin,
+        beacon: None,
+    }))
+}
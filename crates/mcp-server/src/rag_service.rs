@@ -1,13 +1,13 @@
 use anyhow::Result;
-use shared::rag::RAGSystem;
+use shared::rag::{IndexStatus, RAGSystem};
 use shared::{DocumentQuery, DocumentResult};
 use std::path::Path;
 use std::sync::Arc;
-use tokio::sync::RwLock;
 
 #[derive(Clone)]
 pub struct RAGService {
-  rag_system: Arc<RwLock<RAGSystem>>,
+  // The system swaps index snapshots itself, so searches don't wait on ingestion
+  rag_system: Arc<RAGSystem>,
 }
 
 impl RAGService {
@@ -15,21 +15,19 @@ impl RAGService {
       let rag_system = RAGSystem::new(data_dir)?;
       
       Ok(Self {
-          rag_system: Arc::new(RwLock::new(rag_system)),
+          rag_system: Arc::new(rag_system),
       })
   }
   
   pub async fn search_documents(&self, query: DocumentQuery) -> Result<Vec<DocumentResult>> {
-      let rag_system = self.rag_system.read().await;
-      
-      let search_results = rag_system.search(&query.query, query.limit);
+      let search_results = self.rag_system.search(&query.query, query.limit);
       
       let results = search_results
           .into_iter()
           .map(|result| DocumentResult {
               id: result.document.id,
               title: result.document.title,
-              content: result.document.content,
+              content: result.content,
               source: result.document.source,
               score: result.score,
           })
@@ -39,14 +37,13 @@ impl RAGService {
   }
   
   pub async fn get_document(&self, id: &str) -> Result<Option<DocumentResult>> {
-      let rag_system = self.rag_system.read().await;
-      
-      if let Some(doc) = rag_system.get_document_by_id(id) {
+      if let Some(doc) = self.rag_system.get_document_by_id(id) {
+          // Full content is read on demand; only chunks are kept in memory
           Ok(Some(DocumentResult {
-              id: doc.id.clone(),
-              title: doc.title.clone(),
-              content: doc.content.clone(),
-              source: doc.source.clone(),
+              content: self.rag_system.read_content(&doc)?,
+              id: doc.id,
+              title: doc.title,
+              source: doc.source,
               score: 1.0, // Default score for direct retrieval
           }))
       } else {
@@ -55,8 +52,19 @@ impl RAGService {
   }
   
   pub async fn add_document(&self, title: &str, content: &str, source: &str) -> Result<()> {
-      let mut rag_system = self.rag_system.write().await;
-      rag_system.add_document(title, content, source)?;
+      // Chunking and rebuilding the index is CPU work; keep it off the async workers
+      let rag_system = self.rag_system.clone();
+      let (title, content, source) = (title.to_string(), content.to_string(), source.to_string());
+      tokio::task::spawn_blocking(move || rag_system.add_document(&title, &content, &source))
+          .await??;
       Ok(())
   }
+
+  pub async fn document_count(&self) -> usize {
+      self.rag_system.document_count()
+  }
+
+  pub async fn index_status(&self) -> IndexStatus {
+      self.rag_system.status()
+  }
 }
\ No newline at end of file
@@ -0,0 +1,240 @@
+use anyhow::{Result, anyhow};
+use ethers::{
+    providers::Middleware,
+    types::{Filter, Log},
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::blockchain::EthProvider;
+
+// Fragments of the errors providers return when a getLogs range is too large
+const RANGE_LIMIT_ERRORS: &[&str] = &[
+    "more than",
+    "block range",
+    "range is too large",
+    "too many",
+    "limit exceeded",
+    "response size",
+    "query timeout",
+];
+
+#[derive(Debug, Clone)]
+pub struct ScanConfig {
+    /// Blocks per eth_getLogs request
+    pub chunk_size: u64,
+    /// Smallest chunk to shrink to when the provider rejects a range
+    pub min_chunk_size: u64,
+    pub max_retries: u32,
+    pub retry_delay: Duration,
+}
+
+impl Default for ScanConfig {
+    fn default() -> Self {
+        Self {
+            chunk_size: 2000,
+            min_chunk_size: 10,
+            max_retries: 3,
+            retry_delay: Duration::from_millis(500),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScanSummary {
+    pub scan_id: String,
+    pub from_block: u64,
+    pub to_block: u64,
+    pub logs: usize,
+    pub requests: usize,
+    /// Set when the scan continued from a saved checkpoint
+    pub resumed_from: Option<u64>,
+}
+
+// Last fully scanned block per scan, persisted as a JSON object
+pub struct CheckpointStore {
+    path: Option<PathBuf>,
+    checkpoints: RwLock<HashMap<String, u64>>,
+}
+
+impl CheckpointStore {
+    pub fn in_memory() -> Self {
+        Self {
+            path: None,
+            checkpoints: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let checkpoints = if path.exists() {
+            serde_json::from_str(&fs::read_to_string(&path)?)?
+        } else {
+            HashMap::new()
+        };
+
+        Ok(Self {
+            path: Some(path),
+            checkpoints: RwLock::new(checkpoints),
+        })
+    }
+
+    pub fn get(&self, scan_id: &str) -> Option<u64> {
+        self.checkpoints
+            .read()
+            .ok()
+            .and_then(|checkpoints| checkpoints.get(scan_id).copied())
+    }
+
+    pub fn set(&self, scan_id: &str, block: u64) -> Result<()> {
+        let mut checkpoints = self
+            .checkpoints
+            .write()
+            .map_err(|_| anyhow!("Checkpoint store lock poisoned"))?;
+        checkpoints.insert(scan_id.to_string(), block);
+        self.persist(&checkpoints)
+    }
+
+    /// Forget a scan's progress so it starts over next time
+    pub fn reset(&self, scan_id: &str) -> Result<()> {
+        let mut checkpoints = self
+            .checkpoints
+            .write()
+            .map_err(|_| anyhow!("Checkpoint store lock poisoned"))?;
+        checkpoints.remove(scan_id);
+        self.persist(&checkpoints)
+    }
+
+    fn persist(&self, checkpoints: &HashMap<String, u64>) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(checkpoints)?)?;
+        Ok(())
+    }
+}
+
+// Chunked eth_getLogs over block ranges, shrinking chunks when the provider objects,
+// retrying transient failures and checkpointing progress so scans resume after restarts
+pub struct LogScanner<'a> {
+    provider: EthProvider,
+    checkpoints: &'a CheckpointStore,
+    config: ScanConfig,
+}
+
+impl<'a> LogScanner<'a> {
+    pub fn new(provider: EthProvider, checkpoints: &'a CheckpointStore) -> Self {
+        Self {
+            provider,
+            checkpoints,
+            config: ScanConfig::default(),
+        }
+    }
+
+    pub fn with_config(mut self, config: ScanConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Scan `filter` from `from_block` to `to_block` (latest when None), handing each
+    /// chunk's logs to `handle`. The checkpoint only advances after `handle` succeeds.
+    pub async fn scan<F>(
+        &self,
+        scan_id: &str,
+        filter: &Filter,
+        from_block: u64,
+        to_block: Option<u64>,
+        mut handle: F,
+    ) -> Result<ScanSummary>
+    where
+        F: FnMut(Vec<Log>) -> Result<()>,
+    {
+        let to_block = match to_block {
+            Some(block) => block,
+            None => self.provider.get_block_number().await?.as_u64(),
+        };
+
+        let resumed_from = self
+            .checkpoints
+            .get(scan_id)
+            .filter(|checkpoint| *checkpoint >= from_block);
+        let mut start = resumed_from
+            .map(|checkpoint| checkpoint + 1)
+            .unwrap_or(from_block);
+        if let Some(checkpoint) = resumed_from {
+            info!("Resuming scan {} after block {}", scan_id, checkpoint);
+        }
+
+        let mut summary = ScanSummary {
+            scan_id: scan_id.to_string(),
+            from_block,
+            to_block,
+            logs: 0,
+            requests: 0,
+            resumed_from,
+        };
+
+        let mut chunk_size = self.config.chunk_size.max(1);
+        let mut failures = 0;
+
+        while start <= to_block {
+            let end = (start + chunk_size - 1).min(to_block);
+            let chunk_filter = filter.clone().from_block(start).to_block(end);
+            summary.requests += 1;
+
+            match self.provider.get_logs(&chunk_filter).await {
+                Ok(logs) => {
+                    summary.logs += logs.len();
+                    handle(logs)?;
+                    self.checkpoints.set(scan_id, end)?;
+
+                    start = end + 1;
+                    failures = 0;
+                    // Creep back up after a provider forced smaller ranges
+                    chunk_size = (chunk_size * 2).min(self.config.chunk_size.max(1));
+                }
+                Err(e) => {
+                    let message = e.to_string().to_lowercase();
+                    let range_limited = RANGE_LIMIT_ERRORS
+                        .iter()
+                        .any(|fragment| message.contains(fragment));
+
+                    if range_limited && chunk_size > self.config.min_chunk_size {
+                        chunk_size = (chunk_size / 2).max(self.config.min_chunk_size);
+                        warn!(
+                            "getLogs range {}-{} rejected, shrinking chunks to {} blocks",
+                            start, end, chunk_size
+                        );
+                        continue;
+                    }
+
+                    failures += 1;
+                    if failures > self.config.max_retries {
+                        return Err(anyhow!(
+                            "Scan {} failed at blocks {}-{}: {}",
+                            scan_id,
+                            start,
+                            end,
+                            e
+                        ));
+                    }
+                    warn!(
+                        "getLogs {}-{} failed (attempt {}): {}",
+                        start, end, failures, e
+                    );
+                    tokio::time::sleep(self.config.retry_delay * failures).await;
+                }
+            }
+        }
+
+        Ok(summary)
+    }
+}
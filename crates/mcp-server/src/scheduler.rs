@@ -0,0 +1,356 @@
+use anyhow::{Result, anyhow};
+use ethers::providers::Middleware;
+use ethers::types::U256;
+use ethers::utils::format_units;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use std::time::Duration;
+
+use crate::blockchain::{BlockchainService, FeeOverrides};
+
+/// How often due jobs are checked against the gas oracle
+pub const SCHEDULER_INTERVAL: Duration = Duration::from_secs(15);
+// Gas-limited jobs without an explicit expiry give up after a day
+const DEFAULT_EXPIRY_SECS: i64 = 24 * 3600;
+const MAX_NOTIFICATIONS: usize = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Pending,
+    /// Due, but waiting for gas to drop under the job's limit
+    Deferred,
+    Running,
+    Succeeded,
+    Failed,
+    Expired,
+    Cancelled,
+}
+
+impl JobStatus {
+    pub fn is_open(&self) -> bool {
+        matches!(self, Self::Pending | Self::Deferred)
+    }
+}
+
+/// A write request queued to run later, optionally only while gas is cheap
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduledJob {
+    pub id: String,
+    pub namespace: String,
+    /// Server method to run, e.g. "send_eth" or "run_template"
+    pub method: String,
+    pub params: Value,
+    /// Unix time the job becomes due
+    pub run_at: i64,
+    /// Highest gas price (base fee plus priority fee, in gwei) the job may run at
+    pub max_gas_price_gwei: Option<f64>,
+    /// Unix time after which a job that hasn't run is dropped
+    pub expires_at: Option<i64>,
+    pub status: JobStatus,
+    pub created_at: i64,
+    /// Why the job is still waiting, while deferred
+    pub deferral_reason: Option<String>,
+    pub finished_at: Option<i64>,
+    pub result: Option<Value>,
+    pub error: Option<String>,
+}
+
+impl ScheduledJob {
+    pub fn new(
+        namespace: &str,
+        method: &str,
+        params: Value,
+        run_at: Option<i64>,
+        max_gas_price_gwei: Option<f64>,
+        expires_at: Option<i64>,
+    ) -> Result<Self> {
+        let now = chrono::Utc::now().timestamp();
+        if let Some(max) = max_gas_price_gwei
+            && max <= 0.0
+        {
+            return Err(anyhow!("max_gas_price_gwei must be positive"));
+        }
+        let run_at = run_at.unwrap_or(now);
+        let expires_at = match expires_at {
+            Some(expires_at) => Some(expires_at),
+            None => max_gas_price_gwei.map(|_| run_at + DEFAULT_EXPIRY_SECS),
+        };
+        if let Some(expires_at) = expires_at
+            && expires_at <= run_at.max(now)
+        {
+            return Err(anyhow!("The job would expire before it is due"));
+        }
+
+        Ok(Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            namespace: namespace.to_string(),
+            method: method.to_string(),
+            params,
+            run_at,
+            max_gas_price_gwei,
+            expires_at,
+            status: JobStatus::Pending,
+            created_at: now,
+            deferral_reason: None,
+            finished_at: None,
+            result: None,
+            error: None,
+        })
+    }
+
+    pub fn is_due(&self, now: i64) -> bool {
+        self.status.is_open() && self.run_at <= now
+    }
+
+    pub fn is_expired(&self, now: i64) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Notification {
+    pub timestamp: i64,
+    pub namespace: String,
+    pub job_id: String,
+    /// "deferred", "succeeded", "failed" or "expired"
+    pub kind: String,
+    pub message: String,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct JobState {
+    jobs: Vec<ScheduledJob>,
+    notifications: VecDeque<Notification>,
+}
+
+// Scheduled jobs and the notifications they raise, persisted as one JSON file
+pub struct JobStore {
+    path: Option<PathBuf>,
+    state: RwLock<JobState>,
+}
+
+impl JobStore {
+    pub fn in_memory() -> Self {
+        Self {
+            path: None,
+            state: RwLock::new(JobState::default()),
+        }
+    }
+
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut state: JobState = if path.exists() {
+            serde_json::from_str(&fs::read_to_string(&path)?)?
+        } else {
+            JobState::default()
+        };
+        // A job caught mid-run by a restart may or may not have been sent
+        for job in state
+            .jobs
+            .iter_mut()
+            .filter(|job| job.status == JobStatus::Running)
+        {
+            job.status = JobStatus::Failed;
+            job.error = Some(
+                "The server stopped while the job was running; check the audit log before retrying"
+                    .to_string(),
+            );
+        }
+
+        Ok(Self {
+            path: Some(path),
+            state: RwLock::new(state),
+        })
+    }
+
+    pub fn add(&self, job: ScheduledJob) -> Result<()> {
+        self.update(|state| state.jobs.push(job))
+    }
+
+    pub fn get(&self, id: &str) -> Option<ScheduledJob> {
+        let state = self.state.read().ok()?;
+        state.jobs.iter().find(|job| job.id == id).cloned()
+    }
+
+    pub fn list(&self, namespace: &str) -> Vec<ScheduledJob> {
+        self.state
+            .read()
+            .map(|state| {
+                state
+                    .jobs
+                    .iter()
+                    .filter(|job| job.namespace == namespace)
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Jobs whose time has come, across all namespaces
+    pub fn due(&self, now: i64) -> Vec<ScheduledJob> {
+        self.state
+            .read()
+            .map(|state| {
+                state
+                    .jobs
+                    .iter()
+                    .filter(|job| job.is_due(now))
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Mark a job as running unless it was cancelled or already picked up meanwhile
+    pub fn claim(&self, id: &str) -> Result<bool> {
+        let mut claimed = false;
+        self.update(|state| {
+            if let Some(job) = state
+                .jobs
+                .iter_mut()
+                .find(|job| job.id == id && job.status.is_open())
+            {
+                job.status = JobStatus::Running;
+                claimed = true;
+            }
+        })?;
+        Ok(claimed)
+    }
+
+    /// Cancel an open job in the namespace; errors when it has already run
+    pub fn cancel(&self, namespace: &str, id: &str) -> Result<ScheduledJob> {
+        let mut cancelled = None;
+        self.update(|state| {
+            if let Some(job) = state
+                .jobs
+                .iter_mut()
+                .find(|job| job.id == id && job.namespace == namespace)
+            {
+                if job.status.is_open() {
+                    job.status = JobStatus::Cancelled;
+                    job.finished_at = Some(chrono::Utc::now().timestamp());
+                }
+                cancelled = Some(job.clone());
+            }
+        })?;
+
+        match cancelled {
+            Some(job) if job.status == JobStatus::Cancelled => Ok(job),
+            Some(job) => Err(anyhow!(
+                "Job {} can't be cancelled, it is {:?}",
+                id,
+                job.status
+            )),
+            None => Err(anyhow!("No job with id {}", id)),
+        }
+    }
+
+    /// Apply a change to a job and persist it
+    pub fn update_job(&self, id: &str, change: impl FnOnce(&mut ScheduledJob)) -> Result<()> {
+        self.update(|state| {
+            if let Some(job) = state.jobs.iter_mut().find(|job| job.id == id) {
+                change(job);
+            }
+        })
+    }
+
+    pub fn notify(&self, job: &ScheduledJob, kind: &str, message: String) -> Result<()> {
+        let notification = Notification {
+            timestamp: chrono::Utc::now().timestamp(),
+            namespace: job.namespace.clone(),
+            job_id: job.id.clone(),
+            kind: kind.to_string(),
+            message,
+        };
+        self.update(|state| {
+            state.notifications.push_back(notification);
+            while state.notifications.len() > MAX_NOTIFICATIONS {
+                state.notifications.pop_front();
+            }
+        })
+    }
+
+    /// Notifications for a namespace, oldest first, optionally only those after `since`
+    pub fn notifications(&self, namespace: &str, since: Option<i64>) -> Vec<Notification> {
+        self.state
+            .read()
+            .map(|state| {
+                state
+                    .notifications
+                    .iter()
+                    .filter(|notification| notification.namespace == namespace)
+                    .filter(|notification| since.is_none_or(|since| notification.timestamp > since))
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    fn update(&self, change: impl FnOnce(&mut JobState)) -> Result<()> {
+        let mut state = self
+            .state
+            .write()
+            .map_err(|_| anyhow!("Job store lock poisoned"))?;
+        change(&mut state);
+
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(&*state)?)?;
+        Ok(())
+    }
+}
+
+/// Current gas price in gwei as the next transaction would pay it: base fee plus
+/// priority fee on EIP-1559 networks, the node's gas price elsewhere
+pub async fn current_gas_price_gwei(blockchain_service: &BlockchainService) -> Result<f64> {
+    let price: U256 = match blockchain_service
+        .estimate_eip1559_fees(&FeeOverrides::default())
+        .await?
+    {
+        Some(fees) => fees.base_fee_per_gas + fees.max_priority_fee_per_gas,
+        None => blockchain_service.provider().get_gas_price().await?,
+    };
+    Ok(format_units(price, "gwei")?.parse()?)
+}
+
+/// Why a job has to wait for cheaper gas, or None when it can run now
+pub async fn gas_deferral(
+    blockchain_service: &BlockchainService,
+    max_gas_price_gwei: Option<f64>,
+) -> Result<Option<String>> {
+    let Some(max) = max_gas_price_gwei else {
+        return Ok(None);
+    };
+    let current = current_gas_price_gwei(blockchain_service).await?;
+    if current <= max {
+        return Ok(None);
+    }
+    Ok(Some(format!(
+        "Gas is {:.2} gwei, above the {} gwei limit",
+        current, max
+    )))
+}
+
+/// Read a time param given as a Unix timestamp or an RFC 3339 date
+pub fn timestamp_param(value: &Value) -> Result<Option<i64>> {
+    match value {
+        Value::Null => Ok(None),
+        Value::Number(number) => number
+            .as_i64()
+            .map(Some)
+            .ok_or_else(|| anyhow!("Invalid timestamp {}", number)),
+        Value::String(text) => chrono::DateTime::parse_from_rfc3339(text)
+            .map(|time| Some(time.timestamp()))
+            .map_err(|e| anyhow!("Invalid time '{}': {}", text, e)),
+        other => Err(anyhow!("Invalid time {}", other)),
+    }
+}
@@ -0,0 +1,93 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use tracing::{info, warn};
+
+use crate::external_apis::ExternalAPIService;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DenylistEntry {
+    pub address: String,
+    pub label: String,
+    pub category: String, // e.g. "phishing", "scam", "sanctioned"
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScreeningResult {
+    pub address: String,
+    pub flagged: bool,
+    pub reasons: Vec<String>,
+}
+
+pub struct AddressScreener {
+    denylist: HashMap<String, DenylistEntry>,
+}
+
+impl AddressScreener {
+    pub fn new() -> Self {
+        Self {
+            denylist: HashMap::new(),
+        }
+    }
+
+    /// Load a JSON array of denylist entries; a missing file yields an empty denylist
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let mut screener = Self::new();
+
+        if !path.exists() {
+            warn!("Denylist file not found: {}", path.display());
+            return Ok(screener);
+        }
+
+        let content = fs::read_to_string(path)?;
+        let entries: Vec<DenylistEntry> = serde_json::from_str(&content)?;
+        for entry in entries {
+            screener
+                .denylist
+                .insert(entry.address.to_lowercase(), entry);
+        }
+
+        info!(
+            "Loaded {} denylisted addresses from {}",
+            screener.denylist.len(),
+            path.display()
+        );
+        Ok(screener)
+    }
+
+    pub async fn screen(
+        &self,
+        address: &str,
+        external_apis: &ExternalAPIService,
+    ) -> Result<ScreeningResult> {
+        let mut reasons = Vec::new();
+
+        if let Some(entry) = self.denylist.get(&address.to_lowercase()) {
+            reasons.push(format!("{} ({})", entry.label, entry.category));
+        }
+
+        // External reports are best-effort; an unreachable service never blocks a send
+        match external_apis.get_chainabuse_reports(address).await {
+            Ok(Some(count)) if count > 0 => {
+                reasons.push(format!("{} scam report(s) on Chainabuse", count));
+            }
+            Ok(_) => {}
+            Err(e) => warn!("Chainabuse lookup failed for {}: {}", address, e),
+        }
+
+        Ok(ScreeningResult {
+            address: address.to_string(),
+            flagged: !reasons.is_empty(),
+            reasons,
+        })
+    }
+}
+
+impl Default for AddressScreener {
+    fn default() -> Self {
+        Self::new()
+    }
+}
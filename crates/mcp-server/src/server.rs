@@ -1,64 +1,323 @@
 use anyhow::Result;
+use ethers::abi::{EventExt, FunctionExt};
+use ethers::providers::Middleware;
+use ethers::types::{Address, U256, transaction::eip2718::TypedTransaction};
+use ethers::utils::{format_ether, format_units};
 use serde_json::{Value, json};
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
 use tokio::net::{TcpListener, TcpStream};
-use tracing::{error, info};
+use tokio::sync::mpsc;
+use tracing::{error, info, warn};
 
-use crate::blockchain::BlockchainService;
+use crate::aave::{self, LendingAction};
+use crate::accounts::AccountManager;
+use crate::activity;
+use crate::aggregator::{self, Aggregator, QuoteRequest};
+use crate::audit::AuditLog;
+use crate::blockchain::{
+    ContractArtifact, Dex, FeeOverrides, GasEstimateRequest, NetworkRegistry, UnsignedRequest,
+};
+use crate::calldata;
+use crate::capabilities;
+use crate::chain_info;
+use crate::claims::{self, ClaimsConfig};
+use crate::depeg::{self, DepegConfig};
+use crate::deploy_address::{self, AddressInputs};
+use crate::devchain;
+use crate::diagnostics;
+use crate::dust::{self, DustPlan};
+use crate::events::{self, EventQuery};
 use crate::external_apis::ExternalAPIService;
+use crate::gas;
+use crate::governance::{self, GovernanceConfig};
+use crate::heads;
+use crate::historical;
+use crate::inbox::{self, InboxStore};
+use crate::liquidity::{self, AddLiquidityRequest};
+use crate::lp;
+use crate::migrations::{self, MigrationsConfig};
+use crate::paths::{data_dir, data_path};
+use crate::payment_uri;
+use crate::pending::{self, PendingStore};
+use crate::pnl;
+use crate::policy::{PolicyEngine, WRITE_METHODS};
+use crate::pools;
+use crate::portfolio;
+use crate::prices;
+use crate::protocol_health;
 use crate::rag_service::RAGService;
+use crate::scanner::CheckpointStore;
+use crate::scheduler::{self, JobStatus, JobStore, ScheduledJob};
+use crate::screening::AddressScreener;
+use crate::session_keys::{SessionKey, SessionKeyStore};
+use crate::sessions::{self, SessionStore};
+use crate::signing;
+use crate::smart_accounts::{self, SmartAccountCall, SmartAccountConfig};
+use crate::snapshot_voting;
+use crate::snapshots::{self, SnapshotStore};
+use crate::streams::{self, StreamRequest, StreamStore};
+use crate::templates::{self, OperationTemplate, TemplateStep, TemplateStore};
+use crate::tenants::{self, BudgetConfig, DEFAULT_NAMESPACE, Namespace, TenantRegistry};
+use crate::token_deploy::{self, TokenSpec};
+use crate::token_safety;
 use crate::tools::{ToolContext, ToolRegistry};
-use shared::{Account, BalanceQuery};
+use crate::topups::{self, TopUp, TopUpLimits, TopUpRule, TopUpStore};
+use crate::twap;
+use crate::tx_history;
+use crate::tx_inspect;
+use crate::tx_tracker::{self, TxTracker};
+use crate::units;
+use crate::vesting;
+use crate::walletconnect::{self, WalletConnectConfig, WalletStore};
+use crate::yields::{self, YieldConfig, YieldQuery};
+use shared::{Account, BalanceQuery, ContractCall, utils};
+
+// Methods that take variable names and placeholders literally instead of resolving them
+const LITERAL_METHODS: &[&str] = &[
+    "set_variable",
+    "unset_variable",
+    "list_variables",
+    "save_template",
+    "set_display_preferences",
+];
+// Transfers a single send_batch request may hold
+const MAX_BATCH_TRANSFERS: usize = 100;
 
 pub struct Server {
-    blockchain_service: Arc<BlockchainService>,
-    rag_service: Arc<RAGService>,
     tool_registry: Arc<ToolRegistry>,
-    external_apis: Arc<ExternalAPIService>,
-    accounts: Arc<std::collections::HashMap<String, Account>>,
+    context: ToolContext,
+    tenants: Arc<TenantRegistry>,
+    pending: Arc<PendingStore>,
+    snapshot_interval: Option<Duration>,
 }
 
 impl Server {
     pub fn new(
-        blockchain_service: BlockchainService,
+        networks: NetworkRegistry,
         tool_registry: ToolRegistry,
         accounts: std::collections::HashMap<String, Account>,
-    ) -> Self {
-        Self {
-            blockchain_service: Arc::new(blockchain_service),
-            tool_registry: Arc::new(tool_registry),
-            rag_service: Arc::new(RAGService::new("./data").unwrap()),
+        policy: PolicyEngine,
+    ) -> Result<Self> {
+        let audit = AuditLog::open(data_path("audit"), DEFAULT_NAMESPACE).unwrap_or_else(|e| {
+            warn!("Failed to open audit log, keeping it in memory: {}", e);
+            AuditLog::in_memory(DEFAULT_NAMESPACE)
+        });
+        let default_namespace = Namespace::new(
+            DEFAULT_NAMESPACE,
+            AccountManager::new(accounts.clone()),
+            policy,
+            audit,
+            BudgetConfig::default(),
+        );
+
+        let tenants = TenantRegistry::load(
+            data_path("tenants.json"),
+            default_namespace,
+            &accounts,
+            data_path("audit"),
+        )
+        .map_err(|e| {
+            anyhow::anyhow!(
+                "Failed to load tenants from {}: {}",
+                data_path("tenants.json").display(),
+                e
+            )
+        })?;
+        let namespace = tenants.default_namespace();
+
+        let pending = Arc::new(
+            PendingStore::open(data_path("pending_transactions.json")).unwrap_or_else(|e| {
+                warn!(
+                    "Failed to load pending transactions, keeping them in memory: {}",
+                    e
+                );
+                PendingStore::in_memory()
+            }),
+        );
+        let networks = networks.with_pending_store(pending.clone());
+        let blockchain_service = networks
+            .service(None)
+            .map_err(|e| anyhow::anyhow!("Failed to connect to default network: {}", e))?;
+
+        let context = ToolContext {
+            blockchain_service,
+            accounts: Arc::new(namespace.accounts.as_map()),
+            rag_service: Arc::new(RAGService::new(data_dir()).unwrap()),
             external_apis: Arc::new(ExternalAPIService::new()),
-            accounts: Arc::new(accounts),
-        }
+            policy: namespace.policy.clone(),
+            screener: Arc::new(
+                AddressScreener::from_file(data_path("denylist.json")).unwrap_or_else(|e| {
+                    warn!("Failed to load denylist: {}", e);
+                    AddressScreener::new()
+                }),
+            ),
+            namespace,
+            networks: Arc::new(networks),
+            snapshots: Arc::new(
+                SnapshotStore::open(data_path("snapshots")).unwrap_or_else(|e| {
+                    warn!("Failed to open snapshot store, keeping it in memory: {}", e);
+                    SnapshotStore::in_memory()
+                }),
+            ),
+            checkpoints: Arc::new(
+                CheckpointStore::open(data_path("scanner/checkpoints.json")).unwrap_or_else(|e| {
+                    warn!("Failed to load scan checkpoints: {}", e);
+                    CheckpointStore::in_memory()
+                }),
+            ),
+            templates: Arc::new(
+                TemplateStore::open(data_path("templates.json")).unwrap_or_else(|e| {
+                    warn!("Failed to load operation templates: {}", e);
+                    TemplateStore::in_memory()
+                }),
+            ),
+            jobs: Arc::new(JobStore::open(data_path("jobs.json")).unwrap_or_else(|e| {
+                warn!(
+                    "Failed to load scheduled jobs, keeping them in memory: {}",
+                    e
+                );
+                JobStore::in_memory()
+            })),
+            inbox: Arc::new(
+                InboxStore::open(data_path("inbox.json")).unwrap_or_else(|e| {
+                    warn!("Failed to load the inbox, keeping it in memory: {}", e);
+                    InboxStore::in_memory()
+                }),
+            ),
+            streams: Arc::new(
+                StreamStore::open(data_path("streams.json")).unwrap_or_else(|e| {
+                    warn!(
+                        "Failed to load payment streams, keeping them in memory: {}",
+                        e
+                    );
+                    StreamStore::in_memory()
+                }),
+            ),
+            session_keys: Arc::new(
+                SessionKeyStore::open(data_path("session_keys.json")).unwrap_or_else(|e| {
+                    warn!("Failed to load session keys, keeping them in memory: {}", e);
+                    SessionKeyStore::in_memory()
+                }),
+            ),
+            tx_tracker: Arc::new(TxTracker::new()),
+            top_ups: Arc::new(
+                TopUpStore::open(data_path("top_ups.json")).unwrap_or_else(|e| {
+                    warn!("Failed to load top-up rules, keeping them in memory: {}", e);
+                    TopUpStore::in_memory()
+                }),
+            ),
+            wallets: Arc::new(
+                WalletStore::open(data_path("wallets.json")).unwrap_or_else(|e| {
+                    warn!("Failed to load paired wallets: {}", e);
+                    WalletStore::in_memory()
+                }),
+            ),
+            sessions: Arc::new(SessionStore::new()),
+            session: None,
+        };
+
+        Ok(Self {
+            tool_registry: Arc::new(tool_registry),
+            context,
+            tenants: Arc::new(tenants),
+            pending,
+            snapshot_interval: Some(Duration::from_secs(3600)),
+        })
+    }
+
+    /// How often to snapshot account balances (None disables snapshots)
+    pub fn with_snapshot_interval(mut self, interval: Option<Duration>) -> Self {
+        self.snapshot_interval = interval;
+        self
     }
 
     pub async fn run(&self, addr: &str) -> Result<()> {
         let listener = TcpListener::bind(addr).await?;
         info!("Server listening on {}", addr);
+        if self.tenants.is_multi_tenant() {
+            info!("Multi-tenant mode: requests must carry an auth token");
+        }
+
+        if let Some(interval) = self.snapshot_interval {
+            let mut addresses: Vec<String> = self
+                .tenants
+                .namespaces()
+                .iter()
+                .flat_map(|namespace| namespace.accounts.addresses())
+                .map(|address| address.to_lowercase())
+                .collect();
+            addresses.sort();
+            addresses.dedup();
+
+            snapshots::spawn_snapshot_job(
+                self.context.blockchain_service.clone(),
+                addresses,
+                self.context.snapshots.clone(),
+                interval,
+            );
+        }
+
+        // Periodically warn when the default network's RPC falls behind the chain head
+        let service = self.context.blockchain_service.clone();
+        if let Some(max_lag) = service.rpc_settings().max_block_lag {
+            tokio::spawn(async move {
+                let mut ticker = tokio::time::interval(max_lag);
+                loop {
+                    ticker.tick().await;
+                    if let Err(e) = service.check_block_lag().await {
+                        warn!("Block lag check failed: {}", e);
+                    }
+                }
+            });
+        }
+
+        self.spawn_job_runner();
+        inbox::spawn_inbox_watcher(
+            self.context.blockchain_service.clone(),
+            self.tenants.clone(),
+            self.context.checkpoints.clone(),
+            self.context.inbox.clone(),
+        );
+        let default_network = self.context.networks.default_network();
+        heads::spawn_head_subscription(
+            self.context.blockchain_service.clone(),
+            self.context
+                .networks
+                .get(default_network)
+                .and_then(|network| network.ws_url.clone()),
+            self.tenants.clone(),
+            self.pending.clone(),
+            self.context.inbox.clone(),
+        );
+        self.spawn_top_up_watcher();
+        pending::spawn_pending_reconciler(
+            self.context.networks.clone(),
+            self.tenants.clone(),
+            self.pending.clone(),
+            self.context.inbox.clone(),
+        );
+        depeg::spawn_depeg_monitor(
+            self.context.blockchain_service.clone(),
+            self.context.external_apis.clone(),
+            self.tenants.clone(),
+            self.context.inbox.clone(),
+            DepegConfig::from_env(),
+        );
 
         loop {
             match listener.accept().await {
                 Ok((stream, addr)) => {
                     info!("New connection from {}", addr);
 
-                    let blockchain_service = self.blockchain_service.clone();
                     let tool_registry = self.tool_registry.clone();
-                    let accounts = self.accounts.clone();
-                    let rag_service = self.rag_service.clone();
-                    let external_apis = self.external_apis.clone();
+                    let context = self.context.clone();
+                    let tenants = self.tenants.clone();
 
                     tokio::spawn(async move {
-                        if let Err(e) = Self::handle_connection(
-                            stream,
-                            blockchain_service,
-                            tool_registry,
-                            accounts,
-                            rag_service,
-                            external_apis,
-                        )
-                        .await
+                        if let Err(e) =
+                            Self::handle_connection(stream, tool_registry, context, tenants).await
                         {
                             error!("Error handling connection: {}", e);
                         }
@@ -73,11 +332,9 @@ impl Server {
 
     async fn handle_connection(
         stream: TcpStream,
-        blockchain_service: Arc<BlockchainService>,
         tool_registry: Arc<ToolRegistry>,
-        accounts: Arc<std::collections::HashMap<String, Account>>,
-        rag_service: Arc<RAGService>,
-        external_apis: Arc<ExternalAPIService>,
+        context: ToolContext,
+        tenants: Arc<TenantRegistry>,
     ) -> Result<()> {
         let (reader, mut writer) = stream.into_split();
         let mut reader = BufReader::new(reader);
@@ -90,25 +347,41 @@ impl Server {
         let id = request["id"].as_u64().unwrap_or(0);
         let method = request["method"].as_str().unwrap_or("");
         let params = request["params"].clone();
+        let auth_token = request["auth_token"].as_str();
+        let session_id = request["session_id"].as_str();
 
         info!("Received request: method={}, id={}", method, id);
 
-        let result = Self::handle_request(
-            method,
-            params,
-            blockchain_service,
-            tool_registry,
-            accounts,
-            rag_service,
-            external_apis,
-        )
-        .await?;
+        let context = tenants
+            .resolve(auth_token)
+            .and_then(|namespace| context.with_namespace(namespace).on_selected_network())
+            .map(|context| context.with_session(session_id));
+        let result = match context {
+            Ok(context)
+                if WRITE_METHODS.contains(&method)
+                    && !params["wait"].as_bool().unwrap_or(false) =>
+            {
+                Self::submit(method, params, tool_registry, context).await
+            }
+            Ok(context) => Self::dispatch(method, params, tool_registry, context).await,
+            Err(e) => Err(e),
+        };
 
-        let response = json!({
-            "jsonrpc": "2.0",
-            "id": id,
-            "result": result
-        });
+        let response = match result {
+            Ok(result) => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "result": result
+            }),
+            Err(e) => {
+                error!("Request {} ({}) failed: {}", id, method, e);
+                json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "error": {"code": -32000, "message": e.to_string()}
+                })
+            }
+        };
 
         let response_str = serde_json::to_string(&response)?;
         writer.write_all(response_str.as_bytes()).await?;
@@ -117,34 +390,735 @@ impl Server {
         Ok(())
     }
 
-    async fn handle_request(
+    // Run due scheduled jobs, holding gas-limited ones back until fees drop or they expire
+    fn spawn_job_runner(&self) {
+        let tool_registry = self.tool_registry.clone();
+        let context = self.context.clone();
+        let tenants = self.tenants.clone();
+
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(scheduler::SCHEDULER_INTERVAL);
+            loop {
+                ticker.tick().await;
+                let now = chrono::Utc::now().timestamp();
+                for job in context.jobs.due(now) {
+                    let id = job.id.clone();
+                    if let Err(e) = Self::run_job(job, &tool_registry, &context, &tenants).await {
+                        warn!("Scheduled job {} not run: {}", id, e);
+                    }
+                }
+            }
+        });
+    }
+
+    // Check top-up rules as each block arrives on the default network
+    fn spawn_top_up_watcher(&self) {
+        let tool_registry = self.tool_registry.clone();
+        let context = self.context.clone();
+        let tenants = self.tenants.clone();
+        let mut heads = self.context.blockchain_service.heads().subscribe();
+
+        tokio::spawn(async move {
+            while heads.changed().await.is_ok() {
+                for rule in context.top_ups.all() {
+                    let id = rule.id.clone();
+                    if let Err(e) = Self::run_top_up(rule, &tool_registry, &context, &tenants).await
+                    {
+                        warn!("Top-up rule {} not checked: {}", id, e);
+                    }
+                }
+            }
+        });
+    }
+
+    // Send what a rule's account is owed through send_eth, so the namespace's policy,
+    // budget, screening and audit trail apply as for any other transfer
+    async fn run_top_up(
+        rule: TopUpRule,
+        tool_registry: &Arc<ToolRegistry>,
+        context: &ToolContext,
+        tenants: &TenantRegistry,
+    ) -> Result<()> {
+        let now = chrono::Utc::now().timestamp();
+        if rule.is_paused(now) {
+            return Ok(());
+        }
+        let namespace = tenants
+            .namespace(&rule.namespace)
+            .ok_or_else(|| anyhow::anyhow!("Namespace {} no longer exists", rule.namespace))?;
+        let mut context = context.with_namespace(namespace);
+        context.blockchain_service = context.networks.service(Some(&rule.network))?;
+        let service = context.blockchain_service.clone();
+        let accounts = context.namespace.accounts.clone();
+
+        let address = service
+            .parse_address(&accounts.resolve_address(&rule.account))
+            .await?;
+        // Read the balance directly: a cached one could trigger a second top-up
+        let balance = service.provider().get_balance(address, None).await?;
+        let retry_at = Some(now + topups::RETRY_AFTER_SECS);
+        let amount = match rule.amount_due(balance, now) {
+            Ok(Some(amount)) => format_units(amount, 18)?,
+            Ok(None) => return Ok(()),
+            Err(e) => {
+                return context
+                    .top_ups
+                    .record_error(&rule.id, e.to_string(), retry_at);
+            }
+        };
+
+        info!(
+            "Topping up {} with {} ETH from {} (rule {})",
+            rule.account, amount, rule.from, rule.id
+        );
+        let params = json!({
+            "from": rule.from,
+            "to": rule.account,
+            "amount": amount,
+            "top_up_rule": rule.id,
+        });
+        let result = match Self::dispatch(
+            "send_eth",
+            params,
+            tool_registry.clone(),
+            context.clone(),
+        )
+        .await
+        {
+            Ok(result) => result,
+            Err(e) => {
+                return context.top_ups.record_error(
+                    &rule.id,
+                    format!("Top-up of {} ETH from {} failed: {}", amount, rule.from, e),
+                    retry_at,
+                );
+            }
+        };
+
+        let status = result["status"].as_str().unwrap_or("pending").to_string();
+        let hash = result["hash"].as_str().map(|hash| hash.to_string());
+        // The balance only reflects a confirmed top-up; wait before checking again
+        let paused_until = if status == "success" { None } else { retry_at };
+        context.top_ups.record(
+            &rule.id,
+            TopUp {
+                at: now,
+                amount: amount.clone(),
+                hash: hash.clone(),
+                status: status.clone(),
+            },
+            paused_until,
+        )?;
+        if let Some(from) = accounts.get(&rule.from)
+            && let Err(e) = context.inbox.record_for_address(
+                tenants,
+                &from.address,
+                "top_up",
+                &format!(
+                    "Topped up {} with {} ETH from {} ({})",
+                    rule.account, amount, rule.from, status
+                ),
+                result["block_number"].as_u64(),
+                hash,
+            )
+        {
+            warn!("Failed to record top-up in the inbox: {}", e);
+        }
+        Ok(())
+    }
+
+    async fn run_job(
+        job: ScheduledJob,
+        tool_registry: &Arc<ToolRegistry>,
+        context: &ToolContext,
+        tenants: &TenantRegistry,
+    ) -> Result<()> {
+        let jobs = context.jobs.clone();
+        let now = chrono::Utc::now().timestamp();
+
+        if job.is_expired(now) {
+            jobs.update_job(&job.id, |job| {
+                job.status = JobStatus::Expired;
+                job.finished_at = Some(now);
+            })?;
+            let reason = job
+                .deferral_reason
+                .clone()
+                .unwrap_or_else(|| "it was not run in time".to_string());
+            return jobs.notify(
+                &job,
+                "expired",
+                format!("{} job {} expired: {}", job.method, job.id, reason),
+            );
+        }
+
+        let namespace = tenants
+            .namespace(&job.namespace)
+            .ok_or_else(|| anyhow::anyhow!("Namespace {} no longer exists", job.namespace))?;
+        let context = context.with_namespace(namespace).on_selected_network()?;
+
+        let deferral = match scheduler::gas_deferral(
+            &context.blockchain_service,
+            job.max_gas_price_gwei,
+        )
+        .await
+        {
+            Ok(deferral) => deferral,
+            Err(e) => Some(format!("Gas price unavailable: {}", e)),
+        };
+        if let Some(reason) = deferral {
+            // Notify once when the job starts waiting rather than on every check
+            let first = job.deferral_reason.is_none();
+            jobs.update_job(&job.id, |job| {
+                job.status = JobStatus::Deferred;
+                job.deferral_reason = Some(reason.clone());
+            })?;
+            if first {
+                jobs.notify(
+                    &job,
+                    "deferred",
+                    format!("{} job {} deferred: {}", job.method, job.id, reason),
+                )?;
+            }
+            return Ok(());
+        }
+
+        if !jobs.claim(&job.id)? {
+            return Ok(());
+        }
+        info!("Running scheduled {} job {}", job.method, job.id);
+        let result = Self::dispatch(
+            &job.method,
+            job.params.clone(),
+            tool_registry.clone(),
+            context,
+        )
+        .await;
+
+        let finished_at = chrono::Utc::now().timestamp();
+        match result {
+            Ok(result) => {
+                jobs.update_job(&job.id, |job| {
+                    job.status = JobStatus::Succeeded;
+                    job.finished_at = Some(finished_at);
+                    job.result = Some(result);
+                })?;
+                jobs.notify(
+                    &job,
+                    "succeeded",
+                    format!("{} job {} ran", job.method, job.id),
+                )
+            }
+            Err(e) => {
+                jobs.update_job(&job.id, |job| {
+                    job.status = JobStatus::Failed;
+                    job.finished_at = Some(finished_at);
+                    job.error = Some(e.to_string());
+                })?;
+                jobs.notify(
+                    &job,
+                    "failed",
+                    format!("{} job {} failed: {}", job.method, job.id, e),
+                )
+            }
+        }
+    }
+
+    // Resolve and estimate every step without sending anything
+    async fn preview_template(
+        template: &OperationTemplate,
+        steps: Vec<TemplateStep>,
+        tool_registry: Arc<ToolRegistry>,
+        context: ToolContext,
+    ) -> Value {
+        let mut previews = Vec::new();
+        for (index, mut step) in steps.into_iter().enumerate() {
+            let mut preview = json!({"step": index + 1, "method": step.method});
+            match templates::resolve_share(&mut step, &context).await {
+                Ok(share) => preview["share"] = json!(share),
+                Err(e) => {
+                    preview["params"] = step.params;
+                    preview["error"] = json!(e.to_string());
+                    previews.push(preview);
+                    continue;
+                }
+            }
+            preview["params"] = step.params.clone();
+
+            // Simulate with the estimate methods the agent uses for single transactions
+            let estimate = match step.method.as_str() {
+                "swap_tokens" => {
+                    let mut params = step.params.clone();
+                    params["from"] = params["recipient"].clone();
+                    Some(("estimate_swap", params))
+                }
+                "send_eth" | "send_erc20" => {
+                    let mut params = step.params.clone();
+                    params["type"] = json!(step.method);
+                    Some(("estimate_gas", params))
+                }
+                _ => None,
+            };
+            if let Some((method, params)) = estimate {
+                let result = Box::pin(Self::handle_request(
+                    method,
+                    params,
+                    tool_registry.clone(),
+                    context.clone(),
+                ))
+                .await;
+                match result {
+                    Ok(estimate) => preview["estimate"] = estimate,
+                    Err(e) => preview["estimate_error"] = json!(e.to_string()),
+                }
+            }
+            previews.push(preview);
+        }
+
+        json!({
+            "template": template.name,
+            "description": template.description,
+            "executed": false,
+            "steps": previews,
+            "note": "Nothing was sent. Show these steps to the user and call run_template again with confirm set to true once they agree. Percentage amounts are recalculated from balances as each step runs.",
+        })
+    }
+
+    // Run the steps in order through the normal write path, stopping at the first failure
+    async fn execute_template(
+        template: &OperationTemplate,
+        steps: Vec<TemplateStep>,
+        tool_registry: Arc<ToolRegistry>,
+        context: ToolContext,
+    ) -> Value {
+        let total = steps.len();
+        let mut results = Vec::new();
+        let mut failed = false;
+        for (index, mut step) in steps.into_iter().enumerate() {
+            let mut entry = json!({"step": index + 1, "method": step.method});
+            let result = match templates::resolve_share(&mut step, &context).await {
+                Ok(share) => {
+                    entry["share"] = json!(share);
+                    entry["params"] = step.params.clone();
+                    Box::pin(Self::dispatch(
+                        &step.method,
+                        step.params,
+                        tool_registry.clone(),
+                        context.clone(),
+                    ))
+                    .await
+                }
+                Err(e) => Err(e),
+            };
+
+            match result {
+                Ok(result) => entry["result"] = result,
+                Err(e) => {
+                    entry["error"] = json!(e.to_string());
+                    failed = true;
+                }
+            }
+            results.push(entry);
+            if failed {
+                break;
+            }
+        }
+
+        json!({
+            "template": template.name,
+            "executed": true,
+            "completed": !failed,
+            "steps": results.clone(),
+            "skipped_steps": total - results.len(),
+        })
+    }
+
+    // Run a dust plan's swaps through the normal write path. Each swap stands alone, so
+    // a failed one doesn't stop the rest.
+    async fn execute_dust_plan(
+        account: &str,
+        plan: DustPlan,
+        tool_registry: Arc<ToolRegistry>,
+        context: ToolContext,
+    ) -> Value {
+        let mut results = Vec::new();
+        let mut failed = 0;
+        for swap in plan.swaps {
+            let params = json!({
+                "from_token": swap.token_address,
+                "to_token": plan.target,
+                "amount": swap.balance,
+                "recipient": account,
+                "dex": swap.quote.dex,
+            });
+            let mut entry = json!({"token": swap.token, "amount": swap.balance});
+            match Box::pin(Self::dispatch(
+                "swap_tokens",
+                params,
+                tool_registry.clone(),
+                context.clone(),
+            ))
+            .await
+            {
+                Ok(result) => entry["result"] = result,
+                Err(e) => {
+                    entry["error"] = json!(e.to_string());
+                    failed += 1;
+                }
+            }
+            results.push(entry);
+        }
+
+        json!({
+            "target": plan.target,
+            "executed": true,
+            "completed": failed == 0,
+            "swaps": results,
+            "failed_swaps": failed,
+            "skipped": plan.skipped,
+        })
+    }
+
+    // The caller's session key; variables and display preferences need a client that
+    // sends a session_id
+    fn session(context: &ToolContext) -> Result<&str> {
+        context.session.as_deref().ok_or_else(|| {
+            anyhow::anyhow!("This method needs a session: send a session_id with the request")
+        })
+    }
+
+    // Name of the account that would sign a write request, if any
+    fn signer_for(method: &str, params: &Value) -> Option<String> {
+        if !WRITE_METHODS.contains(&method) {
+            return None;
+        }
+
+        let field = match method {
+            "swap_tokens" => "recipient",
+            "sign_message" | "consolidate_dust" => "account",
+            "deploy_smart_account" | "send_user_operation" | "grant_session_key" => "owner",
+            "send_with_wallet" => "wallet",
+            _ => "from",
+        };
+        params[field].as_str().map(|s| s.to_string())
+    }
+
+    // Write requests that only plan until called again with confirm; a plan sends
+    // nothing, so it doesn't count against the write rate limit
+    fn is_preview(method: &str, params: &Value) -> bool {
+        matches!(method, "consolidate_dust" | "migrate_token")
+            && !params["confirm"].as_bool().unwrap_or(false)
+    }
+
+    // The namespace account that signed the transaction a broadcast request submits, and
+    // the ETH it sends
+    fn signed_transaction_sender(
+        context: &ToolContext,
+        method: &str,
+        params: &Value,
+    ) -> Option<(String, U256)> {
+        let raw = match method {
+            "broadcast_raw_transaction" => params["raw_transaction"].as_str()?.to_string(),
+            "attach_signature" if params["broadcast"].as_bool().unwrap_or(true) => {
+                let transaction = serde_json::from_value(params["transaction"].clone()).ok()?;
+                context
+                    .blockchain_service
+                    .attach_signature(&transaction, params["signature"].as_str()?)
+                    .ok()?
+                    .raw
+            }
+            _ => return None,
+        };
+        let (tx, signer) = context
+            .blockchain_service
+            .decode_raw_transaction(&raw)
+            .ok()?;
+        let account = context
+            .namespace
+            .accounts
+            .name_for_address(&format!("{:?}", signer))?;
+        Some((account, tx.value().copied().unwrap_or_default()))
+    }
+
+    // A transaction signed outside the server is held to the same rules as one it signs:
+    // the signer must be an account of the namespace, and the send must pass the write
+    // policy, the daily budget and recipient screening
+    async fn check_signed_transaction(
+        context: &ToolContext,
+        tx: &TypedTransaction,
+        signer: Address,
+        params: &Value,
+    ) -> Result<()> {
+        let signer = format!("{:?}", signer);
+        let account = context
+            .namespace
+            .accounts
+            .name_for_address(&signer)
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "The transaction is signed by {}, which isn't one of your accounts",
+                    signer
+                )
+            })?;
+        context.policy.check_write(&account)?;
+
+        context
+            .namespace
+            .check_budget(&account, tx.value().copied().unwrap_or_default())?;
+
+        if let Some(to) = tx.to_addr() {
+            let screening = context
+                .screener
+                .screen(&format!("{:?}", to), &context.external_apis)
+                .await?;
+            let override_screening = params["override_screening"].as_bool().unwrap_or(false);
+            context
+                .policy
+                .check_screening(&screening, override_screening)?;
+        }
+        Ok(())
+    }
+
+    // A contract call from call_contract / write_contract parameters; non-string
+    // parameters are passed as their JSON text
+    fn contract_call(params: &Value) -> Result<ContractCall> {
+        let contract_address = params["contract_address"]
+            .as_str()
+            .ok_or_else(|| anyhow::anyhow!("Missing contract_address"))?;
+        let function_signature = params["function_signature"]
+            .as_str()
+            .ok_or_else(|| anyhow::anyhow!("Missing function_signature"))?;
+        let parameters = match &params["parameters"] {
+            Value::Null => Vec::new(),
+            Value::Array(values) => values
+                .iter()
+                .map(|value| match value {
+                    Value::String(text) => text.clone(),
+                    other => other.to_string(),
+                })
+                .collect(),
+            _ => return Err(anyhow::anyhow!("parameters must be an array")),
+        };
+        Ok(ContractCall {
+            contract_address: contract_address.to_string(),
+            function_signature: function_signature.to_string(),
+            parameters,
+            from: params["from"].as_str().map(|from| from.to_string()),
+        })
+    }
+
+    // Fetch the contract's verified ABI so its functions can be called by name and their
+    // results decoded; without one the call goes ahead on its signature alone
+    async fn fetch_verified_abi(context: &ToolContext, contract: &str) {
+        let contract = context.namespace.accounts.resolve_address(contract);
+        if let Err(e) = calldata::verified_contract(
+            &context.blockchain_service,
+            &context.external_apis,
+            &contract,
+        )
+        .await
+        {
+            warn!("Verified ABI for {} unavailable: {}", contract, e);
+        }
+    }
+
+    // The call a smart account should make: a contract function when a signature is
+    // given, otherwise a plain ETH transfer to contract_address
+    fn smart_account_call(params: &Value) -> Result<SmartAccountCall> {
+        let target = params["contract_address"]
+            .as_str()
+            .ok_or_else(|| anyhow::anyhow!("Missing contract_address"))?;
+        Ok(SmartAccountCall {
+            target: target.to_string(),
+            value: params["value"].as_str().unwrap_or("0").to_string(),
+            function: match params["function_signature"] {
+                Value::Null => None,
+                _ => Some(Self::contract_call(params)?),
+            },
+        })
+    }
+
+    // The transaction prepare_unsigned_transaction and send_with_wallet build, by type
+    fn unsigned_request(params: &Value) -> Result<UnsignedRequest> {
+        let to = params["to"].as_str().unwrap_or("").to_string();
+        let amount = params["amount"].as_str().unwrap_or("0").to_string();
+        Ok(match params["type"].as_str().unwrap_or("send_eth") {
+            "send_eth" => UnsignedRequest::SendEth { to, amount },
+            "send_erc20" => UnsignedRequest::SendErc20 {
+                to,
+                token: params["token"].as_str().unwrap_or("").to_string(),
+                amount,
+            },
+            "write_contract" => UnsignedRequest::ContractCall {
+                call: Self::contract_call(params)?,
+                value: params["value"].as_str().unwrap_or("0").to_string(),
+                gas_limit: params["gas_limit"].as_u64(),
+            },
+            other => return Err(anyhow::anyhow!("Unknown transaction type: {}", other)),
+        })
+    }
+
+    // Smart account salt, a number or hex, defaulting to the owner's first account
+    fn account_salt(params: &Value) -> Result<U256> {
+        Ok(params["salt"]
+            .as_str()
+            .map(deploy_address::parse_salt)
+            .transpose()?
+            .map(|salt| U256::from_big_endian(salt.as_bytes()))
+            .unwrap_or_default())
+    }
+
+    // Run a write request in the background, answering as soon as its first transaction
+    // is sent instead of when it confirms. Requests that finish without sending one,
+    // such as previews, exports and failures, answer with their result as usual.
+    async fn submit(
         method: &str,
         params: Value,
-        blockchain_service: Arc<BlockchainService>,
         tool_registry: Arc<ToolRegistry>,
-        accounts: Arc<std::collections::HashMap<String, Account>>,
-        rag_service: Arc<RAGService>,
-        external_apis: Arc<ExternalAPIService>,
+        context: ToolContext,
     ) -> Result<Value> {
-        
-        let context = ToolContext {
-            blockchain_service: blockchain_service.clone(),
-            accounts: accounts.clone(),
-            rag_service: rag_service.clone(),
-            external_apis: external_apis.clone(),
+        let tracker = context.tx_tracker.clone();
+        let request_id = tracker.start(&context.namespace.name, method);
+        let (sent, mut submitted) = mpsc::unbounded_channel();
+        let mut task = tokio::spawn({
+            let tracker = tracker.clone();
+            let request_id = request_id.clone();
+            let method = method.to_string();
+            async move {
+                let result = tx_tracker::run_tracked(
+                    tracker.clone(),
+                    request_id.clone(),
+                    sent,
+                    Self::dispatch(&method, params, tool_registry, context),
+                )
+                .await;
+                tracker.finish(&request_id, &result);
+                result
+            }
+        });
+
+        tokio::select! {
+            result = &mut task => {
+                result.map_err(|e| anyhow::anyhow!("Request failed: {}", e))?
+            }
+            Some(hash) = submitted.recv() => Ok(json!({
+                "status": "submitted",
+                "hash": hash,
+                "request_id": request_id,
+                "note": "The transaction was sent and is confirming in the background. Check it with get_tx_status, which also gives the request's result once it finishes.",
+            })),
+        }
+    }
+
+    // Run a request, recording write operations in the namespace's audit trail
+    async fn dispatch(
+        method: &str,
+        params: Value,
+        tool_registry: Arc<ToolRegistry>,
+        context: ToolContext,
+    ) -> Result<Value> {
+        let mut params = params;
+        let resolved = match &context.session {
+            Some(session) if !LITERAL_METHODS.contains(&method) => {
+                context.sessions.substitute(session, &mut params)
+            }
+            _ => Default::default(),
         };
-        
+        let preferences = context
+            .session
+            .as_deref()
+            .map(|session| context.sessions.preferences(session));
+
+        let mut signer = Self::signer_for(method, &params);
+        let mut audited_params = params.clone();
+        // Transactions signed elsewhere are audited against their signer, with the ETH they
+        // send, so they count towards the daily budget
+        if let Some((account, value)) = Self::signed_transaction_sender(&context, method, &params) {
+            signer = Some(account);
+            audited_params["value"] = json!(format_ether(value));
+        }
+        let audit = context.namespace.audit.clone();
+        let blockchain_service = context.blockchain_service.clone();
+        let external_apis = context.external_apis.clone();
+
+        // ETH the request is cleared to send stays held against the budget until its
+        // audit entry counts it
+        let result = tenants::holding_budget(async {
+            let result = Self::handle_request(method, params, tool_registry, context).await;
+
+            if signer.is_some() {
+                let gas_cost = match &result {
+                    Ok(value) => {
+                        gas::execution_cost(&blockchain_service, &external_apis, value).await
+                    }
+                    Err(_) => None,
+                };
+                audit.record(method, signer, &audited_params, &result, gas_cost);
+            }
+            result
+        })
+        .await;
+
+        // Show which variables the request used so the values can be checked
+        result.map(|mut value| {
+            if let Some(preferences) = &preferences {
+                preferences.apply(&mut value);
+            }
+            if !resolved.is_empty()
+                && let Some(fields) = value.as_object_mut()
+            {
+                fields.insert("resolved_variables".to_string(), json!(resolved));
+            }
+            value
+        })
+    }
+
+    async fn handle_request(
+        method: &str,
+        params: Value,
+        tool_registry: Arc<ToolRegistry>,
+        context: ToolContext,
+    ) -> Result<Value> {
+        let blockchain_service = context.blockchain_service.clone();
+        let accounts = context.accounts.clone();
+        let account_manager = context.namespace.accounts.clone();
+
+        if let Some(signer) = Self::signer_for(method, &params) {
+            // Paired wallets sign for themselves, so they aren't accounts here
+            let paired = method == "send_with_wallet"
+                && context
+                    .wallets
+                    .get(&context.namespace.name, &signer)
+                    .is_some();
+            if account_manager.get(&signer).is_none() && !paired {
+                return Err(anyhow::anyhow!("Unknown account: {}", signer));
+            }
+            if !Self::is_preview(method, &params) {
+                context.policy.check_write(&signer)?;
+            }
+        }
+
         match method {
             "get_balance" => {
                 let address = params["address"].as_str().unwrap_or("").to_string();
                 let token = params["token"].as_str().map(|s| s.to_string());
 
-                // Resolve named accounts
-                let resolved_address = if let Some(account) = accounts.get(&address) {
-                    account.address.clone()
-                } else {
-                    address
-                };
+                // Resolve named accounts, aliases and ENS names
+                let resolved_address = blockchain_service
+                    .resolve_address(&account_manager.resolve_address(&address))
+                    .await?;
+
+                // A past block reads state at that block instead of the cached balance
+                if let Some(block) = historical::parse_block(&params["block"])? {
+                    return historical::historical_balance(
+                        &blockchain_service,
+                        &context.external_apis,
+                        &resolved_address,
+                        token.as_deref(),
+                        block,
+                    )
+                    .await;
+                }
 
                 let query = BalanceQuery {
                     address: resolved_address,
@@ -166,94 +1140,2618 @@ impl Server {
                     return Err(anyhow::anyhow!("Unknown account: {}", from));
                 };
 
-                let to_address = if let Some(account) = accounts.get(&to) {
-                    account.address.clone()
+                let to_address = blockchain_service
+                    .resolve_address(&account_manager.resolve_address(&to))
+                    .await?;
+
+                // Keep within the namespace's daily spending budget
+                context
+                    .namespace
+                    .check_budget(&from, utils::parse_amount(&amount, 18)?)?;
+
+                // Screen the recipient against known scam and sanctioned addresses
+                let screening = context
+                    .screener
+                    .screen(&to_address, &context.external_apis)
+                    .await?;
+                let override_screening = params["override_screening"].as_bool().unwrap_or(false);
+                context
+                    .policy
+                    .check_screening(&screening, override_screening)?;
+
+                let fees = FeeOverrides::from_params(&params)?;
+                if params["export"].as_bool().unwrap_or(false) {
+                    let signed = blockchain_service
+                        .export_eth_transfer(&from_account, &to_address, &amount, &fees)
+                        .await?;
+                    return Ok(json!({
+                        "signed_transaction": signed,
+                        "note": "Signed but not broadcast. Submit the raw transaction with broadcast_raw_transaction or any eth_sendRawTransaction endpoint; anything this account sends first invalidates it",
+                    }));
+                }
+                let simulation = if params["simulate"].as_bool().unwrap_or(false) {
+                    let simulation = blockchain_service
+                        .simulate_eth_transfer(&from_account, &to_address, &amount, &fees)
+                        .await?;
+                    if !simulation.success {
+                        return Err(anyhow::anyhow!(simulation.failure_message()));
+                    }
+                    Some(simulation)
                 } else {
-                    to
+                    None
                 };
-
+                let watched = blockchain_service
+                    .balances_before(&[
+                        (from_account.address.clone(), None),
+                        (to_address.clone(), None),
+                    ])
+                    .await;
                 let result = blockchain_service
-                    .send_transaction(&from_account, &to_address, &amount)
-                    .await?;
-                Ok(json!(result))
-            }
-            "check_contract" => {
-                let address = params["address"].as_str().unwrap_or("").to_string();
-                let result = blockchain_service.check_contract_deployed(&address).await?;
-                Ok(json!({"deployed": result}))
-            }
-            "search_web" => {
-                let query = params["query"].as_str().unwrap_or("").to_string();
-                let search_tool = tool_registry.get_tool("search_web")?;
-                let result = search_tool
-                    .execute(json!({"query": query}), &context)
+                    .send_transaction(&from_account, &to_address, &amount, &fees)
                     .await?;
 
+                let balance_changes = blockchain_service
+                    .refresh_balances(watched, result.block_number)
+                    .await;
+                let mut result = json!(result);
+                result["balance_changes"] = json!(balance_changes);
+                if let Some(simulation) = simulation {
+                    result["simulation"] = json!(simulation);
+                }
                 Ok(result)
             }
-            "get_token_price" => {
+            "send_erc20" => {
+                let from = params["from"].as_str().unwrap_or("").to_string();
+                let to = params["to"].as_str().unwrap_or("").to_string();
                 let token = params["token"].as_str().unwrap_or("").to_string();
-                let price_tool = tool_registry.get_tool("get_token_price")?;
-                let result = price_tool
-                    .execute(json!({"token": token}), &context)
+                let amount = params["amount"].as_str().unwrap_or("0").to_string();
+
+                let from_account = if let Some(account) = accounts.get(&from) {
+                    account.clone()
+                } else {
+                    return Err(anyhow::anyhow!("Unknown account: {}", from));
+                };
+
+                let to_address = blockchain_service
+                    .resolve_address(&account_manager.resolve_address(&to))
                     .await?;
 
-                Ok(result)
-            }
-            "search_docs" => {
-                let query = params["query"].as_str().unwrap_or("").to_string();
-                let limit = params["limit"].as_u64().unwrap_or(5) as usize;
-                let docs_tool = tool_registry.get_tool("search_docs")?;
-                let result = docs_tool
-                    .execute(json!({"query": query, "limit": limit}), &context)
+                let screening = context
+                    .screener
+                    .screen(&to_address, &context.external_apis)
                     .await?;
+                let override_screening = params["override_screening"].as_bool().unwrap_or(false);
+                context
+                    .policy
+                    .check_screening(&screening, override_screening)?;
 
-                Ok(result)
-            }
-            "get_document" => {
-                let id = params["id"].as_str().unwrap_or("").to_string();
-                let docs_tool = tool_registry.get_tool("get_docs")?;
-                let result = docs_tool
-                    .execute(json!({"id": id}), &context)
+                let fees = FeeOverrides::from_params(&params)?;
+                if params["export"].as_bool().unwrap_or(false) {
+                    let signed = blockchain_service
+                        .export_erc20_transfer(&from_account, &to_address, &token, &amount, &fees)
+                        .await?;
+                    return Ok(json!({
+                        "signed_transaction": signed,
+                        "note": "Signed but not broadcast. Submit the raw transaction with broadcast_raw_transaction or any eth_sendRawTransaction endpoint; anything this account sends first invalidates it",
+                    }));
+                }
+                let simulation = if params["simulate"].as_bool().unwrap_or(false) {
+                    let simulation = blockchain_service
+                        .simulate_erc20_transfer(&from_account, &to_address, &token, &amount, &fees)
+                        .await?;
+                    if !simulation.success {
+                        return Err(anyhow::anyhow!(simulation.failure_message()));
+                    }
+                    Some(simulation)
+                } else {
+                    None
+                };
+                let watched = blockchain_service
+                    .balances_before(&[
+                        (from_account.address.clone(), Some(token.clone())),
+                        (from_account.address.clone(), None),
+                        (to_address.clone(), Some(token.clone())),
+                    ])
+                    .await;
+                let result = blockchain_service
+                    .send_erc20(&from_account, &to_address, &token, &amount, &fees)
                     .await?;
-                
+
+                let balance_changes = blockchain_service
+                    .refresh_balances(watched, result.block_number)
+                    .await;
+                let mut result = json!(result);
+                result["balance_changes"] = json!(balance_changes);
+                if let Some(simulation) = simulation {
+                    result["simulation"] = json!(simulation);
+                }
                 Ok(result)
             }
-            "list_supported_tokens" => {
-                let tokens = blockchain_service.get_supported_tokens();
-                let token_list: Vec<Value> = tokens
+            "send_batch" => {
+                let from = params["from"].as_str().unwrap_or("").to_string();
+                let token = params["token"]
+                    .as_str()
+                    .filter(|token| !token.eq_ignore_ascii_case("eth"));
+                let transfers = params["transfers"]
+                    .as_array()
+                    .filter(|transfers| !transfers.is_empty())
+                    .ok_or_else(|| anyhow::anyhow!("transfers must be a non-empty list"))?;
+                if transfers.len() > MAX_BATCH_TRANSFERS {
+                    return Err(anyhow::anyhow!(
+                        "A batch can hold at most {} transfers",
+                        MAX_BATCH_TRANSFERS
+                    ));
+                }
+                let transfers: Vec<(String, String)> = transfers
                     .iter()
-                    .map(|token| {
-                        json!({
-                            "symbol": token.symbol,
-                            "name": token.name,
-                            "address": token.address,
-                            "decimals": token.decimals
-                        })
+                    .map(|transfer| {
+                        let to = transfer["to"]
+                            .as_str()
+                            .ok_or_else(|| anyhow::anyhow!("Each transfer needs a 'to'"))?;
+                        let amount = match &transfer["amount"] {
+                            Value::String(amount) => amount.clone(),
+                            Value::Number(amount) => amount.to_string(),
+                            _ => return Err(anyhow::anyhow!("Each transfer needs an 'amount'")),
+                        };
+                        Ok((to.to_string(), amount))
                     })
-                    .collect();
+                    .collect::<Result<_>>()?;
 
-                Ok(json!({"tokens": token_list}))
-            }
-            "swap_tokens" => {
+                if !params["disperse"].as_bool().unwrap_or(false) {
+                    // One send at a time through the normal write path, so every transfer
+                    // is screened, budgeted and audited on its own
+                    let mut results = Vec::new();
+                    let mut succeeded = 0;
+                    for (to, amount) in transfers {
+                        let mut send = json!({
+                            "from": from,
+                            "to": to,
+                            "amount": amount,
+                            "override_screening": params["override_screening"],
+                        });
+                        let method = match token {
+                            Some(token) => {
+                                send["token"] = json!(token);
+                                "send_erc20"
+                            }
+                            None => "send_eth",
+                        };
+                        let mut entry = json!({"to": to, "amount": amount});
+                        match Box::pin(Self::dispatch(
+                            method,
+                            send,
+                            tool_registry.clone(),
+                            context.clone(),
+                        ))
+                        .await
+                        {
+                            Ok(result) => {
+                                succeeded += 1;
+                                entry["result"] = result;
+                            }
+                            Err(e) => entry["error"] = json!(e.to_string()),
+                        }
+                        results.push(entry);
+                    }
+                    return Ok(json!({
+                        "mode": "sequential",
+                        "token": token.unwrap_or("ETH"),
+                        "succeeded": succeeded,
+                        "failed": results.len() - succeeded,
+                        "transfers": results,
+                    }));
+                }
+
+                let from_account = accounts
+                    .get(&from)
+                    .cloned()
+                    .ok_or_else(|| anyhow::anyhow!("Unknown account: {}", from))?;
+                let token_info = match token {
+                    Some(token) => Some(blockchain_service.resolve_token(token).await?),
+                    None => None,
+                };
+                let decimals = token_info.as_ref().map_or(18, |token| token.decimals);
+                let override_screening = params["override_screening"].as_bool().unwrap_or(false);
+                let mut resolved = Vec::new();
+                let mut total_wei = U256::zero();
+                for (to, amount) in &transfers {
+                    let to_address = blockchain_service
+                        .resolve_address(&account_manager.resolve_address(to))
+                        .await?;
+                    let screening = context
+                        .screener
+                        .screen(&to_address, &context.external_apis)
+                        .await?;
+                    context
+                        .policy
+                        .check_screening(&screening, override_screening)?;
+                    let units = utils::parse_amount(amount, decimals)?;
+                    total_wei = total_wei.saturating_add(units);
+                    resolved.push((to_address.parse()?, units));
+                }
+                if token_info.is_none() {
+                    context.namespace.check_budget(&from, total_wei)?;
+                }
+
+                let fees = FeeOverrides::from_params(&params)?;
+                let result = blockchain_service
+                    .disperse(&from_account, token_info.as_ref(), &resolved, &fees)
+                    .await?;
+                Ok(json!({
+                    "mode": "disperse",
+                    "token": token_info.map_or("ETH".to_string(), |token| token.symbol),
+                    "transaction": result,
+                    "transfers": transfers
+                        .iter()
+                        .zip(&resolved)
+                        .map(|((to, amount), (address, _))| {
+                            json!({"to": to, "address": format!("{:?}", address), "amount": amount})
+                        })
+                        .collect::<Vec<_>>(),
+                }))
+            }
+            "broadcast_raw_transaction" => {
+                let raw = params["raw_transaction"]
+                    .as_str()
+                    .ok_or_else(|| anyhow::anyhow!("Missing raw_transaction"))?;
+                let (tx, signer) = blockchain_service.decode_raw_transaction(raw)?;
+                Self::check_signed_transaction(&context, &tx, signer, &params).await?;
+                let result = blockchain_service.broadcast_raw_transaction(raw).await?;
+                Ok(json!(result))
+            }
+            "prepare_unsigned_transaction" => {
+                let from = params["from"]
+                    .as_str()
+                    .ok_or_else(|| anyhow::anyhow!("Missing from"))?;
+                let fees = FeeOverrides::from_params(&params)?;
+                let unsigned = blockchain_service
+                    .prepare_unsigned(
+                        from,
+                        Self::unsigned_request(&params)?,
+                        &|name| account_manager.resolve_address(name),
+                        &fees,
+                    )
+                    .await?;
+                Ok(json!({
+                    "unsigned_transaction": unsigned,
+                    "note": "Nothing was signed or sent. Sign signing_hash (or unsigned_raw) with the sender's key, then pass the transaction and signature to attach_signature",
+                }))
+            }
+            "attach_signature" => {
+                let transaction: TypedTransaction =
+                    serde_json::from_value(params["transaction"].clone())
+                        .map_err(|e| anyhow::anyhow!("Invalid transaction: {}", e))?;
+                let signature = params["signature"]
+                    .as_str()
+                    .ok_or_else(|| anyhow::anyhow!("Missing signature"))?;
+
+                let signed = blockchain_service.attach_signature(&transaction, signature)?;
+                if !params["broadcast"].as_bool().unwrap_or(true) {
+                    return Ok(json!({"signed_transaction": signed}));
+                }
+                let (tx, signer) = blockchain_service.decode_raw_transaction(&signed.raw)?;
+                Self::check_signed_transaction(&context, &tx, signer, &params).await?;
+                let result = blockchain_service
+                    .broadcast_raw_transaction(&signed.raw)
+                    .await?;
+                Ok(json!({"signed_transaction": signed, "result": result}))
+            }
+            "sign_message" => {
+                let account = params["account"].as_str().unwrap_or("");
+                let message = params["message"]
+                    .as_str()
+                    .ok_or_else(|| anyhow::anyhow!("Missing message"))?;
+                let hex = params["hex"].as_bool().unwrap_or(false);
+
+                let account = accounts
+                    .get(account)
+                    .cloned()
+                    .ok_or_else(|| anyhow::anyhow!("Unknown account: {}", account))?;
+                Ok(json!(signing::sign_message(&account, message, hex).await?))
+            }
+            "verify_signature" => {
+                let message = params["message"]
+                    .as_str()
+                    .ok_or_else(|| anyhow::anyhow!("Missing message"))?;
+                let signature = params["signature"]
+                    .as_str()
+                    .ok_or_else(|| anyhow::anyhow!("Missing signature"))?;
+                let hex = params["hex"].as_bool().unwrap_or(false);
+                let expected = match params["address"].as_str() {
+                    Some(address) => Some(
+                        blockchain_service
+                            .parse_address(&account_manager.resolve_address(address))
+                            .await?,
+                    ),
+                    None => None,
+                };
+
+                Ok(json!(signing::verify_signature(
+                    message, signature, hex, expected
+                )?))
+            }
+            "get_audit_log" => {
+                let account = params["account"].as_str();
+                let since = params["since"].as_i64().unwrap_or(0);
+                let entries = context.namespace.audit.entries(since, account);
+                Ok(json!({"namespace": context.namespace.name, "entries": entries}))
+            }
+            "self_check" => Ok(json!(diagnostics::self_check(&context).await)),
+            "set_variable" => {
+                let session = Self::session(&context)?;
+                let name = params["name"]
+                    .as_str()
+                    .ok_or_else(|| anyhow::anyhow!("Missing name"))?;
+                let value = match &params["value"] {
+                    Value::String(value) => value.clone(),
+                    Value::Null => return Err(anyhow::anyhow!("Missing value")),
+                    other => other.to_string(),
+                };
+
+                context.sessions.set(session, name, &value)?;
+                Ok(json!({"name": name, "value": value}))
+            }
+            "unset_variable" => {
+                let session = Self::session(&context)?;
+                let name = params["name"]
+                    .as_str()
+                    .ok_or_else(|| anyhow::anyhow!("Missing name"))?;
+
+                let previous = context.sessions.unset(session, name);
+                Ok(json!({"name": name, "removed": previous.is_some(), "previous_value": previous}))
+            }
+            "list_variables" => {
+                let session = Self::session(&context)?;
+                Ok(json!({"variables": context.sessions.variables(session)}))
+            }
+            "set_display_preferences" => {
+                let session = Self::session(&context)?;
+                let preferences = context.sessions.set_preferences(session, &params)?;
+                Ok(json!({"preferences": preferences}))
+            }
+            "get_display_preferences" => {
+                let session = Self::session(&context)?;
+                Ok(json!({"preferences": context.sessions.preferences(session)}))
+            }
+            "save_template" => {
+                let name = params["name"]
+                    .as_str()
+                    .ok_or_else(|| anyhow::anyhow!("Missing name"))?;
+                let steps: Vec<TemplateStep> = serde_json::from_value(params["steps"].clone())
+                    .map_err(|e| anyhow::anyhow!("Invalid template steps: {}", e))?;
+                let parameters = match &params["parameters"] {
+                    Value::Null => Default::default(),
+                    parameters => serde_json::from_value(parameters.clone())
+                        .map_err(|e| anyhow::anyhow!("Invalid template parameters: {}", e))?,
+                };
+
+                let template = OperationTemplate {
+                    name: name.to_string(),
+                    description: params["description"].as_str().map(|s| s.to_string()),
+                    parameters,
+                    steps,
+                    created_at: chrono::Utc::now().timestamp(),
+                };
+                let replaced = context
+                    .templates
+                    .save(&context.namespace.name, template.clone())?;
+                Ok(json!({"template": template, "replaced": replaced}))
+            }
+            "list_templates" => Ok(json!({
+                "templates": context.templates.list(&context.namespace.name)
+            })),
+            "delete_template" => {
+                let name = params["name"].as_str().unwrap_or("");
+                let deleted = context.templates.delete(&context.namespace.name, name)?;
+                Ok(json!({"name": name, "deleted": deleted}))
+            }
+            "run_template" => {
+                let name = params["name"].as_str().unwrap_or("");
+                let template = context
+                    .templates
+                    .get(&context.namespace.name, name)
+                    .ok_or_else(|| anyhow::anyhow!("No template named '{}'", name))?;
+                let steps = template.instantiate(&params["args"])?;
+
+                if params["confirm"].as_bool().unwrap_or(false) {
+                    // With a gas limit, wait in the scheduler rather than run at today's fees
+                    let max_gas_price_gwei = params["max_gas_price_gwei"].as_f64();
+                    if let Some(reason) =
+                        scheduler::gas_deferral(&blockchain_service, max_gas_price_gwei).await?
+                    {
+                        let job = ScheduledJob::new(
+                            &context.namespace.name,
+                            "run_template",
+                            json!({"name": name, "args": params["args"], "confirm": true}),
+                            None,
+                            max_gas_price_gwei,
+                            scheduler::timestamp_param(&params["expires_at"])?,
+                        )?;
+                        context.jobs.add(job.clone())?;
+                        return Ok(json!({
+                            "template": name,
+                            "executed": false,
+                            "deferred": true,
+                            "reason": reason,
+                            "job": job,
+                        }));
+                    }
+                    Ok(Self::execute_template(&template, steps, tool_registry, context).await)
+                } else {
+                    Ok(Self::preview_template(&template, steps, tool_registry, context).await)
+                }
+            }
+            "schedule_job" => {
+                let method = params["method"]
+                    .as_str()
+                    .ok_or_else(|| anyhow::anyhow!("Missing method"))?;
+                if method == "schedule_job" || !WRITE_METHODS.contains(&method) {
+                    return Err(anyhow::anyhow!(
+                        "Only transactions and templates can be scheduled, not {}",
+                        method
+                    ));
+                }
+                let mut job_params = params["params"].clone();
+                if !job_params.is_object() {
+                    return Err(anyhow::anyhow!("params must be an object"));
+                }
+
+                if method == "run_template" {
+                    let name = job_params["name"].as_str().unwrap_or("");
+                    if context
+                        .templates
+                        .get(&context.namespace.name, name)
+                        .is_none()
+                    {
+                        return Err(anyhow::anyhow!("No template named '{}'", name));
+                    }
+                    // Scheduling is the confirmation
+                    job_params["confirm"] = json!(true);
+                }
+                if let Some(signer) = Self::signer_for(method, &job_params)
+                    && account_manager.get(&signer).is_none()
+                {
+                    return Err(anyhow::anyhow!("Unknown account: {}", signer));
+                }
+
+                let now = chrono::Utc::now().timestamp();
+                let run_at = match scheduler::timestamp_param(&params["run_at"])? {
+                    Some(run_at) => Some(run_at),
+                    None => params["delay_seconds"].as_i64().map(|delay| now + delay),
+                };
+                let expires_at = match scheduler::timestamp_param(&params["expires_at"])? {
+                    Some(expires_at) => Some(expires_at),
+                    None => params["expires_in_seconds"]
+                        .as_i64()
+                        .map(|seconds| run_at.unwrap_or(now) + seconds),
+                };
+
+                let job = ScheduledJob::new(
+                    &context.namespace.name,
+                    method,
+                    job_params,
+                    run_at,
+                    params["max_gas_price_gwei"].as_f64(),
+                    expires_at,
+                )?;
+                context.jobs.add(job.clone())?;
+
+                let current_gas_price_gwei = match job.max_gas_price_gwei {
+                    Some(_) => scheduler::current_gas_price_gwei(&blockchain_service)
+                        .await
+                        .ok(),
+                    None => None,
+                };
+                Ok(json!({"job": job, "current_gas_price_gwei": current_gas_price_gwei}))
+            }
+            "add_top_up_rule" => {
+                let account = params["account"]
+                    .as_str()
+                    .ok_or_else(|| anyhow::anyhow!("Missing account"))?;
+                let from = params["from"].as_str().unwrap_or("");
+                let to_address = blockchain_service
+                    .resolve_address(&account_manager.resolve_address(account))
+                    .await?;
+                let from_address = account_manager
+                    .get(from)
+                    .map(|account| account.address.clone())
+                    .ok_or_else(|| anyhow::anyhow!("Unknown account: {}", from))?;
+                if to_address.eq_ignore_ascii_case(&from_address) {
+                    return Err(anyhow::anyhow!("An account can't top itself up"));
+                }
+
+                let limits = TopUpLimits {
+                    min_balance: params["min_balance"]
+                        .as_str()
+                        .ok_or_else(|| anyhow::anyhow!("Missing min_balance"))?
+                        .to_string(),
+                    target_balance: params["target_balance"].as_str().map(|s| s.to_string()),
+                    max_per_top_up: params["max_per_top_up"].as_str().map(|s| s.to_string()),
+                    max_per_day: params["max_per_day"]
+                        .as_str()
+                        .ok_or_else(|| anyhow::anyhow!("Missing max_per_day"))?
+                        .to_string(),
+                };
+                let network = context
+                    .namespace
+                    .network()
+                    .unwrap_or_else(|| context.networks.default_network().to_string());
+                let rule =
+                    TopUpRule::new(&context.namespace.name, &network, account, from, &limits)?;
+                context.top_ups.add(rule.clone())?;
+                Ok(json!({"rule": rule}))
+            }
+            "list_top_up_rules" => Ok(json!({
+                "rules": context.top_ups.list(&context.namespace.name)
+            })),
+            "remove_top_up_rule" => {
+                let id = params["id"].as_str().unwrap_or("");
+                if !context.top_ups.remove(&context.namespace.name, id)? {
+                    return Err(anyhow::anyhow!("No top-up rule {}", id));
+                }
+                Ok(json!({"removed": id}))
+            }
+            "list_jobs" => {
+                let mut jobs = context.jobs.list(&context.namespace.name);
+                if let Some(status) = params["status"].as_str() {
+                    let status: JobStatus = serde_json::from_value(json!(status))
+                        .map_err(|_| anyhow::anyhow!("Unknown job status: {}", status))?;
+                    jobs.retain(|job| job.status == status);
+                }
+                Ok(json!({"jobs": jobs}))
+            }
+            "cancel_job" => {
+                let id = params["id"].as_str().unwrap_or("");
+                let job = context.jobs.cancel(&context.namespace.name, id)?;
+                Ok(json!({"job": job}))
+            }
+            "get_notifications" => {
+                let since = scheduler::timestamp_param(&params["since"])?;
+                Ok(json!({
+                    "notifications": context.jobs.notifications(&context.namespace.name, since)
+                }))
+            }
+            "get_inbox" => {
+                let include_read = params["include_read"].as_bool().unwrap_or(false);
+                let items = context.inbox.items(&context.namespace.name, include_read);
+                if params["mark_read"].as_bool().unwrap_or(true)
+                    && let Some(last) = items.last()
+                {
+                    context.inbox.mark_read(&context.namespace.name, last.id)?;
+                }
+                Ok(json!({"count": items.len(), "items": items}))
+            }
+            "get_stablecoin_status" => {
+                let mut config = DepegConfig::from_env();
+                if let Some(tokens) = params["tokens"].as_array() {
+                    config.stablecoins = tokens
+                        .iter()
+                        .filter_map(|token| token.as_str().map(str::to_string))
+                        .collect();
+                }
+                if let Some(threshold) = params["threshold_percent"].as_f64() {
+                    config.threshold_percent = threshold;
+                }
+                let stablecoins = depeg::check_stablecoins(
+                    &context.blockchain_service,
+                    &context.external_apis,
+                    &config,
+                )
+                .await;
+                Ok(json!({
+                    "threshold_percent": config.threshold_percent,
+                    "stablecoins": stablecoins,
+                }))
+            }
+            "snapshot_chain" => Ok(json!(devchain::snapshot_chain(&blockchain_service).await?)),
+            "revert_chain" => {
+                let id = params["id"]
+                    .as_str()
+                    .ok_or_else(|| anyhow::anyhow!("id is required"))?;
+                Ok(json!(
+                    devchain::revert_chain(&blockchain_service, id).await?
+                ))
+            }
+            "increase_time" => {
+                let seconds = params["seconds"]
+                    .as_u64()
+                    .ok_or_else(|| anyhow::anyhow!("seconds is required"))?;
+                let mine = params["mine"].as_bool().unwrap_or(true);
+                Ok(json!(
+                    devchain::increase_time(&blockchain_service, seconds, mine).await?
+                ))
+            }
+            "mine_blocks" => {
+                let blocks = params["blocks"].as_u64().unwrap_or(1);
+                Ok(json!(
+                    devchain::mine_blocks(&blockchain_service, blocks).await?
+                ))
+            }
+            "set_next_block_timestamp" => {
+                let timestamp = params["timestamp"]
+                    .as_u64()
+                    .ok_or_else(|| anyhow::anyhow!("timestamp is required"))?;
+                let mine = params["mine"].as_bool().unwrap_or(true);
+                Ok(json!(
+                    devchain::set_next_block_timestamp(&blockchain_service, timestamp, mine)
+                        .await?
+                ))
+            }
+            "fund_account" => {
+                let account = params["account"]
+                    .as_str()
+                    .ok_or_else(|| anyhow::anyhow!("account is required"))?;
+                let amount = params["amount"]
+                    .as_str()
+                    .ok_or_else(|| anyhow::anyhow!("amount is required"))?;
+                let address = blockchain_service
+                    .resolve_address(&account_manager.resolve_address(account))
+                    .await?;
+                Ok(json!(
+                    devchain::fund_account(
+                        &blockchain_service,
+                        &address,
+                        params["token"].as_str(),
+                        amount
+                    )
+                    .await?
+                ))
+            }
+            "tools/list" => Ok(capabilities::tools_list(&tool_registry, &context)),
+            "list_networks" => {
+                let active = context
+                    .namespace
+                    .network()
+                    .unwrap_or_else(|| context.networks.default_network().to_string());
+                let networks: Vec<Value> = context
+                    .networks
+                    .networks()
+                    .iter()
+                    .map(|network| {
+                        json!({
+                            "name": network.name,
+                            "chain_id": network.chain_id,
+                            "rpc_url": network.rpc_url,
+                            "router": network.router,
+                            "active": network.name.eq_ignore_ascii_case(&active),
+                        })
+                    })
+                    .collect();
+                Ok(json!({"active": active, "networks": networks}))
+            }
+            "switch_network" => {
+                let name = params["network"].as_str().unwrap_or("").to_string();
+                let network = context
+                    .networks
+                    .get(&name)
+                    .cloned()
+                    .ok_or_else(|| anyhow::anyhow!("Unknown network: {}", name))?;
+
+                // Make sure the endpoint actually serves the chain we expect
+                let service = context.networks.service(Some(&network.name))?;
+                let rpc_chain_id = service.provider().get_chainid().await?.as_u64();
+                if rpc_chain_id != network.chain_id {
+                    return Err(anyhow::anyhow!(
+                        "RPC for {} reports chain {}, expected {}",
+                        network.name,
+                        rpc_chain_id,
+                        network.chain_id
+                    ));
+                }
+
+                context.namespace.set_network(&network.name);
+                info!(
+                    "Namespace {} switched to {}",
+                    context.namespace.name, network.name
+                );
+                let mut result = json!({
+                    "network": network.name,
+                    "chain_id": network.chain_id,
+                    "rpc_url": network.rpc_url,
+                });
+                if let Ok(Some(lag)) = service.check_block_lag().await {
+                    result["warning"] = json!(format!(
+                        "The RPC's latest block is {}s old; balances and quotes may be stale",
+                        lag.as_secs()
+                    ));
+                }
+                Ok(result)
+            }
+            "estimate_gas" => {
+                let kind = params["type"].as_str().unwrap_or("send_eth");
+                let from = params["from"].as_str().unwrap_or("").to_string();
+                let from_address = account_manager.resolve_address(&from);
+                let to_address = match params["to"].as_str() {
+                    Some(to) => {
+                        blockchain_service
+                            .resolve_address(&account_manager.resolve_address(to))
+                            .await?
+                    }
+                    None => String::new(),
+                };
+                let amount = params["amount"].as_str().unwrap_or("0").to_string();
+
+                let request = match kind {
+                    "send_eth" => GasEstimateRequest::SendEth {
+                        from: from_address,
+                        to: to_address,
+                        amount,
+                    },
+                    "send_erc20" => GasEstimateRequest::SendErc20 {
+                        from: from_address,
+                        to: to_address,
+                        token: params["token"].as_str().unwrap_or("").to_string(),
+                        amount,
+                    },
+                    "swap" | "swap_tokens" => GasEstimateRequest::Swap {
+                        from: from_address,
+                        from_token: params["from_token"].as_str().unwrap_or("").to_string(),
+                        to_token: params["to_token"].as_str().unwrap_or("").to_string(),
+                        amount,
+                    },
+                    other => return Err(anyhow::anyhow!("Unknown transaction type: {}", other)),
+                };
+
+                let estimate = blockchain_service.estimate_gas(request).await?;
+                let eth_price =
+                    pnl::token_usd_price(&blockchain_service, &context.external_apis, "ETH").await;
+                let to_usd = |eth: &str| {
+                    eth_price.and_then(|price| eth.parse::<f64>().ok().map(|eth| eth * price))
+                };
+
+                Ok(json!({
+                    "estimate": estimate,
+                    "eth_price_usd": eth_price,
+                    "expected_cost_usd": to_usd(&estimate.expected_cost_eth),
+                    "max_cost_usd": to_usd(&estimate.max_cost_eth),
+                }))
+            }
+            "estimate_swap" => {
+                let from_token = params["from_token"].as_str().unwrap_or("").to_string();
+                let to_token = params["to_token"].as_str().unwrap_or("").to_string();
+                let amount = params["amount"].as_str().unwrap_or("0").to_string();
+                let aggregators = match params["dex"].as_str() {
+                    Some(dex) => Aggregator::from_dex(dex),
+                    None => Some(Aggregator::ALL.to_vec()),
+                };
+                // Aggregator swaps are quoted on Uniswap too, for comparison
+                let quote = blockchain_service
+                    .quote_swap(
+                        &from_token,
+                        &to_token,
+                        &amount,
+                        params["dex"]
+                            .as_str()
+                            .filter(|dex| Aggregator::from_dex(dex).is_none()),
+                        params["slippage"].as_f64(),
+                    )
+                    .await?;
+
+                let mut result = json!({
+                    "quote": quote,
+                    "note": "Nothing was sent. Show this quote to the user and ask for confirmation before calling swap_tokens",
+                });
+                if let Some(limit) = context.policy.config().max_price_impact_percent
+                    && quote.price_impact_percent > limit
+                {
+                    result["price_impact_warning"] = json!(format!(
+                        "Price impact of {}% is above the {}% limit; swap_tokens will refuse it unless the user accepts the impact",
+                        quote.price_impact_percent, limit
+                    ));
+                }
+
+                // Aggregators quote for a taker, so they're compared only when there's a sender
+                if let Some(from) = params["from"].as_str()
+                    && let Some(aggregators) = &aggregators
+                {
+                    let taker = blockchain_service
+                        .resolve_address(&account_manager.resolve_address(from))
+                        .await?;
+                    match aggregator::best_quote(
+                        &blockchain_service,
+                        &context.external_apis,
+                        aggregators,
+                        &QuoteRequest {
+                            from_token: from_token.clone(),
+                            to_token: to_token.clone(),
+                            amount: amount.clone(),
+                            taker: taker.parse()?,
+                            slippage_percent: params["slippage"].as_f64().unwrap_or(0.5),
+                        },
+                    )
+                    .await
+                    {
+                        Ok(aggregator_quote) => {
+                            result["best_dex"] = json!(if aggregator_quote.beats(&quote) {
+                                aggregator_quote.aggregator.as_str()
+                            } else {
+                                quote.dex.as_str()
+                            });
+                            result["aggregator_quote"] = json!(aggregator_quote);
+                        }
+                        Err(e) => result["aggregator_error"] = json!(e.to_string()),
+                    }
+                }
+
+                // Gas needs a sender to simulate from
+                if let Some(from) = params["from"].as_str() {
+                    let request = GasEstimateRequest::Swap {
+                        from: account_manager.resolve_address(from),
+                        from_token,
+                        to_token,
+                        amount,
+                    };
+                    match blockchain_service.estimate_gas(request).await {
+                        Ok(mut estimate) => {
+                            // Gas is simulated on the V2 router
+                            estimate.approximate |= quote.dex == Dex::UniswapV3.as_str();
+                            result["gas"] = json!(estimate);
+                        }
+                        Err(e) => result["gas_error"] = json!(e.to_string()),
+                    }
+                }
+                Ok(result)
+            }
+            "get_historical_balance" => {
+                let account = params["account"].as_str().unwrap_or("").to_string();
+                let token = params["token"].as_str();
+                let block = historical::parse_block(&params["block"])?
+                    .ok_or_else(|| anyhow::anyhow!("Missing block parameter"))?;
+                let address = blockchain_service
+                    .resolve_address(&account_manager.resolve_address(&account))
+                    .await?;
+                historical::historical_balance(
+                    &blockchain_service,
+                    &context.external_apis,
+                    &address,
+                    token,
+                    block,
+                )
+                .await
+            }
+            "get_historical_price" => {
+                let token = params["token"].as_str().unwrap_or("ETH").to_string();
+                let block = params["block"]
+                    .as_u64()
+                    .ok_or_else(|| anyhow::anyhow!("Missing block parameter"))?;
+                historical::historical_price(
+                    &blockchain_service,
+                    &context.external_apis,
+                    &token,
+                    block,
+                )
+                .await
+            }
+            "get_twap" => {
+                let token = params["token"]
+                    .as_str()
+                    .ok_or_else(|| anyhow::anyhow!("Missing token"))?;
+                let quote = params["quote"].as_str().unwrap_or("USDC");
+                let window = params["window_seconds"]
+                    .as_u64()
+                    .unwrap_or(twap::DEFAULT_TWAP_WINDOW);
+                let dex = params["dex"].as_str().map(Dex::parse).transpose()?;
+                let fee = params["fee"].as_u64().map(|fee| fee as u32);
+                let result =
+                    twap::get_twap(&blockchain_service, token, quote, window, dex, fee).await?;
+                Ok(json!(result))
+            }
+            "get_pool_info" => {
+                let token = params["token"]
+                    .as_str()
+                    .ok_or_else(|| anyhow::anyhow!("Missing token"))?;
+                let quote = params["quote"].as_str().unwrap_or("USDC");
+                let result = pools::get_pool_info(&blockchain_service, token, quote).await?;
+                Ok(json!(result))
+            }
+            "get_lending_position" => {
+                let address = params["address"]
+                    .as_str()
+                    .ok_or_else(|| anyhow::anyhow!("address is required"))?;
+                let address = blockchain_service
+                    .resolve_address(&account_manager.resolve_address(address))
+                    .await?;
+                Ok(json!(
+                    aave::get_lending_position(&blockchain_service, &address).await?
+                ))
+            }
+            "get_balance_history" => {
+                let account = params["account"].as_str().unwrap_or("").to_string();
+                let range = params["range"].as_str().unwrap_or("30d");
+                let token = params["token"].as_str();
+                let address = blockchain_service
+                    .resolve_address(&account_manager.resolve_address(&account))
+                    .await?;
+                let since = chrono::Utc::now().timestamp() - activity::parse_range(range)?;
+                let series = context.snapshots.history(&address, since, token);
+                Ok(json!({
+                    "account": account,
+                    "address": address,
+                    "since": since,
+                    "series": series,
+                }))
+            }
+            "get_gas_price" => {
+                let blocks = params["blocks"]
+                    .as_u64()
+                    .unwrap_or(gas::DEFAULT_FEE_HISTORY_BLOCKS);
+                let report =
+                    gas::get_gas_price(&blockchain_service, &context.external_apis, blocks).await?;
+                Ok(json!(report))
+            }
+            "get_gas_report" => {
+                let account = params["account"].as_str();
+                let range = params["range"].as_str().unwrap_or("30d");
+                let since = chrono::Utc::now().timestamp() - activity::parse_range(range)?;
+                let report = gas::gas_report(&context.namespace.audit, since, account);
+                Ok(json!(report))
+            }
+            "get_pnl" => {
+                let account = params["account"].as_str().unwrap_or("").to_string();
+                let token = params["token"].as_str();
+                let report = pnl::get_pnl(
+                    &blockchain_service,
+                    &context.external_apis,
+                    &context.namespace,
+                    &account,
+                    token,
+                )
+                .await?;
+                Ok(json!(report))
+            }
+            "summarize_activity" => {
+                let account = params["account"].as_str().unwrap_or("").to_string();
+                let range = params["range"].as_str().unwrap_or("7d").to_string();
+                let summary = activity::summarize_activity(
+                    &blockchain_service,
+                    &context.external_apis,
+                    &context.namespace,
+                    &account,
+                    &range,
+                )
+                .await?;
+                Ok(json!(summary))
+            }
+            "get_transaction_history" => {
+                let account = params["account"].as_str().unwrap_or("").to_string();
+                let limit = params["limit"].as_u64().unwrap_or(20).min(200) as usize;
+                let scan_blocks = params["scan_blocks"]
+                    .as_u64()
+                    .unwrap_or(tx_history::DEFAULT_SCAN_BLOCKS);
+                let history = tx_history::get_transaction_history(
+                    &blockchain_service,
+                    &context.external_apis,
+                    &context.namespace,
+                    &account,
+                    params["range"].as_str(),
+                    limit,
+                    scan_blocks,
+                )
+                .await?;
+                Ok(json!(history))
+            }
+            "get_events" => {
+                let query = EventQuery {
+                    address: params["address"].as_str().unwrap_or("").to_string(),
+                    signature: params["event"].as_str().unwrap_or("").to_string(),
+                    topics: params["topics"].as_array().cloned().unwrap_or_default(),
+                    from_block: params["from_block"].as_u64(),
+                    to_block: params["to_block"].as_u64(),
+                    limit: params["limit"]
+                        .as_u64()
+                        .map(|limit| limit.min(1000) as usize)
+                        .unwrap_or(events::DEFAULT_EVENT_LIMIT),
+                };
+                let result = events::get_events(&blockchain_service, query).await?;
+                Ok(json!(result))
+            }
+            "convert_units" => {
+                let amount = params["amount"]
+                    .as_str()
+                    .map(|amount| amount.to_string())
+                    .or_else(|| params["amount"].as_u64().map(|amount| amount.to_string()))
+                    .ok_or_else(|| anyhow::anyhow!("Missing amount"))?;
+                let from = params["from"].as_str().unwrap_or("wei");
+                let to = params["to"].as_str().unwrap_or("ether");
+                let conversion = units::convert_units(
+                    &blockchain_service,
+                    &amount,
+                    from,
+                    to,
+                    params["token"].as_str(),
+                )
+                .await?;
+                Ok(json!(conversion))
+            }
+            "call_contract" => {
+                let call = Self::contract_call(&params)?;
+                Self::fetch_verified_abi(&context, &call.contract_address).await;
+                let result = blockchain_service
+                    .call_contract(&call, &|name| account_manager.resolve_address(name))
+                    .await?;
+                Ok(json!(result))
+            }
+            "write_contract" => {
+                let from = params["from"].as_str().unwrap_or("").to_string();
+                let call = Self::contract_call(&params)?;
+                Self::fetch_verified_abi(&context, &call.contract_address).await;
+                let value = match &params["value"] {
+                    Value::Null => "0".to_string(),
+                    Value::String(value) => value.clone(),
+                    other => other.to_string(),
+                };
+                let gas_limit = match &params["gas_limit"] {
+                    Value::Null => None,
+                    Value::String(limit) => Some(
+                        limit
+                            .parse::<u64>()
+                            .map_err(|_| anyhow::anyhow!("Invalid gas_limit '{}'", limit))?,
+                    ),
+                    other => Some(
+                        other
+                            .as_u64()
+                            .ok_or_else(|| anyhow::anyhow!("Invalid gas_limit {}", other))?,
+                    ),
+                };
+
+                let from_account = accounts
+                    .get(&from)
+                    .cloned()
+                    .ok_or_else(|| anyhow::anyhow!("Unknown account: {}", from))?;
+
+                // ETH sent with the call counts against the daily budget like a send
+                context
+                    .namespace
+                    .check_budget(&from, utils::parse_amount(&value, 18)?)?;
+
+                let contract_address = blockchain_service
+                    .resolve_address(&account_manager.resolve_address(&call.contract_address))
+                    .await?;
+                let screening = context
+                    .screener
+                    .screen(&contract_address, &context.external_apis)
+                    .await?;
+                let override_screening = params["override_screening"].as_bool().unwrap_or(false);
+                context
+                    .policy
+                    .check_screening(&screening, override_screening)?;
+
+                let fees = FeeOverrides::from_params(&params)?;
+                let resolve_name = |name: &str| account_manager.resolve_address(name);
+                if params["export"].as_bool().unwrap_or(false) {
+                    let signed = blockchain_service
+                        .export_contract_write(
+                            &from_account,
+                            &call,
+                            &value,
+                            gas_limit,
+                            &resolve_name,
+                            &fees,
+                        )
+                        .await?;
+                    return Ok(json!({
+                        "signed_transaction": signed,
+                        "note": "Signed but not broadcast. Submit the raw transaction with broadcast_raw_transaction or any eth_sendRawTransaction endpoint; anything this account sends first invalidates it",
+                    }));
+                }
+                let result = blockchain_service
+                    .write_contract(
+                        &from_account,
+                        &call,
+                        &value,
+                        gas_limit,
+                        &resolve_name,
+                        &fees,
+                    )
+                    .await?;
+                let mut result = json!(result);
+                result["function"] = json!(call.function_signature);
+                Ok(result)
+            }
+            "get_tx_status" => {
+                let hash = params["hash"]
+                    .as_str()
+                    .ok_or_else(|| anyhow::anyhow!("Missing hash"))?;
+                let mut status =
+                    json!(pending::transaction_status(&blockchain_service, hash).await?);
+                // Requests answered at submission report their outcome here
+                if let Some(request) = context.tx_tracker.for_hash(&context.namespace.name, hash) {
+                    status["request"] = json!(request);
+                }
+                Ok(status)
+            }
+            "list_pending_transactions" => {
+                let transactions: Vec<Value> = blockchain_service
+                    .pending_store()
+                    .map(|store| store.list())
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter(|transaction| transaction.chain_id == blockchain_service.chain_id())
+                    .filter_map(|transaction| {
+                        let account = account_manager.name_for_address(&transaction.from)?;
+                        let request = context
+                            .tx_tracker
+                            .for_hash(&context.namespace.name, &transaction.hash);
+                        Some(json!({
+                            "hash": transaction.hash,
+                            "account": account,
+                            "description": transaction.description,
+                            "nonce": transaction.nonce,
+                            "submitted_at": transaction.submitted_at,
+                            "request_id": request.map(|request| request.id),
+                        }))
+                    })
+                    .collect();
+                Ok(json!({
+                    "transactions": transactions,
+                    "requests": context.tx_tracker.in_progress(&context.namespace.name),
+                }))
+            }
+            "get_transaction" => {
+                let hash = params["hash"]
+                    .as_str()
+                    .ok_or_else(|| anyhow::anyhow!("Missing hash"))?;
+                let details =
+                    tx_inspect::get_transaction(&blockchain_service, &context.external_apis, hash)
+                        .await?;
+                Ok(json!(details))
+            }
+            "get_chain_info" => {
+                let mut info = json!(chain_info::get_chain_info(&blockchain_service).await?);
+                info["network"] = json!(
+                    context
+                        .namespace
+                        .network()
+                        .unwrap_or_else(|| context.networks.default_network().to_string())
+                );
+                Ok(info)
+            }
+            "get_block" => {
+                let block = match &params["block"] {
+                    Value::Number(number) => number.to_string(),
+                    Value::String(block) => block.clone(),
+                    _ => "latest".to_string(),
+                };
+                let limit = params["limit"]
+                    .as_u64()
+                    .map_or(chain_info::DEFAULT_BLOCK_TRANSACTIONS, |limit| {
+                        limit as usize
+                    });
+                let details = chain_info::get_block(&blockchain_service, &block, limit).await?;
+                Ok(json!(details))
+            }
+            "get_contract_source" => {
+                let contract = params["contract"]
+                    .as_str()
+                    .ok_or_else(|| anyhow::anyhow!("Missing contract"))?;
+                let include_source = params["include_source"].as_bool().unwrap_or(false);
+                let verified = calldata::verified_contract(
+                    &blockchain_service,
+                    &context.external_apis,
+                    &account_manager.resolve_address(contract),
+                )
+                .await?
+                .ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "{} isn't verified on Etherscan, or ETHERSCAN_API_KEY isn't set",
+                        contract
+                    )
+                })?;
+                let functions: Vec<String> = verified
+                    .abi
+                    .functions()
+                    .map(|function| {
+                        let mutability = format!("{:?}", function.state_mutability).to_lowercase();
+                        format!("{} {}", function.abi_signature(), mutability)
+                    })
+                    .collect();
+                Ok(json!({
+                    "address": verified.address,
+                    "name": verified.name,
+                    "compiler": verified.compiler,
+                    "implementation": verified.implementation,
+                    "functions": functions,
+                    "events": verified.abi.events().map(|event| event.abi_signature()).collect::<Vec<_>>(),
+                    "source_code": include_source.then(|| verified.source_code.clone()),
+                }))
+            }
+            "decode_calldata" => {
+                let data = params["data"]
+                    .as_str()
+                    .ok_or_else(|| anyhow::anyhow!("Missing data"))?;
+                let contract = params["contract_address"]
+                    .as_str()
+                    .map(|contract| account_manager.resolve_address(contract));
+                let decoded = calldata::decode_calldata(
+                    &blockchain_service,
+                    &context.external_apis,
+                    data,
+                    contract.as_deref(),
+                )
+                .await?;
+                Ok(json!(decoded))
+            }
+            "parse_payment_uri" => {
+                let uri = params["uri"]
+                    .as_str()
+                    .ok_or_else(|| anyhow::anyhow!("Missing uri"))?;
+                let mut request =
+                    payment_uri::parse_payment_uri(&blockchain_service, &context.networks, uri)
+                        .await?;
+                if let Some(from) = params["from"].as_str() {
+                    request.params["from"] = json!(from);
+                }
+
+                let mut result = json!(request);
+                result["note"] = json!(if request.on_current_network {
+                    "Nothing was sent. Confirm the recipient, asset and amount with the user, then call the method with these params"
+                } else {
+                    "Nothing was sent. The URI is for another chain: switch_network first (if it is configured), then confirm and call the method"
+                });
+                Ok(result)
+            }
+            "screen_address" => {
+                let address = params["address"].as_str().unwrap_or("").to_string();
+                let result = context
+                    .screener
+                    .screen(&address, &context.external_apis)
+                    .await?;
+                Ok(json!(result))
+            }
+            "check_contract" => {
+                let address = params["address"].as_str().unwrap_or("").to_string();
+                let result = blockchain_service.check_contract_deployed(&address).await?;
+                Ok(json!(result))
+            }
+            "search_web" => {
+                let query = params["query"].as_str().unwrap_or("").to_string();
+                let search_tool = tool_registry.get_tool("search_web")?;
+                let result = search_tool
+                    .execute(json!({"query": query}), &context)
+                    .await?;
+
+                Ok(result)
+            }
+            "get_token_price" => {
+                let token = params["token"].as_str().unwrap_or("").to_string();
+                let price_tool = tool_registry.get_tool("get_token_price")?;
+                let result = price_tool
+                    .execute(json!({"token": token}), &context)
+                    .await?;
+
+                Ok(result)
+            }
+            "search_docs" => {
+                let query = params["query"].as_str().unwrap_or("").to_string();
+                let limit = params["limit"].as_u64().unwrap_or(5) as usize;
+                let docs_tool = tool_registry.get_tool("search_docs")?;
+                let result = docs_tool
+                    .execute(json!({"query": query, "limit": limit}), &context)
+                    .await?;
+
+                Ok(result)
+            }
+            "get_document" => {
+                let id = params["id"].as_str().unwrap_or("").to_string();
+                let docs_tool = tool_registry.get_tool("get_docs")?;
+                let result = docs_tool.execute(json!({"id": id}), &context).await?;
+
+                Ok(result)
+            }
+            "index_status" => Ok(json!(context.rag_service.index_status().await)),
+            "list_supported_tokens" => {
+                let tokens = blockchain_service.get_supported_tokens();
+                let token_list: Vec<Value> = tokens
+                    .iter()
+                    .map(|token| {
+                        json!({
+                            "symbol": token.symbol,
+                            "name": token.name,
+                            "address": token.address,
+                            "decimals": token.decimals
+                        })
+                    })
+                    .collect();
+                // Tokens read from their contracts aren't vetted like configured ones
+                let discovered: Vec<Value> = blockchain_service
+                    .discovered_tokens()
+                    .into_iter()
+                    .map(|discovered| {
+                        json!({
+                            "symbol": discovered.token.symbol,
+                            "name": discovered.token.name,
+                            "address": discovered.token.address,
+                            "decimals": discovered.token.decimals,
+                            "discovered_at": discovered.discovered_at,
+                        })
+                    })
+                    .collect();
+
+                Ok(json!({"tokens": token_list, "discovered_tokens": discovered}))
+            }
+            "forget_discovered_tokens" => {
+                let token = params["token"].as_str();
+                let forgotten = blockchain_service.forget_discovered_tokens(token);
+                Ok(json!({"token": token, "forgotten": forgotten}))
+            }
+            "refresh_token_list" => {
+                let tokens = blockchain_service.refresh_token_list()?;
+                Ok(json!({
+                    "chain_id": blockchain_service.chain_id(),
+                    "source": blockchain_service.token_source(),
+                    "tokens": tokens,
+                }))
+            }
+            "swap_tokens" => {
                 let from_token = params["from_token"].as_str().unwrap_or("").to_string();
                 let to_token = params["to_token"].as_str().unwrap_or("").to_string();
                 let amount = params["amount"].as_str().unwrap_or("0").to_string();
                 let recipient = params["recipient"].as_str().unwrap_or("").to_string();
+                let acknowledge_risk = params["acknowledge_risk"].as_bool().unwrap_or(false);
+
+                // Swaps spend from and pay out to the signing account
+                let swapper = accounts
+                    .get(&recipient)
+                    .map(|account| account.address.clone())
+                    .unwrap_or_default();
+                let mut targets = vec![(swapper.clone(), None)];
+                for token in [&from_token, &to_token] {
+                    if !token.eq_ignore_ascii_case("eth") {
+                        targets.push((swapper.clone(), Some(token.clone())));
+                    }
+                }
+                let watched = blockchain_service.balances_before(&targets).await;
 
                 let swap_tool = tool_registry.get_tool("swap_tokens")?;
-                let result = swap_tool
+                let mut result = swap_tool
                     .execute(
                         json!({
                             "from_token": from_token,
                             "to_token": to_token,
                             "amount": amount,
-                            "recipient": recipient
+                            "recipient": recipient,
+                            "acknowledge_risk": acknowledge_risk,
+                            "accept_price_impact": params["accept_price_impact"],
+                            "dex": params["dex"],
+                            "simulate": params["simulate"]
                         }),
                         &context,
                     )
                     .await?;
 
+                let balance_changes = blockchain_service
+                    .refresh_balances(watched, result["block_number"].as_u64())
+                    .await;
+                result["balance_changes"] = json!(balance_changes);
+                Ok(result)
+            }
+            "get_portfolio" => {
+                let address = params["address"].as_str().unwrap_or("").to_string();
+                let discover_blocks = match params["discover_tokens"].as_bool().unwrap_or(false) {
+                    true => Some(
+                        params["discover_blocks"]
+                            .as_u64()
+                            .unwrap_or(portfolio::DEFAULT_DISCOVERY_BLOCKS),
+                    ),
+                    false => None,
+                };
+
+                let result = portfolio::get_portfolio(
+                    &blockchain_service,
+                    &context.external_apis,
+                    &context.streams,
+                    &account_manager.resolve_address(&address),
+                    discover_blocks,
+                )
+                .await?;
+                Ok(json!(result))
+            }
+            "consolidate_dust" => {
+                let account = params["account"].as_str().unwrap_or("");
+                let target = params["target"].as_str().unwrap_or("ETH");
+                let threshold = params["threshold_usd"]
+                    .as_f64()
+                    .unwrap_or(dust::DEFAULT_DUST_THRESHOLD_USD);
+                let discover_blocks = params["discover_tokens"]
+                    .as_bool()
+                    .unwrap_or(false)
+                    .then_some(portfolio::DEFAULT_DISCOVERY_BLOCKS);
+
+                // Planned again on confirmation, so the swaps use current balances
+                let plan = dust::plan_dust_consolidation(
+                    &blockchain_service,
+                    &context.external_apis,
+                    &context.streams,
+                    &account_manager.resolve_address(account),
+                    target,
+                    threshold,
+                    discover_blocks,
+                )
+                .await?;
+                if plan.swaps.is_empty() {
+                    return Ok(json!({
+                        "plan": plan,
+                        "executed": false,
+                        "note": "No dust worth swapping was found",
+                    }));
+                }
+                if params["confirm"].as_bool().unwrap_or(false) {
+                    Ok(Self::execute_dust_plan(account, plan, tool_registry, context).await)
+                } else {
+                    Ok(json!({
+                        "plan": plan,
+                        "executed": false,
+                        "note": "Nothing was sent. Show these swaps and their totals to the user and call consolidate_dust again with confirm set to true once they agree.",
+                    }))
+                }
+            }
+            "get_lp_performance" => {
+                let address = params["address"].as_str().unwrap_or("").to_string();
+                let lookback = params["lookback_blocks"]
+                    .as_u64()
+                    .unwrap_or(lp::DEFAULT_LP_LOOKBACK_BLOCKS);
+                let report = lp::get_lp_performance(
+                    &blockchain_service,
+                    &context.external_apis,
+                    &account_manager.resolve_address(&address),
+                    lookback,
+                )
+                .await?;
+                Ok(json!(report))
+            }
+            "find_yield" => {
+                let chain = match params["chain"].as_str() {
+                    Some(chain) => chain.to_string(),
+                    None => yields::llama_chain(blockchain_service.chain_id())
+                        .ok_or_else(|| {
+                            anyhow::anyhow!(
+                                "No yield data for chain {}; pass chain explicitly",
+                                blockchain_service.chain_id()
+                            )
+                        })?
+                        .to_string(),
+                };
+                let query = YieldQuery {
+                    chain,
+                    asset: params["asset"].as_str().map(str::to_string),
+                    min_tvl_usd: params["min_tvl_usd"].as_f64(),
+                    include_unaudited: params["include_unaudited"].as_bool().unwrap_or(false),
+                    stablecoins_only: params["stablecoins_only"].as_bool().unwrap_or(false),
+                    single_asset_only: params["single_asset_only"].as_bool().unwrap_or(false),
+                    limit: params["limit"]
+                        .as_u64()
+                        .map(|limit| limit as usize)
+                        .unwrap_or(yields::DEFAULT_YIELD_LIMIT),
+                };
+                let result =
+                    yields::find_yield(&context.external_apis, &YieldConfig::from_env(), &query)
+                        .await?;
+                Ok(json!(result))
+            }
+            "get_protocol_health" => {
+                let protocol = params["protocol"]
+                    .as_str()
+                    .filter(|protocol| !protocol.trim().is_empty())
+                    .ok_or_else(|| anyhow::anyhow!("protocol is required"))?;
+                let health =
+                    protocol_health::get_protocol_health(&context.external_apis, protocol).await?;
+                Ok(json!(health))
+            }
+            "list_proposals" | "get_proposal" | "get_voting_power" | "cast_vote" => {
+                let governor = params["governor"]
+                    .as_str()
+                    .ok_or_else(|| anyhow::anyhow!("governor is required"))?;
+                let config = GovernanceConfig::from_env()
+                    .resolve(governor, blockchain_service.chain_id())?;
+                let proposal_id = params["proposal_id"].as_str();
+
+                match method {
+                    "list_proposals" => {
+                        let limit = params["limit"]
+                            .as_u64()
+                            .map(|limit| limit as usize)
+                            .unwrap_or(governance::DEFAULT_PROPOSAL_LIMIT);
+                        let include_closed = params["include_closed"].as_bool().unwrap_or(false);
+                        Ok(json!(
+                            governance::list_proposals(
+                                &blockchain_service,
+                                config,
+                                include_closed,
+                                limit
+                            )
+                            .await?
+                        ))
+                    }
+                    "get_proposal" => {
+                        let proposal_id = proposal_id
+                            .ok_or_else(|| anyhow::anyhow!("proposal_id is required"))?;
+                        Ok(json!(
+                            governance::get_proposal(&blockchain_service, config, proposal_id)
+                                .await?
+                        ))
+                    }
+                    "get_voting_power" => {
+                        let account = params["account"]
+                            .as_str()
+                            .ok_or_else(|| anyhow::anyhow!("account is required"))?;
+                        let address = blockchain_service
+                            .resolve_address(&account_manager.resolve_address(account))
+                            .await?;
+                        Ok(json!(
+                            governance::get_voting_power(
+                                &blockchain_service,
+                                config,
+                                &address,
+                                proposal_id
+                            )
+                            .await?
+                        ))
+                    }
+                    _ => {
+                        let from = params["from"].as_str().unwrap_or("").to_string();
+                        let from_account = accounts
+                            .get(&from)
+                            .cloned()
+                            .ok_or_else(|| anyhow::anyhow!("Unknown account: {}", from))?;
+                        let proposal_id = proposal_id
+                            .ok_or_else(|| anyhow::anyhow!("proposal_id is required"))?;
+                        let support = params["support"]
+                            .as_str()
+                            .ok_or_else(|| anyhow::anyhow!("support is required"))?;
+                        let reason = params["reason"].as_str().unwrap_or("");
+                        let fees = FeeOverrides::from_params(&params)?;
+                        Ok(json!(
+                            governance::cast_vote(
+                                &blockchain_service,
+                                config,
+                                &from_account,
+                                proposal_id,
+                                support,
+                                reason,
+                                &fees
+                            )
+                            .await?
+                        ))
+                    }
+                }
+            }
+            "list_snapshot_spaces" => {
+                let ids: Vec<String> = params["spaces"]
+                    .as_array()
+                    .map(|ids| {
+                        ids.iter()
+                            .filter_map(|id| id.as_str().map(str::to_string))
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                let follower = match params["account"].as_str() {
+                    Some(account) => Some(
+                        blockchain_service
+                            .resolve_address(&account_manager.resolve_address(account))
+                            .await?,
+                    ),
+                    None => None,
+                };
+                Ok(json!(
+                    snapshot_voting::list_spaces(&context.external_apis, &ids, follower.as_deref())
+                        .await?
+                ))
+            }
+            "list_snapshot_proposals" => {
+                let space = params["space"]
+                    .as_str()
+                    .ok_or_else(|| anyhow::anyhow!("space is required"))?;
+                let state = params["state"].as_str().or(Some("active"));
+                let limit = params["limit"]
+                    .as_u64()
+                    .unwrap_or(snapshot_voting::DEFAULT_SNAPSHOT_LIMIT);
+                Ok(json!(
+                    snapshot_voting::list_proposals(&context.external_apis, space, state, limit)
+                        .await?
+                ))
+            }
+            "get_snapshot_proposal" => {
+                let id = params["proposal_id"]
+                    .as_str()
+                    .ok_or_else(|| anyhow::anyhow!("proposal_id is required"))?;
+                Ok(json!(
+                    snapshot_voting::get_proposal(&context.external_apis, id).await?
+                ))
+            }
+            "get_snapshot_voting_power" => {
+                let space = params["space"]
+                    .as_str()
+                    .ok_or_else(|| anyhow::anyhow!("space is required"))?;
+                let account = params["account"]
+                    .as_str()
+                    .ok_or_else(|| anyhow::anyhow!("account is required"))?;
+                let address = blockchain_service
+                    .resolve_address(&account_manager.resolve_address(account))
+                    .await?;
+                Ok(json!(
+                    snapshot_voting::get_voting_power(
+                        &context.external_apis,
+                        space,
+                        params["proposal_id"].as_str(),
+                        &address
+                    )
+                    .await?
+                ))
+            }
+            "cast_snapshot_vote" => {
+                let from = params["from"].as_str().unwrap_or("").to_string();
+                let from_account = accounts
+                    .get(&from)
+                    .cloned()
+                    .ok_or_else(|| anyhow::anyhow!("Unknown account: {}", from))?;
+                let proposal_id = params["proposal_id"]
+                    .as_str()
+                    .ok_or_else(|| anyhow::anyhow!("proposal_id is required"))?;
+                let choice = match &params["choice"] {
+                    Value::String(choice) => choice.clone(),
+                    Value::Number(index) => index.to_string(),
+                    _ => return Err(anyhow::anyhow!("choice is required")),
+                };
+                let reason = params["reason"].as_str().unwrap_or("");
+                Ok(json!(
+                    snapshot_voting::cast_vote(
+                        &context.external_apis,
+                        &from_account,
+                        proposal_id,
+                        &choice,
+                        reason
+                    )
+                    .await?
+                ))
+            }
+            "check_claims" => {
+                let address = params["address"]
+                    .as_str()
+                    .ok_or_else(|| anyhow::anyhow!("address is required"))?;
+                let address = blockchain_service
+                    .resolve_address(&account_manager.resolve_address(address))
+                    .await?;
+                Ok(json!(
+                    claims::check_claims(
+                        &blockchain_service,
+                        &context.external_apis,
+                        &ClaimsConfig::from_env(),
+                        &address
+                    )
+                    .await?
+                ))
+            }
+            "claim_airdrop" => {
+                let from = params["from"].as_str().unwrap_or("").to_string();
+                let from_account = accounts
+                    .get(&from)
+                    .cloned()
+                    .ok_or_else(|| anyhow::anyhow!("Unknown account: {}", from))?;
+                let source = params["source"]
+                    .as_str()
+                    .ok_or_else(|| anyhow::anyhow!("source is required"))?;
+                let fees = FeeOverrides::from_params(&params)?;
+                Ok(json!(
+                    claims::claim(
+                        &blockchain_service,
+                        &context.external_apis,
+                        &ClaimsConfig::from_env(),
+                        &from_account,
+                        source,
+                        &fees
+                    )
+                    .await?
+                ))
+            }
+            "list_token_migrations" => {
+                let address = match params["address"].as_str() {
+                    Some(address) => Some(
+                        blockchain_service
+                            .resolve_address(&account_manager.resolve_address(address))
+                            .await?,
+                    ),
+                    None => None,
+                };
+                Ok(json!({
+                    "migrations": migrations::list_migrations(
+                        &blockchain_service,
+                        &MigrationsConfig::from_env(),
+                        address.as_deref(),
+                    )
+                    .await?
+                }))
+            }
+            "migrate_token" => {
+                let from = params["from"].as_str().unwrap_or("").to_string();
+                let from_account = accounts
+                    .get(&from)
+                    .cloned()
+                    .ok_or_else(|| anyhow::anyhow!("Unknown account: {}", from))?;
+                let token = params["token"]
+                    .as_str()
+                    .ok_or_else(|| anyhow::anyhow!("token is required"))?;
+                let amount = params["amount"].as_str();
+                let config = MigrationsConfig::from_env();
+
+                if !params["confirm"].as_bool().unwrap_or(false) {
+                    let plan = migrations::plan_migration(
+                        &blockchain_service,
+                        &config,
+                        &from_account,
+                        token,
+                        amount,
+                    )
+                    .await?;
+                    return Ok(json!({
+                        "plan": plan,
+                        "executed": false,
+                        "note": "Nothing was sent. Show these steps to the user and call migrate_token again with confirm set to true once they agree.",
+                    }));
+                }
+                let fees = FeeOverrides::from_params(&params)?;
+                Ok(json!(
+                    migrations::migrate(
+                        &blockchain_service,
+                        &config,
+                        &from_account,
+                        token,
+                        amount,
+                        &fees
+                    )
+                    .await?
+                ))
+            }
+            "get_vesting" => {
+                let contract = params["contract"]
+                    .as_str()
+                    .ok_or_else(|| anyhow::anyhow!("contract is required"))?;
+                let contract = blockchain_service
+                    .resolve_address(&account_manager.resolve_address(contract))
+                    .await?;
+                let holder = match params["address"].as_str() {
+                    Some(address) => Some(
+                        blockchain_service
+                            .resolve_address(&account_manager.resolve_address(address))
+                            .await?,
+                    ),
+                    None => None,
+                };
+                let lookback = params["lookback_blocks"]
+                    .as_u64()
+                    .unwrap_or(vesting::DEFAULT_VESTING_LOOKBACK_BLOCKS);
+                let schedules = vesting::get_vesting(
+                    &blockchain_service,
+                    &contract,
+                    holder.as_deref(),
+                    params["token"].as_str(),
+                    params["stream_id"].as_str(),
+                    lookback,
+                )
+                .await?;
+                Ok(json!({"schedules": schedules}))
+            }
+            "release_vested" => {
+                let from = params["from"].as_str().unwrap_or("").to_string();
+                let from_account = accounts
+                    .get(&from)
+                    .cloned()
+                    .ok_or_else(|| anyhow::anyhow!("Unknown account: {}", from))?;
+                let contract = params["contract"]
+                    .as_str()
+                    .ok_or_else(|| anyhow::anyhow!("contract is required"))?;
+                let contract = blockchain_service
+                    .resolve_address(&account_manager.resolve_address(contract))
+                    .await?;
+                let fees = FeeOverrides::from_params(&params)?;
+                Ok(json!(
+                    vesting::release_vested(
+                        &blockchain_service,
+                        &from_account,
+                        &contract,
+                        params["token"].as_str(),
+                        params["stream_id"].as_str(),
+                        &fees
+                    )
+                    .await?
+                ))
+            }
+            "create_stream" => {
+                let from = params["from"].as_str().unwrap_or("").to_string();
+                let from_account = accounts
+                    .get(&from)
+                    .cloned()
+                    .ok_or_else(|| anyhow::anyhow!("Unknown account: {}", from))?;
+                let recipient = params["recipient"]
+                    .as_str()
+                    .ok_or_else(|| anyhow::anyhow!("recipient is required"))?;
+                let recipient = blockchain_service
+                    .resolve_address(&account_manager.resolve_address(recipient))
+                    .await?;
+                let screening = context
+                    .screener
+                    .screen(&recipient, &context.external_apis)
+                    .await?;
+                context.policy.check_screening(
+                    &screening,
+                    params["override_screening"].as_bool().unwrap_or(false),
+                )?;
+                let request = StreamRequest {
+                    protocol: params["protocol"].as_str().unwrap_or("sablier").parse()?,
+                    recipient: recipient.parse()?,
+                    token: params["token"]
+                        .as_str()
+                        .ok_or_else(|| anyhow::anyhow!("token is required"))?
+                        .to_string(),
+                    amount: params["amount"]
+                        .as_str()
+                        .ok_or_else(|| anyhow::anyhow!("amount is required"))?
+                        .to_string(),
+                    duration_secs: params["duration_seconds"]
+                        .as_u64()
+                        .ok_or_else(|| anyhow::anyhow!("duration_seconds is required"))?,
+                    cliff_secs: params["cliff_seconds"].as_u64().unwrap_or(0),
+                    cancelable: params["cancelable"].as_bool().unwrap_or(true),
+                };
+                let fees = FeeOverrides::from_params(&params)?;
+                Ok(json!(
+                    streams::create_stream(
+                        &blockchain_service,
+                        &context.streams,
+                        &from_account,
+                        &request,
+                        &fees
+                    )
+                    .await?
+                ))
+            }
+            "add_liquidity" | "remove_liquidity" => {
+                let from = params["from"].as_str().unwrap_or("").to_string();
+                let from_account = accounts
+                    .get(&from)
+                    .cloned()
+                    .ok_or_else(|| anyhow::anyhow!("Unknown account: {}", from))?;
+                let token_a = params["token_a"]
+                    .as_str()
+                    .ok_or_else(|| anyhow::anyhow!("token_a is required"))?;
+                let token_b = params["token_b"]
+                    .as_str()
+                    .ok_or_else(|| anyhow::anyhow!("token_b is required"))?;
+                let slippage = params["slippage"]
+                    .as_f64()
+                    .unwrap_or(liquidity::DEFAULT_LIQUIDITY_SLIPPAGE);
+                let fees = FeeOverrides::from_params(&params)?;
+
+                let result = if method == "add_liquidity" {
+                    let request = AddLiquidityRequest {
+                        token_a: token_a.to_string(),
+                        amount_a: params["amount_a"]
+                            .as_str()
+                            .ok_or_else(|| anyhow::anyhow!("amount_a is required"))?
+                            .to_string(),
+                        token_b: token_b.to_string(),
+                        amount_b: params["amount_b"].as_str().map(|s| s.to_string()),
+                        slippage_percent: slippage,
+                    };
+                    liquidity::add_liquidity(&blockchain_service, &from_account, &request, &fees)
+                        .await?
+                } else {
+                    liquidity::remove_liquidity(
+                        &blockchain_service,
+                        &from_account,
+                        token_a,
+                        token_b,
+                        params["percent"].as_f64().unwrap_or(100.0),
+                        slippage,
+                        &fees,
+                    )
+                    .await?
+                };
+                Ok(json!(result))
+            }
+            "aave_supply" | "aave_withdraw" | "aave_borrow" | "aave_repay" => {
+                let from = params["from"].as_str().unwrap_or("").to_string();
+                let from_account = accounts
+                    .get(&from)
+                    .cloned()
+                    .ok_or_else(|| anyhow::anyhow!("Unknown account: {}", from))?;
+                let token = params["token"]
+                    .as_str()
+                    .ok_or_else(|| anyhow::anyhow!("token is required"))?;
+                let action = match method {
+                    "aave_supply" => LendingAction::Supply,
+                    "aave_withdraw" => LendingAction::Withdraw,
+                    "aave_borrow" => LendingAction::Borrow,
+                    _ => LendingAction::Repay,
+                };
+                let fees = FeeOverrides::from_params(&params)?;
+                Ok(json!(
+                    aave::execute(
+                        &blockchain_service,
+                        &from_account,
+                        action,
+                        token,
+                        params["amount"].as_str(),
+                        &fees,
+                    )
+                    .await?
+                ))
+            }
+            "cancel_stream" => {
+                let from = params["from"].as_str().unwrap_or("").to_string();
+                let from_account = accounts
+                    .get(&from)
+                    .cloned()
+                    .ok_or_else(|| anyhow::anyhow!("Unknown account: {}", from))?;
+                let recipient = match params["recipient"].as_str() {
+                    Some(recipient) => Some(
+                        blockchain_service
+                            .resolve_address(&account_manager.resolve_address(recipient))
+                            .await?
+                            .parse()?,
+                    ),
+                    None => None,
+                };
+                let fees = FeeOverrides::from_params(&params)?;
+                Ok(json!(
+                    streams::cancel_stream(
+                        &blockchain_service,
+                        &from_account,
+                        params["protocol"].as_str().unwrap_or("sablier").parse()?,
+                        params["stream_id"].as_str(),
+                        params["token"].as_str(),
+                        recipient,
+                        &fees
+                    )
+                    .await?
+                ))
+            }
+            "get_streams" => {
+                let address = match params["address"].as_str() {
+                    Some(address) => Some(
+                        blockchain_service
+                            .resolve_address(&account_manager.resolve_address(address))
+                            .await?
+                            .parse()?,
+                    ),
+                    None => None,
+                };
+                let streams = streams::get_streams(
+                    &blockchain_service,
+                    &context.streams,
+                    address,
+                    params["stream_id"].as_str(),
+                )
+                .await?;
+                Ok(json!({"streams": streams}))
+            }
+            "get_allowance" => {
+                let owner = params["owner"].as_str().unwrap_or("").to_string();
+                let token = params["token"].as_str().unwrap_or("").to_string();
+                let spender = params["spender"].as_str().unwrap_or("uniswap_v2");
+                let owner_address = account_manager.resolve_address(&owner);
+                let result = blockchain_service
+                    .get_allowance(&owner_address, &token, spender)
+                    .await?;
+                Ok(json!(result))
+            }
+            "approve_token" | "revoke_approval" => {
+                let from = params["from"].as_str().unwrap_or("").to_string();
+                let token = params["token"]
+                    .as_str()
+                    .ok_or_else(|| anyhow::anyhow!("Missing token"))?;
+                let spender = params["spender"]
+                    .as_str()
+                    .ok_or_else(|| anyhow::anyhow!("Missing spender"))?;
+                let from_account = accounts
+                    .get(&from)
+                    .cloned()
+                    .ok_or_else(|| anyhow::anyhow!("Unknown account: {}", from))?;
+
+                let token_info = blockchain_service.resolve_token(token).await?;
+                let (spender_address, spender_name) = blockchain_service
+                    .resolve_spender(&account_manager.resolve_address(spender))
+                    .await?;
+                let (amount, adjustment) = if method == "revoke_approval" {
+                    (U256::zero(), None)
+                } else {
+                    let requested = params["amount"]
+                        .as_str()
+                        .ok_or_else(|| anyhow::anyhow!("Missing amount"))?;
+                    let requested: U256 = if requested == "unlimited" {
+                        U256::MAX
+                    } else {
+                        utils::parse_amount(requested, token_info.decimals)?
+                    };
+
+                    // New spenders are screened like recipients
+                    let screening = context
+                        .screener
+                        .screen(&format!("{:?}", spender_address), &context.external_apis)
+                        .await?;
+                    let override_screening =
+                        params["override_screening"].as_bool().unwrap_or(false);
+                    context
+                        .policy
+                        .check_screening(&screening, override_screening)?;
+
+                    // Tokens outside the registry get the same safety check as a swap
+                    let acknowledge_risk = params["acknowledge_risk"].as_bool().unwrap_or(false);
+                    if !blockchain_service.is_registered_token(token) {
+                        let report = token_safety::assess_token(
+                            &blockchain_service,
+                            &context.external_apis,
+                            token,
+                        )
+                        .await?;
+                        if report.risk_level != "low" && !acknowledge_risk {
+                            return Err(anyhow::anyhow!(
+                                "Token safety check requires confirmation: {}. Confirm with the user and retry with acknowledge_risk set to proceed",
+                                report.summary()
+                            ));
+                        }
+                    }
+
+                    // The multiplier cap applies to the amount the spender is expected to move
+                    let spend_amount = params["spend_amount"].as_str();
+                    let spend: U256 = match spend_amount {
+                        Some(spend) => utils::parse_amount(spend, token_info.decimals)?,
+                        None => requested,
+                    };
+                    let price = prices::usd_price(
+                        &blockchain_service,
+                        &context.external_apis,
+                        &token_info.address,
+                    )
+                    .await;
+                    let (capped, adjustment) = context.policy.cap_approval(
+                        requested,
+                        spend,
+                        token_info.decimals,
+                        price,
+                    )?;
+                    let override_cap = params["override_approval_cap"].as_bool().unwrap_or(false);
+                    match adjustment {
+                        Some(adjustment)
+                            if context
+                                .policy
+                                .allows_uncapped_approval(&adjustment, override_cap) =>
+                        {
+                            (requested, None)
+                        }
+                        Some(adjustment) if spend_amount.is_none() => {
+                            return Err(anyhow::anyhow!(
+                                "Approving {} {} is above the approval policy cap ({}). Pass spend_amount to approve a capped amount{}",
+                                adjustment.requested,
+                                token_info.symbol,
+                                adjustment.reason,
+                                if context.policy.config().allow_approval_cap_override {
+                                    ", or confirm with the user and retry with override_approval_cap set"
+                                } else {
+                                    ""
+                                }
+                            ));
+                        }
+                        adjustment => (capped, adjustment),
+                    }
+                };
+
+                let fees = FeeOverrides::from_params(&params)?;
+                let result = blockchain_service
+                    .set_allowance(&from_account, &token_info, spender_address, amount, &fees)
+                    .await?;
+                Ok(json!({
+                    "transaction": result,
+                    "token": token_info.symbol,
+                    "spender": format!("{:?}", spender_address),
+                    "spender_name": spender_name,
+                    "allowance": if amount == U256::MAX {
+                        "unlimited".to_string()
+                    } else {
+                        format_units(amount, token_info.decimals as u32)?
+                    },
+                    "adjustment": adjustment,
+                }))
+            }
+            "preview_approval" => {
+                let token = params["token"].as_str().unwrap_or("").to_string();
+                let swap_amount = params["swap_amount"].as_str().unwrap_or("0").to_string();
+                let requested = params["amount"].as_str().unwrap_or("unlimited").to_string();
+
+                let token_info = blockchain_service.resolve_token(&token).await?;
+                let swap_units: U256 = utils::parse_amount(&swap_amount, token_info.decimals)?;
+                let requested_units: U256 = if requested == "unlimited" {
+                    U256::MAX
+                } else {
+                    utils::parse_amount(&requested, token_info.decimals)?
+                };
+                let price = prices::usd_price(
+                    &blockchain_service,
+                    &context.external_apis,
+                    &token_info.address,
+                )
+                .await;
+
+                let (approved, adjustment) = context.policy.cap_approval(
+                    requested_units,
+                    swap_units,
+                    token_info.decimals,
+                    price,
+                )?;
+
+                Ok(json!({
+                    "token": token_info.symbol,
+                    "requested": requested,
+                    "approved": format_units(approved, token_info.decimals as u32)?,
+                    "adjustment": adjustment
+                }))
+            }
+            "get_smart_account" => {
+                let owner = params["owner"]
+                    .as_str()
+                    .ok_or_else(|| anyhow::anyhow!("owner is required"))?;
+                let owner = blockchain_service
+                    .resolve_address(&account_manager.resolve_address(owner))
+                    .await?
+                    .parse()?;
+                let info = smart_accounts::get_smart_account(
+                    &blockchain_service,
+                    &SmartAccountConfig::from_env()?,
+                    owner,
+                    Self::account_salt(&params)?,
+                )
+                .await?;
+                Ok(json!(info))
+            }
+            "deploy_smart_account" => {
+                let owner = params["owner"].as_str().unwrap_or("").to_string();
+                let owner_account = accounts
+                    .get(&owner)
+                    .cloned()
+                    .ok_or_else(|| anyhow::anyhow!("Unknown account: {}", owner))?;
+                let fees = FeeOverrides::from_params(&params)?;
+                let (account, transaction) = smart_accounts::deploy_smart_account(
+                    &blockchain_service,
+                    &SmartAccountConfig::from_env()?,
+                    &owner_account,
+                    Self::account_salt(&params)?,
+                    &fees,
+                )
+                .await?;
+                Ok(json!({
+                    "account": account,
+                    "transaction": transaction
+                }))
+            }
+            "estimate_user_operation" => {
+                let owner = params["owner"]
+                    .as_str()
+                    .ok_or_else(|| anyhow::anyhow!("owner is required"))?;
+                let owner = blockchain_service
+                    .resolve_address(&account_manager.resolve_address(owner))
+                    .await?
+                    .parse()?;
+                let fees = FeeOverrides::from_params(&params)?;
+                let estimate = smart_accounts::estimate_user_operation(
+                    &blockchain_service,
+                    &SmartAccountConfig::from_env()?,
+                    owner,
+                    Self::account_salt(&params)?,
+                    &Self::smart_account_call(&params)?,
+                    &|name| account_manager.resolve_address(name),
+                    &fees,
+                )
+                .await?;
+                Ok(json!(estimate))
+            }
+            "send_user_operation" => {
+                let owner = params["owner"].as_str().unwrap_or("").to_string();
+                let owner_account = accounts
+                    .get(&owner)
+                    .cloned()
+                    .ok_or_else(|| anyhow::anyhow!("Unknown account: {}", owner))?;
+                let call = Self::smart_account_call(&params)?;
+                let salt = Self::account_salt(&params)?;
+
+                // The target receives the call and any ETH sent with it, so it's screened
+                // like a send_eth recipient
+                let target = blockchain_service
+                    .resolve_address(&account_manager.resolve_address(&call.target))
+                    .await?;
+                let screening = context
+                    .screener
+                    .screen(&target, &context.external_apis)
+                    .await?;
+                let override_screening = params["override_screening"].as_bool().unwrap_or(false);
+                context
+                    .policy
+                    .check_screening(&screening, override_screening)?;
+
+                // Sent without the user's confirmation, so it must stay inside the scope
+                // they granted. While a key is active for the smart account, leaving
+                // session_key out doesn't lift its scope.
+                let keys = match params["session_key"].as_str() {
+                    Some(id) => vec![
+                        context
+                            .session_keys
+                            .get(&context.namespace.name, id)
+                            .ok_or_else(|| anyhow::anyhow!("No session key {}", id))?,
+                    ],
+                    None => context.session_keys.for_smart_account(
+                        &context.namespace.name,
+                        &owner,
+                        salt,
+                    ),
+                };
+                let mut session_key = None;
+                let mut reasons = Vec::new();
+                for key in &keys {
+                    match key.out_of_scope(&owner, salt, target.parse()?, &call.value)? {
+                        Some(reason) => reasons.push(format!("session key {}: {}", key.id, reason)),
+                        None => {
+                            session_key = Some(key.id.clone());
+                            break;
+                        }
+                    }
+                }
+                if session_key.is_none() && !reasons.is_empty() {
+                    return Err(anyhow::anyhow!(
+                        "Outside the granted scope ({}). Operations outside it need the user to revoke the session key first",
+                        reasons.join("; ")
+                    ));
+                }
+
+                // The owner controls the smart account, so its ETH counts against the
+                // owner's daily budget
+                context
+                    .namespace
+                    .check_budget(&owner, utils::parse_amount(&call.value, 18)?)?;
+
+                let fees = FeeOverrides::from_params(&params)?;
+                let result = smart_accounts::send_user_operation(
+                    &blockchain_service,
+                    &SmartAccountConfig::from_env()?,
+                    &owner_account,
+                    salt,
+                    &call,
+                    &|name| account_manager.resolve_address(name),
+                    &fees,
+                )
+                .await?;
+                let mut result = json!(result);
+                if let Some(id) = session_key {
+                    result["session_key"] = json!(id);
+                }
+                Ok(result)
+            }
+            "grant_session_key" => {
+                let owner = params["owner"].as_str().unwrap_or("").to_string();
+                let owner_account = accounts
+                    .get(&owner)
+                    .cloned()
+                    .ok_or_else(|| anyhow::anyhow!("Unknown account: {}", owner))?;
+                let salt = Self::account_salt(&params)?;
+                let Some(targets) = params["allowed_targets"].as_array() else {
+                    return Err(anyhow::anyhow!("allowed_targets must be an array"));
+                };
+                let mut allowed_targets = Vec::new();
+                for target in targets {
+                    let target = target
+                        .as_str()
+                        .ok_or_else(|| anyhow::anyhow!("allowed_targets must be strings"))?;
+                    allowed_targets.push(
+                        blockchain_service
+                            .resolve_address(&account_manager.resolve_address(target))
+                            .await?
+                            .parse()?,
+                    );
+                }
+                let now = chrono::Utc::now().timestamp();
+                let expires_at = match scheduler::timestamp_param(&params["expires_at"])? {
+                    Some(expires_at) => expires_at,
+                    None => params["expires_in_seconds"]
+                        .as_i64()
+                        .map(|seconds| now + seconds)
+                        .ok_or_else(|| {
+                            anyhow::anyhow!("expires_at or expires_in_seconds is required")
+                        })?,
+                };
+
+                let account = smart_accounts::get_smart_account(
+                    &blockchain_service,
+                    &SmartAccountConfig::from_env()?,
+                    owner_account.address.parse()?,
+                    salt,
+                )
+                .await?;
+                let key = SessionKey::new(
+                    &owner,
+                    account.address.parse()?,
+                    salt,
+                    &allowed_targets,
+                    params["max_value"].as_str().unwrap_or("0"),
+                    expires_at,
+                )?;
+                context
+                    .session_keys
+                    .grant(&context.namespace.name, key.clone())?;
+                Ok(json!({"session_key": key}))
+            }
+            "list_session_keys" => {
+                let mut keys = context.session_keys.list(&context.namespace.name);
+                if let Some(owner) = params["owner"].as_str() {
+                    keys.retain(|key| key.owner == owner);
+                }
+                Ok(json!({"session_keys": keys}))
+            }
+            "pair_wallet" => {
+                let name = params["name"]
+                    .as_str()
+                    .ok_or_else(|| anyhow::anyhow!("Missing name"))?;
+                if !sessions::is_valid_name(name) {
+                    return Err(anyhow::anyhow!(
+                        "Invalid wallet name '{}': use letters, digits and underscores, starting with a letter",
+                        name
+                    ));
+                }
+                if account_manager.get(name).is_some() {
+                    return Err(anyhow::anyhow!("{} is already an account name", name));
+                }
+                if context
+                    .wallets
+                    .get(&context.namespace.name, name)
+                    .is_some_and(|wallet| wallet.is_connected())
+                {
+                    return Err(anyhow::anyhow!(
+                        "Wallet {} is already paired; disconnect_wallet first to pair it again",
+                        name
+                    ));
+                }
+                let chain_ids: Vec<u64> = context
+                    .networks
+                    .networks()
+                    .iter()
+                    .map(|network| network.chain_id)
+                    .collect();
+                let pairing = walletconnect::pair_wallet(
+                    &WalletConnectConfig::from_env()?,
+                    context.wallets.clone(),
+                    &context.namespace.name,
+                    name,
+                    &chain_ids,
+                )
+                .await?;
+                Ok(json!({
+                    "uri": pairing.uri,
+                    "wallet": pairing.wallet.redacted(),
+                    "note": "Open the URI in the wallet (paste it, or show it as a QR code) and approve the connection there within 5 minutes. list_wallets shows when it is connected.",
+                }))
+            }
+            "list_wallets" => {
+                let wallets: Vec<_> = context
+                    .wallets
+                    .list(&context.namespace.name)
+                    .iter()
+                    .map(|wallet| wallet.redacted())
+                    .collect();
+                Ok(json!({"wallets": wallets}))
+            }
+            "send_with_wallet" => {
+                let name = params["wallet"].as_str().unwrap_or("");
+                let wallet = context
+                    .wallets
+                    .get(&context.namespace.name, name)
+                    .ok_or_else(|| anyhow::anyhow!("No paired wallet named {}", name))?;
+                let address = wallet
+                    .address
+                    .clone()
+                    .ok_or_else(|| anyhow::anyhow!("Wallet {} is not connected", name))?;
+                let request = Self::unsigned_request(&params)?;
+                // Contract calls are screened on the contract, like write_contract
+                let recipient = match &request {
+                    UnsignedRequest::SendEth { to, .. } | UnsignedRequest::SendErc20 { to, .. } => {
+                        to
+                    }
+                    UnsignedRequest::ContractCall { call, .. } => &call.contract_address,
+                };
+                let recipient = blockchain_service
+                    .resolve_address(&account_manager.resolve_address(recipient))
+                    .await?;
+                let screening = context
+                    .screener
+                    .screen(&recipient, &context.external_apis)
+                    .await?;
+                context.policy.check_screening(
+                    &screening,
+                    params["override_screening"].as_bool().unwrap_or(false),
+                )?;
+
+                let fees = FeeOverrides::from_params(&params)?;
+                let unsigned = blockchain_service
+                    .prepare_unsigned(
+                        &address,
+                        request,
+                        &|name| account_manager.resolve_address(name),
+                        &fees,
+                    )
+                    .await?;
+                let hash = walletconnect::send_transaction(
+                    &WalletConnectConfig::from_env()?,
+                    &wallet,
+                    blockchain_service.chain_id(),
+                    &unsigned.transaction,
+                )
+                .await?;
+                Ok(json!({
+                    "hash": hash,
+                    "status": "submitted",
+                    "wallet": name,
+                    "from": address,
+                    "note": "Approved and sent from the wallet. Check it with get_tx_status.",
+                }))
+            }
+            "disconnect_wallet" => {
+                let name = params["name"].as_str().unwrap_or("");
+                let wallet = context
+                    .wallets
+                    .remove(&context.namespace.name, name)?
+                    .ok_or_else(|| anyhow::anyhow!("No paired wallet named {}", name))?;
+                // The session is forgotten here even if the wallet can't be told
+                if wallet.is_connected()
+                    && let Err(e) =
+                        walletconnect::disconnect(&WalletConnectConfig::from_env()?, &wallet).await
+                {
+                    warn!("Failed to tell wallet {} it was disconnected: {}", name, e);
+                }
+                Ok(json!({"disconnected": name}))
+            }
+            "revoke_session_key" => {
+                let id = params["id"].as_str().unwrap_or("");
+                let revoked = context.session_keys.revoke(&context.namespace.name, id)?;
+                if !revoked {
+                    return Err(anyhow::anyhow!("No session key {}", id));
+                }
+                Ok(json!({"revoked": id}))
+            }
+            "compute_address" => {
+                let salt = params["salt"]
+                    .as_str()
+                    .map(deploy_address::parse_salt)
+                    .transpose()?;
+                // CREATE2 deploys made here go through the CREATE2 deployer, so it is
+                // the default deployer for salted addresses
+                let deployer = match (params["deployer"].as_str(), salt) {
+                    (Some(deployer), _) => blockchain_service
+                        .resolve_address(&account_manager.resolve_address(deployer))
+                        .await?
+                        .parse()?,
+                    (None, Some(_)) => deploy_address::CREATE2_DEPLOYER.parse()?,
+                    (None, None) => {
+                        return Err(anyhow::anyhow!("deployer is required without a salt"));
+                    }
+                };
+                let init_code = match (params["init_code"].as_str(), params["artifact"].as_str()) {
+                    (Some(init_code), _) => Some(init_code.parse()?),
+                    (None, Some(artifact)) => {
+                        let artifact = ContractArtifact::load_local(artifact)?;
+                        let args = match &params["args"] {
+                            Value::Null => Vec::new(),
+                            Value::Array(args) => args.clone(),
+                            _ => return Err(anyhow::anyhow!("args must be an array")),
+                        };
+                        Some(
+                            blockchain_service
+                                .init_code(&artifact, &args, &|name| {
+                                    account_manager.resolve_address(name)
+                                })
+                                .await?,
+                        )
+                    }
+                    (None, None) => None,
+                };
+                let inputs = AddressInputs {
+                    nonce: params["nonce"].as_u64(),
+                    salt,
+                    init_code,
+                    init_code_hash: params["init_code_hash"]
+                        .as_str()
+                        .map(|hash| hash.parse())
+                        .transpose()?,
+                };
+                Ok(json!(
+                    deploy_address::compute_address(&blockchain_service, deployer, inputs).await?
+                ))
+            }
+            "deploy_contract" => {
+                let from = params["from"].as_str().unwrap_or("").to_string();
+                let artifact = params["artifact"]
+                    .as_str()
+                    .ok_or_else(|| anyhow::anyhow!("Missing artifact"))?;
+                let args = match &params["args"] {
+                    Value::Null => Vec::new(),
+                    Value::Array(args) => args.clone(),
+                    _ => return Err(anyhow::anyhow!("args must be an array")),
+                };
+
+                let from_account = accounts
+                    .get(&from)
+                    .cloned()
+                    .ok_or_else(|| anyhow::anyhow!("Unknown account: {}", from))?;
+
+                let artifact = ContractArtifact::load_local(artifact)?;
+                let salt = params["salt"]
+                    .as_str()
+                    .map(deploy_address::parse_salt)
+                    .transpose()?;
+
+                let fees = FeeOverrides::from_params(&params)?;
+                let result = blockchain_service
+                    .deploy_contract(
+                        &from_account,
+                        &artifact,
+                        &args,
+                        salt,
+                        &|name| account_manager.resolve_address(name),
+                        &fees,
+                    )
+                    .await?;
+                Ok(json!(result))
+            }
+            "deploy_token" => {
+                let from = params["from"].as_str().unwrap_or("").to_string();
+                let from_account = accounts
+                    .get(&from)
+                    .cloned()
+                    .ok_or_else(|| anyhow::anyhow!("Unknown account: {}", from))?;
+                let decimals = params["decimals"].as_u64().unwrap_or(18);
+                let spec = TokenSpec {
+                    name: params["name"].as_str().unwrap_or("").to_string(),
+                    symbol: params["symbol"].as_str().unwrap_or("").to_string(),
+                    decimals: u8::try_from(decimals)
+                        .map_err(|_| anyhow::anyhow!("Invalid decimals: {}", decimals))?,
+                    initial_supply: params["initial_supply"].as_str().unwrap_or("0").to_string(),
+                    artifact: params["artifact"]
+                        .as_str()
+                        .map(ContractArtifact::load_local)
+                        .transpose()?,
+                };
+                let mint_to: Vec<String> = match &params["mint_to"] {
+                    Value::Null => Vec::new(),
+                    Value::Array(accounts) => accounts
+                        .iter()
+                        .map(|account| {
+                            account
+                                .as_str()
+                                .map(|account| account.to_string())
+                                .ok_or_else(|| anyhow::anyhow!("mint_to must list account names"))
+                        })
+                        .collect::<Result<_>>()?,
+                    _ => return Err(anyhow::anyhow!("mint_to must be an array")),
+                };
+
+                let fees = FeeOverrides::from_params(&params)?;
+                let result = token_deploy::deploy_token(
+                    &blockchain_service,
+                    &from_account,
+                    spec,
+                    &mint_to,
+                    params["mint_amount"].as_str(),
+                    &|name| account_manager.resolve_address(name),
+                    &fees,
+                )
+                .await?;
+                Ok(json!(result))
+            }
+            "get_nft_balance" | "owner_of" | "transfer_nft" => {
+                let nft_tool = tool_registry.get_tool(method)?;
+                nft_tool.execute(params, &context).await
+            }
+            "check_token_safety" => {
+                let token = params["token"].as_str().unwrap_or("").to_string();
+                let safety_tool = tool_registry.get_tool("check_token_safety")?;
+                let result = safety_tool
+                    .execute(json!({"token": token}), &context)
+                    .await?;
+
                 Ok(result)
             }
             _ => Err(anyhow::anyhow!("Unknown method: {}", method)),
@@ -0,0 +1,273 @@
+use anyhow::{Result, anyhow};
+use ethers::types::{Address, U256};
+use ethers::utils::to_checksum;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+// Session keys are meant for a working session, not standing authority
+const MAX_SESSION_SECONDS: i64 = 30 * 24 * 3600;
+
+/// Authority the user grants the assistant to send user operations from one smart
+/// account without asking each time. SimpleAccount only accepts its owner's signature,
+/// so the scope is enforced here, before the owner signs; anything outside it needs
+/// the user's confirmation as usual.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionKey {
+    pub id: String,
+    /// Account name of the smart account's owner
+    pub owner: String,
+    pub smart_account: String,
+    pub salt: String,
+    /// Contracts and recipients the key may call
+    pub allowed_targets: Vec<String>,
+    /// Most ETH a single operation may send
+    pub max_value_eth: String,
+    pub expires_at: i64,
+    pub created_at: i64,
+}
+
+impl SessionKey {
+    pub fn new(
+        owner: &str,
+        smart_account: Address,
+        salt: U256,
+        allowed_targets: &[Address],
+        max_value_eth: &str,
+        expires_at: i64,
+    ) -> Result<Self> {
+        let now = chrono::Utc::now().timestamp();
+        if allowed_targets.is_empty() {
+            return Err(anyhow!("A session key needs at least one allowed target"));
+        }
+        if expires_at <= now {
+            return Err(anyhow!("A session key must expire in the future"));
+        }
+        if expires_at - now > MAX_SESSION_SECONDS {
+            return Err(anyhow!(
+                "Session keys can last at most {} days",
+                MAX_SESSION_SECONDS / 86400
+            ));
+        }
+        shared::utils::parse_amount(max_value_eth, 18)?;
+
+        Ok(Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            owner: owner.to_string(),
+            smart_account: to_checksum(&smart_account, None),
+            salt: salt.to_string(),
+            allowed_targets: allowed_targets
+                .iter()
+                .map(|target| to_checksum(target, None))
+                .collect(),
+            max_value_eth: max_value_eth.trim().to_string(),
+            expires_at,
+            created_at: now,
+        })
+    }
+
+    pub fn is_expired(&self, now: i64) -> bool {
+        self.expires_at <= now
+    }
+
+    /// Why an operation falls outside the key's scope, or None when the key covers it
+    pub fn out_of_scope(
+        &self,
+        owner: &str,
+        salt: U256,
+        target: Address,
+        value_eth: &str,
+    ) -> Result<Option<String>> {
+        if self.is_expired(chrono::Utc::now().timestamp()) {
+            return Ok(Some("the key has expired".to_string()));
+        }
+        if owner != self.owner || salt.to_string() != self.salt {
+            return Ok(Some(format!(
+                "the key is for {}'s smart account {}",
+                self.owner, self.smart_account
+            )));
+        }
+        let target = to_checksum(&target, None);
+        if !self.allowed_targets.contains(&target) {
+            return Ok(Some(format!("{} is not an allowed target", target)));
+        }
+        let value = shared::utils::parse_amount(value_eth, 18)?;
+        if value > shared::utils::parse_amount(&self.max_value_eth, 18)? {
+            return Ok(Some(format!(
+                "{} ETH is over the key's {} ETH limit",
+                value_eth.trim(),
+                self.max_value_eth
+            )));
+        }
+        Ok(None)
+    }
+}
+
+// Session keys per namespace, persisted as a JSON object
+pub struct SessionKeyStore {
+    path: Option<PathBuf>,
+    keys: RwLock<HashMap<String, BTreeMap<String, SessionKey>>>,
+}
+
+impl SessionKeyStore {
+    pub fn in_memory() -> Self {
+        Self {
+            path: None,
+            keys: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let keys = if path.exists() {
+            serde_json::from_str(&fs::read_to_string(&path)?)?
+        } else {
+            HashMap::new()
+        };
+
+        Ok(Self {
+            path: Some(path),
+            keys: RwLock::new(keys),
+        })
+    }
+
+    /// Store a key, dropping the namespace's expired ones
+    pub fn grant(&self, namespace: &str, key: SessionKey) -> Result<()> {
+        let now = chrono::Utc::now().timestamp();
+        let mut keys = self
+            .keys
+            .write()
+            .map_err(|_| anyhow!("Session key store lock poisoned"))?;
+        let namespace_keys = keys.entry(namespace.to_string()).or_default();
+        namespace_keys.retain(|_, key| !key.is_expired(now));
+        namespace_keys.insert(key.id.clone(), key);
+        self.persist(&keys)
+    }
+
+    pub fn get(&self, namespace: &str, id: &str) -> Option<SessionKey> {
+        self.keys
+            .read()
+            .ok()
+            .and_then(|keys| keys.get(namespace)?.get(id).cloned())
+    }
+
+    /// Keys that haven't expired for one owner's smart account
+    pub fn for_smart_account(&self, namespace: &str, owner: &str, salt: U256) -> Vec<SessionKey> {
+        self.list(namespace)
+            .into_iter()
+            .filter(|key| key.owner == owner && key.salt == salt.to_string())
+            .collect()
+    }
+
+    /// Keys that haven't expired
+    pub fn list(&self, namespace: &str) -> Vec<SessionKey> {
+        let now = chrono::Utc::now().timestamp();
+        self.keys
+            .read()
+            .ok()
+            .and_then(|keys| {
+                keys.get(namespace).map(|keys| {
+                    keys.values()
+                        .filter(|key| !key.is_expired(now))
+                        .cloned()
+                        .collect()
+                })
+            })
+            .unwrap_or_default()
+    }
+
+    /// Remove a key; false when there was none with that id
+    pub fn revoke(&self, namespace: &str, id: &str) -> Result<bool> {
+        let mut keys = self
+            .keys
+            .write()
+            .map_err(|_| anyhow!("Session key store lock poisoned"))?;
+        let removed = keys
+            .get_mut(namespace)
+            .and_then(|keys| keys.remove(id))
+            .is_some();
+        if removed {
+            self.persist(&keys)?;
+        }
+        Ok(removed)
+    }
+
+    fn persist(&self, keys: &HashMap<String, BTreeMap<String, SessionKey>>) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(keys)?)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(owner: &str, salt: u64, expires_in: i64) -> SessionKey {
+        SessionKey::new(
+            owner,
+            Address::repeat_byte(0xaa),
+            U256::from(salt),
+            &[Address::repeat_byte(0x01)],
+            "0.1",
+            chrono::Utc::now().timestamp() + expires_in,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn smart_account_keys_are_found_without_their_id() {
+        let store = SessionKeyStore::in_memory();
+        let alice = key("alice", 0, 3600);
+        let mut expired = key("alice", 0, 3600);
+        expired.expires_at = chrono::Utc::now().timestamp() - 1;
+        store.grant("team", alice.clone()).unwrap();
+        store.grant("team", expired).unwrap();
+        store.grant("team", key("alice", 1, 3600)).unwrap();
+        store.grant("team", key("bob", 0, 3600)).unwrap();
+        store.grant("other", key("alice", 0, 3600)).unwrap();
+
+        let keys = store.for_smart_account("team", "alice", U256::zero());
+        assert_eq!(keys.len(), 1);
+        assert_eq!(keys[0].id, alice.id);
+        assert!(
+            store
+                .for_smart_account("team", "carol", U256::zero())
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn scope_covers_allowed_targets_up_to_the_value_limit() {
+        let key = key("alice", 0, 3600);
+        let allowed = Address::repeat_byte(0x01);
+        assert!(
+            key.out_of_scope("alice", U256::zero(), allowed, "0.1")
+                .unwrap()
+                .is_none()
+        );
+        assert!(
+            key.out_of_scope("alice", U256::zero(), allowed, "0.2")
+                .unwrap()
+                .is_some()
+        );
+        let other = Address::repeat_byte(0x02);
+        assert!(
+            key.out_of_scope("alice", U256::zero(), other, "0")
+                .unwrap()
+                .is_some()
+        );
+        assert!(
+            key.out_of_scope("bob", U256::zero(), allowed, "0")
+                .unwrap()
+                .is_some()
+        );
+    }
+}
@@ -0,0 +1,234 @@
+use anyhow::{Result, anyhow};
+use serde_json::Value;
+use shared::format::DisplayPreferences;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+// Sessions nobody has used for this long are dropped
+const SESSION_TTL: Duration = Duration::from_secs(24 * 3600);
+const MAX_VARIABLES: usize = 100;
+const MAX_VALUE_LEN: usize = 1024;
+
+struct Session {
+    variables: BTreeMap<String, String>,
+    preferences: DisplayPreferences,
+    last_used: Instant,
+}
+
+/// Variables users define in a conversation ("let AMOUNT = 0.25") and how they like
+/// amounts displayed, kept in memory per client session. Variables are substituted
+/// into request params before they run.
+pub struct SessionStore {
+    sessions: RwLock<HashMap<String, Session>>,
+}
+
+impl Default for SessionStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SessionStore {
+    pub fn new() -> Self {
+        Self {
+            sessions: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub fn set(&self, session: &str, name: &str, value: &str) -> Result<()> {
+        if !is_valid_name(name) {
+            return Err(anyhow!(
+                "Invalid variable name '{}': use letters, digits and underscores, starting with a letter",
+                name
+            ));
+        }
+        if value.len() > MAX_VALUE_LEN {
+            return Err(anyhow!(
+                "Variable values are limited to {} characters",
+                MAX_VALUE_LEN
+            ));
+        }
+
+        self.update(session, |session| {
+            if session.variables.len() >= MAX_VARIABLES && !session.variables.contains_key(name) {
+                return Err(anyhow!(
+                    "A session can hold at most {} variables",
+                    MAX_VARIABLES
+                ));
+            }
+            session
+                .variables
+                .insert(name.to_string(), value.to_string());
+            Ok(())
+        })
+    }
+
+    /// Remove a variable, returning its value if it was set
+    pub fn unset(&self, session: &str, name: &str) -> Option<String> {
+        let mut sessions = self.sessions.write().unwrap();
+        sessions
+            .get_mut(session)
+            .and_then(|session| session.variables.remove(name))
+    }
+
+    pub fn variables(&self, session: &str) -> BTreeMap<String, String> {
+        let sessions = self.sessions.read().unwrap();
+        sessions
+            .get(session)
+            .map(|session| session.variables.clone())
+            .unwrap_or_default()
+    }
+
+    /// Change some of the session's display preferences, returning the full set
+    pub fn set_preferences(&self, session: &str, changes: &Value) -> Result<DisplayPreferences> {
+        self.update(session, |session| {
+            let mut preferences = session.preferences.clone();
+            preferences.update(changes)?;
+            session.preferences = preferences.clone();
+            Ok(preferences)
+        })
+    }
+
+    pub fn preferences(&self, session: &str) -> DisplayPreferences {
+        let sessions = self.sessions.read().unwrap();
+        sessions
+            .get(session)
+            .map(|session| session.preferences.clone())
+            .unwrap_or_default()
+    }
+
+    /// Replace variable references in every string of `params`: a value that is exactly
+    /// a variable name, or `$NAME` / `${NAME}` inside a longer string. Returns the
+    /// variables that were used so callers can report what was resolved.
+    pub fn substitute(&self, session: &str, params: &mut Value) -> BTreeMap<String, String> {
+        let mut used = BTreeMap::new();
+        let mut sessions = self.sessions.write().unwrap();
+        let Some(session) = sessions.get_mut(session) else {
+            return used;
+        };
+        if session.variables.is_empty() {
+            return used;
+        }
+
+        substitute_value(params, &session.variables, &mut used);
+        session.last_used = Instant::now();
+        used
+    }
+
+    // Change a session, creating it if needed, and drop sessions that went stale
+    fn update<T>(
+        &self,
+        session: &str,
+        change: impl FnOnce(&mut Session) -> Result<T>,
+    ) -> Result<T> {
+        let mut sessions = self.sessions.write().unwrap();
+        sessions.retain(|_, session| session.last_used.elapsed() < SESSION_TTL);
+
+        let session = sessions
+            .entry(session.to_string())
+            .or_insert_with(|| Session {
+                variables: BTreeMap::new(),
+                preferences: DisplayPreferences::default(),
+                last_used: Instant::now(),
+            });
+        session.last_used = Instant::now();
+        change(session)
+    }
+}
+
+/// Letters, digits and underscores, starting with a letter
+pub fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Expand `$NAME` and `${NAME}` references in every string of `value`, returning the
+/// variables that were used
+pub fn expand_placeholders(
+    value: &mut Value,
+    variables: &BTreeMap<String, String>,
+) -> BTreeMap<String, String> {
+    let mut used = BTreeMap::new();
+    for_each_string(value, &mut |text| {
+        if text.contains('$') {
+            *text = interpolate(text, variables, &mut used);
+        }
+    });
+    used
+}
+
+fn substitute_value(
+    value: &mut Value,
+    variables: &BTreeMap<String, String>,
+    used: &mut BTreeMap<String, String>,
+) {
+    for_each_string(value, &mut |text| {
+        if let Some(resolved) = variables.get(text.as_str()) {
+            used.insert(text.clone(), resolved.clone());
+            *text = resolved.clone();
+        } else if text.contains('$') {
+            *text = interpolate(text, variables, used);
+        }
+    });
+}
+
+fn for_each_string(value: &mut Value, f: &mut impl FnMut(&mut String)) {
+    match value {
+        Value::String(text) => f(text),
+        Value::Array(items) => {
+            for item in items {
+                for_each_string(item, f);
+            }
+        }
+        Value::Object(fields) => {
+            for field in fields.values_mut() {
+                for_each_string(field, f);
+            }
+        }
+        _ => {}
+    }
+}
+
+// Expand `$NAME` and `${NAME}`; references to unknown variables are left as written
+fn interpolate(
+    text: &str,
+    variables: &BTreeMap<String, String>,
+    used: &mut BTreeMap<String, String>,
+) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('$') {
+        output.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+
+        let (name, consumed) = if let Some(braced) = after.strip_prefix('{') {
+            match braced.find('}') {
+                Some(end) => (&braced[..end], end + 2),
+                None => ("", 0),
+            }
+        } else {
+            let end = after
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(after.len());
+            (&after[..end], end)
+        };
+
+        match variables.get(name) {
+            Some(resolved) => {
+                used.insert(name.to_string(), resolved.clone());
+                output.push_str(resolved);
+                rest = &after[consumed..];
+            }
+            None => {
+                output.push('$');
+                rest = after;
+            }
+        }
+    }
+
+    output.push_str(rest);
+    output
+}
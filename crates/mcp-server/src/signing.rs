@@ -0,0 +1,87 @@
+use anyhow::{Result, anyhow};
+use ethers::signers::{LocalWallet, Signer};
+use ethers::types::transaction::eip712::{Eip712, TypedData};
+use ethers::types::{Address, Bytes, Signature};
+use ethers::utils::{hash_message, to_checksum};
+use serde::{Deserialize, Serialize};
+use shared::Account;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignedMessage {
+    pub account: String,
+    pub address: String,
+    pub message: String,
+    /// 65 bytes of hex, r || s || v with v as 27/28
+    pub signature: String,
+    /// EIP-191 hash that was signed
+    pub message_hash: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignatureCheck {
+    pub signer: String,
+    /// Whether the signer is the expected address; None when none was given
+    pub matches: Option<bool>,
+}
+
+// The bytes a message stands for: UTF-8 text, or decoded hex when `hex` is set
+fn message_bytes(message: &str, hex: bool) -> Result<Vec<u8>> {
+    if !hex {
+        return Ok(message.as_bytes().to_vec());
+    }
+    Bytes::from_str(message.trim())
+        .map(|bytes| bytes.to_vec())
+        .map_err(|e| anyhow!("Invalid hex message: {}", e))
+}
+
+/// Sign a message the way personal_sign does, with the "\x19Ethereum Signed Message"
+/// prefix, so it can never be replayed as a transaction
+pub async fn sign_message(account: &Account, message: &str, hex: bool) -> Result<SignedMessage> {
+    let bytes = message_bytes(message, hex)?;
+    let wallet = LocalWallet::from_str(&account.private_key)?;
+    let signature = wallet.sign_message(&bytes).await?;
+
+    Ok(SignedMessage {
+        account: account.name.clone(),
+        address: to_checksum(&wallet.address(), None),
+        message: message.to_string(),
+        signature: format!("0x{}", signature),
+        message_hash: format!("{:#x}", hash_message(&bytes)),
+    })
+}
+
+/// Sign EIP-712 typed data (eth_signTypedData_v4) with a named account's key,
+/// returning the signature and the digest that was signed
+pub async fn sign_typed_data(account: &Account, data: &TypedData) -> Result<(Signature, [u8; 32])> {
+    let wallet = LocalWallet::from_str(&account.private_key)?;
+    let digest = data
+        .encode_eip712()
+        .map_err(|e| anyhow!("Invalid typed data: {}", e))?;
+    let signature = wallet
+        .sign_typed_data(data)
+        .await
+        .map_err(|e| anyhow!("Couldn't sign typed data: {}", e))?;
+    Ok((signature, digest))
+}
+
+/// Recover the address that produced a personal_sign signature over `message`,
+/// comparing it to `expected` when given
+pub fn verify_signature(
+    message: &str,
+    signature: &str,
+    hex: bool,
+    expected: Option<Address>,
+) -> Result<SignatureCheck> {
+    let bytes = message_bytes(message, hex)?;
+    let signature = Signature::from_str(signature.trim().trim_start_matches("0x"))
+        .map_err(|e| anyhow!("Invalid signature: {}", e))?;
+    let signer = signature
+        .recover(hash_message(&bytes))
+        .map_err(|e| anyhow!("Invalid signature: {}", e))?;
+
+    Ok(SignatureCheck {
+        signer: to_checksum(&signer, None),
+        matches: expected.map(|expected| expected == signer),
+    })
+}
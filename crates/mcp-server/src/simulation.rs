@@ -0,0 +1,209 @@
+use anyhow::Result;
+use ethers::abi::{ParamType, Token, decode};
+use ethers::providers::{Middleware, RpcError};
+use ethers::types::transaction::eip2718::TypedTransaction;
+use ethers::types::{Address, Bytes, U256};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::str::FromStr;
+use tracing::info;
+
+use crate::blockchain::EthProvider;
+
+// Error(string) and Panic(uint256), the revert payloads Solidity emits
+const ERROR_SELECTOR: [u8; 4] = [0x08, 0xc3, 0x79, 0xa0];
+const PANIC_SELECTOR: [u8; 4] = [0x4e, 0x48, 0x7b, 0x71];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Simulation {
+    pub success: bool,
+    pub gas_used: Option<u64>,
+    /// Revert reason or node error when the transaction would fail
+    pub revert_reason: Option<String>,
+    /// What the failure most likely means for the user
+    pub explanation: Option<String>,
+    /// Innermost call that failed, from debug_traceCall when the node supports it
+    pub failing_call: Option<Value>,
+}
+
+impl Simulation {
+    /// Summary for the error returned when a failing transaction is not sent
+    pub fn failure_message(&self) -> String {
+        let reason = self.revert_reason.as_deref().unwrap_or("reverted");
+        match &self.explanation {
+            Some(explanation) => format!(
+                "Simulation failed, nothing was sent: {} ({})",
+                explanation, reason
+            ),
+            None => format!("Simulation failed, nothing was sent: {}", reason),
+        }
+    }
+}
+
+/// Run a transaction through eth_call and eth_estimateGas against the latest block
+/// without broadcasting it. Reverts and node rejections (e.g. insufficient funds)
+/// come back as a failed simulation; only transport errors are returned as `Err`.
+pub async fn simulate(
+    provider: &EthProvider,
+    from: Address,
+    tx: &TypedTransaction,
+) -> Result<Simulation> {
+    let mut tx = tx.clone();
+    tx.set_from(from);
+
+    let failure = match provider.call(&tx, None).await {
+        Ok(_) => match provider.estimate_gas(&tx, None).await {
+            Ok(gas) => {
+                return Ok(Simulation {
+                    success: true,
+                    gas_used: Some(gas.as_u64()),
+                    revert_reason: None,
+                    explanation: None,
+                    failing_call: None,
+                });
+            }
+            Err(e) => e,
+        },
+        Err(e) => e,
+    };
+    let Some(response) = failure.as_error_response() else {
+        return Err(failure.into());
+    };
+
+    let reason = response
+        .as_revert_data()
+        .and_then(|data| decode_revert(&data))
+        .unwrap_or_else(|| response.message.clone());
+    let failing_call = trace_failure(provider, &tx).await;
+    let inner_reason = failing_call
+        .as_ref()
+        .and_then(|call| call["revert_reason"].as_str());
+
+    Ok(Simulation {
+        success: false,
+        gas_used: None,
+        explanation: explain(inner_reason.unwrap_or(&reason)).map(str::to_string),
+        revert_reason: Some(reason),
+        failing_call,
+    })
+}
+
+/// Readable reason from Error(string) or Panic(uint256) revert data
+pub fn decode_revert(data: &Bytes) -> Option<String> {
+    if data.len() < 4 {
+        return None;
+    }
+    let (selector, payload) = data.split_at(4);
+    if selector == ERROR_SELECTOR {
+        match decode(&[ParamType::String], payload).ok()?.pop()? {
+            Token::String(reason) => Some(reason),
+            _ => None,
+        }
+    } else if selector == PANIC_SELECTOR {
+        let code = decode(&[ParamType::Uint(256)], payload)
+            .ok()?
+            .pop()?
+            .into_uint()?;
+        Some(panic_reason(code))
+    } else {
+        Some(format!("custom error 0x{}", hex::encode(selector)))
+    }
+}
+
+fn panic_reason(code: U256) -> String {
+    let reason = match code.low_u64() {
+        0x01 => "assertion failed",
+        0x11 => "arithmetic overflow or underflow",
+        0x12 => "division by zero",
+        0x21 => "invalid enum value",
+        0x32 => "array index out of bounds",
+        0x41 => "out of memory",
+        0x51 => "call to an uninitialized function",
+        _ => "panic",
+    };
+    format!("{} (panic 0x{:x})", reason, code)
+}
+
+// The deepest failing frame of a callTracer trace, or None when the node has no
+// debug namespace
+async fn trace_failure(provider: &EthProvider, tx: &TypedTransaction) -> Option<Value> {
+    let trace: Value = match provider
+        .request(
+            "debug_traceCall",
+            (tx, "latest", json!({"tracer": "callTracer"})),
+        )
+        .await
+    {
+        Ok(trace) => trace,
+        Err(e) => {
+            info!("debug_traceCall unavailable: {}", e);
+            return None;
+        }
+    };
+
+    let mut frame = &trace;
+    loop {
+        let failed_child = frame["calls"]
+            .as_array()
+            .and_then(|calls| calls.iter().rev().find(|call| call["error"].is_string()));
+        match failed_child {
+            Some(child) => frame = child,
+            None => break,
+        }
+    }
+    if !frame["error"].is_string() {
+        return None;
+    }
+
+    let revert_reason = frame["revertReason"]
+        .as_str()
+        .map(str::to_string)
+        .or_else(|| {
+            frame["output"]
+                .as_str()
+                .and_then(|output| Bytes::from_str(output).ok())
+                .and_then(|data| decode_revert(&data))
+        });
+    Some(json!({
+        "to": frame["to"],
+        "error": frame["error"],
+        "revert_reason": revert_reason,
+    }))
+}
+
+// Plain-language meaning of the reverts and rejections users most often hit
+fn explain(reason: &str) -> Option<&'static str> {
+    let reason = reason.to_lowercase();
+    let explanation = if reason.contains("insufficient funds") {
+        "the account doesn't have enough ETH for the amount plus gas"
+    } else if reason.contains("allowance")
+        || reason == "stf"
+        || reason.contains("transfer_from_failed")
+    {
+        "the spender isn't approved for enough of the token; approve it first"
+    } else if reason.contains("exceeds balance")
+        || reason.contains("insufficient balance")
+        || reason.contains("transfer amount exceeds")
+    {
+        "the account doesn't hold enough of the token"
+    } else if reason.contains("insufficient_output_amount")
+        || reason.contains("too little received")
+    {
+        "the price moved past the slippage tolerance; retry with a smaller amount or more slippage"
+    } else if reason.contains("insufficient_liquidity") || reason.contains("liquidity") {
+        "the pool doesn't have enough liquidity for this trade"
+    } else if reason.contains("expired") || reason.contains("too old") {
+        "the transaction's deadline has passed"
+    } else if reason.contains("nonce too low") {
+        "the account's nonce was already used; another transaction went first"
+    } else if reason.contains("paused") {
+        "the contract is paused"
+    } else if reason.contains("blacklist") || reason.contains("blocked") {
+        "the token contract blocks this address"
+    } else if reason.contains("overflow or underflow") {
+        "an amount is larger than the contract allows, often a balance or allowance shortfall"
+    } else {
+        return None;
+    };
+    Some(explanation)
+}
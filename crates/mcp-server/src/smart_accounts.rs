@@ -0,0 +1,537 @@
+use anyhow::{Result, anyhow};
+use ethers::abi::{Token, encode, parse_abi};
+use ethers::contract::Contract;
+use ethers::providers::{Http, Middleware, Provider};
+use ethers::signers::{LocalWallet, Signer};
+use ethers::types::{Address, Bytes, H256, U256};
+use ethers::utils::{format_ether, keccak256, to_checksum};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use shared::{Account, ContractCall, TransactionResult, utils};
+use std::str::FromStr;
+use std::time::Duration;
+use tracing::info;
+
+use crate::blockchain::{BlockchainService, FeeOverrides, NameResolver};
+
+// ERC-4337 v0.6 EntryPoint and the eth-infinitism SimpleAccountFactory, deployed at the
+// same addresses on every network; ENTRY_POINT and SMART_ACCOUNT_FACTORY override them
+const ENTRY_POINT_V06: &str = "0x5FF137D4b0FDCD49DcA30c7CF57E578a026d2789";
+const SIMPLE_ACCOUNT_FACTORY: &str = "0x9406Cc6185a346906296840746125a0E44976454";
+const ENTRY_POINT_ABI: &[&str] = &[
+    "function getNonce(address sender, uint192 key) view returns (uint256 nonce)",
+    "function balanceOf(address account) view returns (uint256)",
+];
+const ACCOUNT_FACTORY_ABI: &[&str] = &[
+    "function createAccount(address owner, uint256 salt) returns (address)",
+    "function getAddress(address owner, uint256 salt) view returns (address)",
+];
+const SIMPLE_ACCOUNT_ABI: &[&str] = &["function execute(address dest, uint256 value, bytes func)"];
+// Signature of the right length and shape for gas estimation; SimpleAccount rejects it
+// without reverting, so validation runs to completion
+const DUMMY_SIGNATURE: &str = "0xfffffffffffffffffffffffffffffff0000000000000000000000000000000007aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa1c";
+// How long send_user_operation waits for the bundler to include the operation
+const RECEIPT_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Where user operations go: the bundler and, optionally, a paymaster that sponsors gas
+#[derive(Debug, Clone)]
+pub struct SmartAccountConfig {
+    pub bundler_url: Option<String>,
+    pub entry_point: Address,
+    pub factory: Address,
+    /// Endpoint serving `pm_sponsorUserOperation`; without one the account pays its own gas
+    pub paymaster_url: Option<String>,
+    /// Passed to the paymaster with each request, e.g. a sponsorship policy id
+    pub paymaster_context: Option<Value>,
+}
+
+impl SmartAccountConfig {
+    /// Read BUNDLER_RPC_URL, ENTRY_POINT, SMART_ACCOUNT_FACTORY, PAYMASTER_URL and
+    /// PAYMASTER_CONTEXT (JSON)
+    pub fn from_env() -> Result<Self> {
+        let env = |key: &str| std::env::var(key).ok().filter(|value| !value.is_empty());
+        let address = |key: &str, default: &str| -> Result<Address> {
+            let value = env(key).unwrap_or_else(|| default.to_string());
+            Address::from_str(&value).map_err(|e| anyhow!("Invalid {} {}: {}", key, value, e))
+        };
+
+        Ok(Self {
+            bundler_url: env("BUNDLER_RPC_URL"),
+            entry_point: address("ENTRY_POINT", ENTRY_POINT_V06)?,
+            factory: address("SMART_ACCOUNT_FACTORY", SIMPLE_ACCOUNT_FACTORY)?,
+            paymaster_url: env("PAYMASTER_URL"),
+            paymaster_context: env("PAYMASTER_CONTEXT")
+                .map(|context| serde_json::from_str(&context))
+                .transpose()
+                .map_err(|e| anyhow!("Invalid PAYMASTER_CONTEXT: {}", e))?,
+        })
+    }
+
+    fn bundler(&self) -> Result<Provider<Http>> {
+        let url = self.bundler_url.as_deref().ok_or_else(|| {
+            anyhow!("Set BUNDLER_RPC_URL to a bundler endpoint to send user operations")
+        })?;
+        Provider::<Http>::try_from(url).map_err(|e| anyhow!("Invalid BUNDLER_RPC_URL: {}", e))
+    }
+}
+
+/// An ERC-4337 v0.6 user operation, serialized as bundlers expect it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserOperation {
+    pub sender: Address,
+    pub nonce: U256,
+    pub init_code: Bytes,
+    pub call_data: Bytes,
+    pub call_gas_limit: U256,
+    pub verification_gas_limit: U256,
+    pub pre_verification_gas: U256,
+    pub max_fee_per_gas: U256,
+    pub max_priority_fee_per_gas: U256,
+    pub paymaster_and_data: Bytes,
+    pub signature: Bytes,
+}
+
+impl UserOperation {
+    /// The hash the account's owner signs: the packed operation bound to the
+    /// EntryPoint and chain
+    pub fn hash(&self, entry_point: Address, chain_id: u64) -> H256 {
+        let packed = encode(&[
+            Token::Address(self.sender),
+            Token::Uint(self.nonce),
+            Token::FixedBytes(keccak256(&self.init_code).to_vec()),
+            Token::FixedBytes(keccak256(&self.call_data).to_vec()),
+            Token::Uint(self.call_gas_limit),
+            Token::Uint(self.verification_gas_limit),
+            Token::Uint(self.pre_verification_gas),
+            Token::Uint(self.max_fee_per_gas),
+            Token::Uint(self.max_priority_fee_per_gas),
+            Token::FixedBytes(keccak256(&self.paymaster_and_data).to_vec()),
+        ]);
+        H256(keccak256(encode(&[
+            Token::FixedBytes(keccak256(packed).to_vec()),
+            Token::Address(entry_point),
+            Token::Uint(chain_id.into()),
+        ])))
+    }
+
+    // Most the account (or its paymaster) may be charged for the operation
+    fn max_cost(&self) -> U256 {
+        let multiplier = if self.paymaster_and_data.is_empty() {
+            1
+        } else {
+            3
+        };
+        (self.call_gas_limit + self.verification_gas_limit * multiplier + self.pre_verification_gas)
+            * self.max_fee_per_gas
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SmartAccountInfo {
+    pub address: String,
+    pub owner: String,
+    pub salt: String,
+    pub factory: String,
+    pub entry_point: String,
+    /// False for a counterfactual account; it is deployed by its first user operation
+    /// or by `deploy_smart_account`
+    pub deployed: bool,
+    pub nonce: String,
+    pub balance_eth: String,
+    /// ETH deposited with the EntryPoint to pay for gas
+    pub deposit_eth: String,
+}
+
+/// A call for a smart account to make
+#[derive(Debug, Clone)]
+pub struct SmartAccountCall {
+    pub target: String,
+    /// ETH sent with the call
+    pub value: String,
+    /// Function and parameters to encode; empty for a plain ETH transfer
+    pub function: Option<ContractCall>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserOperationEstimate {
+    pub user_operation: UserOperation,
+    pub sponsored: bool,
+    /// Whether the operation deploys the account first
+    pub deploys_account: bool,
+    /// Most the operation can cost the account, zero when sponsored
+    pub max_cost_eth: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserOperationResult {
+    pub user_op_hash: String,
+    pub sender: String,
+    /// "success", "failed" (included but reverted) or "pending"
+    pub status: String,
+    pub transaction_hash: Option<String>,
+    pub block_number: Option<u64>,
+    pub actual_gas_cost_eth: Option<String>,
+    pub sponsored: bool,
+    pub deploys_account: bool,
+    /// Revert reason when the call failed
+    pub reason: Option<String>,
+}
+
+// Counterfactual address of the owner's account, the same before and after deployment
+async fn account_address(
+    blockchain_service: &BlockchainService,
+    config: &SmartAccountConfig,
+    owner: Address,
+    salt: U256,
+) -> Result<Address> {
+    let factory = Contract::new(
+        config.factory,
+        parse_abi(ACCOUNT_FACTORY_ABI)?,
+        blockchain_service.provider(),
+    );
+    factory
+        .method::<_, Address>("getAddress", (owner, salt))?
+        .call()
+        .await
+        .map_err(|e| {
+            anyhow!(
+                "Couldn't derive the smart account from factory {:?} on chain {}: {}",
+                config.factory,
+                blockchain_service.chain_id(),
+                e
+            )
+        })
+}
+
+/// The smart account `owner` controls for `salt`, whether or not it is deployed yet
+pub async fn get_smart_account(
+    blockchain_service: &BlockchainService,
+    config: &SmartAccountConfig,
+    owner: Address,
+    salt: U256,
+) -> Result<SmartAccountInfo> {
+    let provider = blockchain_service.provider();
+    let address = account_address(blockchain_service, config, owner, salt).await?;
+    let entry_point = Contract::new(
+        config.entry_point,
+        parse_abi(ENTRY_POINT_ABI)?,
+        provider.clone(),
+    );
+    let nonce: U256 = entry_point
+        .method("getNonce", (address, U256::zero()))?
+        .call()
+        .await?;
+    let deposit: U256 = entry_point.method("balanceOf", address)?.call().await?;
+
+    Ok(SmartAccountInfo {
+        address: to_checksum(&address, None),
+        owner: to_checksum(&owner, None),
+        salt: salt.to_string(),
+        factory: to_checksum(&config.factory, None),
+        entry_point: to_checksum(&config.entry_point, None),
+        deployed: !provider.get_code(address, None).await?.is_empty(),
+        nonce: nonce.to_string(),
+        balance_eth: format_ether(provider.get_balance(address, None).await?),
+        deposit_eth: format_ether(deposit),
+    })
+}
+
+/// Deploy the owner's smart account with a transaction from the owner's own account,
+/// rather than leaving it to the first user operation
+pub async fn deploy_smart_account(
+    blockchain_service: &BlockchainService,
+    config: &SmartAccountConfig,
+    owner: &Account,
+    salt: U256,
+    fees: &FeeOverrides,
+) -> Result<(SmartAccountInfo, Option<TransactionResult>)> {
+    let owner_address = Address::from_str(&owner.address)?;
+    let info = get_smart_account(blockchain_service, config, owner_address, salt).await?;
+    if info.deployed {
+        return Ok((info, None));
+    }
+
+    let call = ContractCall {
+        contract_address: format!("{:?}", config.factory),
+        function_signature: "createAccount(address,uint256)".to_string(),
+        parameters: vec![owner.address.clone(), salt.to_string()],
+        from: None,
+    };
+    let transaction = blockchain_service
+        .write_contract(owner, &call, "0", None, &|name| name.to_string(), fees)
+        .await?;
+    let info = get_smart_account(blockchain_service, config, owner_address, salt).await?;
+    Ok((info, Some(transaction)))
+}
+
+// A quantity from a bundler or paymaster response, given as a hex string or a number
+fn quantity(response: &Value, field: &str) -> Result<Option<U256>> {
+    match &response[field] {
+        Value::Null => Ok(None),
+        Value::Number(number) => Ok(number.as_u64().map(U256::from)),
+        Value::String(text) if text.starts_with("0x") => Ok(Some(U256::from_str_radix(
+            text.trim_start_matches("0x"),
+            16,
+        )?)),
+        Value::String(text) => Ok(Some(U256::from_dec_str(text)?)),
+        other => Err(anyhow!("Unexpected {} in response: {}", field, other)),
+    }
+}
+
+fn apply_gas(operation: &mut UserOperation, response: &Value) -> Result<()> {
+    if let Some(gas) = quantity(response, "callGasLimit")? {
+        operation.call_gas_limit = gas;
+    }
+    if let Some(gas) = quantity(response, "verificationGasLimit")? {
+        operation.verification_gas_limit = gas;
+    }
+    if let Some(gas) = quantity(response, "preVerificationGas")? {
+        operation.pre_verification_gas = gas;
+    }
+    Ok(())
+}
+
+/// Build the user operation for `call` from the owner's smart account and fill in its
+/// gas, through the paymaster when one is configured or the bundler otherwise.
+/// The operation is left unsigned.
+pub async fn estimate_user_operation(
+    blockchain_service: &BlockchainService,
+    config: &SmartAccountConfig,
+    owner: Address,
+    salt: U256,
+    call: &SmartAccountCall,
+    resolve_name: &NameResolver<'_>,
+    fees: &FeeOverrides,
+) -> Result<UserOperationEstimate> {
+    let provider = blockchain_service.provider();
+    let sender = account_address(blockchain_service, config, owner, salt).await?;
+    let deploys_account = provider.get_code(sender, None).await?.is_empty();
+
+    let init_code = if deploys_account {
+        let factory = Contract::new(
+            config.factory,
+            parse_abi(ACCOUNT_FACTORY_ABI)?,
+            provider.clone(),
+        );
+        let create = factory
+            .method::<_, Address>("createAccount", (owner, salt))?
+            .calldata()
+            .ok_or_else(|| anyhow!("Couldn't encode createAccount"))?;
+        let mut init_code = config.factory.as_bytes().to_vec();
+        init_code.extend_from_slice(&create);
+        Bytes::from(init_code)
+    } else {
+        Bytes::default()
+    };
+
+    let target = blockchain_service
+        .parse_address(&resolve_name(&call.target))
+        .await?;
+    let value = utils::parse_amount(&call.value, 18)?;
+    let data = match &call.function {
+        Some(function) => {
+            blockchain_service
+                .encode_call(function, resolve_name)
+                .await?
+                .2
+        }
+        None => Bytes::default(),
+    };
+    let account = Contract::new(sender, parse_abi(SIMPLE_ACCOUNT_ABI)?, provider.clone());
+    let call_data = account
+        .method::<_, ()>("execute", (target, value, data))?
+        .calldata()
+        .ok_or_else(|| anyhow!("Couldn't encode execute"))?;
+
+    let entry_point = Contract::new(
+        config.entry_point,
+        parse_abi(ENTRY_POINT_ABI)?,
+        provider.clone(),
+    );
+    let nonce: U256 = entry_point
+        .method("getNonce", (sender, U256::zero()))?
+        .call()
+        .await?;
+    let (max_fee_per_gas, max_priority_fee_per_gas) =
+        match blockchain_service.estimate_eip1559_fees(fees).await? {
+            Some(fees) => (fees.max_fee_per_gas, fees.max_priority_fee_per_gas),
+            None => {
+                let gas_price = provider.get_gas_price().await?;
+                (gas_price, gas_price)
+            }
+        };
+
+    let mut operation = UserOperation {
+        sender,
+        nonce,
+        init_code,
+        call_data,
+        call_gas_limit: U256::zero(),
+        verification_gas_limit: U256::zero(),
+        pre_verification_gas: U256::zero(),
+        max_fee_per_gas,
+        max_priority_fee_per_gas,
+        paymaster_and_data: Bytes::default(),
+        signature: Bytes::from_str(DUMMY_SIGNATURE)?,
+    };
+    let entry_point = format!("{:?}", config.entry_point);
+
+    // A paymaster estimates gas itself, as its data changes what validation costs
+    let sponsored = match &config.paymaster_url {
+        Some(url) => {
+            let paymaster = Provider::<Http>::try_from(url.as_str())
+                .map_err(|e| anyhow!("Invalid PAYMASTER_URL: {}", e))?;
+            let mut params = vec![json!(operation), json!(entry_point)];
+            if let Some(context) = &config.paymaster_context {
+                params.push(context.clone());
+            }
+            let sponsorship: Value = paymaster
+                .request("pm_sponsorUserOperation", params)
+                .await
+                .map_err(|e| anyhow!("The paymaster declined the operation: {}", e))?;
+            let paymaster_and_data = sponsorship["paymasterAndData"]
+                .as_str()
+                .ok_or_else(|| anyhow!("The paymaster returned no paymasterAndData"))?;
+            operation.paymaster_and_data = Bytes::from_str(paymaster_and_data)?;
+            apply_gas(&mut operation, &sponsorship)?;
+            true
+        }
+        None => false,
+    };
+    if !sponsored || operation.call_gas_limit.is_zero() {
+        let estimate: Value = config
+            .bundler()?
+            .request(
+                "eth_estimateUserOperationGas",
+                (json!(operation), json!(entry_point)),
+            )
+            .await
+            .map_err(|e| anyhow!("The bundler couldn't estimate the operation: {}", e))?;
+        apply_gas(&mut operation, &estimate)?;
+    }
+
+    let max_cost = if sponsored {
+        U256::zero()
+    } else {
+        operation.max_cost()
+    };
+    Ok(UserOperationEstimate {
+        user_operation: operation,
+        sponsored,
+        deploys_account,
+        max_cost_eth: format_ether(max_cost),
+    })
+}
+
+/// Build, sign and submit a user operation making `call` from the owner's smart
+/// account, then wait for the bundler to include it. An operation not included within
+/// the timeout comes back "pending" with its hash.
+pub async fn send_user_operation(
+    blockchain_service: &BlockchainService,
+    config: &SmartAccountConfig,
+    owner: &Account,
+    salt: U256,
+    call: &SmartAccountCall,
+    resolve_name: &NameResolver<'_>,
+    fees: &FeeOverrides,
+) -> Result<UserOperationResult> {
+    let wallet = LocalWallet::from_str(&owner.private_key)?;
+    let estimate = estimate_user_operation(
+        blockchain_service,
+        config,
+        wallet.address(),
+        salt,
+        call,
+        resolve_name,
+        fees,
+    )
+    .await?;
+    let mut operation = estimate.user_operation;
+
+    // Without a paymaster the account pays from its EntryPoint deposit or its balance
+    if !estimate.sponsored {
+        let provider = blockchain_service.provider();
+        let entry_point = Contract::new(
+            config.entry_point,
+            parse_abi(ENTRY_POINT_ABI)?,
+            provider.clone(),
+        );
+        let deposit: U256 = entry_point
+            .method("balanceOf", operation.sender)?
+            .call()
+            .await?;
+        let balance = provider.get_balance(operation.sender, None).await?;
+        let required = operation.max_cost();
+        if deposit + balance < required {
+            return Err(anyhow!(
+                "Smart account {} holds {} ETH but the operation may cost up to {} ETH; fund it or configure a paymaster",
+                to_checksum(&operation.sender, None),
+                format_ether(deposit + balance),
+                format_ether(required)
+            ));
+        }
+    }
+
+    // SimpleAccount checks an EIP-191 signature over the operation hash
+    let hash = operation.hash(config.entry_point, blockchain_service.chain_id());
+    let signature = wallet.sign_message(hash.as_bytes()).await?;
+    operation.signature = signature.to_vec().into();
+
+    let bundler = config.bundler()?;
+    let user_op_hash: H256 = bundler
+        .request(
+            "eth_sendUserOperation",
+            (json!(operation), json!(format!("{:?}", config.entry_point))),
+        )
+        .await
+        .map_err(|e| anyhow!("The bundler rejected the operation: {}", e))?;
+    info!(
+        "Sent user operation {:#x} from {:?}",
+        user_op_hash, operation.sender
+    );
+
+    let mut result = UserOperationResult {
+        user_op_hash: format!("{:#x}", user_op_hash),
+        sender: to_checksum(&operation.sender, None),
+        status: "pending".to_string(),
+        transaction_hash: None,
+        block_number: None,
+        actual_gas_cost_eth: None,
+        sponsored: estimate.sponsored,
+        deploys_account: estimate.deploys_account,
+        reason: None,
+    };
+
+    let poll_interval = blockchain_service.rpc_settings().poll_interval;
+    let deadline = tokio::time::Instant::now() + RECEIPT_TIMEOUT;
+    while tokio::time::Instant::now() < deadline {
+        tokio::time::sleep(poll_interval).await;
+        let receipt: Value = bundler
+            .request("eth_getUserOperationReceipt", [user_op_hash])
+            .await
+            .unwrap_or(Value::Null);
+        if receipt.is_null() {
+            continue;
+        }
+
+        result.status = if receipt["success"].as_bool().unwrap_or(false) {
+            "success".to_string()
+        } else {
+            "failed".to_string()
+        };
+        result.transaction_hash = receipt["receipt"]["transactionHash"]
+            .as_str()
+            .map(|hash| hash.to_string());
+        result.block_number =
+            quantity(&receipt["receipt"], "blockNumber")?.map(|number| number.as_u64());
+        result.actual_gas_cost_eth = quantity(&receipt, "actualGasCost")?.map(format_ether);
+        result.reason = receipt["reason"]
+            .as_str()
+            .filter(|reason| !reason.is_empty())
+            .map(|reason| reason.to_string());
+        break;
+    }
+
+    Ok(result)
+}
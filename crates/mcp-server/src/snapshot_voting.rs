@@ -0,0 +1,499 @@
+use anyhow::{Result, anyhow};
+use ethers::types::transaction::eip712::TypedData;
+use ethers::utils::to_checksum;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use shared::Account;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use crate::external_apis::ExternalAPIService;
+use crate::signing;
+
+/// Proposals returned when the caller gives no limit
+pub const DEFAULT_SNAPSHOT_LIMIT: u64 = 10;
+const MAX_SNAPSHOT_LIMIT: u64 = 100;
+// EIP-712 domain Snapshot's sequencer verifies votes against
+const SNAPSHOT_DOMAIN_NAME: &str = "snapshot";
+const SNAPSHOT_DOMAIN_VERSION: &str = "0.1.4";
+
+const SPACES_QUERY: &str = "query Spaces($ids: [String]) { spaces(where: { id_in: $ids }) { id name network symbol followersCount proposalsCount } }";
+const FOLLOWS_QUERY: &str = "query Follows($follower: String!) { follows(first: 100, where: { follower: $follower }) { space { id name network symbol followersCount proposalsCount } } }";
+const PROPOSALS_QUERY: &str = "query Proposals($first: Int!, $where: ProposalWhere) { proposals(first: $first, where: $where, orderBy: \"created\", orderDirection: desc) { id title state type choices start end snapshot scores scores_total quorum author link privacy space { id } } }";
+const PROPOSAL_QUERY: &str = "query Proposal($id: String!) { proposal(id: $id) { id title body state type choices start end snapshot scores scores_total scores_state quorum votes author link privacy space { id } } }";
+const VP_QUERY: &str = "query Vp($voter: String!, $space: String!, $proposal: String) { vp(voter: $voter, space: $space, proposal: $proposal) { vp vp_state } }";
+const VOTE_QUERY: &str = "query Votes($proposal: String!, $voter: String!) { votes(first: 1, where: { proposal: $proposal, voter: $voter }) { choice created reason } }";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotSpace {
+    /// ENS name identifying the space, e.g. "aave.eth"
+    pub id: String,
+    pub name: String,
+    /// Chain id the space's voting strategies read balances on
+    pub network: Option<String>,
+    pub symbol: Option<String>,
+    pub followers: Option<u64>,
+    pub proposals: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChoiceResult {
+    /// 1-based, as votes refer to choices
+    pub index: usize,
+    pub choice: String,
+    pub score: f64,
+    pub percent: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotProposal {
+    pub id: String,
+    pub space: String,
+    pub title: String,
+    /// "pending", "active" or "closed"
+    pub state: String,
+    /// "single-choice", "approval", "ranked-choice", "weighted", "quadratic" or "basic"
+    pub voting_type: String,
+    pub results: Vec<ChoiceResult>,
+    pub scores_total: f64,
+    /// Whether the scores are final; they are recomputed for a while after closing
+    pub scores_final: Option<bool>,
+    pub quorum: f64,
+    pub start: i64,
+    pub end: i64,
+    /// Block whose balances set voting power
+    pub snapshot_block: Option<String>,
+    pub author: String,
+    pub link: Option<String>,
+    pub votes: Option<u64>,
+    /// Full markdown description; only fetched for a single proposal
+    pub body: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExistingVote {
+    pub choice: Value,
+    pub created: i64,
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotVotingPower {
+    pub space: String,
+    pub proposal_id: Option<String>,
+    pub account: String,
+    pub voting_power: f64,
+    /// "final" once the proposal's snapshot block is settled, "pending" before
+    pub state: Option<String>,
+    /// Earlier vote on the proposal; voting again replaces it
+    pub existing_vote: Option<ExistingVote>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotVote {
+    /// Receipt id the sequencer assigned to the vote
+    pub id: Option<String>,
+    pub ipfs: Option<String>,
+    pub proposal_id: String,
+    pub space: String,
+    pub account: String,
+    /// The chosen options, by name
+    pub choice: String,
+    pub voting_power: f64,
+    pub replaced_previous_vote: bool,
+}
+
+fn strings(value: &Value) -> Vec<String> {
+    value
+        .as_array()
+        .map(|items| {
+            items
+                .iter()
+                .map(|item| {
+                    item.as_str()
+                        .map(str::to_string)
+                        .unwrap_or_else(|| item.to_string())
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn space(value: &Value) -> SnapshotSpace {
+    SnapshotSpace {
+        id: value["id"].as_str().unwrap_or_default().to_string(),
+        name: value["name"].as_str().unwrap_or_default().to_string(),
+        network: value["network"].as_str().map(str::to_string),
+        symbol: value["symbol"].as_str().map(str::to_string),
+        followers: value["followersCount"].as_u64(),
+        proposals: value["proposalsCount"].as_u64(),
+    }
+}
+
+fn proposal(value: &Value) -> SnapshotProposal {
+    let choices = strings(&value["choices"]);
+    let scores: Vec<f64> = value["scores"]
+        .as_array()
+        .map(|scores| {
+            scores
+                .iter()
+                .map(|score| score.as_f64().unwrap_or(0.0))
+                .collect()
+        })
+        .unwrap_or_default();
+    let scores_total = value["scores_total"].as_f64().unwrap_or(0.0);
+    let results = choices
+        .into_iter()
+        .enumerate()
+        .map(|(i, choice)| {
+            let score = scores.get(i).copied().unwrap_or(0.0);
+            ChoiceResult {
+                index: i + 1,
+                choice,
+                score,
+                percent: if scores_total > 0.0 {
+                    score / scores_total * 100.0
+                } else {
+                    0.0
+                },
+            }
+        })
+        .collect();
+
+    SnapshotProposal {
+        id: value["id"].as_str().unwrap_or_default().to_string(),
+        space: value["space"]["id"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string(),
+        title: value["title"].as_str().unwrap_or_default().to_string(),
+        state: value["state"].as_str().unwrap_or_default().to_string(),
+        voting_type: value["type"]
+            .as_str()
+            .unwrap_or("single-choice")
+            .to_string(),
+        results,
+        scores_total,
+        scores_final: value["scores_state"].as_str().map(|state| state == "final"),
+        quorum: value["quorum"].as_f64().unwrap_or(0.0),
+        start: value["start"].as_i64().unwrap_or(0),
+        end: value["end"].as_i64().unwrap_or(0),
+        snapshot_block: value["snapshot"].as_str().map(str::to_string),
+        author: value["author"].as_str().unwrap_or_default().to_string(),
+        link: value["link"].as_str().map(str::to_string),
+        votes: value["votes"].as_u64(),
+        body: value["body"].as_str().map(str::to_string),
+    }
+}
+
+/// Snapshot spaces by id, or the spaces `follower` follows when no ids are given
+pub async fn list_spaces(
+    external_apis: &ExternalAPIService,
+    ids: &[String],
+    follower: Option<&str>,
+) -> Result<Vec<SnapshotSpace>> {
+    let spaces = match follower {
+        Some(follower) if ids.is_empty() => {
+            let data = external_apis
+                .query_snapshot(FOLLOWS_QUERY, json!({ "follower": follower }))
+                .await?;
+            data["follows"]
+                .as_array()
+                .map(|follows| {
+                    follows
+                        .iter()
+                        .map(|follow| space(&follow["space"]))
+                        .collect()
+                })
+                .unwrap_or_default()
+        }
+        _ if !ids.is_empty() => {
+            let data = external_apis
+                .query_snapshot(SPACES_QUERY, json!({ "ids": ids }))
+                .await?;
+            data["spaces"]
+                .as_array()
+                .map(|spaces| spaces.iter().map(space).collect())
+                .unwrap_or_default()
+        }
+        _ => {
+            return Err(anyhow!(
+                "Give space ids or an account whose followed spaces to list"
+            ));
+        }
+    };
+    Ok(spaces)
+}
+
+/// A space's latest proposals, newest first, optionally only those in `state`
+pub async fn list_proposals(
+    external_apis: &ExternalAPIService,
+    space: &str,
+    state: Option<&str>,
+    limit: u64,
+) -> Result<Vec<SnapshotProposal>> {
+    if limit == 0 || limit > MAX_SNAPSHOT_LIMIT {
+        return Err(anyhow!(
+            "limit must be between 1 and {}",
+            MAX_SNAPSHOT_LIMIT
+        ));
+    }
+    let mut filter = json!({ "space": space.trim() });
+    if let Some(state) = state.filter(|state| *state != "all") {
+        filter["state"] = json!(state);
+    }
+    let data = external_apis
+        .query_snapshot(PROPOSALS_QUERY, json!({ "first": limit, "where": filter }))
+        .await?;
+    Ok(data["proposals"]
+        .as_array()
+        .map(|proposals| proposals.iter().map(proposal).collect())
+        .unwrap_or_default())
+}
+
+async fn fetch_proposal(external_apis: &ExternalAPIService, id: &str) -> Result<Value> {
+    let data = external_apis
+        .query_snapshot(PROPOSAL_QUERY, json!({ "id": id.trim() }))
+        .await?;
+    if data["proposal"].is_null() {
+        return Err(anyhow!("Snapshot has no proposal {}", id));
+    }
+    Ok(data["proposal"].clone())
+}
+
+/// One proposal with its description and current results
+pub async fn get_proposal(
+    external_apis: &ExternalAPIService,
+    id: &str,
+) -> Result<SnapshotProposal> {
+    Ok(proposal(&fetch_proposal(external_apis, id).await?))
+}
+
+async fn existing_vote(
+    external_apis: &ExternalAPIService,
+    proposal_id: &str,
+    voter: &str,
+) -> Result<Option<ExistingVote>> {
+    let data = external_apis
+        .query_snapshot(
+            VOTE_QUERY,
+            json!({ "proposal": proposal_id, "voter": voter }),
+        )
+        .await?;
+    Ok(data["votes"][0].as_object().map(|vote| ExistingVote {
+        choice: vote["choice"].clone(),
+        created: vote["created"].as_i64().unwrap_or(0),
+        reason: vote["reason"]
+            .as_str()
+            .filter(|reason| !reason.is_empty())
+            .map(str::to_string),
+    }))
+}
+
+/// An account's voting power in a space, computed by the space's strategies; on a
+/// proposal, as of its snapshot block, along with any vote already cast
+pub async fn get_voting_power(
+    external_apis: &ExternalAPIService,
+    space: &str,
+    proposal_id: Option<&str>,
+    account: &str,
+) -> Result<SnapshotVotingPower> {
+    let data = external_apis
+        .query_snapshot(
+            VP_QUERY,
+            json!({ "voter": account, "space": space, "proposal": proposal_id }),
+        )
+        .await?;
+    let existing_vote = match proposal_id {
+        Some(proposal_id) => existing_vote(external_apis, proposal_id, account).await?,
+        None => None,
+    };
+
+    Ok(SnapshotVotingPower {
+        space: space.to_string(),
+        proposal_id: proposal_id.map(str::to_string),
+        account: account.to_string(),
+        voting_power: data["vp"]["vp"].as_f64().unwrap_or(0.0),
+        state: data["vp"]["vp_state"].as_str().map(str::to_string),
+        existing_vote,
+    })
+}
+
+// 1-based index of a choice given by number or (case-insensitively) by name
+fn choice_index(choices: &[String], choice: &str) -> Result<u32> {
+    let choice = choice.trim();
+    if let Ok(index) = choice.parse::<usize>()
+        && (1..=choices.len()).contains(&index)
+    {
+        return Ok(index as u32);
+    }
+    choices
+        .iter()
+        .position(|option| option.eq_ignore_ascii_case(choice))
+        .map(|i| i as u32 + 1)
+        .ok_or_else(|| {
+            anyhow!(
+                "'{}' isn't one of the proposal's choices: {}",
+                choice,
+                choices.join(", ")
+            )
+        })
+}
+
+// The vote's choice field and its EIP-712 type for the proposal's voting system.
+// Single choice takes one option; approval and ranked choice a comma-separated list;
+// weighted and quadratic "option=weight" pairs.
+fn encode_choice(
+    voting_type: &str,
+    choices: &[String],
+    choice: &str,
+) -> Result<(Value, &'static str)> {
+    match voting_type {
+        "approval" | "ranked-choice" => {
+            let indexes = choice
+                .split(',')
+                .filter(|part| !part.trim().is_empty())
+                .map(|part| choice_index(choices, part))
+                .collect::<Result<Vec<u32>>>()?;
+            if indexes.is_empty() {
+                return Err(anyhow!("Choose at least one option"));
+            }
+            Ok((json!(indexes), "uint32[]"))
+        }
+        "weighted" | "quadratic" => {
+            let mut weights = BTreeMap::new();
+            for part in choice.split(',').filter(|part| !part.trim().is_empty()) {
+                let (option, weight) = part.split_once('=').ok_or_else(|| {
+                    anyhow!("Weighted votes take option=weight pairs, e.g. \"For=60,Against=40\"")
+                })?;
+                let weight: f64 = weight
+                    .trim()
+                    .parse()
+                    .map_err(|_| anyhow!("Invalid weight '{}'", weight.trim()))?;
+                weights.insert(choice_index(choices, option)?.to_string(), weight);
+            }
+            if weights.is_empty() {
+                return Err(anyhow!("Give at least one option=weight pair"));
+            }
+            // Snapshot signs weighted choices as a JSON string
+            Ok((json!(serde_json::to_string(&weights)?), "string"))
+        }
+        _ => Ok((json!(choice_index(choices, choice)?), "uint32")),
+    }
+}
+
+/// Sign a vote on an active Snapshot proposal with a named account's key (EIP-712)
+/// and submit it to the Snapshot sequencer. Nothing is sent on-chain and no gas is
+/// paid; voting again before the proposal closes replaces the earlier vote.
+pub async fn cast_vote(
+    external_apis: &ExternalAPIService,
+    account: &Account,
+    proposal_id: &str,
+    choice: &str,
+    reason: &str,
+) -> Result<SnapshotVote> {
+    let raw = fetch_proposal(external_apis, proposal_id).await?;
+    let details = proposal(&raw);
+    if details.state != "active" {
+        return Err(anyhow!(
+            "Proposal {} is {}; Snapshot only accepts votes while it's active",
+            details.id,
+            details.state
+        ));
+    }
+    if raw["privacy"]
+        .as_str()
+        .is_some_and(|privacy| !privacy.is_empty())
+    {
+        return Err(anyhow!(
+            "Proposal {} uses shielded voting, which needs encrypted votes; vote on snapshot.org instead",
+            details.id
+        ));
+    }
+    let choices: Vec<String> = details
+        .results
+        .iter()
+        .map(|result| result.choice.clone())
+        .collect();
+    let (choice_value, choice_type) = encode_choice(&details.voting_type, &choices, choice)?;
+
+    let voter = ethers::types::Address::from_str(&account.address)?;
+    let voter = to_checksum(&voter, None);
+    let power = get_voting_power(external_apis, &details.space, Some(&details.id), &voter).await?;
+    if power.voting_power <= 0.0 {
+        return Err(anyhow!(
+            "{} has no voting power in {} for this proposal, so its vote wouldn't count",
+            account.name,
+            details.space
+        ));
+    }
+
+    // Current proposal ids are bytes32; older ones are IPFS hashes signed as strings
+    let proposal_type = if details.id.starts_with("0x") && details.id.len() == 66 {
+        "bytes32"
+    } else {
+        "string"
+    };
+    let typed_data = json!({
+        "domain": { "name": SNAPSHOT_DOMAIN_NAME, "version": SNAPSHOT_DOMAIN_VERSION },
+        "types": {
+            "Vote": [
+                { "name": "from", "type": "address" },
+                { "name": "space", "type": "string" },
+                { "name": "timestamp", "type": "uint64" },
+                { "name": "proposal", "type": proposal_type },
+                { "name": "choice", "type": choice_type },
+                { "name": "reason", "type": "string" },
+                { "name": "app", "type": "string" },
+                { "name": "metadata", "type": "string" }
+            ]
+        },
+        "primaryType": "Vote",
+        "message": {
+            "from": voter,
+            "space": details.space,
+            "timestamp": chrono::Utc::now().timestamp(),
+            "proposal": details.id,
+            "choice": choice_value,
+            "reason": reason,
+            "app": "",
+            "metadata": "{}"
+        }
+    });
+    let data: TypedData = serde_json::from_value(typed_data.clone())?;
+    let (signature, _) = signing::sign_typed_data(account, &data).await?;
+
+    let envelope = json!({
+        "address": voter,
+        "sig": format!("0x{}", signature),
+        "data": {
+            "domain": typed_data["domain"],
+            "types": typed_data["types"],
+            "message": typed_data["message"],
+        }
+    });
+    let receipt = external_apis.submit_snapshot_message(&envelope).await?;
+
+    let chosen = match &choice_value {
+        Value::Array(indexes) => indexes
+            .iter()
+            .filter_map(|index| choices.get(index.as_u64()? as usize - 1).cloned())
+            .collect::<Vec<_>>()
+            .join(", "),
+        Value::Number(index) => index
+            .as_u64()
+            .and_then(|index| choices.get(index as usize - 1).cloned())
+            .unwrap_or_default(),
+        _ => choice.to_string(),
+    };
+    Ok(SnapshotVote {
+        id: receipt["id"].as_str().map(str::to_string),
+        ipfs: receipt["ipfs"].as_str().map(str::to_string),
+        proposal_id: details.id,
+        space: details.space,
+        account: voter,
+        choice: chosen,
+        voting_power: power.voting_power,
+        replaced_previous_vote: power.existing_vote.is_some(),
+    })
+}
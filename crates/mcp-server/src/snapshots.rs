@@ -0,0 +1,188 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use shared::BalanceQuery;
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::blockchain::BlockchainService;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BalanceSnapshot {
+    pub timestamp: i64,
+    pub chain_id: u64,
+    pub address: String,
+    /// Token symbol -> formatted balance
+    pub balances: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BalancePoint {
+    pub timestamp: i64,
+    pub balance: f64,
+}
+
+// Periodic balance snapshots, mirrored to a JSONL file
+pub struct SnapshotStore {
+    path: Option<PathBuf>,
+    snapshots: RwLock<Vec<BalanceSnapshot>>,
+}
+
+impl SnapshotStore {
+    pub fn in_memory() -> Self {
+        Self {
+            path: None,
+            snapshots: RwLock::new(Vec::new()),
+        }
+    }
+
+    /// Open (or create) `<dir>/balances.jsonl`, loading existing snapshots
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+        let path = dir.join("balances.jsonl");
+
+        let mut snapshots = Vec::new();
+        if path.exists() {
+            for line in fs::read_to_string(&path)?.lines() {
+                match serde_json::from_str::<BalanceSnapshot>(line) {
+                    Ok(snapshot) => snapshots.push(snapshot),
+                    Err(e) => warn!("Skipping malformed snapshot in {}: {}", path.display(), e),
+                }
+            }
+        }
+
+        Ok(Self {
+            path: Some(path),
+            snapshots: RwLock::new(snapshots),
+        })
+    }
+
+    pub fn record(&self, snapshot: BalanceSnapshot) {
+        if let Some(path) = &self.path
+            && let Err(e) = Self::append(path, &snapshot)
+        {
+            warn!(
+                "Failed to persist balance snapshot to {}: {}",
+                path.display(),
+                e
+            );
+        }
+
+        if let Ok(mut snapshots) = self.snapshots.write() {
+            snapshots.push(snapshot);
+        }
+    }
+
+    /// Time series per token for an address since `since` (unix seconds)
+    pub fn history(
+        &self,
+        address: &str,
+        since: i64,
+        token: Option<&str>,
+    ) -> BTreeMap<String, Vec<BalancePoint>> {
+        let mut series: BTreeMap<String, Vec<BalancePoint>> = BTreeMap::new();
+        let Ok(snapshots) = self.snapshots.read() else {
+            return series;
+        };
+
+        for snapshot in snapshots
+            .iter()
+            .filter(|snapshot| snapshot.timestamp >= since)
+            .filter(|snapshot| snapshot.address.eq_ignore_ascii_case(address))
+        {
+            for (symbol, balance) in &snapshot.balances {
+                if let Some(token) = token
+                    && !symbol.eq_ignore_ascii_case(token)
+                {
+                    continue;
+                }
+                series
+                    .entry(symbol.clone())
+                    .or_default()
+                    .push(BalancePoint {
+                        timestamp: snapshot.timestamp,
+                        balance: balance.parse().unwrap_or(0.0),
+                    });
+            }
+        }
+
+        series
+    }
+
+    fn append(path: &Path, snapshot: &BalanceSnapshot) -> Result<()> {
+        let line = serde_json::to_string(snapshot)?;
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        writeln!(file, "{}", line)?;
+        Ok(())
+    }
+}
+
+/// Snapshot ETH and registry token balances for an address
+pub async fn take_snapshot(
+    blockchain_service: &BlockchainService,
+    address: &str,
+) -> Result<BalanceSnapshot> {
+    let mut balances = BTreeMap::new();
+
+    let eth = blockchain_service
+        .get_balance(BalanceQuery {
+            address: address.to_string(),
+            token: None,
+        })
+        .await?;
+    balances.insert("ETH".to_string(), eth.balance);
+
+    for token in blockchain_service.get_supported_tokens() {
+        match blockchain_service
+            .get_balance(BalanceQuery {
+                address: address.to_string(),
+                token: Some(token.address.clone()),
+            })
+            .await
+        {
+            Ok(balance) => {
+                balances.insert(token.symbol.clone(), balance.balance);
+            }
+            Err(e) => warn!("Failed to snapshot {} for {}: {}", token.symbol, address, e),
+        }
+    }
+
+    Ok(BalanceSnapshot {
+        timestamp: chrono::Utc::now().timestamp(),
+        chain_id: blockchain_service.chain_id(),
+        address: address.to_string(),
+        balances,
+    })
+}
+
+/// Background job that snapshots every address at a fixed interval
+pub fn spawn_snapshot_job(
+    blockchain_service: Arc<BlockchainService>,
+    addresses: Vec<String>,
+    store: Arc<SnapshotStore>,
+    interval: Duration,
+) {
+    info!(
+        "Snapshotting {} account balances every {}s",
+        addresses.len(),
+        interval.as_secs()
+    );
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            for address in &addresses {
+                match take_snapshot(&blockchain_service, address).await {
+                    Ok(snapshot) => store.record(snapshot),
+                    Err(e) => warn!("Balance snapshot failed for {}: {}", address, e),
+                }
+            }
+        }
+    });
+}
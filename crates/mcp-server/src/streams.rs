@@ -0,0 +1,841 @@
+use anyhow::{Result, anyhow};
+use ethers::abi::parse_abi;
+use ethers::contract::Contract;
+use ethers::providers::Middleware;
+use ethers::types::{Address, BlockNumber, H256, I256, U256};
+use ethers::utils::{format_units, keccak256};
+use serde::{Deserialize, Serialize};
+use shared::{Account, ContractCall, TransactionResult, utils};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::RwLock;
+use tracing::warn;
+
+use crate::blockchain::{BlockchainService, FeeOverrides, TokenInfo};
+use crate::vesting::{SABLIER_LOCKUP_ABI, SABLIER_STATUSES};
+
+// Sablier V2 LockupLinear (v1.1) deployments; SABLIER_LOCKUP_LINEAR overrides them
+const SABLIER_LOCKUP_LINEAR: &[(u64, &str)] = &[(1, "0xAFb979d9afAd1aD27C5eFf4E27226E3AB9e5dCC9")];
+// Superfluid's CFAv1Forwarder, deployed at the same address on every network
+const SUPERFLUID_CFA_FORWARDER: &str = "0xcfA132E353cB4E398080B9700609bb008eceB125";
+const CFA_FORWARDER_ABI: &[&str] = &[
+    "function getFlowInfo(address token, address sender, address receiver) view returns (uint256 lastUpdated, int96 flowrate, uint256 deposit, uint256 owedDeposit)",
+    "function getBufferAmountByFlowrate(address token, int96 flowrate) view returns (uint256 bufferAmount)",
+];
+const SUPER_TOKEN_ABI: &[&str] = &[
+    "function realtimeBalanceOfNow(address account) view returns (int256 availableBalance, uint256 deposit, uint256 owedDeposit, uint256 timestamp)",
+];
+const SECONDS_PER_DAY: u64 = 86_400;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StreamProtocol {
+    /// Fixed-amount streams escrowed up front in a Sablier lockup contract
+    Sablier,
+    /// Open-ended Superfluid flows of a super token, paid from the sender's balance
+    Superfluid,
+}
+
+impl FromStr for StreamProtocol {
+    type Err = anyhow::Error;
+
+    fn from_str(protocol: &str) -> Result<Self> {
+        match protocol.trim().to_lowercase().as_str() {
+            "sablier" => Ok(Self::Sablier),
+            "superfluid" => Ok(Self::Superfluid),
+            other => Err(anyhow!(
+                "Unknown stream protocol '{}'; use sablier or superfluid",
+                other
+            )),
+        }
+    }
+}
+
+/// A stream created through the server, kept so it can be looked up later
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StreamRecord {
+    pub protocol: StreamProtocol,
+    pub chain_id: u64,
+    pub contract: String,
+    /// Sablier stream id; Superfluid flows are identified by token, sender and recipient
+    pub stream_id: Option<String>,
+    pub token: String,
+    pub sender: String,
+    pub recipient: String,
+    /// Amount to stream, in the token's base units
+    pub amount: String,
+    pub start: u64,
+    pub end: u64,
+    pub transaction_hash: String,
+}
+
+// Streams created through the server; persisted as one JSON file
+pub struct StreamStore {
+    path: Option<PathBuf>,
+    streams: RwLock<Vec<StreamRecord>>,
+}
+
+impl StreamStore {
+    pub fn in_memory() -> Self {
+        Self {
+            path: None,
+            streams: RwLock::new(Vec::new()),
+        }
+    }
+
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let streams = if path.exists() {
+            serde_json::from_str(&fs::read_to_string(&path)?)?
+        } else {
+            Vec::new()
+        };
+
+        Ok(Self {
+            path: Some(path),
+            streams: RwLock::new(streams),
+        })
+    }
+
+    pub fn add(&self, record: StreamRecord) -> Result<()> {
+        let mut streams = self
+            .streams
+            .write()
+            .map_err(|_| anyhow!("Stream store lock poisoned"))?;
+        // A new Superfluid flow between the same pair replaces the old one
+        streams.retain(|existing| !same_stream(existing, &record));
+        streams.push(record);
+
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(&*streams)?)?;
+        Ok(())
+    }
+
+    pub fn list(&self) -> Vec<StreamRecord> {
+        self.streams
+            .read()
+            .map(|streams| streams.clone())
+            .unwrap_or_default()
+    }
+
+    // Streams on `chain_id` that `address` sends or receives
+    fn involving(&self, chain_id: u64, address: Address) -> Vec<StreamRecord> {
+        let address = format!("{:?}", address);
+        self.list()
+            .into_iter()
+            .filter(|record| {
+                record.chain_id == chain_id
+                    && (record.sender.eq_ignore_ascii_case(&address)
+                        || record.recipient.eq_ignore_ascii_case(&address))
+            })
+            .collect()
+    }
+}
+
+fn same_stream(a: &StreamRecord, b: &StreamRecord) -> bool {
+    a.protocol == b.protocol
+        && a.chain_id == b.chain_id
+        && a.contract.eq_ignore_ascii_case(&b.contract)
+        && match a.protocol {
+            StreamProtocol::Sablier => a.stream_id == b.stream_id,
+            StreamProtocol::Superfluid => {
+                a.token.eq_ignore_ascii_case(&b.token)
+                    && a.sender.eq_ignore_ascii_case(&b.sender)
+                    && a.recipient.eq_ignore_ascii_case(&b.recipient)
+            }
+        }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PaymentStream {
+    pub protocol: StreamProtocol,
+    pub contract: String,
+    pub stream_id: Option<String>,
+    pub sender: String,
+    pub recipient: String,
+    pub token: String,
+    pub token_address: String,
+    pub rate_per_day: String,
+    /// Everything the stream pays out; for Superfluid, the amount planned when it was created
+    pub total: Option<String>,
+    /// Paid to the recipient so far; for Superfluid, since the flow rate was last set
+    pub streamed: String,
+    /// Streamed but not withdrawn yet (Sablier; Superfluid pays straight into the
+    /// recipient's balance)
+    pub withdrawable: Option<String>,
+    /// Still to be streamed; what the sender gets back by cancelling a Sablier stream
+    pub remaining: Option<String>,
+    pub start: u64,
+    /// When a Sablier stream ends, or when a Superfluid flow was planned to be stopped
+    pub end: Option<u64>,
+    /// Sablier: "pending", "streaming", "settled", "canceled" or "depleted";
+    /// Superfluid: "streaming" or "stopped"
+    pub status: String,
+    pub cancelable: bool,
+    pub note: Option<String>,
+}
+
+impl PaymentStream {
+    fn is_active(&self) -> bool {
+        matches!(self.status.as_str(), "pending" | "streaming")
+            || self
+                .withdrawable
+                .as_deref()
+                .is_some_and(|amount| amount.parse::<f64>().unwrap_or(0.0) > 0.0)
+    }
+}
+
+/// What `create_stream` sets up
+#[derive(Debug, Clone)]
+pub struct StreamRequest {
+    pub protocol: StreamProtocol,
+    pub recipient: Address,
+    pub token: String,
+    /// Total to stream, in token units
+    pub amount: String,
+    pub duration_secs: u64,
+    /// Sablier only: nothing is withdrawable until this long after the start
+    pub cliff_secs: u64,
+    /// Sablier only: whether the sender may cancel and take back the unstreamed part
+    pub cancelable: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StreamCreation {
+    /// The token approval, when one was needed, then the stream's creation
+    pub transactions: Vec<TransactionResult>,
+    pub stream: PaymentStream,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StreamCancellation {
+    pub transaction: TransactionResult,
+    /// Returned to the sender: a Sablier stream's unstreamed deposit
+    pub refunded: Option<String>,
+    pub stream: PaymentStream,
+}
+
+/// The LockupLinear contract streams are created in on the current chain
+fn sablier_lockup(chain_id: u64) -> Result<Address> {
+    if let Ok(address) = std::env::var("SABLIER_LOCKUP_LINEAR") {
+        return Address::from_str(&address)
+            .map_err(|_| anyhow!("Invalid SABLIER_LOCKUP_LINEAR address: {}", address));
+    }
+    // Anvil forking mainnet keeps mainnet contracts
+    let chain_id = if chain_id == 31337 { 1 } else { chain_id };
+    SABLIER_LOCKUP_LINEAR
+        .iter()
+        .find(|(chain, _)| *chain == chain_id)
+        .map(|(_, address)| Address::from_str(address))
+        .transpose()?
+        .ok_or_else(|| {
+            anyhow!(
+                "No Sablier LockupLinear deployment known for chain {}; set SABLIER_LOCKUP_LINEAR",
+                chain_id
+            )
+        })
+}
+
+fn units(raw: U256, decimals: u8) -> Result<String> {
+    Ok(format_units(raw, decimals as u32)?)
+}
+
+async fn latest_timestamp(blockchain_service: &BlockchainService) -> Result<u64> {
+    Ok(blockchain_service
+        .provider()
+        .get_block(BlockNumber::Latest)
+        .await?
+        .map(|block| block.timestamp.as_u64())
+        .unwrap_or_else(|| chrono::Utc::now().timestamp() as u64))
+}
+
+fn parse_amount(amount: &str, token: &TokenInfo) -> Result<U256> {
+    let amount = utils::parse_amount(amount, token.decimals)?;
+    if amount.is_zero() {
+        return Err(anyhow!("amount must be greater than zero"));
+    }
+    Ok(amount)
+}
+
+async fn sablier_stream(
+    blockchain_service: &BlockchainService,
+    lockup: Address,
+    id: U256,
+) -> Result<PaymentStream> {
+    let lockup = Contract::new(
+        lockup,
+        parse_abi(SABLIER_LOCKUP_ABI)?,
+        blockchain_service.provider(),
+    );
+    let lockup = &lockup;
+    let amount = |name: &'static str| async move {
+        let value: U256 = lockup.method(name, id)?.call().await?;
+        Ok::<U256, anyhow::Error>(value)
+    };
+    let sender: Address = lockup.method("getSender", id)?.call().await?;
+    let recipient: Address = lockup.method("getRecipient", id)?.call().await?;
+    let token: Address = lockup.method("getAsset", id)?.call().await?;
+    let info = blockchain_service
+        .resolve_token(&format!("{:?}", token))
+        .await?;
+    let deposited = amount("getDepositedAmount").await?;
+    let refunded = amount("getRefundedAmount").await.unwrap_or_default();
+    let streamed = amount("streamedAmountOf").await?;
+    let withdrawable = amount("withdrawableAmountOf").await?;
+    let start = amount("getStartTime").await?.as_u64();
+    let end = amount("getEndTime").await?.as_u64();
+    let status: u8 = lockup.method("statusOf", id)?.call().await?;
+    let cancelable: bool = lockup.method("isCancelable", id)?.call().await?;
+    let rate = deposited * U256::from(SECONDS_PER_DAY) / U256::from((end - start).max(1));
+
+    Ok(PaymentStream {
+        protocol: StreamProtocol::Sablier,
+        contract: format!("{:?}", lockup.address()),
+        stream_id: Some(id.to_string()),
+        sender: format!("{:?}", sender),
+        recipient: format!("{:?}", recipient),
+        token: info.symbol.clone(),
+        token_address: info.address.clone(),
+        rate_per_day: units(rate, info.decimals)?,
+        total: Some(units(deposited.saturating_sub(refunded), info.decimals)?),
+        streamed: units(streamed, info.decimals)?,
+        withdrawable: Some(units(withdrawable, info.decimals)?),
+        remaining: Some(units(
+            deposited.saturating_sub(refunded).saturating_sub(streamed),
+            info.decimals,
+        )?),
+        start,
+        end: Some(end),
+        status: SABLIER_STATUSES
+            .get(status as usize)
+            .unwrap_or(&"unknown")
+            .to_string(),
+        cancelable,
+        note: None,
+    })
+}
+
+async fn superfluid_stream(
+    blockchain_service: &BlockchainService,
+    token: Address,
+    sender: Address,
+    recipient: Address,
+    record: Option<&StreamRecord>,
+) -> Result<PaymentStream> {
+    let forwarder = Address::from_str(SUPERFLUID_CFA_FORWARDER)?;
+    let forwarder = Contract::new(
+        forwarder,
+        parse_abi(CFA_FORWARDER_ABI)?,
+        blockchain_service.provider(),
+    );
+    let info = blockchain_service
+        .resolve_token(&format!("{:?}", token))
+        .await?;
+    let (last_updated, flow_rate, _, _): (U256, I256, U256, U256) = forwarder
+        .method("getFlowInfo", (token, sender, recipient))?
+        .call()
+        .await?;
+    let rate = flow_rate.max(I256::zero()).into_raw();
+    let now = latest_timestamp(blockchain_service).await?;
+    let start = last_updated.as_u64();
+    let streamed = rate * U256::from(now.saturating_sub(start));
+
+    // The planned amount and end only apply while the flow still runs at the rate it
+    // was created with
+    let planned = record.filter(|record| record.start == start && !rate.is_zero());
+    let total = planned.and_then(|record| U256::from_dec_str(&record.amount).ok());
+    let end = planned.map(|record| record.end);
+
+    let mut note = None;
+    if let Some(end) = end.filter(|end| *end <= now) {
+        note = Some(format!(
+            "Past its planned end at {}; cancel it to stop paying",
+            end
+        ));
+    } else if !rate.is_zero() {
+        let super_token = Contract::new(
+            token,
+            parse_abi(SUPER_TOKEN_ABI)?,
+            blockchain_service.provider(),
+        );
+        let (available, _, _, _): (I256, U256, U256, U256) = super_token
+            .method("realtimeBalanceOfNow", sender)?
+            .call()
+            .await?;
+        let runway = available.max(I256::zero()).into_raw() / rate;
+        if end.is_none_or(|end| now + runway.as_u64() < end) {
+            note = Some(format!(
+                "The sender's balance covers about {} more days; the flow is liquidated when it runs out",
+                runway.as_u64() / SECONDS_PER_DAY
+            ));
+        }
+    }
+
+    Ok(PaymentStream {
+        protocol: StreamProtocol::Superfluid,
+        contract: format!("{:?}", forwarder.address()),
+        stream_id: None,
+        sender: format!("{:?}", sender),
+        recipient: format!("{:?}", recipient),
+        token: info.symbol.clone(),
+        token_address: info.address.clone(),
+        rate_per_day: units(rate * U256::from(SECONDS_PER_DAY), info.decimals)?,
+        total: total.map(|total| units(total, info.decimals)).transpose()?,
+        streamed: units(streamed, info.decimals)?,
+        withdrawable: None,
+        remaining: total
+            .map(|total| units(total.saturating_sub(streamed), info.decimals))
+            .transpose()?,
+        start,
+        end,
+        status: if rate.is_zero() {
+            "stopped"
+        } else {
+            "streaming"
+        }
+        .to_string(),
+        cancelable: true,
+        note,
+    })
+}
+
+async fn read_record(
+    blockchain_service: &BlockchainService,
+    record: &StreamRecord,
+) -> Result<PaymentStream> {
+    match record.protocol {
+        StreamProtocol::Sablier => {
+            let id = record
+                .stream_id
+                .as_deref()
+                .ok_or_else(|| anyhow!("Sablier stream record without an id"))?;
+            sablier_stream(
+                blockchain_service,
+                Address::from_str(&record.contract)?,
+                U256::from_dec_str(id)?,
+            )
+            .await
+        }
+        StreamProtocol::Superfluid => {
+            superfluid_stream(
+                blockchain_service,
+                Address::from_str(&record.token)?,
+                Address::from_str(&record.sender)?,
+                Address::from_str(&record.recipient)?,
+                Some(record),
+            )
+            .await
+        }
+    }
+}
+
+/// Stream `amount` of `token` to `recipient` over `duration_secs`: a Sablier linear
+/// stream escrowing the whole amount (approving the lockup contract first if needed,
+/// with an optional cliff), or a Superfluid flow of a super token at the matching
+/// rate. Superfluid flows don't stop on their own, so one must be cancelled at its
+/// planned end.
+pub async fn create_stream(
+    blockchain_service: &BlockchainService,
+    store: &StreamStore,
+    from_account: &Account,
+    request: &StreamRequest,
+    fees: &FeeOverrides,
+) -> Result<StreamCreation> {
+    let StreamRequest {
+        protocol,
+        recipient,
+        ref token,
+        ref amount,
+        duration_secs,
+        cliff_secs,
+        cancelable,
+    } = *request;
+    if duration_secs == 0 {
+        return Err(anyhow!("duration must be greater than zero"));
+    }
+    if cliff_secs >= duration_secs {
+        return Err(anyhow!(
+            "The cliff must be shorter than the stream's duration"
+        ));
+    }
+    let sender = Address::from_str(&from_account.address)?;
+    if recipient == sender {
+        return Err(anyhow!("A stream can't pay its own sender"));
+    }
+    let info = blockchain_service.resolve_token(token).await?;
+    let token_address = Address::from_str(&info.address)?;
+    let raw_amount = parse_amount(amount, &info)?;
+    let mut transactions = Vec::new();
+
+    let (contract, stream_id, start, transaction) = match protocol {
+        StreamProtocol::Sablier => {
+            if raw_amount > U256::from(u128::MAX) {
+                return Err(anyhow!("amount is too large for a Sablier stream"));
+            }
+            let lockup = sablier_lockup(blockchain_service.chain_id())?;
+            let allowance = blockchain_service
+                .token_allowance(token_address, sender, lockup)
+                .await?;
+            if allowance < raw_amount {
+                let approval = blockchain_service
+                    .set_allowance(from_account, &info, lockup, raw_amount, fees)
+                    .await?;
+                if approval.status != "success" {
+                    return Err(anyhow!(
+                        "Approving the Sablier contract failed ({}): {}",
+                        approval.status,
+                        approval.hash
+                    ));
+                }
+                transactions.push(approval);
+            }
+
+            // (sender, recipient, totalAmount, asset, cancelable, transferable,
+            //  (cliff, total), (broker, brokerFee))
+            let params = format!(
+                "({:?},{:?},{},{:?},{},true,({},{}),({:?},0))",
+                sender,
+                recipient,
+                raw_amount,
+                token_address,
+                cancelable,
+                cliff_secs,
+                duration_secs,
+                Address::zero()
+            );
+            let call = ContractCall {
+                contract_address: format!("{:?}", lockup),
+                function_signature: "createWithDurations((address,address,uint128,address,bool,bool,(uint40,uint40),(address,uint256)))".to_string(),
+                parameters: vec![params],
+                from: None,
+            };
+            let transaction = blockchain_service
+                .write_contract(
+                    from_account,
+                    &call,
+                    "0",
+                    None,
+                    &|name| name.to_string(),
+                    fees,
+                )
+                .await?;
+            let (stream_id, start) =
+                created_stream_id(blockchain_service, lockup, &transaction).await?;
+            (lockup, Some(stream_id), start, transaction)
+        }
+        StreamProtocol::Superfluid => {
+            if cliff_secs > 0 {
+                return Err(anyhow!("Superfluid flows have no cliff"));
+            }
+            let super_token = Contract::new(
+                token_address,
+                parse_abi(SUPER_TOKEN_ABI)?,
+                blockchain_service.provider(),
+            );
+            let (available, _, _, _): (I256, U256, U256, U256) = super_token
+                .method("realtimeBalanceOfNow", sender)?
+                .call()
+                .await
+                .map_err(|_| {
+                    anyhow!(
+                        "{} isn't a Superfluid super token; wrap it into its super token (e.g. USDCx) first",
+                        info.symbol
+                    )
+                })?;
+            let rate = raw_amount / U256::from(duration_secs);
+            if rate.is_zero() {
+                return Err(anyhow!(
+                    "{} {} over {}s rounds down to a flow rate of zero",
+                    amount,
+                    info.symbol,
+                    duration_secs
+                ));
+            }
+            if rate >= U256::one() << 95 {
+                return Err(anyhow!("The flow rate is too large for Superfluid"));
+            }
+            if available.max(I256::zero()).into_raw() < raw_amount {
+                return Err(anyhow!(
+                    "{} holds {} {} but the stream needs {} plus a deposit",
+                    from_account.name,
+                    units(available.max(I256::zero()).into_raw(), info.decimals)?,
+                    info.symbol,
+                    amount
+                ));
+            }
+
+            let forwarder = Address::from_str(SUPERFLUID_CFA_FORWARDER)?;
+            let call = ContractCall {
+                contract_address: format!("{:?}", forwarder),
+                function_signature: "setFlowrate(address,address,int96)".to_string(),
+                parameters: vec![
+                    format!("{:?}", token_address),
+                    format!("{:?}", recipient),
+                    rate.to_string(),
+                ],
+                from: None,
+            };
+            let transaction = blockchain_service
+                .write_contract(
+                    from_account,
+                    &call,
+                    "0",
+                    None,
+                    &|name| name.to_string(),
+                    fees,
+                )
+                .await?;
+            if transaction.status != "success" {
+                return Err(anyhow!(
+                    "Creating the flow failed ({}): {}",
+                    transaction.status,
+                    transaction.hash
+                ));
+            }
+            let forwarder_contract = Contract::new(
+                forwarder,
+                parse_abi(CFA_FORWARDER_ABI)?,
+                blockchain_service.provider(),
+            );
+            let (last_updated, _, _, _): (U256, I256, U256, U256) = forwarder_contract
+                .method("getFlowInfo", (token_address, sender, recipient))?
+                .call()
+                .await?;
+            (forwarder, None, last_updated.as_u64(), transaction)
+        }
+    };
+    let transaction_hash = transaction.hash.clone();
+    transactions.push(transaction);
+
+    let record = StreamRecord {
+        protocol,
+        chain_id: blockchain_service.chain_id(),
+        contract: format!("{:?}", contract),
+        stream_id,
+        token: format!("{:?}", token_address),
+        sender: format!("{:?}", sender),
+        recipient: format!("{:?}", recipient),
+        amount: raw_amount.to_string(),
+        start,
+        end: start + duration_secs,
+        transaction_hash,
+    };
+    if let Err(e) = store.add(record.clone()) {
+        warn!("Failed to record stream {}: {}", record.transaction_hash, e);
+    }
+    let mut stream = read_record(blockchain_service, &record).await?;
+    if protocol == StreamProtocol::Superfluid {
+        stream.note = Some(format!(
+            "Superfluid flows run until cancelled; cancel this one at {} to stop after {} {}",
+            record.end, amount, info.symbol
+        ));
+    }
+
+    Ok(StreamCreation {
+        transactions,
+        stream,
+    })
+}
+
+// Id of the stream a createWithDurations transaction minted, read from the stream
+// NFT's Transfer from the zero address, and the block's timestamp
+async fn created_stream_id(
+    blockchain_service: &BlockchainService,
+    lockup: Address,
+    transaction: &TransactionResult,
+) -> Result<(String, u64)> {
+    if transaction.status != "success" {
+        return Err(anyhow!(
+            "Creating the stream failed ({}): {}",
+            transaction.status,
+            transaction.hash
+        ));
+    }
+    let provider = blockchain_service.provider();
+    let receipt = provider
+        .get_transaction_receipt(H256::from_str(&transaction.hash)?)
+        .await?
+        .ok_or_else(|| anyhow!("No receipt for {}", transaction.hash))?;
+    let transfer = H256::from(keccak256("Transfer(address,address,uint256)"));
+    let id = receipt
+        .logs
+        .iter()
+        .find(|log| {
+            log.address == lockup
+                && log.topics.len() == 4
+                && log.topics[0] == transfer
+                && log.topics[1] == H256::zero()
+        })
+        .map(|log| U256::from_big_endian(log.topics[3].as_bytes()))
+        .ok_or_else(|| anyhow!("{} didn't mint a stream", transaction.hash))?;
+    let start = match receipt.block_number {
+        Some(number) => provider
+            .get_block(number)
+            .await?
+            .map(|block| block.timestamp.as_u64())
+            .unwrap_or_default(),
+        None => latest_timestamp(blockchain_service).await?,
+    };
+    Ok((id.to_string(), start))
+}
+
+/// Cancel a stream the account sends: a Sablier stream by id, refunding what hasn't
+/// streamed yet (what has stays withdrawable by the recipient), or the account's
+/// Superfluid flow of `token` to `recipient`
+pub async fn cancel_stream(
+    blockchain_service: &BlockchainService,
+    from_account: &Account,
+    protocol: StreamProtocol,
+    stream_id: Option<&str>,
+    token: Option<&str>,
+    recipient: Option<Address>,
+    fees: &FeeOverrides,
+) -> Result<StreamCancellation> {
+    let sender = Address::from_str(&from_account.address)?;
+    match protocol {
+        StreamProtocol::Sablier => {
+            let id = stream_id.ok_or_else(|| anyhow!("stream_id is required for Sablier"))?;
+            let id =
+                U256::from_dec_str(id.trim()).map_err(|_| anyhow!("Invalid stream id: {}", id))?;
+            let lockup = sablier_lockup(blockchain_service.chain_id())?;
+            let stream = sablier_stream(blockchain_service, lockup, id).await?;
+            if Address::from_str(&stream.sender)? != sender {
+                return Err(anyhow!(
+                    "Stream {} is sent by {}, not {}",
+                    id,
+                    stream.sender,
+                    from_account.name
+                ));
+            }
+            if !stream.cancelable || !matches!(stream.status.as_str(), "pending" | "streaming") {
+                return Err(anyhow!(
+                    "Stream {} can't be cancelled (status {}, cancelable {})",
+                    id,
+                    stream.status,
+                    stream.cancelable
+                ));
+            }
+            let call = ContractCall {
+                contract_address: format!("{:?}", lockup),
+                function_signature: "cancel(uint256)".to_string(),
+                parameters: vec![id.to_string()],
+                from: None,
+            };
+            let transaction = blockchain_service
+                .write_contract(
+                    from_account,
+                    &call,
+                    "0",
+                    None,
+                    &|name| name.to_string(),
+                    fees,
+                )
+                .await?;
+            Ok(StreamCancellation {
+                transaction,
+                refunded: stream.remaining,
+                stream: sablier_stream(blockchain_service, lockup, id).await?,
+            })
+        }
+        StreamProtocol::Superfluid => {
+            let token = token.ok_or_else(|| anyhow!("token is required for Superfluid"))?;
+            let recipient =
+                recipient.ok_or_else(|| anyhow!("recipient is required for Superfluid"))?;
+            let token = Address::from_str(&blockchain_service.resolve_token(token).await?.address)?;
+            let stream =
+                superfluid_stream(blockchain_service, token, sender, recipient, None).await?;
+            if stream.status != "streaming" {
+                return Err(anyhow!(
+                    "{} has no {} flow to {:?}",
+                    from_account.name,
+                    stream.token,
+                    recipient
+                ));
+            }
+            // Setting the rate to zero deletes the flow and returns the deposit
+            let call = ContractCall {
+                contract_address: SUPERFLUID_CFA_FORWARDER.to_string(),
+                function_signature: "setFlowrate(address,address,int96)".to_string(),
+                parameters: vec![
+                    format!("{:?}", token),
+                    format!("{:?}", recipient),
+                    "0".to_string(),
+                ],
+                from: None,
+            };
+            let transaction = blockchain_service
+                .write_contract(
+                    from_account,
+                    &call,
+                    "0",
+                    None,
+                    &|name| name.to_string(),
+                    fees,
+                )
+                .await?;
+            Ok(StreamCancellation {
+                transaction,
+                refunded: None,
+                stream: superfluid_stream(blockchain_service, token, sender, recipient, None)
+                    .await?,
+            })
+        }
+    }
+}
+
+/// A Sablier stream by id, or every stream created through the server on the current
+/// chain that `address` sends or receives (all of them without an address)
+pub async fn get_streams(
+    blockchain_service: &BlockchainService,
+    store: &StreamStore,
+    address: Option<Address>,
+    stream_id: Option<&str>,
+) -> Result<Vec<PaymentStream>> {
+    if let Some(id) = stream_id {
+        let id = U256::from_dec_str(id.trim()).map_err(|_| anyhow!("Invalid stream id: {}", id))?;
+        let lockup = sablier_lockup(blockchain_service.chain_id())?;
+        return Ok(vec![sablier_stream(blockchain_service, lockup, id).await?]);
+    }
+
+    let chain_id = blockchain_service.chain_id();
+    let records = match address {
+        Some(address) => store.involving(chain_id, address),
+        None => store
+            .list()
+            .into_iter()
+            .filter(|record| record.chain_id == chain_id)
+            .collect(),
+    };
+    let mut streams = Vec::with_capacity(records.len());
+    for record in &records {
+        match read_record(blockchain_service, record).await {
+            Ok(stream) => streams.push(stream),
+            Err(e) => warn!("Failed to read stream {}: {}", record.transaction_hash, e),
+        }
+    }
+    Ok(streams)
+}
+
+/// Streams `owner` sends or receives that still move money, for the portfolio view
+pub async fn active_streams(
+    blockchain_service: &BlockchainService,
+    store: &StreamStore,
+    owner: Address,
+) -> Vec<PaymentStream> {
+    match get_streams(blockchain_service, store, Some(owner), None).await {
+        Ok(streams) => streams
+            .into_iter()
+            .filter(PaymentStream::is_active)
+            .collect(),
+        Err(e) => {
+            warn!("Failed to read streams of {:?}: {}", owner, e);
+            Vec::new()
+        }
+    }
+}
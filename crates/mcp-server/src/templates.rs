@@ -0,0 +1,279 @@
+use anyhow::{Result, anyhow};
+use ethers::types::U256;
+use ethers::utils::{format_units, parse_units};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use shared::BalanceQuery;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+use crate::sessions::{expand_placeholders, is_valid_name};
+use crate::tools::ToolContext;
+
+const MAX_STEPS: usize = 10;
+// Transactions a template can contain; templates don't nest or schedule other work
+const STEP_METHODS: &[&str] = &["send_eth", "send_erc20", "swap_tokens", "transfer_nft"];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TemplateStep {
+    /// Write method to run, e.g. "swap_tokens"
+    pub method: String,
+    /// Params for the method. Strings may reference template parameters as `${name}`,
+    /// and `amount` may be a share of the signer's balance such as "30%".
+    pub params: Value,
+}
+
+/// A named, parameterized sequence of transactions ("swap 30% of USDC to ETH, then
+/// send 0.1 ETH to bob") saved per namespace
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OperationTemplate {
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Parameter name -> default value; parameters without one must be given when running
+    #[serde(default)]
+    pub parameters: BTreeMap<String, Option<String>>,
+    pub steps: Vec<TemplateStep>,
+    pub created_at: i64,
+}
+
+impl OperationTemplate {
+    pub fn validate(&self) -> Result<()> {
+        if !is_valid_name(&self.name) {
+            return Err(anyhow!(
+                "Invalid template name '{}': use letters, digits and underscores, starting with a letter",
+                self.name
+            ));
+        }
+        if let Some(parameter) = self.parameters.keys().find(|name| !is_valid_name(name)) {
+            return Err(anyhow!("Invalid parameter name '{}'", parameter));
+        }
+        if self.steps.is_empty() {
+            return Err(anyhow!("A template needs at least one step"));
+        }
+        if self.steps.len() > MAX_STEPS {
+            return Err(anyhow!("Templates are limited to {} steps", MAX_STEPS));
+        }
+
+        for (index, step) in self.steps.iter().enumerate() {
+            if !STEP_METHODS.contains(&step.method.as_str()) {
+                return Err(anyhow!(
+                    "Step {} uses '{}'; template steps must be one of send_eth, send_erc20, swap_tokens or transfer_nft",
+                    index + 1,
+                    step.method
+                ));
+            }
+            if !step.params.is_object() {
+                return Err(anyhow!("Step {} params must be an object", index + 1));
+            }
+        }
+        Ok(())
+    }
+
+    /// The steps with parameters filled in from `args`, falling back to their defaults
+    pub fn instantiate(&self, args: &Value) -> Result<Vec<TemplateStep>> {
+        let args = match args {
+            Value::Null => serde_json::Map::new(),
+            Value::Object(args) => args.clone(),
+            _ => return Err(anyhow!("Template args must be an object")),
+        };
+        if let Some(unknown) = args
+            .keys()
+            .find(|name| !self.parameters.contains_key(*name))
+        {
+            return Err(anyhow!(
+                "Template '{}' has no parameter '{}'",
+                self.name,
+                unknown
+            ));
+        }
+
+        let mut values = BTreeMap::new();
+        for (name, default) in &self.parameters {
+            let value = match args.get(name) {
+                Some(Value::String(value)) => value.clone(),
+                Some(value) => value.to_string(),
+                None => default.clone().ok_or_else(|| {
+                    anyhow!("Template '{}' needs a value for '{}'", self.name, name)
+                })?,
+            };
+            values.insert(name.clone(), value);
+        }
+
+        Ok(self
+            .steps
+            .iter()
+            .map(|step| {
+                let mut params = step.params.clone();
+                expand_placeholders(&mut params, &values);
+                TemplateStep {
+                    method: step.method.clone(),
+                    params,
+                }
+            })
+            .collect())
+    }
+}
+
+/// Replace an `amount` given as a percentage with that share of the signer's current
+/// balance. Returns a description of the conversion when one was made.
+pub async fn resolve_share(
+    step: &mut TemplateStep,
+    context: &ToolContext,
+) -> Result<Option<String>> {
+    let Some(share) = step.params["amount"]
+        .as_str()
+        .and_then(|amount| amount.trim().strip_suffix('%'))
+    else {
+        return Ok(None);
+    };
+    let percent: f64 = share
+        .trim()
+        .parse()
+        .map_err(|_| anyhow!("Invalid percentage amount '{}%'", share))?;
+    if !(percent > 0.0 && percent <= 100.0) {
+        return Err(anyhow!("Percentage amounts must be between 0 and 100"));
+    }
+
+    let (signer_field, token) = match step.method.as_str() {
+        "send_eth" => ("from", None),
+        "send_erc20" => ("from", step.params["token"].as_str()),
+        "swap_tokens" => ("recipient", step.params["from_token"].as_str()),
+        other => return Err(anyhow!("{} doesn't take a percentage amount", other)),
+    };
+    let signer = step.params[signer_field].as_str().unwrap_or("");
+    let balance = context
+        .blockchain_service
+        .get_balance(BalanceQuery {
+            address: context.namespace.accounts.resolve_address(signer),
+            token: token.map(|token| token.to_string()),
+        })
+        .await?;
+
+    let decimals = balance.decimals as u32;
+    let units: U256 = parse_units(&balance.balance, decimals)?.into();
+    let basis_points = U256::from((percent * 100.0).round() as u64);
+    let amount = format_units(units * basis_points / U256::from(10_000), decimals)?;
+    let amount = if amount.contains('.') {
+        amount
+            .trim_end_matches('0')
+            .trim_end_matches('.')
+            .to_string()
+    } else {
+        amount
+    };
+    if amount == "0" {
+        return Err(anyhow!(
+            "{}% of {}'s {} balance is zero",
+            share.trim(),
+            signer,
+            token.unwrap_or("ETH")
+        ));
+    }
+
+    let note = format!(
+        "{}% of {} {} = {}",
+        share.trim(),
+        balance.balance,
+        token.unwrap_or("ETH"),
+        amount
+    );
+    step.params["amount"] = Value::String(amount);
+    Ok(Some(note))
+}
+
+// Templates per namespace, persisted as a JSON object
+pub struct TemplateStore {
+    path: Option<PathBuf>,
+    templates: RwLock<HashMap<String, BTreeMap<String, OperationTemplate>>>,
+}
+
+impl TemplateStore {
+    pub fn in_memory() -> Self {
+        Self {
+            path: None,
+            templates: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let templates = if path.exists() {
+            serde_json::from_str(&fs::read_to_string(&path)?)?
+        } else {
+            HashMap::new()
+        };
+
+        Ok(Self {
+            path: Some(path),
+            templates: RwLock::new(templates),
+        })
+    }
+
+    /// Store a template, replacing any with the same name; true when one was replaced
+    pub fn save(&self, namespace: &str, template: OperationTemplate) -> Result<bool> {
+        template.validate()?;
+        let mut templates = self
+            .templates
+            .write()
+            .map_err(|_| anyhow!("Template store lock poisoned"))?;
+        let replaced = templates
+            .entry(namespace.to_string())
+            .or_default()
+            .insert(template.name.clone(), template)
+            .is_some();
+        self.persist(&templates)?;
+        Ok(replaced)
+    }
+
+    pub fn get(&self, namespace: &str, name: &str) -> Option<OperationTemplate> {
+        self.templates
+            .read()
+            .ok()
+            .and_then(|templates| templates.get(namespace)?.get(name).cloned())
+    }
+
+    pub fn list(&self, namespace: &str) -> Vec<OperationTemplate> {
+        self.templates
+            .read()
+            .ok()
+            .and_then(|templates| {
+                templates
+                    .get(namespace)
+                    .map(|templates| templates.values().cloned().collect())
+            })
+            .unwrap_or_default()
+    }
+
+    /// Remove a template; false when there was none by that name
+    pub fn delete(&self, namespace: &str, name: &str) -> Result<bool> {
+        let mut templates = self
+            .templates
+            .write()
+            .map_err(|_| anyhow!("Template store lock poisoned"))?;
+        let removed = templates
+            .get_mut(namespace)
+            .and_then(|templates| templates.remove(name))
+            .is_some();
+        if removed {
+            self.persist(&templates)?;
+        }
+        Ok(removed)
+    }
+
+    fn persist(
+        &self,
+        templates: &HashMap<String, BTreeMap<String, OperationTemplate>>,
+    ) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(templates)?)?;
+        Ok(())
+    }
+}
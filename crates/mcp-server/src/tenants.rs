@@ -0,0 +1,480 @@
+use anyhow::{Result, anyhow};
+use ethers::types::U256;
+use ethers::utils::format_ether;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use shared::{Account, utils};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use tracing::{info, warn};
+
+use crate::accounts::AccountManager;
+use crate::audit::{AuditEntry, AuditLog};
+use crate::policy::{PolicyConfig, PolicyEngine};
+
+pub const DEFAULT_NAMESPACE: &str = "default";
+
+// Spending limits for a namespace, evaluated against its audit trail
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BudgetConfig {
+    /// Maximum ETH sent per account per rolling 24 hours (None = unlimited)
+    pub max_eth_per_day: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TenantConfig {
+    pub name: String,
+    pub auth_token: String,
+    /// Names of server accounts this tenant may use
+    #[serde(default)]
+    pub accounts: Vec<String>,
+    /// Accounts that only exist inside this tenant
+    #[serde(default)]
+    pub extra_accounts: Vec<Account>,
+    #[serde(default)]
+    pub aliases: HashMap<String, String>,
+    #[serde(default)]
+    pub policy: PolicyConfig,
+    #[serde(default)]
+    pub budget: BudgetConfig,
+}
+
+// Everything a single user (or team) sees: accounts, policy, budget and audit trail
+pub struct Namespace {
+    pub name: String,
+    pub accounts: Arc<AccountManager>,
+    pub policy: Arc<PolicyEngine>,
+    pub audit: Arc<AuditLog>,
+    pub budget: BudgetConfig,
+    /// Network selected with switch_network; None uses the server default
+    network: RwLock<Option<String>>,
+    /// ETH held against the budget by requests still running, by hold id
+    in_flight: Arc<Mutex<HashMap<u64, (String, U256)>>>,
+    next_hold: AtomicU64,
+}
+
+impl Namespace {
+    pub fn new(
+        name: &str,
+        accounts: AccountManager,
+        policy: PolicyEngine,
+        audit: AuditLog,
+        budget: BudgetConfig,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            accounts: Arc::new(accounts),
+            policy: Arc::new(policy),
+            audit: Arc::new(audit),
+            budget,
+            network: RwLock::new(None),
+            in_flight: Default::default(),
+            next_hold: AtomicU64::new(0),
+        }
+    }
+
+    pub fn network(&self) -> Option<String> {
+        self.network.read().ok().and_then(|network| network.clone())
+    }
+
+    pub fn set_network(&self, network: &str) {
+        if let Ok(mut current) = self.network.write() {
+            *current = Some(network.to_string());
+        }
+    }
+
+    /// Check ETH leaving an account (sends and contract call values) against the
+    /// namespace's rolling daily budget. An amount that passes is held against the
+    /// budget until the request sending it finishes and its audit entry is recorded.
+    pub fn check_budget(&self, account: &str, amount: U256) -> Result<()> {
+        let Some(max_eth) = self.budget.max_eth_per_day else {
+            return Ok(());
+        };
+        let max = utils::parse_amount(&format!("{:.18}", max_eth), 18)?;
+
+        let since = chrono::Utc::now().timestamp() - 24 * 3600;
+        let mut in_flight = self
+            .in_flight
+            .lock()
+            .map_err(|_| anyhow!("Budget reservations are unavailable"))?;
+        let spent = self
+            .audit
+            .entries(since, Some(account))
+            .iter()
+            .filter(|entry| entry.status == "success")
+            .filter_map(Self::eth_sent)
+            .chain(
+                in_flight
+                    .values()
+                    .filter(|(holder, _)| holder.eq_ignore_ascii_case(account))
+                    .map(|(_, amount)| *amount),
+            )
+            .fold(U256::zero(), |total, amount| total.saturating_add(amount));
+
+        if spent.saturating_add(amount) > max {
+            return Err(anyhow!(
+                "Daily budget exceeded for {}: {} ETH already sent, limit is {} ETH",
+                account,
+                format_ether(spent),
+                max_eth
+            ));
+        }
+
+        let id = self.next_hold.fetch_add(1, Ordering::Relaxed);
+        in_flight.insert(id, (account.to_string(), amount));
+        drop(in_flight);
+        let hold = BudgetHold {
+            in_flight: self.in_flight.clone(),
+            id,
+        };
+        // Outside a write request (e.g. in tests) the hold is released straight away
+        let _ = HOLDS.try_with(move |holds| holds.borrow_mut().push(hold));
+
+        Ok(())
+    }
+
+    // Wei an audited request sent, parsed the same way as when it was checked
+    fn eth_sent(entry: &AuditEntry) -> Option<U256> {
+        let eth = |amount: &Value| match amount {
+            Value::String(amount) => utils::parse_amount(amount, 18).ok(),
+            Value::Number(amount) => utils::parse_amount(&amount.to_string(), 18).ok(),
+            _ => None,
+        };
+        match entry.method.as_str() {
+            "send_eth" => eth(&entry.params["amount"]),
+            "write_contract"
+            | "send_user_operation"
+            | "broadcast_raw_transaction"
+            | "attach_signature" => eth(&entry.params["value"]),
+            // Sequential batches are counted through their individual sends
+            "send_batch"
+                if entry.params["disperse"].as_bool() == Some(true)
+                    && entry.params["token"]
+                        .as_str()
+                        .is_none_or(|token| token.eq_ignore_ascii_case("eth")) =>
+            {
+                entry.params["transfers"].as_array().map(|transfers| {
+                    transfers
+                        .iter()
+                        .filter_map(|transfer| eth(&transfer["amount"]))
+                        .fold(U256::zero(), |total, amount| total.saturating_add(amount))
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+tokio::task_local! {
+    // Budget held by the write request running on this task
+    static HOLDS: RefCell<Vec<BudgetHold>>;
+}
+
+// ETH a request was cleared to send, released when the request finishes
+struct BudgetHold {
+    in_flight: Arc<Mutex<HashMap<u64, (String, U256)>>>,
+    id: u64,
+}
+
+impl Drop for BudgetHold {
+    fn drop(&mut self) {
+        if let Ok(mut in_flight) = self.in_flight.lock() {
+            in_flight.remove(&self.id);
+        }
+    }
+}
+
+/// Run a write request, holding the budget it checks until `future` finishes; record
+/// its audit entry inside `future` so the spend is never uncounted
+pub async fn holding_budget<T>(future: impl Future<Output = T>) -> T {
+    HOLDS.scope(RefCell::new(Vec::new()), future).await
+}
+
+pub struct TenantRegistry {
+    default: Arc<Namespace>,
+    by_token: HashMap<String, Arc<Namespace>>,
+}
+
+impl TenantRegistry {
+    /// A registry where every request lands in the default namespace
+    pub fn single(default: Namespace) -> Self {
+        Self {
+            default: Arc::new(default),
+            by_token: HashMap::new(),
+        }
+    }
+
+    /// Load tenants from a JSON file; tenant accounts are picked from `server_accounts`
+    pub fn load(
+        path: impl AsRef<Path>,
+        default: Namespace,
+        server_accounts: &HashMap<String, Account>,
+        audit_dir: impl AsRef<Path>,
+    ) -> Result<Self> {
+        let path = path.as_ref();
+        let mut registry = Self::single(default);
+
+        if !path.exists() {
+            return Ok(registry);
+        }
+
+        let tenants: Vec<TenantConfig> = serde_json::from_str(&fs::read_to_string(path)?)?;
+        for tenant in tenants {
+            let mut accounts = HashMap::new();
+            for name in &tenant.accounts {
+                match server_accounts.get(name) {
+                    Some(account) => {
+                        accounts.insert(name.clone(), account.clone());
+                    }
+                    None => warn!("Tenant {} references unknown account {}", tenant.name, name),
+                }
+            }
+            for account in tenant.extra_accounts {
+                accounts.insert(account.name.to_lowercase(), account);
+            }
+
+            let namespace = Namespace::new(
+                &tenant.name,
+                AccountManager::new(accounts).with_aliases(tenant.aliases),
+                PolicyEngine::new(tenant.policy),
+                AuditLog::open(audit_dir.as_ref(), &tenant.name)?,
+                tenant.budget,
+            );
+            registry
+                .by_token
+                .insert(tenant.auth_token, Arc::new(namespace));
+        }
+
+        info!(
+            "Loaded {} tenant namespaces from {}",
+            registry.by_token.len(),
+            path.display()
+        );
+        Ok(registry)
+    }
+
+    pub fn default_namespace(&self) -> Arc<Namespace> {
+        self.default.clone()
+    }
+
+    /// The default namespace followed by every tenant
+    pub fn namespaces(&self) -> Vec<Arc<Namespace>> {
+        let mut namespaces = vec![self.default.clone()];
+        namespaces.extend(self.by_token.values().cloned());
+        namespaces
+    }
+
+    /// Look a namespace up by name, e.g. for work queued by one of its requests
+    pub fn namespace(&self, name: &str) -> Option<Arc<Namespace>> {
+        self.namespaces()
+            .into_iter()
+            .find(|namespace| namespace.name == name)
+    }
+
+    pub fn is_multi_tenant(&self) -> bool {
+        !self.by_token.is_empty()
+    }
+
+    /// Resolve the namespace for a request; tokens are mandatory once tenants are configured
+    pub fn resolve(&self, auth_token: Option<&str>) -> Result<Arc<Namespace>> {
+        if !self.is_multi_tenant() {
+            return Ok(self.default.clone());
+        }
+
+        let token = auth_token.ok_or_else(|| anyhow!("Authentication required"))?;
+        self.by_token
+            .get(token)
+            .cloned()
+            .ok_or_else(|| anyhow!("Invalid auth token"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn namespace(name: &str, audit: AuditLog, budget: BudgetConfig) -> Namespace {
+        Namespace::new(
+            name,
+            AccountManager::new(HashMap::new()),
+            PolicyEngine::new(PolicyConfig::default()),
+            audit,
+            budget,
+        )
+    }
+
+    fn record(audit: &AuditLog, method: &str, account: &str, params: Value) {
+        audit.record(
+            method,
+            Some(account.to_string()),
+            &params,
+            &Ok(json!({})),
+            None,
+        );
+    }
+
+    fn eth(amount: &str) -> U256 {
+        utils::parse_amount(amount, 18).unwrap()
+    }
+
+    fn rejection(registry: &TenantRegistry, auth_token: Option<&str>) -> String {
+        match registry.resolve(auth_token) {
+            Ok(namespace) => panic!("resolved to {}", namespace.name),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn resolve_requires_a_known_token_once_tenants_exist() {
+        let mut registry = TenantRegistry::single(namespace(
+            DEFAULT_NAMESPACE,
+            AuditLog::in_memory(DEFAULT_NAMESPACE),
+            BudgetConfig::default(),
+        ));
+        assert_eq!(registry.resolve(None).unwrap().name, DEFAULT_NAMESPACE);
+        assert_eq!(
+            registry.resolve(Some("anything")).unwrap().name,
+            DEFAULT_NAMESPACE
+        );
+
+        registry.by_token.insert(
+            "secret".to_string(),
+            Arc::new(namespace(
+                "team",
+                AuditLog::in_memory("team"),
+                BudgetConfig::default(),
+            )),
+        );
+        assert_eq!(rejection(&registry, None), "Authentication required");
+        assert_eq!(rejection(&registry, Some("wrong")), "Invalid auth token");
+        assert_eq!(registry.resolve(Some("secret")).unwrap().name, "team");
+    }
+
+    #[test]
+    fn budget_sums_eth_sent_in_the_last_day() {
+        let dir = std::env::temp_dir().join(format!("tenants-budget-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let old = AuditEntry {
+            timestamp: chrono::Utc::now().timestamp() - 25 * 3600,
+            namespace: "team".to_string(),
+            method: "send_eth".to_string(),
+            account: Some("alice".to_string()),
+            params: json!({"amount": "5"}),
+            status: "success".to_string(),
+            tx_hash: None,
+            gas_used: None,
+            gas_cost: None,
+            result: None,
+            error: None,
+        };
+        fs::write(
+            dir.join("team.jsonl"),
+            format!("{}\n", serde_json::to_string(&old).unwrap()),
+        )
+        .unwrap();
+        let audit = AuditLog::open(&dir, "team").unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        record(&audit, "send_eth", "alice", json!({"amount": "0.5"}));
+        record(&audit, "write_contract", "alice", json!({"value": "0.125"}));
+        record(
+            &audit,
+            "broadcast_raw_transaction",
+            "alice",
+            json!({"raw_transaction": "0x02", "value": "0.125"}),
+        );
+        record(
+            &audit,
+            "send_batch",
+            "alice",
+            json!({"disperse": true, "transfers": [{"amount": "0.0625"}, {"amount": 0.0625}]}),
+        );
+        // Token batches, failed sends and other accounts don't count
+        record(
+            &audit,
+            "send_batch",
+            "alice",
+            json!({"disperse": true, "token": "USDC", "transfers": [{"amount": "100"}]}),
+        );
+        audit.record(
+            "send_eth",
+            Some("alice".to_string()),
+            &json!({"amount": "3"}),
+            &Err(anyhow!("reverted")),
+            None,
+        );
+        record(&audit, "send_eth", "bob", json!({"amount": "3"}));
+
+        let namespace = namespace(
+            "team",
+            audit,
+            BudgetConfig {
+                max_eth_per_day: Some(1.0),
+            },
+        );
+        // 0.875 ETH already sent today
+        assert!(namespace.check_budget("alice", eth("0.125")).is_ok());
+        assert!(namespace.check_budget("alice", eth("0.25")).is_err());
+        assert!(namespace.check_budget("bob", eth("0.25")).is_err());
+        assert!(namespace.check_budget("carol", eth("1")).is_ok());
+    }
+
+    #[test]
+    fn attached_signatures_count_towards_the_budget() {
+        let namespace = namespace(
+            "team",
+            AuditLog::in_memory("team"),
+            BudgetConfig {
+                max_eth_per_day: Some(1.0),
+            },
+        );
+        let attached = json!({"signature": "0x01", "value": "0.5"});
+
+        record(
+            &namespace.audit,
+            "attach_signature",
+            "alice",
+            attached.clone(),
+        );
+        assert!(namespace.check_budget("alice", eth("0.5")).is_ok());
+
+        // Together the two transactions use up the whole budget
+        record(&namespace.audit, "attach_signature", "alice", attached);
+        assert!(namespace.check_budget("alice", eth("0.000001")).is_err());
+        // Ones only signed, not broadcast, send nothing
+        record(
+            &namespace.audit,
+            "attach_signature",
+            "bob",
+            json!({"signature": "0x01", "broadcast": false}),
+        );
+        assert!(namespace.check_budget("bob", eth("1")).is_ok());
+    }
+
+    #[tokio::test]
+    async fn budget_is_held_while_a_request_runs() {
+        let namespace = namespace(
+            "team",
+            AuditLog::in_memory("team"),
+            BudgetConfig {
+                max_eth_per_day: Some(1.0),
+            },
+        );
+
+        holding_budget(async {
+            assert!(namespace.check_budget("alice", eth("0.75")).is_ok());
+            // Nothing is audited yet, but the first send is still counted
+            assert!(namespace.check_budget("alice", eth("0.5")).is_err());
+            assert!(namespace.check_budget("alice", eth("0.25")).is_ok());
+        })
+        .await;
+
+        // Finished without an audited send, so the holds are released
+        assert!(namespace.check_budget("alice", eth("1")).is_ok());
+    }
+}
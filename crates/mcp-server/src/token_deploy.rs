@@ -0,0 +1,255 @@
+use anyhow::{Result, anyhow};
+use ethers::abi::{ParamType, parse_abi};
+use ethers::types::{Bytes, U256};
+use ethers::utils::format_units;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use shared::{Account, ContractCall, TransactionResult, utils};
+use std::str::FromStr;
+use tracing::warn;
+
+use crate::blockchain::{
+    BlockchainService, ContractArtifact, DeploymentResult, FeeOverrides, NameResolver, TokenInfo,
+};
+
+// Creation code of a minimal mintable ERC-20: constructor(string name, string symbol,
+// uint8 decimals, uint256 initialSupply) mints the supply to the deployer, who becomes
+// the owner and the only account allowed to mint. Names and symbols are stored in a
+// single slot, so each is limited to 32 bytes.
+const MINTABLE_ERC20_BYTECODE: &str = "0x6104c03803806104c06000396080811061009d575060005180518060201061009d576005556020015160045560205180518060201061009d57600855602001516007556040516006556060518060005533600155803360005260026020526040600020556000523360007fddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef60206000a361041e806100a26000396000f35b600080fd3461008c576004361061008c5760003560e01c806306fdde031461009157806395d89b41146100a8578063313ce567146100bf57806318160ddd146100cb57806370a08231146100e3578063a9059cbb14610168578063dd62ed3e14610113578063095ea7b3146102e357806323b872dd146101f057806340c10f191461036c5780638da5cb5b146100d7575b600080fd5b602060005260055460205260045460405260606000f35b602060005260085460205260075460405260606000f35b60065460005260206000f35b60005460005260206000f35b60015460005260206000f35b60043573ffffffffffffffffffffffffffffffffffffffff16600052600260205260406000205460005260206000f35b60043573ffffffffffffffffffffffffffffffffffffffff1660243573ffffffffffffffffffffffffffffffffffffffff16906000526003602052604060002060205260005260406000205460005260206000f35b3360043573ffffffffffffffffffffffffffffffffffffffff16602435811561008c578260005260026020526040600020805482811061008c5782900390558160005260026020526040600020805482019055600052907fddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef60206000a3600160005260206000f35b60043573ffffffffffffffffffffffffffffffffffffffff163390600052600360205260406000206020526000526040600020805460443581191561023f5780821061008c5790039055610243565b5050505b60043573ffffffffffffffffffffffffffffffffffffffff1660243573ffffffffffffffffffffffffffffffffffffffff16604435811561008c578260005260026020526040600020805482811061008c5782900390558160005260026020526040600020805482019055600052907fddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef60206000a3600160005260206000f35b3360043573ffffffffffffffffffffffffffffffffffffffff1690600052600360205260406000206020526000526040600020602435905560243560005260043573ffffffffffffffffffffffffffffffffffffffff16337f8c5be1e5ebec7d5bd14f71427d1e84f3dd0314c0f7b2291e5b200ac8c7c3b92560206000a3600160005260206000f35b60015433141561008c5760043573ffffffffffffffffffffffffffffffffffffffff161561008c57600054602435810180821161008c576000555060043573ffffffffffffffffffffffffffffffffffffffff1660005260026020526040600020805460243501905560243560005260043573ffffffffffffffffffffffffffffffffffffffff1660007fddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef60206000a300";
+const MINTABLE_ERC20_ABI: &[&str] = &[
+    "constructor(string name, string symbol, uint8 decimals, uint256 initialSupply)",
+    "function name() view returns (string)",
+    "function symbol() view returns (string)",
+    "function decimals() view returns (uint8)",
+    "function totalSupply() view returns (uint256)",
+    "function balanceOf(address account) view returns (uint256)",
+    "function allowance(address owner, address spender) view returns (uint256)",
+    "function owner() view returns (address)",
+    "function transfer(address to, uint256 amount) returns (bool)",
+    "function approve(address spender, uint256 amount) returns (bool)",
+    "function transferFrom(address from, address to, uint256 amount) returns (bool)",
+    "function mint(address to, uint256 amount)",
+    "event Transfer(address indexed from, address indexed to, uint256 value)",
+    "event Approval(address indexed owner, address indexed spender, uint256 value)",
+];
+const MAX_EMBEDDED_STRING_BYTES: usize = 32;
+
+/// What to deploy with `deploy_token`
+#[derive(Debug, Clone)]
+pub struct TokenSpec {
+    pub name: String,
+    pub symbol: String,
+    pub decimals: u8,
+    /// Whole tokens minted to the deployer, e.g. "1000000"
+    pub initial_supply: String,
+    /// A compiled token taking the same constructor arguments; the embedded
+    /// mintable ERC-20 is used when unset
+    pub artifact: Option<ContractArtifact>,
+}
+
+/// Tokens minted to an account after deployment
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenMint {
+    pub account: String,
+    pub amount: String,
+    pub transaction: TransactionResult,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenDeployment {
+    pub deployment: DeploymentResult,
+    /// The registered token, once the deployment is mined
+    pub token: Option<TokenInfo>,
+    pub initial_supply: String,
+    pub owner: String,
+    pub mints: Vec<TokenMint>,
+}
+
+fn embedded_token() -> Result<ContractArtifact> {
+    Ok(ContractArtifact {
+        name: Some("MintableERC20".to_string()),
+        abi: parse_abi(MINTABLE_ERC20_ABI)?,
+        bytecode: Bytes::from_str(MINTABLE_ERC20_BYTECODE)?,
+    })
+}
+
+// Artifacts must take the embedded token's constructor arguments, and be mintable
+// when minting was asked for
+fn check_artifact(artifact: &ContractArtifact, minting: bool) -> Result<()> {
+    let name = artifact.name.as_deref().unwrap_or("The artifact");
+    let inputs: Vec<ParamType> = artifact
+        .abi
+        .constructor()
+        .map(|constructor| {
+            constructor
+                .inputs
+                .iter()
+                .map(|input| input.kind.clone())
+                .collect()
+        })
+        .unwrap_or_default();
+    let expected = [
+        ParamType::String,
+        ParamType::String,
+        ParamType::Uint(8),
+        ParamType::Uint(256),
+    ];
+    if inputs != expected {
+        return Err(anyhow!(
+            "{} must take (string name, string symbol, uint8 decimals, uint256 initialSupply) in its constructor",
+            name
+        ));
+    }
+    let mintable = artifact
+        .abi
+        .functions_by_name("mint")
+        .is_ok_and(|functions| {
+            functions.iter().any(|function| {
+                function
+                    .inputs
+                    .iter()
+                    .map(|input| &input.kind)
+                    .eq(&[ParamType::Address, ParamType::Uint(256)])
+            })
+        });
+    if minting && !mintable {
+        return Err(anyhow!("{} has no mint(address,uint256) function", name));
+    }
+    Ok(())
+}
+
+/// Deploy an ERC-20 from `from_account`, register it so it can be used by symbol, and
+/// mint `mint_amount` whole tokens to each of `mint_to` (account names or addresses)
+pub async fn deploy_token(
+    blockchain_service: &BlockchainService,
+    from_account: &Account,
+    spec: TokenSpec,
+    mint_to: &[String],
+    mint_amount: Option<&str>,
+    resolve_name: &NameResolver<'_>,
+    fees: &FeeOverrides,
+) -> Result<TokenDeployment> {
+    let name = spec.name.trim();
+    let symbol = spec.symbol.trim();
+    if name.is_empty() || symbol.is_empty() {
+        return Err(anyhow!("A token needs a name and a symbol"));
+    }
+    if spec.decimals > 36 {
+        return Err(anyhow!(
+            "decimals must be at most 36, got {}",
+            spec.decimals
+        ));
+    }
+    let initial_supply = utils::parse_amount(&spec.initial_supply, spec.decimals)
+        .map_err(|e| anyhow!("Invalid initial supply: {}", e))?;
+    let mint_amount: Option<U256> = match (mint_to.is_empty(), mint_amount) {
+        (true, _) => None,
+        (false, Some(amount)) => Some(
+            utils::parse_amount(amount, spec.decimals)
+                .map_err(|e| anyhow!("Invalid mint amount: {}", e))?,
+        ),
+        (false, None) => return Err(anyhow!("mint_amount is required with mint_to")),
+    };
+
+    let artifact = match spec.artifact {
+        Some(artifact) => {
+            check_artifact(&artifact, mint_amount.is_some())?;
+            artifact
+        }
+        None => {
+            for (field, value) in [("name", name), ("symbol", symbol)] {
+                if value.len() > MAX_EMBEDDED_STRING_BYTES {
+                    return Err(anyhow!(
+                        "The token {} can be at most {} bytes; deploy from an artifact for longer ones",
+                        field,
+                        MAX_EMBEDDED_STRING_BYTES
+                    ));
+                }
+            }
+            embedded_token()?
+        }
+    };
+    if let Some(known) = blockchain_service
+        .get_supported_tokens()
+        .into_iter()
+        .find(|token| token.symbol.eq_ignore_ascii_case(symbol))
+    {
+        // Still deployable, but the new token takes over the symbol
+        warn!(
+            "{} is already registered at {}; registering the new deployment in its place",
+            symbol, known.address
+        );
+    }
+
+    let deployment = blockchain_service
+        .deploy_contract(
+            from_account,
+            &artifact,
+            &[
+                json!(name),
+                json!(symbol),
+                json!(spec.decimals.to_string()),
+                json!(initial_supply.to_string()),
+            ],
+            // The token mints to and is owned by its deployer, which a CREATE2
+            // deployment would make the CREATE2 deployer
+            None,
+            resolve_name,
+            fees,
+        )
+        .await?;
+    let mut result = TokenDeployment {
+        token: None,
+        initial_supply: format_units(initial_supply, u32::from(spec.decimals))?,
+        owner: from_account.address.clone(),
+        mints: Vec::new(),
+        deployment,
+    };
+    let Some(address) = result.deployment.contract_address.clone() else {
+        return Ok(result);
+    };
+    if result.deployment.status != "success" {
+        return Ok(result);
+    }
+
+    let token = TokenInfo {
+        address: address.clone(),
+        symbol: symbol.to_string(),
+        decimals: spec.decimals,
+        name: name.to_string(),
+    };
+    blockchain_service.register_token(token.clone());
+    result.token = Some(token);
+
+    if let Some(amount) = mint_amount {
+        for account in mint_to {
+            let recipient = blockchain_service
+                .resolve_address(&resolve_name(account))
+                .await?;
+            let call = ContractCall {
+                contract_address: address.clone(),
+                function_signature: "mint(address,uint256)".to_string(),
+                parameters: vec![recipient, amount.to_string()],
+                from: None,
+            };
+            let transaction = blockchain_service
+                .write_contract(
+                    from_account,
+                    &call,
+                    "0",
+                    None,
+                    &|name| name.to_string(),
+                    fees,
+                )
+                .await?;
+            result.mints.push(TokenMint {
+                account: account.clone(),
+                amount: format_units(amount, u32::from(spec.decimals))?,
+                transaction,
+            });
+        }
+    }
+
+    Ok(result)
+}
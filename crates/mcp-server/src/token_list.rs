@@ -0,0 +1,333 @@
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use shared::TokenConfig;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant, SystemTime};
+use tracing::{info, warn};
+
+use crate::blockchain::{TokenInfo, TokenRegistrySource};
+
+// How often a token file's modification time is checked for changes
+const RELOAD_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+// The mainnet tokens bundled with the service
+const BUILTIN_LIST: &str = r#"{
+  "name": "Built-in tokens",
+  "version": {"major": 1, "minor": 0, "patch": 0},
+  "tokens": [
+    {"chainId": 1, "address": "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48", "symbol": "USDC", "name": "USD Coin", "decimals": 6},
+    {"chainId": 1, "address": "0xdAC17F958D2ee523a2206206994597C13D831ec7", "symbol": "USDT", "name": "Tether USD", "decimals": 6},
+    {"chainId": 1, "address": "0x6B175474E89094C44Da98b954EedeAC495271d0F", "symbol": "DAI", "name": "Dai Stablecoin", "decimals": 18},
+    {"chainId": 1, "address": "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2", "symbol": "WETH", "name": "Wrapped Ether", "decimals": 18},
+    {"chainId": 1, "address": "0x1f9840a85d5aF5bf1D1762F925BDADdC4201F984", "symbol": "UNI", "name": "Uniswap", "decimals": 18},
+    {"chainId": 1, "address": "0x514910771AF9Ca656af840dff83E8264EcF986CA", "symbol": "LINK", "name": "ChainLink Token", "decimals": 18},
+    {"chainId": 1, "address": "0x2260FAC5E5542a773Aa44fBCfeDf7C193bc2C599", "symbol": "WBTC", "name": "Wrapped BTC", "decimals": 8}
+  ]
+}"#;
+
+/// A token list in the Uniswap token-list standard (https://tokenlists.org)
+#[derive(Debug, Clone, Deserialize)]
+pub struct TokenList {
+    pub name: String,
+    pub tokens: Vec<TokenListEntry>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenListEntry {
+    pub chain_id: u64,
+    pub address: String,
+    pub symbol: String,
+    pub name: String,
+    pub decimals: u8,
+}
+
+/// Tokens for `chain_id` from a token list, or every token in the older plain array
+/// format of `data/tokens.json`. Mainnet entries also apply on an Anvil fork.
+pub fn parse(content: &str, chain_id: u64) -> Result<Vec<TokenInfo>> {
+    let value: serde_json::Value = serde_json::from_str(content)?;
+    if value.is_array() {
+        let tokens: Vec<TokenConfig> = serde_json::from_value(value)?;
+        return Ok(tokens
+            .into_iter()
+            .map(|token| TokenInfo {
+                address: token.address,
+                symbol: token.symbol,
+                decimals: token.decimals,
+                name: token.name,
+            })
+            .collect());
+    }
+
+    let list: TokenList = serde_json::from_value(value)?;
+    Ok(list
+        .tokens
+        .into_iter()
+        .filter(|token| token.chain_id == chain_id || (token.chain_id == 1 && chain_id == 31337))
+        .map(|token| TokenInfo {
+            address: token.address,
+            symbol: token.symbol,
+            decimals: token.decimals,
+            name: token.name,
+        })
+        .collect())
+}
+
+/// A token found on-chain rather than configured, with when its metadata was read
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiscoveredToken {
+    #[serde(flatten)]
+    pub token: TokenInfo,
+    pub discovered_at: i64,
+}
+
+/// A network's tokens, indexed by symbol and address. File-backed registries reload
+/// when the file changes, checked as tokens are looked up.
+pub struct TokenRegistry {
+    source: TokenRegistrySource,
+    chain_id: u64,
+    entries: RwLock<HashMap<String, TokenInfo>>,
+    // Tokens registered at runtime, kept across reloads
+    registered: RwLock<Vec<TokenInfo>>,
+    // When the source file was last checked, and its modification time then
+    checked: Mutex<(Instant, Option<SystemTime>)>,
+    // Tokens read from their contracts, by lowercase address. They're only looked up
+    // by address so an unknown token can't take over a configured symbol.
+    discovered: RwLock<HashMap<String, DiscoveredToken>>,
+    discovered_path: Option<PathBuf>,
+}
+
+impl TokenRegistry {
+    pub fn load(source: TokenRegistrySource, chain_id: u64) -> Result<Self> {
+        let registry = Self {
+            source,
+            chain_id,
+            entries: RwLock::new(HashMap::new()),
+            registered: RwLock::new(Vec::new()),
+            checked: Mutex::new((Instant::now(), None)),
+            discovered: RwLock::new(HashMap::new()),
+            discovered_path: None,
+        };
+        registry.refresh()?;
+        Ok(registry)
+    }
+
+    /// Keep discovered tokens in the JSON file at `path`, so they're still known after a
+    /// restart
+    pub fn with_discovered_file(mut self, path: PathBuf) -> Self {
+        if path.exists() {
+            match fs::read_to_string(&path)
+                .map_err(anyhow::Error::from)
+                .and_then(|content| Ok(serde_json::from_str::<Vec<DiscoveredToken>>(&content)?))
+            {
+                Ok(tokens) => {
+                    if let Ok(discovered) = self.discovered.get_mut() {
+                        *discovered = tokens
+                            .into_iter()
+                            .map(|token| (token.token.address.to_lowercase(), token))
+                            .collect();
+                    }
+                }
+                Err(e) => warn!("Ignoring discovered tokens in {}: {}", path.display(), e),
+            }
+        }
+        self.discovered_path = Some(path);
+        self
+    }
+
+    pub fn source(&self) -> &TokenRegistrySource {
+        &self.source
+    }
+
+    fn modified(&self) -> Option<SystemTime> {
+        match &self.source {
+            TokenRegistrySource::File(path) => fs::metadata(path).and_then(|m| m.modified()).ok(),
+            _ => None,
+        }
+    }
+
+    /// Reload the tokens from the source, returning how many it lists
+    pub fn refresh(&self) -> Result<usize> {
+        let modified = self.modified();
+        let tokens = match &self.source {
+            TokenRegistrySource::Builtin => parse(BUILTIN_LIST, 1)?,
+            TokenRegistrySource::File(path) => {
+                let content = fs::read_to_string(path)
+                    .map_err(|e| anyhow!("Couldn't read token list {}: {}", path.display(), e))?;
+                parse(&content, self.chain_id)
+                    .map_err(|e| anyhow!("Invalid token list {}: {}", path.display(), e))?
+            }
+            TokenRegistrySource::Tokens(tokens) => tokens.clone(),
+        };
+        let count = tokens.len();
+
+        // Index by symbol and by address for direct lookups
+        let mut entries = HashMap::new();
+        for token in tokens {
+            entries.insert(token.symbol.to_lowercase(), token.clone());
+            entries.insert(token.address.to_lowercase(), token);
+        }
+        if let Ok(mut current) = self.entries.write() {
+            *current = entries;
+        }
+        let registered = self
+            .registered
+            .read()
+            .map(|registered| registered.clone())
+            .unwrap_or_default();
+        for token in registered {
+            self.insert(token);
+        }
+
+        if let Ok(mut checked) = self.checked.lock() {
+            *checked = (Instant::now(), modified);
+        }
+        Ok(count)
+    }
+
+    // Reload when the source file has changed, looking at most every few seconds
+    fn reload_if_changed(&self) {
+        let TokenRegistrySource::File(path) = &self.source else {
+            return;
+        };
+        {
+            let Ok(mut checked) = self.checked.lock() else {
+                return;
+            };
+            if checked.0.elapsed() < RELOAD_CHECK_INTERVAL {
+                return;
+            }
+            checked.0 = Instant::now();
+            if self.modified() == checked.1 {
+                return;
+            }
+        }
+        // A failed reload (e.g. a half-written file) keeps the current tokens and is
+        // retried at the next check
+        match self.refresh() {
+            Ok(count) => info!("Reloaded {} tokens from {}", count, path.display()),
+            Err(e) => warn!("Keeping the current tokens: {}", e),
+        }
+    }
+
+    pub fn get(&self, identifier: &str) -> Option<TokenInfo> {
+        self.reload_if_changed();
+        self.entries
+            .read()
+            .ok()
+            .and_then(|entries| entries.get(&identifier.to_lowercase()).cloned())
+    }
+
+    pub fn tokens(&self) -> Vec<TokenInfo> {
+        self.reload_if_changed();
+        let Ok(entries) = self.entries.read() else {
+            return Vec::new();
+        };
+        entries
+            .values()
+            .filter(|token| token.address.starts_with("0x") && token.address.len() == 42)
+            .cloned()
+            .collect()
+    }
+
+    /// Add a token, replacing any registered under the same symbol or address; it
+    /// survives reloads of the source
+    pub fn register(&self, token: TokenInfo) {
+        if let Ok(mut registered) = self.registered.write() {
+            registered.retain(|known| !Self::same_token(known, &token));
+            registered.push(token.clone());
+        }
+        self.insert(token);
+    }
+
+    fn insert(&self, token: TokenInfo) {
+        if let Ok(mut entries) = self.entries.write() {
+            entries.retain(|_, known| !Self::same_token(known, &token));
+            entries.insert(token.symbol.to_lowercase(), token.clone());
+            entries.insert(token.address.to_lowercase(), token);
+        }
+    }
+
+    /// A token discovered at `address` no longer than `max_age` ago
+    pub fn discovered(&self, address: &str, max_age: Duration) -> Option<TokenInfo> {
+        let now = chrono::Utc::now().timestamp();
+        self.discovered
+            .read()
+            .ok()?
+            .get(&address.to_lowercase())
+            .filter(|discovered| now - discovered.discovered_at < max_age.as_secs() as i64)
+            .map(|discovered| discovered.token.clone())
+    }
+
+    /// Remember a token read from its contract, replacing what was known at its address
+    pub fn discover(&self, token: TokenInfo) {
+        if let Ok(mut discovered) = self.discovered.write() {
+            discovered.insert(
+                token.address.to_lowercase(),
+                DiscoveredToken {
+                    token,
+                    discovered_at: chrono::Utc::now().timestamp(),
+                },
+            );
+        }
+        self.save_discovered();
+    }
+
+    /// Forget the token discovered at `address`, or every discovered token, so they're
+    /// read from their contracts again; returns how many were forgotten
+    pub fn forget(&self, address: Option<&str>) -> usize {
+        let forgotten = match self.discovered.write() {
+            Ok(mut discovered) => match address {
+                Some(address) => discovered
+                    .remove(&address.to_lowercase())
+                    .into_iter()
+                    .count(),
+                None => discovered.drain().count(),
+            },
+            Err(_) => 0,
+        };
+        if forgotten > 0 {
+            self.save_discovered();
+        }
+        forgotten
+    }
+
+    /// Discovered tokens, oldest first
+    pub fn discovered_tokens(&self) -> Vec<DiscoveredToken> {
+        let mut tokens: Vec<DiscoveredToken> = self
+            .discovered
+            .read()
+            .map(|discovered| discovered.values().cloned().collect())
+            .unwrap_or_default();
+        tokens.sort_by_key(|token| token.discovered_at);
+        tokens
+    }
+
+    fn save_discovered(&self) {
+        let Some(path) = &self.discovered_path else {
+            return;
+        };
+        if let Err(e) = Self::write_discovered(path, &self.discovered_tokens()) {
+            warn!(
+                "Couldn't save discovered tokens to {}: {}",
+                path.display(),
+                e
+            );
+        }
+    }
+
+    fn write_discovered(path: &Path, tokens: &[DiscoveredToken]) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(tokens)?)?;
+        Ok(())
+    }
+
+    fn same_token(a: &TokenInfo, b: &TokenInfo) -> bool {
+        a.symbol.eq_ignore_ascii_case(&b.symbol) || a.address.eq_ignore_ascii_case(&b.address)
+    }
+}
@@ -0,0 +1,432 @@
+use anyhow::Result;
+use ethers::{
+    abi::{ParamType, Token, decode, encode},
+    providers::{Middleware, RawCall},
+    types::{Address, Bytes, TransactionRequest, U256, U512, spoof},
+    utils::id,
+};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use tracing::{info, warn};
+
+use crate::blockchain::{BlockchainService, EthProvider};
+use crate::external_apis::ExternalAPIService;
+
+// Getters of tokens that take a fee on transfers
+const FEE_GETTERS: &[&str] = &[
+    "_taxFee()",
+    "_liquidityFee()",
+    "buyTax()",
+    "sellTax()",
+    "totalFees()",
+];
+
+// Functions that let the owner change transfer fees
+const FEE_SETTERS: &[&str] = &[
+    "setTaxFeePercent(uint256)",
+    "setFee(uint256)",
+    "setFees(uint256,uint256)",
+    "setBuyFee(uint256)",
+    "setSellFee(uint256)",
+    "setTaxes(uint256,uint256)",
+];
+
+// Functions that let the owner restrict who can trade
+const CONTROL_FUNCTIONS: &[&str] = &[
+    "blacklist(address)",
+    "addToBlacklist(address)",
+    "setBots(address[])",
+    "setMaxTxAmount(uint256)",
+    "setMaxTxPercent(uint256)",
+    "setTradingEnabled(bool)",
+    "enableTrading()",
+    "mint(address,uint256)",
+];
+
+// Fees above this share of a transfer make a token high risk
+const HIGH_FEE_PERCENT: f64 = 10.0;
+
+// Runtime code swapped in for the pair during the sell simulation. Called with
+// (token, recipient, amount) it reads the recipient's balance, transfers `amount` from
+// the pair, reads the balance again and returns (before, after); a failing call's revert
+// data is passed through. Any other calldata (e.g. the token calling back into the pair)
+// returns nothing.
+//
+//   CALLDATASIZE PUSH1 0x60 EQ PUSH1 start JUMPI STOP
+//   start: balanceOf(recipient) -> mem[0x80]
+//          transfer(recipient, amount)
+//          balanceOf(recipient) -> mem[0xa0]
+//          RETURN mem[0x80..0xc0]
+//   fail:  RETURNDATACOPY, REVERT
+const SELL_PROBE: &str = "36606014600857005b6370a0823160e01b60005260203560045260206080602460006000355afa15607c5763a9059cbb60e01b600052602035600452604035602452600060006044600060006000355af115607c576370a0823160e01b600052602035600452602060a0602460006000355afa15607c5760406080f35b3d600060003e3d6000fd";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenSafetyReport {
+    pub address: String,
+    pub symbol: String,
+    pub verified_source: Option<bool>,
+    pub owner: Option<String>,
+    pub ownership_renounced: Option<bool>,
+    pub sell_simulation: String, // "ok", "taxed", "blocked", "no_liquidity" or "reverted"
+    /// Share of a simulated transfer out of the pool that the recipient didn't receive
+    pub transfer_fee_percent: Option<f64>,
+    pub fee_getters: Vec<String>,
+    pub fee_setters: Vec<String>,
+    pub control_functions: Vec<String>,
+    pub risk_level: String, // "low", "medium" or "high"
+    pub warnings: Vec<String>,
+}
+
+impl TokenSafetyReport {
+    pub fn summary(&self) -> String {
+        if self.warnings.is_empty() {
+            format!(
+                "{} ({}): {} risk",
+                self.symbol, self.address, self.risk_level
+            )
+        } else {
+            format!(
+                "{} ({}): {} risk - {}",
+                self.symbol,
+                self.address,
+                self.risk_level,
+                self.warnings.join("; ")
+            )
+        }
+    }
+}
+
+/// Run safety heuristics against a token before the first interaction with it
+pub async fn assess_token(
+    blockchain_service: &BlockchainService,
+    external_apis: &ExternalAPIService,
+    token: &str,
+) -> Result<TokenSafetyReport> {
+    let token_info = blockchain_service.resolve_token(token).await?;
+    let token_address = Address::from_str(&token_info.address)?;
+    let provider = blockchain_service.provider();
+    let mut warnings = Vec::new();
+    let mut high_risk = false;
+
+    info!("Assessing safety of token {}", token_info.address);
+
+    // Verified source on Etherscan
+    let verified_source = match external_apis
+        .get_contract_source(blockchain_service.chain_id(), &token_info.address)
+        .await
+    {
+        Ok(Some(source)) => Some(!source["SourceCode"].as_str().unwrap_or("").is_empty()),
+        Ok(None) => None,
+        Err(e) => {
+            warn!("Etherscan lookup failed for {}: {}", token_info.address, e);
+            None
+        }
+    };
+    if verified_source == Some(false) {
+        warnings.push("Contract source is not verified on Etherscan".to_string());
+    }
+
+    // Ownership / renounce status
+    let owner = call(&provider, None, token_address, "owner()", &[])
+        .await
+        .ok()
+        .and_then(|data| decode(&[ParamType::Address], &data).ok())
+        .and_then(|tokens| tokens.into_iter().next())
+        .and_then(|token| token.into_address());
+    let ownership_renounced = owner.map(|owner| owner.is_zero());
+
+    // Tax-on-transfer and trading controls, detected from dispatcher selectors in the bytecode
+    let code = provider.get_code(token_address, None).await?;
+    let fee_getters = find_functions(&code, FEE_GETTERS);
+    let fee_setters = find_functions(&code, FEE_SETTERS);
+    let control_functions = find_functions(&code, CONTROL_FUNCTIONS);
+    if !fee_getters.is_empty() {
+        warnings.push(format!("Charges transfer fees: {}", fee_getters.join(", ")));
+    }
+    if !fee_setters.is_empty() && ownership_renounced != Some(true) {
+        warnings.push(format!(
+            "Owner-adjustable transfer fees: {}",
+            fee_setters.join(", ")
+        ));
+    }
+    if !control_functions.is_empty() && ownership_renounced != Some(true) {
+        warnings.push(format!(
+            "Owner can restrict trading: {}",
+            control_functions.join(", ")
+        ));
+    }
+
+    // Honeypot heuristic: simulate moving tokens out of the WETH pair and measure what arrives
+    let mut transfer_fee_percent = None;
+    let sell_simulation = match simulate_sell(blockchain_service, token_address).await {
+        Ok(Some(fee_percent)) => {
+            transfer_fee_percent = Some(fee_percent);
+            sell_outcome(fee_percent).to_string()
+        }
+        Ok(None) => "no_liquidity".to_string(),
+        Err(e) => {
+            high_risk = true;
+            warnings.push(format!("Simulated sell failed: {}", e));
+            "reverted".to_string()
+        }
+    };
+    match sell_simulation.as_str() {
+        "no_liquidity" => {
+            high_risk = true;
+            warnings.push("No Uniswap V2 liquidity against WETH".to_string());
+        }
+        "blocked" => {
+            high_risk = true;
+            warnings.push("Simulated transfer out of the pool delivered nothing".to_string());
+        }
+        "taxed" => {
+            let fee_percent = transfer_fee_percent.unwrap_or_default();
+            high_risk |= fee_percent > HIGH_FEE_PERCENT;
+            warnings.push(format!(
+                "Simulated transfer lost {:.2}% to fees",
+                fee_percent
+            ));
+        }
+        _ => {}
+    }
+
+    let risk_level = if high_risk {
+        "high"
+    } else if warnings.is_empty() {
+        "low"
+    } else {
+        "medium"
+    };
+
+    Ok(TokenSafetyReport {
+        address: token_info.address,
+        symbol: token_info.symbol,
+        verified_source,
+        owner: owner.map(|owner| format!("{:#x}", owner)),
+        ownership_renounced,
+        sell_simulation,
+        transfer_fee_percent,
+        fee_getters,
+        fee_setters,
+        control_functions,
+        risk_level: risk_level.to_string(),
+        warnings,
+    })
+}
+
+// Percent of a transfer out of the token's WETH pair that its recipient doesn't receive,
+// or None when there's no pair or it holds no tokens
+async fn simulate_sell(
+    blockchain_service: &BlockchainService,
+    token: Address,
+) -> Result<Option<f64>> {
+    let provider = blockchain_service.provider();
+    let router_config = blockchain_service.router_config();
+    let router = Address::from_str(&router_config.uniswap_v2_router)?;
+    let weth = Address::from_str(&router_config.weth)?;
+
+    let factory_data = call(&provider, None, router, "factory()", &[]).await?;
+    let factory = decode(&[ParamType::Address], &factory_data)?
+        .into_iter()
+        .next()
+        .and_then(|token| token.into_address())
+        .unwrap_or_default();
+
+    let pair_data = call(
+        &provider,
+        None,
+        factory,
+        "getPair(address,address)",
+        &[Token::Address(token), Token::Address(weth)],
+    )
+    .await?;
+    let pair = decode(&[ParamType::Address], &pair_data)?
+        .into_iter()
+        .next()
+        .and_then(|token| token.into_address())
+        .unwrap_or_default();
+    if pair.is_zero() {
+        return Ok(None);
+    }
+
+    let balance_data = call(
+        &provider,
+        None,
+        token,
+        "balanceOf(address)",
+        &[Token::Address(pair)],
+    )
+    .await?;
+    let pair_balance = decode(&[ParamType::Uint(256)], &balance_data)?
+        .into_iter()
+        .next()
+        .and_then(|token| token.into_uint())
+        .unwrap_or_default();
+    if pair_balance.is_zero() {
+        return Ok(None);
+    }
+
+    // A token that refuses transfers initiated from its own pool can't be sold. The
+    // recipient is an ordinary address, so fee exemptions for the router don't hide a tax.
+    let amount = (pair_balance / U256::from(1000)).max(U256::one());
+    let recipient = Address::repeat_byte(0x5e);
+    let probe_call = encode(&[
+        Token::Address(token),
+        Token::Address(recipient),
+        Token::Uint(amount),
+    ]);
+    let tx = TransactionRequest::new().to(pair).data(probe_call).into();
+    let state = spoof::code(pair, Bytes::from(hex::decode(SELL_PROBE)?));
+    let balances = provider.call_raw(&tx).state(&state).await?;
+    let (before, after) =
+        match decode(&[ParamType::Uint(256), ParamType::Uint(256)], &balances)?.as_slice() {
+            [Token::Uint(before), Token::Uint(after)] => (*before, *after),
+            _ => return Err(anyhow::anyhow!("Unexpected sell probe output")),
+        };
+
+    // Quote a sell back to WETH through the router
+    call(
+        &provider,
+        None,
+        router,
+        "getAmountsOut(uint256,address[])",
+        &[
+            Token::Uint(amount),
+            Token::Array(vec![Token::Address(token), Token::Address(weth)]),
+        ],
+    )
+    .await?;
+
+    Ok(Some(transfer_fee_percent(amount, before, after)))
+}
+
+// Percent of `sent` missing from the recipient's balance change
+fn transfer_fee_percent(sent: U256, before: U256, after: U256) -> f64 {
+    if sent.is_zero() {
+        return 0.0;
+    }
+    let received = after.saturating_sub(before).min(sent);
+    let basis_points = (sent - received).full_mul(U256::from(10_000)) / U512::from(sent);
+    basis_points.low_u64() as f64 / 100.0
+}
+
+fn sell_outcome(fee_percent: f64) -> &'static str {
+    if fee_percent >= 100.0 {
+        "blocked"
+    } else if fee_percent > 0.0 {
+        "taxed"
+    } else {
+        "ok"
+    }
+}
+
+fn find_functions(code: &Bytes, signatures: &[&str]) -> Vec<String> {
+    signatures
+        .iter()
+        .filter(|signature| {
+            // Solidity dispatchers compare against selectors pushed with PUSH4 (0x63)
+            let mut needle = vec![0x63];
+            needle.extend_from_slice(&id(signature)[..4]);
+            code.windows(needle.len())
+                .any(|window| window == needle.as_slice())
+        })
+        .map(|signature| signature.to_string())
+        .collect()
+}
+
+async fn call(
+    provider: &EthProvider,
+    from: Option<Address>,
+    to: Address,
+    signature: &str,
+    args: &[Token],
+) -> Result<Bytes> {
+    let mut data = id(signature)[..4].to_vec();
+    data.extend(encode(args));
+
+    let mut tx = TransactionRequest::new().to(to).data(data);
+    if let Some(from) = from {
+        tx = tx.from(from);
+    }
+
+    Ok(provider.call(&tx.into(), None).await?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Bytecode with a PUSH4 for each signature's selector, as a dispatcher has
+    fn dispatcher(signatures: &[&str]) -> Bytes {
+        let mut code = Vec::new();
+        for signature in signatures {
+            code.push(0x63);
+            code.extend_from_slice(&id(signature)[..4]);
+            code.extend_from_slice(&[0x14, 0x61, 0x00, 0x00, 0x57]);
+        }
+        code.into()
+    }
+
+    #[test]
+    fn fee_getters_and_setters_are_found_separately() {
+        let code = dispatcher(&["buyTax()", "sellTax()", "setFee(uint256)", "owner()"]);
+        assert_eq!(
+            find_functions(&code, FEE_GETTERS),
+            ["buyTax()", "sellTax()"]
+        );
+        assert_eq!(find_functions(&code, FEE_SETTERS), ["setFee(uint256)"]);
+        assert!(find_functions(&code, CONTROL_FUNCTIONS).is_empty());
+
+        let getters_only = dispatcher(&["_taxFee()"]);
+        assert_eq!(find_functions(&getters_only, FEE_GETTERS), ["_taxFee()"]);
+        assert!(find_functions(&getters_only, FEE_SETTERS).is_empty());
+    }
+
+    #[test]
+    fn transfer_fee_is_measured_from_the_balance_change() {
+        let sent = U256::from(1000);
+        let before = U256::from(7);
+        assert_eq!(transfer_fee_percent(sent, before, U256::from(1007)), 0.0);
+        assert_eq!(transfer_fee_percent(sent, before, U256::from(957)), 5.0);
+        assert_eq!(transfer_fee_percent(sent, before, U256::from(7)), 100.0);
+        // A balance that grows by more than was sent (reflection rewards) isn't a fee
+        assert_eq!(transfer_fee_percent(sent, before, U256::from(2000)), 0.0);
+        // A balance that shrinks counts as receiving nothing
+        assert_eq!(transfer_fee_percent(sent, before, U256::zero()), 100.0);
+
+        let large = U256::MAX / 8 * 4;
+        assert_eq!(
+            transfer_fee_percent(large, U256::zero(), large - large / 4),
+            25.0
+        );
+    }
+
+    #[test]
+    fn sell_outcome_follows_the_measured_fee() {
+        assert_eq!(sell_outcome(0.0), "ok");
+        assert_eq!(sell_outcome(0.01), "taxed");
+        assert_eq!(sell_outcome(99.99), "taxed");
+        assert_eq!(sell_outcome(100.0), "blocked");
+    }
+
+    #[test]
+    fn sell_probe_jumps_to_jumpdests() {
+        let code = hex::decode(SELL_PROBE).unwrap();
+        let mut targets = Vec::new();
+        let mut pc = 0;
+        while pc < code.len() {
+            match code[pc] {
+                // PUSH1 followed by JUMPI
+                0x60 if code.get(pc + 2) == Some(&0x57) => targets.push(code[pc + 1] as usize),
+                _ => {}
+            }
+            pc += match code[pc] {
+                0x60 => 2,
+                0x63 => 5,
+                _ => 1,
+            };
+        }
+        assert_eq!(targets.len(), 4);
+        assert!(targets.iter().all(|&target| code[target] == 0x5b));
+    }
+}
@@ -1,15 +1,34 @@
 use anyhow::Result;
 use async_trait::async_trait;
+use ethers::types::Address;
 use serde_json::{Value, json};
 use std::collections::HashMap;
+use std::str::FromStr;
 use std::sync::Arc;
 use tracing::{error, info};
 
 use shared::{Account, DocumentQuery};
 
-use crate::blockchain::BlockchainService;
+use crate::aggregator::{self, Aggregator, QuoteRequest};
+use crate::blockchain::{BlockchainService, FeeOverrides, NetworkRegistry};
 use crate::external_apis::ExternalAPIService;
+use crate::inbox::InboxStore;
+use crate::pnl;
+use crate::policy::{PolicyEngine, SwapApprovals};
 use crate::rag_service::RAGService;
+use crate::scanner::CheckpointStore;
+use crate::scheduler::JobStore;
+use crate::screening::AddressScreener;
+use crate::session_keys::SessionKeyStore;
+use crate::sessions::SessionStore;
+use crate::snapshots::SnapshotStore;
+use crate::streams::StreamStore;
+use crate::templates::TemplateStore;
+use crate::tenants::Namespace;
+use crate::token_safety;
+use crate::topups::TopUpStore;
+use crate::tx_tracker::TxTracker;
+use crate::walletconnect::WalletStore;
 
 #[derive(Clone)]
 pub struct ToolContext {
@@ -17,6 +36,48 @@ pub struct ToolContext {
     pub accounts: Arc<HashMap<String, Account>>,
     pub external_apis: Arc<ExternalAPIService>,
     pub rag_service: Arc<RAGService>,
+    pub policy: Arc<PolicyEngine>,
+    pub screener: Arc<AddressScreener>,
+    pub namespace: Arc<Namespace>,
+    pub networks: Arc<NetworkRegistry>,
+    pub snapshots: Arc<SnapshotStore>,
+    pub checkpoints: Arc<CheckpointStore>,
+    pub templates: Arc<TemplateStore>,
+    pub jobs: Arc<JobStore>,
+    pub inbox: Arc<InboxStore>,
+    pub streams: Arc<StreamStore>,
+    pub session_keys: Arc<SessionKeyStore>,
+    pub tx_tracker: Arc<TxTracker>,
+    pub wallets: Arc<WalletStore>,
+    pub top_ups: Arc<TopUpStore>,
+    pub sessions: Arc<SessionStore>,
+    /// Key of the caller's conversation in `sessions`, scoped to its namespace
+    pub session: Option<String>,
+}
+
+impl ToolContext {
+    /// The same shared services, scoped to another namespace's accounts and policy
+    pub fn with_namespace(&self, namespace: Arc<Namespace>) -> Self {
+        Self {
+            accounts: Arc::new(namespace.accounts.as_map()),
+            policy: namespace.policy.clone(),
+            namespace,
+            ..self.clone()
+        }
+    }
+
+    /// Attach the client's conversation so its variables resolve in request params
+    pub fn with_session(mut self, session_id: Option<&str>) -> Self {
+        self.session = session_id.map(|id| format!("{}/{}", self.namespace.name, id));
+        self
+    }
+
+    /// Point the context at the network the namespace has selected
+    pub fn on_selected_network(mut self) -> Result<Self> {
+        let network = self.namespace.network();
+        self.blockchain_service = self.networks.service(network.as_deref())?;
+        Ok(self)
+    }
 }
 
 #[async_trait]
@@ -49,12 +110,20 @@ impl ToolRegistry {
             .ok_or_else(|| anyhow::anyhow!("Tool not found: {}", name))
     }
 
+    pub fn tools(&self) -> impl Iterator<Item = &dyn Tool> {
+        self.tools.values().map(|t| t.as_ref())
+    }
+
     pub fn register_default_tools(&mut self) {
         self.register_tool(Box::new(SearchWebTool));
         self.register_tool(Box::new(TokenPriceTool));
         self.register_tool(Box::new(SearchDocsTool));
         self.register_tool(Box::new(GetDocsTool));
         self.register_tool(Box::new(SwapTokensTool));
+        self.register_tool(Box::new(TokenSafetyTool));
+        self.register_tool(Box::new(NftBalanceTool));
+        self.register_tool(Box::new(NftOwnerTool));
+        self.register_tool(Box::new(TransferNftTool));
     }
 }
 
@@ -221,26 +290,146 @@ impl Tool for SwapTokensTool {
             recipient
         };
 
-        // In a real implementation, you would:
-        // 1. Resolve token addresses
-        // 2. Calculate exchange rate
-        // 3. Execute swap via DEX (e.g., Uniswap)
+        // Run safety checks on tokens outside the configured registry before touching them
+        let acknowledge_risk = params["acknowledge_risk"].as_bool().unwrap_or(false);
+        let mut safety_reports = Vec::new();
+        for token in [&from_token, &to_token] {
+            if context.blockchain_service.is_registered_token(token) {
+                continue;
+            }
+
+            let report = token_safety::assess_token(
+                &context.blockchain_service,
+                &context.external_apis,
+                token,
+            )
+            .await?;
+            if report.risk_level != "low" && !acknowledge_risk {
+                return Err(anyhow::anyhow!(
+                    "Token safety check requires confirmation: {}. Confirm with the user and retry with acknowledge_risk set to proceed",
+                    report.summary()
+                ));
+            }
+            safety_reports.push(report);
+        }
+
+        // "0x", "1inch" or "aggregator" swap through a DEX aggregator; with no dex the
+        // best aggregator quote is used when it beats Uniswap
+        let dex = params["dex"].as_str();
+        let aggregators = match dex {
+            Some(dex) => Aggregator::from_dex(dex),
+            None => Some(Aggregator::ALL.to_vec()),
+        };
+        let aggregator_only = dex.is_some() && aggregators.is_some();
+        let slippage_percent = slippage.parse::<f64>().unwrap_or(0.5);
+
+        // Quote first so a swap into a thin pool is caught before anything is approved
+        let uniswap_quote = match context
+            .blockchain_service
+            .quote_swap(
+                &from_token,
+                &to_token,
+                &amount,
+                dex.filter(|_| !aggregator_only),
+                None,
+            )
+            .await
+        {
+            Ok(quote) => Some(quote),
+            // Uniswap's mid-price is only a reference for aggregator swaps
+            Err(_) if aggregator_only => None,
+            Err(e) => return Err(e),
+        };
+        let aggregator_quote = match &aggregators {
+            Some(aggregators) => match aggregator::best_quote(
+                &context.blockchain_service,
+                &context.external_apis,
+                aggregators,
+                &QuoteRequest {
+                    from_token: from_token.clone(),
+                    to_token: to_token.clone(),
+                    amount: amount.clone(),
+                    taker: Address::from_str(&from_account.address)?,
+                    slippage_percent,
+                },
+            )
+            .await
+            {
+                Ok(quote) => Some(quote),
+                Err(e) if aggregator_only => return Err(e),
+                Err(e) => {
+                    info!("Swapping through Uniswap: {}", e);
+                    None
+                }
+            },
+            None => None,
+        }
+        .filter(|quote| {
+            aggregator_only
+                || uniswap_quote
+                    .as_ref()
+                    .is_none_or(|uniswap| quote.beats(uniswap))
+        });
+        let quote = match &aggregator_quote {
+            Some(aggregator_quote) => aggregator_quote.to_swap_quote(uniswap_quote.as_ref()),
+            None => uniswap_quote.ok_or_else(|| anyhow::anyhow!("No quote for this swap"))?,
+        };
+        context.policy.check_price_impact(
+            &quote,
+            params["accept_price_impact"].as_bool().unwrap_or(false),
+        )?;
 
         // Create a swap request
         let swap_request = shared::SwapRequest {
             from_token: from_token.clone(),
             to_token: to_token.clone(),
             amount: amount.clone(),
-            slippage: Some(slippage.parse::<f64>().unwrap_or(0.5)),
+            slippage: Some(slippage_percent),
+            dex: dex.map(|dex| dex.to_string()),
+            simulate: params["simulate"].as_bool().unwrap_or(false),
+        };
+
+        // Approvals the swap needs are capped against its input, priced for the USD cap
+        let price_from = pnl::token_usd_price(
+            &context.blockchain_service,
+            &context.external_apis,
+            &from_token,
+        )
+        .await;
+        let approvals = SwapApprovals {
+            policy: &context.policy,
+            price_usd: price_from,
         };
 
         // Execute the actual swap using the blockchain service
-        match context
-            .blockchain_service
-            .swap_tokens(&from_account, swap_request)
-            .await
-        {
+        let result = match &aggregator_quote {
+            Some(aggregator_quote) => {
+                aggregator::execute(
+                    &context.blockchain_service,
+                    from_account,
+                    aggregator_quote,
+                    &approvals,
+                    swap_request.simulate,
+                )
+                .await
+            }
+            None => {
+                context
+                    .blockchain_service
+                    .swap_tokens(from_account, swap_request, &approvals)
+                    .await
+            }
+        };
+        match result {
             Ok(result) => {
+                // Execution prices give swaps a cost basis for P&L tracking
+                let price_to = pnl::token_usd_price(
+                    &context.blockchain_service,
+                    &context.external_apis,
+                    &to_token,
+                )
+                .await;
+
                 // Return the successful swap result
                 Ok(json!({
                     "from_token": from_token,
@@ -251,7 +440,12 @@ impl Tool for SwapTokensTool {
                     "transaction_hash": result.hash,
                     "status": result.status,
                     "block_number": result.block_number,
-                    "gas_used": result.gas_used
+                    "gas_used": result.gas_used,
+                    "dex": result.dex,
+                    "route": result.route,
+                    "price_impact_percent": quote.price_impact_percent,
+                    "prices_usd": {"from": price_from, "to": price_to},
+                    "token_safety": safety_reports
                 }))
             }
             Err(e) => {
@@ -261,3 +455,144 @@ impl Tool for SwapTokensTool {
         }
     }
 }
+
+// Token Safety Tool
+pub struct TokenSafetyTool;
+
+#[async_trait]
+impl Tool for TokenSafetyTool {
+    fn name(&self) -> &'static str {
+        "check_token_safety"
+    }
+
+    fn description(&self) -> &'static str {
+        "Run safety heuristics (verified source, honeypot, ownership, transfer tax) on a token"
+    }
+
+    async fn execute(&self, params: Value, context: &ToolContext) -> Result<Value> {
+        let token = params["token"]
+            .as_str()
+            .ok_or_else(|| anyhow::anyhow!("Missing token parameter"))?;
+
+        let report =
+            token_safety::assess_token(&context.blockchain_service, &context.external_apis, token)
+                .await?;
+        Ok(json!(report))
+    }
+}
+
+// NFT Balance Tool
+pub struct NftBalanceTool;
+
+#[async_trait]
+impl Tool for NftBalanceTool {
+    fn name(&self) -> &'static str {
+        "get_nft_balance"
+    }
+
+    fn description(&self) -> &'static str {
+        "Get how many NFTs an account holds in an ERC-721 collection, with token IDs when available"
+    }
+
+    async fn execute(&self, params: Value, context: &ToolContext) -> Result<Value> {
+        let owner = params["owner"]
+            .as_str()
+            .ok_or_else(|| anyhow::anyhow!("Missing owner parameter"))?;
+        let collection = params["collection"]
+            .as_str()
+            .ok_or_else(|| anyhow::anyhow!("Missing collection parameter"))?;
+
+        let owner_address = context.namespace.accounts.resolve_address(owner);
+        let balance = context
+            .blockchain_service
+            .get_nft_balance(&owner_address, collection)
+            .await?;
+        Ok(json!(balance))
+    }
+}
+
+// NFT Owner Tool
+pub struct NftOwnerTool;
+
+#[async_trait]
+impl Tool for NftOwnerTool {
+    fn name(&self) -> &'static str {
+        "owner_of"
+    }
+
+    fn description(&self) -> &'static str {
+        "Get the current owner of an ERC-721 token"
+    }
+
+    async fn execute(&self, params: Value, context: &ToolContext) -> Result<Value> {
+        let collection = params["collection"]
+            .as_str()
+            .ok_or_else(|| anyhow::anyhow!("Missing collection parameter"))?;
+        let token_id = params["token_id"]
+            .as_str()
+            .ok_or_else(|| anyhow::anyhow!("Missing token_id parameter"))?;
+
+        let owner = context
+            .blockchain_service
+            .owner_of(collection, token_id)
+            .await?;
+        let account = context.namespace.accounts.name_for_address(&owner);
+        Ok(json!({
+            "collection": collection,
+            "token_id": token_id,
+            "owner": owner,
+            "account": account
+        }))
+    }
+}
+
+// Transfer NFT Tool
+pub struct TransferNftTool;
+
+#[async_trait]
+impl Tool for TransferNftTool {
+    fn name(&self) -> &'static str {
+        "transfer_nft"
+    }
+
+    fn description(&self) -> &'static str {
+        "Transfer an ERC-721 token to another address"
+    }
+
+    async fn execute(&self, params: Value, context: &ToolContext) -> Result<Value> {
+        let from = params["from"].as_str().unwrap_or("");
+        let to = params["to"].as_str().unwrap_or("");
+        let collection = params["collection"]
+            .as_str()
+            .ok_or_else(|| anyhow::anyhow!("Missing collection parameter"))?;
+        let token_id = params["token_id"]
+            .as_str()
+            .ok_or_else(|| anyhow::anyhow!("Missing token_id parameter"))?;
+
+        let from_account = context
+            .accounts
+            .get(from)
+            .ok_or_else(|| anyhow::anyhow!("Unknown account: {}", from))?;
+        let to_address = context
+            .blockchain_service
+            .resolve_address(&context.namespace.accounts.resolve_address(to))
+            .await?;
+
+        // Screen the recipient like any other outgoing transfer
+        let screening = context
+            .screener
+            .screen(&to_address, &context.external_apis)
+            .await?;
+        let override_screening = params["override_screening"].as_bool().unwrap_or(false);
+        context
+            .policy
+            .check_screening(&screening, override_screening)?;
+
+        let fees = FeeOverrides::from_params(&params)?;
+        let result = context
+            .blockchain_service
+            .transfer_nft(from_account, collection, &to_address, token_id, &fees)
+            .await?;
+        Ok(json!(result))
+    }
+}
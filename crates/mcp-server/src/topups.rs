@@ -0,0 +1,247 @@
+use anyhow::{Result, anyhow};
+use ethers::types::U256;
+use ethers::utils::format_units;
+use serde::{Deserialize, Serialize};
+use shared::utils::parse_amount;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+// Top-ups counted against a rule's daily cap
+const DAY_SECS: i64 = 24 * 3600;
+/// How long a rule waits after a top-up that failed or hasn't confirmed, so it isn't
+/// retried on every block
+pub const RETRY_AFTER_SECS: i64 = 300;
+
+/// ETH sent by a top-up rule
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TopUp {
+    pub at: i64,
+    pub amount: String,
+    pub hash: Option<String>,
+    /// "success", "failed" or "pending"
+    pub status: String,
+}
+
+/// "Keep bob above 0.05 ETH by topping up from alice": when `account`'s ETH balance
+/// drops below `min_balance`, `from` sends enough to bring it back to
+/// `target_balance`, limited per top-up and per day
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TopUpRule {
+    pub id: String,
+    pub namespace: String,
+    /// Network the balance is kept on
+    pub network: String,
+    /// Account, address or ENS name kept funded
+    pub account: String,
+    /// Named account the top-ups are sent from
+    pub from: String,
+    pub min_balance: String,
+    pub target_balance: String,
+    pub max_per_top_up: String,
+    pub max_per_day: String,
+    /// Top-ups in the last day
+    #[serde(default)]
+    pub top_ups: Vec<TopUp>,
+    /// Why the last check didn't top up, when it should have
+    pub last_error: Option<String>,
+    /// Checks are skipped until then after a failed or unconfirmed top-up
+    pub paused_until: Option<i64>,
+    pub created_at: i64,
+}
+
+/// Balances and caps for a new rule, in ETH
+#[derive(Debug, Clone)]
+pub struct TopUpLimits {
+    pub min_balance: String,
+    /// Defaults to twice the minimum
+    pub target_balance: Option<String>,
+    /// Defaults to the target balance
+    pub max_per_top_up: Option<String>,
+    pub max_per_day: String,
+}
+
+impl TopUpRule {
+    pub fn new(
+        namespace: &str,
+        network: &str,
+        account: &str,
+        from: &str,
+        limits: &TopUpLimits,
+    ) -> Result<Self> {
+        let min = parse_amount(&limits.min_balance, 18)?;
+        let target = match &limits.target_balance {
+            Some(target) => parse_amount(target, 18)?,
+            None => min * 2,
+        };
+        if min.is_zero() || target <= min {
+            return Err(anyhow!(
+                "min_balance must be above zero and below target_balance"
+            ));
+        }
+        let per_top_up = match &limits.max_per_top_up {
+            Some(max) => parse_amount(max, 18)?,
+            None => target,
+        };
+        let per_day = parse_amount(&limits.max_per_day, 18)?;
+        if per_top_up.is_zero() || per_day.is_zero() {
+            return Err(anyhow!("Top-up caps must be above zero"));
+        }
+
+        Ok(Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            namespace: namespace.to_string(),
+            network: network.to_string(),
+            account: account.to_string(),
+            from: from.to_string(),
+            min_balance: format_units(min, 18)?,
+            target_balance: format_units(target, 18)?,
+            max_per_top_up: format_units(per_top_up, 18)?,
+            max_per_day: format_units(per_day, 18)?,
+            top_ups: Vec::new(),
+            last_error: None,
+            paused_until: None,
+            created_at: chrono::Utc::now().timestamp(),
+        })
+    }
+
+    pub fn is_paused(&self, now: i64) -> bool {
+        self.paused_until.is_some_and(|until| until > now)
+    }
+
+    /// ETH sent or still pending in the last day
+    pub fn spent_today(&self, now: i64) -> Result<U256> {
+        self.top_ups
+            .iter()
+            .filter(|top_up| top_up.status != "failed" && now - top_up.at < DAY_SECS)
+            .try_fold(U256::zero(), |total, top_up| {
+                Ok(total + parse_amount(&top_up.amount, 18)?)
+            })
+    }
+
+    /// The wei to send for a `balance` in wei: None while the balance is at or above
+    /// the minimum, an error when a top-up is due but the daily cap is spent
+    pub fn amount_due(&self, balance: U256, now: i64) -> Result<Option<U256>> {
+        let target = parse_amount(&self.target_balance, 18)?;
+        if balance >= parse_amount(&self.min_balance, 18)? {
+            return Ok(None);
+        }
+        let left_today =
+            parse_amount(&self.max_per_day, 18)?.saturating_sub(self.spent_today(now)?);
+        if left_today.is_zero() {
+            return Err(anyhow!(
+                "{} is below {} ETH, but the {} ETH daily cap is spent",
+                self.account,
+                self.min_balance,
+                self.max_per_day
+            ));
+        }
+        Ok(Some(
+            (target - balance)
+                .min(parse_amount(&self.max_per_top_up, 18)?)
+                .min(left_today),
+        ))
+    }
+}
+
+// Top-up rules for every namespace, persisted as one JSON file
+pub struct TopUpStore {
+    path: Option<PathBuf>,
+    rules: RwLock<Vec<TopUpRule>>,
+}
+
+impl TopUpStore {
+    pub fn in_memory() -> Self {
+        Self {
+            path: None,
+            rules: RwLock::new(Vec::new()),
+        }
+    }
+
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let rules = if path.exists() {
+            serde_json::from_str(&fs::read_to_string(&path)?)?
+        } else {
+            Vec::new()
+        };
+
+        Ok(Self {
+            path: Some(path),
+            rules: RwLock::new(rules),
+        })
+    }
+
+    pub fn add(&self, rule: TopUpRule) -> Result<()> {
+        self.update(|rules| rules.push(rule))
+    }
+
+    pub fn all(&self) -> Vec<TopUpRule> {
+        self.rules
+            .read()
+            .map(|rules| rules.clone())
+            .unwrap_or_default()
+    }
+
+    pub fn list(&self, namespace: &str) -> Vec<TopUpRule> {
+        self.all()
+            .into_iter()
+            .filter(|rule| rule.namespace == namespace)
+            .collect()
+    }
+
+    /// Remove a rule; false when the namespace has none with that id
+    pub fn remove(&self, namespace: &str, id: &str) -> Result<bool> {
+        let mut removed = false;
+        self.update(|rules| {
+            let before = rules.len();
+            rules.retain(|rule| !(rule.namespace == namespace && rule.id == id));
+            removed = rules.len() < before;
+        })?;
+        Ok(removed)
+    }
+
+    /// Record a top-up, dropping ones older than a day
+    pub fn record(&self, id: &str, top_up: TopUp, paused_until: Option<i64>) -> Result<()> {
+        self.update_rule(id, |rule| {
+            rule.top_ups
+                .retain(|earlier| top_up.at - earlier.at < DAY_SECS);
+            rule.top_ups.push(top_up);
+            rule.last_error = None;
+            rule.paused_until = paused_until;
+        })
+    }
+
+    /// Note why a due top-up didn't happen, and wait before checking again
+    pub fn record_error(&self, id: &str, error: String, paused_until: Option<i64>) -> Result<()> {
+        self.update_rule(id, |rule| {
+            rule.last_error = Some(error);
+            rule.paused_until = paused_until;
+        })
+    }
+
+    fn update_rule(&self, id: &str, change: impl FnOnce(&mut TopUpRule)) -> Result<()> {
+        self.update(|rules| {
+            if let Some(rule) = rules.iter_mut().find(|rule| rule.id == id) {
+                change(rule);
+            }
+        })
+    }
+
+    fn update(&self, change: impl FnOnce(&mut Vec<TopUpRule>)) -> Result<()> {
+        let mut rules = self
+            .rules
+            .write()
+            .map_err(|_| anyhow!("Top-up rule lock poisoned"))?;
+        change(&mut rules);
+
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(&*rules)?)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,355 @@
+use anyhow::{Result, anyhow};
+use ethers::abi::{Abi, parse_abi};
+use ethers::contract::Contract;
+use ethers::providers::{Middleware, Provider};
+use ethers::types::{Address, BlockId, BlockNumber, I256, U256};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+use crate::blockchain::{BlockchainService, Dex, TokenInfo};
+use crate::failover::FailoverClient;
+
+/// Window averaged over when the caller gives none
+pub const DEFAULT_TWAP_WINDOW: u64 = 1_800;
+// Longer windows outrun the observation history most pools keep
+const MAX_TWAP_WINDOW: u64 = 7 * 24 * 3600;
+const V3_FEE_TIERS: [u32; 4] = [100, 500, 3000, 10000];
+// Blocks sampled to estimate the block time when looking up a V2 window's start
+const BLOCK_TIME_SAMPLE: u64 = 100;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TwapResult {
+    pub token: String,
+    pub quote: String,
+    pub dex: String,
+    pub pool: String,
+    /// V3 fee tier in hundredths of a bip
+    pub fee: Option<u32>,
+    /// Seconds actually averaged over; V2 windows snap to block timestamps
+    pub window_seconds: u64,
+    /// Time-weighted average price of one `token` in `quote`
+    pub twap: f64,
+    /// Price in the same pool right now, for comparison
+    pub spot: f64,
+    /// How far spot is from the average, in percent; large gaps hint at manipulation
+    pub spot_deviation_percent: f64,
+}
+
+/// Time-weighted average price of `token` in `quote` over the last `window` seconds,
+/// from Uniswap V3 oracle observations or V2 cumulative prices. Unlike a spot quote it
+/// can't be moved within a single block.
+pub async fn get_twap(
+    blockchain_service: &BlockchainService,
+    token: &str,
+    quote: &str,
+    window: u64,
+    dex: Option<Dex>,
+    fee: Option<u32>,
+) -> Result<TwapResult> {
+    if window == 0 || window > MAX_TWAP_WINDOW {
+        return Err(anyhow!(
+            "window_seconds must be between 1 and {}",
+            MAX_TWAP_WINDOW
+        ));
+    }
+    let base = pool_token(blockchain_service, token).await?;
+    let quote_token = pool_token(blockchain_service, quote).await?;
+    if base.address.eq_ignore_ascii_case(&quote_token.address) {
+        return Err(anyhow!("token and quote are the same token"));
+    }
+
+    let router = blockchain_service.router_config();
+    let dex = match dex {
+        Some(dex) => dex,
+        None if router.uniswap_v3.is_some() => Dex::UniswapV3,
+        None => Dex::UniswapV2,
+    };
+    let (pool, fee, twap, spot, window) = match dex {
+        Dex::UniswapV3 => {
+            let (pool, fee, twap, spot) =
+                v3_twap(blockchain_service, &base, &quote_token, window, fee).await?;
+            (pool, Some(fee), twap, spot, window)
+        }
+        Dex::UniswapV2 => {
+            let (pool, twap, spot, window) =
+                v2_twap(blockchain_service, &base, &quote_token, window).await?;
+            (pool, None, twap, spot, window)
+        }
+    };
+
+    Ok(TwapResult {
+        token: base.symbol,
+        quote: quote_token.symbol,
+        dex: dex.as_str().to_string(),
+        pool: format!("{:?}", pool),
+        fee,
+        window_seconds: window,
+        twap,
+        spot,
+        spot_deviation_percent: if twap > 0.0 {
+            (spot - twap) / twap * 100.0
+        } else {
+            0.0
+        },
+    })
+}
+
+// Pools hold WETH rather than ETH
+async fn pool_token(blockchain_service: &BlockchainService, token: &str) -> Result<TokenInfo> {
+    if token.eq_ignore_ascii_case("eth") {
+        return blockchain_service
+            .resolve_token(&blockchain_service.router_config().weth)
+            .await;
+    }
+    blockchain_service.resolve_token(token).await
+}
+
+fn contract(
+    blockchain_service: &BlockchainService,
+    address: Address,
+    abi: &[&str],
+) -> Result<Contract<Provider<FailoverClient>>> {
+    let abi: Abi = parse_abi(abi)?;
+    Ok(Contract::new(address, abi, blockchain_service.provider()))
+}
+
+// Pool address, fee tier, TWAP and spot price from a V3 pool's oracle. Without a
+// fee tier the most liquid pool is used.
+async fn v3_twap(
+    blockchain_service: &BlockchainService,
+    base: &TokenInfo,
+    quote: &TokenInfo,
+    window: u64,
+    fee: Option<u32>,
+) -> Result<(Address, u32, f64, f64)> {
+    let v3 = blockchain_service
+        .router_config()
+        .uniswap_v3
+        .clone()
+        .ok_or_else(|| {
+            anyhow!(
+                "Uniswap V3 isn't configured for chain {}",
+                blockchain_service.chain_id()
+            )
+        })?;
+    let base_addr = Address::from_str(&base.address)?;
+    let quote_addr = Address::from_str(&quote.address)?;
+
+    let router = contract(
+        blockchain_service,
+        Address::from_str(&v3.router)?,
+        &["function factory() view returns (address)"],
+    )?;
+    let factory_addr: Address = router.method::<_, Address>("factory", ())?.call().await?;
+    let factory = contract(
+        blockchain_service,
+        factory_addr,
+        &["function getPool(address,address,uint24) view returns (address)"],
+    )?;
+
+    let pool_abi = [
+        "function liquidity() view returns (uint128)",
+        "function slot0() view returns (uint160 sqrtPriceX96, int24 tick, uint16 observationIndex, uint16 observationCardinality, uint16 observationCardinalityNext, uint8 feeProtocol, bool unlocked)",
+        "function observe(uint32[] secondsAgos) view returns (int56[] tickCumulatives, uint160[] secondsPerLiquidityCumulativeX128s)",
+    ];
+    let tiers = match fee {
+        Some(fee) => vec![fee],
+        None => V3_FEE_TIERS.to_vec(),
+    };
+    let mut best: Option<(Address, u32, u128)> = None;
+    for tier in tiers {
+        let pool: Address = factory
+            .method::<_, Address>("getPool", (base_addr, quote_addr, tier))?
+            .call()
+            .await?;
+        if pool.is_zero() {
+            continue;
+        }
+        let liquidity: u128 = contract(blockchain_service, pool, &pool_abi)?
+            .method::<_, u128>("liquidity", ())?
+            .call()
+            .await?;
+        if best.is_none_or(|(_, _, most)| liquidity > most) {
+            best = Some((pool, tier, liquidity));
+        }
+    }
+    let (pool_addr, tier, _) = best.ok_or_else(|| {
+        anyhow!(
+            "No Uniswap V3 pool found for {}/{}",
+            base.symbol,
+            quote.symbol
+        )
+    })?;
+    let pool = contract(blockchain_service, pool_addr, &pool_abi)?;
+
+    let (tick_cumulatives, _): (Vec<I256>, Vec<U256>) = pool
+        .method::<_, (Vec<I256>, Vec<U256>)>("observe", vec![window as u32, 0u32])?
+        .call()
+        .await
+        .map_err(|e| {
+            anyhow!(
+                "The pool's oracle doesn't reach back {}s ({}); try a shorter window",
+                window,
+                e
+            )
+        })?;
+    if tick_cumulatives.len() != 2 {
+        return Err(anyhow!("The pool returned no observations"));
+    }
+    let delta = tick_cumulatives[1] - tick_cumulatives[0];
+    let window_ticks = I256::from(window as i64);
+    let mut average_tick = delta / window_ticks;
+    // Round toward negative infinity, as the Uniswap OracleLibrary does
+    if delta.is_negative() && !(delta % window_ticks).is_zero() {
+        average_tick -= I256::one();
+    }
+    let (_, spot_tick, _, _, _, _, _): (U256, i32, u16, u16, u16, u8, bool) = pool
+        .method::<_, (U256, i32, u16, u16, u16, u8, bool)>("slot0", ())?
+        .call()
+        .await?;
+
+    // Ticks price token0 (the lower address) in token1
+    let base_is_token0 = base_addr < quote_addr;
+    let price = |tick: f64| {
+        let token0_in_token1 = 1.0001f64.powf(tick);
+        if base_is_token0 {
+            token0_in_token1 * 10f64.powi(base.decimals as i32 - quote.decimals as i32)
+        } else {
+            10f64.powi(base.decimals as i32 - quote.decimals as i32) / token0_in_token1
+        }
+    };
+    Ok((
+        pool_addr,
+        tier,
+        price(average_tick.as_i64() as f64),
+        price(spot_tick as f64),
+    ))
+}
+
+// Pair address, TWAP, spot price and the window actually covered, from a V2 pair's
+// cumulative prices now and at the block closest to `window` seconds ago
+async fn v2_twap(
+    blockchain_service: &BlockchainService,
+    base: &TokenInfo,
+    quote: &TokenInfo,
+    window: u64,
+) -> Result<(Address, f64, f64, u64)> {
+    let base_addr = Address::from_str(&base.address)?;
+    let quote_addr = Address::from_str(&quote.address)?;
+    let router = contract(
+        blockchain_service,
+        Address::from_str(&blockchain_service.router_config().uniswap_v2_router)?,
+        &["function factory() view returns (address)"],
+    )?;
+    let factory_addr: Address = router.method::<_, Address>("factory", ())?.call().await?;
+    let factory = contract(
+        blockchain_service,
+        factory_addr,
+        &["function getPair(address,address) view returns (address)"],
+    )?;
+    let pair_addr: Address = factory
+        .method::<_, Address>("getPair", (base_addr, quote_addr))?
+        .call()
+        .await?;
+    if pair_addr.is_zero() {
+        return Err(anyhow!(
+            "No Uniswap V2 pair found for {}/{}",
+            base.symbol,
+            quote.symbol
+        ));
+    }
+    let pair = contract(
+        blockchain_service,
+        pair_addr,
+        &[
+            "function price0CumulativeLast() view returns (uint256)",
+            "function price1CumulativeLast() view returns (uint256)",
+            "function getReserves() view returns (uint112 reserve0, uint112 reserve1, uint32 blockTimestampLast)",
+        ],
+    )?;
+
+    let provider = blockchain_service.provider();
+    let latest = provider
+        .get_block(BlockNumber::Latest)
+        .await?
+        .ok_or_else(|| anyhow!("Latest block not found"))?;
+    let latest_number = latest
+        .number
+        .ok_or_else(|| anyhow!("Latest block has no number"))?
+        .as_u64();
+    let sample = provider
+        .get_block(latest_number.saturating_sub(BLOCK_TIME_SAMPLE))
+        .await?
+        .ok_or_else(|| anyhow!("Block {} not found", latest_number - BLOCK_TIME_SAMPLE))?;
+    let block_time = ((latest.timestamp - sample.timestamp).as_u64() / BLOCK_TIME_SAMPLE).max(1);
+    let start_number = latest_number.saturating_sub(window.div_ceil(block_time));
+    let start = provider
+        .get_block(start_number)
+        .await?
+        .ok_or_else(|| anyhow!("Block {} not found", start_number))?;
+
+    let base_is_token0 = base_addr < quote_addr;
+    let read = |block: u64, timestamp: u64| {
+        let pair = pair.clone();
+        async move {
+            let block_id = BlockId::Number(BlockNumber::Number(block.into()));
+            let cumulative_fn = if base_is_token0 {
+                "price0CumulativeLast"
+            } else {
+                "price1CumulativeLast"
+            };
+            let cumulative: U256 = pair
+                .method::<_, U256>(cumulative_fn, ())?
+                .block(block_id)
+                .call()
+                .await?;
+            let (reserve0, reserve1, last): (u128, u128, u32) = pair
+                .method::<_, (u128, u128, u32)>("getReserves", ())?
+                .block(block_id)
+                .call()
+                .await?;
+            let (reserve_base, reserve_quote) = if base_is_token0 {
+                (reserve0, reserve1)
+            } else {
+                (reserve1, reserve0)
+            };
+            if reserve_base == 0 {
+                return Err(anyhow!("The pair has no liquidity"));
+            }
+            // Accumulate the current price up to the block's timestamp, as the pair
+            // itself would on its next update
+            let price = (U256::from(reserve_quote) << 112) / U256::from(reserve_base);
+            let elapsed = timestamp.saturating_sub(last as u64);
+            let cumulative = cumulative.overflowing_add(price * U256::from(elapsed)).0;
+            Ok::<_, anyhow::Error>((cumulative, price))
+        }
+    };
+    let (cumulative_end, spot) = read(latest_number, latest.timestamp.as_u64()).await?;
+    let (cumulative_start, _) =
+        read(start_number, start.timestamp.as_u64())
+            .await
+            .map_err(|e| {
+                anyhow!(
+                    "Couldn't read the pair at block {} ({}); the RPC may not keep state that old",
+                    start_number,
+                    e
+                )
+            })?;
+
+    let elapsed = (latest.timestamp - start.timestamp).as_u64();
+    if elapsed == 0 {
+        return Err(anyhow!("The window is shorter than one block"));
+    }
+    let average = cumulative_end.overflowing_sub(cumulative_start).0 / U256::from(elapsed);
+    let scale = 10f64.powi(base.decimals as i32 - quote.decimals as i32);
+    Ok((
+        pair_addr,
+        uq112x112_to_f64(average) * scale,
+        uq112x112_to_f64(spot) * scale,
+        elapsed,
+    ))
+}
+
+fn uq112x112_to_f64(value: U256) -> f64 {
+    value.to_string().parse::<f64>().unwrap_or(0.0) / 2f64.powi(112)
+}
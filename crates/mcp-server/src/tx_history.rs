@@ -0,0 +1,347 @@
+use anyhow::Result;
+use ethers::providers::Middleware;
+use ethers::types::{Address, BlockNumber, Filter, H256, U64, U256};
+use ethers::utils::{format_units, keccak256};
+use futures::future::join_all;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+use tracing::warn;
+
+use crate::activity::{field_i64, field_u256, parse_range};
+use crate::blockchain::BlockchainService;
+use crate::external_apis::ExternalAPIService;
+use crate::scanner::{CheckpointStore, LogScanner};
+use crate::tenants::Namespace;
+
+/// Blocks searched when there is no Etherscan key, e.g. on a local Anvil chain
+pub const DEFAULT_SCAN_BLOCKS: u64 = 2_000;
+// Blocks fetched concurrently while scanning
+const BLOCK_BATCH: usize = 25;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub hash: String,
+    pub block_number: u64,
+    pub timestamp: i64,
+    /// "out", "in" or "self", seen from the account
+    pub direction: String,
+    pub counterparty: String,
+    /// Name of the counterparty when it is one of the namespace's accounts
+    pub counterparty_name: Option<String>,
+    /// "ETH" or the token symbol
+    pub asset: String,
+    pub value: String,
+    /// "success" or "failed"
+    pub status: String,
+    /// Called function, when known
+    pub method: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransactionHistory {
+    pub account: String,
+    pub address: String,
+    pub since: Option<i64>,
+    /// Most recent first
+    pub transactions: Vec<HistoryEntry>,
+    /// "etherscan" or "block_scan"
+    pub source: String,
+    /// Block range searched when scanning
+    pub scanned_blocks: Option<(u64, u64)>,
+}
+
+/// Recent ETH and token transactions of an account: from Etherscan when a key is
+/// configured, otherwise by scanning the last `scan_blocks` blocks
+pub async fn get_transaction_history(
+    blockchain_service: &BlockchainService,
+    external_apis: &ExternalAPIService,
+    namespace: &Namespace,
+    account: &str,
+    range: Option<&str>,
+    limit: usize,
+    scan_blocks: u64,
+) -> Result<TransactionHistory> {
+    let address = blockchain_service
+        .resolve_address(&namespace.accounts.resolve_address(account))
+        .await?;
+    let account_name = namespace
+        .accounts
+        .name_for_address(&address)
+        .unwrap_or_else(|| account.to_string());
+    let since = match range {
+        Some(range) => Some(chrono::Utc::now().timestamp() - parse_range(range)?),
+        None => None,
+    };
+
+    let mut history = TransactionHistory {
+        account: account_name,
+        address: address.clone(),
+        since,
+        transactions: Vec::new(),
+        source: "etherscan".to_string(),
+        scanned_blocks: None,
+    };
+
+    match from_etherscan(blockchain_service, external_apis, &address).await {
+        Ok(Some(transactions)) => history.transactions = transactions,
+        result => {
+            if let Err(e) = result {
+                warn!(
+                    "Etherscan history for {} failed, scanning blocks instead: {}",
+                    address, e
+                );
+            }
+            let (transactions, range) =
+                from_block_scan(blockchain_service, &address, scan_blocks).await?;
+            history.transactions = transactions;
+            history.source = "block_scan".to_string();
+            history.scanned_blocks = Some(range);
+        }
+    }
+
+    history
+        .transactions
+        .retain(|tx| since.is_none_or(|since| tx.timestamp >= since));
+    history
+        .transactions
+        .sort_by_key(|tx| Reverse(tx.block_number));
+    history.transactions.truncate(limit);
+    for tx in &mut history.transactions {
+        tx.counterparty_name = namespace.accounts.name_for_address(&tx.counterparty);
+    }
+    Ok(history)
+}
+
+fn direction(address: &str, from: &str, to: &str) -> (&'static str, String) {
+    let outgoing = from.eq_ignore_ascii_case(address);
+    let incoming = to.eq_ignore_ascii_case(address);
+    match (outgoing, incoming) {
+        (true, true) => ("self", to.to_string()),
+        (true, false) => ("out", to.to_string()),
+        _ => ("in", from.to_string()),
+    }
+}
+
+fn format_amount(amount: U256, decimals: u32) -> String {
+    let formatted = format_units(amount, decimals).unwrap_or_else(|_| amount.to_string());
+    if formatted.contains('.') {
+        formatted
+            .trim_end_matches('0')
+            .trim_end_matches('.')
+            .to_string()
+    } else {
+        formatted
+    }
+}
+
+async fn from_etherscan(
+    blockchain_service: &BlockchainService,
+    external_apis: &ExternalAPIService,
+    address: &str,
+) -> Result<Option<Vec<HistoryEntry>>> {
+    let chain_id = blockchain_service.chain_id();
+    let Some(transactions) = external_apis
+        .get_account_history(chain_id, address, "txlist")
+        .await?
+    else {
+        return Ok(None);
+    };
+    let token_transfers = external_apis
+        .get_account_history(chain_id, address, "tokentx")
+        .await?
+        .unwrap_or_default();
+
+    let token_hashes: HashSet<&str> = token_transfers
+        .iter()
+        .filter_map(|transfer| transfer["hash"].as_str())
+        .collect();
+    let mut entries = Vec::new();
+
+    for tx in &transactions {
+        let hash = tx["hash"].as_str().unwrap_or("");
+        let value = field_u256(tx, "value");
+        // Token transfers are listed with their amounts below
+        if value.is_zero() && token_hashes.contains(hash) {
+            continue;
+        }
+
+        let (direction, counterparty) = direction(
+            address,
+            tx["from"].as_str().unwrap_or(""),
+            tx["to"].as_str().unwrap_or(""),
+        );
+        let method = tx["functionName"]
+            .as_str()
+            .and_then(|name| name.split('(').next())
+            .filter(|name| !name.is_empty())
+            .map(|name| name.to_string());
+        entries.push(HistoryEntry {
+            hash: hash.to_string(),
+            block_number: field_i64(tx, "blockNumber") as u64,
+            timestamp: field_i64(tx, "timeStamp"),
+            direction: direction.to_string(),
+            counterparty,
+            counterparty_name: None,
+            asset: "ETH".to_string(),
+            value: format_amount(value, 18),
+            status: if tx["isError"].as_str() == Some("1") {
+                "failed".to_string()
+            } else {
+                "success".to_string()
+            },
+            method,
+        });
+    }
+
+    for transfer in &token_transfers {
+        let (direction, counterparty) = direction(
+            address,
+            transfer["from"].as_str().unwrap_or(""),
+            transfer["to"].as_str().unwrap_or(""),
+        );
+        entries.push(HistoryEntry {
+            hash: transfer["hash"].as_str().unwrap_or("").to_string(),
+            block_number: field_i64(transfer, "blockNumber") as u64,
+            timestamp: field_i64(transfer, "timeStamp"),
+            direction: direction.to_string(),
+            counterparty,
+            counterparty_name: None,
+            asset: transfer["tokenSymbol"]
+                .as_str()
+                .unwrap_or("UNKNOWN")
+                .to_string(),
+            value: format_amount(
+                field_u256(transfer, "value"),
+                field_i64(transfer, "tokenDecimal") as u32,
+            ),
+            // Etherscan only lists transfers from successful transactions
+            status: "success".to_string(),
+            method: Some("transfer".to_string()),
+        });
+    }
+
+    Ok(Some(entries))
+}
+
+// Walk the most recent blocks for transactions and ERC20 transfers involving the address
+async fn from_block_scan(
+    blockchain_service: &BlockchainService,
+    address: &str,
+    scan_blocks: u64,
+) -> Result<(Vec<HistoryEntry>, (u64, u64))> {
+    let provider = blockchain_service.provider();
+    let owner = Address::from_str(address)?;
+    let latest = provider.get_block_number().await?.as_u64();
+    let first = latest.saturating_sub(scan_blocks.saturating_sub(1));
+
+    let mut entries = Vec::new();
+    let mut timestamps = HashMap::new();
+    let block_numbers: Vec<u64> = (first..=latest).collect();
+    for batch in block_numbers.chunks(BLOCK_BATCH) {
+        let blocks = join_all(
+            batch
+                .iter()
+                .map(|number| provider.get_block_with_txs(BlockNumber::Number((*number).into()))),
+        )
+        .await;
+
+        for block in blocks.into_iter().flatten().flatten() {
+            let number = block.number.map(|n| n.as_u64()).unwrap_or_default();
+            let timestamp = block.timestamp.as_u64() as i64;
+            timestamps.insert(number, timestamp);
+
+            for tx in block.transactions {
+                let to = tx.to.unwrap_or_default();
+                if tx.from != owner && to != owner {
+                    continue;
+                }
+                let status = match provider.get_transaction_receipt(tx.hash).await {
+                    Ok(Some(receipt)) if receipt.status == Some(U64::zero()) => "failed",
+                    _ => "success",
+                };
+                let (direction, counterparty) =
+                    direction(address, &format!("{:?}", tx.from), &format!("{:?}", to));
+                entries.push(HistoryEntry {
+                    hash: format!("{:?}", tx.hash),
+                    block_number: number,
+                    timestamp,
+                    direction: direction.to_string(),
+                    counterparty,
+                    counterparty_name: None,
+                    asset: "ETH".to_string(),
+                    value: format_amount(tx.value, 18),
+                    status: status.to_string(),
+                    method: (tx.input.len() >= 4).then(|| format!("0x{}", hex_selector(&tx.input))),
+                });
+            }
+        }
+    }
+
+    // Token transfers in and out, which the transactions above only show as contract calls
+    let transfer_topic = H256::from(keccak256("Transfer(address,address,uint256)"));
+    let owner_topic = H256::from(owner);
+    let checkpoints = CheckpointStore::in_memory();
+    let scanner = LogScanner::new(provider, &checkpoints);
+    let mut logs = Vec::new();
+    for (scan_id, filter) in [
+        (
+            "history_out",
+            Filter::new().topic0(transfer_topic).topic1(owner_topic),
+        ),
+        (
+            "history_in",
+            Filter::new().topic0(transfer_topic).topic2(owner_topic),
+        ),
+    ] {
+        scanner
+            .scan(scan_id, &filter, first, Some(latest), |chunk| {
+                logs.extend(chunk.into_iter().filter(|log| log.topics.len() == 3));
+                Ok(())
+            })
+            .await?;
+    }
+
+    for log in logs {
+        let Ok(token) = blockchain_service
+            .resolve_token(&format!("{:?}", log.address))
+            .await
+        else {
+            continue;
+        };
+        let from = format!("{:?}", Address::from(log.topics[1]));
+        let to = format!("{:?}", Address::from(log.topics[2]));
+        let (direction, counterparty) = direction(address, &from, &to);
+        let block_number = log.block_number.map(|n| n.as_u64()).unwrap_or_default();
+
+        // Drop the contract call already listed for this transaction
+        let hash = log
+            .transaction_hash
+            .map(|hash| format!("{:?}", hash))
+            .unwrap_or_default();
+        entries.retain(|entry: &HistoryEntry| !(entry.hash == hash && entry.value == "0"));
+
+        entries.push(HistoryEntry {
+            hash,
+            block_number,
+            timestamp: timestamps.get(&block_number).copied().unwrap_or_default(),
+            direction: direction.to_string(),
+            counterparty,
+            counterparty_name: None,
+            asset: token.symbol,
+            value: format_amount(U256::from_big_endian(&log.data), token.decimals as u32),
+            status: "success".to_string(),
+            method: Some("transfer".to_string()),
+        });
+    }
+
+    Ok((entries, (first, latest)))
+}
+
+fn hex_selector(input: &[u8]) -> String {
+    input[..4]
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
@@ -0,0 +1,161 @@
+use anyhow::{Result, anyhow};
+use ethers::abi::EventExt;
+use ethers::providers::Middleware;
+use ethers::types::{H256, U64};
+use ethers::utils::format_units;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::str::FromStr;
+use tracing::warn;
+
+use crate::blockchain::BlockchainService;
+use crate::calldata::{self, DecodedCalldata};
+use crate::events::decode_log;
+use crate::external_apis::ExternalAPIService;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransactionLog {
+    pub address: String,
+    /// Event signature when a loaded ABI knows it
+    pub event: Option<String>,
+    pub args: Option<Map<String, Value>>,
+    pub topics: Vec<String>,
+    pub data: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransactionDetails {
+    pub hash: String,
+    /// "success", "failed" or "pending"
+    pub status: String,
+    pub block_number: Option<u64>,
+    pub timestamp: Option<i64>,
+    pub from: String,
+    pub from_name: Option<String>,
+    pub to: Option<String>,
+    pub to_name: Option<String>,
+    /// Address of the contract a deployment created
+    pub contract_address: Option<String>,
+    pub value_eth: String,
+    pub nonce: u64,
+    pub gas_limit: u64,
+    pub gas_used: Option<u64>,
+    pub gas_price_gwei: Option<String>,
+    pub fee_eth: Option<String>,
+    /// Decoded function call; None for plain ETH transfers and deployments
+    pub call: Option<DecodedCalldata>,
+    pub logs: Vec<TransactionLog>,
+}
+
+/// A transaction and its receipt with the call and emitted events decoded
+pub async fn get_transaction(
+    blockchain_service: &BlockchainService,
+    external_apis: &ExternalAPIService,
+    hash: &str,
+) -> Result<TransactionDetails> {
+    let hash =
+        H256::from_str(hash.trim()).map_err(|_| anyhow!("Invalid transaction hash '{}'", hash))?;
+    let provider = blockchain_service.provider();
+    let tx = provider
+        .get_transaction(hash)
+        .await?
+        .ok_or_else(|| anyhow!("Transaction {:?} not found on this network", hash))?;
+    let receipt = provider.get_transaction_receipt(hash).await?;
+
+    let timestamp = match tx.block_number {
+        Some(number) => provider
+            .get_block(number)
+            .await?
+            .map(|block| block.timestamp.as_u64() as i64),
+        None => None,
+    };
+
+    let call = match tx.to {
+        Some(to) if tx.input.len() >= 4 => {
+            match calldata::decode_calldata(
+                blockchain_service,
+                external_apis,
+                &tx.input.to_string(),
+                Some(&format!("{:?}", to)),
+            )
+            .await
+            {
+                Ok(call) => Some(call),
+                Err(e) => {
+                    warn!("Couldn't decode the input of {:?}: {}", hash, e);
+                    None
+                }
+            }
+        }
+        _ => None,
+    };
+
+    let gas_price = receipt
+        .as_ref()
+        .and_then(|receipt| receipt.effective_gas_price)
+        .or(tx.gas_price);
+    let fee = receipt
+        .as_ref()
+        .and_then(|receipt| receipt.gas_used)
+        .zip(gas_price)
+        .map(|(gas_used, price)| gas_used * price);
+
+    let logs = receipt
+        .as_ref()
+        .map(|receipt| receipt.logs.clone())
+        .unwrap_or_default()
+        .into_iter()
+        .map(|log| {
+            let candidates = log
+                .topics
+                .first()
+                .map(|topic0| blockchain_service.find_events_by_topic(*topic0))
+                .unwrap_or_default();
+            let address = format!("{:?}", log.address);
+            let event = candidates.first().map(|event| event.abi_signature());
+            let decoded = decode_log(&candidates, log);
+            TransactionLog {
+                address,
+                event: decoded.args.as_ref().and(event),
+                args: decoded.args,
+                topics: decoded.topics,
+                data: decoded.data,
+            }
+        })
+        .collect();
+
+    Ok(TransactionDetails {
+        hash: format!("{:?}", hash),
+        status: match &receipt {
+            Some(receipt) if receipt.status == Some(U64::one()) => "success".to_string(),
+            Some(_) => "failed".to_string(),
+            None => "pending".to_string(),
+        },
+        block_number: tx.block_number.map(|number| number.as_u64()),
+        timestamp,
+        from: format!("{:?}", tx.from),
+        from_name: blockchain_service.lookup_name(tx.from).await,
+        to: tx.to.map(|to| format!("{:?}", to)),
+        to_name: match tx.to {
+            Some(to) => blockchain_service.lookup_name(to).await,
+            None => None,
+        },
+        contract_address: receipt
+            .as_ref()
+            .and_then(|receipt| receipt.contract_address)
+            .map(|address| format!("{:?}", address)),
+        value_eth: format_units(tx.value, "ether")?,
+        nonce: tx.nonce.as_u64(),
+        gas_limit: tx.gas.as_u64(),
+        gas_used: receipt
+            .as_ref()
+            .and_then(|receipt| receipt.gas_used)
+            .map(|gas| gas.as_u64()),
+        gas_price_gwei: gas_price
+            .map(|price| format_units(price, "gwei"))
+            .transpose()?,
+        fee_eth: fee.map(|fee| format_units(fee, "ether")).transpose()?,
+        call,
+        logs,
+    })
+}
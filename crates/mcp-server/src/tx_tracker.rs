@@ -0,0 +1,173 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::{Arc, RwLock};
+use tokio::sync::mpsc;
+
+// Finished requests are kept this long so their outcome can still be looked up
+const RETAIN_FINISHED_SECS: i64 = 3600;
+
+tokio::task_local! {
+    // The background request a transaction is being sent for, if any
+    static SUBMISSIONS: Submissions;
+}
+
+#[derive(Clone)]
+struct Submissions {
+    tracker: Arc<TxTracker>,
+    request_id: String,
+    sent: mpsc::UnboundedSender<String>,
+}
+
+/// A transaction sent while running a tracked request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubmittedTransaction {
+    pub hash: String,
+    pub description: String,
+    pub submitted_at: i64,
+}
+
+/// A write request that answered once its first transaction was sent and kept running
+/// in the background until its transactions confirmed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrackedRequest {
+    pub id: String,
+    pub namespace: String,
+    pub method: String,
+    /// "submitted" while it runs, then "completed" or "failed"
+    pub status: String,
+    pub transactions: Vec<SubmittedTransaction>,
+    /// What the request returned once it finished
+    pub result: Option<Value>,
+    pub error: Option<String>,
+    pub started_at: i64,
+    pub finished_at: Option<i64>,
+}
+
+// Write requests running in the background, kept in memory: one interrupted by a
+// restart leaves its transactions in the pending store for the reconciler
+#[derive(Default)]
+pub struct TxTracker {
+    requests: RwLock<Vec<TrackedRequest>>,
+}
+
+impl TxTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start tracking a request, dropping long-finished ones; returns its id
+    pub fn start(&self, namespace: &str, method: &str) -> String {
+        let now = chrono::Utc::now().timestamp();
+        let request = TrackedRequest {
+            id: uuid::Uuid::new_v4().to_string(),
+            namespace: namespace.to_string(),
+            method: method.to_string(),
+            status: "submitted".to_string(),
+            transactions: Vec::new(),
+            result: None,
+            error: None,
+            started_at: now,
+            finished_at: None,
+        };
+        let id = request.id.clone();
+        if let Ok(mut requests) = self.requests.write() {
+            requests.retain(|request| {
+                request
+                    .finished_at
+                    .is_none_or(|finished| now - finished < RETAIN_FINISHED_SECS)
+            });
+            requests.push(request);
+        }
+        id
+    }
+
+    pub fn finish(&self, id: &str, result: &Result<Value>) {
+        self.update(id, |request| {
+            request.finished_at = Some(chrono::Utc::now().timestamp());
+            match result {
+                Ok(value) => {
+                    request.status = "completed".to_string();
+                    request.result = Some(value.clone());
+                }
+                Err(e) => {
+                    request.status = "failed".to_string();
+                    request.error = Some(e.to_string());
+                }
+            }
+        });
+    }
+
+    /// The namespace's request that sent `hash`
+    pub fn for_hash(&self, namespace: &str, hash: &str) -> Option<TrackedRequest> {
+        self.requests.read().ok().and_then(|requests| {
+            requests
+                .iter()
+                .find(|request| {
+                    request.namespace == namespace
+                        && request
+                            .transactions
+                            .iter()
+                            .any(|transaction| transaction.hash.eq_ignore_ascii_case(hash))
+                })
+                .cloned()
+        })
+    }
+
+    /// The namespace's requests still waiting on their transactions
+    pub fn in_progress(&self, namespace: &str) -> Vec<TrackedRequest> {
+        self.requests
+            .read()
+            .map(|requests| {
+                requests
+                    .iter()
+                    .filter(|request| {
+                        request.namespace == namespace && request.finished_at.is_none()
+                    })
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    fn update(&self, id: &str, change: impl FnOnce(&mut TrackedRequest)) {
+        if let Ok(mut requests) = self.requests.write()
+            && let Some(request) = requests.iter_mut().find(|request| request.id == id)
+        {
+            change(request);
+        }
+    }
+}
+
+/// Run `future` as tracked request `request_id`; each transaction it sends is recorded
+/// and its hash passed to `sent`
+pub async fn run_tracked<T>(
+    tracker: Arc<TxTracker>,
+    request_id: String,
+    sent: mpsc::UnboundedSender<String>,
+    future: impl Future<Output = T>,
+) -> T {
+    let submissions = Submissions {
+        tracker,
+        request_id,
+        sent,
+    };
+    SUBMISSIONS.scope(submissions, future).await
+}
+
+/// Note a sent transaction against the tracked request sending it, if there is one
+pub fn record_submission(hash: &str, description: &str) {
+    let _ = SUBMISSIONS.try_with(|submissions| {
+        submissions
+            .tracker
+            .update(&submissions.request_id, |request| {
+                request.transactions.push(SubmittedTransaction {
+                    hash: hash.to_string(),
+                    description: description.to_string(),
+                    submitted_at: chrono::Utc::now().timestamp(),
+                });
+            });
+        // The request may already have answered with an earlier transaction
+        let _ = submissions.sent.send(hash.to_string());
+    });
+}
@@ -0,0 +1,117 @@
+use anyhow::{Result, anyhow};
+use ethers::types::U256;
+use serde::{Deserialize, Serialize};
+
+use crate::blockchain::BlockchainService;
+
+// Results with more decimal places than this are rejected rather than printed
+const MAX_FRACTION_DIGITS: usize = 100;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Conversion {
+    pub amount: String,
+    pub from: String,
+    pub to: String,
+    pub result: String,
+    pub from_decimals: u32,
+    pub to_decimals: u32,
+}
+
+/// Convert an amount between wei, gwei, ether, a token's base units ("base") and its
+/// display units (the token symbol or address, or "token" with `token` set). The
+/// conversion is exact: amounts are shifted as integers, never through floats.
+pub async fn convert_units(
+    blockchain_service: &BlockchainService,
+    amount: &str,
+    from: &str,
+    to: &str,
+    token: Option<&str>,
+) -> Result<Conversion> {
+    let from_decimals = unit_decimals(blockchain_service, from, token).await?;
+    let to_decimals = unit_decimals(blockchain_service, to, token).await?;
+    let result = shift_decimals(amount, from_decimals, to_decimals)?;
+
+    Ok(Conversion {
+        amount: amount.trim().to_string(),
+        from: from.to_string(),
+        to: to.to_string(),
+        result,
+        from_decimals,
+        to_decimals,
+    })
+}
+
+// Decimal places a unit sits above the smallest unit
+async fn unit_decimals(
+    blockchain_service: &BlockchainService,
+    unit: &str,
+    token: Option<&str>,
+) -> Result<u32> {
+    match unit.trim().to_lowercase().as_str() {
+        "wei" | "base" | "raw" => Ok(0),
+        "kwei" => Ok(3),
+        "mwei" => Ok(6),
+        "gwei" => Ok(9),
+        "szabo" => Ok(12),
+        "finney" => Ok(15),
+        "ether" | "eth" => Ok(18),
+        "token" => {
+            let token =
+                token.ok_or_else(|| anyhow!("The 'token' unit needs the token to convert"))?;
+            Ok(blockchain_service.resolve_token(token).await?.decimals as u32)
+        }
+        _ => Ok(blockchain_service.resolve_token(unit).await?.decimals as u32),
+    }
+}
+
+/// Move the decimal point of `amount` from `from_decimals` to `to_decimals` places,
+/// e.g. ("1500000", 0, 6) -> "1.5". Accepts plain decimals, comma or underscore digit
+/// grouping and exponents such as "1e18".
+pub fn shift_decimals(amount: &str, from_decimals: u32, to_decimals: u32) -> Result<String> {
+    let cleaned: String = amount
+        .trim()
+        .chars()
+        .filter(|c| *c != ',' && *c != '_')
+        .collect();
+    let (mantissa, exponent) = match cleaned.split_once(['e', 'E']) {
+        Some((mantissa, exponent)) => (
+            mantissa,
+            exponent
+                .parse::<i64>()
+                .map_err(|_| anyhow!("Invalid exponent in '{}'", amount))?,
+        ),
+        None => (cleaned.as_str(), 0),
+    };
+    let (integer, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    let digits = format!("{}{}", integer, fraction);
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return Err(anyhow!("Invalid amount '{}'", amount));
+    }
+    let value =
+        U256::from_dec_str(&digits).map_err(|_| anyhow!("Amount '{}' is too large", amount))?;
+
+    // Powers of ten to multiply `value` by, in the target unit
+    let shift = from_decimals as i64 + exponent - fraction.len() as i64 - to_decimals as i64;
+    if shift >= 0 {
+        let factor = U256::from(10)
+            .checked_pow(U256::from(shift))
+            .ok_or_else(|| anyhow!("Amount '{}' is too large", amount))?;
+        let result = value
+            .checked_mul(factor)
+            .ok_or_else(|| anyhow!("Amount '{}' is too large", amount))?;
+        return Ok(result.to_string());
+    }
+
+    let places = (-shift) as usize;
+    if places > MAX_FRACTION_DIGITS {
+        return Err(anyhow!("Amount '{}' is too small to show", amount));
+    }
+    let text = format!("{:0>width$}", value.to_string(), width = places + 1);
+    let (integer, fraction) = text.split_at(text.len() - places);
+    let fraction = fraction.trim_end_matches('0');
+    if fraction.is_empty() {
+        Ok(integer.to_string())
+    } else {
+        Ok(format!("{}.{}", integer, fraction))
+    }
+}
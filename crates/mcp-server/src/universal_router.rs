@@ -0,0 +1,573 @@
+use anyhow::{Result, anyhow};
+use ethers::abi::{ParamType, Token, decode, encode};
+use ethers::types::{Address, Bytes, U256};
+use ethers::utils::{format_units, id, to_checksum};
+use std::collections::HashMap;
+
+use crate::blockchain::BlockchainService;
+
+// Command bytes; the top bit lets a command revert without failing the whole call
+const V3_SWAP_EXACT_IN: u8 = 0x00;
+const SWEEP: u8 = 0x04;
+const V2_SWAP_EXACT_IN: u8 = 0x08;
+const PERMIT2_PERMIT: u8 = 0x0a;
+const WRAP_ETH: u8 = 0x0b;
+const UNWRAP_WETH: u8 = 0x0c;
+const COMMAND_TYPE_MASK: u8 = 0x3f;
+const FLAG_ALLOW_REVERT: u8 = 0x80;
+
+/// Recipient placeholder for the account calling the router
+pub fn msg_sender() -> Address {
+    Address::from_low_u64_be(1)
+}
+
+/// Recipient placeholder for the router itself, holding tokens for a later command
+pub fn address_this() -> Address {
+    Address::from_low_u64_be(2)
+}
+
+// An amount meaning "whatever the router holds"
+fn contract_balance() -> U256 {
+    U256::one() << 255
+}
+
+/// A Universal Router command with its decoded input
+#[derive(Debug, Clone)]
+pub enum Command {
+    /// Signed Permit2 allowance for `spender` to pull `amount` of `token`
+    Permit2Permit {
+        token: Address,
+        amount: U256,
+        expiration: u64,
+        nonce: u64,
+        spender: Address,
+        sig_deadline: U256,
+        signature: Bytes,
+    },
+    WrapEth {
+        recipient: Address,
+        amount_min: U256,
+    },
+    UnwrapWeth {
+        recipient: Address,
+        amount_min: U256,
+    },
+    V2SwapExactIn {
+        recipient: Address,
+        amount_in: U256,
+        amount_out_min: U256,
+        path: Vec<Address>,
+        /// Paid from the caller through Permit2 rather than from the router's balance
+        payer_is_user: bool,
+    },
+    V3SwapExactIn {
+        recipient: Address,
+        amount_in: U256,
+        amount_out_min: U256,
+        tokens: Vec<Address>,
+        /// `fees[i]` is the pool between `tokens[i]` and `tokens[i + 1]`
+        fees: Vec<u32>,
+        payer_is_user: bool,
+    },
+    /// Send the router's whole balance of `token` (ETH for the zero address)
+    Sweep {
+        token: Address,
+        recipient: Address,
+        amount_min: U256,
+    },
+    /// A command this module doesn't decode
+    Other { command: u8, input: Bytes },
+}
+
+impl Command {
+    fn byte(&self) -> u8 {
+        match self {
+            Self::Permit2Permit { .. } => PERMIT2_PERMIT,
+            Self::WrapEth { .. } => WRAP_ETH,
+            Self::UnwrapWeth { .. } => UNWRAP_WETH,
+            Self::V2SwapExactIn { .. } => V2_SWAP_EXACT_IN,
+            Self::V3SwapExactIn { .. } => V3_SWAP_EXACT_IN,
+            Self::Sweep { .. } => SWEEP,
+            Self::Other { command, .. } => *command,
+        }
+    }
+
+    fn encode_input(&self) -> Bytes {
+        let tokens = match self {
+            Self::Permit2Permit {
+                token,
+                amount,
+                expiration,
+                nonce,
+                spender,
+                sig_deadline,
+                signature,
+            } => vec![
+                Token::Tuple(vec![
+                    Token::Tuple(vec![
+                        Token::Address(*token),
+                        Token::Uint(*amount),
+                        Token::Uint((*expiration).into()),
+                        Token::Uint((*nonce).into()),
+                    ]),
+                    Token::Address(*spender),
+                    Token::Uint(*sig_deadline),
+                ]),
+                Token::Bytes(signature.to_vec()),
+            ],
+            Self::WrapEth {
+                recipient,
+                amount_min,
+            }
+            | Self::UnwrapWeth {
+                recipient,
+                amount_min,
+            } => vec![Token::Address(*recipient), Token::Uint(*amount_min)],
+            Self::V2SwapExactIn {
+                recipient,
+                amount_in,
+                amount_out_min,
+                path,
+                payer_is_user,
+            } => vec![
+                Token::Address(*recipient),
+                Token::Uint(*amount_in),
+                Token::Uint(*amount_out_min),
+                Token::Array(path.iter().map(|token| Token::Address(*token)).collect()),
+                Token::Bool(*payer_is_user),
+            ],
+            Self::V3SwapExactIn {
+                recipient,
+                amount_in,
+                amount_out_min,
+                tokens,
+                fees,
+                payer_is_user,
+            } => vec![
+                Token::Address(*recipient),
+                Token::Uint(*amount_in),
+                Token::Uint(*amount_out_min),
+                Token::Bytes(encode_v3_path(tokens, fees)),
+                Token::Bool(*payer_is_user),
+            ],
+            Self::Sweep {
+                token,
+                recipient,
+                amount_min,
+            } => vec![
+                Token::Address(*token),
+                Token::Address(*recipient),
+                Token::Uint(*amount_min),
+            ],
+            Self::Other { input, .. } => return input.clone(),
+        };
+        Bytes::from(encode(&tokens))
+    }
+
+    fn decode(command: u8, input: &Bytes) -> Result<Self> {
+        let address = ParamType::Address;
+        let uint = ParamType::Uint(256);
+        let command = command & COMMAND_TYPE_MASK;
+        let params = match command {
+            PERMIT2_PERMIT => vec![
+                ParamType::Tuple(vec![
+                    ParamType::Tuple(vec![
+                        address.clone(),
+                        ParamType::Uint(160),
+                        ParamType::Uint(48),
+                        ParamType::Uint(48),
+                    ]),
+                    address.clone(),
+                    uint.clone(),
+                ]),
+                ParamType::Bytes,
+            ],
+            WRAP_ETH | UNWRAP_WETH => vec![address, uint],
+            V2_SWAP_EXACT_IN => vec![
+                address.clone(),
+                uint.clone(),
+                uint,
+                ParamType::Array(Box::new(address)),
+                ParamType::Bool,
+            ],
+            V3_SWAP_EXACT_IN => vec![
+                address,
+                uint.clone(),
+                uint,
+                ParamType::Bytes,
+                ParamType::Bool,
+            ],
+            SWEEP => vec![address.clone(), address, uint],
+            _ => {
+                return Ok(Self::Other {
+                    command,
+                    input: input.clone(),
+                });
+            }
+        };
+        let mut values = decode(&params, input)
+            .map_err(|e| anyhow!("Invalid input for command {:#04x}: {}", command, e))?
+            .into_iter();
+        let mut next = || {
+            values
+                .next()
+                .ok_or_else(|| anyhow!("Missing command input"))
+        };
+        let as_address = |token: Token| token.into_address().unwrap_or_default();
+        let as_uint = |token: Token| token.into_uint().unwrap_or_default();
+        let as_bool = |token: Token| token.into_bool().unwrap_or_default();
+
+        Ok(match command {
+            PERMIT2_PERMIT => {
+                let permit = next()?.into_tuple().unwrap_or_default();
+                let signature = next()?.into_bytes().unwrap_or_default();
+                let [details, spender, sig_deadline] =
+                    <[Token; 3]>::try_from(permit).map_err(|_| anyhow!("Invalid permit"))?;
+                let [token, amount, expiration, nonce] =
+                    <[Token; 4]>::try_from(details.into_tuple().unwrap_or_default())
+                        .map_err(|_| anyhow!("Invalid permit details"))?;
+                Self::Permit2Permit {
+                    token: as_address(token),
+                    amount: as_uint(amount),
+                    expiration: as_uint(expiration).low_u64(),
+                    nonce: as_uint(nonce).low_u64(),
+                    spender: as_address(spender),
+                    sig_deadline: as_uint(sig_deadline),
+                    signature: Bytes::from(signature),
+                }
+            }
+            WRAP_ETH | UNWRAP_WETH => {
+                let recipient = as_address(next()?);
+                let amount_min = as_uint(next()?);
+                if command == WRAP_ETH {
+                    Self::WrapEth {
+                        recipient,
+                        amount_min,
+                    }
+                } else {
+                    Self::UnwrapWeth {
+                        recipient,
+                        amount_min,
+                    }
+                }
+            }
+            V2_SWAP_EXACT_IN => Self::V2SwapExactIn {
+                recipient: as_address(next()?),
+                amount_in: as_uint(next()?),
+                amount_out_min: as_uint(next()?),
+                path: next()?
+                    .into_array()
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(as_address)
+                    .collect(),
+                payer_is_user: as_bool(next()?),
+            },
+            V3_SWAP_EXACT_IN => {
+                let recipient = as_address(next()?);
+                let amount_in = as_uint(next()?);
+                let amount_out_min = as_uint(next()?);
+                let (tokens, fees) = decode_v3_path(&next()?.into_bytes().unwrap_or_default())?;
+                Self::V3SwapExactIn {
+                    recipient,
+                    amount_in,
+                    amount_out_min,
+                    tokens,
+                    fees,
+                    payer_is_user: as_bool(next()?),
+                }
+            }
+            _ => Self::Sweep {
+                token: as_address(next()?),
+                recipient: as_address(next()?),
+                amount_min: as_uint(next()?),
+            },
+        })
+    }
+
+    /// One line saying what the command does, with amounts in `tokens`' units where the
+    /// token is known
+    pub fn describe(&self, tokens: &HashMap<Address, (String, u8)>) -> String {
+        let symbol = |token: &Address| {
+            tokens
+                .get(token)
+                .map(|(symbol, _)| symbol.clone())
+                .unwrap_or_else(|| to_checksum(token, None))
+        };
+        let amount = |value: U256, token: &Address| {
+            if value == contract_balance() {
+                return format!("the router's whole {} balance", symbol(token));
+            }
+            match tokens.get(token) {
+                Some((symbol, decimals)) => match format_units(value, *decimals as u32) {
+                    Ok(value) => format!("{} {}", value, symbol),
+                    Err(_) => format!("{} (raw) {}", value, symbol),
+                },
+                None => format!("{} (raw) of {}", value, to_checksum(token, None)),
+            }
+        };
+        let eth = |value: U256| {
+            format_units(value, 18)
+                .map(|value| format!("{} ETH", value))
+                .unwrap_or_else(|_| format!("{} wei", value))
+        };
+        let recipient = |address: &Address| {
+            if *address == msg_sender() {
+                "the sender".to_string()
+            } else if *address == address_this() {
+                "the router".to_string()
+            } else {
+                to_checksum(address, None)
+            }
+        };
+        let payer = |payer_is_user: bool| {
+            if payer_is_user {
+                "paid by the sender through Permit2"
+            } else {
+                "paid from the router's balance"
+            }
+        };
+
+        match self {
+            Self::Permit2Permit {
+                token,
+                amount: allowed,
+                expiration,
+                spender,
+                ..
+            } => format!(
+                "Permit {} to pull up to {} through Permit2 until {} (a signature, not a transaction)",
+                to_checksum(spender, None),
+                amount(*allowed, token),
+                chrono::DateTime::from_timestamp(*expiration as i64, 0)
+                    .map(|at| at.format("%Y-%m-%d %H:%M UTC").to_string())
+                    .unwrap_or_else(|| expiration.to_string())
+            ),
+            Self::WrapEth {
+                recipient: to,
+                amount_min,
+            } => {
+                let wrapped = if *amount_min == contract_balance() {
+                    "all ETH the router holds".to_string()
+                } else {
+                    eth(*amount_min)
+                };
+                format!("Wrap {} into WETH for {}", wrapped, recipient(to))
+            }
+            Self::UnwrapWeth {
+                recipient: to,
+                amount_min,
+            } => format!(
+                "Unwrap the router's WETH into ETH for {}, at least {}",
+                recipient(to),
+                eth(*amount_min)
+            ),
+            Self::V2SwapExactIn {
+                recipient: to,
+                amount_in,
+                amount_out_min,
+                path,
+                payer_is_user,
+            } => {
+                let (Some(first), Some(last)) = (path.first(), path.last()) else {
+                    return "Swap on Uniswap V2 with an empty path".to_string();
+                };
+                format!(
+                    "Swap {} for at least {} on Uniswap V2 ({}), {}, to {}",
+                    amount(*amount_in, first),
+                    amount(*amount_out_min, last),
+                    path.iter().map(symbol).collect::<Vec<_>>().join(" -> "),
+                    payer(*payer_is_user),
+                    recipient(to)
+                )
+            }
+            Self::V3SwapExactIn {
+                recipient: to,
+                amount_in,
+                amount_out_min,
+                tokens: path,
+                fees,
+                payer_is_user,
+            } => {
+                let (Some(first), Some(last)) = (path.first(), path.last()) else {
+                    return "Swap on Uniswap V3 with an empty path".to_string();
+                };
+                let mut route = symbol(first);
+                for (token, fee) in path.iter().skip(1).zip(fees) {
+                    route.push_str(&format!(
+                        " -({}%)-> {}",
+                        *fee as f64 / 10_000.0,
+                        symbol(token)
+                    ));
+                }
+                format!(
+                    "Swap {} for at least {} on Uniswap V3 ({}), {}, to {}",
+                    amount(*amount_in, first),
+                    amount(*amount_out_min, last),
+                    route,
+                    payer(*payer_is_user),
+                    recipient(to)
+                )
+            }
+            Self::Sweep {
+                token,
+                recipient: to,
+                amount_min,
+            } => {
+                let (what, minimum) = if token.is_zero() {
+                    ("ETH".to_string(), eth(*amount_min))
+                } else {
+                    (symbol(token), amount(*amount_min, token))
+                };
+                format!(
+                    "Sweep the router's {} to {}, at least {}",
+                    what,
+                    recipient(to),
+                    minimum
+                )
+            }
+            Self::Other { command, .. } => {
+                format!("Command {:#04x}, which isn't decoded", command)
+            }
+        }
+    }
+
+    // Tokens the command names, for looking up symbols and decimals
+    fn tokens(&self) -> Vec<Address> {
+        match self {
+            Self::Permit2Permit { token, .. } | Self::Sweep { token, .. } => vec![*token],
+            Self::V2SwapExactIn { path, .. } => path.clone(),
+            Self::V3SwapExactIn { tokens, .. } => tokens.clone(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+// Packed V3 path: token (20 bytes), fee (3 bytes), token, ...
+fn encode_v3_path(tokens: &[Address], fees: &[u32]) -> Vec<u8> {
+    let mut path = Vec::with_capacity(tokens.len() * 23);
+    for (i, token) in tokens.iter().enumerate() {
+        path.extend_from_slice(token.as_bytes());
+        if let Some(fee) = fees.get(i) {
+            path.extend_from_slice(&fee.to_be_bytes()[1..]);
+        }
+    }
+    path
+}
+
+fn decode_v3_path(path: &[u8]) -> Result<(Vec<Address>, Vec<u32>)> {
+    if path.len() < 20 || !(path.len() - 20).is_multiple_of(23) {
+        return Err(anyhow!("Invalid V3 path of {} bytes", path.len()));
+    }
+    let mut tokens = vec![Address::from_slice(&path[..20])];
+    let mut fees = Vec::new();
+    for hop in path[20..].chunks(23) {
+        fees.push(u32::from_be_bytes([0, hop[0], hop[1], hop[2]]));
+        tokens.push(Address::from_slice(&hop[3..]));
+    }
+    Ok((tokens, fees))
+}
+
+/// The `commands` and `inputs` arguments of `execute` for a command list
+pub fn encode_commands(commands: &[Command]) -> (Bytes, Vec<Bytes>) {
+    (
+        Bytes::from(commands.iter().map(Command::byte).collect::<Vec<_>>()),
+        commands.iter().map(Command::encode_input).collect(),
+    )
+}
+
+/// Commands from the `commands` and `inputs` arguments of `execute`
+pub fn decode_commands(commands: &[u8], inputs: &[Bytes]) -> Result<Vec<Command>> {
+    if commands.len() != inputs.len() {
+        return Err(anyhow!(
+            "{} commands but {} inputs",
+            commands.len(),
+            inputs.len()
+        ));
+    }
+    commands
+        .iter()
+        .zip(inputs)
+        .map(|(command, input)| Command::decode(*command, input))
+        .collect()
+}
+
+/// The command bytes and commands of calldata calling `execute` on the Universal
+/// Router; None when the calldata calls something else
+pub fn decode_execute(data: &[u8]) -> Result<Option<(Vec<u8>, Vec<Command>)>> {
+    if data.len() < 4 {
+        return Ok(None);
+    }
+    let params = if data[..4] == id("execute(bytes,bytes[],uint256)") {
+        vec![
+            ParamType::Bytes,
+            ParamType::Array(Box::new(ParamType::Bytes)),
+            ParamType::Uint(256),
+        ]
+    } else if data[..4] == id("execute(bytes,bytes[])") {
+        vec![
+            ParamType::Bytes,
+            ParamType::Array(Box::new(ParamType::Bytes)),
+        ]
+    } else {
+        return Ok(None);
+    };
+    let mut values = decode(&params, &data[4..])?.into_iter();
+    let commands = values
+        .next()
+        .and_then(Token::into_bytes)
+        .unwrap_or_default();
+    let inputs: Vec<Bytes> = values
+        .next()
+        .and_then(Token::into_array)
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(Token::into_bytes)
+        .map(Bytes::from)
+        .collect();
+    let decoded = decode_commands(&commands, &inputs)?;
+    Ok(Some((commands, decoded)))
+}
+
+/// Human-readable steps for a command list, one per command. Commands flagged to
+/// allow reverting say so.
+pub fn describe_commands(
+    command_bytes: &[u8],
+    commands: &[Command],
+    tokens: &HashMap<Address, (String, u8)>,
+) -> Vec<String> {
+    commands
+        .iter()
+        .enumerate()
+        .map(|(i, command)| {
+            let step = command.describe(tokens);
+            match command_bytes.get(i) {
+                Some(byte) if byte & FLAG_ALLOW_REVERT != 0 => {
+                    format!("{} (allowed to fail)", step)
+                }
+                _ => step,
+            }
+        })
+        .collect()
+}
+
+/// Symbols and decimals of the tokens the commands name, skipping any that can't be
+/// resolved
+pub async fn token_labels(
+    blockchain_service: &BlockchainService,
+    commands: &[Command],
+) -> HashMap<Address, (String, u8)> {
+    let mut labels = HashMap::new();
+    for token in commands.iter().flat_map(Command::tokens) {
+        if token.is_zero() || labels.contains_key(&token) {
+            continue;
+        }
+        if let Ok(info) = blockchain_service
+            .resolve_token(&format!("{:?}", token))
+            .await
+        {
+            labels.insert(token, (info.symbol, info.decimals));
+        }
+    }
+    labels
+}
@@ -0,0 +1,520 @@
+use anyhow::{Result, anyhow};
+use ethers::abi::{Abi, parse_abi};
+use ethers::contract::Contract;
+use ethers::providers::{Middleware, Provider};
+use ethers::types::{Address, Filter, H256, U256};
+use ethers::utils::{format_units, keccak256};
+use serde::{Deserialize, Serialize};
+use shared::{Account, ContractCall, TransactionResult};
+use std::str::FromStr;
+
+use crate::blockchain::{BlockchainService, FeeOverrides};
+use crate::failover::FailoverClient;
+use crate::scanner::{CheckpointStore, LogScanner, ScanConfig};
+
+/// How far back Sablier stream NFTs are looked for when the caller gives no stream id
+pub const DEFAULT_VESTING_LOOKBACK_BLOCKS: u64 = 2_000_000;
+// Transfers are filtered by contract and recipient topic, so wide ranges are cheap
+const VESTING_SCAN_CHUNK: u64 = 50_000;
+
+const VESTING_WALLET_ABI: &[&str] = &[
+    "function start() view returns (uint256)",
+    "function duration() view returns (uint256)",
+    "function owner() view returns (address)",
+    "function beneficiary() view returns (address)",
+    "function cliff() view returns (uint256)",
+];
+// released and vestedAmount are overloaded for ETH and tokens, so each variant gets
+// its own ABI rather than being picked by selector
+const VESTING_WALLET_ETH_ABI: &[&str] = &[
+    "function released() view returns (uint256)",
+    "function vestedAmount(uint64 timestamp) view returns (uint256)",
+];
+const VESTING_WALLET_TOKEN_ABI: &[&str] = &[
+    "function released(address token) view returns (uint256)",
+    "function vestedAmount(address token, uint64 timestamp) view returns (uint256)",
+];
+pub(crate) const SABLIER_LOCKUP_ABI: &[&str] = &[
+    "function nextStreamId() view returns (uint256)",
+    "function getSender(uint256 streamId) view returns (address)",
+    "function getRecipient(uint256 streamId) view returns (address)",
+    "function getAsset(uint256 streamId) view returns (address)",
+    "function getDepositedAmount(uint256 streamId) view returns (uint128)",
+    "function getWithdrawnAmount(uint256 streamId) view returns (uint128)",
+    "function getRefundedAmount(uint256 streamId) view returns (uint128)",
+    "function streamedAmountOf(uint256 streamId) view returns (uint128)",
+    "function withdrawableAmountOf(uint256 streamId) view returns (uint128)",
+    "function getStartTime(uint256 streamId) view returns (uint40)",
+    "function getEndTime(uint256 streamId) view returns (uint40)",
+    "function getCliffTime(uint256 streamId) view returns (uint40)",
+    "function isCancelable(uint256 streamId) view returns (bool)",
+    "function statusOf(uint256 streamId) view returns (uint8)",
+];
+pub(crate) const SABLIER_STATUSES: &[&str] =
+    &["pending", "streaming", "settled", "canceled", "depleted"];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Unlock {
+    pub timestamp: u64,
+    /// "cliff" or "fully vested"
+    pub event: String,
+    /// Total vested at that moment
+    pub vested_by_then: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VestingSchedule {
+    /// "vesting_wallet" or "sablier"
+    pub kind: String,
+    pub contract: String,
+    pub stream_id: Option<String>,
+    pub beneficiary: String,
+    pub token: String,
+    pub token_address: Option<String>,
+    /// Everything the schedule will ever pay out
+    pub total: String,
+    pub vested: String,
+    /// Already released to, or withdrawn by, the beneficiary
+    pub released: String,
+    /// Vested but not yet released; what `release_vested` would pay out now
+    pub claimable: String,
+    /// Not vested yet
+    pub locked: String,
+    pub start: u64,
+    pub cliff: Option<u64>,
+    pub end: u64,
+    pub upcoming_unlocks: Vec<Unlock>,
+    /// Sablier only: "pending", "streaming", "settled", "canceled" or "depleted"
+    pub status: Option<String>,
+    pub cancelable: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VestingRelease {
+    pub transaction: TransactionResult,
+    pub contract: String,
+    pub stream_id: Option<String>,
+    pub token: String,
+    pub amount: String,
+}
+
+enum VestingContract {
+    Wallet(Address),
+    Sablier(Contract<Provider<FailoverClient>>),
+}
+
+// What kind of vesting contract lives at `address`, told apart by the getters it answers
+async fn detect(
+    blockchain_service: &BlockchainService,
+    address: Address,
+) -> Result<VestingContract> {
+    let sablier = Contract::new(
+        address,
+        parse_abi(SABLIER_LOCKUP_ABI)?,
+        blockchain_service.provider(),
+    );
+    if sablier
+        .method::<_, U256>("nextStreamId", ())?
+        .call()
+        .await
+        .is_ok()
+    {
+        return Ok(VestingContract::Sablier(sablier));
+    }
+
+    let wallet = Contract::new(
+        address,
+        parse_abi(VESTING_WALLET_ABI)?,
+        blockchain_service.provider(),
+    );
+    if wallet
+        .method::<_, U256>("duration", ())?
+        .call()
+        .await
+        .is_ok()
+    {
+        return Ok(VestingContract::Wallet(address));
+    }
+    Err(anyhow!(
+        "{:?} isn't an OpenZeppelin VestingWallet or a Sablier lockup contract",
+        address
+    ))
+}
+
+// Symbol, decimals and address of the vested asset; ETH when `token` is None
+async fn asset(
+    blockchain_service: &BlockchainService,
+    token: Option<&str>,
+) -> Result<(String, u8, Option<Address>)> {
+    match token.filter(|token| !token.eq_ignore_ascii_case("ETH")) {
+        Some(token) => {
+            let info = blockchain_service.resolve_token(token).await?;
+            Ok((
+                info.symbol,
+                info.decimals,
+                Some(Address::from_str(&info.address)?),
+            ))
+        }
+        None => Ok(("ETH".to_string(), 18, None)),
+    }
+}
+
+fn units(raw: U256, decimals: u8) -> Result<String> {
+    Ok(format_units(raw, decimals as u32)?)
+}
+
+async fn now(blockchain_service: &BlockchainService) -> Result<u64> {
+    Ok(blockchain_service
+        .provider()
+        .get_block(ethers::types::BlockNumber::Latest)
+        .await?
+        .map(|block| block.timestamp.as_u64())
+        .unwrap_or_else(|| chrono::Utc::now().timestamp() as u64))
+}
+
+async fn wallet_schedule(
+    blockchain_service: &BlockchainService,
+    address: Address,
+    token: Option<&str>,
+) -> Result<VestingSchedule> {
+    let (symbol, decimals, token_address) = asset(blockchain_service, token).await?;
+    let variant = match token_address {
+        Some(_) => VESTING_WALLET_TOKEN_ABI,
+        None => VESTING_WALLET_ETH_ABI,
+    };
+    let abi: Abi = parse_abi(&[VESTING_WALLET_ABI, variant].concat())?;
+    let wallet = Contract::new(address, abi, blockchain_service.provider());
+    let start: U256 = wallet.method("start", ())?.call().await?;
+    let duration: U256 = wallet.method("duration", ())?.call().await?;
+    // OpenZeppelin 5 renamed beneficiary() to owner()
+    let beneficiary: Address = match wallet.method::<_, Address>("owner", ())?.call().await {
+        Ok(owner) => owner,
+        Err(_) => wallet.method("beneficiary", ())?.call().await?,
+    };
+    let cliff: Option<U256> = wallet
+        .method::<_, U256>("cliff", ())?
+        .call()
+        .await
+        .ok()
+        .filter(|cliff| *cliff > start);
+    let (start, end) = (start.as_u64(), (start + duration).as_u64());
+
+    let wallet = &wallet;
+    let vested_at = |timestamp: u64| async move {
+        let vested: U256 = match token_address {
+            Some(token) => {
+                wallet
+                    .method("vestedAmount", (token, timestamp))?
+                    .call()
+                    .await?
+            }
+            None => wallet.method("vestedAmount", timestamp)?.call().await?,
+        };
+        Ok::<U256, anyhow::Error>(vested)
+    };
+    let released: U256 = match token_address {
+        Some(token) => wallet.method("released", token)?.call().await?,
+        None => wallet.method("released", ())?.call().await?,
+    };
+    let current = now(blockchain_service).await?;
+    let vested = vested_at(current).await?;
+    // The wallet vests whatever it holds plus what it already paid out
+    let total = vested_at(end).await?;
+
+    let mut upcoming_unlocks = Vec::new();
+    if let Some(cliff) = cliff
+        .map(|cliff| cliff.as_u64())
+        .filter(|cliff| *cliff > current)
+    {
+        upcoming_unlocks.push(Unlock {
+            timestamp: cliff,
+            event: "cliff".to_string(),
+            vested_by_then: units(vested_at(cliff).await?, decimals)?,
+        });
+    }
+    if end > current {
+        upcoming_unlocks.push(Unlock {
+            timestamp: end,
+            event: "fully vested".to_string(),
+            vested_by_then: units(total, decimals)?,
+        });
+    }
+
+    Ok(VestingSchedule {
+        kind: "vesting_wallet".to_string(),
+        contract: format!("{:?}", wallet.address()),
+        stream_id: None,
+        beneficiary: format!("{:?}", beneficiary),
+        token: symbol,
+        token_address: token_address.map(|token| format!("{:?}", token)),
+        total: units(total, decimals)?,
+        vested: units(vested, decimals)?,
+        released: units(released, decimals)?,
+        claimable: units(vested.saturating_sub(released), decimals)?,
+        locked: units(total.saturating_sub(vested), decimals)?,
+        start,
+        cliff: cliff.map(|cliff| cliff.as_u64()),
+        end,
+        upcoming_unlocks,
+        status: None,
+        cancelable: None,
+    })
+}
+
+async fn sablier_schedule(
+    blockchain_service: &BlockchainService,
+    lockup: &Contract<Provider<FailoverClient>>,
+    id: U256,
+) -> Result<VestingSchedule> {
+    let recipient: Address = lockup.method("getRecipient", id)?.call().await?;
+    let token: Address = lockup.method("getAsset", id)?.call().await?;
+    let info = blockchain_service
+        .resolve_token(&format!("{:?}", token))
+        .await?;
+    let amount = |name: &'static str| async move {
+        let value: U256 = lockup.method(name, id)?.call().await?;
+        Ok::<U256, anyhow::Error>(value)
+    };
+    let deposited = amount("getDepositedAmount").await?;
+    let refunded = amount("getRefundedAmount").await.unwrap_or_default();
+    let withdrawn = amount("getWithdrawnAmount").await?;
+    let streamed = amount("streamedAmountOf").await?;
+    let withdrawable = amount("withdrawableAmountOf").await?;
+    let start = amount("getStartTime").await?.as_u64();
+    let end = amount("getEndTime").await?.as_u64();
+    // Only linear streams have a cliff
+    let cliff = amount("getCliffTime")
+        .await
+        .ok()
+        .map(|cliff| cliff.as_u64())
+        .filter(|cliff| *cliff > start);
+    let status: u8 = lockup.method("statusOf", id)?.call().await?;
+    let cancelable: bool = lockup.method("isCancelable", id)?.call().await?;
+    let total = deposited.saturating_sub(refunded);
+
+    let mut upcoming_unlocks = Vec::new();
+    let current = now(blockchain_service).await?;
+    if let Some(cliff) = cliff.filter(|cliff| *cliff > current) {
+        // Linear streams release the whole elapsed share at the cliff
+        let vested_by_then = total * U256::from(cliff - start) / U256::from((end - start).max(1));
+        upcoming_unlocks.push(Unlock {
+            timestamp: cliff,
+            event: "cliff".to_string(),
+            vested_by_then: units(vested_by_then, info.decimals)?,
+        });
+    }
+    if end > current && status == 1 {
+        upcoming_unlocks.push(Unlock {
+            timestamp: end,
+            event: "fully vested".to_string(),
+            vested_by_then: units(total, info.decimals)?,
+        });
+    }
+
+    Ok(VestingSchedule {
+        kind: "sablier".to_string(),
+        contract: format!("{:?}", lockup.address()),
+        stream_id: Some(id.to_string()),
+        beneficiary: format!("{:?}", recipient),
+        token: info.symbol.clone(),
+        token_address: Some(info.address.clone()),
+        total: units(total, info.decimals)?,
+        vested: units(streamed, info.decimals)?,
+        released: units(withdrawn, info.decimals)?,
+        claimable: units(withdrawable, info.decimals)?,
+        locked: units(total.saturating_sub(streamed), info.decimals)?,
+        start,
+        cliff,
+        end,
+        upcoming_unlocks,
+        status: SABLIER_STATUSES
+            .get(status as usize)
+            .map(|status| status.to_string()),
+        cancelable: Some(cancelable),
+    })
+}
+
+// Ids of the stream NFTs `holder` received from the lockup contract and still holds
+async fn sablier_streams(
+    blockchain_service: &BlockchainService,
+    lockup: &Contract<Provider<FailoverClient>>,
+    holder: Address,
+    lookback: u64,
+) -> Result<Vec<U256>> {
+    let latest = blockchain_service
+        .provider()
+        .get_block_number()
+        .await?
+        .as_u64();
+    let mut recipient = [0u8; 32];
+    recipient[12..].copy_from_slice(holder.as_bytes());
+    let filter = Filter::new()
+        .address(lockup.address())
+        .topic0(H256::from(keccak256("Transfer(address,address,uint256)")))
+        .topic2(H256(recipient));
+
+    let mut ids = Vec::new();
+    let checkpoints = CheckpointStore::in_memory();
+    LogScanner::new(blockchain_service.provider(), &checkpoints)
+        .with_config(ScanConfig {
+            chunk_size: VESTING_SCAN_CHUNK,
+            ..ScanConfig::default()
+        })
+        .scan(
+            "vesting",
+            &filter,
+            latest.saturating_sub(lookback),
+            Some(latest),
+            |logs| {
+                ids.extend(
+                    logs.iter()
+                        .filter_map(|log| log.topics.get(3))
+                        .map(|id| U256::from_big_endian(id.as_bytes())),
+                );
+                Ok(())
+            },
+        )
+        .await?;
+    ids.sort();
+    ids.dedup();
+
+    let mut held = Vec::new();
+    for id in ids {
+        let current: Address = lockup.method("getRecipient", id)?.call().await?;
+        if current == holder {
+            held.push(id);
+        }
+    }
+    Ok(held)
+}
+
+/// Vested, claimable and locked amounts and upcoming unlocks for a vesting contract:
+/// an OpenZeppelin VestingWallet (for `token`, or ETH), or one or all of `holder`'s
+/// streams in a Sablier lockup contract
+pub async fn get_vesting(
+    blockchain_service: &BlockchainService,
+    contract: &str,
+    holder: Option<&str>,
+    token: Option<&str>,
+    stream_id: Option<&str>,
+    lookback: u64,
+) -> Result<Vec<VestingSchedule>> {
+    let address = Address::from_str(contract)
+        .map_err(|_| anyhow!("Invalid contract address: {}", contract))?;
+    match detect(blockchain_service, address).await? {
+        VestingContract::Wallet(wallet) => Ok(vec![
+            wallet_schedule(blockchain_service, wallet, token).await?,
+        ]),
+        VestingContract::Sablier(lockup) => {
+            let ids = match (stream_id, holder) {
+                (Some(id), _) => vec![
+                    U256::from_dec_str(id.trim())
+                        .map_err(|_| anyhow!("Invalid stream id: {}", id))?,
+                ],
+                (None, Some(holder)) => {
+                    let holder = Address::from_str(holder)
+                        .map_err(|_| anyhow!("Invalid address: {}", holder))?;
+                    sablier_streams(blockchain_service, &lockup, holder, lookback).await?
+                }
+                (None, None) => {
+                    return Err(anyhow!(
+                        "Give a stream id or the recipient's address for a Sablier contract"
+                    ));
+                }
+            };
+            let mut schedules = Vec::with_capacity(ids.len());
+            for id in ids {
+                schedules.push(sablier_schedule(blockchain_service, &lockup, id).await?);
+            }
+            Ok(schedules)
+        }
+    }
+}
+
+/// Pay out what has vested: release() on a VestingWallet, which anyone may call and
+/// which pays the beneficiary, or a withdrawal of everything withdrawable from a
+/// Sablier stream the account receives
+pub async fn release_vested(
+    blockchain_service: &BlockchainService,
+    from_account: &Account,
+    contract: &str,
+    token: Option<&str>,
+    stream_id: Option<&str>,
+    fees: &FeeOverrides,
+) -> Result<VestingRelease> {
+    let address = Address::from_str(contract)
+        .map_err(|_| anyhow!("Invalid contract address: {}", contract))?;
+    let account = Address::from_str(&from_account.address)?;
+
+    let (schedule, call) = match detect(blockchain_service, address).await? {
+        VestingContract::Wallet(wallet) => {
+            let schedule = wallet_schedule(blockchain_service, wallet, token).await?;
+            let call = match &schedule.token_address {
+                Some(token) => ("release(address)", vec![token.clone()]),
+                None => ("release()", Vec::new()),
+            };
+            (schedule, call)
+        }
+        VestingContract::Sablier(lockup) => {
+            let id =
+                stream_id.ok_or_else(|| anyhow!("stream_id is required for a Sablier contract"))?;
+            let id =
+                U256::from_dec_str(id.trim()).map_err(|_| anyhow!("Invalid stream id: {}", id))?;
+            let schedule = sablier_schedule(blockchain_service, &lockup, id).await?;
+            if Address::from_str(&schedule.beneficiary)? != account {
+                return Err(anyhow!(
+                    "Stream {} pays {}, not {}",
+                    id,
+                    schedule.beneficiary,
+                    from_account.name
+                ));
+            }
+            let withdrawable: U256 = lockup.method("withdrawableAmountOf", id)?.call().await?;
+            let call = (
+                "withdraw(uint256,address,uint128)",
+                vec![
+                    id.to_string(),
+                    format!("{:?}", account),
+                    withdrawable.to_string(),
+                ],
+            );
+            (schedule, call)
+        }
+    };
+    if schedule.claimable.parse::<f64>().unwrap_or(0.0) <= 0.0 {
+        return Err(anyhow!(
+            "Nothing has vested since the last release; the next unlock is {}",
+            schedule
+                .upcoming_unlocks
+                .first()
+                .map(|unlock| format!("at {} ({})", unlock.timestamp, unlock.event))
+                .unwrap_or_else(|| "not scheduled".to_string())
+        ));
+    }
+
+    let (function_signature, parameters) = call;
+    let call = ContractCall {
+        contract_address: format!("{:?}", address),
+        function_signature: function_signature.to_string(),
+        parameters,
+        from: None,
+    };
+    let transaction = blockchain_service
+        .write_contract(
+            from_account,
+            &call,
+            "0",
+            None,
+            &|name| name.to_string(),
+            fees,
+        )
+        .await?;
+
+    Ok(VestingRelease {
+        transaction,
+        contract: format!("{:?}", address),
+        stream_id: schedule.stream_id,
+        token: schedule.token,
+        amount: schedule.claimable,
+    })
+}
@@ -0,0 +1,744 @@
+use anyhow::{Result, anyhow};
+use base64::Engine;
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Nonce};
+use ed25519_dalek::{Signer, SigningKey};
+use ethers::core::rand;
+use ethers::types::transaction::eip2718::TypedTransaction;
+use ethers::types::{NameOrAddress, U256};
+use ethers::utils::to_checksum;
+use futures::{SinkExt, StreamExt};
+use hkdf::Hkdf;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+use tracing::{info, warn};
+use x25519_dalek::{PublicKey, StaticSecret};
+
+const DEFAULT_RELAY_URL: &str = "wss://relay.walletconnect.org";
+// Shown to the user in their wallet when it's asked to pair
+const DEFAULT_APP_URL: &str = "https://github.com/vera-codes6/ai-blockchain-assistant";
+// How long a pairing URI stays valid, and how long a wallet has to answer a request
+const PAIRING_TTL: Duration = Duration::from_secs(300);
+const REQUEST_TTL: Duration = Duration::from_secs(300);
+const SESSION_METHODS: &[&str] = &["eth_sendTransaction", "personal_sign"];
+const SESSION_EVENTS: &[&str] = &["chainChanged", "accountsChanged"];
+
+// Relay message tags from the WalletConnect Sign protocol
+const TAG_SESSION_PROPOSE: u32 = 1100;
+const TAG_SESSION_SETTLE_RESPONSE: u32 = 1103;
+const TAG_SESSION_REQUEST: u32 = 1108;
+const TAG_SESSION_DELETE: u32 = 1112;
+
+/// WalletConnect relay settings, read from WALLETCONNECT_PROJECT_ID,
+/// WALLETCONNECT_RELAY_URL and WALLETCONNECT_APP_URL
+#[derive(Debug, Clone)]
+pub struct WalletConnectConfig {
+    pub project_id: String,
+    pub relay_url: String,
+    /// URL wallets show for the assistant when pairing
+    pub app_url: String,
+}
+
+impl WalletConnectConfig {
+    pub fn from_env() -> Result<Self> {
+        let project_id = std::env::var("WALLETCONNECT_PROJECT_ID").map_err(|_| {
+            anyhow!("Set WALLETCONNECT_PROJECT_ID (from cloud.reown.com) to pair external wallets")
+        })?;
+        Ok(Self {
+            project_id,
+            relay_url: std::env::var("WALLETCONNECT_RELAY_URL")
+                .unwrap_or_else(|_| DEFAULT_RELAY_URL.to_string()),
+            app_url: std::env::var("WALLETCONNECT_APP_URL")
+                .unwrap_or_else(|_| DEFAULT_APP_URL.to_string()),
+        })
+    }
+}
+
+/// An external wallet paired over WalletConnect. The wallet keeps its keys and signs
+/// every transaction itself, after the user approves it there.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WalletSession {
+    /// Name the user refers to the wallet by, e.g. "metamask"
+    pub name: String,
+    /// "pairing" until the wallet approves, then "connected"; "failed" when it didn't
+    pub status: String,
+    pub address: Option<String>,
+    /// CAIP-2 chains the wallet accepted, e.g. "eip155:1"
+    pub chains: Vec<String>,
+    /// Name the wallet gave itself, e.g. "MetaMask Wallet"
+    pub peer: Option<String>,
+    pub expires_at: Option<i64>,
+    pub error: Option<String>,
+    pub created_at: i64,
+    // Session topic and key, kept on file but left out of `redacted`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    topic: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sym_key: Option<String>,
+}
+
+impl WalletSession {
+    pub fn is_connected(&self) -> bool {
+        self.status == "connected"
+            && self
+                .expires_at
+                .is_none_or(|expiry| expiry > chrono::Utc::now().timestamp())
+    }
+
+    /// The session without its topic and key, for showing to clients
+    pub fn redacted(&self) -> Self {
+        Self {
+            topic: None,
+            sym_key: None,
+            ..self.clone()
+        }
+    }
+
+    /// Whether the wallet accepted `chain_id` when it paired
+    pub fn supports_chain(&self, chain_id: u64) -> bool {
+        self.chains.contains(&format!("eip155:{}", chain_id))
+    }
+
+    // The session topic and key, which only connected sessions have
+    fn channel(&self) -> Result<(String, [u8; 32])> {
+        let (Some(topic), Some(key)) = (&self.topic, &self.sym_key) else {
+            return Err(anyhow!("Wallet {} is not connected", self.name));
+        };
+        Ok((topic.clone(), decode_key(key)?))
+    }
+}
+
+/// A new pairing: the URI to open in the wallet, and the session it will settle
+#[derive(Debug, Clone, Serialize)]
+pub struct Pairing {
+    pub uri: String,
+    pub wallet: WalletSession,
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    hex::encode(Sha256::digest(bytes))
+}
+
+fn decode_key(key: &str) -> Result<[u8; 32]> {
+    hex::decode(key)?
+        .try_into()
+        .map_err(|_| anyhow!("Invalid WalletConnect key"))
+}
+
+// Messages are sealed with ChaCha20-Poly1305 in a type 0 envelope: 0x00 || iv || sealed
+fn seal(key: &[u8; 32], payload: &Value) -> Result<String> {
+    let cipher = ChaCha20Poly1305::new(key.into());
+    let iv: [u8; 12] = rand::random();
+    let sealed = cipher
+        .encrypt(&Nonce::from(iv), serde_json::to_vec(payload)?.as_slice())
+        .map_err(|_| anyhow!("Failed to encrypt WalletConnect message"))?;
+    let mut envelope = vec![0u8];
+    envelope.extend_from_slice(&iv);
+    envelope.extend_from_slice(&sealed);
+    Ok(STANDARD.encode(envelope))
+}
+
+fn open(key: &[u8; 32], message: &str) -> Result<Value> {
+    let envelope = STANDARD.decode(message)?;
+    if envelope.len() < 13 || envelope[0] != 0 {
+        return Err(anyhow!("Unsupported WalletConnect envelope"));
+    }
+    let iv: [u8; 12] = envelope[1..13].try_into()?;
+    let cipher = ChaCha20Poly1305::new(key.into());
+    let payload = cipher
+        .decrypt(&Nonce::from(iv), &envelope[13..])
+        .map_err(|_| anyhow!("Failed to decrypt WalletConnect message"))?;
+    Ok(serde_json::from_slice(&payload)?)
+}
+
+// The relay authenticates clients with a JWT signed by an ed25519 key named as a did:key
+fn relay_auth(relay_url: &str) -> String {
+    let key = SigningKey::from_bytes(&rand::random());
+    let mut multicodec = vec![0xed, 0x01];
+    multicodec.extend_from_slice(key.verifying_key().as_bytes());
+    let now = chrono::Utc::now().timestamp();
+    let header = URL_SAFE_NO_PAD.encode(json!({"alg": "EdDSA", "typ": "JWT"}).to_string());
+    let claims = URL_SAFE_NO_PAD.encode(
+        json!({
+            "iss": format!("did:key:z{}", bs58::encode(multicodec).into_string()),
+            "sub": hex::encode(rand::random::<[u8; 32]>()),
+            "aud": relay_url,
+            "iat": now,
+            "exp": now + 86400,
+        })
+        .to_string(),
+    );
+    let signing_input = format!("{}.{}", header, claims);
+    let signature = URL_SAFE_NO_PAD.encode(key.sign(signing_input.as_bytes()).to_bytes());
+    format!("{}.{}", signing_input, signature)
+}
+
+// JSON-RPC ids in the style WalletConnect clients use: milliseconds with a random suffix
+fn payload_id() -> u64 {
+    chrono::Utc::now().timestamp_millis() as u64 * 1000 + rand::random::<u64>() % 1000
+}
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+// A connection to the relay. Messages published to subscribed topics that arrive while
+// waiting for a call's answer are kept for `next_message`.
+struct Relay {
+    socket: Socket,
+    inbox: VecDeque<(String, String)>,
+}
+
+impl Relay {
+    async fn connect(config: &WalletConnectConfig) -> Result<Self> {
+        let url = reqwest::Url::parse_with_params(
+            &config.relay_url,
+            &[
+                ("auth", relay_auth(&config.relay_url)),
+                ("projectId", config.project_id.clone()),
+            ],
+        )?;
+        let (socket, _) = tokio_tungstenite::connect_async(url.as_str())
+            .await
+            .map_err(|e| anyhow!("Failed to connect to the WalletConnect relay: {}", e))?;
+        Ok(Self {
+            socket,
+            inbox: VecDeque::new(),
+        })
+    }
+
+    async fn call(&mut self, method: &str, params: Value) -> Result<Value> {
+        let id = payload_id();
+        self.send(json!({"id": id, "jsonrpc": "2.0", "method": method, "params": params}))
+            .await?;
+        loop {
+            let frame = self.receive().await?;
+            if frame["id"].as_u64() == Some(id) && frame.get("method").is_none() {
+                if let Some(error) = frame.get("error") {
+                    return Err(anyhow!("{} failed: {}", method, error["message"]));
+                }
+                return Ok(frame["result"].clone());
+            }
+            self.accept(frame).await?;
+        }
+    }
+
+    async fn subscribe(&mut self, topic: &str) -> Result<()> {
+        self.call("irn_subscribe", json!({"topic": topic})).await?;
+        Ok(())
+    }
+
+    async fn publish(
+        &mut self,
+        topic: &str,
+        key: &[u8; 32],
+        payload: &Value,
+        tag: u32,
+        ttl: Duration,
+    ) -> Result<()> {
+        self.call(
+            "irn_publish",
+            json!({
+                "topic": topic,
+                "message": seal(key, payload)?,
+                "ttl": ttl.as_secs(),
+                "tag": tag,
+                "prompt": tag == TAG_SESSION_PROPOSE || tag == TAG_SESSION_REQUEST,
+            }),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// The next message published to a subscribed topic, as (topic, sealed message)
+    async fn next_message(&mut self) -> Result<(String, String)> {
+        loop {
+            if let Some(message) = self.inbox.pop_front() {
+                return Ok(message);
+            }
+            let frame = self.receive().await?;
+            self.accept(frame).await?;
+        }
+    }
+
+    // Acknowledge and keep a subscription message; other frames are answers to calls
+    // that already gave up
+    async fn accept(&mut self, frame: Value) -> Result<()> {
+        if frame["method"] != "irn_subscription" {
+            return Ok(());
+        }
+        let data = &frame["params"]["data"];
+        if let (Some(topic), Some(message)) = (data["topic"].as_str(), data["message"].as_str()) {
+            self.inbox
+                .push_back((topic.to_string(), message.to_string()));
+        }
+        self.send(json!({"id": frame["id"], "jsonrpc": "2.0", "result": true}))
+            .await
+    }
+
+    async fn send(&mut self, frame: Value) -> Result<()> {
+        self.socket.send(Message::Text(frame.to_string())).await?;
+        Ok(())
+    }
+
+    async fn receive(&mut self) -> Result<Value> {
+        loop {
+            match self.socket.next().await {
+                Some(Ok(Message::Text(text))) => return Ok(serde_json::from_str(&text)?),
+                Some(Ok(Message::Close(_))) | None => {
+                    return Err(anyhow!("The WalletConnect relay closed the connection"));
+                }
+                Some(Ok(_)) => continue,
+                Some(Err(e)) => return Err(anyhow!("WalletConnect relay error: {}", e)),
+            }
+        }
+    }
+
+    // Wait for the first message on `topic` that `matches`, decrypting with `key`. The
+    // wallet ending the session ends the wait.
+    async fn wait_for(
+        &mut self,
+        topic: &str,
+        key: &[u8; 32],
+        matches: impl Fn(&Value) -> bool,
+    ) -> Result<Value> {
+        loop {
+            let (from_topic, message) = self.next_message().await?;
+            if from_topic != topic {
+                continue;
+            }
+            match open(key, &message) {
+                Ok(payload) if matches(&payload) => return Ok(payload),
+                Ok(payload) if payload["method"] == "wc_sessionDelete" => {
+                    return Err(anyhow!("The wallet ended the session"));
+                }
+                Ok(_) => continue,
+                Err(e) => warn!("Ignoring WalletConnect message on {}: {}", topic, e),
+            }
+        }
+    }
+}
+
+/// Start pairing a wallet as `name` on the given chains. The returned URI is opened in
+/// the wallet (pasted or shown as a QR code); the session is settled in the background
+/// once the user approves it there, and recorded in `store`.
+pub async fn pair_wallet(
+    config: &WalletConnectConfig,
+    store: Arc<WalletStore>,
+    namespace: &str,
+    name: &str,
+    chain_ids: &[u64],
+) -> Result<Pairing> {
+    let sym_key: [u8; 32] = rand::random();
+    let pairing_topic = sha256_hex(&sym_key);
+    let secret = StaticSecret::from(rand::random::<[u8; 32]>());
+    let public = PublicKey::from(&secret);
+    let expiry = chrono::Utc::now().timestamp() + PAIRING_TTL.as_secs() as i64;
+    let chains: Vec<String> = chain_ids
+        .iter()
+        .map(|chain_id| format!("eip155:{}", chain_id))
+        .collect();
+
+    let mut relay = Relay::connect(config).await?;
+    relay.subscribe(&pairing_topic).await?;
+    let proposal_id = payload_id();
+    let proposal = json!({
+        "id": proposal_id,
+        "jsonrpc": "2.0",
+        "method": "wc_sessionPropose",
+        "params": {
+            "relays": [{"protocol": "irn"}],
+            "requiredNamespaces": {},
+            "optionalNamespaces": {
+                "eip155": {
+                    "chains": chains,
+                    "methods": SESSION_METHODS,
+                    "events": SESSION_EVENTS,
+                }
+            },
+            "proposer": {
+                "publicKey": hex::encode(public.as_bytes()),
+                "metadata": {
+                    "name": "AI Blockchain Assistant",
+                    "description": "Prepares transactions for you to approve in your wallet",
+                    "url": config.app_url,
+                    "icons": [],
+                },
+            },
+            "expiryTimestamp": expiry,
+        },
+    });
+    relay
+        .publish(
+            &pairing_topic,
+            &sym_key,
+            &proposal,
+            TAG_SESSION_PROPOSE,
+            PAIRING_TTL,
+        )
+        .await?;
+
+    let wallet = WalletSession {
+        name: name.to_string(),
+        status: "pairing".to_string(),
+        address: None,
+        chains: Vec::new(),
+        peer: None,
+        expires_at: Some(expiry),
+        error: None,
+        created_at: chrono::Utc::now().timestamp(),
+        topic: None,
+        sym_key: None,
+    };
+    store.save(namespace, wallet.clone())?;
+
+    let uri = format!(
+        "wc:{}@2?relay-protocol=irn&symKey={}&expiryTimestamp={}",
+        pairing_topic,
+        hex::encode(sym_key),
+        expiry
+    );
+    let namespace = namespace.to_string();
+    let name = name.to_string();
+    tokio::spawn(async move {
+        let settled = tokio::time::timeout(
+            PAIRING_TTL,
+            settle_session(&mut relay, &pairing_topic, &sym_key, proposal_id, secret),
+        )
+        .await
+        .unwrap_or_else(|_| Err(anyhow!("The wallet didn't approve the pairing in time")));
+        let Some(mut wallet) = store.get(&namespace, &name) else {
+            return;
+        };
+        match settled {
+            Ok(session) => {
+                info!("Wallet {} paired as {:?}", name, session.address);
+                wallet = WalletSession {
+                    name: wallet.name,
+                    created_at: wallet.created_at,
+                    ..session
+                };
+            }
+            Err(e) => {
+                warn!("Pairing wallet {} failed: {}", name, e);
+                wallet.status = "failed".to_string();
+                wallet.error = Some(e.to_string());
+            }
+        }
+        if let Err(e) = store.save(&namespace, wallet) {
+            warn!("Failed to record wallet {}: {}", name, e);
+        }
+    });
+
+    Ok(Pairing { uri, wallet })
+}
+
+// Wait for the wallet to approve the proposal, derive the session key from its public
+// key, and acknowledge the session it settles
+async fn settle_session(
+    relay: &mut Relay,
+    pairing_topic: &str,
+    pairing_key: &[u8; 32],
+    proposal_id: u64,
+    secret: StaticSecret,
+) -> Result<WalletSession> {
+    let response = relay
+        .wait_for(pairing_topic, pairing_key, |payload| {
+            payload["id"].as_u64() == Some(proposal_id) && payload.get("method").is_none()
+        })
+        .await?;
+    if let Some(error) = response.get("error") {
+        return Err(anyhow!(
+            "The wallet rejected the pairing: {}",
+            error["message"]
+        ));
+    }
+    let responder: [u8; 32] = hex::decode(
+        response["result"]["responderPublicKey"]
+            .as_str()
+            .ok_or_else(|| anyhow!("The wallet sent no public key"))?,
+    )?
+    .try_into()
+    .map_err(|_| anyhow!("Invalid wallet public key"))?;
+
+    let shared = secret.diffie_hellman(&PublicKey::from(responder));
+    let mut session_key = [0u8; 32];
+    Hkdf::<Sha256>::new(None, shared.as_bytes())
+        .expand(&[], &mut session_key)
+        .map_err(|_| anyhow!("Failed to derive the session key"))?;
+    let session_topic = sha256_hex(&session_key);
+    relay.subscribe(&session_topic).await?;
+
+    let settle = relay
+        .wait_for(&session_topic, &session_key, |payload| {
+            payload["method"] == "wc_sessionSettle"
+        })
+        .await?;
+    relay
+        .publish(
+            &session_topic,
+            &session_key,
+            &json!({"id": settle["id"], "jsonrpc": "2.0", "result": true}),
+            TAG_SESSION_SETTLE_RESPONSE,
+            PAIRING_TTL,
+        )
+        .await?;
+
+    let params = &settle["params"];
+    // Accounts are CAIP-10, e.g. "eip155:1:0xab…"; the wallet uses one address on all
+    let accounts: Vec<&str> = params["namespaces"]["eip155"]["accounts"]
+        .as_array()
+        .map(|accounts| {
+            accounts
+                .iter()
+                .filter_map(|account| account.as_str())
+                .collect()
+        })
+        .unwrap_or_default();
+    let address = accounts
+        .iter()
+        .find_map(|account| account.rsplit(':').next())
+        .ok_or_else(|| anyhow!("The wallet shared no Ethereum account"))?
+        .parse()
+        .map_err(|_| anyhow!("The wallet shared an invalid account"))?;
+    let mut chains: Vec<String> = accounts
+        .iter()
+        .filter_map(|account| account.rsplit_once(':').map(|(chain, _)| chain.to_string()))
+        .collect();
+    chains.dedup();
+
+    Ok(WalletSession {
+        name: String::new(),
+        status: "connected".to_string(),
+        address: Some(to_checksum(&address, None)),
+        chains,
+        peer: params["controller"]["metadata"]["name"]
+            .as_str()
+            .map(|name| name.to_string()),
+        expires_at: params["expiry"].as_i64(),
+        error: None,
+        created_at: 0,
+        topic: Some(session_topic),
+        sym_key: Some(hex::encode(session_key)),
+    })
+}
+
+// eth_sendTransaction params for a transaction from `prepare_unsigned`; the wallet
+// picks the nonce itself
+fn transaction_params(tx: &TypedTransaction) -> Value {
+    let quantity = |value: &U256| format!("{:#x}", value);
+    let mut params = json!({
+        "from": tx.from().map(|from| to_checksum(from, None)),
+        "data": tx.data().map(|data| data.to_string()).unwrap_or_else(|| "0x".to_string()),
+        "value": quantity(tx.value().unwrap_or(&U256::zero())),
+    });
+    if let Some(NameOrAddress::Address(to)) = tx.to() {
+        params["to"] = json!(to_checksum(to, None));
+    }
+    if let Some(gas) = tx.gas() {
+        params["gas"] = json!(quantity(gas));
+    }
+    match tx {
+        TypedTransaction::Eip1559(tx) => {
+            if let Some(max_fee) = &tx.max_fee_per_gas {
+                params["maxFeePerGas"] = json!(quantity(max_fee));
+            }
+            if let Some(tip) = &tx.max_priority_fee_per_gas {
+                params["maxPriorityFeePerGas"] = json!(quantity(tip));
+            }
+        }
+        _ => {
+            if let Some(gas_price) = tx.gas_price() {
+                params["gasPrice"] = json!(quantity(&gas_price));
+            }
+        }
+    }
+    params
+}
+
+/// Ask a paired wallet to sign and send `tx` on `chain_id`, waiting for the user to
+/// approve it there. Returns the transaction hash the wallet reports.
+pub async fn send_transaction(
+    config: &WalletConnectConfig,
+    wallet: &WalletSession,
+    chain_id: u64,
+    tx: &TypedTransaction,
+) -> Result<String> {
+    if !wallet.is_connected() {
+        return Err(anyhow!(
+            "Wallet {} is not connected; pair it again with pair_wallet",
+            wallet.name
+        ));
+    }
+    if !wallet.supports_chain(chain_id) {
+        return Err(anyhow!(
+            "Wallet {} wasn't paired for chain {}; it accepted {}",
+            wallet.name,
+            chain_id,
+            wallet.chains.join(", ")
+        ));
+    }
+    let (topic, key) = wallet.channel()?;
+
+    let mut relay = Relay::connect(config).await?;
+    relay.subscribe(&topic).await?;
+    let id = payload_id();
+    let request = json!({
+        "id": id,
+        "jsonrpc": "2.0",
+        "method": "wc_sessionRequest",
+        "params": {
+            "request": {
+                "method": "eth_sendTransaction",
+                "params": [transaction_params(tx)],
+            },
+            "chainId": format!("eip155:{}", chain_id),
+        },
+    });
+    relay
+        .publish(&topic, &key, &request, TAG_SESSION_REQUEST, REQUEST_TTL)
+        .await?;
+
+    let response = tokio::time::timeout(
+        REQUEST_TTL,
+        relay.wait_for(&topic, &key, |payload| {
+            payload["id"].as_u64() == Some(id) && payload.get("method").is_none()
+        }),
+    )
+    .await
+    .map_err(|_| {
+        anyhow!(
+            "Wallet {} didn't answer within {}s",
+            wallet.name,
+            REQUEST_TTL.as_secs()
+        )
+    })??;
+    if let Some(error) = response.get("error") {
+        return Err(anyhow!(
+            "The wallet rejected the transaction: {}",
+            error["message"]
+        ));
+    }
+    response["result"]
+        .as_str()
+        .map(|hash| hash.to_string())
+        .ok_or_else(|| anyhow!("The wallet returned no transaction hash"))
+}
+
+/// Tell the wallet the session is over
+pub async fn disconnect(config: &WalletConnectConfig, wallet: &WalletSession) -> Result<()> {
+    let (topic, key) = wallet.channel()?;
+    let mut relay = Relay::connect(config).await?;
+    relay
+        .publish(
+            &topic,
+            &key,
+            &json!({
+                "id": payload_id(),
+                "jsonrpc": "2.0",
+                "method": "wc_sessionDelete",
+                "params": {"code": 6000, "message": "User disconnected."},
+            }),
+            TAG_SESSION_DELETE,
+            Duration::from_secs(86400),
+        )
+        .await
+}
+
+// Paired wallets per namespace, persisted as a JSON object. Pairings a restart
+// interrupted are dropped on load.
+pub struct WalletStore {
+    path: Option<PathBuf>,
+    wallets: RwLock<HashMap<String, BTreeMap<String, WalletSession>>>,
+}
+
+impl WalletStore {
+    pub fn in_memory() -> Self {
+        Self {
+            path: None,
+            wallets: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut wallets: HashMap<String, BTreeMap<String, WalletSession>> = if path.exists() {
+            serde_json::from_str(&fs::read_to_string(&path)?)?
+        } else {
+            HashMap::new()
+        };
+        for wallets in wallets.values_mut() {
+            wallets.retain(|_, wallet| wallet.status != "pairing");
+        }
+
+        Ok(Self {
+            path: Some(path),
+            wallets: RwLock::new(wallets),
+        })
+    }
+
+    pub fn save(&self, namespace: &str, wallet: WalletSession) -> Result<()> {
+        let mut wallets = self
+            .wallets
+            .write()
+            .map_err(|_| anyhow!("Wallet store lock poisoned"))?;
+        wallets
+            .entry(namespace.to_string())
+            .or_default()
+            .insert(wallet.name.clone(), wallet);
+        self.persist(&wallets)
+    }
+
+    pub fn get(&self, namespace: &str, name: &str) -> Option<WalletSession> {
+        self.wallets
+            .read()
+            .ok()
+            .and_then(|wallets| wallets.get(namespace)?.get(name).cloned())
+    }
+
+    pub fn list(&self, namespace: &str) -> Vec<WalletSession> {
+        self.wallets
+            .read()
+            .ok()
+            .and_then(|wallets| {
+                wallets
+                    .get(namespace)
+                    .map(|wallets| wallets.values().cloned().collect())
+            })
+            .unwrap_or_default()
+    }
+
+    /// Forget a wallet; the removed session, if there was one by that name
+    pub fn remove(&self, namespace: &str, name: &str) -> Result<Option<WalletSession>> {
+        let mut wallets = self
+            .wallets
+            .write()
+            .map_err(|_| anyhow!("Wallet store lock poisoned"))?;
+        let removed = wallets
+            .get_mut(namespace)
+            .and_then(|wallets| wallets.remove(name));
+        if removed.is_some() {
+            self.persist(&wallets)?;
+        }
+        Ok(removed)
+    }
+
+    fn persist(&self, wallets: &HashMap<String, BTreeMap<String, WalletSession>>) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(wallets)?)?;
+        Ok(())
+    }
+}
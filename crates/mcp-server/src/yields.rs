@@ -0,0 +1,230 @@
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::external_apis::ExternalAPIService;
+
+/// Opportunities returned when the caller gives no limit
+pub const DEFAULT_YIELD_LIMIT: usize = 10;
+const MAX_YIELD_LIMIT: usize = 50;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct YieldConfig {
+    /// DefiLlama project slugs considered audited; others are only shown on request
+    pub audited_protocols: Vec<String>,
+    /// Pools with less TVL than this are skipped unless the caller lowers the floor
+    pub min_tvl_usd: f64,
+}
+
+impl Default for YieldConfig {
+    fn default() -> Self {
+        Self {
+            audited_protocols: [
+                "aave-v3",
+                "compound-v3",
+                "lido",
+                "rocket-pool",
+                "spark",
+                "morpho-blue",
+                "curve-dex",
+                "convex-finance",
+                "uniswap-v3",
+                "yearn-finance",
+                "frax-ether",
+                "sky-lending",
+            ]
+            .iter()
+            .map(|slug| slug.to_string())
+            .collect(),
+            min_tvl_usd: 1_000_000.0,
+        }
+    }
+}
+
+impl YieldConfig {
+    /// Read the protocol allow-list and TVL floor from `YIELD_*` environment variables
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+
+        Self {
+            audited_protocols: std::env::var("YIELD_AUDITED_PROTOCOLS")
+                .map(|v| {
+                    v.split(',')
+                        .map(|slug| slug.trim().to_lowercase())
+                        .filter(|slug| !slug.is_empty())
+                        .collect()
+                })
+                .unwrap_or(defaults.audited_protocols),
+            min_tvl_usd: std::env::var("YIELD_MIN_TVL_USD")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(defaults.min_tvl_usd),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct YieldQuery {
+    /// DefiLlama chain name, e.g. "Ethereum"
+    pub chain: String,
+    /// Token symbol the pool must hold
+    pub asset: Option<String>,
+    pub min_tvl_usd: Option<f64>,
+    pub include_unaudited: bool,
+    pub stablecoins_only: bool,
+    /// Skip pools of several assets, which carry impermanent loss
+    pub single_asset_only: bool,
+    pub limit: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct YieldOpportunity {
+    pub rank: usize,
+    pub project: String,
+    pub symbol: String,
+    pub chain: String,
+    pub tvl_usd: f64,
+    /// Total APY in percent: base plus rewards
+    pub apy: f64,
+    /// Paid by the protocol itself (interest, trading fees)
+    pub apy_base: Option<f64>,
+    /// Paid in incentive tokens, which tend to dry up
+    pub apy_reward: Option<f64>,
+    pub apy_mean_30d: Option<f64>,
+    pub reward_tokens: Vec<String>,
+    pub stablecoin: bool,
+    pub impermanent_loss_risk: bool,
+    pub audited: bool,
+    pub pool_meta: Option<String>,
+    pub url: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct YieldResult {
+    pub chain: String,
+    pub asset: Option<String>,
+    pub min_tvl_usd: f64,
+    /// Pools that passed the filters, before `limit` was applied
+    pub matched: usize,
+    pub opportunities: Vec<YieldOpportunity>,
+    pub note: String,
+}
+
+/// DefiLlama's name for a chain id, None for chains it doesn't track yields on
+pub fn llama_chain(chain_id: u64) -> Option<&'static str> {
+    match chain_id {
+        // Anvil forks mainnet
+        1 | 31337 => Some("Ethereum"),
+        10 => Some("Optimism"),
+        137 => Some("Polygon"),
+        8453 => Some("Base"),
+        42161 => Some("Arbitrum"),
+        _ => None,
+    }
+}
+
+/// Yield pools from DefiLlama that match the query, ranked by total APY. Pools whose
+/// APY is mostly rewards are ranked on their 30-day mean instead, so a short-lived
+/// incentive spike doesn't top the list.
+pub async fn find_yield(
+    external_apis: &ExternalAPIService,
+    config: &YieldConfig,
+    query: &YieldQuery,
+) -> Result<YieldResult> {
+    if query.limit == 0 || query.limit > MAX_YIELD_LIMIT {
+        return Err(anyhow!("limit must be between 1 and {}", MAX_YIELD_LIMIT));
+    }
+    let min_tvl_usd = query.min_tvl_usd.unwrap_or(config.min_tvl_usd);
+    let asset = query.asset.as_ref().map(|asset| asset.to_uppercase());
+
+    let pools = external_apis.get_yield_pools().await?;
+    let mut opportunities: Vec<(f64, YieldOpportunity)> = pools
+        .iter()
+        .filter(|pool| {
+            pool["chain"]
+                .as_str()
+                .is_some_and(|chain| chain.eq_ignore_ascii_case(&query.chain))
+        })
+        .filter(|pool| pool["tvlUsd"].as_f64().unwrap_or(0.0) >= min_tvl_usd)
+        .filter_map(|pool| opportunity(pool, config))
+        .filter(|opportunity| query.include_unaudited || opportunity.audited)
+        .filter(|opportunity| !query.stablecoins_only || opportunity.stablecoin)
+        .filter(|opportunity| !query.single_asset_only || !opportunity.impermanent_loss_risk)
+        .filter(|opportunity| {
+            asset.as_ref().is_none_or(|asset| {
+                opportunity
+                    .symbol
+                    .to_uppercase()
+                    .split(['-', '/', ' '])
+                    .any(|part| part == asset)
+            })
+        })
+        .map(|opportunity| (ranking_apy(&opportunity), opportunity))
+        .collect();
+    opportunities.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+    let matched = opportunities.len();
+    let opportunities = opportunities
+        .into_iter()
+        .take(query.limit)
+        .enumerate()
+        .map(|(i, (_, mut opportunity))| {
+            opportunity.rank = i + 1;
+            opportunity
+        })
+        .collect();
+
+    Ok(YieldResult {
+        chain: query.chain.clone(),
+        asset: query.asset.clone(),
+        min_tvl_usd,
+        matched,
+        opportunities,
+        note: "Informational only: APYs change constantly and reward APY depends on incentive token prices. Nothing has been deposited".to_string(),
+    })
+}
+
+fn opportunity(pool: &Value, config: &YieldConfig) -> Option<YieldOpportunity> {
+    let project = pool["project"].as_str()?.to_string();
+    let id = pool["pool"].as_str()?;
+    let audited = config
+        .audited_protocols
+        .iter()
+        .any(|slug| slug.eq_ignore_ascii_case(&project));
+
+    Some(YieldOpportunity {
+        rank: 0,
+        symbol: pool["symbol"].as_str().unwrap_or_default().to_string(),
+        chain: pool["chain"].as_str().unwrap_or_default().to_string(),
+        tvl_usd: pool["tvlUsd"].as_f64()?,
+        apy: pool["apy"].as_f64()?,
+        apy_base: pool["apyBase"].as_f64(),
+        apy_reward: pool["apyReward"].as_f64(),
+        apy_mean_30d: pool["apyMean30d"].as_f64(),
+        reward_tokens: pool["rewardTokens"]
+            .as_array()
+            .map(|tokens| {
+                tokens
+                    .iter()
+                    .filter_map(|token| token.as_str().map(str::to_string))
+                    .collect()
+            })
+            .unwrap_or_default(),
+        stablecoin: pool["stablecoin"].as_bool().unwrap_or(false),
+        impermanent_loss_risk: pool["ilRisk"].as_str() == Some("yes"),
+        audited,
+        pool_meta: pool["poolMeta"].as_str().map(str::to_string),
+        url: format!("https://defillama.com/yield/pool/{}", id),
+        project,
+    })
+}
+
+// Total APY, or the 30-day mean when rewards make up most of it
+fn ranking_apy(opportunity: &YieldOpportunity) -> f64 {
+    let reward = opportunity.apy_reward.unwrap_or(0.0);
+    match opportunity.apy_mean_30d {
+        Some(mean) if reward > opportunity.apy / 2.0 => mean.min(opportunity.apy),
+        _ => opportunity.apy,
+    }
+}
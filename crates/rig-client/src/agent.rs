@@ -4,6 +4,7 @@ use anthropic_sdk::{
 };
 use anyhow::Result;
 use serde_json::{Value, from_value, json};
+use shared::format::DisplayPreferences;
 use std::sync::Arc;
 use tracing::info;
 
@@ -26,6 +27,53 @@ impl BlockchainAgent {
           You also have access to documentation about blockchain protocols and smart contracts through the RAG system. \
           When users ask you to perform blockchain operations, use the appropriate tools to fulfill their requests. \
           When users ask about how blockchain protocols or smart contracts work, use the search_docs tool to find relevant information. \
+          When a result includes a display_name for an address, refer to the address by that name. \
+          When users ask what you can do, call describe_capabilities and answer from its result rather than from memory. \
+          Before swapping tokens, call estimate_swap, show the user the quote and ask them to confirm before calling swap_tokens. \
+          If the quote has a price_impact_warning, tell the user how much the price would move and suggest a smaller amount; set accept_price_impact only if they still want the swap. \
+          When the quote has steps, list them so the user sees everything the swap transaction will do. \
+          When estimate_swap returns an aggregator_quote, say which route pays more (best_dex) and its sources before swapping. \
+          Set simulate on send_eth, send_erc20 and swap_tokens for unfamiliar tokens or large amounts; if the simulation fails nothing is sent, so relay its explanation to the user. \
+          After a send or swap, use the balance_changes in its result when telling the user their new balances. \
+          When the user defines a variable (\"let AMOUNT = 0.25\"), call set_variable; when they use it later, pass the variable name unchanged as the parameter value and the server resolves it. \
+          Before running a template with confirm, call run_template without confirm, show the user the steps and wait for their approval. \
+          When the user asks about scheduled operations, check list_jobs and get_notifications, and explain why any job is deferred. \
+          At the start of a conversation call get_inbox and, if anything happened while the user was away, tell them before answering. \
+          Inbox items of kind depeg mean a stablecoin lost or regained its peg; mention them prominently, and use get_stablecoin_status when the user asks whether stablecoins are safe. \
+          When the user asks whether gas is cheap or when to transact, call get_gas_price and explain its assessment; for the cost of a specific transaction use estimate_gas. \
+          For questions about where to earn yield, call find_yield and present the top results with their base and reward APY, noting that reward APY is less durable; never move funds based on it without the user asking. \
+          When the user asks whether a protocol is safe, call get_protocol_health, lead with its warnings and say which sources were unavailable. \
+          For on-chain governance, use list_proposals and get_proposal to show what's up for a vote and get_voting_power to check an account can vote; before cast_vote, confirm the proposal, the vote and the reason with the user, since votes are final. \
+          Many DAOs vote off-chain on Snapshot: use list_snapshot_proposals and get_snapshot_proposal there, check get_snapshot_voting_power, and confirm the choice and reason before cast_snapshot_vote. \
+          When the user asks about airdrops, call check_claims and only offer claim_airdrop for allocations marked claimable. \
+          For vesting contracts, call get_vesting, give unlock timestamps as dates, and only offer release_vested when something is claimable. \
+          To stream payments, call create_stream with the duration in seconds (Sablier unless the user asks for Superfluid, which needs a super token like USDCx and keeps flowing until cancel_stream); check on streams with get_streams. \
+          On a local dev chain, convert durations like \"3 months\" to seconds for increase_time and report the new block time; use mine_blocks for block-based delays, and fund_account when a test account needs tokens rather than swapping for them. \
+          For balances at a past date like \"yesterday\", estimate the block from get_chain_info's latest block and block time (about 12 seconds per block on mainnet) and pass it as get_balance's block. \
+          When the user needs a new test token, use deploy_token; afterwards the token can be used by its symbol. \
+          Use compute_address to tell the user where a contract will be deployed; pass the same salt to deploy_contract to land at a CREATE2 address. \
+          When the user acts through a smart account (smart wallet), use send_user_operation rather than write_contract or send_eth, and estimate_user_operation to quote its cost; get_smart_account gives its address even before it is deployed. \
+          Transactions come back with status \"submitted\" as soon as they are sent: tell the user, give the hash, and keep the conversation going; check get_tx_status for the confirmation and the final result (such as a deployed address) before relying on it, and list_pending_transactions to report what is still in flight. If a transaction comes back with status \"pending\", tell the user it was sent but not yet confirmed and check it later with get_tx_status instead of sending it again. \
+          When the user grants a session key with grant_session_key, pass its id as session_key to send_user_operation for calls inside its scope and send them without asking; if one is rejected as outside the scope, confirm it with the user and send it without session_key. \
+          When the user wants to use their own wallet, pair it with pair_wallet and give them the URI to open in it; for transactions from that wallet use send_with_wallet, which waits for them to approve it in the wallet. \
+          When the user asks to keep an account funded, confirm the accounts, minimum balance and daily cap before calling add_top_up_rule; it then tops up on its own. \
+          When the user wants amounts shown differently (decimal places, thousands separators, no USD, gas in wei or ether), call set_display_preferences; later results arrive formatted that way, so quote amounts as given. \
+          For unit conversions (wei, gwei, ether, raw token amounts) call convert_units rather than calculating yourself. \
+          To read any view function on a contract (e.g. totalSupply, getReserves, allowance) use call_contract. \
+          For an unfamiliar contract, call get_contract_source to see its verified functions before using call_contract or write_contract. \
+          When a price must be hard to manipulate (e.g. checking a quote before a large swap), use get_twap and mention a large spot deviation. \
+          For the price or liquidity of a pair on Uniswap, use get_pool_info and say which pool the price comes from. \
+          To clean up small balances, call consolidate_dust without confirm, show the swaps, totals and skipped tokens, and only call it with confirm after the user agrees. \
+          Before add_liquidity, check the pair with get_pool_info and confirm both amounts with the user; report the LP balance and pool share from the result, and use get_lp_performance when they ask how a position is doing. \
+          When the user holds a token that has been migrated to a new contract (see list_token_migrations), call migrate_token without confirm, show the steps, and only call it with confirm after they agree. \
+          For Aave lending, check get_lending_position before aave_withdraw or aave_borrow, tell the user how their health factor will change (positions are liquidated below 1.0), and confirm before any aave_supply, aave_withdraw, aave_borrow or aave_repay. \
+          To explain a transaction the user pastes, look it up with get_transaction; for bare input data use decode_calldata. \
+          When the user pastes an ethereum: payment URI, call parse_payment_uri, confirm the payment with them, then call the returned method with its params. \
+          To prove ownership of an account or log in to a site, use sign_message after showing the user the exact message; check signatures others give with verify_signature. \
+          For state-changing calls no other tool covers use write_contract, after confirming the details with the user. \
+          When one request pays several recipients, use a single send_batch call rather than separate sends. \
+          To grant or remove a spending allowance use approve_token or revoke_approval; check existing ones with get_allowance and suggest revoking unlimited approvals the user no longer needs. \
+          If the user wants to submit a transaction through their own infrastructure, pass export: true to send it signed but unbroadcast; if their key is kept offline, use prepare_unsigned_transaction and then attach_signature with the signature they produce. \
           Always explain what you're doing in simple terms.";
 
         let conversation_history = vec![MessageParam {
@@ -40,6 +88,38 @@ impl BlockchainAgent {
         })
     }
 
+    /// Account to act on when the user doesn't name one
+    pub fn with_default_account(mut self, account: &str) -> Self {
+        if let Some(MessageParam {
+            content: MessageContent::Text(system_message),
+            ..
+        }) = self.conversation_history.first_mut()
+        {
+            system_message.push_str(&format!(
+                " When the user doesn't say which account to use, use the '{}' account.",
+                account
+            ));
+        }
+        self
+    }
+
+    /// Server session this conversation's variables and preferences live in
+    pub fn session_id(&self) -> &str {
+        self.mcp_client.session_id()
+    }
+
+    /// How the server formats amounts for this conversation
+    pub async fn display_preferences(&self) -> Result<DisplayPreferences> {
+        let result = self.mcp_client.get_display_preferences(json!({})).await?;
+        Ok(from_value(result["preferences"].clone())?)
+    }
+
+    /// Change some display preferences, returning the full set
+    pub async fn set_display_preferences(&self, changes: Value) -> Result<DisplayPreferences> {
+        let result = self.mcp_client.set_display_preferences(changes).await?;
+        Ok(from_value(result["preferences"].clone())?)
+    }
+
     pub async fn process_message(&mut self, user_message: &str) -> Result<String> {
         // Add user message to history
         self.conversation_history.push(MessageParam {
@@ -50,147 +130,2719 @@ impl BlockchainAgent {
         // Define available tools
         let tools = vec![
             Tool {
-                name: "get_balance".to_string(),
-                description: "Get the balance of an Ethereum address or named account".to_string(),
+                name: "get_balance".to_string(),
+                description: "Get the balance of an Ethereum address or named account".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "address": {
+                            "type": "string",
+                            "description": "The Ethereum address, named account (alice, bob) or ENS name (vitalik.eth) to check balance for"
+                        },
+                        "token": {
+                            "type": "string",
+                            "description": "Optional token address to check balance for. If not provided, ETH balance is returned."
+                        },
+                        "block": {
+                            "type": ["integer", "string"],
+                            "description": "Optional past block number (e.g. 19000000 or \"19,000,000\") to read the balance at. Omit for the current balance."
+                        }
+                    },
+                    "required": ["address"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "send_eth".to_string(),
+                description: "Send ETH from one account to another".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "from": {
+                            "type": "string",
+                            "description": "The sender's address or named account (alice, bob)"
+                        },
+                        "to": {
+                            "type": "string",
+                            "description": "The recipient's address, named account (alice, bob) or ENS name (vitalik.eth)"
+                        },
+                        "amount": {
+                            "type": "string",
+                            "description": "The amount of ETH to send (e.g., '1.0')"
+                        },
+                        "override_screening": {
+                            "type": "boolean",
+                            "description": "Send even if the recipient is flagged as a scam or sanctioned address. Only set this when the user explicitly insists."
+                        },
+                        "max_fee_per_gas": {
+                            "type": "string",
+                            "description": "Optional EIP-1559 max fee per gas in gwei (estimated automatically if omitted)"
+                        },
+                        "max_priority_fee_per_gas": {
+                            "type": "string",
+                            "description": "Optional EIP-1559 priority fee (tip) per gas in gwei (estimated automatically if omitted)"
+                        },
+                        "export": {
+                            "type": "boolean",
+                            "description": "Sign the transaction and return the raw hex instead of broadcasting it, for the user to submit elsewhere"
+                        },
+                        "simulate": {
+                            "type": "boolean",
+                            "description": "Simulate the transfer against the latest block first and send nothing if it would revert, returning the reason instead. Use it when the user is unsure the transfer will go through"
+                        }
+                    },
+                    "required": ["from", "to", "amount"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "check_contract".to_string(),
+                description: "Check if a contract is deployed at a specific address, and whether it is an upgradeable proxy (reporting its implementation and admin)".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "address": {
+                            "type": "string",
+                            "description": "The contract address or name (e.g., 'uniswap_v2_router')"
+                        }
+                    },
+                    "required": ["address"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "search_web".to_string(),
+                description: "Search the web for information".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "query": {
+                            "type": "string",
+                            "description": "The search query"
+                        }
+                    },
+                    "required": ["query"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "get_token_price".to_string(),
+                description: "Get the current price of a token".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "token": {
+                            "type": "string",
+                            "description": "The token address or symbol"
+                        }
+                    },
+                    "required": ["token"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "swap_tokens".to_string(),
+                description: "Swap tokens using Uniswap".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "from_token": {
+                            "type": "string",
+                            "description": "The address or symbol of the token to swap from"
+                        },
+                        "to_token": {
+                            "type": "string",
+                            "description": "The address or symbol of the token to swap to"
+                        },
+                        "amount": {
+                            "type": "string",
+                            "description": "The amount to swap"
+                        },
+                        "dex": {
+                            "type": "string",
+                            "enum": ["uniswap_v2", "uniswap_v3", "0x", "1inch", "aggregator"],
+                            "description": "Exchange to swap on; 0x and 1inch are DEX aggregators and \"aggregator\" takes the better of them. Omit to use whichever quotes the better price"
+                        },
+                        "recipient": {
+                            "type": "string",
+                            "description": "The recipient address or named account"
+                        },
+                        "acknowledge_risk": {
+                            "type": "boolean",
+                            "description": "Proceed even though the token safety check reported risks. Only set this after showing the risk summary to the user and getting their confirmation."
+                        },
+                        "accept_price_impact": {
+                            "type": "boolean",
+                            "description": "Proceed even though the price impact is above the limit. Only set this after showing the user the impact and expected output and getting their confirmation."
+                        },
+                        "simulate": {
+                            "type": "boolean",
+                            "description": "Simulate the swap against the latest block first and send nothing if it would revert, returning the reason instead. Use it when the user is unsure the swap will go through"
+                        }
+                    },
+                    "required": ["from_token", "to_token", "amount", "recipient"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "search_docs".to_string(),
+                description: "Search the documentation for information about blockchain protocols and smart contracts. Each result is the best-matching passage of a document; fetch the whole document with get_document".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "query": {
+                            "type": "string",
+                            "description": "The search query"
+                        },
+                        "limit": {
+                            "type": "integer",
+                            "description": "The maximum number of results to return (default: 5)"
+                        },
+                        "source": {
+                            "type": "string",
+                            "description": "Optional source to filter results (e.g., 'uniswap-v2', 'contracts')"
+                        }
+                    },
+                    "required": ["query"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "get_document".to_string(),
+                description: "Get a specific document by ID".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "id": {
+                            "type": "string",
+                            "description": "The document ID"
+                        }
+                    },
+                    "required": ["id"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "check_token_safety".to_string(),
+                description: "Check a token for scam indicators (unverified source, honeypot behaviour, owner controls, transfer tax) before swapping into it".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "token": {
+                            "type": "string",
+                            "description": "The token address or symbol"
+                        }
+                    },
+                    "required": ["token"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "preview_approval".to_string(),
+                description: "Preview how much of a token would actually be approved for a swap after the approval-cap policy is applied. Show any adjustment to the user when confirming.".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "token": {
+                            "type": "string",
+                            "description": "The token address or symbol being approved"
+                        },
+                        "swap_amount": {
+                            "type": "string",
+                            "description": "The amount of the token being swapped"
+                        },
+                        "amount": {
+                            "type": "string",
+                            "description": "The approval amount requested, or \"unlimited\" (default)"
+                        }
+                    },
+                    "required": ["token", "swap_amount"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "summarize_activity".to_string(),
+                description: "Summarize an account's activity over a period (sends, receives, swaps, gas spent and net change per asset). Use it to write a weekly recap or answer questions about recent activity.".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "account": {
+                            "type": "string",
+                            "description": "The account name or address"
+                        },
+                        "range": {
+                            "type": "string",
+                            "description": "Lookback period such as \"24h\", \"7d\" (default) or \"4w\""
+                        }
+                    },
+                    "required": ["account"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "list_networks".to_string(),
+                description: "List the blockchain networks the assistant can use and which one is active".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {}
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "switch_network".to_string(),
+                description: "Switch to a different blockchain network (e.g. mainnet, sepolia, arbitrum, base, anvil). Subsequent balance, send and swap requests use that network.".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "network": {
+                            "type": "string",
+                            "description": "The network name, as returned by list_networks"
+                        }
+                    },
+                    "required": ["network"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "send_erc20".to_string(),
+                description: "Send an ERC20 token from one account to another".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "from": {
+                            "type": "string",
+                            "description": "The sender's address or named account (alice, bob)"
+                        },
+                        "to": {
+                            "type": "string",
+                            "description": "The recipient's address, named account (alice, bob) or ENS name (vitalik.eth)"
+                        },
+                        "token": {
+                            "type": "string",
+                            "description": "The token symbol or address (e.g., USDC)"
+                        },
+                        "amount": {
+                            "type": "string",
+                            "description": "The amount of the token to send (e.g., '100')"
+                        },
+                        "override_screening": {
+                            "type": "boolean",
+                            "description": "Send even if the recipient is flagged as a scam or sanctioned address. Only set this when the user explicitly insists."
+                        },
+                        "max_fee_per_gas": {
+                            "type": "string",
+                            "description": "Optional EIP-1559 max fee per gas in gwei (estimated automatically if omitted)"
+                        },
+                        "max_priority_fee_per_gas": {
+                            "type": "string",
+                            "description": "Optional EIP-1559 priority fee (tip) per gas in gwei (estimated automatically if omitted)"
+                        },
+                        "export": {
+                            "type": "boolean",
+                            "description": "Sign the transaction and return the raw hex instead of broadcasting it, for the user to submit elsewhere"
+                        },
+                        "simulate": {
+                            "type": "boolean",
+                            "description": "Simulate the transfer against the latest block first and send nothing if it would revert, returning the reason instead. Use it when the user is unsure the transfer will go through"
+                        }
+                    },
+                    "required": ["from", "to", "token", "amount"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "get_pnl".to_string(),
+                description: "Get realized and unrealized profit and loss (USD) for token positions opened through the assistant's swaps, using average cost basis and current prices".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "account": {
+                            "type": "string",
+                            "description": "The account name or address"
+                        },
+                        "token": {
+                            "type": "string",
+                            "description": "Optional token symbol to limit the report to one position (e.g., UNI)"
+                        }
+                    },
+                    "required": ["account"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "estimate_gas".to_string(),
+                description: "Estimate the gas and fee cost (in gwei, ETH and USD) of a transaction before sending it. Use this to tell the user the expected cost before committing a send or swap.".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "type": {
+                            "type": "string",
+                            "enum": ["send_eth", "send_erc20", "swap"],
+                            "description": "The kind of transaction to estimate"
+                        },
+                        "from": {
+                            "type": "string",
+                            "description": "The sender's address or named account (alice, bob)"
+                        },
+                        "to": {
+                            "type": "string",
+                            "description": "The recipient (for send_eth and send_erc20)"
+                        },
+                        "token": {
+                            "type": "string",
+                            "description": "The token symbol or address (for send_erc20)"
+                        },
+                        "from_token": {
+                            "type": "string",
+                            "description": "The token to swap from (for swap)"
+                        },
+                        "to_token": {
+                            "type": "string",
+                            "description": "The token to swap to (for swap)"
+                        },
+                        "amount": {
+                            "type": "string",
+                            "description": "The amount to send or swap"
+                        }
+                    },
+                    "required": ["type", "from", "amount"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "get_gas_report".to_string(),
+                description: "Report how much gas the assistant's transactions used and what they cost in fees (ETH and USD at execution time), broken down by account, operation and day".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "account": {
+                            "type": "string",
+                            "description": "Optional account name to limit the report to"
+                        },
+                        "range": {
+                            "type": "string",
+                            "description": "Lookback period such as \"7d\" or \"30d\" (default)"
+                        }
+                    }
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "get_balance_history".to_string(),
+                description: "Get historical balance snapshots (ETH and registry tokens) for an account as a time series, for charts or questions like \"how has my balance changed this month?\"".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "account": {
+                            "type": "string",
+                            "description": "The account name or address"
+                        },
+                        "range": {
+                            "type": "string",
+                            "description": "Lookback period such as \"7d\" or \"30d\" (default)"
+                        },
+                        "token": {
+                            "type": "string",
+                            "description": "Optional token symbol to limit the series to (e.g., ETH)"
+                        }
+                    },
+                    "required": ["account"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "get_nft_balance".to_string(),
+                description: "Get how many NFTs an account owns in an ERC-721 collection, including the token IDs when the collection supports enumeration".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "owner": {
+                            "type": "string",
+                            "description": "The owner's address, named account (alice, bob) or ENS name (vitalik.eth)"
+                        },
+                        "collection": {
+                            "type": "string",
+                            "description": "The ERC-721 collection contract address"
+                        }
+                    },
+                    "required": ["owner", "collection"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "owner_of".to_string(),
+                description: "Get the current owner of a specific NFT".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "collection": {
+                            "type": "string",
+                            "description": "The ERC-721 collection contract address"
+                        },
+                        "token_id": {
+                            "type": "string",
+                            "description": "The token ID"
+                        }
+                    },
+                    "required": ["collection", "token_id"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "transfer_nft".to_string(),
+                description: "Transfer an NFT (ERC-721 token) from one account to another".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "from": {
+                            "type": "string",
+                            "description": "The sender's named account (alice, bob)"
+                        },
+                        "to": {
+                            "type": "string",
+                            "description": "The recipient's address, named account (alice, bob) or ENS name (vitalik.eth)"
+                        },
+                        "collection": {
+                            "type": "string",
+                            "description": "The ERC-721 collection contract address"
+                        },
+                        "token_id": {
+                            "type": "string",
+                            "description": "The token ID to transfer"
+                        },
+                        "override_screening": {
+                            "type": "boolean",
+                            "description": "Send even if the recipient is flagged as a scam or sanctioned address. Only set this when the user explicitly insists."
+                        }
+                    },
+                    "required": ["from", "to", "collection", "token_id"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "get_historical_balance".to_string(),
+                description: "Get an account's ETH or token balance at a past block. The result says whether it came from an archive node or an external API.".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "account": {
+                            "type": "string",
+                            "description": "The account name or address"
+                        },
+                        "token": {
+                            "type": "string",
+                            "description": "Optional token symbol or address (ETH if omitted)"
+                        },
+                        "block": {
+                            "type": "integer",
+                            "description": "The block number"
+                        }
+                    },
+                    "required": ["account", "block"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "get_historical_price".to_string(),
+                description: "Get a token's USD price at a past block. The result says whether it was read from chain state or an external API.".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "token": {
+                            "type": "string",
+                            "description": "The token symbol or address (e.g., ETH, UNI)"
+                        },
+                        "block": {
+                            "type": "integer",
+                            "description": "The block number"
+                        }
+                    },
+                    "required": ["token", "block"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "estimate_swap".to_string(),
+                description: "Quote a token swap without sending anything: expected output, price impact, route and gas estimate. Use it to show the user a quote and get confirmation before swap_tokens".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "from_token": {
+                            "type": "string",
+                            "description": "The address or symbol of the token to swap from"
+                        },
+                        "to_token": {
+                            "type": "string",
+                            "description": "The address or symbol of the token to swap to"
+                        },
+                        "amount": {
+                            "type": "string",
+                            "description": "The amount to swap"
+                        },
+                        "dex": {
+                            "type": "string",
+                            "enum": ["uniswap_v2", "uniswap_v3", "0x", "1inch", "aggregator"],
+                            "description": "Exchange to quote. Omit to quote both Uniswap versions and report the better price; with from set, aggregators are quoted too"
+                        },
+                        "slippage": {
+                            "type": "number",
+                            "description": "Slippage tolerance in percent, used to compute the minimum output"
+                        },
+                        "from": {
+                            "type": "string",
+                            "description": "Account that would swap, used to estimate gas"
+                        }
+                    },
+                    "required": ["from_token", "to_token", "amount"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "describe_capabilities".to_string(),
+                description: "List the tools you can actually use, whether each reads or writes and what needs user confirmation, plus the current network and accounts. Use it when the user asks what you can do".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {}
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "get_allowance".to_string(),
+                description: "Check how much of an account's tokens a spender (a Uniswap router or any address) is approved to transfer".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "owner": {
+                            "type": "string",
+                            "description": "The token owner: address, named account (alice, bob) or ENS name (vitalik.eth)"
+                        },
+                        "token": {
+                            "type": "string",
+                            "description": "The address or symbol of the token"
+                        },
+                        "spender": {
+                            "type": "string",
+                            "description": "uniswap_v2, uniswap_v3, or the spender address. Defaults to uniswap_v2"
+                        }
+                    },
+                    "required": ["owner", "token"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "set_variable".to_string(),
+                description: "Store a conversation variable when the user writes e.g. \"let AMOUNT = 0.25\". Later requests can pass the variable name (AMOUNT) or ${AMOUNT} as any parameter value and the server substitutes it.".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "name": {
+                            "type": "string",
+                            "description": "Variable name: letters, digits and underscores, starting with a letter"
+                        },
+                        "value": {
+                            "type": "string",
+                            "description": "Value to store, e.g. 0.25 or an address"
+                        }
+                    },
+                    "required": ["name", "value"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "unset_variable".to_string(),
+                description: "Remove a conversation variable".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "name": {
+                            "type": "string",
+                            "description": "Variable name"
+                        }
+                    },
+                    "required": ["name"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "list_variables".to_string(),
+                description: "List the variables defined in this conversation and their values".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {}
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "save_template".to_string(),
+                description: "Save a named, reusable sequence of transactions, e.g. \"payday\": swap 30% of USDC to ETH then send 0.1 ETH to bob. Step params may use ${name} placeholders for template parameters, and amount may be a percentage of the signer's balance such as \"30%\".".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "name": {
+                            "type": "string",
+                            "description": "Template name: letters, digits and underscores"
+                        },
+                        "description": {
+                            "type": "string",
+                            "description": "What the template does"
+                        },
+                        "parameters": {
+                            "type": "object",
+                            "description": "Parameter name -> default value (null when the value must be given at run time)"
+                        },
+                        "steps": {
+                            "type": "array",
+                            "description": "Steps in order",
+                            "items": {
+                                "type": "object",
+                                "properties": {
+                                    "method": {
+                                        "type": "string",
+                                        "enum": ["send_eth", "send_erc20", "swap_tokens", "transfer_nft"]
+                                    },
+                                    "params": {
+                                        "type": "object",
+                                        "description": "Params for the method, as for the tool of the same name"
+                                    }
+                                },
+                                "required": ["method", "params"]
+                            }
+                        }
+                    },
+                    "required": ["name", "steps"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "run_template".to_string(),
+                description: "Run a saved template. Call it first without confirm to simulate every step and show the plan to the user; only call again with confirm set to true after they agree.".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "name": {
+                            "type": "string",
+                            "description": "Template name"
+                        },
+                        "args": {
+                            "type": "object",
+                            "description": "Values for the template parameters"
+                        },
+                        "confirm": {
+                            "type": "boolean",
+                            "description": "Execute the steps; leave unset to preview"
+                        }
+                    },
+                    "required": ["name"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "list_templates".to_string(),
+                description: "List saved operation templates and their steps".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {}
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "delete_template".to_string(),
+                description: "Delete a saved operation template".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "name": {
+                            "type": "string",
+                            "description": "Template name"
+                        }
+                    },
+                    "required": ["name"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "schedule_job".to_string(),
+                description: "Queue a transaction or saved template to run later, optionally only once gas is below a limit. Confirm the details with the user first: the job runs without asking again.".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "method": {
+                            "type": "string",
+                            "enum": ["send_eth", "send_erc20", "swap_tokens", "transfer_nft", "run_template"],
+                            "description": "Operation to run"
+                        },
+                        "params": {
+                            "type": "object",
+                            "description": "Params for the operation, as for the tool of the same name"
+                        },
+                        "run_at": {
+                            "type": "string",
+                            "description": "When to run, as an RFC 3339 date; defaults to now"
+                        },
+                        "delay_seconds": {
+                            "type": "integer",
+                            "description": "Run this many seconds from now instead of at run_at"
+                        },
+                        "max_gas_price_gwei": {
+                            "type": "number",
+                            "description": "Wait until gas is at or below this price"
+                        },
+                        "expires_in_seconds": {
+                            "type": "integer",
+                            "description": "Give up if the job hasn't run this long after it is due (default 24 hours with a gas limit)"
+                        }
+                    },
+                    "required": ["method", "params"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "list_jobs".to_string(),
+                description: "List scheduled jobs and their status".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "status": {
+                            "type": "string",
+                            "enum": ["pending", "deferred", "running", "succeeded", "failed", "expired", "cancelled"],
+                            "description": "Only jobs with this status"
+                        }
+                    }
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "cancel_job".to_string(),
+                description: "Cancel a scheduled job that hasn't run yet".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "id": {
+                            "type": "string",
+                            "description": "Job id"
+                        }
+                    },
+                    "required": ["id"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "get_notifications".to_string(),
+                description: "Notifications about scheduled jobs: deferrals while gas is too high, results and expiries".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "since": {
+                            "type": "integer",
+                            "description": "Only notifications after this Unix timestamp"
+                        }
+                    }
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "get_portfolio".to_string(),
+                description: "Get all token balances of an address or named account in one call, with USD prices, each holding's share and the total value. Use this for \"what do I hold\" or \"how much is my wallet worth\".".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "address": {
+                            "type": "string",
+                            "description": "Account name, address or ENS name"
+                        },
+                        "discover_tokens": {
+                            "type": "boolean",
+                            "description": "Also include tokens outside the registry that the address received recently (slower)"
+                        },
+                        "discover_blocks": {
+                            "type": "integer",
+                            "description": "How many recent blocks to search when discovering tokens (default 50000)"
+                        }
+                    },
+                    "required": ["address"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "get_transaction_history".to_string(),
+                description: "Get recent transactions of an account (ETH and tokens) with direction, counterparty, value and status. Use it for questions like 'what did bob do today?'".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "account": {
+                            "type": "string",
+                            "description": "Account name or address"
+                        },
+                        "range": {
+                            "type": "string",
+                            "description": "Optional lookback such as 24h, 7d or 4w"
+                        },
+                        "limit": {
+                            "type": "integer",
+                            "description": "Maximum number of transactions (default 20)"
+                        },
+                        "scan_blocks": {
+                            "type": "integer",
+                            "description": "Blocks to scan when no Etherscan key is configured (default 2000)"
+                        }
+                    },
+                    "required": ["account"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "get_events".to_string(),
+                description: "Query event logs emitted by a contract and decode them. Takes an event name from the known ABIs (e.g. Transfer, Swap) or a full signature, optional filters for the indexed params, and a block range (default: the last 1000 blocks)".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "address": {
+                            "type": "string",
+                            "description": "Contract address or ENS name"
+                        },
+                        "event": {
+                            "type": "string",
+                            "description": "Event name or signature, e.g. Transfer(address indexed from, address indexed to, uint256 value)"
+                        },
+                        "topics": {
+                            "type": "array",
+                            "description": "Filters for the indexed params in order: a value, a list of values, or null to match any",
+                            "items": {}
+                        },
+                        "from_block": {
+                            "type": "integer",
+                            "description": "First block to search"
+                        },
+                        "to_block": {
+                            "type": "integer",
+                            "description": "Last block to search (default: latest)"
+                        },
+                        "limit": {
+                            "type": "integer",
+                            "description": "Maximum number of events, most recent kept (default 100)"
+                        }
+                    },
+                    "required": ["address", "event"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "set_display_preferences".to_string(),
+                description: "Change how amounts are shown in this conversation. Only the given preferences change: decimal places for token amounts, thousands separators, whether USD equivalents are included, and the unit for gas prices".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "decimals": {
+                            "type": ["integer", "null"],
+                            "description": "Decimal places to round token amounts to (0-18), or null for full precision"
+                        },
+                        "thousands_separator": {
+                            "type": "boolean",
+                            "description": "Group large amounts with commas, e.g. 1,234.5"
+                        },
+                        "show_usd": {
+                            "type": "boolean",
+                            "description": "Include USD equivalents in results"
+                        },
+                        "gas_unit": {
+                            "type": "string",
+                            "enum": ["wei", "gwei", "ether"],
+                            "description": "Unit for gas prices"
+                        }
+                    }
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "get_display_preferences".to_string(),
+                description: "Get how amounts are currently formatted in this conversation".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {}
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "deploy_contract".to_string(),
+                description: "Deploy a smart contract from a compiled Foundry or Hardhat artifact (ABI and bytecode) using a named account, and return the new contract address. Confirm the contract, constructor arguments, account and network with the user first".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "from": {
+                            "type": "string",
+                            "description": "Name of the account that deploys and pays for the contract"
+                        },
+                        "artifact": {
+                            "type": "string",
+                            "description": "Path to the artifact JSON relative to the server directory, e.g. out/Counter.sol/Counter.json or artifacts/contracts/Counter.sol/Counter.json"
+                        },
+                        "args": {
+                            "type": "array",
+                            "description": "Constructor arguments in order; addresses may be account or ENS names",
+                            "items": {}
+                        },
+                        "salt": {
+                            "type": "string",
+                            "description": "Optional CREATE2 salt (hex or number) for a deterministic address via the CREATE2 deployer; the constructor then sees the deployer contract as msg.sender"
+                        }
+                    },
+                    "required": ["from", "artifact"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "convert_units".to_string(),
+                description: "Convert an amount exactly between wei, gwei and ether, or between a token's raw base units and its display units. Use this instead of doing the arithmetic yourself, e.g. for raw values pasted from a block explorer".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "amount": {
+                            "type": "string",
+                            "description": "The amount to convert, e.g. 1500000, 0.25 or 1e18"
+                        },
+                        "from": {
+                            "type": "string",
+                            "description": "Unit of the amount: wei, gwei, ether, base (a token's raw units) or a token symbol/address for its display units"
+                        },
+                        "to": {
+                            "type": "string",
+                            "description": "Unit to convert to, same options as from"
+                        },
+                        "token": {
+                            "type": "string",
+                            "description": "Token symbol or address when converting with the unit \"token\""
+                        }
+                    },
+                    "required": ["amount", "from", "to"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "call_contract".to_string(),
+                description: "Call a read-only (view or pure) function on any contract and decode the return values. function_signature is like 'balanceOf(address)', 'balanceOf(address)(uint256)' or 'function getReserves() view returns (uint112,uint112,uint32)'; give return types for functions outside the standard ERC20/ERC721/Uniswap ABIs. For contracts verified on Etherscan the bare function name (e.g. 'getReserves') is enough.".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "contract_address": {
+                            "type": "string",
+                            "description": "Contract address, ENS name or token symbol"
+                        },
+                        "function_signature": {
+                            "type": "string",
+                            "description": "Function signature, optionally with return types"
+                        },
+                        "parameters": {
+                            "type": "array",
+                            "items": {
+                                "type": "string"
+                            },
+                            "description": "Arguments in order; addresses may be account names"
+                        },
+                        "from": {
+                            "type": "string",
+                            "description": "Optional caller address or account name"
+                        }
+                    },
+                    "required": ["contract_address", "function_signature"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "broadcast_raw_transaction".to_string(),
+                description: "Broadcast a transaction that was already signed (e.g. one exported with send_eth or send_erc20 export: true) and wait for it to be mined. Confirm with the user before broadcasting.".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "raw_transaction": {
+                            "type": "string",
+                            "description": "The signed transaction as 0x-prefixed RLP hex"
+                        },
+                        "override_screening": {
+                            "type": "boolean",
+                            "description": "Send even if the recipient is flagged as a scam or sanctioned address. Only set this when the user explicitly insists."
+                        }
+                    },
+                    "required": ["raw_transaction"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "write_contract".to_string(),
+                description: "Sign and send a call to any state-changing contract function from a named account, for contracts the dedicated tools don't cover. function_signature is like 'approve(address,uint256)' or 'function deposit() payable', or just the function name for contracts verified on Etherscan. Confirm the contract, function, arguments, value and account with the user first.".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "from": {
+                            "type": "string",
+                            "description": "The named account that signs the call (alice, bob)"
+                        },
+                        "contract_address": {
+                            "type": "string",
+                            "description": "Contract address, ENS name or token symbol"
+                        },
+                        "function_signature": {
+                            "type": "string",
+                            "description": "Function signature, e.g. transfer(address,uint256)"
+                        },
+                        "parameters": {
+                            "type": "array",
+                            "items": {
+                                "type": "string"
+                            },
+                            "description": "Arguments in order, in the function's base units; addresses may be account names"
+                        },
+                        "value": {
+                            "type": "string",
+                            "description": "ETH to send with the call (e.g. '0.1'), default 0"
+                        },
+                        "gas_limit": {
+                            "type": "string",
+                            "description": "Optional gas limit (estimated automatically if omitted)"
+                        },
+                        "max_fee_per_gas": {
+                            "type": "string",
+                            "description": "Optional EIP-1559 max fee per gas in gwei (estimated automatically if omitted)"
+                        },
+                        "max_priority_fee_per_gas": {
+                            "type": "string",
+                            "description": "Optional EIP-1559 priority fee (tip) per gas in gwei (estimated automatically if omitted)"
+                        },
+                        "override_screening": {
+                            "type": "boolean",
+                            "description": "Call even if the contract is flagged as a scam or sanctioned address. Only set this when the user explicitly insists."
+                        },
+                        "export": {
+                            "type": "boolean",
+                            "description": "Sign the transaction and return the raw hex instead of broadcasting it, for the user to submit elsewhere"
+                        }
+                    },
+                    "required": ["from", "contract_address", "function_signature"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "prepare_unsigned_transaction".to_string(),
+                description: "Build an unsigned transaction (EIP-2718 JSON plus the hash to sign) for a sender whose key is kept offline, e.g. on a hardware wallet or air-gapped machine. Nothing is signed or sent.".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "type": {
+                            "type": "string",
+                            "enum": ["send_eth", "send_erc20", "write_contract"],
+                            "description": "Kind of transaction, default send_eth"
+                        },
+                        "from": {
+                            "type": "string",
+                            "description": "The sender address or account name"
+                        },
+                        "to": {
+                            "type": "string",
+                            "description": "Recipient for send_eth and send_erc20"
+                        },
+                        "amount": {
+                            "type": "string",
+                            "description": "Amount for send_eth and send_erc20"
+                        },
+                        "token": {
+                            "type": "string",
+                            "description": "Token for send_erc20"
+                        },
+                        "contract_address": {
+                            "type": "string",
+                            "description": "Contract for write_contract"
+                        },
+                        "function_signature": {
+                            "type": "string",
+                            "description": "Function for write_contract, e.g. approve(address,uint256)"
+                        },
+                        "parameters": {
+                            "type": "array",
+                            "items": {
+                                "type": "string"
+                            },
+                            "description": "Arguments for write_contract"
+                        },
+                        "value": {
+                            "type": "string",
+                            "description": "ETH sent with a write_contract call"
+                        },
+                        "gas_limit": {
+                            "type": "integer",
+                            "description": "Optional gas limit for write_contract"
+                        },
+                        "max_fee_per_gas": {
+                            "type": "string",
+                            "description": "Optional EIP-1559 max fee per gas in gwei (estimated automatically if omitted)"
+                        },
+                        "max_priority_fee_per_gas": {
+                            "type": "string",
+                            "description": "Optional EIP-1559 priority fee (tip) per gas in gwei (estimated automatically if omitted)"
+                        }
+                    },
+                    "required": ["from"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "attach_signature".to_string(),
+                description: "Attach a signature produced offline to a transaction from prepare_unsigned_transaction and broadcast it. Confirm with the user before broadcasting.".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "transaction": {
+                            "type": "object",
+                            "description": "The transaction object returned by prepare_unsigned_transaction, unchanged"
+                        },
+                        "signature": {
+                            "type": "string",
+                            "description": "65-byte signature of signing_hash as hex (r, s, v)"
+                        },
+                        "broadcast": {
+                            "type": "boolean",
+                            "description": "Set false to only return the signed raw transaction (default true)"
+                        },
+                        "override_screening": {
+                            "type": "boolean",
+                            "description": "Send even if the recipient is flagged as a scam or sanctioned address. Only set this when the user explicitly insists."
+                        }
+                    },
+                    "required": ["transaction", "signature"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "decode_calldata".to_string(),
+                description: "Decode transaction input data (calldata) into the function name and arguments, using the loaded ABIs, the contract's verified ABI and the 4byte signature directory. Use it to explain what a transaction does.".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "data": {
+                            "type": "string",
+                            "description": "The calldata as 0x-prefixed hex"
+                        },
+                        "contract_address": {
+                            "type": "string",
+                            "description": "Optional contract the data is sent to, to use its verified ABI"
+                        }
+                    },
+                    "required": ["data"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "parse_payment_uri".to_string(),
+                description: "Read an EIP-681 payment URI (ethereum:0x...?value=..., or ethereum:<token>/transfer?address=...&uint256=...) into the recipient, asset, amount and chain, with the send_eth or send_erc20 parameters that pay it. Nothing is sent.".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "uri": {
+                            "type": "string",
+                            "description": "The ethereum: payment URI"
+                        },
+                        "from": {
+                            "type": "string",
+                            "description": "Optional named account that will pay, added to the returned params"
+                        }
+                    },
+                    "required": ["uri"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "get_transaction".to_string(),
+                description: "Look up a transaction by hash: status, block and time, sender and recipient, ETH value, gas and fee, the decoded function call and decoded events. Use it to explain a transaction the user pastes.".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "hash": {
+                            "type": "string",
+                            "description": "The transaction hash (0x followed by 64 hex characters)"
+                        }
+                    },
+                    "required": ["hash"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "get_inbox".to_string(),
+                description: "Activity on the user's accounts recorded while they were away: incoming token transfers, approvals granted to them and large ETH balance changes. Returns unread items and marks them read.".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "include_read": {
+                            "type": "boolean",
+                            "description": "Also return items that were already read"
+                        }
+                    }
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "sign_message".to_string(),
+                description: "Sign a message with a named account's key using personal_sign (EIP-191 prefix). Show the user the exact message first and sign only after they agree.".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "account": {
+                            "type": "string",
+                            "description": "Name of the account to sign with"
+                        },
+                        "message": {
+                            "type": "string",
+                            "description": "The message to sign"
+                        },
+                        "hex": {
+                            "type": "boolean",
+                            "description": "Treat message as 0x-prefixed hex bytes instead of text"
+                        }
+                    },
+                    "required": ["account", "message"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "verify_signature".to_string(),
+                description: "Recover the address that signed a personal_sign message, and optionally check it against an expected address or account name.".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "message": {
+                            "type": "string",
+                            "description": "The message that was signed"
+                        },
+                        "signature": {
+                            "type": "string",
+                            "description": "65-byte hex signature"
+                        },
+                        "address": {
+                            "type": "string",
+                            "description": "Expected signer: address, account name or ENS name"
+                        },
+                        "hex": {
+                            "type": "boolean",
+                            "description": "Treat message as 0x-prefixed hex bytes instead of text"
+                        }
+                    },
+                    "required": ["message", "signature"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "get_twap".to_string(),
+                description: "Time-weighted average price of a token from a Uniswap V3 oracle (or V2 cumulative prices) over a window. Resistant to single-block manipulation; also returns the pool's spot price and how far it deviates.".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "token": {
+                            "type": "string",
+                            "description": "Token to price (symbol or address, ETH for WETH)"
+                        },
+                        "quote": {
+                            "type": "string",
+                            "description": "Token to price it in (default USDC)"
+                        },
+                        "window_seconds": {
+                            "type": "integer",
+                            "description": "Averaging window in seconds (default 1800)"
+                        },
+                        "dex": {
+                            "type": "string",
+                            "description": "uniswap_v3 or uniswap_v2 (default V3 where configured)"
+                        },
+                        "fee": {
+                            "type": "integer",
+                            "description": "V3 fee tier, e.g. 500 or 3000 (default: most liquid pool)"
+                        }
+                    },
+                    "required": ["token"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "approve_token".to_string(),
+                description: "Set a named account's ERC20 allowance for a spender (a contract address, ENS name, or uniswap_v2 / uniswap_v3 for the routers). Confirm token, spender and amount with the user first; policy may cap the amount.".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "from": {
+                            "type": "string",
+                            "description": "Name of the account granting the allowance"
+                        },
+                        "token": {
+                            "type": "string",
+                            "description": "Token symbol or address"
+                        },
+                        "spender": {
+                            "type": "string",
+                            "description": "Spender address, ENS name, uniswap_v2 or uniswap_v3"
+                        },
+                        "amount": {
+                            "type": "string",
+                            "description": "Allowance in token units, or \"unlimited\""
+                        },
+                        "spend_amount": {
+                            "type": "string",
+                            "description": "Amount the spender is expected to move, in token units; policy caps the allowance at a multiple of it"
+                        },
+                        "override_screening": {
+                            "type": "boolean",
+                            "description": "Approve a flagged spender after the user accepts the risk"
+                        },
+                        "override_approval_cap": {
+                            "type": "boolean",
+                            "description": "Approve the full amount even though it is above the approval policy cap, where the policy allows it. Only set this when the user explicitly insists."
+                        },
+                        "acknowledge_risk": {
+                            "type": "boolean",
+                            "description": "Proceed even though the token safety check reported risks. Only set this after showing the risk summary to the user and getting their confirmation."
+                        }
+                    },
+                    "required": ["from", "token", "spender", "amount"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "revoke_approval".to_string(),
+                description: "Revoke a named account's ERC20 allowance for a spender by setting it to zero. Confirm the token and spender with the user first.".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "from": {
+                            "type": "string",
+                            "description": "Name of the account that granted the allowance"
+                        },
+                        "token": {
+                            "type": "string",
+                            "description": "Token symbol or address"
+                        },
+                        "spender": {
+                            "type": "string",
+                            "description": "Spender address, ENS name, uniswap_v2 or uniswap_v3"
+                        }
+                    },
+                    "required": ["from", "token", "spender"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "send_batch".to_string(),
+                description: "Send ETH or one ERC20 token from a named account to several recipients, e.g. payroll. Sends one transaction per recipient, or a single Disperse contract transaction with disperse: true. Confirm all recipients, amounts and the total with the user first.".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "from": {
+                            "type": "string",
+                            "description": "Name of the sending account"
+                        },
+                        "token": {
+                            "type": "string",
+                            "description": "Token symbol or address; omit for ETH"
+                        },
+                        "transfers": {
+                            "type": "array",
+                            "description": "Recipients and amounts",
+                            "items": {
+                                "type": "object",
+                                "properties": {
+                                    "to": {
+                                        "type": "string",
+                                        "description": "Recipient address, account or ENS name"
+                                    },
+                                    "amount": {
+                                        "type": "string",
+                                        "description": "Amount in ETH or token units"
+                                    }
+                                },
+                                "required": ["to", "amount"]
+                            }
+                        },
+                        "disperse": {
+                            "type": "boolean",
+                            "description": "Send everything in one transaction through the Disperse contract"
+                        },
+                        "override_screening": {
+                            "type": "boolean",
+                            "description": "Send to flagged recipients after the user accepts the risk"
+                        }
+                    },
+                    "required": ["from", "transfers"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "get_stablecoin_status".to_string(),
+                description: "Check stablecoin prices across Chainlink, a Uniswap TWAP and DefiLlama and report any that deviate from $1 by more than the threshold (default 0.5%). Without tokens it checks the monitored set (USDC, USDT, DAI by default).".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "tokens": {
+                            "type": "array",
+                            "items": {
+                                "type": "string"
+                            },
+                            "description": "Stablecoin symbols or addresses to check"
+                        },
+                        "threshold_percent": {
+                            "type": "number",
+                            "description": "Allowed deviation from $1 in percent"
+                        }
+                    }
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "get_gas_price".to_string(),
+                description: "Report current gas prices from recent fee history: the next base fee and how it compares to the recent median (low/normal/high), priority fee percentiles, and slow/standard/fast fee recommendations with the cost of a plain transfer. Use it to answer whether now is a cheap time to transact.".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "blocks": {
+                            "type": "integer",
+                            "description": "Recent blocks to sample (default 100, max 1024)"
+                        }
+                    }
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "get_lp_performance".to_string(),
+                description: "Check how an account's Uniswap liquidity positions (V3 NFTs and V2 pairs it added liquidity to) are doing: current value, impermanent loss against simply holding the deposited tokens, fees earned and the net result. Use it to answer whether an LP position is actually making money.".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "address": {
+                            "type": "string",
+                            "description": "Account name or address that holds the positions"
+                        },
+                        "lookback_blocks": {
+                            "type": "integer",
+                            "description": "How many recent blocks to search for deposits and withdrawals (default 1000000)"
+                        }
+                    },
+                    "required": ["address"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "find_yield".to_string(),
+                description: "Find yield opportunities from DefiLlama on the current network (or a given chain), ranked by APY with base and reward APY broken out. Only audited protocols and pools above a TVL floor are included by default. Informational only: it deposits nothing.".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "asset": {
+                            "type": "string",
+                            "description": "Token symbol the pool must hold, e.g. USDC or ETH"
+                        },
+                        "chain": {
+                            "type": "string",
+                            "description": "DefiLlama chain name such as Ethereum, Arbitrum or Base (default: the current network)"
+                        },
+                        "min_tvl_usd": {
+                            "type": "number",
+                            "description": "Minimum pool TVL in USD (default 1,000,000)"
+                        },
+                        "include_unaudited": {
+                            "type": "boolean",
+                            "description": "Also include protocols outside the audited list"
+                        },
+                        "stablecoins_only": {
+                            "type": "boolean",
+                            "description": "Only pools of stablecoins"
+                        },
+                        "single_asset_only": {
+                            "type": "boolean",
+                            "description": "Skip multi-asset pools that carry impermanent loss"
+                        },
+                        "limit": {
+                            "type": "integer",
+                            "description": "Number of opportunities to return (default 10, max 50)"
+                        }
+                    }
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "get_protocol_health".to_string(),
+                description: "Gather health signals for a DeFi protocol in one call: TVL trend, its token's price trend, recent Snapshot governance proposals, incidents DefiLlama has marked, exploit news from web search, and warnings derived from them. Use it when the user asks whether a protocol is safe or if they should be worried about it.".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "protocol": {
+                            "type": "string",
+                            "description": "Protocol name or DefiLlama slug, e.g. Aave or curve-dex"
+                        }
+                    },
+                    "required": ["protocol"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "snapshot_chain".to_string(),
+                description: "Checkpoint the local Anvil chain's state before a risky experiment and return a snapshot id to roll back to later. Only works on local dev chains.".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {}
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "revert_chain".to_string(),
+                description: "Roll the local Anvil chain back to a snapshot taken with snapshot_chain, undoing every transaction since. A snapshot can be used once and reverting also discards later snapshots, so take a new one if the user wants to retry. Confirm with the user first.".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "id": {
+                            "type": "string",
+                            "description": "Snapshot id returned by snapshot_chain"
+                        }
+                    },
+                    "required": ["id"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "list_proposals".to_string(),
+                description: "List open proposals on a GovernorBravo governance contract (compound, uniswap or an address), with their titles, descriptions, tallies and voting deadlines".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "governor": {
+                            "type": "string",
+                            "description": "Governor name (compound, uniswap) or GovernorBravo address"
+                        },
+                        "include_closed": {
+                            "type": "boolean",
+                            "description": "Also list proposals that are no longer open for voting"
+                        },
+                        "limit": {
+                            "type": "integer",
+                            "description": "Most proposals to return, newest first (default 10)"
+                        }
+                    },
+                    "required": ["governor"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "get_proposal".to_string(),
+                description: "Get a governance proposal's full description, state and vote tallies".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "governor": {
+                            "type": "string",
+                            "description": "Governor name (compound, uniswap) or GovernorBravo address"
+                        },
+                        "proposal_id": {
+                            "type": "string",
+                            "description": "Proposal id"
+                        }
+                    },
+                    "required": ["governor", "proposal_id"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "get_voting_power".to_string(),
+                description: "Get an account's governance token balance, delegated votes and delegate; with a proposal id, also the votes it can cast on that proposal and whether it already voted".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "governor": {
+                            "type": "string",
+                            "description": "Governor name (compound, uniswap) or GovernorBravo address"
+                        },
+                        "account": {
+                            "type": "string",
+                            "description": "Account name or address"
+                        },
+                        "proposal_id": {
+                            "type": "string",
+                            "description": "Proposal to check votes on"
+                        }
+                    },
+                    "required": ["governor", "account"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "cast_vote".to_string(),
+                description: "Vote for, against or abstain on an active governance proposal from a named account, with an optional reason. Requires user confirmation; votes can't be changed".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "governor": {
+                            "type": "string",
+                            "description": "Governor name (compound, uniswap) or GovernorBravo address"
+                        },
+                        "proposal_id": {
+                            "type": "string",
+                            "description": "Proposal id"
+                        },
+                        "support": {
+                            "type": "string",
+                            "enum": ["for", "against", "abstain"],
+                            "description": "How to vote"
+                        },
+                        "reason": {
+                            "type": "string",
+                            "description": "Reason recorded on-chain with the vote"
+                        },
+                        "from": {
+                            "type": "string",
+                            "description": "Account name that votes"
+                        }
+                    },
+                    "required": ["governor", "proposal_id", "support", "from"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "increase_time".to_string(),
+                description: "Advance the local Anvil chain's clock by a number of seconds, e.g. to get past a vesting cliff, deadline or timelock, or to let interest accrue. Mines a block so contracts see the new time. Only works on local dev chains.".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "seconds": {
+                            "type": "integer",
+                            "description": "Seconds to advance, e.g. 86400 for a day"
+                        },
+                        "mine": {
+                            "type": "boolean",
+                            "description": "Mine a block so the new time takes effect now (default true)"
+                        }
+                    },
+                    "required": ["seconds"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "mine_blocks".to_string(),
+                description: "Mine empty blocks on the local Anvil chain, e.g. to pass a block-based voting delay or lock. Only works on local dev chains.".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "blocks": {
+                            "type": "integer",
+                            "description": "Number of blocks to mine (default 1, at most 10000)"
+                        }
+                    }
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "set_next_block_timestamp".to_string(),
+                description: "Set the exact Unix timestamp of the local Anvil chain's next block, which must be later than the latest block. Mines it unless mine is false. Only works on local dev chains.".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "timestamp": {
+                            "type": "integer",
+                            "description": "Unix timestamp in seconds"
+                        },
+                        "mine": {
+                            "type": "boolean",
+                            "description": "Mine the block now (default true)"
+                        }
+                    },
+                    "required": ["timestamp"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "list_snapshot_spaces".to_string(),
+                description: "List Snapshot (off-chain governance) spaces by id, e.g. aave.eth, or the spaces an account follows".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "spaces": {
+                            "type": "array",
+                            "items": {
+                                "type": "string"
+                            },
+                            "description": "Space ids (ENS names such as aave.eth)"
+                        },
+                        "account": {
+                            "type": "string",
+                            "description": "Account name or address whose followed spaces to list, when no ids are given"
+                        }
+                    }
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "list_snapshot_proposals".to_string(),
+                description: "List proposals in a Snapshot space with their choices and current results, newest first".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "space": {
+                            "type": "string",
+                            "description": "Space id, e.g. aave.eth"
+                        },
+                        "state": {
+                            "type": "string",
+                            "enum": ["active", "pending", "closed", "all"],
+                            "description": "Which proposals to list (default active)"
+                        },
+                        "limit": {
+                            "type": "integer",
+                            "description": "Most proposals to return (default 10)"
+                        }
+                    },
+                    "required": ["space"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "get_snapshot_proposal".to_string(),
+                description: "Get a Snapshot proposal's full description, voting system, choices and results".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "proposal_id": {
+                            "type": "string",
+                            "description": "Snapshot proposal id"
+                        }
+                    },
+                    "required": ["proposal_id"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "get_snapshot_voting_power".to_string(),
+                description: "Get an account's voting power in a Snapshot space, and on a proposal also any vote it already cast".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "space": {
+                            "type": "string",
+                            "description": "Space id, e.g. aave.eth"
+                        },
+                        "account": {
+                            "type": "string",
+                            "description": "Account name or address"
+                        },
+                        "proposal_id": {
+                            "type": "string",
+                            "description": "Proposal to check voting power on"
+                        }
+                    },
+                    "required": ["space", "account"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "cast_snapshot_vote".to_string(),
+                description: "Sign an off-chain Snapshot vote with a named account's key and submit it. No gas is paid; voting again replaces the earlier vote. Requires user confirmation".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "proposal_id": {
+                            "type": "string",
+                            "description": "Snapshot proposal id"
+                        },
+                        "choice": {
+                            "type": "string",
+                            "description": "Choice name or 1-based number; for approval or ranked-choice proposals a comma-separated list; for weighted or quadratic proposals option=weight pairs like \"For=60,Against=40\""
+                        },
+                        "reason": {
+                            "type": "string",
+                            "description": "Reason published with the vote"
+                        },
+                        "from": {
+                            "type": "string",
+                            "description": "Account name that votes"
+                        }
+                    },
+                    "required": ["proposal_id", "choice", "from"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "check_claims".to_string(),
+                description: "Check an address for unclaimed airdrop allocations in the configured merkle distributors on the current network, with amounts and whether each can be claimed".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "address": {
+                            "type": "string",
+                            "description": "Account name, address or ENS name"
+                        }
+                    },
+                    "required": ["address"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "claim_airdrop".to_string(),
+                description: "Claim a named account's airdrop allocation from a distributor listed by check_claims. Requires user confirmation".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "source": {
+                            "type": "string",
+                            "description": "Claim source name from check_claims"
+                        },
+                        "from": {
+                            "type": "string",
+                            "description": "Account name that claims"
+                        }
+                    },
+                    "required": ["source", "from"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "fund_account".to_string(),
+                description: "Give a test account ETH or ERC20 tokens on the local Anvil chain without swapping, by rewriting its balance. The amount is added to what it holds. Only works on local dev chains.".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "account": {
+                            "type": "string",
+                            "description": "Account name or address"
+                        },
+                        "token": {
+                            "type": "string",
+                            "description": "Token symbol or address; omit or ETH for ether"
+                        },
+                        "amount": {
+                            "type": "string",
+                            "description": "Amount to add in token units, e.g. \"1000\""
+                        }
+                    },
+                    "required": ["account", "amount"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "get_vesting".to_string(),
+                description: "Read a token vesting contract (OpenZeppelin VestingWallet or Sablier lockup): total, vested, released, claimable and locked amounts and upcoming unlock dates. For Sablier, give a stream id or the recipient address to find their streams".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "contract": {
+                            "type": "string",
+                            "description": "VestingWallet or Sablier lockup contract address"
+                        },
+                        "address": {
+                            "type": "string",
+                            "description": "Recipient account name or address, to find their Sablier streams"
+                        },
+                        "token": {
+                            "type": "string",
+                            "description": "Token a VestingWallet vests (symbol or address); omit for ETH"
+                        },
+                        "stream_id": {
+                            "type": "string",
+                            "description": "Sablier stream id"
+                        },
+                        "lookback_blocks": {
+                            "type": "integer",
+                            "description": "How far back to look for Sablier streams (default 2000000)"
+                        }
+                    },
+                    "required": ["contract"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "release_vested".to_string(),
+                description: "Release vested tokens from a VestingWallet (paid to its beneficiary) or withdraw everything withdrawable from a Sablier stream to the named account. Requires user confirmation".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "contract": {
+                            "type": "string",
+                            "description": "VestingWallet or Sablier lockup contract address"
+                        },
+                        "token": {
+                            "type": "string",
+                            "description": "Token a VestingWallet vests (symbol or address); omit for ETH"
+                        },
+                        "stream_id": {
+                            "type": "string",
+                            "description": "Sablier stream id"
+                        },
+                        "from": {
+                            "type": "string",
+                            "description": "Account name that sends the transaction"
+                        }
+                    },
+                    "required": ["contract", "from"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "create_stream".to_string(),
+                description: "Stream tokens to a recipient over time, e.g. 'stream 1000 USDC to bob over 30 days'. Sablier (default) escrows the full amount up front and streams it linearly, approving the token first if needed; Superfluid opens a flow of a super token (e.g. USDCx) at the matching rate that runs until cancelled. Requires user confirmation".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "recipient": {
+                            "type": "string",
+                            "description": "Recipient address, account name or ENS name"
+                        },
+                        "token": {
+                            "type": "string",
+                            "description": "Token symbol or address; a super token for Superfluid"
+                        },
+                        "amount": {
+                            "type": "string",
+                            "description": "Total amount to stream, in token units"
+                        },
+                        "duration_seconds": {
+                            "type": "integer",
+                            "description": "How long the stream runs, in seconds (30 days = 2592000)"
+                        },
+                        "protocol": {
+                            "type": "string",
+                            "enum": ["sablier", "superfluid"],
+                            "description": "Streaming protocol (default sablier)"
+                        },
+                        "cliff_seconds": {
+                            "type": "integer",
+                            "description": "Sablier only: nothing is withdrawable until this long after the start (default 0)"
+                        },
+                        "cancelable": {
+                            "type": "boolean",
+                            "description": "Sablier only: whether the sender can cancel the stream (default true)"
+                        },
+                        "from": {
+                            "type": "string",
+                            "description": "Account name that funds the stream"
+                        },
+                        "override_screening": {
+                            "type": "boolean",
+                            "description": "Stream even if the recipient is flagged as a scam or sanctioned address. Only set this when the user explicitly insists."
+                        }
+                    },
+                    "required": ["recipient", "token", "amount", "duration_seconds", "from"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "cancel_stream".to_string(),
+                description: "Cancel a payment stream the named account sends: a Sablier stream by id (the unstreamed part is refunded, the streamed part stays withdrawable by the recipient) or a Superfluid flow by token and recipient. Requires user confirmation".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "protocol": {
+                            "type": "string",
+                            "enum": ["sablier", "superfluid"],
+                            "description": "Streaming protocol (default sablier)"
+                        },
+                        "stream_id": {
+                            "type": "string",
+                            "description": "Sablier stream id"
+                        },
+                        "token": {
+                            "type": "string",
+                            "description": "Superfluid: super token symbol or address"
+                        },
+                        "recipient": {
+                            "type": "string",
+                            "description": "Superfluid: recipient address, account name or ENS name"
+                        },
+                        "from": {
+                            "type": "string",
+                            "description": "Account name that sends the stream"
+                        }
+                    },
+                    "required": ["from"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "get_streams".to_string(),
+                description: "Look up payment streams: a Sablier stream by id, or the streams an address sends or receives that were created through this assistant, with rate per day, amount streamed, withdrawable and remaining, and end date".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "address": {
+                            "type": "string",
+                            "description": "Sender or recipient address, account name or ENS name; omit for all streams"
+                        },
+                        "stream_id": {
+                            "type": "string",
+                            "description": "Sablier stream id"
+                        }
+                    }
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "deploy_token".to_string(),
+                description: "Deploy a standard mintable ERC20 token from a named account with the given name, symbol, decimals and initial supply (minted to the deployer), register it so it can be used by symbol, and optionally mint tokens to other accounts. Confirm the details, account and network with the user first".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "from": {
+                            "type": "string",
+                            "description": "Name of the account that deploys the token, receives the initial supply and may mint more"
+                        },
+                        "name": {
+                            "type": "string",
+                            "description": "Token name, e.g. Test Dollar"
+                        },
+                        "symbol": {
+                            "type": "string",
+                            "description": "Token symbol, e.g. TUSD"
+                        },
+                        "decimals": {
+                            "type": "integer",
+                            "description": "Decimals (default 18)"
+                        },
+                        "initial_supply": {
+                            "type": "string",
+                            "description": "Whole tokens minted to the deployer, e.g. 1000000 (default 0)"
+                        },
+                        "mint_to": {
+                            "type": "array",
+                            "description": "Account names or addresses to mint mint_amount tokens to after deployment",
+                            "items": {
+                                "type": "string"
+                            }
+                        },
+                        "mint_amount": {
+                            "type": "string",
+                            "description": "Whole tokens minted to each mint_to account"
+                        },
+                        "artifact": {
+                            "type": "string",
+                            "description": "Optional Foundry or Hardhat artifact of a token with the same constructor (name, symbol, decimals, initialSupply), used instead of the built-in token"
+                        }
+                    },
+                    "required": ["from", "name", "symbol"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "compute_address".to_string(),
+                description: "Compute the address a contract will be deployed at: with CREATE from a deployer and nonce (the deployer's next nonce by default), or with CREATE2 from a salt and the init code, its hash, or an artifact and constructor arguments. Also reports whether code already exists there".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "deployer": {
+                            "type": "string",
+                            "description": "Deploying account name or address; for CREATE2 it defaults to the CREATE2 deployer that deploy_contract uses with a salt"
+                        },
+                        "nonce": {
+                            "type": "integer",
+                            "description": "CREATE: the deployer nonce to compute for (default: its next nonce)"
+                        },
+                        "salt": {
+                            "type": "string",
+                            "description": "CREATE2 salt, 0x-prefixed hex of up to 32 bytes or a number"
+                        },
+                        "init_code": {
+                            "type": "string",
+                            "description": "CREATE2: hex creation code including encoded constructor arguments"
+                        },
+                        "init_code_hash": {
+                            "type": "string",
+                            "description": "CREATE2: keccak256 of the creation code, instead of init_code"
+                        },
+                        "artifact": {
+                            "type": "string",
+                            "description": "CREATE2: Foundry or Hardhat artifact path to build the init code from, instead of init_code"
+                        },
+                        "args": {
+                            "type": "array",
+                            "description": "Constructor arguments for the artifact",
+                            "items": {}
+                        }
+                    }
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "get_smart_account".to_string(),
+                description: "Look up an owner's ERC-4337 smart account: its address (known before it is deployed), whether it is deployed, its nonce, ETH balance and EntryPoint deposit".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "owner": {
+                            "type": "string",
+                            "description": "Account name or address of the owner"
+                        },
+                        "salt": {
+                            "type": "string",
+                            "description": "Which of the owner's smart accounts, as a number (default 0)"
+                        }
+                    },
+                    "required": ["owner"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "deploy_smart_account".to_string(),
+                description: "Deploy an owner's ERC-4337 smart account with a transaction paid by the owner's account. Not required before use: the first user operation deploys it. Confirm with the user first".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "owner": {
+                            "type": "string",
+                            "description": "Name of the account that owns the smart account"
+                        },
+                        "salt": {
+                            "type": "string",
+                            "description": "Which of the owner's smart accounts, as a number (default 0)"
+                        }
+                    },
+                    "required": ["owner"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "estimate_user_operation".to_string(),
+                description: "Build a user operation for a call from an owner's smart account and estimate its gas and maximum cost through the bundler, or the paymaster when gas is sponsored. Nothing is sent".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "owner": {
+                            "type": "string",
+                            "description": "Name of the account that owns the smart account"
+                        },
+                        "salt": {
+                            "type": "string",
+                            "description": "Which of the owner's smart accounts, as a number (default 0)"
+                        },
+                        "contract_address": {
+                            "type": "string",
+                            "description": "Contract to call, or the recipient of a plain ETH transfer; may be an account or ENS name"
+                        },
+                        "function_signature": {
+                            "type": "string",
+                            "description": "Function to call, e.g. transfer(address,uint256); omit for a plain ETH transfer"
+                        },
+                        "parameters": {
+                            "type": "array",
+                            "description": "Function arguments in order",
+                            "items": {}
+                        },
+                        "value": {
+                            "type": "string",
+                            "description": "ETH to send with the call (default 0)"
+                        }
+                    },
+                    "required": ["owner", "contract_address"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "send_user_operation".to_string(),
+                description: "Make a contract call or ETH transfer from an owner's ERC-4337 smart account: builds the user operation, gets it sponsored when a paymaster is configured, signs it with the owner's key and submits it to the bundler. Confirm the call and who pays gas with the user first, unless a session key covers it".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "owner": {
+                            "type": "string",
+                            "description": "Name of the account that owns the smart account"
+                        },
+                        "salt": {
+                            "type": "string",
+                            "description": "Which of the owner's smart accounts, as a number (default 0)"
+                        },
+                        "contract_address": {
+                            "type": "string",
+                            "description": "Contract to call, or the recipient of a plain ETH transfer; may be an account or ENS name"
+                        },
+                        "function_signature": {
+                            "type": "string",
+                            "description": "Function to call, e.g. transfer(address,uint256); omit for a plain ETH transfer"
+                        },
+                        "parameters": {
+                            "type": "array",
+                            "description": "Function arguments in order",
+                            "items": {}
+                        },
+                        "value": {
+                            "type": "string",
+                            "description": "ETH to send with the call (default 0)"
+                        },
+                        "session_key": {
+                            "type": "string",
+                            "description": "Id of a session key the user granted; calls within its scope are sent without asking the user. While a key is active, calls outside its scope are refused even without session_key"
+                        },
+                        "override_screening": {
+                            "type": "boolean",
+                            "description": "Send even if the target is flagged as a scam or sanctioned address. Only set this when the user explicitly insists."
+                        }
+                    },
+                    "required": ["owner", "contract_address"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "get_tx_status".to_string(),
+                description: "Check where a transaction stands: pending, success or failed with its confirmations, or replaced/dropped for ones sent from here. Use it to follow up on a transaction a tool returned as submitted or pending; once the request that sent it finishes, its result is included".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "hash": {
+                            "type": "string",
+                            "description": "Transaction hash"
+                        }
+                    },
+                    "required": ["hash"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "grant_session_key".to_string(),
+                description: "Grant the assistant a session key for an owner's smart account: user operations to the allowed targets, sending at most max_value ETH each, can then run without asking until the key expires. Confirm the scope and expiry with the user first".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "owner": {
+                            "type": "string",
+                            "description": "Name of the account that owns the smart account"
+                        },
+                        "salt": {
+                            "type": "string",
+                            "description": "Which of the owner's smart accounts, as a number (default 0)"
+                        },
+                        "allowed_targets": {
+                            "type": "array",
+                            "description": "Contracts or recipients the key may call; addresses, account or ENS names",
+                            "items": {
+                                "type": "string"
+                            }
+                        },
+                        "max_value": {
+                            "type": "string",
+                            "description": "Most ETH a single operation may send (default 0)"
+                        },
+                        "expires_in_seconds": {
+                            "type": "integer",
+                            "description": "How long the key lasts, at most 30 days"
+                        },
+                        "expires_at": {
+                            "type": "string",
+                            "description": "When the key expires, as an RFC 3339 time, instead of expires_in_seconds"
+                        }
+                    },
+                    "required": ["owner", "allowed_targets"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "list_session_keys".to_string(),
+                description: "List unexpired session keys with their smart account, allowed targets, ETH limit and expiry".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "owner": {
+                            "type": "string",
+                            "description": "Only keys for this owner account"
+                        }
+                    }
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "revoke_session_key".to_string(),
+                description: "Revoke a session key so its operations need confirmation again".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "id": {
+                            "type": "string",
+                            "description": "Session key id"
+                        }
+                    },
+                    "required": ["id"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "list_pending_transactions".to_string(),
+                description: "List transactions from the user's accounts that were sent but haven't confirmed yet, and write requests still running in the background".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {}
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "pair_wallet".to_string(),
+                description: "Pair the user's own wallet (MetaMask, Rainbow or any WalletConnect wallet) under a name. Returns a wc: URI for the user to open or scan in the wallet; list_wallets shows when they've approved it. The wallet keeps its keys.".to_string(),
                 input_schema: from_value(json!({
                     "type": "object",
                     "properties": {
-                        "address": {
+                        "name": {
+                            "type": "string",
+                            "description": "Name to refer to the wallet by, e.g. metamask"
+                        }
+                    },
+                    "required": ["name"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "list_wallets".to_string(),
+                description: "List paired external wallets with their address, chains and whether they are connected".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {}
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "send_with_wallet".to_string(),
+                description: "Prepare a transaction from a paired wallet and send it there for the user to approve; the wallet signs and submits it. Confirm the details with the user first".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "wallet": {
+                            "type": "string",
+                            "description": "Name of the paired wallet"
+                        },
+                        "type": {
+                            "type": "string",
+                            "enum": ["send_eth", "send_erc20", "write_contract"],
+                            "description": "Kind of transaction, default send_eth"
+                        },
+                        "to": {
+                            "type": "string",
+                            "description": "Recipient for send_eth and send_erc20"
+                        },
+                        "amount": {
                             "type": "string",
-                            "description": "The Ethereum address or named account (alice, bob) to check balance for"
+                            "description": "Amount for send_eth and send_erc20"
                         },
                         "token": {
                             "type": "string",
-                            "description": "Optional token address to check balance for. If not provided, ETH balance is returned."
+                            "description": "Token for send_erc20"
+                        },
+                        "contract_address": {
+                            "type": "string",
+                            "description": "Contract for write_contract"
+                        },
+                        "function_signature": {
+                            "type": "string",
+                            "description": "Function for write_contract, e.g. approve(address,uint256)"
+                        },
+                        "parameters": {
+                            "type": "array",
+                            "items": {
+                                "type": "string"
+                            },
+                            "description": "Arguments for write_contract"
+                        },
+                        "value": {
+                            "type": "string",
+                            "description": "ETH sent with a write_contract call"
+                        },
+                        "override_screening": {
+                            "type": "boolean",
+                            "description": "Send to a flagged recipient after the user accepts the risk"
                         }
                     },
-                    "required": ["address"]
+                    "required": ["wallet"]
                 })).expect("Failed to deserilize ToolInputSchema"),
             },
             Tool {
-                name: "send_eth".to_string(),
-                description: "Send ETH from one account to another".to_string(),
+                name: "disconnect_wallet".to_string(),
+                description: "Disconnect a paired wallet and forget its session".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "name": {
+                            "type": "string",
+                            "description": "Name of the paired wallet"
+                        }
+                    },
+                    "required": ["name"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "add_top_up_rule".to_string(),
+                description: "Keep an account's ETH balance above a minimum by topping it up from one of the user's accounts as new blocks arrive. Top-ups then run without asking, so confirm the accounts, balances and caps with the user first.".to_string(),
                 input_schema: from_value(json!({
                     "type": "object",
                     "properties": {
+                        "account": {
+                            "type": "string",
+                            "description": "Account name, address or ENS name to keep funded"
+                        },
                         "from": {
                             "type": "string",
-                            "description": "The sender's address or named account (alice, bob)"
+                            "description": "Account name to send the top-ups from"
                         },
-                        "to": {
+                        "min_balance": {
+                            "type": "string",
+                            "description": "Top up when the balance drops below this many ETH"
+                        },
+                        "target_balance": {
+                            "type": "string",
+                            "description": "Balance in ETH to top up to (default twice min_balance)"
+                        },
+                        "max_per_top_up": {
+                            "type": "string",
+                            "description": "Most ETH a single top-up may send (default target_balance)"
+                        },
+                        "max_per_day": {
+                            "type": "string",
+                            "description": "Most ETH the rule may send in a day"
+                        }
+                    },
+                    "required": ["account", "from", "min_balance", "max_per_day"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "list_top_up_rules".to_string(),
+                description: "List top-up rules with their recent top-ups and the last error, if any".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {}
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "remove_top_up_rule".to_string(),
+                description: "Stop a top-up rule".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "id": {
+                            "type": "string",
+                            "description": "Top-up rule id"
+                        }
+                    },
+                    "required": ["id"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "get_pool_info".to_string(),
+                description: "Look up the Uniswap V2 pair and V3 pools for two tokens: reserves, spot price, fee tier and how much can be traded before the price moves 2%. Use it for questions about a pool's price or liquidity.".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "token": {
+                            "type": "string",
+                            "description": "Token symbol or address (ETH uses WETH)"
+                        },
+                        "quote": {
+                            "type": "string",
+                            "description": "Token to price it in (default USDC)"
+                        }
+                    },
+                    "required": ["token"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "consolidate_dust".to_string(),
+                description: "Find an account's token balances worth less than a USD threshold and swap them all into one asset. Call without confirm to get the quoted plan, show it to the user, then call again with confirm set to true once they agree.".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "account": {
+                            "type": "string",
+                            "description": "Account name whose dust to consolidate"
+                        },
+                        "target": {
+                            "type": "string",
+                            "description": "Asset to swap the dust into (default ETH)"
+                        },
+                        "threshold_usd": {
+                            "type": "number",
+                            "description": "Balances worth less than this many USD count as dust (default 10)"
+                        },
+                        "discover_tokens": {
+                            "type": "boolean",
+                            "description": "Also look for tokens the account received recently that are not in the registry"
+                        },
+                        "confirm": {
+                            "type": "boolean",
+                            "description": "Send the swaps; only after the user has agreed to the plan"
+                        }
+                    },
+                    "required": ["account"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "add_liquidity".to_string(),
+                description: "Provide liquidity to a Uniswap V2 pair from one of the user's accounts, e.g. 1 ETH and the matching amount of USDC. Omit amount_b to deposit the amount matching the pair's current price. Confirm the amounts with the user first.".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "from": {
+                            "type": "string",
+                            "description": "Account name to deposit from"
+                        },
+                        "token_a": {
+                            "type": "string",
+                            "description": "First token symbol or address, or ETH"
+                        },
+                        "amount_a": {
+                            "type": "string",
+                            "description": "Amount of token_a to deposit"
+                        },
+                        "token_b": {
+                            "type": "string",
+                            "description": "Second token symbol or address, or ETH"
+                        },
+                        "amount_b": {
+                            "type": "string",
+                            "description": "Amount of token_b to deposit; omit to match the pair price. Required for a new pair"
+                        },
+                        "slippage": {
+                            "type": "number",
+                            "description": "Slippage tolerance in percent (default 0.5)"
+                        }
+                    },
+                    "required": ["from", "token_a", "amount_a", "token_b"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "remove_liquidity".to_string(),
+                description: "Withdraw an account's liquidity from a Uniswap V2 pair back into both tokens. Confirm the pair and share with the user first.".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "from": {
+                            "type": "string",
+                            "description": "Account name holding the LP tokens"
+                        },
+                        "token_a": {
+                            "type": "string",
+                            "description": "First token symbol or address, or ETH"
+                        },
+                        "token_b": {
+                            "type": "string",
+                            "description": "Second token symbol or address, or ETH"
+                        },
+                        "percent": {
+                            "type": "number",
+                            "description": "Percentage of the position to withdraw (default 100)"
+                        },
+                        "slippage": {
+                            "type": "number",
+                            "description": "Slippage tolerance in percent (default 0.5)"
+                        }
+                    },
+                    "required": ["from", "token_a", "token_b"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "list_token_migrations".to_string(),
+                description: "List the configured migrations from old token contracts to their replacements on the current network, with an address's balances of the old tokens".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "address": {
+                            "type": "string",
+                            "description": "Account name, address or ENS name whose old-token balances to include"
+                        }
+                    }
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "migrate_token".to_string(),
+                description: "Migrate an old (rebranded or upgraded) token into its replacement through its migration contract. Call without confirm to get the approval and migration steps, show them to the user, then call again with confirm set to true once they agree.".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "from": {
+                            "type": "string",
+                            "description": "Account name holding the old token"
+                        },
+                        "token": {
                             "type": "string",
-                            "description": "The recipient's address or named account (alice, bob)"
+                            "description": "Migration name, or the old token symbol or address"
                         },
                         "amount": {
                             "type": "string",
-                            "description": "The amount of ETH to send (e.g., '1.0')"
+                            "description": "Amount of the old token to migrate (default the whole balance)"
+                        },
+                        "confirm": {
+                            "type": "boolean",
+                            "description": "Send the transactions; only after the user has agreed to the steps"
                         }
                     },
-                    "required": ["from", "to", "amount"]
+                    "required": ["from", "token"]
                 })).expect("Failed to deserilize ToolInputSchema"),
             },
             Tool {
-                name: "check_contract".to_string(),
-                description: "Check if a contract is deployed at a specific address".to_string(),
+                name: "get_lending_position".to_string(),
+                description: "Show an address's Aave V3 lending position: collateral, debt, available borrows, health factor and each supplied or borrowed asset with its rate".to_string(),
                 input_schema: from_value(json!({
                     "type": "object",
                     "properties": {
                         "address": {
                             "type": "string",
-                            "description": "The contract address or name (e.g., 'uniswap_v2_router')"
+                            "description": "Account name, address or ENS name"
                         }
                     },
                     "required": ["address"]
                 })).expect("Failed to deserilize ToolInputSchema"),
             },
             Tool {
-                name: "search_web".to_string(),
-                description: "Search the web for information".to_string(),
+                name: "aave_supply".to_string(),
+                description: "Supply a token from a named account to the Aave V3 pool to earn interest and use it as collateral. Confirm the token and amount with the user first.".to_string(),
                 input_schema: from_value(json!({
                     "type": "object",
                     "properties": {
-                        "query": {
+                        "from": {
                             "type": "string",
-                            "description": "The search query"
+                            "description": "Account name supplying the token"
+                        },
+                        "token": {
+                            "type": "string",
+                            "description": "Token symbol or address (WETH rather than ETH)"
+                        },
+                        "amount": {
+                            "type": "string",
+                            "description": "Amount to supply"
                         }
                     },
-                    "required": ["query"]
+                    "required": ["from", "token", "amount"]
                 })).expect("Failed to deserilize ToolInputSchema"),
             },
             Tool {
-                name: "get_token_price".to_string(),
-                description: "Get the current price of a token".to_string(),
+                name: "aave_withdraw".to_string(),
+                description: "Withdraw a supplied token from the Aave V3 pool. Check the health factor with get_lending_position and confirm with the user first.".to_string(),
                 input_schema: from_value(json!({
                     "type": "object",
                     "properties": {
+                        "from": {
+                            "type": "string",
+                            "description": "Account name that supplied the token"
+                        },
                         "token": {
                             "type": "string",
-                            "description": "The token address or symbol"
+                            "description": "Token symbol or address"
+                        },
+                        "amount": {
+                            "type": "string",
+                            "description": "Amount to withdraw, or max for everything (default max)"
                         }
                     },
-                    "required": ["token"]
+                    "required": ["from", "token"]
                 })).expect("Failed to deserilize ToolInputSchema"),
             },
             Tool {
-                name: "swap_tokens".to_string(),
-                description: "Swap tokens using Uniswap".to_string(),
+                name: "aave_borrow".to_string(),
+                description: "Borrow a token at the variable rate against an account's Aave V3 collateral. Check the health factor with get_lending_position and confirm with the user first.".to_string(),
                 input_schema: from_value(json!({
                     "type": "object",
                     "properties": {
-                        "from_token": {
+                        "from": {
                             "type": "string",
-                            "description": "The address or symbol of the token to swap from"
+                            "description": "Account name borrowing"
                         },
-                        "to_token": {
+                        "token": {
                             "type": "string",
-                            "description": "The address or symbol of the token to swap to"
+                            "description": "Token symbol or address to borrow"
                         },
                         "amount": {
                             "type": "string",
-                            "description": "The amount to swap"
+                            "description": "Amount to borrow"
+                        }
+                    },
+                    "required": ["from", "token", "amount"]
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "aave_repay".to_string(),
+                description: "Repay an account's variable rate Aave V3 debt in a token. Confirm the amount with the user first.".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "from": {
+                            "type": "string",
+                            "description": "Account name repaying"
                         },
-                        "recipient": {
+                        "token": {
                             "type": "string",
-                            "description": "The recipient address or named account"
+                            "description": "Token symbol or address of the debt"
+                        },
+                        "amount": {
+                            "type": "string",
+                            "description": "Amount to repay, or max for the whole debt (default max)"
                         }
                     },
-                    "required": ["from_token", "to_token", "amount", "recipient"]
+                    "required": ["from", "token"]
                 })).expect("Failed to deserilize ToolInputSchema"),
             },
             Tool {
-                name: "search_docs".to_string(),
-                description: "Search the documentation for information about blockchain protocols and smart contracts".to_string(),
+                name: "refresh_token_list".to_string(),
+                description: "Reload the current network's token list (TOKENS_FILE or <NETWORK>_TOKENS_FILE) so newly listed tokens can be used by symbol. The file is also reloaded automatically when it changes".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {},
+                    "required": []
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "forget_discovered_tokens".to_string(),
+                description: "Forget the cached metadata of a token discovered on-chain (or of all of them when token is omitted) so it is read from the contract again".to_string(),
                 input_schema: from_value(json!({
                     "type": "object",
                     "properties": {
-                        "query": {
+                        "token": {
                             "type": "string",
-                            "description": "The search query"
+                            "description": "Address of the discovered token; omit to forget every discovered token"
+                        }
+                    },
+                    "required": []
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "index_status".to_string(),
+                description: "Size of the documentation index (documents, chunks, terms) and the throughput of the last ingestion".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {},
+                    "required": []
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "get_chain_info".to_string(),
+                description: "Get the current network's chain ID, latest block number and timestamp, base fee and the RPC node's client version".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {},
+                    "required": []
+                })).expect("Failed to deserilize ToolInputSchema"),
+            },
+            Tool {
+                name: "get_block".to_string(),
+                description: "Get a block by number, hash or tag (latest, finalized, safe, pending) with its gas, base fee and a summary of its transactions".to_string(),
+                input_schema: from_value(json!({
+                    "type": "object",
+                    "properties": {
+                        "block": {
+                            "type": "string",
+                            "description": "Block number, block hash, or a tag such as latest (default latest)"
                         },
                         "limit": {
                             "type": "integer",
-                            "description": "The maximum number of results to return (default: 5)"
-                        },
-                        "source": {
-                            "type": "string",
-                            "description": "Optional source to filter results (e.g., 'uniswap-v2', 'contracts')"
+                            "description": "Most transactions to list (default 25)"
                         }
                     },
-                    "required": ["query"]
+                    "required": []
                 })).expect("Failed to deserilize ToolInputSchema"),
             },
             Tool {
-                name: "get_document".to_string(),
-                description: "Get a specific document by ID".to_string(),
+                name: "get_contract_source".to_string(),
+                description: "Get a contract's verified name, compiler, functions and events from Etherscan, following proxies to their implementation. Once fetched, call_contract and write_contract accept the contract's bare function names.".to_string(),
                 input_schema: from_value(json!({
                     "type": "object",
                     "properties": {
-                        "id": {
+                        "contract": {
                             "type": "string",
-                            "description": "The document ID"
+                            "description": "Contract address, ENS name or token symbol"
+                        },
+                        "include_source": {
+                            "type": "boolean",
+                            "description": "Include the full Solidity source (large; default false)"
                         }
                     },
-                    "required": ["id"]
+                    "required": ["contract"]
                 })).expect("Failed to deserilize ToolInputSchema"),
             },
         ];
@@ -219,7 +2871,17 @@ impl BlockchainAgent {
                         input: input.clone(),
                     };
 
-                    let tool_result = self.execute_tool(tool_use).await?;
+                    // A failed call (e.g. a write cut off by a server restart) is reported
+                    // rather than aborting the whole turn
+                    let tool_use_id = tool_use.id.clone();
+                    let tool_result = match self.execute_tool(tool_use).await {
+                        Ok(tool_result) => tool_result,
+                        Err(e) => ToolResult {
+                            tool_use_id,
+                            is_error: Some(true),
+                            content: ToolResultContent::Text(e.to_string()),
+                        },
+                    };
 
                     match &tool_result.content {
                         ToolResultContent::Text(text) => {
@@ -262,6 +2924,121 @@ impl BlockchainAgent {
             "swap_tokens" => self.mcp_client.swap_tokens(input).await?,
             "search_docs" => self.mcp_client.search_docs(input).await?,
             "get_document" => self.mcp_client.get_document(input).await?,
+            "check_token_safety" => self.mcp_client.check_token_safety(input).await?,
+            "preview_approval" => self.mcp_client.preview_approval(input).await?,
+            "summarize_activity" => self.mcp_client.summarize_activity(input).await?,
+            "list_networks" => self.mcp_client.list_networks(input).await?,
+            "switch_network" => self.mcp_client.switch_network(input).await?,
+            "send_erc20" => self.mcp_client.send_erc20(input).await?,
+            "get_pnl" => self.mcp_client.get_pnl(input).await?,
+            "estimate_gas" => self.mcp_client.estimate_gas(input).await?,
+            "get_gas_report" => self.mcp_client.get_gas_report(input).await?,
+            "get_balance_history" => self.mcp_client.get_balance_history(input).await?,
+            "get_nft_balance" => self.mcp_client.get_nft_balance(input).await?,
+            "owner_of" => self.mcp_client.owner_of(input).await?,
+            "transfer_nft" => self.mcp_client.transfer_nft(input).await?,
+            "get_historical_balance" => self.mcp_client.get_historical_balance(input).await?,
+            "get_historical_price" => self.mcp_client.get_historical_price(input).await?,
+            "estimate_swap" => self.mcp_client.estimate_swap(input).await?,
+            "describe_capabilities" => self.mcp_client.describe_capabilities(input).await?,
+            "get_allowance" => self.mcp_client.get_allowance(input).await?,
+            "set_variable" => self.mcp_client.set_variable(input).await?,
+            "unset_variable" => self.mcp_client.unset_variable(input).await?,
+            "list_variables" => self.mcp_client.list_variables(input).await?,
+            "save_template" => self.mcp_client.save_template(input).await?,
+            "run_template" => self.mcp_client.run_template(input).await?,
+            "list_templates" => self.mcp_client.list_templates(input).await?,
+            "delete_template" => self.mcp_client.delete_template(input).await?,
+            "schedule_job" => self.mcp_client.schedule_job(input).await?,
+            "list_jobs" => self.mcp_client.list_jobs(input).await?,
+            "cancel_job" => self.mcp_client.cancel_job(input).await?,
+            "get_notifications" => self.mcp_client.get_notifications(input).await?,
+            "get_portfolio" => self.mcp_client.get_portfolio(input).await?,
+            "get_transaction_history" => self.mcp_client.get_transaction_history(input).await?,
+            "get_events" => self.mcp_client.get_events(input).await?,
+            "set_display_preferences" => self.mcp_client.set_display_preferences(input).await?,
+            "get_display_preferences" => self.mcp_client.get_display_preferences(input).await?,
+            "deploy_contract" => self.mcp_client.deploy_contract(input).await?,
+            "convert_units" => self.mcp_client.convert_units(input).await?,
+            "call_contract" => self.mcp_client.call_contract(input).await?,
+            "broadcast_raw_transaction" => self.mcp_client.broadcast_raw_transaction(input).await?,
+            "write_contract" => self.mcp_client.write_contract(input).await?,
+            "prepare_unsigned_transaction" => {
+                self.mcp_client.prepare_unsigned_transaction(input).await?
+            }
+            "attach_signature" => self.mcp_client.attach_signature(input).await?,
+            "decode_calldata" => self.mcp_client.decode_calldata(input).await?,
+            "parse_payment_uri" => self.mcp_client.parse_payment_uri(input).await?,
+            "get_transaction" => self.mcp_client.get_transaction(input).await?,
+            "get_inbox" => self.mcp_client.get_inbox(input).await?,
+            "sign_message" => self.mcp_client.sign_message(input).await?,
+            "verify_signature" => self.mcp_client.verify_signature(input).await?,
+            "get_twap" => self.mcp_client.get_twap(input).await?,
+            "approve_token" => self.mcp_client.approve_token(input).await?,
+            "revoke_approval" => self.mcp_client.revoke_approval(input).await?,
+            "send_batch" => self.mcp_client.send_batch(input).await?,
+            "get_stablecoin_status" => self.mcp_client.get_stablecoin_status(input).await?,
+            "get_gas_price" => self.mcp_client.get_gas_price(input).await?,
+            "get_lp_performance" => self.mcp_client.get_lp_performance(input).await?,
+            "find_yield" => self.mcp_client.find_yield(input).await?,
+            "get_protocol_health" => self.mcp_client.get_protocol_health(input).await?,
+            "snapshot_chain" => self.mcp_client.snapshot_chain(input).await?,
+            "revert_chain" => self.mcp_client.revert_chain(input).await?,
+            "list_proposals" => self.mcp_client.list_proposals(input).await?,
+            "get_proposal" => self.mcp_client.get_proposal(input).await?,
+            "get_voting_power" => self.mcp_client.get_voting_power(input).await?,
+            "cast_vote" => self.mcp_client.cast_vote(input).await?,
+            "increase_time" => self.mcp_client.increase_time(input).await?,
+            "mine_blocks" => self.mcp_client.mine_blocks(input).await?,
+            "set_next_block_timestamp" => self.mcp_client.set_next_block_timestamp(input).await?,
+            "list_snapshot_spaces" => self.mcp_client.list_snapshot_spaces(input).await?,
+            "list_snapshot_proposals" => self.mcp_client.list_snapshot_proposals(input).await?,
+            "get_snapshot_proposal" => self.mcp_client.get_snapshot_proposal(input).await?,
+            "get_snapshot_voting_power" => self.mcp_client.get_snapshot_voting_power(input).await?,
+            "cast_snapshot_vote" => self.mcp_client.cast_snapshot_vote(input).await?,
+            "check_claims" => self.mcp_client.check_claims(input).await?,
+            "claim_airdrop" => self.mcp_client.claim_airdrop(input).await?,
+            "fund_account" => self.mcp_client.fund_account(input).await?,
+            "get_vesting" => self.mcp_client.get_vesting(input).await?,
+            "release_vested" => self.mcp_client.release_vested(input).await?,
+            "create_stream" => self.mcp_client.create_stream(input).await?,
+            "cancel_stream" => self.mcp_client.cancel_stream(input).await?,
+            "get_streams" => self.mcp_client.get_streams(input).await?,
+            "deploy_token" => self.mcp_client.deploy_token(input).await?,
+            "compute_address" => self.mcp_client.compute_address(input).await?,
+            "get_smart_account" => self.mcp_client.get_smart_account(input).await?,
+            "deploy_smart_account" => self.mcp_client.deploy_smart_account(input).await?,
+            "estimate_user_operation" => self.mcp_client.estimate_user_operation(input).await?,
+            "send_user_operation" => self.mcp_client.send_user_operation(input).await?,
+            "get_tx_status" => self.mcp_client.get_tx_status(input).await?,
+            "grant_session_key" => self.mcp_client.grant_session_key(input).await?,
+            "list_session_keys" => self.mcp_client.list_session_keys(input).await?,
+            "revoke_session_key" => self.mcp_client.revoke_session_key(input).await?,
+            "list_pending_transactions" => self.mcp_client.list_pending_transactions(input).await?,
+            "pair_wallet" => self.mcp_client.pair_wallet(input).await?,
+            "list_wallets" => self.mcp_client.list_wallets(input).await?,
+            "send_with_wallet" => self.mcp_client.send_with_wallet(input).await?,
+            "disconnect_wallet" => self.mcp_client.disconnect_wallet(input).await?,
+            "add_top_up_rule" => self.mcp_client.add_top_up_rule(input).await?,
+            "list_top_up_rules" => self.mcp_client.list_top_up_rules(input).await?,
+            "remove_top_up_rule" => self.mcp_client.remove_top_up_rule(input).await?,
+            "get_pool_info" => self.mcp_client.get_pool_info(input).await?,
+            "consolidate_dust" => self.mcp_client.consolidate_dust(input).await?,
+            "add_liquidity" => self.mcp_client.add_liquidity(input).await?,
+            "remove_liquidity" => self.mcp_client.remove_liquidity(input).await?,
+            "list_token_migrations" => self.mcp_client.list_token_migrations(input).await?,
+            "migrate_token" => self.mcp_client.migrate_token(input).await?,
+            "get_lending_position" => self.mcp_client.get_lending_position(input).await?,
+            "aave_supply" => self.mcp_client.aave_supply(input).await?,
+            "aave_withdraw" => self.mcp_client.aave_withdraw(input).await?,
+            "aave_borrow" => self.mcp_client.aave_borrow(input).await?,
+            "aave_repay" => self.mcp_client.aave_repay(input).await?,
+            "refresh_token_list" => self.mcp_client.refresh_token_list(input).await?,
+            "forget_discovered_tokens" => self.mcp_client.forget_discovered_tokens(input).await?,
+            "index_status" => self.mcp_client.index_status(input).await?,
+            "get_chain_info" => self.mcp_client.get_chain_info(input).await?,
+            "get_block" => self.mcp_client.get_block(input).await?,
+            "get_contract_source" => self.mcp_client.get_contract_source(input).await?,
             _ => {
                 return Err(anyhow::anyhow!("Unknown tool: {}", tool_use.name));
             }
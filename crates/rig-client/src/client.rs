@@ -19,6 +19,13 @@ impl RIGClient {
         Ok(Self { agent, repl })
     }
 
+    pub fn with_default_account(mut self, account: Option<&str>) -> Self {
+        if let Some(account) = account {
+            self.agent = self.agent.with_default_account(account);
+        }
+        self
+    }
+
     pub async fn run(&mut self) -> Result<()> {
         info!("Starting RIG Blockchain Client REPL");
         info!("Type 'help' for available commands");
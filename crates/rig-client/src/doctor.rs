@@ -0,0 +1,124 @@
+use anyhow::{Result, anyhow};
+use colored::*;
+
+use crate::init::{check_api_key, check_rpc, check_server};
+use crate::mcp_client::MCPClient;
+
+/// Diagnose the client's configuration and the server's environment, printing a fix
+/// for every problem. Fails when any check fails.
+pub async fn run_doctor(mcp_server: &str, api_key: Option<&str>) -> Result<()> {
+    println!("{}", "Checking your setup".green().bold());
+    println!();
+
+    let mut failures = 0;
+    println!("{}", "Client".yellow().bold());
+
+    match std::env::var("ETH_RPC_URL") {
+        Ok(url) => {
+            failures += report(
+                "rpc",
+                check_rpc(&url).await,
+                "Check ETH_RPC_URL and that the node (e.g. Anvil) is running",
+            )
+        }
+        Err(_) => print_check(
+            "warn",
+            "rpc",
+            "ETH_RPC_URL not set; the server uses its own default",
+            Some("Run `rig-client init` or export ETH_RPC_URL"),
+        ),
+    }
+
+    match api_key {
+        Some(key) => {
+            failures += report(
+                "anthropic_api_key",
+                check_api_key(key).await,
+                "Set a valid ANTHROPIC_API_KEY (see https://console.anthropic.com)",
+            )
+        }
+        None => {
+            failures += 1;
+            print_check(
+                "fail",
+                "anthropic_api_key",
+                "ANTHROPIC_API_KEY not set",
+                Some("Run `rig-client init` or export ANTHROPIC_API_KEY"),
+            );
+        }
+    }
+
+    let server_check = check_server(mcp_server).await;
+    let server_up = server_check.is_ok();
+    failures += report(
+        "mcp_server",
+        server_check,
+        "Start the server with `cargo run -p mcp-server` or pass --mcp-server",
+    );
+
+    if server_up {
+        println!();
+        println!("{}", "Server".yellow().bold());
+        match MCPClient::new(mcp_server)?.self_check().await {
+            Ok(report) => {
+                for check in report["checks"].as_array().into_iter().flatten() {
+                    let status = check["status"].as_str().unwrap_or("fail");
+                    if status == "fail" {
+                        failures += 1;
+                    }
+                    print_check(
+                        status,
+                        check["name"].as_str().unwrap_or("?"),
+                        check["detail"].as_str().unwrap_or(""),
+                        check["fix"].as_str(),
+                    );
+                }
+            }
+            Err(e) => {
+                failures += 1;
+                print_check(
+                    "fail",
+                    "self_check",
+                    &format!("Server self check failed: {}", e),
+                    Some("Update the server; older versions don't support self_check"),
+                );
+            }
+        }
+    }
+
+    println!();
+    if failures == 0 {
+        println!("{}", "Everything looks good".green().bold());
+        Ok(())
+    } else {
+        Err(anyhow!("{} check(s) failed", failures))
+    }
+}
+
+// Print a client-side check and return 1 when it failed
+fn report(name: &str, result: Result<String>, fix: &str) -> usize {
+    match result {
+        Ok(detail) => {
+            print_check("ok", name, &detail, None);
+            0
+        }
+        Err(e) => {
+            print_check("fail", name, &e.to_string(), Some(fix));
+            1
+        }
+    }
+}
+
+fn print_check(status: &str, name: &str, detail: &str, fix: Option<&str>) {
+    let marker = match status {
+        "ok" => "✓".green(),
+        "warn" => "!".yellow(),
+        _ => "✗".red(),
+    };
+    println!("  {} {:<24} {}", marker, name, detail);
+    if status != "ok"
+        && let Some(fix) = fix
+    {
+        println!("    {} {}", "fix:".cyan(), fix);
+    }
+}
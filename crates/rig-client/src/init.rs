@@ -0,0 +1,235 @@
+use anthropic_sdk::{Anthropic, MessageContent, MessageCreateBuilder, MessageParam, Role};
+use anyhow::{Result, anyhow};
+use colored::*;
+use rustyline::DefaultEditor;
+use serde_json::{Value, json};
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+use tokio::net::TcpStream;
+
+use crate::mcp_client::MCPClient;
+
+// Model used for the key check; matches the one the agent talks to
+const VALIDATION_MODEL: &str = "claude-sonnet-4-20250514";
+
+/// Settings collected by the wizard, stored as environment variables
+pub struct InitConfig {
+    pub rpc_url: String,
+    pub api_key: String,
+    pub mcp_server: String,
+    pub default_account: String,
+}
+
+impl InitConfig {
+    fn entries(&self) -> [(&'static str, &str); 4] {
+        [
+            ("ETH_RPC_URL", &self.rpc_url),
+            ("ANTHROPIC_API_KEY", &self.api_key),
+            ("MCP_SERVER", &self.mcp_server),
+            ("DEFAULT_ACCOUNT", &self.default_account),
+        ]
+    }
+}
+
+/// Interactive first-run setup: prompts for each setting, validates it and writes `path`
+pub async fn run_init(path: &Path) -> Result<()> {
+    let mut editor = DefaultEditor::new()?;
+    println!("{}", "Blockchain AI Agent setup".green().bold());
+    println!(
+        "{}",
+        format!("Answers are saved to {}", path.display()).cyan()
+    );
+    println!();
+
+    let env = |key: &str, default: &str| std::env::var(key).unwrap_or_else(|_| default.to_string());
+
+    let rpc_url = prompt_until_valid(
+        &mut editor,
+        "Ethereum RPC URL",
+        &env("ETH_RPC_URL", "http://localhost:8545"),
+        |url| async move { check_rpc(&url).await },
+    )
+    .await?;
+
+    let api_key = prompt_until_valid(
+        &mut editor,
+        "Anthropic API key",
+        &env("ANTHROPIC_API_KEY", ""),
+        |key| async move { check_api_key(&key).await },
+    )
+    .await?;
+
+    let mcp_server = prompt_until_valid(
+        &mut editor,
+        "MCP server address",
+        &env("MCP_SERVER", "127.0.0.1:3000"),
+        |addr| async move { check_server(&addr).await },
+    )
+    .await?;
+
+    let default_account = prompt_until_valid(
+        &mut editor,
+        "Default account (name or address)",
+        &env("DEFAULT_ACCOUNT", "alice"),
+        |account| {
+            let mcp_server = mcp_server.clone();
+            async move { check_account(&account, &mcp_server).await }
+        },
+    )
+    .await?;
+
+    let config = InitConfig {
+        rpc_url,
+        api_key,
+        mcp_server,
+        default_account,
+    };
+    write_env_file(path, &config)?;
+
+    println!();
+    println!(
+        "{} {}",
+        "Saved configuration to".green(),
+        path.display().to_string().green().bold()
+    );
+    println!("Start the MCP server, then run `rig-client` to open the REPL.");
+    Ok(())
+}
+
+/// Ask for a value until `check` accepts it, or the user chooses to keep it anyway
+async fn prompt_until_valid<F, Fut>(
+    editor: &mut DefaultEditor,
+    label: &str,
+    default: &str,
+    check: F,
+) -> Result<String>
+where
+    F: Fn(String) -> Fut,
+    Fut: Future<Output = Result<String>>,
+{
+    loop {
+        let prompt = if default.is_empty() {
+            format!("{}: ", label)
+        } else {
+            format!("{} [{}]: ", label, default)
+        };
+        let input = editor.readline(&prompt)?;
+        let value = match input.trim() {
+            "" => default.to_string(),
+            value => value.to_string(),
+        };
+        if value.is_empty() {
+            println!("  {}", "A value is required".red());
+            continue;
+        }
+
+        match check(value.clone()).await {
+            Ok(message) => {
+                println!("  {} {}", "✓".green(), message);
+                return Ok(value);
+            }
+            Err(e) => {
+                println!("  {} {}", "✗".red(), e);
+                let keep = editor.readline("  Keep this value anyway? [y/N]: ")?;
+                if keep.trim().eq_ignore_ascii_case("y") {
+                    return Ok(value);
+                }
+            }
+        }
+    }
+}
+
+pub(crate) async fn check_rpc(url: &str) -> Result<String> {
+    let response: Value = reqwest::Client::new()
+        .post(url)
+        .timeout(Duration::from_secs(10))
+        .json(&json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "eth_chainId",
+            "params": []
+        }))
+        .send()
+        .await
+        .map_err(|e| anyhow!("RPC unreachable: {}. Is Anvil running?", e))?
+        .json()
+        .await?;
+
+    let chain_id = response["result"]
+        .as_str()
+        .and_then(|hex| u64::from_str_radix(hex.trim_start_matches("0x"), 16).ok())
+        .ok_or_else(|| anyhow!("Unexpected eth_chainId response: {}", response))?;
+    Ok(format!("Connected to chain {}", chain_id))
+}
+
+pub(crate) async fn check_api_key(key: &str) -> Result<String> {
+    let client = Anthropic::new(key)?;
+    let mut params = MessageCreateBuilder::new(VALIDATION_MODEL, 1).build();
+    params.messages = vec![MessageParam {
+        role: Role::User,
+        content: MessageContent::Text("ping".to_string()),
+    }];
+
+    client
+        .messages()
+        .create(params)
+        .await
+        .map_err(|e| anyhow!("Test request failed: {}", e))?;
+    Ok("API key accepted".to_string())
+}
+
+pub(crate) async fn check_server(addr: &str) -> Result<String> {
+    tokio::time::timeout(Duration::from_secs(5), TcpStream::connect(addr))
+        .await
+        .map_err(|_| anyhow!("Timed out connecting to {}", addr))?
+        .map_err(|e| anyhow!("Cannot reach the MCP server at {}: {}", addr, e))?;
+    Ok(format!("MCP server reachable at {}", addr))
+}
+
+async fn check_account(account: &str, mcp_server: &str) -> Result<String> {
+    let known = shared::get_test_accounts();
+    let is_named = known.contains_key(&account.to_lowercase());
+    if !is_named
+        && !shared::utils::is_ens_name(account)
+        && shared::utils::parse_address(account).is_err()
+    {
+        return Err(anyhow!(
+            "{} is not a known account ({}), an address or an ENS name",
+            account,
+            known.keys().cloned().collect::<Vec<_>>().join(", ")
+        ));
+    }
+
+    // Confirm the server can resolve it when it is running
+    let client = MCPClient::new(mcp_server)?;
+    match client.get_balance(json!({ "address": account })).await {
+        Ok(balance) => Ok(format!(
+            "{} holds {} ETH",
+            account,
+            balance["balance"].as_str().unwrap_or("?")
+        )),
+        Err(_) => Ok(format!("{} looks valid (server not checked)", account)),
+    }
+}
+
+/// Set the config's keys in an env file, keeping any other variables already in it
+fn write_env_file(path: &Path, config: &InitConfig) -> Result<()> {
+    let existing = fs::read_to_string(path).unwrap_or_default();
+    let entries = config.entries();
+
+    let mut lines: Vec<String> = existing
+        .lines()
+        .filter(|line| {
+            let key = line.split('=').next().unwrap_or("").trim();
+            !entries.iter().any(|(name, _)| *name == key)
+        })
+        .map(|line| line.to_string())
+        .collect();
+    for (key, value) in entries {
+        lines.push(format!("{}={}", key, value));
+    }
+
+    fs::write(path, lines.join("\n") + "\n")?;
+    Ok(())
+}
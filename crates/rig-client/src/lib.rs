@@ -1,5 +1,7 @@
 pub mod agent;
 pub mod client;
 pub mod commands;
+pub mod doctor;
+pub mod init;
 pub mod mcp_client;
 pub mod repl;
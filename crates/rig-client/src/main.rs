@@ -1,32 +1,67 @@
 use anyhow::Result;
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use dotenv::dotenv;
 use rig_client::client::RIGClient;
+use rig_client::{doctor, init};
+use std::path::PathBuf;
 use tracing::{Level, info};
 use tracing_subscriber;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 struct Args {
-    #[arg(short, long, default_value = "127.0.0.1:3000")]
+    #[command(subcommand)]
+    command: Option<CliCommand>,
+
+    #[arg(short, long, env = "MCP_SERVER", default_value = "127.0.0.1:3000")]
     mcp_server: String,
 
     #[arg(short, long, env = "ANTHROPIC_API_KEY")]
-    api_key: String,
+    api_key: Option<String>,
+
+    #[arg(long, env = "DEFAULT_ACCOUNT")]
+    default_account: Option<String>,
+}
+
+#[derive(Subcommand)]
+enum CliCommand {
+    /// Interactive first-run setup that validates and saves your configuration
+    Init {
+        #[arg(long, default_value = ".env")]
+        path: PathBuf,
+    },
+    /// Diagnose the RPC, API key, server and data setup and suggest fixes
+    Doctor,
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     dotenv().ok();
+
+    let args = Args::parse();
+
+    match &args.command {
+        Some(CliCommand::Init { path }) => return init::run_init(path).await,
+        Some(CliCommand::Doctor) => {
+            return doctor::run_doctor(&args.mcp_server, args.api_key.as_deref()).await;
+        }
+        None => {}
+    }
+
     // Initialize tracing
     tracing_subscriber::fmt().with_max_level(Level::INFO).init();
 
-    let args = Args::parse();
+    let api_key = args.api_key.ok_or_else(|| {
+        anyhow::anyhow!(
+            "ANTHROPIC_API_KEY is not set. Run `rig-client init` to configure the client"
+        )
+    })?;
 
     info!("Starting RIG Blockchain Client");
     info!("MCP Server: {}", args.mcp_server);
 
-    let mut client = RIGClient::new(&args.mcp_server, &args.api_key)?;
+    let mut client = RIGClient::new(&args.mcp_server, &api_key)?
+        .with_default_account(args.default_account.as_deref());
     client.run().await?;
 
     Ok(())
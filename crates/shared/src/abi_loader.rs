@@ -1,52 +1,170 @@
-use anyhow::Result;
+use anyhow::{Result, anyhow};
 use ethers::abi::Abi;
-use serde_json;
+use ethers::types::Bytes;
+use serde_json::{self, Value};
 use std::fs;
 use std::path::Path;
+use std::str::FromStr;
 use tracing::{info, warn};
 
+/// A contract interface an ABI can be checked against
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AbiInterface {
+  Erc20,
+  Erc721,
+  UniswapV2Router,
+  UniswapV3Router,
+}
+
+impl AbiInterface {
+  pub fn name(&self) -> &'static str {
+      match self {
+          AbiInterface::Erc20 => "ERC20",
+          AbiInterface::Erc721 => "ERC721",
+          AbiInterface::UniswapV2Router => "Uniswap V2 router",
+          AbiInterface::UniswapV3Router => "Uniswap V3 router",
+      }
+  }
+
+  /// Functions the server calls through an ABI of this interface
+  pub fn required_functions(&self) -> &'static [&'static str] {
+      match self {
+          AbiInterface::Erc20 => &[
+              "balanceOf",
+              "totalSupply",
+              "transfer",
+              "transferFrom",
+              "approve",
+              "allowance",
+          ],
+          AbiInterface::Erc721 => &[
+              "balanceOf",
+              "ownerOf",
+              "transferFrom",
+              "safeTransferFrom",
+              "approve",
+          ],
+          AbiInterface::UniswapV2Router => &[
+              "WETH",
+              "factory",
+              "getAmountsOut",
+              "swapExactTokensForTokens",
+              "swapExactETHForTokens",
+              "swapExactTokensForETH",
+          ],
+          AbiInterface::UniswapV3Router => &["exactInputSingle", "exactInput"],
+      }
+  }
+}
+
+/// An ABI read from a bare ABI file or a Foundry/Hardhat artifact
+#[derive(Debug, Clone)]
+pub struct AbiArtifact {
+  /// Hardhat's contractName; Foundry artifacts and bare ABIs don't carry one
+  pub name: Option<String>,
+  pub abi: Abi,
+  /// Creation bytecode as hex; None for bare ABIs and artifacts without any
+  pub bytecode: Option<String>,
+}
+
+impl AbiArtifact {
+  /// Parse a bare ABI array, or an artifact object with the ABI under "abi". Foundry
+  /// nests the bytecode under "object", Hardhat stores it directly.
+  pub fn parse(content: &str) -> Result<Self> {
+      let value: Value = serde_json::from_str(content)?;
+      if value.is_array() {
+          return Ok(Self {
+              name: None,
+              abi: serde_json::from_value(value)?,
+              bytecode: None,
+          });
+      }
+
+      let abi: Abi = serde_json::from_value(value["abi"].clone())
+          .map_err(|e| anyhow!("no valid \"abi\" field: {}", e))?;
+      let bytecode = value["bytecode"]["object"]
+          .as_str()
+          .or_else(|| value["bytecode"].as_str())
+          .filter(|bytecode| !bytecode.is_empty() && *bytecode != "0x")
+          .map(|bytecode| bytecode.to_string());
+      Ok(Self {
+          name: value["contractName"].as_str().map(|name| name.to_string()),
+          abi,
+          bytecode,
+      })
+  }
+
+  /// The creation bytecode, ready to deploy
+  pub fn deploy_bytecode(&self) -> Result<Bytes> {
+      let bytecode = self.bytecode.as_deref().ok_or_else(|| {
+          anyhow!("no bytecode; interfaces and abstract contracts can't be deployed")
+      })?;
+      if bytecode.contains("__") {
+          return Err(anyhow!(
+              "references unlinked libraries; link them before deploying"
+          ));
+      }
+      Bytes::from_str(bytecode).map_err(|e| anyhow!("invalid bytecode: {}", e))
+  }
+}
+
 pub struct AbiLoader;
 
 impl AbiLoader {
+  /// Load a bare ABI file or a Foundry/Hardhat artifact
+  pub fn load_artifact<P: AsRef<Path>>(path: P) -> Result<AbiArtifact> {
+      let path_ref = path.as_ref();
+      let content = fs::read_to_string(path_ref)
+          .map_err(|e| anyhow!("Can't read {}: {}", path_ref.display(), e))?;
+      AbiArtifact::parse(&content).map_err(|e| anyhow!("{}: {}", path_ref.display(), e))
+  }
+
+  /// Load an ABI from a bare ABI file or an artifact, checking it has the functions of
+  /// `interface` when one is given
+  pub fn load_abi<P: AsRef<Path>>(path: P, interface: Option<AbiInterface>) -> Result<Abi> {
+      let path_ref = path.as_ref();
+      let abi = Self::load_artifact(path_ref)?.abi;
+
+      if let Some(interface) = interface {
+          Self::validate(&abi, interface).map_err(|e| anyhow!("{}: {}", path_ref.display(), e))?;
+      }
+      Ok(abi)
+  }
+
   /// Load ERC20 ABI from file with fallback
   pub fn load_erc20_abi<P: AsRef<Path>>(path: P) -> Result<Abi> {
       let path_ref = path.as_ref();
-      
+
       if path_ref.exists() {
           info!("Loading ERC20 ABI from file: {}", path_ref.display());
-          let content = fs::read_to_string(path_ref)?;
-          let abi: Abi = serde_json::from_str(&content)?;
-          
-          // Validate the ABI has required ERC20 functions
-          if Self::validate_erc20_abi(&abi) {
-              Ok(abi)
-          } else {
-              warn!("File does not contain a valid ERC20 ABI: {}", path_ref.display());
-              Err(anyhow::anyhow!("Invalid ERC20 ABI"))
+          let abi = Self::load_abi(path_ref, Some(AbiInterface::Erc20));
+          if let Err(e) = &abi {
+              warn!("File does not contain a valid ERC20 ABI: {}", e);
           }
+          abi
       } else {
           warn!("ERC20 ABI file not found: {}", path_ref.display());
           Err(anyhow::anyhow!("ERC20 ABI file not found"))
       }
   }
-  
-  /// Validate that an ABI contains the required ERC20 functions
-  fn validate_erc20_abi(abi: &Abi) -> bool {
-      let required_functions = [
-          "balanceOf",
-          "totalSupply",
-          "transfer",
-          "transferFrom",
-          "approve",
-          "allowance"
-      ];
-      
-      for func_name in &required_functions {
-          if abi.function(func_name).is_err() {
-              return false;
-          }
+
+  /// Check that an ABI has every function `interface` needs, naming any that are missing
+  pub fn validate(abi: &Abi, interface: AbiInterface) -> Result<()> {
+      let missing: Vec<&str> = interface
+          .required_functions()
+          .iter()
+          .filter(|name| abi.function(name).is_err())
+          .copied()
+          .collect();
+
+      if missing.is_empty() {
+          Ok(())
+      } else {
+          Err(anyhow!(
+              "not a valid {} ABI; missing {}",
+              interface.name(),
+              missing.join(", ")
+          ))
       }
-      
-      true
   }
-}
\ No newline at end of file
+}
@@ -0,0 +1,336 @@
+use anyhow::{Result, anyhow};
+use ethers::types::U256;
+use ethers::utils::{format_units, parse_units};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value, json};
+
+// Result fields holding a token or ETH amount as a decimal string
+const AMOUNT_FIELDS: &[&str] = &[
+    "amount",
+    "amount_in",
+    "amount_out",
+    "expected_amount_out",
+    "minimum_amount_out",
+    "balance",
+    "value",
+    "before",
+    "after",
+    "expected_cost_eth",
+    "max_cost_eth",
+];
+const MAX_DECIMALS: u32 = 18;
+
+/// Unit gas prices are shown in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GasUnit {
+    Wei,
+    #[default]
+    Gwei,
+    Ether,
+}
+
+impl GasUnit {
+    pub fn parse(name: &str) -> Result<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "wei" => Ok(Self::Wei),
+            "gwei" => Ok(Self::Gwei),
+            "ether" | "eth" => Ok(Self::Ether),
+            other => Err(anyhow!(
+                "Unknown gas unit '{}': use wei, gwei or ether",
+                other
+            )),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Wei => "wei",
+            Self::Gwei => "gwei",
+            Self::Ether => "ether",
+        }
+    }
+
+    // Suffix of result fields in this unit, e.g. base_fee_gwei -> base_fee_wei
+    fn field_suffix(&self) -> &'static str {
+        match self {
+            Self::Wei => "_wei",
+            Self::Gwei => "_gwei",
+            Self::Ether => "_eth",
+        }
+    }
+}
+
+/// How amounts are shown to a user: in the REPL, the desktop app and the tool results
+/// the agent reads. The defaults leave server output unchanged.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DisplayPreferences {
+    /// Decimal places token amounts are rounded to; None keeps full precision
+    pub decimals: Option<u32>,
+    /// Group the integer part of amounts, e.g. 1,234,567.5
+    pub thousands_separator: bool,
+    /// Include USD equivalents in results
+    pub show_usd: bool,
+    pub gas_unit: GasUnit,
+}
+
+impl Default for DisplayPreferences {
+    fn default() -> Self {
+        Self {
+            decimals: None,
+            thousands_separator: false,
+            show_usd: true,
+            gas_unit: GasUnit::Gwei,
+        }
+    }
+}
+
+impl DisplayPreferences {
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Apply the preferences present in `changes`, e.g. {"decimals": 2, "gas_unit": "wei"}.
+    /// A null `decimals` restores full precision.
+    pub fn update(&mut self, changes: &Value) -> Result<()> {
+        let Some(changes) = changes.as_object() else {
+            return Err(anyhow!("Display preferences must be an object"));
+        };
+        for (key, value) in changes {
+            match key.as_str() {
+                "decimals" => {
+                    self.decimals = match value {
+                        Value::Null => None,
+                        value => {
+                            let decimals = value
+                                .as_u64()
+                                .filter(|decimals| *decimals <= MAX_DECIMALS as u64)
+                                .ok_or_else(|| {
+                                    anyhow!("decimals must be between 0 and {}", MAX_DECIMALS)
+                                })?;
+                            Some(decimals as u32)
+                        }
+                    }
+                }
+                "thousands_separator" => {
+                    self.thousands_separator = value
+                        .as_bool()
+                        .ok_or_else(|| anyhow!("thousands_separator must be true or false"))?
+                }
+                "show_usd" => {
+                    self.show_usd = value
+                        .as_bool()
+                        .ok_or_else(|| anyhow!("show_usd must be true or false"))?
+                }
+                "gas_unit" => {
+                    self.gas_unit = GasUnit::parse(
+                        value
+                            .as_str()
+                            .ok_or_else(|| anyhow!("gas_unit must be wei, gwei or ether"))?,
+                    )?
+                }
+                other => return Err(anyhow!("Unknown display preference '{}'", other)),
+            }
+        }
+        Ok(())
+    }
+
+    /// Format a decimal amount such as "1234.56789"; anything that isn't a plain
+    /// decimal number is returned unchanged
+    pub fn format_amount(&self, amount: &str) -> String {
+        let trimmed = amount.trim();
+        let (negative, digits) = match trimmed.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, trimmed),
+        };
+        let (integer, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+        if integer.is_empty()
+            || !integer.chars().all(|c| c.is_ascii_digit())
+            || !fraction.chars().all(|c| c.is_ascii_digit())
+        {
+            return amount.to_string();
+        }
+
+        let (integer, fraction) = match self.decimals {
+            Some(decimals) => round_decimal(integer, fraction, decimals as usize),
+            None => (integer.to_string(), fraction.to_string()),
+        };
+        let integer = if self.thousands_separator {
+            group_thousands(&integer)
+        } else {
+            integer
+        };
+
+        let mut formatted = String::new();
+        if negative
+            && (integer.chars().any(|c| c.is_ascii_digit() && c != '0')
+                || fraction.chars().any(|c| c != '0'))
+        {
+            formatted.push('-');
+        }
+        formatted.push_str(&integer);
+        if !fraction.is_empty() {
+            formatted.push('.');
+            formatted.push_str(&fraction);
+        }
+        formatted
+    }
+
+    /// A USD value as "$1,234.56", or None when USD equivalents are turned off
+    pub fn format_usd(&self, value: f64) -> Option<String> {
+        if !self.show_usd {
+            return None;
+        }
+        let formatted = format!("{:.2}", value.abs());
+        let (integer, cents) = formatted.split_once('.').unwrap_or((&formatted, "00"));
+        let integer = if self.thousands_separator {
+            group_thousands(integer)
+        } else {
+            integer.to_string()
+        };
+        let sign = if value < 0.0 { "-" } else { "" };
+        Some(format!("{}${}.{}", sign, integer, cents))
+    }
+
+    /// A gas price given in gwei, converted to the preferred unit with its name,
+    /// e.g. "12.5 gwei" or "12500000000 wei"
+    pub fn format_gas_price(&self, gwei: &str) -> String {
+        match self.convert_gwei(gwei) {
+            Some(value) => format!("{} {}", value, self.gas_unit.as_str()),
+            None => format!("{} gwei", gwei),
+        }
+    }
+
+    fn convert_gwei(&self, gwei: &str) -> Option<String> {
+        let wei: U256 = parse_units(gwei.trim(), "gwei").ok()?.into();
+        let value = match self.gas_unit {
+            GasUnit::Wei => wei.to_string(),
+            GasUnit::Gwei => trim_fraction(&format_units(wei, "gwei").ok()?),
+            GasUnit::Ether => trim_fraction(&format_units(wei, "ether").ok()?),
+        };
+        Some(value)
+    }
+
+    /// Rewrite a tool result in place: amounts are rounded and grouped, USD fields are
+    /// dropped when USD is off, and `*_gwei` fields are renamed to the preferred unit
+    pub fn apply(&self, result: &mut Value) {
+        if self.is_default() {
+            return;
+        }
+        match result {
+            Value::Object(fields) => self.apply_fields(fields),
+            Value::Array(items) => {
+                for item in items {
+                    self.apply(item);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn apply_fields(&self, fields: &mut Map<String, Value>) {
+        if !self.show_usd {
+            fields.retain(|key, _| !is_usd_field(key));
+        }
+
+        let keys: Vec<String> = fields.keys().cloned().collect();
+        for key in keys {
+            if let Some(base) = key.strip_suffix("_gwei")
+                && self.gas_unit != GasUnit::Gwei
+            {
+                let gwei = match &fields[&key] {
+                    Value::String(text) => Some(text.clone()),
+                    Value::Number(number) => Some(number.to_string()),
+                    _ => None,
+                };
+                if let Some(converted) = gwei.and_then(|gwei| self.convert_gwei(&gwei)) {
+                    fields.remove(&key);
+                    fields.insert(
+                        format!("{}{}", base, self.gas_unit.field_suffix()),
+                        json!(converted),
+                    );
+                }
+                continue;
+            }
+
+            let Some(value) = fields.get_mut(&key) else {
+                continue;
+            };
+            match value {
+                Value::String(text) if AMOUNT_FIELDS.contains(&key.as_str()) => {
+                    *text = self.format_amount(text);
+                }
+                Value::Object(_) | Value::Array(_) => self.apply(value),
+                _ => {}
+            }
+        }
+    }
+}
+
+fn is_usd_field(key: &str) -> bool {
+    key == "usd" || key.ends_with("_usd") || key.starts_with("usd_")
+}
+
+// Round half up to `decimals` places, padding with zeros
+fn round_decimal(integer: &str, fraction: &str, decimals: usize) -> (String, String) {
+    let mut digits: Vec<u8> = integer
+        .bytes()
+        .chain(
+            fraction
+                .bytes()
+                .chain(std::iter::repeat(b'0'))
+                .take(decimals),
+        )
+        .map(|digit| digit - b'0')
+        .collect();
+
+    if fraction
+        .as_bytes()
+        .get(decimals)
+        .is_some_and(|digit| *digit >= b'5')
+    {
+        let mut index = digits.len();
+        loop {
+            if index == 0 {
+                digits.insert(0, 1);
+                break;
+            }
+            index -= 1;
+            if digits[index] == 9 {
+                digits[index] = 0;
+            } else {
+                digits[index] += 1;
+                break;
+            }
+        }
+    }
+
+    let text: String = digits.iter().map(|digit| (b'0' + digit) as char).collect();
+    let (integer, fraction) = text.split_at(text.len() - decimals);
+    let integer = integer.trim_start_matches('0');
+    let integer = if integer.is_empty() { "0" } else { integer };
+    (integer.to_string(), fraction.to_string())
+}
+
+fn group_thousands(integer: &str) -> String {
+    let mut grouped = String::with_capacity(integer.len() + integer.len() / 3);
+    for (index, digit) in integer.chars().enumerate() {
+        if index > 0 && (integer.len() - index).is_multiple_of(3) {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    grouped
+}
+
+fn trim_fraction(amount: &str) -> String {
+    if amount.contains('.') {
+        amount
+            .trim_end_matches('0')
+            .trim_end_matches('.')
+            .to_string()
+    } else {
+        amount.to_string()
+    }
+}
@@ -2,8 +2,47 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
 pub mod abi_loader;
+pub mod format;
 pub mod rag;
 
+/// Server methods that sign or submit transactions, or sign messages, on behalf of an account
+pub const WRITE_METHODS: &[&str] = &[
+    "send_eth",
+    "send_erc20",
+    "send_batch",
+    "broadcast_raw_transaction",
+    "attach_signature",
+    "sign_message",
+    "swap_tokens",
+    "add_liquidity",
+    "remove_liquidity",
+    "aave_supply",
+    "aave_withdraw",
+    "aave_borrow",
+    "aave_repay",
+    "transfer_nft",
+    "approve_token",
+    "revoke_approval",
+    "write_contract",
+    "deploy_contract",
+    "deploy_token",
+    "deploy_smart_account",
+    "send_user_operation",
+    "grant_session_key",
+    "send_with_wallet",
+    "run_template",
+    "consolidate_dust",
+    "schedule_job",
+    "add_top_up_rule",
+    "cast_vote",
+    "cast_snapshot_vote",
+    "claim_airdrop",
+    "migrate_token",
+    "release_vested",
+    "create_stream",
+    "cancel_stream",
+];
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Account {
     pub address: String,
@@ -26,6 +65,9 @@ pub struct TransactionResult {
     pub status: String,
     pub block_number: Option<u64>,
     pub gas_used: Option<u64>,
+    /// ENS name of the recipient, when it has one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -40,6 +82,9 @@ pub struct BalanceResult {
     pub balance: String,
     pub token: Option<String>,
     pub decimals: u8,
+    /// ENS name of the address, when it has one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -56,6 +101,10 @@ pub struct SwapRequest {
   pub to_token: String,     // Token to swap to (symbol or address)
   pub amount: String,       // Amount to swap (as a string, e.g. "1.5")
   pub slippage: Option<f64>, // Optional slippage tolerance in percentage
+  #[serde(default)]
+  pub dex: Option<String>,  // "uniswap_v2" or "uniswap_v3"; None picks the better quote
+  #[serde(default)]
+  pub simulate: bool,       // Simulate the swap first and don't send it if it would revert
 }
 
 // Result of a swap operation
@@ -69,6 +118,10 @@ pub struct SwapResult {
   pub amount_out: String,   // Amount received (if known)
   pub block_number: Option<u64>, // Block number where the transaction was mined
   pub gas_used: Option<u64>, // Gas used by the transaction
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub dex: Option<String>,  // Exchange the swap was routed through
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub route: Option<String>, // Tokens (and V3 fee tiers) along the swap path
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -93,6 +146,7 @@ pub struct TokenConfig {
     pub address: String,
     pub decimals: u8,
     pub name: String,
+    /// Relative to the data directory
     pub abi_path: Option<String>,
 }
 
@@ -177,19 +231,30 @@ pub fn get_common_contracts() -> HashMap<String, String> {
     contracts
 }
 
-// Load token configuration from file
-pub fn load_token_config() -> Result<Vec<TokenConfig>, Box<dyn std::error::Error>> {
+/// Load token configuration from `tokens.json` in the data directory. ABI paths in the
+/// file are relative to the data directory and come back joined to it.
+pub fn load_token_config(
+    data_dir: &std::path::Path,
+) -> Result<Vec<TokenConfig>, Box<dyn std::error::Error>> {
     use std::fs;
 
-    let config_path = "../../../data/tokens.json";
-    if std::path::Path::new(config_path).exists() {
-        let content = fs::read_to_string(config_path)?;
-        let tokens: Vec<TokenConfig> = serde_json::from_str(&content)?;
-        Ok(tokens)
+    let config_path = data_dir.join("tokens.json");
+    let tokens: Vec<TokenConfig> = if config_path.exists() {
+        let content = fs::read_to_string(&config_path)?;
+        serde_json::from_str(&content)?
     } else {
         // Return default configuration
-        Ok(get_default_token_config())
-    }
+        get_default_token_config()
+    };
+    Ok(tokens
+        .into_iter()
+        .map(|mut token| {
+            token.abi_path = token
+                .abi_path
+                .map(|path| data_dir.join(path).to_string_lossy().into_owned());
+            token
+        })
+        .collect())
 }
 
 fn get_default_token_config() -> Vec<TokenConfig> {
@@ -199,21 +264,21 @@ fn get_default_token_config() -> Vec<TokenConfig> {
             address: "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48".to_string(),
             decimals: 6,
             name: "USD Coin".to_string(),
-            abi_path: Some("./data/erc20_abi.json".to_string()),
+            abi_path: Some("erc20_abi.json".to_string()),
         },
         TokenConfig {
             symbol: "USDT".to_string(),
             address: "0xdAC17F958D2ee523a2206206994597C13D831ec7".to_string(),
             decimals: 6,
             name: "Tether USD".to_string(),
-            abi_path: Some("./data/erc20_abi.json".to_string()),
+            abi_path: Some("erc20_abi.json".to_string()),
         },
         TokenConfig {
             symbol: "DAI".to_string(),
             address: "0x6B175474E89094C44Da98b954EedeAC495271d0F".to_string(),
             decimals: 18,
             name: "Dai Stablecoin".to_string(),
-            abi_path: Some("./data/erc20_abi.json".to_string()),
+            abi_path: Some("erc20_abi.json".to_string()),
         },
     ]
 }
@@ -222,15 +287,84 @@ pub mod utils {
     use ethers::types::{Address, U256};
     use std::str::FromStr;
 
+    /// Whether the input is an ENS name (e.g. `vitalik.eth`) rather than a hex address
+    pub fn is_ens_name(name: &str) -> bool {
+        let name = name.trim();
+        !name.starts_with("0x") && name.contains('.') && !name.ends_with('.')
+    }
+
+    /// Parse a hex address. ENS names need an RPC lookup, so they are rejected here
+    /// with a hint; use `BlockchainService::parse_address` to accept both.
     pub fn parse_address(addr: &str) -> Result<Address, anyhow::Error> {
-        Address::from_str(addr).map_err(|e| anyhow::anyhow!("Invalid address: {}", e))
+        if is_ens_name(addr) {
+            return Err(anyhow::anyhow!(
+                "{} is an ENS name and must be resolved through the RPC",
+                addr
+            ));
+        }
+        Address::from_str(addr.trim()).map_err(|e| anyhow::anyhow!("Invalid address: {}", e))
     }
 
+    /// Parse a decimal amount such as "1.5", ".25" or "2e-3" into base units with
+    /// `decimals` decimal places. The digits are shifted as a string, so the result is
+    /// exact; amounts with more decimal places than `decimals`, negative amounts and
+    /// ones that don't fit in a U256 are rejected rather than rounded.
     pub fn parse_amount(amount: &str, decimals: u8) -> Result<U256, anyhow::Error> {
-        let amount_f64: f64 = amount.parse()?;
-        let multiplier = 10_u64.pow(decimals as u32);
-        let amount_wei = (amount_f64 * multiplier as f64) as u64;
-        Ok(U256::from(amount_wei))
+        let invalid = |reason: &str| anyhow::anyhow!("Invalid amount '{}': {}", amount, reason);
+        let trimmed = amount.trim();
+        let unsigned = trimmed.strip_prefix('+').unwrap_or(trimmed);
+        if unsigned.starts_with('-') {
+            return Err(invalid("amounts can't be negative"));
+        }
+
+        let (mantissa, exponent) = match unsigned.split_once(['e', 'E']) {
+            Some((mantissa, exponent)) => {
+                let exponent: i64 = exponent
+                    .parse()
+                    .map_err(|_| invalid("the exponent isn't a whole number"))?;
+                (mantissa, exponent)
+            }
+            None => (unsigned, 0),
+        };
+        let (whole, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+        if whole.is_empty() && fraction.is_empty() {
+            return Err(invalid("expected a number"));
+        }
+        if !whole.chars().chain(fraction.chars()).all(|c| c.is_ascii_digit()) {
+            return Err(invalid("expected a decimal number"));
+        }
+
+        // The amount is digits * 10^-scale; shift it to base units
+        let digits = format!("{}{}", whole, fraction);
+        let shift = i64::from(decimals)
+            .saturating_add(exponent)
+            .saturating_sub(fraction.len() as i64);
+        let digits = digits.trim_start_matches('0');
+        let digits = if shift >= 0 {
+            if digits.is_empty() {
+                return Ok(U256::zero());
+            }
+            // 10^78 exceeds U256, so longer results can't fit
+            if digits.len() as i64 + shift > 78 {
+                return Err(invalid("too large"));
+            }
+            format!("{}{}", digits, "0".repeat(shift as usize))
+        } else {
+            let dropped = shift.unsigned_abs() as usize;
+            let kept = digits.len().saturating_sub(dropped);
+            if !digits[kept..].trim_end_matches('0').is_empty() {
+                return Err(anyhow::anyhow!(
+                    "Invalid amount '{}': more than {} decimal places",
+                    amount,
+                    decimals
+                ));
+            }
+            digits[..kept].to_string()
+        };
+        if digits.is_empty() {
+            return Ok(U256::zero());
+        }
+        U256::from_dec_str(&digits).map_err(|_| invalid("too large"))
     }
 
     pub fn format_balance(balance: U256, decimals: u8) -> String {
@@ -239,4 +373,91 @@ pub mod utils {
         let formatted = balance_u128 as f64 / divisor as f64;
         format!("{:.6}", formatted)
     }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn wei(amount: &str) -> U256 {
+            U256::from_dec_str(amount).unwrap()
+        }
+
+        #[test]
+        fn parses_whole_and_fractional_amounts() {
+            assert_eq!(parse_amount("1", 18).unwrap(), wei("1000000000000000000"));
+            assert_eq!(parse_amount("1.5", 6).unwrap(), wei("1500000"));
+            assert_eq!(parse_amount(".25", 2).unwrap(), wei("25"));
+            assert_eq!(parse_amount("2.", 2).unwrap(), wei("200"));
+            assert_eq!(parse_amount(" +3 ", 0).unwrap(), wei("3"));
+            assert_eq!(parse_amount("0007.10", 2).unwrap(), wei("710"));
+        }
+
+        #[test]
+        fn keeps_every_digit() {
+            // f64 only holds ~16 significant digits
+            assert_eq!(
+                parse_amount("0.123456789012345678", 18).unwrap(),
+                wei("123456789012345678")
+            );
+            assert_eq!(
+                parse_amount("123456789.123456789123456789", 18).unwrap(),
+                wei("123456789123456789123456789")
+            );
+        }
+
+        #[test]
+        fn handles_amounts_beyond_u64() {
+            // u64 overflows above ~18.4 ETH in wei
+            assert_eq!(parse_amount("20", 18).unwrap(), wei("20000000000000000000"));
+            assert_eq!(
+                parse_amount("1000000000", 18).unwrap(),
+                wei("1000000000000000000000000000")
+            );
+        }
+
+        #[test]
+        fn accepts_exponents() {
+            assert_eq!(parse_amount("1e3", 0).unwrap(), wei("1000"));
+            assert_eq!(parse_amount("2.5E-3", 6).unwrap(), wei("2500"));
+            assert_eq!(parse_amount("1e18", 0).unwrap(), wei("1000000000000000000"));
+            assert_eq!(parse_amount("15e-1", 1).unwrap(), wei("15"));
+        }
+
+        #[test]
+        fn parses_zero() {
+            assert_eq!(parse_amount("0", 18).unwrap(), U256::zero());
+            assert_eq!(parse_amount("0.000", 2).unwrap(), U256::zero());
+            assert_eq!(parse_amount("0e999", 18).unwrap(), U256::zero());
+        }
+
+        #[test]
+        fn allows_trailing_zeros_past_the_decimals() {
+            assert_eq!(parse_amount("1.500000", 2).unwrap(), wei("150"));
+            assert_eq!(parse_amount("100e-2", 0).unwrap(), wei("1"));
+        }
+
+        #[test]
+        fn rejects_more_decimal_places_than_the_token_has() {
+            assert!(parse_amount("1.5", 0).is_err());
+            assert!(parse_amount("0.1234567", 6).is_err());
+            assert!(parse_amount("1e-19", 18).is_err());
+        }
+
+        #[test]
+        fn rejects_amounts_too_large_for_u256() {
+            let max = U256::MAX.to_string();
+            assert_eq!(parse_amount(&max, 0).unwrap(), U256::MAX);
+            assert!(parse_amount(&format!("{}0", max), 0).is_err());
+            assert!(parse_amount(&max, 1).is_err());
+            assert!(parse_amount("1e78", 0).is_err());
+            assert!(parse_amount("1e9999999999", 18).is_err());
+        }
+
+        #[test]
+        fn rejects_malformed_amounts() {
+            for amount in ["", " ", ".", "-1", "abc", "1.2.3", "1,000", "1_000", "0x10", "1e", "e5", "1e1.5", "NaN", "inf"] {
+                assert!(parse_amount(amount, 18).is_err(), "{:?} parsed", amount);
+            }
+        }
+    }
 }
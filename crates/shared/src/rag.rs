@@ -1,27 +1,219 @@
-use anyhow::Result;
+use anyhow::{Result, anyhow};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::fs::{self};
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
+use std::thread;
+use std::time::Instant;
 
+// Chunks are cut at the first line break after this many bytes
+const CHUNK_SIZE: usize = 2000;
+
+// Most threads documents are chunked and tokenized on at once
+const MAX_INGEST_THREADS: usize = 8;
+
+/// A document's metadata; its content stays on disk at `path`
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Document {
     pub id: String,
     pub title: String,
-    pub content: String,
     pub source: String,
+    pub path: PathBuf,
     pub embedding: Option<Vec<f32>>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SearchResult {
     pub document: Document,
+    /// The document's best-matching chunk
+    pub content: String,
     pub score: f32,
 }
 
+/// Size of the index, and how loading the document directories went
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexStatus {
+    pub documents: usize,
+    pub chunks: usize,
+    /// Distinct words indexed
+    pub terms: usize,
+    pub last_ingestion: Option<IngestionStats>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IngestionStats {
+    pub documents: usize,
+    pub chunks: usize,
+    pub bytes: usize,
+    pub threads: usize,
+    pub seconds: f64,
+    pub documents_per_second: f64,
+    pub megabytes_per_second: f64,
+}
+
+// A slice of a document's text, the unit that gets indexed and returned by searches
+#[derive(Clone)]
+struct Chunk {
+    document: u32,
+    text: Arc<str>,
+}
+
+// A chunk a term appears in, and how many times
+#[derive(Debug, Clone, Copy)]
+struct Posting {
+    chunk: u32,
+    frequency: u32,
+}
+
+// Terms interned to ids, each id with one posting per chunk containing the term
+#[derive(Clone, Default)]
+struct InvertedIndex {
+    terms: HashMap<Box<str>, u32>,
+    postings: Vec<Vec<Posting>>,
+}
+
+impl InvertedIndex {
+    fn add(&mut self, term: &str, posting: Posting) {
+        let id = match self.terms.get(term) {
+            Some(&id) => id,
+            None => {
+                let id = self.postings.len() as u32;
+                self.terms.insert(term.into(), id);
+                self.postings.push(Vec::new());
+                id
+            }
+        };
+        self.postings[id as usize].push(posting);
+    }
+
+    fn postings(&self, term: &str) -> &[Posting] {
+        self.terms
+            .get(term)
+            .map_or(&[], |&id| &self.postings[id as usize])
+    }
+
+    fn len(&self) -> usize {
+        self.terms.len()
+    }
+
+    // Give back capacity left over from growing the lists during a bulk load
+    fn shrink_to_fit(&mut self) {
+        self.terms.shrink_to_fit();
+        self.postings.shrink_to_fit();
+        for postings in &mut self.postings {
+            postings.shrink_to_fit();
+        }
+    }
+}
+
+// A chunk with its word counts, ready to be indexed
+struct TokenizedChunk {
+    text: String,
+    counts: HashMap<String, u32>,
+}
+
+impl TokenizedChunk {
+    fn new(text: String) -> Self {
+        let mut counts: HashMap<String, u32> = HashMap::new();
+        for word in RAGSystem::tokenize(&text) {
+            *counts.entry(word).or_default() += 1;
+        }
+        Self { text, counts }
+    }
+}
+
+/// A document to add to the index at runtime
+#[derive(Debug, Clone)]
+pub struct NewDocument {
+    pub title: String,
+    pub content: String,
+    pub source: String,
+}
+
+// An immutable view of the index. Queries run against one while the next is built.
+#[derive(Clone, Default)]
+struct IndexSnapshot {
+    documents: Vec<Arc<Document>>,
+    chunks: Vec<Chunk>,
+    index: InvertedIndex,
+    last_ingestion: Option<IngestionStats>,
+}
+
+impl IndexSnapshot {
+    fn add_chunks(&mut self, document: Document, chunks: Vec<TokenizedChunk>) {
+        let doc_idx = self.documents.len() as u32;
+        self.documents.push(Arc::new(document));
+
+        for TokenizedChunk { text, counts } in chunks {
+            let chunk = self.chunks.len() as u32;
+            for (word, frequency) in counts {
+                self.index.add(&word, Posting { chunk, frequency });
+            }
+            self.chunks.push(Chunk {
+                document: doc_idx,
+                text: text.into(),
+            });
+        }
+    }
+
+    fn search(&self, query: &str, limit: usize) -> Vec<SearchResult> {
+        let mut query_tokens = RAGSystem::tokenize(query);
+        query_tokens.sort();
+        query_tokens.dedup();
+        let mut scores: HashMap<u32, f32> = HashMap::new();
+
+        // Calculate TF-IDF like scores, with term frequency damped so a word repeated
+        // throughout a chunk doesn't outweigh rarer query words
+        for token in query_tokens {
+            let postings = self.index.postings(&token);
+            if postings.is_empty() {
+                continue;
+            }
+            let idf = (self.chunks.len() as f32 / postings.len() as f32).ln();
+
+            for posting in postings {
+                let entry = scores.entry(posting.chunk).or_insert(0.0);
+                *entry += idf * (1.0 + (posting.frequency as f32).ln());
+            }
+        }
+
+        // Keep each document's best chunk
+        let mut best: HashMap<u32, (u32, f32)> = HashMap::new();
+        for (chunk_idx, score) in scores {
+            let doc_idx = self.chunks[chunk_idx as usize].document;
+            if best.get(&doc_idx).is_none_or(|&(_, best)| score > best) {
+                best.insert(doc_idx, (chunk_idx, score));
+            }
+        }
+
+        // Convert to vector and sort
+        let mut results: Vec<SearchResult> = best
+            .into_iter()
+            .map(|(doc_idx, (chunk_idx, score))| SearchResult {
+                document: Document::clone(&self.documents[doc_idx as usize]),
+                content: self.chunks[chunk_idx as usize].text.to_string(),
+                score,
+            })
+            .collect();
+
+        results.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap());
+
+        // Limit results
+        results.truncate(limit);
+
+        results
+    }
+}
+
 pub struct RAGSystem {
-    documents: Vec<Document>,
-    index: HashMap<String, Vec<usize>>,
+    // Queries clone the current snapshot and let go of the lock straight away; writers
+    // build the next snapshot beside it and swap it in, so ingestion never stalls them
+    current: RwLock<Arc<IndexSnapshot>>,
+    // One writer at a time, so concurrent batches don't drop each other's documents
+    writer: Mutex<()>,
     data_dir: PathBuf,
 }
 
@@ -33,37 +225,103 @@ impl RAGSystem {
         fs::create_dir_all(&data_dir.join("docs"))?;
         fs::create_dir_all(&data_dir.join("embeddings"))?;
 
-        let mut rag = Self {
-            documents: Vec::new(),
-            index: HashMap::new(),
-            data_dir,
-        };
-
-        // Load documents
-        rag.load_documents()?;
+        // Documents added at runtime only live as long as the process
+        let _ = fs::remove_dir_all(data_dir.join("store"));
+        fs::create_dir_all(data_dir.join("store"))?;
 
-        // Build index
-        rag.build_index()?;
+        // Load and index documents
+        let snapshot = Self::load_documents(&data_dir)?;
 
-        Ok(rag)
+        Ok(Self {
+            current: RwLock::new(Arc::new(snapshot)),
+            writer: Mutex::new(()),
+            data_dir,
+        })
     }
 
-    fn load_documents(&mut self) -> Result<()> {
-        // Load Uniswap V2 docs
-        self.load_document_directory(&self.data_dir.join("docs/uniswap-v2"), "uniswap-v2")?;
-
-        // Load Uniswap V3 docs
-        self.load_document_directory(&self.data_dir.join("docs/uniswap-v3"), "uniswap-v3")?;
+    fn snapshot(&self) -> Arc<IndexSnapshot> {
+        self.current
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
 
-        // Load contract source code
-        self.load_document_directory(&self.data_dir.join("docs/contracts"), "contracts")?;
+    fn load_documents(data_dir: &Path) -> Result<IndexSnapshot> {
+        let started = Instant::now();
+        let mut snapshot = IndexSnapshot::default();
+
+        // Uniswap V2 docs, Uniswap V3 docs, then contract source code
+        let mut files = Vec::new();
+        for (dir, source) in [
+            ("docs/uniswap-v2", "uniswap-v2"),
+            ("docs/uniswap-v3", "uniswap-v3"),
+            ("docs/contracts", "contracts"),
+        ] {
+            files.extend(Self::list_document_directory(&data_dir.join(dir), source)?);
+        }
 
-        Ok(())
+        // Files are streamed into chunks and tokenized on a few threads, then indexed in
+        // their original order
+        let threads = thread::available_parallelism()
+            .map_or(1, |threads| threads.get())
+            .min(MAX_INGEST_THREADS)
+            .min(files.len())
+            .max(1);
+        let next = AtomicUsize::new(0);
+        let mut tokenized: Vec<(usize, Result<Vec<TokenizedChunk>>)> = thread::scope(|scope| {
+            let workers: Vec<_> = (0..threads)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut done = Vec::new();
+                        loop {
+                            let index = next.fetch_add(1, Ordering::Relaxed);
+                            let Some(document) = files.get(index) else {
+                                break;
+                            };
+                            let chunks = File::open(&document.path)
+                                .map_err(anyhow::Error::from)
+                                .and_then(|file| Self::chunk(BufReader::new(file)));
+                            done.push((index, chunks));
+                        }
+                        done
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .flat_map(|worker| worker.join().expect("document ingestion thread panicked"))
+                .collect()
+        });
+        tokenized.sort_by_key(|(index, _)| *index);
+
+        let mut bytes = 0;
+        for (document, (_, chunks)) in files.into_iter().zip(tokenized) {
+            let chunks =
+                chunks.map_err(|e| anyhow!("Couldn't load {}: {}", document.path.display(), e))?;
+            bytes += chunks.iter().map(|chunk| chunk.text.len()).sum::<usize>();
+            snapshot.add_chunks(document, chunks);
+        }
+        snapshot.index.shrink_to_fit();
+
+        let seconds = started.elapsed().as_secs_f64();
+        let rate = |amount: f64| if seconds > 0.0 { amount / seconds } else { 0.0 };
+        snapshot.last_ingestion = Some(IngestionStats {
+            documents: snapshot.documents.len(),
+            chunks: snapshot.chunks.len(),
+            bytes,
+            threads,
+            seconds,
+            documents_per_second: rate(snapshot.documents.len() as f64),
+            megabytes_per_second: rate(bytes as f64 / 1_000_000.0),
+        });
+
+        Ok(snapshot)
     }
 
-    fn load_document_directory(&mut self, dir: &Path, source: &str) -> Result<()> {
+    fn list_document_directory(dir: &Path, source: &str) -> Result<Vec<Document>> {
+        let mut documents = Vec::new();
         if !dir.exists() {
-            return Ok(());
+            return Ok(documents);
         }
 
         for entry in fs::read_dir(dir)? {
@@ -74,40 +332,36 @@ impl RAGSystem {
                 let file_name = path.file_name().unwrap().to_string_lossy().to_string();
                 let id = format!("{}/{}", source, file_name);
 
-                let content = fs::read_to_string(&path)?;
-
-                // Create document
-                let document = Document {
-                    id: id.clone(),
+                // Only metadata is kept; the file is streamed into chunks when ingested
+                documents.push(Document {
+                    id,
                     title: file_name,
-                    content,
                     source: source.to_string(),
+                    path,
                     embedding: None,
-                };
-
-                self.documents.push(document);
+                });
             }
         }
 
-        Ok(())
+        Ok(documents)
     }
 
-    fn build_index(&mut self) -> Result<()> {
-        // Simple keyword-based index for demonstration
-        // In a real implementation, you'd use a vector database or similar
-
-        for (doc_idx, doc) in self.documents.iter().enumerate() {
-            let words = Self::tokenize(&doc.content);
-
-            for word in words {
-                self.index
-                    .entry(word)
-                    .or_insert_with(Vec::new)
-                    .push(doc_idx);
+    // Split what `reader` yields into chunks and count each chunk's words
+    fn chunk(reader: impl BufRead) -> Result<Vec<TokenizedChunk>> {
+        let mut chunks = Vec::new();
+        let mut text = String::new();
+        for line in reader.lines() {
+            text.push_str(&line?);
+            text.push('\n');
+            if text.len() >= CHUNK_SIZE {
+                chunks.push(TokenizedChunk::new(std::mem::take(&mut text)));
             }
         }
+        if !text.trim().is_empty() {
+            chunks.push(TokenizedChunk::new(text));
+        }
 
-        Ok(())
+        Ok(chunks)
     }
 
     fn tokenize(text: &str) -> Vec<String> {
@@ -119,67 +373,73 @@ impl RAGSystem {
     }
 
     pub fn search(&self, query: &str, limit: usize) -> Vec<SearchResult> {
-        let query_tokens = Self::tokenize(query);
-        let mut scores: HashMap<usize, f32> = HashMap::new();
-
-        // Calculate TF-IDF like scores
-        for token in query_tokens {
-            if let Some(doc_indices) = self.index.get(&token) {
-                let idf = (self.documents.len() as f32 / doc_indices.len() as f32).ln();
+        self.snapshot().search(query, limit)
+    }
 
-                for &doc_idx in doc_indices {
-                    let entry = scores.entry(doc_idx).or_insert(0.0);
-                    *entry += idf;
-                }
-            }
+    /// Index a batch of documents, swapping in a single new snapshot once all of them
+    /// are in. Each swap copies the index, so larger batches are cheaper per document.
+    pub fn add_documents(&self, documents: Vec<NewDocument>) -> Result<()> {
+        let _writer = self.writer.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut next = IndexSnapshot::clone(&self.snapshot());
+
+        for document in documents {
+            // Store the content on disk like loaded documents
+            let path = self
+                .data_dir
+                .join("store")
+                .join(format!("{}.txt", next.documents.len()));
+            fs::write(&path, &document.content)?;
+
+            let chunks = Self::chunk(document.content.as_bytes())?;
+            next.add_chunks(
+                Document {
+                    id: format!("{}/{}", document.source, document.title),
+                    title: document.title,
+                    source: document.source,
+                    path,
+                    embedding: None,
+                },
+                chunks,
+            );
         }
 
-        // Convert to vector and sort
-        let mut results: Vec<SearchResult> = scores
-            .into_iter()
-            .map(|(doc_idx, score)| SearchResult {
-                document: self.documents[doc_idx].clone(),
-                score,
-            })
-            .collect();
-
-        results.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap());
-
-        // Limit results
-        results.truncate(limit);
-
-        results
+        *self.current.write().unwrap_or_else(PoisonError::into_inner) = Arc::new(next);
+        Ok(())
     }
 
-    pub fn add_document(&mut self, title: &str, content: &str, source: &str) -> Result<()> {
-        let id = format!("{}/{}", source, title);
-
-        let document = Document {
-            id: id.clone(),
+    pub fn add_document(&self, title: &str, content: &str, source: &str) -> Result<()> {
+        self.add_documents(vec![NewDocument {
             title: title.to_string(),
             content: content.to_string(),
             source: source.to_string(),
-            embedding: None,
-        };
-
-        // Add to documents
-        self.documents.push(document);
+        }])
+    }
 
-        // Update index
-        let doc_idx = self.documents.len() - 1;
-        let words = Self::tokenize(content);
+    pub fn get_document_by_id(&self, id: &str) -> Option<Document> {
+        self.snapshot()
+            .documents
+            .iter()
+            .find(|doc| doc.id == id)
+            .map(|doc| Document::clone(doc))
+    }
 
-        for word in words {
-            self.index
-                .entry(word)
-                .or_insert_with(Vec::new)
-                .push(doc_idx);
-        }
+    /// A document's full content, read from disk
+    pub fn read_content(&self, document: &Document) -> Result<String> {
+        fs::read_to_string(&document.path)
+            .map_err(|e| anyhow!("Couldn't read {}: {}", document.id, e))
+    }
 
-        Ok(())
+    pub fn document_count(&self) -> usize {
+        self.snapshot().documents.len()
     }
 
-    pub fn get_document_by_id(&self, id: &str) -> Option<&Document> {
-        self.documents.iter().find(|doc| doc.id == id)
+    pub fn status(&self) -> IndexStatus {
+        let snapshot = self.snapshot();
+        IndexStatus {
+            documents: snapshot.documents.len(),
+            chunks: snapshot.chunks.len(),
+            terms: snapshot.index.len(),
+            last_ingestion: snapshot.last_ingestion.clone(),
+        }
     }
 }